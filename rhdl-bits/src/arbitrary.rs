@@ -0,0 +1,27 @@
+use proptest::arbitrary::Arbitrary;
+use proptest::strategy::{BoxedStrategy, Strategy};
+
+use crate::bits_impl::Bits;
+use crate::signed_bits_impl::SignedBits;
+
+// Values are drawn uniformly from the full range of the type
+// (including 128 bit widths), and shrink toward zero.
+impl<const N: usize> Arbitrary for Bits<N> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (0..=Self::mask().0).prop_map(Self).boxed()
+    }
+}
+
+impl<const N: usize> Arbitrary for SignedBits<N> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (Self::min_value()..=Self::max_value())
+            .prop_map(Self)
+            .boxed()
+    }
+}
@@ -1,9 +1,6 @@
 #![allow(non_camel_case_types)]
 use crate::signed_bits_impl::SignedBits;
-use derive_more::{
-    Binary, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Display, LowerHex,
-    UpperHex,
-};
+use derive_more::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Display};
 use seq_macro::seq;
 
 /// The [Bits] type is a fixed-sized bit vector.  It is meant to
@@ -40,9 +37,6 @@ use seq_macro::seq;
     BitXor,
     BitXorAssign,
     Display,
-    LowerHex,
-    UpperHex,
-    Binary,
 )]
 #[repr(transparent)]
 pub struct Bits<const N: usize>(pub u128);
@@ -105,20 +99,187 @@ impl<const N: usize> Bits<N> {
             SignedBits(self.0 as i128)
         }
     }
+    /// Compare the [Bits] value with a [SignedBits] value, treating
+    /// both as signed.  There is deliberately no `PartialOrd` between
+    /// [Bits] and [SignedBits], as it is not clear which of these
+    /// comparisons is meant.
+    /// ```
+    /// # use rhdl_bits::{bits, signed};
+    /// # use std::cmp::Ordering;
+    /// // 0xFF is -1 when treated as signed
+    /// assert_eq!(bits::<8>(0xFF).cmp_signed(signed(0)), Ordering::Less);
+    /// ```
+    pub fn cmp_signed(self, other: SignedBits<N>) -> std::cmp::Ordering {
+        self.as_signed().cmp(&other)
+    }
+    /// Compare the [Bits] value with a [SignedBits] value, treating
+    /// both as unsigned.
+    /// ```
+    /// # use rhdl_bits::{bits, signed};
+    /// # use std::cmp::Ordering;
+    /// // -1 is 0xFF when treated as unsigned
+    /// assert_eq!(bits::<8>(1).cmp_unsigned(signed(-1)), Ordering::Less);
+    /// ```
+    pub fn cmp_unsigned(self, other: SignedBits<N>) -> std::cmp::Ordering {
+        self.cmp(&other.as_unsigned())
+    }
     /// Extract the raw `u128` behind the [Bits] value.
     pub fn raw(self) -> u128 {
         self.0
     }
-    /// Build a (dynamic, stack allocated) vector containing
-    /// the bits that make up this value.  This will be slow.
-    pub fn to_bools(self) -> Vec<bool> {
-        let mut v = Vec::new();
-        let mut x = self.0;
-        for _i in 0..N {
-            v.push(x & 1 == 1);
-            x = x.wrapping_shr(1);
+    /// Overwrite the `M` bits starting at bit `start` with `value`,
+    /// leaving the rest of the bits unchanged.  This is the write-side
+    /// complement of `slice`, and is handy for updating a field of a
+    /// register.  Panics if the field does not fit (`start + M > N`).
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let reg: Bits<8> = 0b1010_0101.into();
+    /// let reg = reg.insert(4, Bits::<4>::from(0b0011));
+    /// assert_eq!(reg, 0b0011_0101);
+    /// ```
+    pub fn insert<const M: usize>(self, start: usize, value: Bits<M>) -> Bits<N> {
+        assert!(start + M <= N);
+        let mask = Bits::<M>::mask().0.checked_shl(start as u32).unwrap_or(0);
+        let value = value.0.checked_shl(start as u32).unwrap_or(0);
+        Self((self.0 & !mask) | value)
+    }
+    /// Test if the `len` bits starting at bit `start` are all set.
+    /// This is `all` for a field of the value, without slicing it
+    /// out first.  An empty range is all set.  Panics if the range
+    /// does not fit (`start + len > N`).
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let flags: Bits<8> = 0b1111_0010.into();
+    /// assert!(flags.range_all(4, 4));
+    /// assert!(!flags.range_all(0, 2));
+    /// ```
+    pub fn range_all(&self, start: usize, len: usize) -> bool {
+        let mask = Self::range_mask(start, len);
+        self.0 & mask == mask
+    }
+    /// Test if any of the `len` bits starting at bit `start` are set.
+    /// This is `any` for a field of the value, without slicing it out
+    /// first.  An empty range has no bits set.  Panics if the range
+    /// does not fit (`start + len > N`).
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let flags: Bits<8> = 0b1111_0010.into();
+    /// assert!(flags.range_any(0, 2));
+    /// assert!(!flags.range_any(2, 2));
+    /// ```
+    pub fn range_any(&self, start: usize, len: usize) -> bool {
+        self.0 & Self::range_mask(start, len) != 0
+    }
+    fn range_mask(start: usize, len: usize) -> u128 {
+        assert!(
+            start + len <= N,
+            "The range of {len} bits at bit {start} does not fit in {N} bits"
+        );
+        if len == 0 {
+            0
+        } else {
+            (u128::MAX >> (128 - len)) << start
+        }
+    }
+    /// Change the width of the [Bits] value to `M` bits.  Widening
+    /// zero-extends the value, and narrowing truncates it (keeping only
+    /// the low `M` bits).
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let bits: Bits<8> = 0b1101_1010.into();
+    /// assert_eq!(bits.resize::<4>(), 0b1010);
+    /// assert_eq!(bits.resize::<12>(), 0b0000_1101_1010);
+    /// ```
+    pub fn resize<const M: usize>(self) -> Bits<M> {
+        Bits::<M>(self.0 & Bits::<M>::mask().0)
+    }
+    /// Change the width of the [Bits] value to `M` bits, like `resize`,
+    /// except that narrowing a value that does not fit in `M` bits
+    /// saturates to `Bits::<M>::mask()` instead of truncating it.
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let bits: Bits<8> = 0b0001_1010.into();
+    /// assert_eq!(bits.resize::<4>(), 0b1010);
+    /// assert_eq!(bits.resize_saturating::<4>(), 0b1111);
+    /// ```
+    pub fn resize_saturating<const M: usize>(self) -> Bits<M> {
+        if self.0 > Bits::<M>::mask().0 {
+            Bits::<M>::mask()
+        } else {
+            Bits::<M>(self.0)
+        }
+    }
+    /// Multiply by a value of `M` bits, keeping the full product in `R`
+    /// bits.  The product of an `N` bit value and an `M` bit value needs
+    /// `N + M` bits, so with `R >= N + M` nothing is lost (unlike a
+    /// multiply that wraps to the width of its arguments).  Stable Rust
+    /// cannot spell the result as `Bits<{N + M}>`, so the width is given
+    /// separately, and a width that is too small is caught by a debug
+    /// assertion (the product is truncated to `R` bits otherwise).
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let a: Bits<8> = 200.into();
+    /// let product: Bits<16> = a.widening_mul_into(a);
+    /// assert_eq!(product, 40_000);
+    /// ```
+    pub fn widening_mul_into<const M: usize, const R: usize>(self, rhs: Bits<M>) -> Bits<R> {
+        debug_assert!(
+            R >= N + M,
+            "The product of a {N} bit and a {M} bit value needs {} bits, not {R}",
+            N + M
+        );
+        Bits::<R>(self.0.wrapping_mul(rhs.0) & Bits::<R>::mask().0)
+    }
+    /// Test if exactly one of the `N` bits is set.
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// assert!(Bits::<8>::from(0b1000).is_power_of_two());
+    /// assert!(!Bits::<8>::from(0b1010).is_power_of_two());
+    /// ```
+    pub fn is_power_of_two(self) -> bool {
+        (self.0 & Self::mask().0).count_ones() == 1
+    }
+    /// Round up to the next power of two (zero rounds up to one).  If
+    /// the result would not fit in `N` bits, it saturates to
+    /// `Bits::<N>::mask()`.
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// assert_eq!(Bits::<8>::from(5).next_power_of_two(), 8);
+    /// assert_eq!(Bits::<8>::from(0x81).next_power_of_two(), 0xFF);
+    /// ```
+    pub fn next_power_of_two(self) -> Bits<N> {
+        match self.0.checked_next_power_of_two() {
+            Some(next) if next <= Self::mask().0 => Self(next),
+            _ => Self::mask(),
         }
-        v
+    }
+    /// Split this value into an array of its bits.  Element 0
+    /// of the array is the least significant bit, so the array
+    /// has the same layout as the [Bits] value itself.  Inside
+    /// a kernel, this is pure wiring.
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let bits: Bits<4> = 0b0110.into();
+    /// assert_eq!(bits.to_bools(), [false, true, true, false]);
+    /// ```
+    pub fn to_bools(self) -> [bool; N] {
+        std::array::from_fn(|i| (self.0 >> i) & 1 == 1)
+    }
+    /// Build a [Bits] value from an array of its bits, with
+    /// element 0 as the least significant bit.  This is the
+    /// inverse of [Bits::to_bools].
+    /// ```
+    /// # use rhdl_bits::Bits;
+    /// let bits = Bits::<4>::from_bools([false, true, true, false]);
+    /// assert_eq!(bits, 0b0110);
+    /// ```
+    pub fn from_bools(bools: [bool; N]) -> Bits<N> {
+        Bits::<N>(
+            bools
+                .iter()
+                .rev()
+                .fold(0, |acc, &bit| (acc << 1) | bit as u128),
+        )
     }
 }
 
@@ -140,6 +301,42 @@ impl<const N: usize> From<u128> for Bits<N> {
     }
 }
 
+/// Binary formatting is zero padded to the width of the [Bits] value.
+/// ```
+/// # use rhdl_bits::Bits;
+/// let bits: Bits<8> = 5.into();
+/// assert_eq!(format!("{:b}", bits), "00000101");
+/// assert_eq!(format!("{:#b}", bits), "0b00000101");
+/// ```
+impl<const N: usize> std::fmt::Binary for Bits<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad_integral(true, "0b", &format!("{:0N$b}", self.0))
+    }
+}
+
+/// Hex formatting is zero padded to the number of hex digits
+/// needed to hold the [Bits] value.
+/// ```
+/// # use rhdl_bits::Bits;
+/// let bits: Bits<8> = 5.into();
+/// assert_eq!(format!("{:x}", bits), "05");
+/// let bits: Bits<10> = 0x2A.into();
+/// assert_eq!(format!("{:#x}", bits), "0x02a");
+/// ```
+impl<const N: usize> std::fmt::LowerHex for Bits<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = N.div_ceil(4);
+        f.pad_integral(true, "0x", &format!("{:0digits$x}", self.0))
+    }
+}
+
+impl<const N: usize> std::fmt::UpperHex for Bits<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = N.div_ceil(4);
+        f.pad_integral(true, "0x", &format!("{:0digits$X}", self.0))
+    }
+}
+
 impl<const N: usize> PartialEq<u128> for Bits<N> {
     fn eq(&self, other: &u128) -> bool {
         self == &Self::from(*other)
@@ -181,6 +378,12 @@ mod tests {
     fn test_binary_format() {
         let bits: Bits<8> = 0b1101_1010.into();
         assert_eq!(format!("{:b}", bits), "11011010");
+        let bits: Bits<8> = 5.into();
+        assert_eq!(format!("{:b}", bits), "00000101");
+        let bits: Bits<12> = 0b1010.into();
+        assert_eq!(format!("{:b}", bits), "000000001010");
+        assert_eq!(format!("{:#b}", bits), "0b000000001010");
+        assert_eq!(format!("{:>14b}", bits), "  000000001010");
     }
 
     #[test]
@@ -188,15 +391,143 @@ mod tests {
         let bits: Bits<8> = 0b1101_1010.into();
         assert_eq!(format!("{:x}", bits), "da");
         assert_eq!(format!("{:X}", bits), "DA");
+        let bits: Bits<8> = 5.into();
+        assert_eq!(format!("{:x}", bits), "05");
+        let bits: Bits<13> = 0xAB.into();
+        assert_eq!(format!("{:x}", bits), "00ab");
+        assert_eq!(format!("{:#X}", bits), "0x00AB");
+        let bits: Bits<128> = 1.into();
+        assert_eq!(format!("{:x}", bits), format!("{:032x}", 1));
+    }
+
+    #[test]
+    fn test_insert() {
+        let bits: Bits<8> = 0b1101_1010.into();
+        let result = bits.insert(4, Bits::<4>::from(0b0110));
+        assert_eq!(result, 0b0110_1010);
+        let result = bits.insert(0, Bits::<8>::from(0b0101_0101));
+        assert_eq!(result, 0b0101_0101);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_insert_out_of_range() {
+        let bits: Bits<8> = 0b1101_1010.into();
+        bits.insert(6, Bits::<4>::from(0b0110));
+    }
+
+    #[test]
+    fn test_range_all_and_any() {
+        for (value, all_high, any_low) in [
+            (0b0000_0000, false, false),
+            (0b1111_0000, true, false),
+            (0b1111_1111, true, true),
+            (0b0111_0001, false, true),
+            (0b1110_0010, false, true),
+            (0b1011_0011, false, true),
+            (0b0000_0100, false, false),
+        ] {
+            let bits: Bits<8> = value.into();
+            assert_eq!(bits.range_all(4, 4), all_high, "{value:08b}");
+            assert_eq!(bits.range_any(0, 2), any_low, "{value:08b}");
+        }
+        // The whole value matches `all` and `any`
+        let bits: Bits<8> = 0xFF.into();
+        assert!(bits.range_all(0, 8) && bits.range_any(0, 8));
+        let bits: Bits<8> = 0x80.into();
+        assert!(!bits.range_all(0, 8) && bits.range_any(7, 1));
+        // Empty ranges, even at the end of the value
+        assert!(bits.range_all(8, 0) && !bits.range_any(8, 0));
+        assert!(Bits::<128>::mask().range_all(0, 128));
+        assert!(!Bits::<128>::from(1 << 127).range_any(0, 127));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_range_out_of_bounds() {
+        let bits: Bits<8> = 0xFF.into();
+        bits.range_all(6, 3);
+    }
+
+    #[test]
+    fn test_resize() {
+        let bits: Bits<8> = 0x1F.into();
+        assert_eq!(bits.resize::<4>(), 0xF);
+        assert_eq!(bits.resize::<16>(), 0x1F);
+        let bits: Bits<8> = 0x25.into();
+        assert_eq!(bits.resize::<4>(), 0x5);
+        assert_eq!(Bits::<128>::mask().resize::<128>(), Bits::<128>::mask());
+    }
+
+    #[test]
+    fn test_resize_saturating() {
+        // Values that fit are unchanged
+        let bits: Bits<8> = 0x0A.into();
+        assert_eq!(bits.resize_saturating::<4>(), 0xA);
+        assert_eq!(bits.resize_saturating::<16>(), 0x0A);
+        // Values that do not fit clamp to the mask, where resize
+        // would have kept only the low bits
+        let bits: Bits<8> = 0x25.into();
+        assert_eq!(bits.resize::<4>(), 0x5);
+        assert_eq!(bits.resize_saturating::<4>(), 0xF);
+        let bits: Bits<8> = 0x10.into();
+        assert_eq!(bits.resize::<4>(), 0x0);
+        assert_eq!(bits.resize_saturating::<4>(), 0xF);
+        let bits: Bits<8> = 0xFF.into();
+        assert_eq!(bits.resize_saturating::<4>(), 0xF);
+    }
+
+    #[test]
+    fn test_widening_mul_into() {
+        let product: Bits<16> = Bits::<8>::from(200).widening_mul_into(Bits::<8>::from(200));
+        assert_eq!(product, 40_000);
+        let product: Bits<12> = Bits::<8>::mask().widening_mul_into(Bits::<4>::mask());
+        assert_eq!(product, 0xFF * 0xF);
+        let product: Bits<128> = Bits::<64>::mask().widening_mul_into(Bits::<64>::mask());
+        assert_eq!(product, (u64::MAX as u128) * (u64::MAX as u128));
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_widening_mul_into_too_narrow() {
+        let _: Bits<8> = Bits::<8>::from(200).widening_mul_into(Bits::<8>::from(200));
+    }
+
+    #[test]
+    fn test_is_power_of_two() {
+        assert!(Bits::<8>::from(0b1000).is_power_of_two());
+        assert!(Bits::<8>::from(0x80).is_power_of_two());
+        assert!(!Bits::<8>::from(0b1010).is_power_of_two());
+        assert!(!Bits::<8>::from(0).is_power_of_two());
+        assert!(!Bits::<8>::mask().is_power_of_two());
+    }
+
+    #[test]
+    fn test_next_power_of_two() {
+        assert_eq!(Bits::<8>::from(5).next_power_of_two(), 8);
+        assert_eq!(Bits::<8>::from(8).next_power_of_two(), 8);
+        assert_eq!(Bits::<8>::from(0).next_power_of_two(), 1);
+        assert_eq!(Bits::<8>::from(0x80).next_power_of_two(), 0x80);
+        // Anything above half of the range would need a ninth bit
+        assert_eq!(Bits::<8>::from(0x81).next_power_of_two(), 0xFF);
+        assert_eq!(Bits::<8>::mask().next_power_of_two(), 0xFF);
+        assert_eq!(Bits::<128>::mask().next_power_of_two(), Bits::<128>::mask());
     }
 
     #[test]
     fn test_to_bits_method() {
         let bits: Bits<8> = 0b1101_1010.into();
         let result = bits.to_bools();
-        assert_eq!(
-            result,
-            vec![false, true, false, true, true, false, true, true]
-        );
+        assert_eq!(result, [false, true, false, true, true, false, true, true]);
+    }
+
+    #[test]
+    fn test_from_bools_round_trip() {
+        let bits: Bits<8> = 0b1101_1010.into();
+        assert_eq!(Bits::<8>::from_bools(bits.to_bools()), bits);
+        assert_eq!(Bits::<128>::from_bools([true; 128]), Bits::<128>::mask());
+        assert_eq!(Bits::<128>::mask().to_bools(), [true; 128]);
+        assert_eq!(Bits::<1>::from_bools([true]), 1);
     }
 }
@@ -383,6 +383,8 @@
 //!
 #[doc(hidden)]
 pub mod add;
+#[cfg(feature = "proptest")]
+mod arbitrary;
 #[doc(hidden)]
 pub mod and;
 #[doc(hidden)]
@@ -23,7 +23,7 @@ impl<const M: usize, const N: usize> Shl<Bits<M>> for Bits<N> {
     type Output = Self;
     fn shl(self, rhs: Bits<M>) -> Self::Output {
         assert!(M <= 8, "Shift amount must be less than 8 bits");
-        Self(u128::wrapping_shl(self.0, rhs.0 as u32) & Self::mask().0)
+        Self(u128::checked_shl(self.0, rhs.0 as u32).unwrap_or(0) & Self::mask().0)
     }
 }
 
@@ -23,7 +23,7 @@ impl<const M: usize, const N: usize> Shr<Bits<M>> for Bits<N> {
     type Output = Self;
     fn shr(self, rhs: Bits<M>) -> Self::Output {
         assert!(M <= 8, "Shift amount must be less than 8 bits");
-        Self(u128::wrapping_shr(self.0, rhs.0 as u32) & Self::mask().0)
+        Self(u128::checked_shr(self.0, rhs.0 as u32).unwrap_or(0) & Self::mask().0)
     }
 }
 
@@ -39,11 +39,15 @@ impl<const N: usize> ShrAssign<u128> for Bits<N> {
     }
 }
 
+// Shifting a signed value right is an arithmetic shift: the sign bit
+// is copied into the vacated bits, so a negative value stays negative.
+// This matches `>>>` on a signed value in Verilog.  Shifting by the
+// width or more leaves only copies of the sign bit.
 impl<const M: usize, const N: usize> Shr<Bits<M>> for SignedBits<N> {
     type Output = Self;
     fn shr(self, rhs: Bits<M>) -> Self::Output {
         assert!(M <= 8, "Shift amount must be less than 8 bits");
-        Self(i128::wrapping_shr(self.0, rhs.0 as u32))
+        Self(self.0 >> (rhs.0 as u32).min(127))
     }
 }
 
@@ -114,4 +118,18 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_shr_by_large_amounts() {
+        // Shift amounts past 127 must not wrap around
+        let bits: Bits<8> = 0b1101_1010.into();
+        let shift: Bits<8> = 130.into();
+        assert_eq!((bits >> shift).0, 0);
+        let bits: SignedBits<8> = (-6).into();
+        assert_eq!((bits >> shift).0, -1);
+        let bits: SignedBits<8> = 6.into();
+        assert_eq!((bits >> shift).0, 0);
+        let bits: Bits<8> = 0b1101_1010.into();
+        assert_eq!((bits << shift).0, 0);
+    }
 }
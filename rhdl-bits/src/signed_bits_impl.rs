@@ -140,6 +140,19 @@ impl<const N: usize> SignedBits<N> {
     pub fn is_non_negative(&self) -> bool {
         self.0 >= 0
     }
+    /// Shift the value right arithmetically, so that copies of the
+    /// sign bit fill in from the left.  This is what `>>` does on a
+    /// [SignedBits] value, and in a kernel it lowers to `>>>` in
+    /// Verilog in the same way.
+    /// ```
+    /// # use rhdl_bits::{bits, signed};
+    /// assert_eq!(signed::<8>(-128).arithmetic_shr(bits::<3>(2)), signed::<8>(-32));
+    /// assert_eq!(signed::<8>(-5).arithmetic_shr(bits::<3>(7)), signed::<8>(-1));
+    /// assert_eq!(signed::<8>(100).arithmetic_shr(bits::<3>(3)), signed::<8>(12));
+    /// ```
+    pub fn arithmetic_shr<const M: usize>(self, rhs: Bits<M>) -> Self {
+        self >> rhs
+    }
     /// Reinterpret the [SignedBits] value as an unsigned
     /// [Bits] value.  This is useful for performing
     /// bit manipulations on the value that may or not
@@ -153,15 +166,34 @@ impl<const N: usize> SignedBits<N> {
     pub fn as_unsigned(self) -> Bits<N> {
         Bits(self.0 as u128 & Bits::<N>::mask().0)
     }
+    /// Compare the [SignedBits] value with a [Bits] value, treating
+    /// both as signed.  See [Bits::cmp_signed].
+    /// ```
+    /// # use rhdl_bits::{bits, signed};
+    /// # use std::cmp::Ordering;
+    /// assert_eq!(signed::<8>(0).cmp_signed(bits(0xFF)), Ordering::Greater);
+    /// ```
+    pub fn cmp_signed(self, other: Bits<N>) -> std::cmp::Ordering {
+        self.cmp(&other.as_signed())
+    }
+    /// Compare the [SignedBits] value with a [Bits] value, treating
+    /// both as unsigned.  See [Bits::cmp_unsigned].
+    /// ```
+    /// # use rhdl_bits::{bits, signed};
+    /// # use std::cmp::Ordering;
+    /// assert_eq!(signed::<8>(-1).cmp_unsigned(bits(1)), Ordering::Greater);
+    /// ```
+    pub fn cmp_unsigned(self, other: Bits<N>) -> std::cmp::Ordering {
+        self.as_unsigned().cmp(&other)
+    }
     /// Extract the raw signed `i128` backing this SignedBits
     /// value.
     pub fn raw(self) -> i128 {
         self.0
     }
-    /// Build a (dynamic, stack allocated) vector
-    /// containing the bits that make up this value.
-    /// This will be slow.
-    pub fn to_bools(self) -> Vec<bool> {
+    /// Split this value into an array of its bits (in two's
+    /// complement), with element 0 as the least significant bit.
+    pub fn to_bools(self) -> [bool; N] {
         self.as_unsigned().to_bools()
     }
 }
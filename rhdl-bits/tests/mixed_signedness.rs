@@ -0,0 +1,26 @@
+#[path = "../../rhdl-macro/tests/common/mod.rs"]
+mod common;
+
+#[test]
+fn test_mixed_comparison_is_rejected() {
+    common::assert_compile_error(
+        "tests/ui/mixed_comparison.rs",
+        &[
+            "error[E0277]: can't compare `Bits<8>` with `SignedBits<8>`",
+            "mixed_comparison.rs:8:15",
+            "^ no implementation for `Bits<8> < SignedBits<8>` and `Bits<8> > SignedBits<8>`",
+        ],
+    );
+}
+
+#[test]
+fn test_mixed_arithmetic_is_rejected() {
+    common::assert_compile_error(
+        "tests/ui/mixed_arithmetic.rs",
+        &[
+            "error[E0277]: cannot add `SignedBits<8>` to `Bits<8>`",
+            "mixed_arithmetic.rs:8:15",
+            "^ no implementation for `Bits<8> + SignedBits<8>`",
+        ],
+    );
+}
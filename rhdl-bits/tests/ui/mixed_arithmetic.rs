@@ -0,0 +1,9 @@
+use rhdl_bits::{bits, signed};
+
+// Adding a Bits to a SignedBits must go through `as_signed` or
+// `as_unsigned`.
+fn main() {
+    let a = bits::<8>(0x80);
+    let b = signed::<8>(1);
+    let _ = a + b;
+}
@@ -0,0 +1,9 @@
+use rhdl_bits::{bits, signed};
+
+// Comparing a Bits with a SignedBits must be spelled out with
+// `cmp_signed` or `cmp_unsigned`.
+fn main() {
+    let a = bits::<8>(0x80);
+    let b = signed::<8>(1);
+    let _ = a < b;
+}
@@ -0,0 +1,326 @@
+// Generate `Digital` enums from a table of variants, such as a command
+// set or pinout exported from a spreadsheet as CSV.  This is meant to be
+// called from `build.rs`, with the output written to `OUT_DIR` and
+// pulled in with `include!`, so that the Rust side cannot drift from the
+// table.
+//
+// The first line of the table names the columns.  The `name` and
+// `discriminant` columns are required.  An optional `payload_width`
+// column gives the width of the `Bits` payload of a variant (leave it
+// empty for a variant without one).  Any other column is an attribute,
+// which `lookup_kernel_from_table` can turn into a kernel.  Blank lines
+// and lines starting with `#` are skipped.
+//
+//   name,discriminant,payload_width,latency
+//   Nop,0,,1
+//   Read,1,8,4
+//
+// The generated code refers to `rhdl_bits`, `rhdl_core` and `rhdl_macro`
+// by their crate names, so the including crate must depend on them.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::io::Read;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+#[derive(Clone, Debug)]
+pub struct EnumTableOptions {
+    // The name of the generated enum.
+    pub name: String,
+    // The width of the discriminant.  It is an error for a discriminant
+    // not to fit.  If not given, the narrowest width that fits all the
+    // discriminants is used.
+    pub discriminant_width: Option<usize>,
+    // Wrap the payload of each variant in a newtype named after the enum
+    // and the variant (e.g., `CommandRead`), rather than using `Bits`.
+    pub newtype_payloads: bool,
+}
+
+impl Default for EnumTableOptions {
+    fn default() -> Self {
+        Self {
+            name: "Table".into(),
+            discriminant_width: None,
+            newtype_payloads: false,
+        }
+    }
+}
+
+struct Row {
+    line: usize,
+    name: String,
+    discriminant: i64,
+    payload_width: Option<usize>,
+    attributes: BTreeMap<String, String>,
+}
+
+const NAME: &str = "name";
+const DISCRIMINANT: &str = "discriminant";
+const PAYLOAD_WIDTH: &str = "payload_width";
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_table(mut reader: impl Read) -> Result<Vec<Row>> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(ndx, line)| (ndx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    let (_, header) = lines.next().ok_or(anyhow!("The table is empty"))?;
+    let columns = header.split(',').map(str::trim).collect::<Vec<_>>();
+    for required in [NAME, DISCRIMINANT] {
+        ensure!(
+            columns.contains(&required),
+            "The table has no `{required}` column"
+        );
+    }
+    let mut rows = vec![];
+    for (line, text) in lines {
+        let cells = text.split(',').map(str::trim).collect::<Vec<_>>();
+        ensure!(
+            cells.len() == columns.len(),
+            "Line {line} has {} cells, but the table has {} columns",
+            cells.len(),
+            columns.len()
+        );
+        let mut cells = columns
+            .iter()
+            .map(|c| c.to_string())
+            .zip(cells.into_iter().map(String::from))
+            .collect::<BTreeMap<_, _>>();
+        let name = cells.remove(NAME).unwrap_or_default();
+        ensure!(
+            is_identifier(&name),
+            "Line {line}: `{name}` is not a valid variant name"
+        );
+        let discriminant = cells.remove(DISCRIMINANT).unwrap_or_default();
+        let discriminant = parse_integer(&discriminant)
+            .with_context(|| format!("Line {line}: bad discriminant for {name}"))?;
+        let payload_width = match cells.remove(PAYLOAD_WIDTH).as_deref() {
+            None | Some("") => None,
+            Some(width) => {
+                let width = width
+                    .parse::<usize>()
+                    .with_context(|| format!("Line {line}: bad payload width for {name}"))?;
+                ensure!(
+                    width > 0,
+                    "Line {line}: the payload of {name} must be at least 1 bit wide"
+                );
+                Some(width)
+            }
+        };
+        rows.push(Row {
+            line,
+            name,
+            discriminant,
+            payload_width,
+            attributes: cells,
+        });
+    }
+    ensure!(!rows.is_empty(), "The table has no variants");
+    Ok(rows)
+}
+
+// Integers may be decimal, or hexadecimal or binary with a `0x` or `0b`
+// prefix, as they often are in a datasheet.
+fn parse_integer(text: &str) -> Result<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)?
+    } else {
+        digits.parse::<i64>()?
+    };
+    Ok(if negative { -value } else { value })
+}
+
+// The narrowest width that holds all the values, which is signed if
+// any of them is negative.
+fn min_width(values: impl Iterator<Item = i64> + Clone) -> usize {
+    let signed = values.clone().any(|x| x < 0);
+    (1..=64)
+        .find(|width| values.clone().all(|x| fits(x, *width, signed)))
+        .unwrap_or(64)
+}
+
+fn fits(value: i64, width: usize, signed: bool) -> bool {
+    let value = value as i128;
+    if signed {
+        let min = -(1_i128 << (width - 1));
+        (min..-min).contains(&value)
+    } else {
+        (0..(1_i128 << width)).contains(&value)
+    }
+}
+
+fn validate(rows: &[Row], options: &EnumTableOptions) -> Result<()> {
+    let mut names = HashSet::new();
+    let mut discriminants = BTreeMap::new();
+    for row in rows {
+        ensure!(
+            names.insert(row.name.as_str()),
+            "Line {}: duplicate variant name {}",
+            row.line,
+            row.name
+        );
+        if let Some(other) = discriminants.insert(row.discriminant, row.name.as_str()) {
+            bail!(
+                "Line {}: {} has the same discriminant ({}) as {other}",
+                row.line,
+                row.name,
+                row.discriminant
+            );
+        }
+    }
+    if let Some(width) = options.discriminant_width {
+        ensure!(
+            (1..=64).contains(&width),
+            "A discriminant width of {width} is not supported"
+        );
+        let signed = rows.iter().any(|row| row.discriminant < 0);
+        for row in rows {
+            ensure!(
+                fits(row.discriminant, width, signed),
+                "Line {}: the discriminant {} of {} does not fit in {width} {} bits",
+                row.line,
+                row.discriminant,
+                row.name,
+                if signed { "signed" } else { "unsigned" }
+            );
+        }
+    }
+    Ok(())
+}
+
+fn payload_type(options: &EnumTableOptions, row: &Row) -> Option<String> {
+    row.payload_width.map(|width| {
+        if options.newtype_payloads {
+            format!("{}{}", options.name, row.name)
+        } else {
+            format!("rhdl_bits::Bits<{width}>")
+        }
+    })
+}
+
+const HEADER: &str = "// Generated by rhdl_codegen.  Do not edit.\n";
+
+// Emit the source for a `#[derive(Digital)]` enum with a variant for each
+// row of the table.  If no variant has a discriminant of zero, the first
+// one is the default.
+pub fn enum_from_table(reader: impl Read, options: &EnumTableOptions) -> Result<String> {
+    ensure!(
+        is_identifier(&options.name),
+        "`{}` is not a valid enum name",
+        options.name
+    );
+    let rows = parse_table(reader)?;
+    validate(&rows, options)?;
+    let mut code = String::from(HEADER);
+    if options.newtype_payloads {
+        for row in &rows {
+            if let Some(width) = row.payload_width {
+                writeln!(
+                    code,
+                    "#[derive(Copy, Clone, PartialEq, Debug, Default, rhdl_macro::Digital)]"
+                )?;
+                writeln!(
+                    code,
+                    "pub struct {}{}(pub rhdl_bits::Bits<{width}>);\n",
+                    options.name, row.name
+                )?;
+            }
+        }
+    }
+    writeln!(
+        code,
+        "#[derive(Copy, Clone, PartialEq, Debug, rhdl_macro::Digital)]"
+    )?;
+    if let Some(width) = options.discriminant_width {
+        writeln!(code, "#[rhdl(discriminant_width = {width})]")?;
+    }
+    writeln!(code, "#[repr(i64)]")?;
+    writeln!(code, "pub enum {} {{", options.name)?;
+    let has_zero = rows.iter().any(|row| row.discriminant == 0);
+    for (ndx, row) in rows.iter().enumerate() {
+        if ndx == 0 && !has_zero {
+            writeln!(code, "    #[rhdl(default)]")?;
+        }
+        match payload_type(options, row) {
+            Some(payload) => writeln!(code, "    {}({payload}) = {},", row.name, row.discriminant)?,
+            None => writeln!(code, "    {} = {},", row.name, row.discriminant)?,
+        }
+    }
+    writeln!(code, "}}")?;
+    Ok(code)
+}
+
+// Emit the source for a kernel named `kernel` that maps a value of the
+// enum generated by `enum_from_table` to its entry in the given attribute
+// column.  The entries must be non-negative integers, and the kernel
+// returns the narrowest `Bits` that holds them all.
+pub fn lookup_kernel_from_table(
+    reader: impl Read,
+    options: &EnumTableOptions,
+    column: &str,
+    kernel: &str,
+) -> Result<String> {
+    ensure!(
+        is_identifier(kernel),
+        "`{kernel}` is not a valid kernel name"
+    );
+    let rows = parse_table(reader)?;
+    validate(&rows, options)?;
+    let values = rows
+        .iter()
+        .map(|row| {
+            let value = row
+                .attributes
+                .get(column)
+                .ok_or(anyhow!("The table has no `{column}` column"))?;
+            let value = parse_integer(value)
+                .with_context(|| format!("Line {}: bad {column} for {}", row.line, row.name))?;
+            ensure!(
+                value >= 0,
+                "Line {}: the {column} of {} is negative",
+                row.line,
+                row.name
+            );
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let width = min_width(values.iter().copied());
+    let name = &options.name;
+    let mut code = String::from(HEADER);
+    writeln!(code, "#[rhdl_macro::kernel]")?;
+    writeln!(
+        code,
+        "pub fn {kernel}(value: {name}) -> rhdl_bits::Bits<{width}> {{"
+    )?;
+    writeln!(code, "    match value {{")?;
+    for (row, value) in rows.iter().zip(values) {
+        let pattern = if row.payload_width.is_some() {
+            format!("{name}::{}(_payload)", row.name)
+        } else {
+            format!("{name}::{}", row.name)
+        };
+        writeln!(
+            code,
+            "        {pattern} => rhdl_bits::bits::<{width}>({value}),"
+        )?;
+    }
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")?;
+    Ok(code)
+}
@@ -0,0 +1,5 @@
+pub mod enum_table;
+
+pub use enum_table::enum_from_table;
+pub use enum_table::lookup_kernel_from_table;
+pub use enum_table::EnumTableOptions;
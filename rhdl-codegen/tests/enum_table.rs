@@ -0,0 +1,145 @@
+use rhdl_bits::{bits, Bits};
+use rhdl_codegen::{enum_from_table, lookup_kernel_from_table, EnumTableOptions};
+use rhdl_core::{
+    compile_design, digital_fn::DigitalFn, rhif::vm::execute_function, Digital, KernelFnKind, Kind,
+};
+
+mod generated {
+    include!("fixtures/commands.rs");
+    include!("fixtures/command_latency.rs");
+    include!("fixtures/pins.rs");
+}
+
+use generated::{command_latency, Command, Pin, PinLed, PinUart};
+
+const COMMANDS: &str = include_str!("fixtures/commands.csv");
+const PINS: &str = include_str!("fixtures/pins.csv");
+
+fn command_options() -> EnumTableOptions {
+    EnumTableOptions {
+        name: "Command".into(),
+        discriminant_width: Some(4),
+        newtype_payloads: false,
+    }
+}
+
+fn pin_options() -> EnumTableOptions {
+    EnumTableOptions {
+        name: "Pin".into(),
+        discriminant_width: None,
+        newtype_payloads: true,
+    }
+}
+
+#[test]
+fn test_fixtures_match_the_generator() -> anyhow::Result<()> {
+    assert_eq!(
+        enum_from_table(COMMANDS.as_bytes(), &command_options())?,
+        include_str!("fixtures/commands.rs")
+    );
+    assert_eq!(
+        lookup_kernel_from_table(
+            COMMANDS.as_bytes(),
+            &command_options(),
+            "latency",
+            "command_latency"
+        )?,
+        include_str!("fixtures/command_latency.rs")
+    );
+    assert_eq!(
+        enum_from_table(PINS.as_bytes(), &pin_options())?,
+        include_str!("fixtures/pins.rs")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_generated_enum_kind() {
+    let Kind::Enum(kind) = Command::static_kind() else {
+        panic!("Command is not an enum");
+    };
+    assert_eq!(kind.discriminant_layout.width, 4);
+    let variants = kind
+        .variants
+        .iter()
+        .map(|v| (v.name.as_str(), v.discriminant, v.kind.bits()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        variants,
+        vec![
+            ("Nop", 0, 0),
+            ("Read", 1, 8),
+            ("Write", 2, 16),
+            ("Refresh", 10, 0)
+        ]
+    );
+    assert_eq!(Command::static_kind().bits(), 4 + 16);
+    assert_eq!(Command::default(), Command::Nop);
+    // Without a zero discriminant, the first variant is the default
+    let Kind::Enum(kind) = Pin::static_kind() else {
+        panic!("Pin is not an enum");
+    };
+    assert_eq!(kind.discriminant_layout.width, 2);
+    assert_eq!(
+        kind.variants[2].kind,
+        Kind::make_tuple(vec![PinUart::static_kind()])
+    );
+    assert_eq!(Pin::default(), Pin::Led(PinLed(Bits::default())));
+}
+
+#[test]
+fn test_generated_lookup_kernel() -> anyhow::Result<()> {
+    let Some(KernelFnKind::Kernel(kernel)) = command_latency::kernel_fn() else {
+        panic!("Kernel not found");
+    };
+    let design = compile_design(kernel)?;
+    for (command, latency) in [
+        (Command::Nop, 1),
+        (Command::Read(bits(3)), 4),
+        (Command::Write(bits(1000)), 2),
+        (Command::Refresh, 9),
+    ] {
+        assert_eq!(command_latency(command), bits::<4>(latency));
+        assert_eq!(
+            execute_function(&design, vec![command.typed_bits()])?,
+            bits::<4>(latency).typed_bits()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_names_are_rejected() {
+    let table = "name,discriminant\nIdle,0\nBusy,1\nIdle,2\n";
+    let err = enum_from_table(table.as_bytes(), &EnumTableOptions::default()).unwrap_err();
+    assert_eq!(err.to_string(), "Line 4: duplicate variant name Idle");
+    let table = "name,discriminant\nIdle,0\nBusy,0\n";
+    let err = enum_from_table(table.as_bytes(), &EnumTableOptions::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Line 3: Busy has the same discriminant (0) as Idle"
+    );
+}
+
+#[test]
+fn test_out_of_range_discriminants_are_rejected() {
+    let options = EnumTableOptions {
+        discriminant_width: Some(3),
+        ..Default::default()
+    };
+    let table = "name,discriminant\nIdle,0\nBusy,8\n";
+    let err = enum_from_table(table.as_bytes(), &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Line 3: the discriminant 8 of Busy does not fit in 3 unsigned bits"
+    );
+    let table = "name,discriminant\nIdle,0\nBusy,-5\n";
+    let err = enum_from_table(table.as_bytes(), &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Line 3: the discriminant -5 of Busy does not fit in 3 signed bits"
+    );
+    // The lookup kernel checks the same table
+    let table = "name,discriminant,latency\nIdle,0,1\nBusy,8,2\n";
+    assert!(lookup_kernel_from_table(table.as_bytes(), &options, "latency", "latency").is_err());
+}
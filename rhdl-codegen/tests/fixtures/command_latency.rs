@@ -0,0 +1,10 @@
+// Generated by rhdl_codegen.  Do not edit.
+#[rhdl_macro::kernel]
+pub fn command_latency(value: Command) -> rhdl_bits::Bits<4> {
+    match value {
+        Command::Nop => rhdl_bits::bits::<4>(1),
+        Command::Read(_payload) => rhdl_bits::bits::<4>(4),
+        Command::Write(_payload) => rhdl_bits::bits::<4>(2),
+        Command::Refresh => rhdl_bits::bits::<4>(9),
+    }
+}
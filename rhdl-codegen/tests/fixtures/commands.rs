@@ -0,0 +1,10 @@
+// Generated by rhdl_codegen.  Do not edit.
+#[derive(Copy, Clone, PartialEq, Debug, rhdl_macro::Digital)]
+#[rhdl(discriminant_width = 4)]
+#[repr(i64)]
+pub enum Command {
+    Nop = 0,
+    Read(rhdl_bits::Bits<8>) = 1,
+    Write(rhdl_bits::Bits<16>) = 2,
+    Refresh = 10,
+}
@@ -0,0 +1,15 @@
+// Generated by rhdl_codegen.  Do not edit.
+#[derive(Copy, Clone, PartialEq, Debug, Default, rhdl_macro::Digital)]
+pub struct PinLed(pub rhdl_bits::Bits<3>);
+
+#[derive(Copy, Clone, PartialEq, Debug, Default, rhdl_macro::Digital)]
+pub struct PinUart(pub rhdl_bits::Bits<2>);
+
+#[derive(Copy, Clone, PartialEq, Debug, rhdl_macro::Digital)]
+#[repr(i64)]
+pub enum Pin {
+    #[rhdl(default)]
+    Led(PinLed) = 1,
+    Button = 2,
+    Uart(PinUart) = 3,
+}
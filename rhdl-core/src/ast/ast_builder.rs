@@ -1,5 +1,6 @@
 use std::hash::{Hash, Hasher};
 
+use crate::codegen::mux::MuxStrategy;
 use crate::kernel::KernelFnKind;
 use crate::types::typed_bits::TypedBits;
 use crate::DigitalSignature;
@@ -12,7 +13,15 @@ pub fn binary_expr(op: BinOp, lhs: Box<Expr>, rhs: Box<Expr>) -> Box<Expr> {
     })
 }
 
+// Negating an integer literal gives a negative literal, rather than the
+// negation of a positive one.  That way `-32` is checked against the
+// width of its type as a negative value (and fits in 6 signed bits).
 pub fn unary_expr(op: UnOp, expr: Box<Expr>) -> Box<Expr> {
+    if let (UnOp::Neg, ExprKind::Lit(ExprLit::Int(value))) = (&op, &expr.kind) {
+        if !value.starts_with('-') {
+            return lit_expr(ExprLit::Int(format!("-{value}")));
+        }
+    }
     Box::new(Expr {
         id: INVALID_NODE_ID,
         kind: ExprKind::Unary(ExprUnary { op, expr }),
@@ -115,6 +124,8 @@ pub fn arm_wild(body: Box<Expr>) -> Box<Arm> {
         id: INVALID_NODE_ID,
         kind: ArmKind::Wild,
         body,
+        guard: None,
+        cfg: None,
     })
 }
 
@@ -123,9 +134,34 @@ pub fn arm_constant(value: ExprLit, body: Box<Expr>) -> Box<Arm> {
         id: INVALID_NODE_ID,
         kind: ArmKind::Constant(ArmConstant { value }),
         body,
+        guard: None,
+        cfg: None,
+    })
+}
+
+pub fn arm_cases(mut cases: Vec<ArmKind>, body: Box<Expr>) -> Box<Arm> {
+    let kind = if cases.len() == 1 {
+        cases.remove(0)
+    } else {
+        ArmKind::Or(ArmOr { cases })
+    };
+    Box::new(Arm {
+        id: INVALID_NODE_ID,
+        kind,
+        body,
+        guard: None,
+        cfg: None,
     })
 }
 
+pub fn arm_kind_constant(value: ExprLit) -> ArmKind {
+    ArmKind::Constant(ArmConstant { value })
+}
+
+pub fn arm_kind_range(start: ExprLit, limits: RangeLimits, end: ExprLit) -> ArmKind {
+    ArmKind::Range(ArmRange { start, limits, end })
+}
+
 pub fn arm_enum(
     pat: Box<Pat>,
     template: TypedBits,
@@ -140,6 +176,8 @@ pub fn arm_enum(
             payload_kind,
         }),
         body,
+        guard: None,
+        cfg: None,
     })
 }
 
@@ -344,7 +382,38 @@ pub fn local_stmt(pat: Box<Pat>, init: Option<Box<Expr>>) -> Box<Stmt> {
             id: INVALID_NODE_ID,
             pat,
             init,
+            probe: None,
+            keep: false,
+        })),
+        cfg: None,
+    })
+}
+
+pub fn probe_local_stmt(pat: Box<Pat>, init: Option<Box<Expr>>, name: &str) -> Box<Stmt> {
+    Box::new(Stmt {
+        id: INVALID_NODE_ID,
+        kind: StmtKind::Local(Box::new(Local {
+            id: INVALID_NODE_ID,
+            pat,
+            init,
+            probe: Some(name.into()),
+            keep: false,
         })),
+        cfg: None,
+    })
+}
+
+pub fn keep_local_stmt(pat: Box<Pat>, init: Option<Box<Expr>>, name: &str) -> Box<Stmt> {
+    Box::new(Stmt {
+        id: INVALID_NODE_ID,
+        kind: StmtKind::Local(Box::new(Local {
+            id: INVALID_NODE_ID,
+            pat,
+            init,
+            probe: Some(name.into()),
+            keep: true,
+        })),
+        cfg: None,
     })
 }
 
@@ -352,6 +421,7 @@ pub fn semi_stmt(expr: Box<Expr>) -> Box<Stmt> {
     Box::new(Stmt {
         id: INVALID_NODE_ID,
         kind: StmtKind::Semi(expr),
+        cfg: None,
     })
 }
 
@@ -359,9 +429,28 @@ pub fn expr_stmt(expr: Box<Expr>) -> Box<Stmt> {
     Box::new(Stmt {
         id: INVALID_NODE_ID,
         kind: StmtKind::Expr(expr),
+        cfg: None,
     })
 }
 
+// Mark a statement as compiled only when the given flag is enabled.
+pub fn cfg_stmt(mut stmt: Box<Stmt>, flag: &str) -> Box<Stmt> {
+    stmt.cfg = Some(flag.into());
+    stmt
+}
+
+// Add an `if` guard to a match arm.
+pub fn guard_arm(mut arm: Box<Arm>, guard: Box<Expr>) -> Box<Arm> {
+    arm.guard = Some(guard);
+    arm
+}
+
+// Mark a match arm as compiled only when the given flag is enabled.
+pub fn cfg_arm(mut arm: Box<Arm>, flag: &str) -> Box<Arm> {
+    arm.cfg = Some(flag.into());
+    arm
+}
+
 pub fn block_expr(block: Box<Block>) -> Box<Expr> {
     Box::new(Expr {
         id: INVALID_NODE_ID,
@@ -429,11 +518,54 @@ pub fn kernel_fn(
             ret,
             body,
             fn_id,
+            pure: false,
+            const_eval: false,
+            params: vec![],
+            mux_strategy: None,
         })
         .into(),
     )
 }
 
+pub fn pure_kernel_fn(
+    name: &str,
+    inputs: Vec<Box<Pat>>,
+    ret: Kind,
+    body: Box<Block>,
+    fn_id: std::any::TypeId,
+) -> KernelFnKind {
+    let mut kernel = kernel_fn(name, inputs, ret, body, fn_id);
+    if let KernelFnKind::Kernel(kernel) = &mut kernel {
+        kernel.inner_mut().pure = true;
+    }
+    kernel
+}
+
+// Mark the inputs at the given positions as parameters.
+pub fn with_params(mut kernel: KernelFnKind, params: Vec<usize>) -> KernelFnKind {
+    if let KernelFnKind::Kernel(kernel) = &mut kernel {
+        kernel.inner_mut().params = params;
+    }
+    kernel
+}
+
+// Mark the kernel so that its calls with constant arguments are
+// evaluated at compile time.
+pub fn with_const_eval(mut kernel: KernelFnKind) -> KernelFnKind {
+    if let KernelFnKind::Kernel(kernel) = &mut kernel {
+        kernel.inner_mut().const_eval = true;
+    }
+    kernel
+}
+
+// Choose how the dynamic indices of the kernel are written in Verilog.
+pub fn with_mux_strategy(mut kernel: KernelFnKind, strategy: MuxStrategy) -> KernelFnKind {
+    if let KernelFnKind::Kernel(kernel) = &mut kernel {
+        kernel.inner_mut().mux_strategy = Some(strategy);
+    }
+    kernel
+}
+
 pub fn expr_typed_bits(path: Box<Path>, value: TypedBits) -> Box<Expr> {
     Box::new(Expr {
         id: INVALID_NODE_ID,
@@ -1,11 +1,14 @@
 use std::fmt::Display;
 
-use crate::{kernel::KernelFnKind, types::typed_bits::TypedBits, DigitalSignature, Kind};
+use crate::{
+    codegen::mux::MuxStrategy, kernel::KernelFnKind, types::typed_bits::TypedBits,
+    DigitalSignature, Kind,
+};
 use serde::{Deserialize, Serialize};
 
 // Modeled after rustc's AST
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Hash, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Hash, Eq, PartialOrd, Ord)]
 pub struct NodeId(Option<u32>);
 
 impl NodeId {
@@ -37,6 +40,10 @@ impl Display for NodeId {
 pub struct Stmt {
     pub id: NodeId,
     pub kind: StmtKind,
+    // The flag that must be enabled for the statement to be compiled, if
+    // it is marked `#[rhdl(cfg(flag = "..."))]`.
+    #[serde(default)]
+    pub cfg: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,6 +64,12 @@ pub struct Local {
     pub id: NodeId,
     pub pat: Box<Pat>,
     pub init: Option<Box<Expr>>,
+    // The name of the probe, if the binding is marked `#[rhdl(probe)]`.
+    #[serde(default)]
+    pub probe: Option<String>,
+    // Set by `#[rhdl(keep)]`, which also makes the binding a probe.
+    #[serde(default)]
+    pub keep: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -367,12 +380,21 @@ pub struct Arm {
     pub id: NodeId,
     pub kind: ArmKind,
     pub body: Box<Expr>,
+    // The `if` guard of the arm, which is evaluated with the bindings of
+    // the pattern in scope.
+    #[serde(default)]
+    pub guard: Option<Box<Expr>>,
+    // As for `Stmt`.
+    #[serde(default)]
+    pub cfg: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ArmKind {
     Wild,
     Constant(ArmConstant),
+    Range(ArmRange),
+    Or(ArmOr),
     Enum(ArmEnum),
 }
 
@@ -381,6 +403,18 @@ pub struct ArmConstant {
     pub value: ExprLit,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmRange {
+    pub start: ExprLit,
+    pub limits: RangeLimits,
+    pub end: ExprLit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmOr {
+    pub cases: Vec<ArmKind>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArmEnum {
     pub pat: Box<Pat>,
@@ -444,4 +478,23 @@ pub struct KernelFn {
     pub ret: Kind,
     pub body: Box<Block>,
     pub fn_id: FunctionId,
+    // Set by `#[kernel(pure)]`.  Calls to a pure kernel may be
+    // memoized by the interpreter.
+    #[serde(default)]
+    pub pure: bool,
+    // Set by `#[kernel(const_eval)]`.  Calls to the kernel with literal
+    // arguments are replaced by their result when the caller is
+    // compiled.
+    #[serde(default)]
+    pub const_eval: bool,
+    // The positions of the inputs marked with `#[rhdl(param)]`.  These
+    // are configuration constants, that can be fixed when the design is
+    // compiled (see `compile_design_with_params`).
+    #[serde(default)]
+    pub params: Vec<usize>,
+    // Set by `#[kernel(mux = "...")]`.  How dynamic indices into arrays
+    // are written in the Verilog for the kernel, in place of the strategy
+    // in the compile options.
+    #[serde(default)]
+    pub mux_strategy: Option<MuxStrategy>,
 }
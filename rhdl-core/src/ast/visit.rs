@@ -113,6 +113,9 @@ pub fn visit_match_arm<V>(visitor: &mut V, arm: &Arm) -> Result<()>
 where
     V: Visitor + ?Sized,
 {
+    if let Some(guard) = &arm.guard {
+        visitor.visit_expr(guard)?;
+    }
     visitor.visit_expr(&arm.body)?;
     Ok(())
 }
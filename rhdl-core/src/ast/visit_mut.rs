@@ -117,6 +117,9 @@ where
     if let ArmKind::Enum(enum_arm) = &mut arm.kind {
         visitor.visit_mut_pat(&mut enum_arm.pat)?;
     }
+    if let Some(guard) = &mut arm.guard {
+        visitor.visit_mut_expr(guard)?;
+    }
     visitor.visit_mut_expr(&mut arm.body)?;
     Ok(())
 }
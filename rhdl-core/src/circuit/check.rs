@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::compiler::driver::installed_options;
+use crate::path::{bit_range, leaf_paths, Path};
+use crate::rhif::spec::{
+    Array, Assert, Assign, Binary, Case, Cast, Enum, Exec, Index, Member, OpCode, Repeat, Select,
+    Slot, Splice, Struct, Tuple, Unary,
+};
+use crate::rhif::Object;
+use crate::{compile_design, Circuit, CircuitDescriptor, DigitalFn, KernelFnKind, Kind, Module};
+
+// Check the wiring between a circuit's update kernel and its children.
+// This catches a common composition bug, where a child is added to the
+// circuit, but the update kernel never routes anything to its inputs
+// (the child's field of D is only ever set from a default or zero
+// literal), or never looks at its outputs (the child's field of Q is
+// never read).  Both compile and simulate without complaint, but
+// produce silently wrong hardware.
+//
+// Only the circuit itself is checked, not its children.  All of the
+// problems found are reported together in the returned error.
+pub fn check_circuit<C: Circuit>(circuit: &C) -> Result<()> {
+    let Some(KernelFnKind::Kernel(kernel)) = C::Update::kernel_fn() else {
+        bail!("No kernel function for {}", circuit.name());
+    };
+    let descriptor = circuit.descriptor();
+    descriptor.check_size(installed_options().max_kind_bits)?;
+    let module = compile_design(kernel)?;
+    descriptor.check_update_signature()?;
+    let issues = check_wiring(&descriptor, &module)?;
+    if !issues.is_empty() {
+        bail!(
+            "Circuit {} has wiring problems:\n  {}",
+            circuit.name(),
+            issues.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+fn check_wiring(descriptor: &CircuitDescriptor, module: &Module) -> Result<Vec<String>> {
+    let mut issues = vec![];
+    // Sort the children so that the report is stable from run to run
+    let mut names = descriptor.children.keys().collect::<Vec<_>>();
+    names.sort();
+    // First check that D and Q have a field for each child of the right type
+    for name in &names {
+        let child = &descriptor.children[*name];
+        for (port, kind, child_kind) in [
+            ("D", &descriptor.d_kind, &child.input_kind),
+            ("Q", &descriptor.q_kind, &child.output_kind),
+        ] {
+            match bit_range(kind.clone(), &Path::default().field(name)) {
+                Ok((_, field_kind)) if field_kind == *child_kind => {}
+                Ok((_, field_kind)) => issues.push(format!(
+                    "field `{name}` of {port} has type {field_kind:?}, but child `{name}` needs {child_kind:?}"
+                )),
+                Err(_) => issues.push(format!("{port} has no field for child `{name}`")),
+            }
+        }
+    }
+    for (port, kind) in [("D", &descriptor.d_kind), ("Q", &descriptor.q_kind)] {
+        if let Kind::Struct(structure) = kind {
+            for field in &structure.fields {
+                if !descriptor.children.contains_key(&field.name) {
+                    issues.push(format!(
+                        "field `{}` of {port} does not belong to any child",
+                        field.name
+                    ));
+                }
+            }
+        }
+    }
+    if !issues.is_empty() {
+        return Ok(issues);
+    }
+    let obj = &module.objects[&module.top];
+    let flow = WiringFlow::new(obj)?;
+    // The update kernel returns (O, D), so find where D lives in the return value
+    let ret_kind = flow.kind(&obj.return_slot)?;
+    let (d_range, _) = bit_range(ret_kind, &Path::default().index(1))?;
+    let driven = &flow.driven(&obj.return_slot)?[d_range];
+    for name in &names {
+        let child = &descriptor.children[*name];
+        let (child_range, _) = bit_range(descriptor.d_kind.clone(), &Path::default().field(name))?;
+        let child_driven = &driven[child_range];
+        for path in leaf_paths(&child.input_kind, Path::default()) {
+            let (leaf_range, _) = bit_range(child.input_kind.clone(), &path)?;
+            if !leaf_range.is_empty() && !child_driven[leaf_range].iter().any(|x| *x) {
+                issues.push(format!(
+                    "input `{name}{path}` of child `{name}` is never driven"
+                ));
+            }
+        }
+    }
+    for name in &names {
+        let (child_range, _) = bit_range(descriptor.q_kind.clone(), &Path::default().field(name))?;
+        if !child_range.is_empty() && !flow.q_used[child_range].iter().any(|x| *x) {
+            issues.push(format!("output of child `{name}` is never read"));
+        }
+    }
+    Ok(issues)
+}
+
+// A simple forward analysis of the update kernel.  For each register,
+// we track which of its bits could carry something other than a zero
+// (or default) literal.  For the Q argument, we track which bits are
+// actually read.  Indexing into Q just narrows the view, so that
+// taking `q.child` and never using it does not count as a read.
+struct WiringFlow<'a> {
+    obj: &'a Object,
+    driven: HashMap<Slot, Vec<bool>>,
+    q_views: HashMap<Slot, Range<usize>>,
+    q_used: Vec<bool>,
+}
+
+impl<'a> WiringFlow<'a> {
+    fn new(obj: &'a Object) -> Result<Self> {
+        let mut flow = WiringFlow {
+            obj,
+            driven: HashMap::new(),
+            q_views: HashMap::new(),
+            q_used: vec![],
+        };
+        for arg in &obj.arguments {
+            let bits = flow.kind(arg)?.bits();
+            flow.driven.insert(*arg, vec![true; bits]);
+        }
+        if let Some(q) = obj.arguments.get(1) {
+            let bits = flow.kind(q)?.bits();
+            flow.q_used = vec![false; bits];
+            flow.q_views.insert(*q, 0..bits);
+        }
+        for op in &obj.ops {
+            flow.op(op)?;
+        }
+        flow.read(&obj.return_slot);
+        Ok(flow)
+    }
+
+    fn kind(&self, slot: &Slot) -> Result<Kind> {
+        match slot {
+            Slot::Empty => Ok(Kind::Empty),
+            Slot::Literal(_) => Ok(self.obj.literal(*slot)?.kind.clone()),
+            Slot::Register(_) => self.obj.kind.get(slot).cloned().ok_or(anyhow!(
+                "No type for slot {} in {}",
+                slot,
+                self.obj.name
+            )),
+        }
+    }
+
+    fn driven(&self, slot: &Slot) -> Result<Vec<bool>> {
+        match slot {
+            Slot::Empty => Ok(vec![]),
+            Slot::Literal(_) => Ok(self.obj.literal(*slot)?.bits.clone()),
+            Slot::Register(_) => match self.driven.get(slot) {
+                Some(driven) => Ok(driven.clone()),
+                None => Ok(vec![true; self.kind(slot)?.bits()]),
+            },
+        }
+    }
+
+    fn any_driven(&self, slots: &[Slot]) -> Result<bool> {
+        for slot in slots {
+            if self.driven(slot)?.iter().any(|x| *x) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn read(&mut self, slot: &Slot) {
+        if let Some(range) = self.q_views.get(slot) {
+            self.q_used[range.clone()]
+                .iter_mut()
+                .for_each(|x| *x = true);
+        }
+    }
+
+    fn set(&mut self, lhs: &Slot, driven: Vec<bool>) {
+        self.driven.insert(*lhs, driven);
+    }
+
+    fn set_all(&mut self, lhs: &Slot, args: &[Slot]) -> Result<()> {
+        let bits = self.kind(lhs)?.bits();
+        let any = self.any_driven(args)?;
+        self.set(lhs, vec![any; bits]);
+        Ok(())
+    }
+
+    fn splice(&self, driven: &mut [bool], kind: &Kind, path: &Path, value: &Slot) -> Result<()> {
+        let (range, _) = bit_range(kind.clone(), path)?;
+        driven[range].copy_from_slice(&self.driven(value)?);
+        Ok(())
+    }
+
+    fn members(
+        &mut self,
+        lhs: &Slot,
+        mut driven: Vec<bool>,
+        base: Path,
+        fields: &[(Member, Slot)],
+    ) -> Result<()> {
+        let kind = self.kind(lhs)?;
+        for (member, value) in fields {
+            self.read(value);
+            let path = match member {
+                Member::Unnamed(ndx) => base.clone().index(*ndx as usize),
+                Member::Named(name) => base.clone().field(name),
+            };
+            self.splice(&mut driven, &kind, &path, value)?;
+        }
+        self.set(lhs, driven);
+        Ok(())
+    }
+
+    fn op(&mut self, op: &OpCode) -> Result<()> {
+        match op {
+            OpCode::Noop | OpCode::Comment(_) => {}
+            OpCode::Assert(Assert { cond, .. }) => self.read(cond),
+            OpCode::Binary(Binary {
+                lhs, arg1, arg2, ..
+            }) => {
+                self.read(arg1);
+                self.read(arg2);
+                self.set_all(lhs, &[*arg1, *arg2])?;
+            }
+            OpCode::Unary(Unary { lhs, arg1, .. }) => {
+                self.read(arg1);
+                self.set_all(lhs, &[*arg1])?;
+            }
+            OpCode::AsBits(Cast { lhs, arg, .. }) | OpCode::AsSigned(Cast { lhs, arg, .. }) => {
+                self.read(arg);
+                self.set_all(lhs, &[*arg])?;
+            }
+            OpCode::Repeat(Repeat { lhs, value, .. }) => {
+                self.read(value);
+                self.set_all(lhs, &[*value])?;
+            }
+            OpCode::Exec(Exec { lhs, args, .. }) => {
+                args.iter().for_each(|arg| self.read(arg));
+                self.set_all(lhs, args)?;
+            }
+            OpCode::Assign(Assign { lhs, rhs }) => {
+                if let Some(range) = self.q_views.get(rhs).cloned() {
+                    self.q_views.insert(*lhs, range);
+                }
+                self.set(lhs, self.driven(rhs)?);
+            }
+            OpCode::Select(Select {
+                lhs,
+                cond,
+                true_value,
+                false_value,
+            }) => {
+                self.read(cond);
+                self.read(true_value);
+                self.read(false_value);
+                let driven = self
+                    .driven(true_value)?
+                    .into_iter()
+                    .zip(self.driven(false_value)?)
+                    .map(|(a, b)| a | b)
+                    .collect();
+                self.set(lhs, driven);
+            }
+            OpCode::Case(Case {
+                lhs,
+                discriminant,
+                table,
+            }) => {
+                self.read(discriminant);
+                let mut driven = vec![false; self.kind(lhs)?.bits()];
+                for (_, slot) in table {
+                    self.read(slot);
+                    driven
+                        .iter_mut()
+                        .zip(self.driven(slot)?)
+                        .for_each(|(a, b)| *a |= b);
+                }
+                self.set(lhs, driven);
+            }
+            OpCode::Index(Index { lhs, arg, path }) => {
+                path.dynamic_slots().for_each(|slot| self.read(slot));
+                if path.any_dynamic() {
+                    self.read(arg);
+                    self.set_all(lhs, &[*arg])?;
+                } else {
+                    let (range, _) = bit_range(self.kind(arg)?, path)?;
+                    if let Some(view) = self.q_views.get(arg) {
+                        let start = view.start + range.start;
+                        self.q_views.insert(*lhs, start..start + range.len());
+                    }
+                    self.set(lhs, self.driven(arg)?[range].to_vec());
+                }
+            }
+            OpCode::Splice(Splice {
+                lhs,
+                orig,
+                path,
+                subst,
+            }) => {
+                path.dynamic_slots().for_each(|slot| self.read(slot));
+                self.read(orig);
+                self.read(subst);
+                let mut driven = self.driven(orig)?;
+                if path.any_dynamic() {
+                    if self.any_driven(&[*subst])? {
+                        driven.iter_mut().for_each(|x| *x = true);
+                    }
+                } else {
+                    self.splice(&mut driven, &self.kind(orig)?, path, subst)?;
+                }
+                self.set(lhs, driven);
+            }
+            OpCode::Tuple(Tuple { lhs, fields }) => {
+                let fields = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(ndx, slot)| (Member::Unnamed(ndx as u32), *slot))
+                    .collect::<Vec<_>>();
+                let bits = self.kind(lhs)?.bits();
+                self.members(lhs, vec![false; bits], Path::default(), &fields)?;
+            }
+            OpCode::Array(Array { lhs, elements }) => {
+                let kind = self.kind(lhs)?;
+                let mut driven = vec![false; kind.bits()];
+                for (ndx, element) in elements.iter().enumerate() {
+                    self.read(element);
+                    self.splice(&mut driven, &kind, &Path::default().index(ndx), element)?;
+                }
+                self.set(lhs, driven);
+            }
+            OpCode::Struct(Struct {
+                lhs,
+                fields,
+                rest,
+                template,
+            }) => {
+                let driven = match rest {
+                    Some(rest) => {
+                        self.read(rest);
+                        self.driven(rest)?
+                    }
+                    None => template.bits.clone(),
+                };
+                let fields = fields
+                    .iter()
+                    .map(|f| (f.member.clone(), f.value))
+                    .collect::<Vec<_>>();
+                self.members(lhs, driven, Path::default(), &fields)?;
+            }
+            OpCode::Enum(Enum {
+                lhs,
+                fields,
+                template,
+            }) => {
+                let base = Path::default().payload_by_value(template.discriminant()?.as_i64()?);
+                let fields = fields
+                    .iter()
+                    .map(|f| (f.member.clone(), f.value))
+                    .collect::<Vec<_>>();
+                self.members(lhs, template.bits.clone(), base, &fields)?;
+            }
+        }
+        Ok(())
+    }
+}
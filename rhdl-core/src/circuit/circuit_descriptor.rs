@@ -1,19 +1,104 @@
 use crate::circuit::circuit_impl::Tristate;
-use crate::path::Path;
+use crate::path::{bit_range, Path};
 use crate::rhif::spec::Member;
 use crate::schematic::builder::build_schematic;
 use crate::schematic::components::{
     BlackBoxComponent, ComponentKind, FieldPin, IndexComponent, KernelComponent, StructComponent,
 };
 use crate::schematic::schematic_impl::Schematic;
-use crate::types::digital::Digital;
+use crate::types::digital::{Digital, TimingException};
 use crate::types::digital_fn::DigitalFn;
-use crate::{compile_design, KernelFnKind};
+use crate::compiler::check_signature::compiled_signature;
+use crate::{compile_design, DigitalSignature, KernelFnKind, Module};
 use crate::{util::hash_id, Kind};
-use std::collections::HashMap;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
 
 use super::circuit_impl::Circuit;
 
+// A value marked with `#[rhdl(probe)]` in the update kernel of a circuit,
+// or in one of the kernels it calls (the `function`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProbeDescriptor {
+    pub name: String,
+    pub function: String,
+    pub kind: Kind,
+}
+
+// A timing exception on a field of the input (`i`), output (`o`), D
+// (`d`) or Q (`q`) of a circuit, from the attributes on its types.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TimingDescriptor {
+    pub port: String,
+    pub path: Path,
+    pub exception: TimingException,
+}
+
+// The doc comment on a field of one of the ports of a circuit, from the
+// `///` lines on the fields of its types.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DocDescriptor {
+    pub port: String,
+    pub path: Path,
+    pub doc: String,
+}
+
+// A clock of a circuit: the (single bit) field of its input that
+// carries the clock, from `#[rhdl(clock = field)]`.  A testbench drives
+// each of them on its own schedule (see `ClockedTestbench`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClockDomainDescriptor {
+    pub name: String,
+    pub path: Path,
+}
+
+// The names of the input and output ports of the module generated for a
+// circuit.  They are `i` and `o` unless the circuit picks others, such as
+// when the module is instantiated in a hand-written top level.  Inside
+// the module (and in timing exceptions), the ports are still `i` and `o`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PortNames {
+    pub input: String,
+    pub output: String,
+}
+
+impl Default for PortNames {
+    fn default() -> Self {
+        Self {
+            input: "i".into(),
+            output: "o".into(),
+        }
+    }
+}
+
+impl PortNames {
+    pub fn check(&self) -> Result<()> {
+        for (name, default) in [(&self.input, "i"), (&self.output, "o")] {
+            let mut chars = name.chars();
+            ensure!(
+                chars
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$'),
+                "The port name `{name}` is not a Verilog identifier"
+            );
+            ensure!(
+                name == default || !["i", "o", "io", "od", "d", "q"].contains(&name.as_str()),
+                "The port name `{name}` clashes with a wire of the generated module"
+            );
+        }
+        ensure!(
+            self.input != self.output,
+            "The input and output ports are both named `{}`",
+            self.input
+        );
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CircuitDescriptor {
     pub unique_name: String,
@@ -24,12 +109,298 @@ pub struct CircuitDescriptor {
     pub num_tristate: usize,
     pub tristate_offset_in_parent: usize,
     pub update_schematic: Option<Schematic>,
-    pub children: HashMap<String, CircuitDescriptor>,
+    pub probes: Vec<ProbeDescriptor>,
+    // If set, the generated Verilog connects each child through named
+    // wires (`<child>_d` and `<child>_q`) rather than slices of `d` and `q`.
+    pub named_child_wires: bool,
+    pub port_names: PortNames,
+    // The flags tested by the update kernel (and the kernels it calls),
+    // or by the children marked `#[rhdl(cfg(flag = "..."))]`, and whether
+    // each was enabled.
+    pub flags: BTreeMap<String, bool>,
+    // The signature that the update kernel was compiled to, if the
+    // circuit has one.
+    pub update_signature: Option<DigitalSignature>,
+    pub timing: Vec<TimingDescriptor>,
+    // The doc comment on the circuit, if it has one, and those on the
+    // fields of its ports.
+    pub doc: Option<String>,
+    pub field_docs: Vec<DocDescriptor>,
+    pub clock_domains: Vec<ClockDomainDescriptor>,
+    // The children are shared, so that a design with many copies of the
+    // same circuit can hold one descriptor for all of them (see
+    // `dedup_children`).
+    pub children: HashMap<String, Arc<CircuitDescriptor>>,
 }
 
 impl CircuitDescriptor {
     pub fn add_child<C: Circuit>(&mut self, name: &str, circuit: &C) {
-        self.children.insert(name.into(), circuit.descriptor());
+        self.insert_child(name, circuit.descriptor());
+    }
+    pub fn insert_child(&mut self, name: &str, descriptor: CircuitDescriptor) {
+        self.children.insert(name.into(), Arc::new(descriptor));
+    }
+    pub fn with_clock_domain(mut self, name: &str, path: Path) -> Self {
+        self.clock_domains.push(ClockDomainDescriptor {
+            name: name.into(),
+            path,
+        });
+        self
+    }
+    pub fn with_named_child_wires(self) -> Self {
+        Self {
+            named_child_wires: true,
+            ..self
+        }
+    }
+    // The update kernel maps the input of the circuit and the outputs of
+    // its children (Q) to the output of the circuit and the inputs of its
+    // children (D).
+    pub fn expected_update_signature(&self) -> DigitalSignature {
+        DigitalSignature {
+            arguments: vec![self.input_kind.clone(), self.q_kind.clone()],
+            ret: Kind::make_tuple(vec![self.output_kind.clone(), self.d_kind.clone()]),
+        }
+    }
+    // The update kernel compiles on its own, whatever the types of the
+    // circuit, so a kernel that does not fit them would otherwise only
+    // show up as badly sliced Verilog.
+    pub fn check_update_signature(&self) -> Result<()> {
+        let Some(actual) = &self.update_signature else {
+            return Ok(());
+        };
+        let expected = self.expected_update_signature();
+        ensure!(
+            *actual == expected,
+            "The update kernel of circuit {} has the signature {actual}, but the circuit needs {expected}, i.e., [I, Q] -> (O, D)",
+            self.unique_name
+        );
+        Ok(())
+    }
+    fn port_kind(&self, port: &str) -> Option<&Kind> {
+        match port {
+            "i" => Some(&self.input_kind),
+            "o" => Some(&self.output_kind),
+            "d" => Some(&self.d_kind),
+            "q" => Some(&self.q_kind),
+            _ => None,
+        }
+    }
+    // Find the bits of the signal that each timing exception is on.  An
+    // exception must name a field that exists (and has bits), since the
+    // attributes of a hand written `Digital` impl are not checked.
+    pub fn resolve_timing(&self) -> Result<Vec<(&TimingDescriptor, Range<usize>)>> {
+        self.timing
+            .iter()
+            .map(|timing| {
+                let name = format!("{}{}", timing.port, timing.path);
+                let kind = self.port_kind(&timing.port).ok_or_else(|| {
+                    anyhow!(
+                        "The timing exception on {name} in {} is not on i, o, d or q",
+                        self.unique_name
+                    )
+                })?;
+                let (range, _) = bit_range(kind.clone(), &timing.path).with_context(|| {
+                    format!(
+                        "The timing exception on {name} in {} does not name a signal",
+                        self.unique_name
+                    )
+                })?;
+                ensure!(
+                    !range.is_empty(),
+                    "The timing exception on {name} in {} names a signal with no bits",
+                    self.unique_name
+                );
+                if let TimingException::Multicycle(cycles) = timing.exception {
+                    ensure!(
+                        cycles >= 2,
+                        "The multicycle path on {name} in {} must take at least 2 cycles",
+                        self.unique_name
+                    );
+                }
+                Ok((timing, range))
+            })
+            .collect()
+    }
+    // The number of bits in the ports (I, O, D and Q) of the circuit and
+    // of all of its children.  Each port, each circuit and the design as
+    // a whole must fit in `limit` bits (`CompileOptions::max_kind_bits`).
+    pub fn check_size(&self, limit: usize) -> Result<usize> {
+        let name = &self.unique_name;
+        let own = [
+            ("i", &self.input_kind),
+            ("o", &self.output_kind),
+            ("d", &self.d_kind),
+            ("q", &self.q_kind),
+        ]
+        .into_iter()
+        .map(|(port, kind)| {
+            kind.checked_bits(limit)
+                .map_err(|err| anyhow!("Port {port} of circuit {name} is too wide: {err}"))
+        })
+        .sum::<Result<usize>>()?;
+        ensure!(
+            own <= limit,
+            "Circuit {name} has {own} bits in its I, O, D and Q, which is more than the limit of {limit} bits"
+        );
+        let total = self
+            .child_names()
+            .into_iter()
+            .map(|child| self.children[child].check_size(limit))
+            .sum::<Result<usize>>()?
+            + own;
+        ensure!(
+            total <= limit,
+            "The design of circuit {name} has {total} bits in the ports of its circuits, which is more than the limit of {limit} bits"
+        );
+        Ok(total)
+    }
+    // A hash of everything in the descriptor, and in those of its
+    // children.  The update schematic is left out, since it is compiled
+    // from the update kernel, which is fixed by the type of the circuit,
+    // and so by the `unique_name`.  Descriptors that are the same (see
+    // `same_structure`) have the same hash.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = fnv::FnvHasher::default();
+        self.unique_name.hash(&mut hasher);
+        self.input_kind.hash(&mut hasher);
+        self.output_kind.hash(&mut hasher);
+        self.d_kind.hash(&mut hasher);
+        self.q_kind.hash(&mut hasher);
+        self.num_tristate.hash(&mut hasher);
+        self.tristate_offset_in_parent.hash(&mut hasher);
+        self.probes.hash(&mut hasher);
+        self.named_child_wires.hash(&mut hasher);
+        self.port_names.hash(&mut hasher);
+        self.flags.hash(&mut hasher);
+        self.update_signature.hash(&mut hasher);
+        self.timing.hash(&mut hasher);
+        self.clock_domains.hash(&mut hasher);
+        for name in self.child_names() {
+            name.hash(&mut hasher);
+            self.children[name].structural_hash().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+    // Whether the two descriptors are the same in everything that goes
+    // into the `structural_hash`.
+    pub fn same_structure(&self, other: &CircuitDescriptor) -> bool {
+        self.unique_name == other.unique_name
+            && self.input_kind == other.input_kind
+            && self.output_kind == other.output_kind
+            && self.d_kind == other.d_kind
+            && self.q_kind == other.q_kind
+            && self.num_tristate == other.num_tristate
+            && self.tristate_offset_in_parent == other.tristate_offset_in_parent
+            && self.probes == other.probes
+            && self.named_child_wires == other.named_child_wires
+            && self.port_names == other.port_names
+            && self.flags == other.flags
+            && self.update_signature == other.update_signature
+            && self.timing == other.timing
+            && self.clock_domains == other.clock_domains
+            && self.children.len() == other.children.len()
+            && self.children.iter().all(|(name, child)| {
+                other
+                    .children
+                    .get(name)
+                    .is_some_and(|other| Arc::ptr_eq(child, other) || child.same_structure(other))
+            })
+    }
+    // Replace the children (at every level) that are the same as another
+    // child in the tree with a reference to a single copy, so that a
+    // design that instantiates a circuit many times holds (and walks) one
+    // descriptor for it.  Nothing else about the tree changes.
+    pub fn dedup_children(&mut self) {
+        self.intern_children(&mut HashMap::new());
+    }
+    // The interned descriptors are kept by their hash, and each is checked
+    // to be the same before it is shared, in case two hashes collide.
+    fn intern_children(&mut self, interned: &mut HashMap<u64, Vec<Arc<CircuitDescriptor>>>) {
+        for child in self.children.values_mut() {
+            let hash = child.structural_hash();
+            let shared = interned
+                .get(&hash)
+                .and_then(|shared| shared.iter().find(|shared| shared.same_structure(child)));
+            match shared {
+                Some(shared) => *child = shared.clone(),
+                None => {
+                    Arc::make_mut(child).intern_children(interned);
+                    interned.entry(hash).or_default().push(child.clone());
+                }
+            }
+        }
+    }
+    // The number of distinct descriptors below this one in the tree, with
+    // each shared descriptor counted once.
+    pub fn distinct_descendants(&self) -> usize {
+        fn walk(circuit: &CircuitDescriptor, seen: &mut HashSet<*const CircuitDescriptor>) {
+            for child in circuit.children.values() {
+                if seen.insert(Arc::as_ptr(child)) {
+                    walk(child, seen);
+                }
+            }
+        }
+        let mut seen = HashSet::new();
+        walk(self, &mut seen);
+        seen.len()
+    }
+    fn child_names(&self) -> Vec<&String> {
+        let mut names = self.children.keys().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+    // The number of bits in the flattened state of the design, which is
+    // the `q` of the circuit, followed by the flattened state of each of
+    // its children (in the order of their names).
+    pub fn flat_bits(&self) -> usize {
+        self.q_kind.bits()
+            + self
+                .children
+                .values()
+                .map(|child| child.flat_bits())
+                .sum::<usize>()
+    }
+    // The offset of the flattened state of a child in the flattened state
+    // of the circuit.
+    fn flat_offset_of_child(&self, name: &str) -> usize {
+        self.q_kind.bits()
+            + self
+                .child_names()
+                .into_iter()
+                .take_while(|child| child.as_str() != name)
+                .map(|child| self.children[child].flat_bits())
+                .sum::<usize>()
+    }
+    // Find a signal in the hierarchy below the circuit, given a dotted
+    // path like `child0.child1.reg_a` or `child0.history[2]`.  The
+    // leading parts name children, down to the circuit that holds the
+    // signal, and the rest is a path into the `q` of that circuit (i.e.,
+    // the outputs of its own children, as its update kernel sees them),
+    // with `[n]` to index an array or tuple.  So `child0.child1` is the
+    // output of `child1` as `child0` sees it.  The range is of the bits in
+    // the flattened state of the design (see `flat_bits`), with the offset
+    // of each child added on the way down.  This is the hierarchical
+    // analogue of `bit_range`.
+    pub fn resolve_path(&self, dotted: &str) -> Result<(Range<usize>, Kind)> {
+        let parts = dotted.split('.').collect::<Vec<_>>();
+        let mut circuit = self;
+        let mut offset = 0;
+        let mut rest = &parts[..];
+        while let [part, tail @ ..] = rest {
+            let Some(child) = circuit.children.get(*part).filter(|_| !tail.is_empty()) else {
+                break;
+            };
+            offset += circuit.flat_offset_of_child(part);
+            circuit = child;
+            rest = tail;
+        }
+        let (range, kind) = rest
+            .iter()
+            .try_fold(Path::default(), |path, part| dotted_part(path, part))
+            .and_then(|path| bit_range(circuit.q_kind.clone(), &path))
+            .with_context(|| format!("Cannot resolve {dotted} in {}", self.unique_name))?;
+        Ok((range.start + offset..range.end + offset, kind))
     }
     // This is a drawing of the circuit dfg construction
     //
@@ -62,6 +433,15 @@ impl CircuitDescriptor {
     //     |                            |
     //     +--< Out    child 1     In <-+
     pub fn schematic(&self) -> Option<Schematic> {
+        self.try_schematic().ok()
+    }
+    // As `schematic`, but with the reason that there is none
+    pub fn try_schematic(&self) -> Result<Schematic> {
+        self.check_update_signature()?;
+        let update_schematic = self
+            .update_schematic
+            .clone()
+            .ok_or_else(|| anyhow!("Circuit {} has no update schematic", self.unique_name))?;
         let mut schematic = Schematic::default();
         // The input and output buffers hold the pins that enter and leave the schematic
         let (input_buffer_in, input_buffer_out) =
@@ -108,7 +488,7 @@ impl CircuitDescriptor {
             ComponentKind::Kernel(KernelComponent {
                 name: "update".into(),
                 args: vec![update_input_pin, update_q_pin],
-                sub_schematic: self.update_schematic.clone()?,
+                sub_schematic: update_schematic,
                 output: update_output_pin,
             }),
             None,
@@ -175,7 +555,9 @@ impl CircuitDescriptor {
                 schematic.make_pin(child_descriptor.input_kind.clone(), name.clone(), None);
             let child_output_pin =
                 schematic.make_pin(child_descriptor.output_kind.clone(), name.clone(), None);
-            let sub_schematic = child_descriptor.schematic()?;
+            let sub_schematic = child_descriptor
+                .try_schematic()
+                .with_context(|| format!("In child {name} of {}", self.unique_name))?;
             let child_component = schematic.make_component(
                 ComponentKind::Kernel(KernelComponent {
                     name: name.clone(),
@@ -197,21 +579,116 @@ impl CircuitDescriptor {
         }
         schematic.inputs = vec![input_buffer_in];
         schematic.output = output_buffer_out;
-        Some(schematic)
+        Ok(schematic)
     }
 }
 
-fn root_schematic<C: Circuit>() -> Option<Schematic> {
-    if let Some(KernelFnKind::Kernel(kernel)) = C::Update::kernel_fn() {
-        let module = compile_design(kernel).ok()?;
-        let schematic = build_schematic(&module, module.top).ok()?;
-        Some(schematic)
-    } else {
-        None
+// One part of a dotted path: a field name (or the index of a tuple
+// field), followed by any number of `[n]` indices.
+fn dotted_part(path: Path, part: &str) -> Result<Path> {
+    let mut pieces = part.split('[');
+    let name = pieces.next().unwrap_or_default();
+    let mut path = match name.parse::<usize>() {
+        Ok(ndx) => path.index(ndx),
+        Err(_) if !name.is_empty() => path.field(name),
+        Err(_) => bail!("Missing a field name in `{part}`"),
+    };
+    for piece in pieces {
+        let ndx = piece
+            .strip_suffix(']')
+            .and_then(|ndx| ndx.parse().ok())
+            .ok_or_else(|| anyhow!("Invalid index in `{part}`"))?;
+        path = path.index(ndx);
     }
+    Ok(path)
+}
+
+fn probes(module: &Module) -> Vec<ProbeDescriptor> {
+    module
+        .objects
+        .values()
+        .flat_map(|obj| {
+            obj.probes.iter().filter_map(|probe| {
+                Some(ProbeDescriptor {
+                    name: probe.name.clone(),
+                    function: obj.name.clone(),
+                    kind: obj.kind.get(&probe.slot)?.clone(),
+                })
+            })
+        })
+        .collect()
+}
+
+fn flags(module: &Module) -> BTreeMap<String, bool> {
+    module
+        .objects
+        .values()
+        .flat_map(|obj| obj.flags.clone())
+        .collect()
+}
+
+fn timing<C: Circuit>() -> Vec<TimingDescriptor> {
+    [
+        ("i", C::I::timing_exceptions()),
+        ("o", C::O::timing_exceptions()),
+        ("d", C::D::timing_exceptions()),
+        ("q", C::Q::timing_exceptions()),
+    ]
+    .into_iter()
+    .flat_map(|(port, exceptions)| {
+        exceptions
+            .into_iter()
+            .map(move |(path, exception)| TimingDescriptor {
+                port: port.into(),
+                path,
+                exception,
+            })
+    })
+    .collect()
+}
+
+fn field_docs<C: Circuit>() -> Vec<DocDescriptor> {
+    [
+        ("i", C::I::doc_comments()),
+        ("o", C::O::doc_comments()),
+        ("d", C::D::doc_comments()),
+        ("q", C::Q::doc_comments()),
+    ]
+    .into_iter()
+    .flat_map(|(port, docs)| {
+        docs.into_iter().map(move |(path, doc)| DocDescriptor {
+            port: port.into(),
+            path,
+            doc,
+        })
+    })
+    .collect()
+}
+
+// The schematic, the probes, the flags and the signature of the update
+// kernel, which share a compilation of the kernel.
+fn root_update<C: Circuit>() -> (
+    Option<Schematic>,
+    Vec<ProbeDescriptor>,
+    BTreeMap<String, bool>,
+    Option<DigitalSignature>,
+) {
+    let Some(KernelFnKind::Kernel(kernel)) = C::Update::kernel_fn() else {
+        return Default::default();
+    };
+    let Ok(module) = compile_design(kernel) else {
+        return Default::default();
+    };
+    (
+        build_schematic(&module, module.top).ok(),
+        probes(&module),
+        flags(&module),
+        compiled_signature(&module.objects[&module.top]).ok(),
+    )
 }
 
 pub fn root_descriptor<C: Circuit>(circuit: &C) -> CircuitDescriptor {
+    let (update_schematic, probes, flags, update_signature) = root_update::<C>();
     CircuitDescriptor {
         unique_name: format!(
             "{}_{:x}",
@@ -223,8 +700,258 @@ pub fn root_descriptor<C: Circuit>(circuit: &C) -> CircuitDescriptor {
         d_kind: C::D::static_kind(),
         q_kind: C::Q::static_kind(),
         num_tristate: C::Z::N,
-        update_schematic: root_schematic::<C>(),
+        update_schematic,
+        probes,
+        named_child_wires: false,
+        port_names: Default::default(),
+        flags,
+        update_signature,
+        timing: timing::<C>(),
+        doc: None,
+        field_docs: field_docs::<C>(),
+        clock_domains: vec![],
         tristate_offset_in_parent: 0,
         children: Default::default(),
     }
 }
+
+// A descriptor with a name and nothing else, for building designs by
+// hand in tests.
+#[cfg(test)]
+impl CircuitDescriptor {
+    pub(crate) fn named(name: &str) -> Self {
+        CircuitDescriptor {
+            unique_name: name.into(),
+            input_kind: Kind::Empty,
+            output_kind: Kind::Empty,
+            d_kind: Kind::Empty,
+            q_kind: Kind::Empty,
+            num_tristate: 0,
+            tristate_offset_in_parent: 0,
+            update_schematic: None,
+            probes: vec![],
+            named_child_wires: false,
+            port_names: Default::default(),
+            flags: Default::default(),
+            update_signature: None,
+            timing: vec![],
+            doc: None,
+            field_docs: vec![],
+            clock_domains: vec![],
+            children: Default::default(),
+        }
+    }
+}
+
+// A counter of decimal digits, with the state of its inner module as an
+// enum, for the tests of the design manifest and the docs built from it.
+#[cfg(test)]
+pub(crate) fn two_level_design() -> CircuitDescriptor {
+    use crate::{DiscriminantAlignment, DiscriminantType};
+
+    let state = Kind::make_enum(
+        "State",
+        vec![
+            Kind::make_variant("Idle", Kind::Empty, 0),
+            Kind::make_variant("Run", Kind::make_bits(4), 1),
+            Kind::make_variant(
+                "Done",
+                Kind::make_tuple(vec![Kind::make_bool(), Kind::make_signed(3)]),
+                2,
+            ),
+        ],
+        Kind::make_discriminant_layout(2, DiscriminantAlignment::Msb, DiscriminantType::Unsigned),
+    );
+    let mut inner = CircuitDescriptor {
+        input_kind: Kind::make_struct(
+            "Inner",
+            vec![
+                Kind::make_field("clock", Kind::make_bool()),
+                Kind::make_field("data", Kind::make_array(Kind::make_bits(3), 2)),
+                Kind::make_field("digit", Kind::make_bits_ranged(4, 0, 9)),
+            ],
+        ),
+        output_kind: state.clone(),
+        ..CircuitDescriptor::named("inner_1234")
+    };
+    inner.probes.push(ProbeDescriptor {
+        name: "next_digit".into(),
+        function: "inner_update".into(),
+        kind: Kind::make_bits(4),
+    });
+    inner.doc = Some("Counts one decimal digit.".into());
+    inner.field_docs.push(DocDescriptor {
+        port: "i".into(),
+        path: Path::default().field("digit"),
+        doc: "The digit to count from".into(),
+    });
+    inner.flags.insert("debug_counters".into(), true);
+    inner.timing.push(TimingDescriptor {
+        port: "i".into(),
+        path: Path::default().field("data").index(1),
+        exception: TimingException::Multicycle(2),
+    });
+    let mut top = CircuitDescriptor {
+        input_kind: Kind::make_bits(8),
+        output_kind: Kind::make_bits(8),
+        d_kind: Kind::make_struct(
+            "TopD",
+            vec![Kind::make_field("inner", inner.input_kind.clone())],
+        ),
+        q_kind: Kind::make_struct("TopQ", vec![Kind::make_field("inner", state)]),
+        ..CircuitDescriptor::named("top_5678")
+    };
+    top.insert_child("inner", inner);
+    top
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(name: &str, q_kind: Kind) -> CircuitDescriptor {
+        CircuitDescriptor {
+            q_kind,
+            ..CircuitDescriptor::named(name)
+        }
+    }
+
+    #[test]
+    fn test_resolve_path_through_two_levels() {
+        let leaf = descriptor(
+            "leaf",
+            Kind::make_struct(
+                "LeafQ",
+                vec![
+                    Kind::make_field("reg_a", Kind::make_bits(4)),
+                    Kind::make_field("reg_b", Kind::make_bits(3)),
+                ],
+            ),
+        );
+        let mut middle = descriptor(
+            "middle",
+            Kind::make_struct(
+                "MiddleQ",
+                vec![
+                    Kind::make_field("history", Kind::make_array(Kind::make_bits(2), 3)),
+                    Kind::make_field("child1", Kind::make_bits(5)),
+                ],
+            ),
+        );
+        middle.insert_child("child1", leaf);
+        let mut top = descriptor(
+            "top",
+            Kind::make_struct(
+                "TopQ",
+                vec![
+                    Kind::make_field("alpha", Kind::make_bits(6)),
+                    Kind::make_field("child0", Kind::make_bits(1)),
+                ],
+            ),
+        );
+        top.insert_child("child0", middle);
+        top.insert_child("alpha", descriptor("alpha", Kind::Empty));
+        // The flattened state is the q of top (7 bits), then that of
+        // alpha (none), then that of child0 (11 bits), and last that of
+        // child1 (7 bits)
+        assert_eq!(top.flat_bits(), 25);
+        // The leaf signals are in the q of child1, at 7 + 11 bits
+        let (range, kind) = top.resolve_path("child0.child1.reg_a").unwrap();
+        assert_eq!(range, 18..22);
+        assert_eq!(kind, Kind::make_bits(4));
+        let (range, _) = top.resolve_path("child0.child1.reg_b").unwrap();
+        assert_eq!(range, 22..25);
+        // A path that stops at a circuit gives its output, as its parent
+        // sees it
+        let (range, kind) = top.resolve_path("child0.child1").unwrap();
+        assert_eq!(range, 13..18);
+        assert_eq!(kind, Kind::make_bits(5));
+        let (range, _) = top.resolve_path("alpha").unwrap();
+        assert_eq!(range, 0..6);
+        let (range, kind) = top.resolve_path("child0.history[2]").unwrap();
+        assert_eq!(range, 11..13);
+        assert_eq!(kind, Kind::make_bits(2));
+        let err = top.resolve_path("child0.child1.reg_c").unwrap_err();
+        assert!(err.to_string().contains("child0.child1.reg_c"));
+        let err = top.resolve_path("child0.history[x]").unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid index in `history[x]`"));
+    }
+
+    #[test]
+    fn test_dedup_children_shares_identical_descriptors() {
+        // A bank of 100 counters, each with a register of its own, and
+        // one circuit that differs from them in its q
+        let counter = || {
+            let mut counter = descriptor("counter", Kind::make_bits(8));
+            counter.insert_child("reg", descriptor("reg", Kind::make_bits(8)));
+            counter
+        };
+        let mut top = descriptor("top", Kind::make_bits(1));
+        for ndx in 0..100 {
+            top.insert_child(&format!("counter_{ndx}"), counter());
+        }
+        let mut other = counter();
+        other.q_kind = Kind::make_bits(4);
+        top.insert_child("other", other);
+        let hash = top.structural_hash();
+        assert_eq!(top.distinct_descendants(), 202);
+        top.dedup_children();
+        // The counters share one descriptor, as do all of the registers
+        // (including that of the other circuit)
+        assert_eq!(top.distinct_descendants(), 3);
+        let counters = top
+            .children
+            .iter()
+            .filter(|(name, _)| name.starts_with("counter_"))
+            .map(|(_, child)| child)
+            .collect::<Vec<_>>();
+        assert_eq!(counters.len(), 100);
+        assert!(counters.iter().all(|child| Arc::ptr_eq(child, counters[0])));
+        assert!(!Arc::ptr_eq(&top.children["other"], counters[0]));
+        assert_eq!(top.structural_hash(), hash);
+        // The interned counter is the only one of its kind
+        let mut bank = descriptor("bank", Kind::make_bits(1));
+        for ndx in 0..100 {
+            bank.insert_child(
+                &format!("counter_{ndx}"),
+                descriptor("counter", Kind::make_bits(8)),
+            );
+        }
+        bank.dedup_children();
+        assert_eq!(bank.distinct_descendants(), 1);
+    }
+
+    #[test]
+    fn test_same_structure_looks_at_the_children() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        // Shared descriptors can be handed to other threads
+        assert_send_sync::<CircuitDescriptor>();
+        let with_reg = |width| {
+            let mut counter = descriptor("counter", Kind::make_bits(8));
+            counter.insert_child("reg", descriptor("reg", Kind::make_bits(width)));
+            counter
+        };
+        assert!(with_reg(8).same_structure(&with_reg(8)));
+        assert!(!with_reg(8).same_structure(&with_reg(4)));
+        let mut renamed = with_reg(8);
+        let reg = renamed.children.remove("reg").unwrap();
+        renamed.children.insert("other".into(), reg);
+        assert!(!with_reg(8).same_structure(&renamed));
+    }
+
+    #[test]
+    fn test_check_size_sums_the_design() {
+        let mut top = descriptor("top", Kind::make_bits(5));
+        top.insert_child("alpha", descriptor("alpha", Kind::make_bits(6)));
+        assert_eq!(top.check_size(16).unwrap(), 11);
+        let err = top.check_size(8).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The design of circuit top has 11 bits in the ports of its circuits, which is more than the limit of 8 bits"
+        );
+        let err = top.check_size(4).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Port q of circuit top is too wide: b5 is 5 bits wide"));
+    }
+}
@@ -1,5 +1,8 @@
-use crate::{Digital, DigitalFn};
+use crate::test_module::{circuit_test_module, masked_circuit_test_module, TestModule};
+use crate::{Digital, DigitalFn, TypedBits};
 
+use super::clocked_testbench::{clocked_test_module, ClockedTestbench};
+use super::output_mask::{MaskStats, OutputMask};
 use super::{circuit_descriptor::CircuitDescriptor, hdl_descriptor::HDLDescriptor};
 
 pub type CircuitUpdateFn<C> =
@@ -25,8 +28,50 @@ pub trait CircuitIO: 'static + Sized + Clone {
 
 pub struct NoUpdateFn {}
 
+// The Circuit derive calls the update kernel through `run_update_kernel`,
+// with the types of the circuit given explicitly.  A kernel whose signature
+// does not match the circuit is then reported as not being an `UpdateKernel`
+// (with a readable message), rather than as a type error in generated code.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not an update kernel for this circuit",
+    label = "expected an update kernel of type `fn({I}, {Q}) -> ({O}, {D})`",
+    note = "an update kernel has the form `fn(I, Q) -> (O, D)`, with the input (I) and output (O) of the circuit, and the outputs (Q) and inputs (D) of its children"
+)]
+pub trait UpdateKernel<I, Q, O, D> {
+    fn update(&self, input: I, q: Q) -> (O, D);
+}
+
+impl<I, Q, O, D> UpdateKernel<I, Q, O, D> for fn(I, Q) -> (O, D) {
+    fn update(&self, input: I, q: Q) -> (O, D) {
+        self(input, q)
+    }
+}
+
+pub fn run_update_kernel<I, Q, O, D>(
+    kernel: impl UpdateKernel<I, Q, O, D>,
+    input: I,
+    q: Q,
+) -> (O, D) {
+    kernel.update(input, q)
+}
+
 impl DigitalFn for NoUpdateFn {}
 
+// Name the state signals of the child circuit `name`.  Used by the
+// Circuit derive.
+pub fn child_state_signals(
+    name: &str,
+    signals: Vec<(String, TypedBits)>,
+) -> impl Iterator<Item = (String, TypedBits)> + '_ {
+    signals.into_iter().map(move |(signal, value)| {
+        if signal.is_empty() {
+            (name.to_string(), value)
+        } else {
+            (format!("{name}.{signal}"), value)
+        }
+    })
+}
+
 pub trait Circuit: 'static + Sized + Clone + CircuitIO {
     type D: Digital;
     type Q: Digital;
@@ -48,6 +93,13 @@ pub trait Circuit: 'static + Sized + Clone + CircuitIO {
         Default::default()
     }
 
+    // The named registers held in the simulation state, for debugging.
+    // Registers report themselves with an empty name, and the derive
+    // prefixes the signals of each child with the name of the child.
+    fn state_signals(&self, _state: &Self::S) -> Vec<(String, TypedBits)> {
+        vec![]
+    }
+
     // auto derived
     fn name(&self) -> &'static str;
 
@@ -57,6 +109,55 @@ pub trait Circuit: 'static + Sized + Clone + CircuitIO {
     // auto derived
     fn as_hdl(&self, kind: HDLKind) -> anyhow::Result<HDLDescriptor>;
 
+    // Build a testbench that drives the HDL for this circuit with the
+    // given inputs, and checks it against the simulation.  Run it with
+    // `TestModule::run_iverilog`.
+    fn testbench(&self, inputs: &[Self::I]) -> anyhow::Result<TestModule> {
+        circuit_test_module(self, inputs)
+    }
+
+    // As `testbench`, but the don't-care bits of the output given by
+    // `mask` are left out of the comparison.
+    fn masked_testbench(
+        &self,
+        inputs: &[Self::I],
+        mask: &OutputMask<Self::O>,
+    ) -> anyhow::Result<TestModule> {
+        masked_circuit_test_module(self, inputs, mask)
+    }
+
+    // As `testbench`, but with the clocks in the inputs driven on their
+    // own schedules (see `ClockedTestbench`).  The clock fields of the
+    // given inputs are ignored.
+    fn clocked_testbench(
+        &self,
+        bench: &ClockedTestbench,
+        inputs: &[Self::I],
+    ) -> anyhow::Result<TestModule> {
+        clocked_test_module(self, bench, inputs)
+    }
+
+    // For a combinational circuit, check that the HDL agrees with the
+    // simulation on `samples` random inputs, and on the inputs with all
+    // bits clear and all bits set.  The first input on which they
+    // disagree is reported.
+    #[cfg(feature = "iverilog")]
+    fn check_combinational_equivalence(&self, samples: usize) -> anyhow::Result<()> {
+        super::equivalence::check_combinational_equivalence(self, samples)
+    }
+
+    // As `check_combinational_equivalence`, but the don't-care bits of
+    // the output given by `mask` are left out of the comparison.  Returns
+    // the number of bits that were masked for each input.
+    #[cfg(feature = "iverilog")]
+    fn check_masked_combinational_equivalence(
+        &self,
+        samples: usize,
+        mask: &OutputMask<Self::O>,
+    ) -> anyhow::Result<MaskStats> {
+        super::equivalence::check_masked_combinational_equivalence(self, samples, mask)
+    }
+
     // auto derived
     // First is 0, then 0 + c0::NumZ, then 0 + c0::NumZ + c1::NumZ, etc
     fn z_offsets() -> impl Iterator<Item = usize> {
@@ -0,0 +1,504 @@
+// Testbenches for circuits with more than one clock.  The clocks are
+// the clock domains of the circuit (fields of its input, marked with
+// `#[rhdl(clock = field)]`), and each one is driven on its own schedule,
+// given by the `ClockSpec` of the same name.  The inputs are applied in
+// step with one of them (the primary clock).  The
+// simulation in Rust is run along the same timeline as the testbench, so
+// each child steps on the edges of its own clock.
+//
+// The timeline is made of the edges of all of the clocks, and of the
+// times at which the inputs change.  The inputs for cycle `k` are
+// applied on the falling edge of the primary clock before its `k`-th
+// rising edge (or at time 0 for the first cycle), so that they are held
+// steady for the low part of the period before the edge that samples
+// them.  An edge of another clock at the same time as a change of the
+// inputs sees the inputs from before the change.  The testbench does the
+// same by changing the inputs with nonblocking assignments.
+//
+// The outputs are checked 1ps after each sampled time, so the times on
+// the timeline must be at least 2ps apart.  Clocks whose periods are not
+// multiples of each other can bring edges closer than that, in which
+// case one of the phases has to be moved.
+use std::collections::BTreeSet;
+
+use anyhow::{bail, ensure, Result};
+
+use crate::path::bit_range;
+use crate::test_module::TestModule;
+use crate::{
+    as_verilog_literal, verify_initial_state, Circuit, CircuitIO, Digital, HDLKind,
+    HDLPortDirection,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClockSpec {
+    // The name of the clock domain of the circuit
+    pub name: String,
+    pub period_ps: u64,
+    // The time of the first rising edge.  The clock is low until then.
+    pub phase_ps: u64,
+    // The fraction of each period for which the clock is high
+    pub duty: f64,
+}
+
+impl ClockSpec {
+    // A clock with an even duty cycle, that first rises after half a
+    // period
+    pub fn new(name: &str, period_ps: u64) -> Self {
+        Self {
+            name: name.into(),
+            period_ps,
+            phase_ps: period_ps / 2,
+            duty: 0.5,
+        }
+    }
+    pub fn with_phase(mut self, phase_ps: u64) -> Self {
+        self.phase_ps = phase_ps;
+        self
+    }
+    pub fn with_duty(mut self, duty: f64) -> Self {
+        self.duty = duty;
+        self
+    }
+    fn high_ps(&self) -> u64 {
+        (self.period_ps as f64 * self.duty).round() as u64
+    }
+    fn level_at(&self, time: u64) -> bool {
+        time >= self.phase_ps && (time - self.phase_ps) % self.period_ps < self.high_ps()
+    }
+    fn is_edge_at(&self, time: u64) -> bool {
+        time > 0 && self.level_at(time) != self.level_at(time - 1)
+    }
+    fn rising_edge(&self, cycle: u64) -> u64 {
+        self.phase_ps + cycle * self.period_ps
+    }
+    // The times of the edges of the clock before `end`
+    fn edges_before(&self, end: u64) -> impl Iterator<Item = u64> + '_ {
+        (0..)
+            .map(|cycle| self.rising_edge(cycle))
+            .take_while(move |time| *time < end)
+            .flat_map(|time| [time, time + self.high_ps()])
+            .filter(move |time| *time < end)
+    }
+    fn check(&self) -> Result<()> {
+        ensure!(
+            self.phase_ps > 0,
+            "Clock {} starts low, so its first rising edge must come after time 0",
+            self.name
+        );
+        let high = self.high_ps();
+        ensure!(
+            high > 0 && high < self.period_ps,
+            "Clock {} with a period of {}ps and a duty of {} is never high or never low",
+            self.name,
+            self.period_ps,
+            self.duty
+        );
+        Ok(())
+    }
+}
+
+// When the outputs are checked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputSampling {
+    // After each rising edge of the primary clock
+    #[default]
+    PrimaryClock,
+    // After each edge of any of the clocks
+    EveryEdge,
+    // After each rising edge of the given clock, i.e., once per cycle
+    // of its domain
+    Clock(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct ClockedTestbench {
+    pub clocks: Vec<ClockSpec>,
+    // The index of the clock that the inputs are applied with
+    pub primary: usize,
+    pub sampling: OutputSampling,
+}
+
+impl ClockedTestbench {
+    pub fn new(clocks: Vec<ClockSpec>) -> Self {
+        Self {
+            clocks,
+            primary: 0,
+            sampling: OutputSampling::default(),
+        }
+    }
+    pub fn with_primary(mut self, primary: usize) -> Self {
+        self.primary = primary;
+        self
+    }
+    pub fn with_sampling(mut self, sampling: OutputSampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+    // The timeline for `cycles` inputs
+    pub(crate) fn schedule(&self, cycles: usize) -> Result<Vec<Step>> {
+        ensure!(cycles > 0, "A clocked testbench needs at least one input");
+        let Some(primary) = self.clocks.get(self.primary) else {
+            bail!(
+                "There is no clock {} to apply the inputs with",
+                self.primary
+            );
+        };
+        for clock in &self.clocks {
+            clock.check()?;
+        }
+        if let OutputSampling::Clock(ndx) = self.sampling {
+            ensure!(
+                ndx < self.clocks.len(),
+                "There is no clock {ndx} to sample the outputs with"
+            );
+        }
+        let change = |cycle: usize| match cycle {
+            0 => 0,
+            _ => primary.rising_edge(cycle as u64 - 1) + primary.high_ps(),
+        };
+        let end = change(cycles);
+        let mut times = (0..cycles).map(change).collect::<BTreeSet<_>>();
+        for clock in &self.clocks {
+            times.extend(clock.edges_before(end));
+        }
+        let times = times.into_iter().collect::<Vec<_>>();
+        if let Some(pair) = times.windows(2).find(|pair| pair[1] - pair[0] < 2) {
+            bail!(
+                "The clock edges and input changes at {}ps and {}ps are too close together to check the outputs in between",
+                pair[0],
+                pair[1]
+            );
+        }
+        let mut input = 0;
+        Ok(times
+            .into_iter()
+            .map(|time| {
+                if input + 1 < cycles && change(input + 1) == time {
+                    input += 1;
+                }
+                let sample = match self.sampling {
+                    OutputSampling::PrimaryClock => {
+                        primary.is_edge_at(time) && primary.level_at(time)
+                    }
+                    OutputSampling::EveryEdge => {
+                        self.clocks.iter().any(|clock| clock.is_edge_at(time))
+                    }
+                    OutputSampling::Clock(ndx) => {
+                        let clock = &self.clocks[ndx];
+                        clock.is_edge_at(time) && clock.level_at(time)
+                    }
+                };
+                Step {
+                    time_ps: time,
+                    input,
+                    clocks: self
+                        .clocks
+                        .iter()
+                        .map(|clock| clock.level_at(time))
+                        .collect(),
+                    sample,
+                }
+            })
+            .collect())
+    }
+}
+
+// A point on the timeline of a clocked testbench
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Step {
+    pub time_ps: u64,
+    // The index of the input applied from this time on
+    pub input: usize,
+    // The level of each clock from this time on
+    pub clocks: Vec<bool>,
+    pub sample: bool,
+}
+
+impl ClockedTestbench {
+    // The bit of the input that carries each clock.  Each clock domain of
+    // the circuit needs a `ClockSpec`, and each `ClockSpec` must name a
+    // clock domain.
+    fn clock_bits<C: Circuit>(&self, circuit: &C) -> Result<Vec<usize>> {
+        let descriptor = circuit.descriptor();
+        for domain in &descriptor.clock_domains {
+            ensure!(
+                self.clocks.iter().any(|clock| clock.name == domain.name),
+                "There is no ClockSpec for the clock {} of {}",
+                domain.name,
+                circuit.name()
+            );
+        }
+        self.clocks
+            .iter()
+            .map(|clock| {
+                let Some(domain) = descriptor
+                    .clock_domains
+                    .iter()
+                    .find(|domain| domain.name == clock.name)
+                else {
+                    bail!(
+                        "Circuit {} has no clock {} (clocks are marked with #[rhdl(clock = field)])",
+                        circuit.name(),
+                        clock.name
+                    );
+                };
+                let (range, _) = bit_range(C::I::static_kind(), &domain.path)?;
+                ensure!(
+                    range.len() == 1,
+                    "The clock {} ({}) of {} is not a single bit",
+                    clock.name,
+                    domain.path,
+                    circuit.name()
+                );
+                Ok(range.start)
+            })
+            .collect()
+    }
+    // Run the circuit along the timeline for the given inputs, and call
+    // `visit` with each step, and the output at the end of it.  The
+    // clocks change first, so that their edges see the inputs from
+    // before the change.
+    fn run<C: Circuit>(
+        &self,
+        circuit: &C,
+        inputs: &[<C as CircuitIO>::I],
+        mut visit: impl FnMut(&Step, &<C as CircuitIO>::O),
+    ) -> Result<()> {
+        let clock_bits = self.clock_bits(circuit)?;
+        let drive = |input: &C::I, clocks: &[bool]| -> Result<C::I> {
+            let mut bits = input.bin();
+            for (bit, level) in clock_bits.iter().zip(clocks) {
+                bits[*bit] = *level;
+            }
+            C::I::maybe_from_bin(&bits)
+        };
+        let mut state = circuit.init_state();
+        let mut io = C::Z::default();
+        let mut previous = 0;
+        for step in &self.schedule(inputs.len())? {
+            let mut output =
+                circuit.sim(drive(&inputs[previous], &step.clocks)?, &mut state, &mut io);
+            if step.input != previous {
+                output = circuit.sim(
+                    drive(&inputs[step.input], &step.clocks)?,
+                    &mut state,
+                    &mut io,
+                );
+            }
+            previous = step.input;
+            visit(step, &output);
+        }
+        Ok(())
+    }
+    // The outputs of the circuit at each of the sampled times, as the
+    // testbench expects them
+    pub fn simulate<C: Circuit>(
+        &self,
+        circuit: &C,
+        inputs: &[<C as CircuitIO>::I],
+    ) -> Result<Vec<<C as CircuitIO>::O>> {
+        let mut outputs = vec![];
+        self.run(circuit, inputs, |step, output| {
+            if step.sample {
+                outputs.push(*output);
+            }
+        })?;
+        Ok(outputs)
+    }
+}
+
+pub(crate) fn clocked_test_module<C: Circuit>(
+    circuit: &C,
+    bench: &ClockedTestbench,
+    inputs: &[<C as CircuitIO>::I],
+) -> Result<TestModule> {
+    ensure!(
+        C::O::bits() != 0,
+        "Circuit {} has no outputs to test",
+        circuit.name()
+    );
+    let clock_bits = bench.clock_bits(circuit)?;
+    verify_initial_state(circuit)?;
+    let hdl = circuit.as_hdl(HDLKind::Verilog)?;
+    let mut now = 0;
+    let mut previous = None;
+    let mut cases = String::new();
+    let mut num_cases = 0;
+    bench.run(circuit, inputs, |step, output| {
+        if previous != Some(step.input) {
+            cases.push_str(&format!(
+                "      #{} d <= {};\n",
+                step.time_ps - now,
+                as_verilog_literal(&inputs[step.input].typed_bits())
+            ));
+            now = step.time_ps;
+        }
+        previous = Some(step.input);
+        if step.sample {
+            cases.push_str(&format!(
+                "      #{} $display(\"0x%0h 0x%0h\", {}, o);\n",
+                step.time_ps + 1 - now,
+                as_verilog_literal(&output.typed_bits())
+            ));
+            now = step.time_ps + 1;
+            num_cases += 1;
+        }
+    })?;
+    let clocks = bench
+        .clocks
+        .iter()
+        .map(|clock| {
+            let high = clock.high_ps();
+            format!(
+                "   reg clk_{name};
+   initial begin
+      clk_{name} = 1'b0;
+      #{phase};
+      forever begin
+         clk_{name} = 1'b1;
+         #{high};
+         clk_{name} = 1'b0;
+         #{low};
+      end
+   end
+",
+                name = clock.name,
+                phase = clock.phase_ps,
+                low = clock.period_ps - high,
+            )
+        })
+        .collect::<String>();
+    // The input is the data with the clocks spliced in, as a single
+    // concatenation, so that the clocks do not glitch when the data
+    // changes
+    let mut clock_names = clock_bits
+        .iter()
+        .zip(&bench.clocks)
+        .map(|(bit, clock)| (*bit, clock.name.as_str()))
+        .collect::<Vec<_>>();
+    clock_names.sort();
+    let mut parts = vec![];
+    let mut next = 0;
+    for (bit, name) in clock_names {
+        ensure!(
+            bit >= next,
+            "Two of the clocks are the same bit of the input"
+        );
+        if bit > next {
+            parts.push(format!("d[{}:{next}]", bit - 1));
+        }
+        parts.push(format!("clk_{name}"));
+        next = bit + 1;
+    }
+    if next < C::I::bits() {
+        parts.push(format!("d[{}:{next}]", C::I::bits() - 1));
+    }
+    parts.reverse();
+    let assemble = parts.join(", ");
+    Ok(TestModule {
+        testbench: format!(
+            "
+`timescale 1ps/1ps
+module testbench;
+   reg[{input_bits}:0] d;
+   wire[{input_bits}:0] i;
+   wire[{output_bits}:0] o;
+
+{clocks}
+   assign i = {{{assemble}}};
+
+   {name} dut(.{input_port}(i), .{output_port}(o));
+
+   initial begin
+{cases}      $finish;
+   end
+endmodule
+
+{hdl}
+",
+            input_bits = C::I::bits() - 1,
+            output_bits = C::O::bits() - 1,
+            name = hdl.name,
+            input_port = hdl.port_name(HDLPortDirection::Input).unwrap_or("i"),
+            output_port = hdl.port_name(HDLPortDirection::Output).unwrap_or("o"),
+        ),
+        num_cases,
+        stimulus: None,
+        masked: Default::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fast clock at 100MHz, and a slow one at 30MHz, which do not line
+    // up with each other
+    fn two_clocks() -> ClockedTestbench {
+        ClockedTestbench::new(vec![
+            ClockSpec::new("fast", 10_000),
+            ClockSpec::new("slow", 33_333).with_phase(20_000),
+        ])
+    }
+
+    #[test]
+    fn test_schedule_follows_both_clocks() {
+        let steps = two_clocks().schedule(6).unwrap();
+        // The inputs change on the falling edges of the fast clock
+        let changes = steps
+            .windows(2)
+            .filter(|pair| pair[0].input != pair[1].input)
+            .map(|pair| pair[1].time_ps)
+            .collect::<Vec<_>>();
+        assert_eq!(changes, vec![10_000, 20_000, 30_000, 40_000, 50_000]);
+        // Each input is sampled by one rising edge of the fast clock
+        let samples = steps.iter().filter(|step| step.sample).collect::<Vec<_>>();
+        assert_eq!(samples.len(), 6);
+        assert!(samples
+            .iter()
+            .enumerate()
+            .all(|(ndx, step)| step.input == ndx && step.clocks[0]));
+        // The slow clock rises at 20000 (with an input change) and 53333
+        let slow_rising = steps
+            .windows(2)
+            .filter(|pair| !pair[0].clocks[1] && pair[1].clocks[1])
+            .map(|pair| pair[1].time_ps)
+            .collect::<Vec<_>>();
+        assert_eq!(slow_rising, vec![20_000, 53_333]);
+        // 11 edges of the fast clock and 3 of the slow one, with the two
+        // at 20000 sampled once
+        let every_edge = two_clocks()
+            .with_sampling(OutputSampling::EveryEdge)
+            .schedule(6)
+            .unwrap();
+        assert_eq!(every_edge.iter().filter(|step| step.sample).count(), 13);
+        // Once per cycle of the slow clock
+        let slow = two_clocks()
+            .with_sampling(OutputSampling::Clock(1))
+            .schedule(6)
+            .unwrap();
+        let samples = slow
+            .iter()
+            .filter(|step| step.sample)
+            .map(|step| step.time_ps)
+            .collect::<Vec<_>>();
+        assert_eq!(samples, vec![20_000, 53_333]);
+        assert!(two_clocks()
+            .with_sampling(OutputSampling::Clock(2))
+            .schedule(6)
+            .is_err());
+    }
+
+    #[test]
+    fn test_schedule_rejects_edges_that_are_too_close() {
+        let bench = ClockedTestbench::new(vec![
+            ClockSpec::new("a", 10_000),
+            ClockSpec::new("b", 10_000).with_phase(5_001),
+        ]);
+        let err = bench.schedule(4).unwrap_err().to_string();
+        assert!(err.contains("at 5000ps and 5001ps are too close"), "{err}");
+        let bench = ClockedTestbench::new(vec![ClockSpec::new("a", 10_000).with_phase(0)]);
+        assert!(bench.schedule(4).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+// A quick check that the generated Verilog of a combinational circuit
+// computes the same function as its `sim`.  Rather than going through
+// every input, the check uses a sample of random inputs, along with the
+// inputs with all bits clear and all bits set (where the edge cases of
+// arithmetic tend to be).  The random inputs come from a fixed seed, so
+// that a failure can be reproduced.  Bit patterns that are not legal
+// values of the input (such as an enum discriminant with no variant)
+// are skipped.
+//
+// Bits of the output that are don't-cares can be left out with an
+// `OutputMask`, in which case the Verilog may leave them undefined.
+use anyhow::{bail, ensure, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::circuit::output_mask::{MaskStats, OutputMask};
+use crate::test_module::circuit_test_module;
+use crate::{Circuit, Digital};
+
+fn sample_inputs<C: Circuit>(samples: usize) -> Vec<C::I> {
+    let bits = C::I::bits();
+    let mut rng = StdRng::seed_from_u64(0xc0b1);
+    let random = (0..samples)
+        .map(|_| (0..bits).map(|_| rng.gen()).collect::<Vec<bool>>())
+        .collect::<Vec<_>>();
+    [vec![false; bits], vec![true; bits]]
+        .into_iter()
+        .chain(random)
+        .filter_map(|bits| C::I::maybe_from_bin(&bits).ok())
+        .collect()
+}
+
+// The bits of a value displayed by the simulator with `0x%0h`, LSB
+// first, where `None` is an unknown (X or Z) bit.  An unknown digit is
+// taken as four unknown bits, and leading digits that were left out are
+// zero, or unknown if the first digit shown is.
+fn parse_verilog_hex(text: &str, width: usize) -> Result<Vec<Option<bool>>> {
+    let Some(digits) = text.strip_prefix("0x") else {
+        bail!("Cannot parse the value `{text}` displayed by the Verilog simulation");
+    };
+    let digits = digits
+        .chars()
+        .rev()
+        .map(|digit| match digit {
+            'x' | 'X' | 'z' | 'Z' => Ok(None),
+            _ => digit
+                .to_digit(16)
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("Invalid hex digit in `{text}`")),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let fill = if digits.last().is_some_and(Option::is_none) {
+        None
+    } else {
+        Some(0)
+    };
+    Ok((0..width)
+        .map(|ndx| {
+            digits
+                .get(ndx / 4)
+                .copied()
+                .unwrap_or(fill)
+                .map(|digit| digit & (1 << (ndx % 4)) != 0)
+        })
+        .collect())
+}
+
+pub(crate) fn check_combinational_equivalence<C: Circuit>(
+    circuit: &C,
+    samples: usize,
+) -> Result<()> {
+    check_masked_combinational_equivalence(circuit, samples, &OutputMask::default()).map(|_| ())
+}
+
+pub(crate) fn check_masked_combinational_equivalence<C: Circuit>(
+    circuit: &C,
+    samples: usize,
+    mask: &OutputMask<C::O>,
+) -> Result<MaskStats> {
+    let inputs = sample_inputs::<C>(samples);
+    ensure!(
+        !inputs.is_empty(),
+        "None of the sampled inputs of circuit {} are legal values of {}",
+        circuit.name(),
+        C::I::static_kind()
+    );
+    // The output of a combinational circuit does not depend on the
+    // inputs that came before, so simulating each input on its own has
+    // to give the same result as simulating them in a row.
+    let mut state = circuit.init_state();
+    let mut io = C::Z::default();
+    let outputs = inputs
+        .iter()
+        .map(|input| {
+            let output = circuit.sim(*input, &mut state, &mut io);
+            let alone = circuit.sim(*input, &mut circuit.init_state(), &mut C::Z::default());
+            ensure!(
+                output == alone,
+                "Circuit {} is not combinational, since its output for the input {} depends on the inputs before it",
+                circuit.name(),
+                input.typed_bits()
+            );
+            Ok(output)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut masked = MaskStats::default();
+    let tm = circuit_test_module(circuit, &inputs)?;
+    let output = tm.iverilog_output()?;
+    let actual = output.lines().collect::<Vec<_>>();
+    for (ndx, input) in inputs.iter().enumerate() {
+        let Some(line) = actual.get(ndx) else {
+            bail!(
+                "The Verilog of circuit {} stopped after {ndx} of {} inputs",
+                circuit.name(),
+                inputs.len()
+            );
+        };
+        let Some((sim, hdl)) = line.split_once(' ') else {
+            bail!("Cannot parse the output `{line}` of the Verilog simulation");
+        };
+        let ignored = mask.ignored(&outputs[ndx]);
+        masked.record(&ignored);
+        if sim == hdl {
+            continue;
+        }
+        let agree = outputs[ndx]
+            .bin()
+            .into_iter()
+            .zip(parse_verilog_hex(hdl, C::O::bits())?)
+            .zip(&ignored)
+            .all(|((sim, hdl), ignored)| *ignored || hdl == Some(sim));
+        if !agree {
+            bail!(
+                "Circuit {} diverges from its Verilog for the input {} (sample {ndx}): sim gives {sim} but the Verilog gives {hdl}",
+                circuit.name(),
+                input.typed_bits()
+            );
+        }
+    }
+    Ok(masked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verilog_hex() {
+        let bits = |text| parse_verilog_hex(text, 6).unwrap();
+        let known = |value: u8| {
+            (0..6)
+                .map(|ndx| Some(value & (1 << ndx) != 0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bits("0x2a"), known(0x2a));
+        assert_eq!(bits("0x5"), known(5));
+        assert_eq!(bits("0xx"), vec![None; 6]);
+        assert_eq!(
+            bits("0x1X"),
+            [vec![None; 4], known(0x10)[4..].to_vec()].concat()
+        );
+        assert!(parse_verilog_hex("2a", 6).is_err());
+    }
+}
@@ -1,12 +1,68 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
-use crate::{root_verilog, Circuit, HDLKind};
+use anyhow::{bail, Result};
+
+use crate::path::{bit_range, Path};
+use crate::types::digital::Digital;
+use crate::{as_verilog_literal, root_verilog, Circuit, HDLKind, Kind, Tristate, TypedBits};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HDLPortDirection {
+    Input,
+    Output,
+    InOut,
+}
+
+impl std::fmt::Display for HDLPortDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HDLPortDirection::Input => write!(f, "input"),
+            HDLPortDirection::Output => write!(f, "output"),
+            HDLPortDirection::InOut => write!(f, "inout"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HDLPort {
+    pub name: String,
+    pub direction: HDLPortDirection,
+    pub kind: Kind,
+}
+
+impl HDLPort {
+    // The declaration of the port in the module header.
+    pub fn decl(&self) -> String {
+        format!(
+            "{} wire[{}:0] {}",
+            self.direction,
+            self.kind.bits() - 1,
+            self.name
+        )
+    }
+}
+
+// The value that the HDL of a circuit gives one of its registers when
+// the design starts.  The `name` is that of the register in
+// `Circuit::state_signals` (empty for the register of a primitive), and
+// `reg` is the Verilog register that holds it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HDLInitial {
+    pub name: String,
+    pub reg: String,
+    pub value: TypedBits,
+}
 
 #[derive(Clone, Debug)]
 pub struct HDLDescriptor {
     pub name: String,
     pub body: String,
+    pub ports: Vec<HDLPort>,
     pub children: HashMap<String, HDLDescriptor>,
+    // The initial values of the registers of this module (but not of its
+    // children), as written by `initial_block`
+    pub initial: Vec<HDLInitial>,
 }
 
 impl std::fmt::Display for HDLDescriptor {
@@ -19,7 +75,103 @@ impl std::fmt::Display for HDLDescriptor {
     }
 }
 
+// The ports of the module for a circuit.  Zero width values have no
+// representation in Verilog, so the ports that would carry one are
+// left out.
+pub fn circuit_ports<C: Circuit>() -> Vec<HDLPort> {
+    [
+        ("i", HDLPortDirection::Input, C::I::static_kind()),
+        ("o", HDLPortDirection::Output, C::O::static_kind()),
+        ("io", HDLPortDirection::InOut, Kind::make_bits(C::Z::N)),
+    ]
+    .into_iter()
+    .filter(|(_, _, kind)| kind.bits() != 0)
+    .map(|(name, direction, kind)| HDLPort {
+        name: name.into(),
+        direction,
+        kind,
+    })
+    .collect()
+}
+
+// The `initial` block that sets each register to its initial value.
+// Modules that have registers keep the same list in
+// `HDLDescriptor::initial`, so that `verify_initial_state` can check the
+// values against those of the simulation without parsing the Verilog.
+pub fn initial_block(initial: &[HDLInitial]) -> String {
+    let body = initial
+        .iter()
+        .map(|init| {
+            format!(
+                "      {} = {};\n",
+                init.reg,
+                as_verilog_literal(&init.value)
+            )
+        })
+        .collect::<String>();
+    format!("   initial begin\n{body}   end\n")
+}
+
+// The logical fields that make up a value of the given kind, with their
+// bit ranges.  Enums are kept whole, since the payloads of the variants
+// share bits.
+fn port_fields(kind: &Kind) -> Result<Vec<(Path, Range<usize>)>> {
+    fn walk(kind: &Kind, path: Path, paths: &mut Vec<Path>) {
+        match kind {
+            Kind::Struct(structure) => {
+                for field in &structure.fields {
+                    walk(&field.kind, path.clone().field(&field.name), paths);
+                }
+            }
+            Kind::Tuple(tuple) => {
+                for (ndx, element) in tuple.elements.iter().enumerate() {
+                    walk(element, path.clone().index(ndx), paths);
+                }
+            }
+            Kind::Array(array) => {
+                for ndx in 0..array.size {
+                    walk(&array.base, path.clone().index(ndx), paths);
+                }
+            }
+            _ if kind.bits() != 0 => paths.push(path),
+            _ => {}
+        }
+    }
+    let mut paths = vec![];
+    walk(kind, Path::default(), &mut paths);
+    paths
+        .into_iter()
+        .map(|path| {
+            let (range, _) = bit_range(kind.clone(), &path)?;
+            Ok((path, range))
+        })
+        .collect()
+}
+
+fn slice(name: &str, range: &Range<usize>) -> String {
+    if range.len() == 1 {
+        format!("{name}[{}]", range.start)
+    } else {
+        format!("{name}[{}:{}]", range.end - 1, range.start)
+    }
+}
+
+// A Verilog identifier for a field of a port, i.e., `i_data_0` for
+// `i.data[0]`.
+fn field_identifier(port: &str, path: &Path) -> String {
+    let path = path.to_string().replace(['.', '['], "_").replace(']', "");
+    format!("{port}{path}")
+}
+
 impl HDLDescriptor {
+    // The name of the module port with the given direction.  The input
+    // and output are `i` and `o`, unless the circuit renamed them.
+    pub fn port_name(&self, direction: HDLPortDirection) -> Option<&str> {
+        self.ports
+            .iter()
+            .find(|port| port.direction == direction)
+            .map(|port| port.name.as_str())
+    }
     pub fn add_child<C: Circuit>(
         &mut self,
         name: &str,
@@ -29,6 +181,59 @@ impl HDLDescriptor {
         self.children.insert(name.into(), circuit.as_hdl(kind)?);
         Ok(())
     }
+    fn instance(
+        &self,
+        instance_name: &str,
+        connect: impl Fn(&HDLPort) -> String,
+    ) -> Result<String> {
+        let mut lines = vec![];
+        for (ndx, port) in self.ports.iter().enumerate() {
+            let comma = if ndx + 1 < self.ports.len() { "," } else { "" };
+            lines.push(format!(
+                "    .{name}({wire}){comma} // {direction} [{msb}:0]",
+                name = port.name,
+                wire = connect(port),
+                direction = port.direction,
+                msb = port.kind.bits() - 1,
+            ));
+            for (path, range) in port_fields(&port.kind)? {
+                if !path.is_empty() {
+                    lines.push(format!("    //   {} {path}", slice(&port.name, &range)));
+                }
+            }
+        }
+        Ok(format!(
+            "{name} {instance_name} (\n{ports}\n);\n",
+            name = self.name,
+            ports = lines.join("\n")
+        ))
+    }
+    // A skeleton for instantiating this module, with a comment for each
+    // logical field of the ports giving the bits that carry it.
+    pub fn instantiation_template(&self) -> Result<String> {
+        Ok(format!(
+            "// Instantiation template for {name}\n{instance}",
+            name = self.name,
+            instance = self.instance(&format!("{}_inst", self.name), |port| port.name.clone())?
+        ))
+    }
+    // Write the module (and the modules of its children) to `dir` as
+    // `<name>.v`, along with the instantiation template as `<name>_inst.v`
+    // if `templates` is set.
+    pub fn write_to_dir(&self, dir: &std::path::Path, templates: bool) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(format!("{}.v", self.name)), &self.body)?;
+        if templates {
+            std::fs::write(
+                dir.join(format!("{}_inst.v", self.name)),
+                self.instantiation_template()?,
+            )?;
+        }
+        for child in self.children.values() {
+            child.write_to_dir(dir, templates)?;
+        }
+        Ok(())
+    }
 }
 
 pub fn root_hdl<C: Circuit>(circuit: &C, kind: HDLKind) -> anyhow::Result<HDLDescriptor> {
@@ -36,3 +241,93 @@ pub fn root_hdl<C: Circuit>(circuit: &C, kind: HDLKind) -> anyhow::Result<HDLDes
         HDLKind::Verilog => root_verilog(circuit),
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct ExampleTopOptions {
+    // The name of the example top module.
+    pub name: String,
+    // The field of the circuit input driven by the `clk` pin of the
+    // example top, if any.
+    pub clock: Option<Path>,
+}
+
+impl Default for ExampleTopOptions {
+    fn default() -> Self {
+        Self {
+            name: "top".into(),
+            clock: None,
+        }
+    }
+}
+
+// A minimal top level module that wraps the circuit, with a `clk` pin
+// and a separate pin for each logical field of the inputs and outputs.
+pub fn example_top<C: Circuit>(circuit: &C, options: &ExampleTopOptions) -> Result<String> {
+    let hdl = circuit.as_hdl(HDLKind::Verilog)?;
+    let clock = match &options.clock {
+        Some(path) => {
+            let (range, _) = bit_range(C::I::static_kind(), path)?;
+            if range.len() != 1 {
+                bail!("The clock {path} of {} is not a single bit", hdl.name);
+            }
+            Some(range)
+        }
+        None => None,
+    };
+    let mut pins = vec![];
+    let mut wires = vec![];
+    let mut assigns = vec![];
+    if let Some(range) = &clock {
+        pins.push("input wire clk".to_string());
+        let input = hdl.port_name(HDLPortDirection::Input).unwrap_or("i");
+        assigns.push(format!(
+            "assign {} = clk;",
+            slice(&format!("dut_{input}"), range)
+        ));
+    }
+    for port in &hdl.ports {
+        if port.direction == HDLPortDirection::InOut {
+            pins.push(port.decl());
+            continue;
+        }
+        let wire = format!("dut_{}", port.name);
+        wires.push(format!("wire[{}:0] {wire};", port.kind.bits() - 1));
+        for (path, range) in port_fields(&port.kind)? {
+            let is_clock = clock
+                .as_ref()
+                .is_some_and(|clock| clock.start < range.end && range.start < clock.end);
+            if port.direction == HDLPortDirection::Input && is_clock {
+                continue;
+            }
+            let pin = field_identifier(&port.name, &path);
+            pins.push(format!(
+                "{} wire[{}:0] {pin}",
+                port.direction,
+                range.len() - 1
+            ));
+            assigns.push(match port.direction {
+                HDLPortDirection::Input => format!("assign {} = {pin};", slice(&wire, &range)),
+                _ => format!("assign {pin} = {};", slice(&wire, &range)),
+            });
+        }
+    }
+    Ok(format!(
+        "// Example top level for {name}
+module {top}({pins});
+{wires}
+{assigns}
+
+{instance}
+endmodule
+",
+        name = hdl.name,
+        top = options.name,
+        pins = pins.join(", "),
+        wires = wires.join("\n"),
+        assigns = assigns.join("\n"),
+        instance = hdl.instance("dut", |port| match port.direction {
+            HDLPortDirection::InOut => port.name.clone(),
+            _ => format!("dut_{}", port.name),
+        })?,
+    ))
+}
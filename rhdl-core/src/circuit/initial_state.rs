@@ -0,0 +1,91 @@
+// Check that the registers of a circuit start out with the same values
+// in the simulation and in its HDL.  The simulation values come from
+// `Circuit::init_state`, through `Circuit::state_signals`, and the HDL
+// values from the `initial` lists of the HDL descriptors (which are what
+// the generators wrote into the `initial` blocks).  A register that only
+// one side knows about is also an error, since the other side then
+// starts it at some other value (or at `x`).
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::path::{leaf_paths, Path};
+use crate::{child_state_signals, Circuit, HDLDescriptor, HDLKind, TypedBits};
+
+fn hdl_initial(hdl: &HDLDescriptor) -> Vec<(String, TypedBits)> {
+    let mut children = hdl.children.iter().collect::<Vec<_>>();
+    children.sort_by_key(|(name, _)| *name);
+    hdl.initial
+        .iter()
+        .map(|init| (init.name.clone(), init.value.clone()))
+        .chain(children.into_iter().flat_map(|(name, child)| {
+            child_state_signals(name, hdl_initial(child)).collect::<Vec<_>>()
+        }))
+        .collect()
+}
+
+// The fields of a register that start out differently, or the whole
+// register if the two sides do not even agree on its kind
+fn differing_fields(sim: &TypedBits, hdl: &TypedBits) -> Result<Vec<Path>> {
+    if sim.kind != hdl.kind {
+        return Ok(vec![Path::default()]);
+    }
+    let mut paths = vec![];
+    for path in leaf_paths(&sim.kind, Path::default()) {
+        if sim.path(&path)? != hdl.path(&path)? {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+pub fn verify_initial_state<C: Circuit>(circuit: &C) -> Result<()> {
+    let name = circuit.name();
+    let register = |signal: &str| {
+        if signal.is_empty() {
+            name.to_string()
+        } else {
+            format!("{name}.{signal}")
+        }
+    };
+    let sim = circuit
+        .state_signals(&circuit.init_state())
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    let hdl = hdl_initial(&circuit.as_hdl(HDLKind::Verilog)?)
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    let mut errors = vec![];
+    for (signal, sim_value) in &sim {
+        let Some(hdl_value) = hdl.get(signal) else {
+            errors.push(format!(
+                "register {} starts at {sim_value} in the simulation, but is not initialized by the HDL",
+                register(signal)
+            ));
+            continue;
+        };
+        for path in differing_fields(sim_value, hdl_value)? {
+            errors.push(format!(
+                "register {}{path} starts at {} in the simulation, but at {} in the HDL",
+                register(signal),
+                sim_value.path(&path)?,
+                hdl_value.path(&path)?
+            ));
+        }
+    }
+    for (signal, hdl_value) in &hdl {
+        if !sim.contains_key(signal) {
+            errors.push(format!(
+                "register {} is initialized to {hdl_value} by the HDL, but is not in the simulation state",
+                register(signal)
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        bail!(
+            "The initial state of circuit {name} differs between the simulation and the HDL:\n{}",
+            errors.join("\n")
+        );
+    }
+    Ok(())
+}
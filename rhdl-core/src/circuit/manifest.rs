@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::ops::{Range, RangeInclusive};
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::circuit_descriptor::{DocDescriptor, ProbeDescriptor};
+use crate::path::{bit_range, leaf_paths, range_bounds, Path};
+use crate::{Circuit, CircuitDescriptor, Kind, TimingException};
+
+// Bump this whenever the layout of the manifest changes in a way
+// that downstream tools would notice.
+pub const MANIFEST_VERSION: u32 = 1;
+
+// A machine readable description of a generated design, intended
+// for downstream tooling (driver generators, documentation, bring-up
+// scripts, etc.).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DesignManifest {
+    pub version: u32,
+    pub tool: String,
+    pub tool_version: String,
+    pub top: ModuleManifest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleManifest {
+    pub instance_name: String,
+    pub unique_name: String,
+    // The doc comment on the circuit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    pub ports: Vec<PortManifest>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub probes: Vec<ProbeManifest>,
+    // The flags tested by `#[rhdl(cfg)]` attributes in the kernels of
+    // the module, and whether each was enabled.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub flags: BTreeMap<String, bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timing: Vec<TimingManifest>,
+    pub children: Vec<ModuleManifest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortManifest {
+    pub name: String,
+    pub width: usize,
+    pub kind: Kind,
+    pub leaves: Vec<LeafManifest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeafManifest {
+    pub path: String,
+    pub bits: Range<usize>,
+    pub kind: Kind,
+    // The values the leaf is allowed to hold, if it is a ranged field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<RangeInclusive<i128>>,
+    // The doc comment on the innermost field that holds the leaf, if
+    // any field that holds it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+// A value marked with `#[rhdl(probe)]` in the kernel `function`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeManifest {
+    pub name: String,
+    pub function: String,
+    pub width: usize,
+    pub kind: Kind,
+}
+
+// A timing exception on the bits `bits` of one of the ports of the
+// module, from a `#[rhdl(multicycle = N)]` or `#[rhdl(false_path)]`
+// attribute on the field at `path`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimingManifest {
+    pub port: String,
+    pub path: String,
+    pub bits: Range<usize>,
+    pub exception: TimingException,
+}
+
+impl From<&ProbeDescriptor> for ProbeManifest {
+    fn from(probe: &ProbeDescriptor) -> Self {
+        Self {
+            name: probe.name.clone(),
+            function: probe.function.clone(),
+            width: probe.kind.bits(),
+            kind: probe.kind.clone(),
+        }
+    }
+}
+
+impl PortManifest {
+    fn new(name: &str, kind: &Kind, docs: &[DocDescriptor]) -> Result<Self> {
+        let leaves = leaf_paths(kind, Path::default())
+            .into_iter()
+            .map(|path| {
+                let (bits, leaf_kind) = bit_range(kind.clone(), &path)?;
+                // The docs of a field come before those of the fields
+                // inside it
+                let doc = docs
+                    .iter()
+                    .rev()
+                    .find(|doc| doc.port == name && doc.path.is_prefix_of(&path))
+                    .map(|doc| doc.doc.clone());
+                Ok(LeafManifest {
+                    path: path.to_string(),
+                    bits,
+                    kind: leaf_kind,
+                    range: range_bounds(kind, &path)?.map(|(min, max)| min..=max),
+                    doc,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            name: name.into(),
+            width: kind.bits(),
+            kind: kind.clone(),
+            leaves,
+        })
+    }
+}
+
+impl ModuleManifest {
+    fn new(instance_name: &str, descriptor: &CircuitDescriptor) -> Result<Self> {
+        let ports = [
+            ("i", &descriptor.input_kind),
+            ("o", &descriptor.output_kind),
+            ("d", &descriptor.d_kind),
+            ("q", &descriptor.q_kind),
+        ]
+        .into_iter()
+        .map(|(name, kind)| PortManifest::new(name, kind, &descriptor.field_docs))
+        .collect::<Result<Vec<_>>>()?;
+        let timing = descriptor
+            .resolve_timing()?
+            .into_iter()
+            .map(|(timing, bits)| TimingManifest {
+                port: timing.port.clone(),
+                path: timing.path.to_string(),
+                bits,
+                exception: timing.exception,
+            })
+            .collect();
+        // Sort the children so that the manifest is stable from run to run
+        let mut names = descriptor.children.keys().collect::<Vec<_>>();
+        names.sort();
+        let children = names
+            .into_iter()
+            .map(|name| ModuleManifest::new(name, &descriptor.children[name]))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            instance_name: instance_name.into(),
+            unique_name: descriptor.unique_name.clone(),
+            doc: descriptor.doc.clone(),
+            ports,
+            probes: descriptor.probes.iter().map(Into::into).collect(),
+            flags: descriptor.flags.clone(),
+            timing,
+            children,
+        })
+    }
+}
+
+impl DesignManifest {
+    pub fn from_circuit<C: Circuit>(circuit: &C) -> Result<Self> {
+        Self::from_descriptor(&circuit.descriptor())
+    }
+    pub fn from_descriptor(descriptor: &CircuitDescriptor) -> Result<Self> {
+        Ok(Self {
+            version: MANIFEST_VERSION,
+            tool: env!("CARGO_PKG_NAME").into(),
+            tool_version: env!("CARGO_PKG_VERSION").into(),
+            top: ModuleManifest::new("top", descriptor)?,
+        })
+    }
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    pub fn from_json(json: &str) -> Result<Self> {
+        let manifest: Self = serde_json::from_str(json)?;
+        ensure!(
+            manifest.version == MANIFEST_VERSION,
+            "Unsupported manifest version {} (expected {})",
+            manifest.version,
+            MANIFEST_VERSION
+        );
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::circuit_descriptor::{two_level_design, TimingDescriptor};
+
+    // The path and bits of each leaf of the port named `name`
+    fn leaves<'a>(module: &'a ModuleManifest, name: &str) -> Vec<(&'a str, Range<usize>)> {
+        module
+            .ports
+            .iter()
+            .find(|port| port.name == name)
+            .unwrap()
+            .leaves
+            .iter()
+            .map(|leaf| (leaf.path.as_str(), leaf.bits.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_manifest_matches_bit_ranges() {
+        let manifest = DesignManifest::from_descriptor(&two_level_design()).unwrap();
+        assert_eq!(manifest.top.unique_name, "top_5678");
+        assert_eq!(manifest.top.children.len(), 1);
+        let inner = &manifest.top.children[0];
+        assert_eq!(inner.instance_name, "inner");
+        assert_eq!(inner.unique_name, "inner_1234");
+        assert_eq!(
+            leaves(inner, "i"),
+            vec![
+                (".clock", 0..1),
+                (".data[0]", 1..4),
+                (".data[1]", 4..7),
+                (".digit", 7..11),
+            ]
+        );
+        // The payloads of the variants overlap, below the discriminant
+        assert_eq!(
+            leaves(inner, "o"),
+            vec![
+                ("#0", 0..0),
+                ("#1", 0..4),
+                ("#2[0]", 0..1),
+                ("#2[1]", 1..4),
+                ("#", 4..6),
+            ]
+        );
+        // The d and q of the top hold the ports of the child
+        assert_eq!(
+            leaves(&manifest.top, "d"),
+            vec![
+                (".inner.clock", 0..1),
+                (".inner.data[0]", 1..4),
+                (".inner.data[1]", 4..7),
+                (".inner.digit", 7..11),
+            ]
+        );
+        assert_eq!(
+            leaves(&manifest.top, "q"),
+            vec![
+                (".inner#0", 0..0),
+                (".inner#1", 0..4),
+                (".inner#2[0]", 0..1),
+                (".inner#2[1]", 1..4),
+                (".inner#", 4..6),
+            ]
+        );
+        assert_eq!(leaves(&manifest.top, "i"), vec![("", 0..8)]);
+        assert_eq!(leaves(inner, "q"), vec![("", 0..0)]);
+        assert!(manifest.top.probes.is_empty());
+        assert_eq!(inner.probes.len(), 1);
+        assert_eq!(inner.probes[0].name, "next_digit");
+        assert_eq!(inner.probes[0].function, "inner_update");
+        assert_eq!(inner.probes[0].width, 4);
+        assert!(manifest.top.flags.is_empty());
+        assert!(inner.flags["debug_counters"]);
+        let o = inner.ports.iter().find(|p| p.name == "o").unwrap();
+        assert_eq!(o.width, 6);
+        let disc = o.leaves.iter().find(|l| l.path == "#").unwrap();
+        assert_eq!(disc.bits, 4..6);
+        // Only the ranged field carries bounds, and they do not change
+        // the layout of the port
+        let i = inner.ports.iter().find(|p| p.name == "i").unwrap();
+        assert_eq!(i.width, 11);
+        let digit = i.leaves.iter().find(|l| l.path == ".digit").unwrap();
+        assert_eq!(digit.range, Some(0..=9));
+        assert_eq!(digit.kind, Kind::make_bits(4));
+        assert_eq!(digit.bits, 7..11);
+        assert!(i
+            .leaves
+            .iter()
+            .filter(|l| l.path != ".digit")
+            .all(|l| l.range.is_none()));
+        assert!(manifest.top.timing.is_empty());
+        assert_eq!(
+            inner.timing,
+            vec![TimingManifest {
+                port: "i".into(),
+                path: ".data[1]".into(),
+                bits: 4..7,
+                exception: TimingException::Multicycle(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_manifest_rejects_missing_timing_path() {
+        let mut design = two_level_design();
+        design.timing.push(TimingDescriptor {
+            port: "q".into(),
+            path: Path::default().field("outer"),
+            exception: TimingException::FalsePath,
+        });
+        let err = DesignManifest::from_descriptor(&design).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("The timing exception on q.outer in top_5678 does not name a signal"));
+    }
+
+    #[test]
+    fn test_manifest_json_round_trip() {
+        let manifest = DesignManifest::from_descriptor(&two_level_design()).unwrap();
+        let json = manifest.to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], MANIFEST_VERSION);
+        assert!(json.contains("\"Run\""));
+        assert!(json.contains("\"next_digit\""));
+        assert!(json.contains("\"debug_counters\""));
+        assert!(json.contains("\"Multicycle\""));
+        let round_trip = DesignManifest::from_json(&json).unwrap();
+        assert_eq!(round_trip, manifest);
+    }
+}
@@ -1,5 +1,16 @@
 pub mod bitz;
+pub mod check;
 pub mod circuit_descriptor;
 pub mod circuit_impl;
+pub mod clocked_testbench;
+#[cfg(feature = "iverilog")]
+pub mod equivalence;
 pub mod hdl_descriptor;
+pub mod initial_state;
+pub mod manifest;
+pub mod output_mask;
+pub mod primitive;
+pub mod sdc;
+pub mod stats;
+pub mod trace;
 pub mod verilog;
@@ -0,0 +1,174 @@
+// Don't-care bits of the output of a circuit, which are left out when
+// its simulation is compared with its HDL (or with a recorded trace).
+// Some outputs are legitimately undefined, such as the data of a bus in
+// the cycles where it is not valid, and a difference there should not
+// fail the comparison.  A mask ignores a fixed set of paths, or a set of
+// paths in only the cycles where a predicate holds on the expected
+// output, as in
+//
+//   OutputMask::ignore_when(&[Path::default().field("data")], |o: &BusO| !o.valid)
+//
+// The paths are checked against the kind of the output when the mask is
+// built.
+use anyhow::{anyhow, Result};
+
+use crate::path::{bit_range, Path};
+use crate::Digital;
+
+type MaskPredicate<T> = Box<dyn Fn(&T) -> bool>;
+
+struct MaskRule<T> {
+    // The bits of the output covered by the rule
+    bits: Vec<bool>,
+    // The rule only applies if this holds on the expected output
+    when: Option<MaskPredicate<T>>,
+}
+
+pub struct OutputMask<T: Digital> {
+    rules: Vec<MaskRule<T>>,
+}
+
+impl<T: Digital> Default for OutputMask<T> {
+    fn default() -> Self {
+        Self { rules: vec![] }
+    }
+}
+
+fn path_bits<T: Digital>(paths: &[Path]) -> Result<Vec<bool>> {
+    let kind = T::static_kind();
+    let mut bits = vec![false; kind.bits()];
+    for path in paths {
+        let (range, _) = bit_range(kind.clone(), path)
+            .map_err(|_| anyhow!("Cannot mask {path}, since {kind} has no such path"))?;
+        bits[range].fill(true);
+    }
+    Ok(bits)
+}
+
+impl<T: Digital> OutputMask<T> {
+    // Ignore the given paths of the output in every cycle.
+    pub fn ignore(paths: &[Path]) -> Result<Self> {
+        Ok(Self {
+            rules: vec![MaskRule {
+                bits: path_bits::<T>(paths)?,
+                when: None,
+            }],
+        })
+    }
+    // Ignore the given paths of the output in the cycles where `when`
+    // holds on the expected output.
+    pub fn ignore_when(paths: &[Path], when: impl Fn(&T) -> bool + 'static) -> Result<Self> {
+        Ok(Self {
+            rules: vec![MaskRule {
+                bits: path_bits::<T>(paths)?,
+                when: Some(Box::new(when)),
+            }],
+        })
+    }
+    // A mask that ignores the bits ignored by either mask.
+    pub fn and(mut self, other: OutputMask<T>) -> Self {
+        self.rules.extend(other.rules);
+        self
+    }
+    // The bits that are ignored in a cycle where the expected output is
+    // `expected`.
+    pub fn ignored(&self, expected: &T) -> Vec<bool> {
+        let mut ignored = vec![false; T::bits()];
+        for rule in &self.rules {
+            if rule.when.as_ref().is_none_or(|when| when(expected)) {
+                for (ignored, bit) in ignored.iter_mut().zip(&rule.bits) {
+                    *ignored |= bit;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+// The bits with the ignored ones cleared, so that values that differ
+// only in ignored bits compare equal.
+pub(crate) fn clear_ignored(bits: &[bool], ignored: &[bool]) -> Vec<bool> {
+    bits.iter()
+        .zip(ignored)
+        .map(|(bit, ignored)| *bit && !ignored)
+        .collect()
+}
+
+// The number of bits that a mask ignored in each cycle of a comparison.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaskStats {
+    pub masked_bits: Vec<usize>,
+}
+
+impl MaskStats {
+    pub(crate) fn record(&mut self, ignored: &[bool]) {
+        self.masked_bits
+            .push(ignored.iter().filter(|bit| **bit).count());
+    }
+    pub fn total(&self) -> usize {
+        self.masked_bits.iter().sum()
+    }
+    // The number of cycles in which any bit was masked.
+    pub fn masked_cycles(&self) -> usize {
+        self.masked_bits.iter().filter(|bits| **bits != 0).count()
+    }
+}
+
+impl std::fmt::Display for MaskStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bits masked in {} of {} cycles",
+            self.total(),
+            self.masked_cycles(),
+            self.masked_bits.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rhdl_bits::{bits, Bits};
+
+    use super::*;
+
+    // A valid flag and its data
+    type Bus = (bool, Bits<8>);
+
+    #[test]
+    fn test_mask_follows_the_output() {
+        let data = Path::default().index(1);
+        let mask = OutputMask::<Bus>::ignore_when(&[data], |bus| !bus.0).unwrap();
+        let idle = (false, bits(3));
+        let busy = (true, bits(3));
+        assert_eq!(
+            mask.ignored(&idle),
+            [[false].as_slice(), &[true; 8]].concat()
+        );
+        assert!(!mask.ignored(&busy).contains(&true));
+        let mut stats = MaskStats::default();
+        stats.record(&mask.ignored(&idle));
+        stats.record(&mask.ignored(&busy));
+        assert_eq!(stats.masked_bits, [8, 0]);
+        assert_eq!(stats.to_string(), "8 bits masked in 1 of 2 cycles");
+        assert_eq!(
+            clear_ignored(&idle.bin(), &mask.ignored(&idle)),
+            (false, bits::<8>(0)).bin()
+        );
+    }
+
+    #[test]
+    fn test_mask_with_a_missing_path_is_rejected() {
+        let err = OutputMask::<Bus>::ignore(&[Path::default().field("data")])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Cannot mask .data, since {} has no such path",
+                Bus::static_kind()
+            )
+        );
+        assert!(OutputMask::<Bus>::ignore(&[Path::default().index(2)]).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+// A vendor primitive (a carry chain, a DSP block, an IO buffer, ...) is
+// instantiated by name in the generated HDL, rather than described by a
+// kernel.  The primitive gives the name of the vendor module and its
+// parameters, how the ports of the module map onto the bits of its input
+// and output, and a Rust model of what it computes, which stands in for
+// the module in simulation.  `PrimitiveCircuit` wraps a primitive as a
+// circuit, so that it can be used as the child of any other circuit.
+//
+// The vendor library that defines the module is usually not available to
+// iverilog.  A primitive can provide a fallback Verilog model of the
+// module, which `PrimitiveCircuit::with_fallback_model` adds to the
+// generated HDL, so that the design can still be checked against its
+// simulation.
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::path::{bit_range, Path};
+use crate::schematic::components::{BlackBoxComponent, ComponentKind};
+use crate::schematic::schematic_impl::{pin_path, PinIx, PinPath, Schematic};
+use crate::{
+    as_verilog_literal, circuit_ports, note, root_descriptor, BlackBoxTrait, Circuit,
+    CircuitDescriptor, CircuitIO, Digital, HDLDescriptor, HDLKind, HDLPortDirection, NoUpdateFn,
+    TypedBits,
+};
+
+// What drives (or is driven by) a port of the vendor module.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortBinding {
+    // The bits of the input of the primitive at the path
+    Input(Path),
+    // The bits of the output of the primitive at the path
+    Output(Path),
+    // A constant, for the ports of the module that the primitive does
+    // not use (such as a clock enable that is always on)
+    Constant(TypedBits),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrimitivePort {
+    pub name: String,
+    pub binding: PortBinding,
+}
+
+impl PrimitivePort {
+    pub fn input(name: &str, path: Path) -> Self {
+        Self {
+            name: name.into(),
+            binding: PortBinding::Input(path),
+        }
+    }
+    pub fn output(name: &str, path: Path) -> Self {
+        Self {
+            name: name.into(),
+            binding: PortBinding::Output(path),
+        }
+    }
+    pub fn constant(name: &str, value: impl Digital) -> Self {
+        Self {
+            name: name.into(),
+            binding: PortBinding::Constant(value.typed_bits()),
+        }
+    }
+}
+
+pub trait Primitive: CircuitIO {
+    // The name of the vendor module.
+    fn module(&self) -> &'static str;
+    // The parameters of the instance, as Verilog expressions.
+    fn parameters(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+    // The ports of the module.  Every bit of the output must be driven
+    // by exactly one output port.
+    fn ports(&self) -> Vec<PrimitivePort>;
+    // The output of the module for the given input.
+    fn model(&self, input: Self::I) -> Self::O;
+    // A Verilog definition of the module (with the same name, parameters
+    // and ports), to use when the vendor library is not available.
+    fn fallback_model(&self) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Clone)]
+pub struct PrimitiveCircuit<P: Primitive> {
+    primitive: P,
+    fallback: bool,
+}
+
+impl<P: Primitive> From<P> for PrimitiveCircuit<P> {
+    fn from(primitive: P) -> Self {
+        Self {
+            primitive,
+            fallback: false,
+        }
+    }
+}
+
+impl<P: Primitive + Default> Default for PrimitiveCircuit<P> {
+    fn default() -> Self {
+        P::default().into()
+    }
+}
+
+impl<P: Primitive> PrimitiveCircuit<P> {
+    pub fn primitive(&self) -> &P {
+        &self.primitive
+    }
+    // The same circuit, with the fallback model of the primitive included
+    // in its HDL in place of the vendor library.
+    pub fn with_fallback_model(self) -> Self {
+        Self {
+            fallback: true,
+            ..self
+        }
+    }
+    fn instance(&self) -> Result<String> {
+        let module = self.primitive.module();
+        let mut driven = vec![0; P::O::bits()];
+        let connections = self
+            .primitive
+            .ports()
+            .into_iter()
+            .map(|port| {
+                let signal = match &port.binding {
+                    PortBinding::Input(path) => {
+                        select_bits("i", bound_bits::<P::I>("i", path, &port.name)?)
+                    }
+                    PortBinding::Output(path) => {
+                        let bits = bound_bits::<P::O>("o", path, &port.name)?;
+                        driven[bits.clone()]
+                            .iter_mut()
+                            .for_each(|count| *count += 1);
+                        select_bits("o", bits)
+                    }
+                    PortBinding::Constant(value) => as_verilog_literal(value),
+                };
+                Ok(format!("      .{}({signal})", port.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(bit) = driven.iter().position(|count| *count != 1) {
+            bail!(
+                "Bit {bit} of the output of {module} is driven by {} ports, rather than one",
+                driven[bit]
+            );
+        }
+        let parameters = self
+            .primitive
+            .parameters()
+            .into_iter()
+            .map(|(name, value)| format!("      .{name}({value})"))
+            .collect::<Vec<_>>();
+        let parameters = if parameters.is_empty() {
+            String::new()
+        } else {
+            format!("#(\n{}\n   ) ", parameters.join(",\n"))
+        };
+        Ok(format!(
+            "   {module} {parameters}primitive (\n{}\n   );\n",
+            connections.join(",\n")
+        ))
+    }
+}
+
+// The bits of the input (or output) `signal` of type `T` that a port is
+// bound to.
+fn bound_bits<T: Digital>(signal: &str, path: &Path, port: &str) -> Result<Range<usize>> {
+    let (bits, _) = bit_range(T::static_kind(), path)
+        .map_err(|_| anyhow!("The port {port} is bound to {signal}{path}, which does not exist"))?;
+    Ok(bits)
+}
+
+fn select_bits(signal: &str, bits: Range<usize>) -> String {
+    match bits.len() {
+        0 => "".into(),
+        1 => format!("{signal}[{}]", bits.start),
+        _ => format!("{signal}[{}:{}]", bits.end - 1, bits.start),
+    }
+}
+
+// The primitive appears in the schematic of its parent as a black box,
+// with each bit of its output depending on all of its input.
+#[derive(Clone, Debug)]
+struct PrimitiveComponent {
+    module: &'static str,
+    input: PinIx,
+    output: PinIx,
+}
+
+impl BlackBoxTrait for PrimitiveComponent {
+    fn name(&self) -> &str {
+        self.module
+    }
+    fn args(&self) -> Vec<PinIx> {
+        vec![self.input]
+    }
+    fn output(&self) -> PinIx {
+        self.output
+    }
+    fn upstream(&self, _output: PinPath) -> Result<Vec<PinPath>> {
+        Ok(vec![pin_path(self.input, Path::default())])
+    }
+    fn downstream(&self, _input: PinPath) -> Result<Vec<PinPath>> {
+        Ok(vec![pin_path(self.output, Path::default())])
+    }
+    fn offset(&self, shift: usize) -> BlackBoxComponent {
+        BlackBoxComponent::new(PrimitiveComponent {
+            module: self.module,
+            input: self.input.offset(shift),
+            output: self.output.offset(shift),
+        })
+    }
+}
+
+impl<P: Primitive> CircuitIO for PrimitiveCircuit<P> {
+    type I = P::I;
+    type O = P::O;
+}
+
+impl<P: Primitive> Circuit for PrimitiveCircuit<P>
+where
+    P::O: Default,
+{
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = NoUpdateFn;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |_, _| (Default::default(), ());
+
+    type S = ();
+
+    fn sim(&self, input: Self::I, _state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        let output = self.primitive.model(input);
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        self.primitive.module()
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        let mut desc = root_descriptor(self);
+        // Instances with different parameters need modules of their own
+        let mut hasher = fnv::FnvHasher::default();
+        self.primitive.parameters().hash(&mut hasher);
+        desc.unique_name = format!("{}_{:x}", desc.unique_name, hasher.finish());
+        let mut schematic = Schematic::default();
+        let (input_rx, input_tx) = schematic.make_buffer(P::I::static_kind(), None);
+        let input = schematic.make_pin(P::I::static_kind(), "i".into(), None);
+        let output = schematic.make_pin(P::O::static_kind(), "o".into(), None);
+        let component = schematic.make_component(
+            ComponentKind::BlackBox(BlackBoxComponent::new(PrimitiveComponent {
+                module: self.primitive.module(),
+                input,
+                output,
+            })),
+            None,
+        );
+        schematic.pin_mut(input).parent(component);
+        schematic.pin_mut(output).parent(component);
+        schematic.wire(input_tx, input);
+        schematic.inputs = vec![input_rx];
+        schematic.output = output;
+        desc.update_schematic = Some(schematic);
+        desc
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        let module = self.primitive.module();
+        let name = self.descriptor().unique_name;
+        let ports = circuit_ports::<Self>();
+        let declarations = ports
+            .iter()
+            .map(|port| {
+                let direction = match port.direction {
+                    HDLPortDirection::Input => "input",
+                    HDLPortDirection::Output => "output",
+                    HDLPortDirection::InOut => "inout",
+                };
+                format!("{direction} wire[{}:0] {}", port.kind.bits() - 1, port.name)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let instance = self
+            .instance()
+            .with_context(|| format!("While instantiating the primitive {module}"))?;
+        let mut hdl = HDLDescriptor {
+            name: name.clone(),
+            body: format!("\nmodule {name}({declarations});\n{instance}endmodule\n"),
+            ports,
+            children: Default::default(),
+            initial: vec![],
+        };
+        if self.fallback {
+            let Some(body) = self.primitive.fallback_model() else {
+                bail!("The primitive {module} has no fallback model");
+            };
+            hdl.children.insert(
+                module.to_string(),
+                HDLDescriptor {
+                    name: module.to_string(),
+                    body,
+                    ports: vec![],
+                    children: Default::default(),
+                    initial: vec![],
+                },
+            );
+        }
+        Ok(hdl)
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use std::ops::Range;
+
+use crate::types::digital::TimingException;
+use crate::{Circuit, CircuitDescriptor};
+
+// The timing constraints (in SDC) for the Verilog generated for a
+// circuit, from the timing exceptions on its signals.  Those on the
+// input and output name the input and output ports of the module, and those
+// on D and Q name the `d` and `q` nets inside it, which the Verilog
+// generator keeps whenever they carry an exception.  The D and Q of a
+// circuit hold the inputs and outputs of its children, so annotations
+// on the types of a child show up here as well.
+pub fn sdc_constraints(descriptor: &CircuitDescriptor) -> Result<String> {
+    let mut lines = vec![format!(
+        "# Timing exceptions for {}",
+        descriptor.unique_name
+    )];
+    for (timing, bits) in descriptor.resolve_timing()? {
+        let target = match timing.port.as_str() {
+            "i" => format!(
+                "-from [get_ports {}]",
+                bit_names(&descriptor.port_names.input, bits)
+            ),
+            "o" => format!(
+                "-to [get_ports {}]",
+                bit_names(&descriptor.port_names.output, bits)
+            ),
+            port => format!("-through [get_nets {}]", bit_names(port, bits)),
+        };
+        lines.push(format!("# {}{}", timing.port, timing.path));
+        match timing.exception {
+            TimingException::Multicycle(cycles) => {
+                lines.push(format!("set_multicycle_path -setup {cycles} {target}"));
+                lines.push(format!("set_multicycle_path -hold {} {target}", cycles - 1));
+            }
+            TimingException::FalsePath => {
+                lines.push(format!("set_false_path {target}"));
+            }
+        }
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+pub fn root_sdc<C: Circuit>(circuit: &C) -> Result<String> {
+    sdc_constraints(&circuit.descriptor())
+}
+
+fn bit_names(name: &str, bits: Range<usize>) -> String {
+    format!(
+        "{{{}}}",
+        bits.map(|bit| format!("{name}[{bit}]"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
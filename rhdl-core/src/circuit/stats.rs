@@ -0,0 +1,585 @@
+// Statistics of the signals of a circuit over a simulation run, kept as
+// the run goes, so that a run of millions of cycles can be summarized
+// without keeping its trace.  Each statistic is of a leaf of one of the
+// signals of the circuit: its input (`i`), its output (`o`), or one of
+// the registers named by `Circuit::state_signals`.  For a leaf that is
+//
+// - a single bit (such as a valid or ready flag), it counts the cycles
+//   in which the bit was set, which gives its duty cycle,
+// - an enum (such as the state of an FSM), it counts the cycles spent in
+//   each variant,
+// - a number, it keeps the least and greatest values (such as the
+//   high-water mark of the fill of a FIFO), and their mean.
+//
+// The registers are seen as they are at the start of each cycle, i.e.,
+// before the call to `sim`, alongside the input and output of the cycle.
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+
+use crate::path::{bit_range, leaf_paths, Path, PathElement};
+use crate::types::kind::Enum;
+use crate::{Circuit, CircuitIO, Digital, Kind, TypedBits};
+
+// The leaves of the signals to collect statistics for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatsLeaves {
+    // The given leaves, each a signal and a path within it, as in
+    // `("o".into(), Path::default().field("valid"))`.  A path to an
+    // enum (or to its discriminant) counts its variants.
+    Paths(Vec<(String, Path)>),
+    // The discriminant of every enum in the signals, including those in
+    // the payloads of other enums
+    AllEnums,
+    // Every single bit leaf of the signals that is not part of an enum
+    AllBools,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Signal {
+    Input,
+    Output,
+    // The position of the register in `Circuit::state_signals`
+    State(usize),
+}
+
+#[derive(Clone, Debug)]
+enum Tally {
+    Bool {
+        set: u64,
+    },
+    Enum {
+        kind: Enum,
+        counts: Vec<u64>,
+        invalid: u64,
+    },
+    Unsigned {
+        min: u128,
+        max: u128,
+        sum: f64,
+    },
+    Signed {
+        min: i128,
+        max: i128,
+        sum: f64,
+    },
+}
+
+fn to_unsigned(bits: &[bool]) -> u128 {
+    bits.iter()
+        .rev()
+        .fold(0_u128, |acc, b| (acc << 1) | (*b as u128))
+}
+
+fn to_signed(bits: &[bool]) -> i128 {
+    let value = to_unsigned(bits) as i128;
+    match bits.last() {
+        Some(true) if bits.len() < 128 => value - (1 << bits.len()),
+        _ => value,
+    }
+}
+
+impl Tally {
+    fn for_kind(kind: &Kind) -> Result<Tally> {
+        Ok(match kind.operand_kind() {
+            Kind::Enum(enumeration) => Tally::Enum {
+                kind: enumeration.clone(),
+                counts: vec![0; enumeration.variants.len()],
+                invalid: 0,
+            },
+            Kind::Bits(1) => Tally::Bool { set: 0 },
+            Kind::Bits(width) if *width <= 128 => Tally::Unsigned {
+                min: u128::MAX,
+                max: 0,
+                sum: 0.0,
+            },
+            Kind::Signed(width) if *width <= 128 => Tally::Signed {
+                min: i128::MAX,
+                max: i128::MIN,
+                sum: 0.0,
+            },
+            _ => bail!("a leaf of {kind} has no statistics"),
+        })
+    }
+    fn record(&mut self, bits: &[bool]) {
+        match self {
+            Tally::Bool { set } => *set += bits[0] as u64,
+            Tally::Enum {
+                kind,
+                counts,
+                invalid,
+            } => match kind.variant_index_for_discriminant(bits) {
+                Some(ndx) => counts[ndx] += 1,
+                None => *invalid += 1,
+            },
+            Tally::Unsigned { min, max, sum } => {
+                let value = to_unsigned(bits);
+                *min = (*min).min(value);
+                *max = (*max).max(value);
+                *sum += value as f64;
+            }
+            Tally::Signed { min, max, sum } => {
+                let value = to_signed(bits);
+                *min = (*min).min(value);
+                *max = (*max).max(value);
+                *sum += value as f64;
+            }
+        }
+    }
+    fn stats(&self, cycles: u64) -> LeafStats {
+        // With no cycles, the numbers are all reported as zero
+        let mean = |sum: f64| {
+            if cycles == 0 {
+                0.0
+            } else {
+                sum / cycles as f64
+            }
+        };
+        match self {
+            Tally::Bool { set } => LeafStats::Bool {
+                set: *set,
+                duty_cycle: mean(*set as f64),
+            },
+            Tally::Enum {
+                kind,
+                counts,
+                invalid,
+            } => LeafStats::Enum {
+                variants: kind
+                    .variants
+                    .iter()
+                    .zip(counts)
+                    .map(|(variant, count)| (variant.name.clone(), *count))
+                    .collect(),
+                invalid: *invalid,
+            },
+            Tally::Unsigned { min, max, sum } => LeafStats::Unsigned {
+                min: if cycles == 0 { 0 } else { *min },
+                max: *max,
+                mean: mean(*sum),
+            },
+            Tally::Signed { min, max, sum } => LeafStats::Signed {
+                min: if cycles == 0 { 0 } else { *min },
+                max: if cycles == 0 { 0 } else { *max },
+                mean: mean(*sum),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Leaf {
+    name: String,
+    signal: Signal,
+    range: Range<usize>,
+    tally: Tally,
+}
+
+// Resolve a leaf of a signal to the bits it is read from, and the tally
+// that it is counted in.  An enum is read from its discriminant.
+fn resolve_leaf(kind: &Kind, path: &Path) -> Result<(Range<usize>, Tally)> {
+    let path = match path.elements.last() {
+        Some(PathElement::EnumDiscriminant) => Path {
+            elements: path.elements[..path.len() - 1].into(),
+        },
+        _ => path.clone(),
+    };
+    let (range, leaf_kind) = bit_range(kind.clone(), &path)?;
+    let tally = Tally::for_kind(&leaf_kind)?;
+    if let Tally::Enum { .. } = tally {
+        let (range, _) = bit_range(kind.clone(), &path.discriminant())?;
+        return Ok((range, tally));
+    }
+    Ok((range, tally))
+}
+
+// The leaves of a signal picked by one of the presets.
+fn preset_paths(kind: &Kind, leaves: &StatsLeaves) -> Vec<Path> {
+    let in_enum = |path: &Path| {
+        path.iter().any(|element| {
+            matches!(
+                element,
+                PathElement::EnumDiscriminant
+                    | PathElement::EnumPayload(_)
+                    | PathElement::EnumPayloadByValue(_)
+            )
+        })
+    };
+    match leaves {
+        StatsLeaves::Paths(_) => vec![],
+        StatsLeaves::AllEnums => leaf_paths(kind, Path::default())
+            .into_iter()
+            .filter(|path| path.elements.last() == Some(&PathElement::EnumDiscriminant))
+            .collect(),
+        StatsLeaves::AllBools => kind
+            .leaves()
+            .into_iter()
+            .filter(|(path, _, kind)| kind.is_bool() && !in_enum(path))
+            .map(|(path, _, _)| path)
+            .collect(),
+    }
+}
+
+// Collects the statistics of the leaves of a circuit, one cycle at a
+// time.  It keeps a fixed amount of data for each leaf, however long the
+// run.  Use it with `TraceRecorder::with_stats`, or `collect_stats`.
+#[derive(Clone, Debug)]
+pub struct StatsCollector {
+    name: String,
+    cycles: u64,
+    leaves: Vec<Leaf>,
+    observes_state: bool,
+}
+
+impl StatsCollector {
+    // The leaves are checked against the kinds of the signals of the
+    // circuit, and those of its registers in its initial state.  A signal
+    // named `i` or `o` is the input or output, even if a register has the
+    // same name.
+    pub fn new<C: Circuit>(circuit: &C, leaves: &StatsLeaves) -> Result<Self> {
+        let signals = [
+            ("i".to_string(), Signal::Input, C::I::static_kind()),
+            ("o".to_string(), Signal::Output, C::O::static_kind()),
+        ]
+        .into_iter()
+        .chain(
+            circuit
+                .state_signals(&circuit.init_state())
+                .into_iter()
+                .enumerate()
+                .map(|(ndx, (name, value))| (name, Signal::State(ndx), value.kind)),
+        )
+        .collect::<Vec<_>>();
+        let leaf_name = |signal: &str, path: &Path| {
+            let path = path.to_string();
+            let path = path.strip_suffix('#').unwrap_or(&path);
+            // The register of a primitive circuit has no name of its own
+            if signal.is_empty() {
+                format!("{}{path}", circuit.name())
+            } else {
+                format!("{signal}{path}")
+            }
+        };
+        let paths = match leaves {
+            StatsLeaves::Paths(paths) => paths
+                .iter()
+                .map(|(name, path)| {
+                    let (_, signal, kind) = signals
+                        .iter()
+                        .find(|(signal, _, _)| signal == name)
+                        .ok_or_else(|| {
+                            anyhow!("Circuit {} has no signal named `{name}`", circuit.name())
+                        })?;
+                    Ok((name.as_str(), *signal, kind, path.clone()))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => signals
+                .iter()
+                .flat_map(|(name, signal, kind)| {
+                    preset_paths(kind, leaves)
+                        .into_iter()
+                        .map(move |path| (name.as_str(), *signal, kind, path))
+                })
+                .collect(),
+        };
+        let leaves = paths
+            .into_iter()
+            .map(|(signal_name, signal, kind, path)| {
+                let name = leaf_name(signal_name, &path);
+                let (range, tally) = resolve_leaf(kind, &path).map_err(|err| {
+                    anyhow!(
+                        "Cannot collect statistics for {name} in circuit {}: {err}",
+                        circuit.name()
+                    )
+                })?;
+                Ok(Leaf {
+                    name,
+                    signal,
+                    range,
+                    tally,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let observes_state = leaves
+            .iter()
+            .any(|leaf| matches!(leaf.signal, Signal::State(_)));
+        Ok(Self {
+            name: circuit.name().to_string(),
+            cycles: 0,
+            leaves,
+            observes_state,
+        })
+    }
+    // Record a cycle, given the state of the circuit at the start of the
+    // cycle, and its input and output.
+    pub fn observe<C: Circuit>(
+        &mut self,
+        circuit: &C,
+        state: &C::S,
+        input: <C as CircuitIO>::I,
+        output: <C as CircuitIO>::O,
+    ) {
+        let state = if self.observes_state {
+            circuit.state_signals(state)
+        } else {
+            vec![]
+        };
+        self.record(&input.bin(), &output.bin(), &state);
+    }
+    fn record(&mut self, input: &[bool], output: &[bool], state: &[(String, TypedBits)]) {
+        for leaf in &mut self.leaves {
+            let bits = match leaf.signal {
+                Signal::Input => input,
+                Signal::Output => output,
+                Signal::State(ndx) => &state[ndx].1.bits,
+            };
+            leaf.tally.record(&bits[leaf.range.clone()]);
+        }
+        self.cycles += 1;
+    }
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+    // The statistics of the cycles recorded so far.
+    pub fn report(&self) -> StatsReport {
+        StatsReport {
+            name: self.name.clone(),
+            cycles: self.cycles,
+            leaves: self
+                .leaves
+                .iter()
+                .map(|leaf| LeafReport {
+                    name: leaf.name.clone(),
+                    stats: leaf.tally.stats(self.cycles),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum LeafStats {
+    // The number of cycles in which the bit was set, and the fraction of
+    // all cycles that is
+    Bool {
+        set: u64,
+        duty_cycle: f64,
+    },
+    // The number of cycles spent in each variant, in the order of the
+    // variants, and in which the discriminant was that of no variant
+    Enum {
+        variants: Vec<(String, u64)>,
+        invalid: u64,
+    },
+    Unsigned {
+        min: u128,
+        max: u128,
+        mean: f64,
+    },
+    Signed {
+        min: i128,
+        max: i128,
+        mean: f64,
+    },
+}
+
+impl LeafStats {
+    // The number of cycles spent in the given variant of an enum.
+    pub fn occupancy(&self, variant: &str) -> Option<u64> {
+        let LeafStats::Enum { variants, .. } = self else {
+            return None;
+        };
+        variants
+            .iter()
+            .find(|(name, _)| name == variant)
+            .map(|(_, count)| *count)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LeafReport {
+    pub name: String,
+    pub stats: LeafStats,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StatsReport {
+    pub name: String,
+    pub cycles: u64,
+    pub leaves: Vec<LeafReport>,
+}
+
+impl StatsReport {
+    // The statistics of a leaf, by its name, as in `o.valid`.
+    pub fn leaf(&self, name: &str) -> Option<&LeafStats> {
+        self.leaves
+            .iter()
+            .find(|leaf| leaf.name == name)
+            .map(|leaf| &leaf.stats)
+    }
+}
+
+impl std::fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let percent = |count: u64| {
+            if self.cycles == 0 {
+                0.0
+            } else {
+                100.0 * count as f64 / self.cycles as f64
+            }
+        };
+        writeln!(f, "Statistics of {} over {} cycles", self.name, self.cycles)?;
+        for leaf in &self.leaves {
+            write!(f, "  {}: ", leaf.name)?;
+            match &leaf.stats {
+                LeafStats::Bool { set, .. } => writeln!(
+                    f,
+                    "set in {set} of {} cycles ({:.1}%)",
+                    self.cycles,
+                    percent(*set)
+                )?,
+                LeafStats::Enum { variants, invalid } => {
+                    let invalid = (*invalid != 0).then_some(("invalid".to_string(), *invalid));
+                    let counts = variants
+                        .iter()
+                        .cloned()
+                        .chain(invalid)
+                        .map(|(name, count)| format!("{name} {count} ({:.1}%)", percent(count)))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    writeln!(f, "{counts}")?
+                }
+                LeafStats::Unsigned { min, max, mean } => {
+                    writeln!(f, "from {min} to {max}, mean {mean:.2}")?
+                }
+                LeafStats::Signed { min, max, mean } => {
+                    writeln!(f, "from {min} to {max}, mean {mean:.2}")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Simulate the circuit with the inputs (from its initial state), and
+// collect the statistics of the given leaves, without keeping a trace.
+pub fn collect_stats<C: Circuit>(
+    circuit: &C,
+    leaves: &StatsLeaves,
+    inputs: impl IntoIterator<Item = <C as CircuitIO>::I>,
+) -> Result<StatsReport> {
+    let mut stats = StatsCollector::new(circuit, leaves)?;
+    let mut state = circuit.init_state();
+    let mut io = C::Z::default();
+    for input in inputs {
+        let start = stats.observes_state.then(|| state.clone());
+        let output = circuit.sim(input, &mut state, &mut io);
+        stats.observe(circuit, start.as_ref().unwrap_or(&state), input, output);
+    }
+    Ok(stats.report())
+}
+
+#[cfg(test)]
+mod tests {
+    use rhdl_bits::Bits;
+
+    use super::*;
+
+    #[test]
+    fn test_tallies_of_each_kind() {
+        let mut tally = Tally::for_kind(&Kind::make_signed(4)).unwrap();
+        for value in [3_i128, -8, 7, -1] {
+            tally.record(&rhdl_bits::SignedBits::<4>(value).typed_bits().bits);
+        }
+        assert_eq!(
+            tally.stats(4),
+            LeafStats::Signed {
+                min: -8,
+                max: 7,
+                mean: 0.25
+            }
+        );
+        let mut tally = Tally::for_kind(&Kind::make_bits(8)).unwrap();
+        assert_eq!(
+            tally.stats(0),
+            LeafStats::Unsigned {
+                min: 0,
+                max: 0,
+                mean: 0.0
+            }
+        );
+        for value in [12, 200, 7] {
+            tally.record(&Bits::<8>(value).typed_bits().bits);
+        }
+        assert_eq!(
+            tally.stats(3),
+            LeafStats::Unsigned {
+                min: 7,
+                max: 200,
+                mean: 73.0
+            }
+        );
+        let mut tally = Tally::for_kind(&Kind::make_bool()).unwrap();
+        for value in [true, false, true, true] {
+            tally.record(&[value]);
+        }
+        assert_eq!(
+            tally.stats(4),
+            LeafStats::Bool {
+                set: 3,
+                duty_cycle: 0.75
+            }
+        );
+        let err = Tally::for_kind(&Kind::make_tuple(vec![Kind::make_bool()])).unwrap_err();
+        assert_eq!(err.to_string(), "a leaf of (b1) has no statistics");
+    }
+
+    #[test]
+    fn test_presets_pick_leaves() {
+        let state = Kind::make_enum(
+            "State",
+            vec![
+                Kind::make_variant("Idle", Kind::Empty, 0),
+                Kind::make_variant(
+                    "Busy",
+                    Kind::make_tuple(vec![Kind::make_bool(), Kind::make_bits(3)]),
+                    1,
+                ),
+            ],
+            Kind::make_discriminant_layout(
+                1,
+                crate::types::kind::DiscriminantAlignment::Msb,
+                crate::types::kind::DiscriminantType::Unsigned,
+            ),
+        );
+        let kind = Kind::make_struct(
+            "Bus",
+            vec![
+                Kind::make_field("valid", Kind::make_bool()),
+                Kind::make_field("data", Kind::make_bits(8)),
+                Kind::make_field("state", state),
+                Kind::make_field("ready", Kind::make_array(Kind::make_bool(), 2)),
+            ],
+        );
+        let names = |leaves| {
+            preset_paths(&kind, &leaves)
+                .iter()
+                .map(|path| path.to_string())
+                .collect::<Vec<_>>()
+        };
+        // The bool in the payload of `Busy` is left out
+        assert_eq!(
+            names(StatsLeaves::AllBools),
+            [".valid", ".ready[0]", ".ready[1]"]
+        );
+        assert_eq!(names(StatsLeaves::AllEnums), [".state#"]);
+        let (range, tally) = resolve_leaf(&kind, &Path::default().field("state")).unwrap();
+        assert_eq!(range, 13..14);
+        assert!(matches!(tally, Tally::Enum { .. }));
+        let (same, _) =
+            resolve_leaf(&kind, &Path::default().field("state").discriminant()).unwrap();
+        assert_eq!(same, range);
+        assert!(resolve_leaf(&kind, &Path::default().field("ready")).is_err());
+    }
+}
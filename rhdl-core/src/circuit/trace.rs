@@ -0,0 +1,477 @@
+use std::hash::Hasher;
+use std::io::{BufRead, Write};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::output_mask::{clear_ignored, MaskStats, OutputMask};
+use crate::circuit::stats::{StatsCollector, StatsReport};
+use crate::path::{diff_paths, Path};
+use crate::{Circuit, CircuitIO, Digital, Kind, TypedBits};
+
+pub const TRACE_FILE_VERSION: u32 = 1;
+
+const TRACE_FILE_MAGIC: &str = "rhdl-trace";
+
+// A recording of a simulation run of a circuit.  For each cycle (i.e.,
+// each call to `sim`), the input and output are stored as packed bits
+// (LSB first, 8 bits to a byte).  The kinds of the input and output
+// are stored alongside, so that a replay can check that the circuit
+// still has the same interface.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraceFile {
+    pub name: String,
+    pub input_kind: Kind,
+    pub output_kind: Kind,
+    pub cycles: Vec<TraceCycle>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TraceCycle {
+    pub input: Vec<u8>,
+    pub output: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TraceSchema {
+    name: String,
+    input_kind: Kind,
+    output_kind: Kind,
+    cycles: usize,
+}
+
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (ndx, bit)| acc | ((*bit as u8) << ndx))
+        })
+        .collect()
+}
+
+fn unpack_bits(bytes: &[u8], width: usize) -> Vec<bool> {
+    (0..width)
+        .map(|ndx| bytes[ndx / 8] & (1 << (ndx % 8)) != 0)
+        .collect()
+}
+
+fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().rev().map(|x| format!("{x:02x}")).collect()
+}
+
+fn hex_to_bytes(hex: &str, width: usize) -> Result<Vec<u8>> {
+    let len = width.div_ceil(8);
+    ensure!(
+        hex.len() == len * 2,
+        "Expected {} hex digits for a {width} bit value, got `{hex}`",
+        len * 2
+    );
+    // Checked before slicing, since a non-ASCII character would put the
+    // byte offsets off a character boundary
+    ensure!(
+        hex.bytes().all(|x| x.is_ascii_hexdigit()),
+        "Invalid hex value `{hex}`"
+    );
+    let mut bytes = (0..len)
+        .map(|ndx| u8::from_str_radix(&hex[ndx * 2..ndx * 2 + 2], 16))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|err| anyhow!("Invalid hex value `{hex}`: {err}"))?;
+    bytes.reverse();
+    ensure!(
+        pack_bits(&unpack_bits(&bytes, width)) == bytes,
+        "Value `{hex}` does not fit in {width} bits"
+    );
+    Ok(bytes)
+}
+
+// A hash of the input and output kinds, stored in the header so that
+// a file can be checked against a circuit without parsing the schema.
+// It is taken over the JSON of the kinds (as in the schema), rather than
+// with `Hash`, whose encoding depends on the platform and the compiler.
+pub fn kind_hash(input_kind: &Kind, output_kind: &Kind) -> u64 {
+    let mut hasher = fnv::FnvHasher::default();
+    for kind in [input_kind, output_kind] {
+        let json = serde_json::to_string(kind).expect("Kind serializes to JSON");
+        hasher.write(json.as_bytes());
+        hasher.write_u8(b'\n');
+    }
+    hasher.finish()
+}
+
+impl TraceFile {
+    pub fn new<C: Circuit>(circuit: &C) -> Self {
+        Self {
+            name: circuit.name().to_string(),
+            input_kind: C::I::static_kind(),
+            output_kind: C::O::static_kind(),
+            cycles: vec![],
+        }
+    }
+    pub fn kind_hash(&self) -> u64 {
+        kind_hash(&self.input_kind, &self.output_kind)
+    }
+    pub fn input(&self, cycle: usize) -> TypedBits {
+        TypedBits {
+            bits: unpack_bits(&self.cycles[cycle].input, self.input_kind.bits()),
+            kind: self.input_kind.clone(),
+        }
+    }
+    pub fn output(&self, cycle: usize) -> TypedBits {
+        TypedBits {
+            bits: unpack_bits(&self.cycles[cycle].output, self.output_kind.bits()),
+            kind: self.output_kind.clone(),
+        }
+    }
+    // The file starts with a header line holding the format version and
+    // the kind hash, followed by a line of JSON holding the schema.  Each
+    // cycle is then a line with the input and output as hex (MSB first),
+    // separated by a single space.
+    pub fn write<W: Write>(&self, mut w: W) -> Result<()> {
+        writeln!(
+            w,
+            "{TRACE_FILE_MAGIC} {TRACE_FILE_VERSION} {:016x}",
+            self.kind_hash()
+        )?;
+        let schema = TraceSchema {
+            name: self.name.clone(),
+            input_kind: self.input_kind.clone(),
+            output_kind: self.output_kind.clone(),
+            cycles: self.cycles.len(),
+        };
+        writeln!(w, "{}", serde_json::to_string(&schema)?)?;
+        for cycle in &self.cycles {
+            writeln!(
+                w,
+                "{} {}",
+                bytes_to_hex(&cycle.input),
+                bytes_to_hex(&cycle.output)
+            )?;
+        }
+        Ok(())
+    }
+    pub fn read<R: BufRead>(r: R) -> Result<Self> {
+        let mut lines = r.lines();
+        let mut next_line = || -> Result<String> {
+            lines
+                .next()
+                .ok_or_else(|| anyhow!("Unexpected end of trace file"))?
+                .map_err(Into::into)
+        };
+        let header = next_line()?;
+        let header = header.split(' ').collect::<Vec<_>>();
+        let [magic, version, hash] = header[..] else {
+            bail!("Invalid trace file header {header:?}");
+        };
+        ensure!(magic == TRACE_FILE_MAGIC, "Not a trace file");
+        ensure!(
+            version == TRACE_FILE_VERSION.to_string(),
+            "Unsupported trace file version {version} (expected {TRACE_FILE_VERSION})"
+        );
+        let hash = u64::from_str_radix(hash, 16)?;
+        let schema: TraceSchema = serde_json::from_str(&next_line()?)?;
+        ensure!(
+            kind_hash(&schema.input_kind, &schema.output_kind) == hash,
+            "Trace file schema does not match the kind hash in its header"
+        );
+        let input_bits = schema.input_kind.bits();
+        let output_bits = schema.output_kind.bits();
+        let cycles = (0..schema.cycles)
+            .map(|_| {
+                let line = next_line()?;
+                let (input, output) = line
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("Invalid trace file line `{line}`"))?;
+                Ok(TraceCycle {
+                    input: hex_to_bytes(input, input_bits)?,
+                    output: hex_to_bytes(output, output_bits)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            name: schema.name,
+            input_kind: schema.input_kind,
+            output_kind: schema.output_kind,
+            cycles,
+        })
+    }
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write(std::io::BufWriter::new(file))
+    }
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Self::read(std::io::BufReader::new(file))
+    }
+    // The recorded inputs, one per line, in the hex format read by
+    // `$readmemh`.  A testbench can load them with
+    //   reg [W-1:0] stimulus[0:N-1];
+    //   initial $readmemh("stimulus.mem", stimulus);
+    // and then apply `stimulus[k]` to the circuit at cycle `k`.
+    pub fn input_stimulus_verilog(&self) -> String {
+        let input_bits = self.input_kind.bits();
+        let digits = input_bits.div_ceil(4);
+        let mut ret = format!(
+            "// Stimulus for {} ({} cycles of {} bit inputs)\n",
+            self.name,
+            self.cycles.len(),
+            input_bits
+        );
+        for cycle in &self.cycles {
+            let hex = bytes_to_hex(&cycle.input);
+            ret.push_str(&hex[hex.len() - digits..]);
+            ret.push('\n');
+        }
+        ret
+    }
+}
+
+// Records the inputs and outputs of a circuit while it is simulated,
+// and (with `with_stats`) the statistics of its signals.
+pub struct TraceRecorder<'a, C: Circuit> {
+    circuit: &'a C,
+    state: C::S,
+    io: C::Z,
+    trace: TraceFile,
+    keep_cycles: bool,
+    stats: Option<StatsCollector>,
+}
+
+impl<'a, C: Circuit> TraceRecorder<'a, C> {
+    pub fn new(circuit: &'a C) -> Self {
+        Self {
+            circuit,
+            state: circuit.init_state(),
+            io: C::Z::default(),
+            trace: TraceFile::new(circuit),
+            keep_cycles: true,
+            stats: None,
+        }
+    }
+    // Collect statistics alongside the trace.
+    pub fn with_stats(self, stats: StatsCollector) -> Self {
+        Self {
+            stats: Some(stats),
+            ..self
+        }
+    }
+    // Do not keep the inputs and outputs of each cycle, so that a long
+    // run that only collects statistics does not grow the trace.
+    pub fn without_cycles(self) -> Self {
+        Self {
+            keep_cycles: false,
+            ..self
+        }
+    }
+    pub fn step(&mut self, input: <C as CircuitIO>::I) -> <C as CircuitIO>::O {
+        // The statistics see the registers as they are at the start of
+        // the cycle
+        let start = self.stats.as_ref().map(|_| self.state.clone());
+        let output = self.circuit.sim(input, &mut self.state, &mut self.io);
+        if self.keep_cycles {
+            self.trace.cycles.push(TraceCycle {
+                input: pack_bits(&input.bin()),
+                output: pack_bits(&output.bin()),
+            });
+        }
+        if let (Some(stats), Some(start)) = (&mut self.stats, start) {
+            stats.observe(self.circuit, &start, input, output);
+        }
+        output
+    }
+    // The statistics of the cycles so far, if they are being collected.
+    pub fn stats(&self) -> Option<StatsReport> {
+        self.stats.as_ref().map(StatsCollector::report)
+    }
+    pub fn finish(self) -> TraceFile {
+        self.trace
+    }
+    pub fn finish_with_stats(self) -> (TraceFile, Option<StatsReport>) {
+        let stats = self.stats();
+        (self.trace, stats)
+    }
+}
+
+pub fn record<C: Circuit>(
+    circuit: &C,
+    inputs: impl IntoIterator<Item = <C as CircuitIO>::I>,
+) -> TraceFile {
+    let mut recorder = TraceRecorder::new(circuit);
+    for input in inputs {
+        recorder.step(input);
+    }
+    recorder.finish()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceMismatch {
+    pub cycle: usize,
+    pub expected: TypedBits,
+    pub actual: TypedBits,
+    // The leaf paths (within the output) that differ
+    pub paths: Vec<Path>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayReport {
+    pub cycles: usize,
+    pub mismatches: Vec<TraceMismatch>,
+    // The bits left out of the comparison by the mask (see `replay_masked`)
+    pub masked: MaskStats,
+}
+
+impl ReplayReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+    pub fn first_mismatch(&self) -> Option<&TraceMismatch> {
+        self.mismatches.first()
+    }
+}
+
+impl std::fmt::Display for TraceMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let paths = self
+            .paths
+            .iter()
+            .map(|path| format!("o{path}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            "cycle {}: expected {} but got {} (differs in {paths})",
+            self.cycle, self.expected, self.actual
+        )
+    }
+}
+
+impl std::fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Replayed {} cycles with {} mismatches",
+            self.cycles,
+            self.mismatches.len()
+        )?;
+        if self.masked.total() != 0 {
+            writeln!(f, "  {}", self.masked)?;
+        }
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {mismatch}")?;
+        }
+        Ok(())
+    }
+}
+
+// Re-run the recorded inputs through the circuit (starting from its
+// initial state), and compare the outputs with the recorded ones.
+pub fn replay<C: Circuit>(circuit: &C, trace: &TraceFile) -> Result<ReplayReport> {
+    replay_masked(circuit, trace, &OutputMask::default())
+}
+
+// As `replay`, but the bits of the output ignored by `mask` (given the
+// recorded output of each cycle) are left out of the comparison.
+pub fn replay_masked<C: Circuit>(
+    circuit: &C,
+    trace: &TraceFile,
+    mask: &OutputMask<C::O>,
+) -> Result<ReplayReport> {
+    let input_kind = C::I::static_kind();
+    let output_kind = C::O::static_kind();
+    ensure!(
+        input_kind == trace.input_kind,
+        "Input of circuit {} is {}, but the trace of {} was recorded with {}",
+        circuit.name(),
+        input_kind,
+        trace.name,
+        trace.input_kind
+    );
+    ensure!(
+        output_kind == trace.output_kind,
+        "Output of circuit {} is {}, but the trace of {} was recorded with {}",
+        circuit.name(),
+        output_kind,
+        trace.name,
+        trace.output_kind
+    );
+    let mut state = circuit.init_state();
+    let mut io = C::Z::default();
+    let mut mismatches = vec![];
+    let mut masked = MaskStats::default();
+    for cycle in 0..trace.cycles.len() {
+        let input = C::I::maybe_from_bin(&trace.input(cycle).bits)?;
+        let actual = circuit.sim(input, &mut state, &mut io).typed_bits();
+        let expected = trace.output(cycle);
+        let ignored = mask.ignored(&C::O::maybe_from_bin(&expected.bits)?);
+        masked.record(&ignored);
+        let expected_bits = clear_ignored(&expected.bits, &ignored);
+        let actual_bits = clear_ignored(&actual.bits, &ignored);
+        if actual_bits != expected_bits {
+            let paths = diff_paths(&output_kind, &expected_bits, &actual_bits)?;
+            mismatches.push(TraceMismatch {
+                cycle,
+                expected,
+                actual,
+                paths,
+            });
+        }
+    }
+    Ok(ReplayReport {
+        cycles: trace.cycles.len(),
+        mismatches,
+        masked,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        let bits = [
+            true, false, true, true, false, false, false, false, true, true,
+        ];
+        let bytes = pack_bits(&bits);
+        assert_eq!(bytes, vec![0x0D, 0x03]);
+        assert_eq!(bytes_to_hex(&bytes), "030d");
+        assert_eq!(hex_to_bytes("030d", 10).unwrap(), bytes);
+        assert_eq!(unpack_bits(&bytes, 10), bits);
+        assert!(hex_to_bytes("070d", 10).is_err());
+        assert!(hex_to_bytes("0d", 10).is_err());
+        assert!(hex_to_bytes("0x0d", 10).is_err());
+        assert!(hex_to_bytes("0\u{e9}d", 10).is_err());
+        assert_eq!(hex_to_bytes("", 0).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_trace_file_round_trip() {
+        let trace = TraceFile {
+            name: "test".into(),
+            input_kind: Kind::make_tuple(vec![Kind::make_bool(), Kind::make_bits(10)]),
+            output_kind: Kind::make_signed(4),
+            cycles: vec![
+                TraceCycle {
+                    input: vec![0x1B, 0x06],
+                    output: vec![0x0F],
+                },
+                TraceCycle {
+                    input: vec![0x00, 0x00],
+                    output: vec![0x08],
+                },
+            ],
+        };
+        let mut buf = vec![];
+        trace.write(&mut buf).unwrap();
+        let text = String::from_utf8(buf.clone()).unwrap();
+        // The hash is the same on every platform
+        assert_eq!(text.lines().next(), Some("rhdl-trace 1 9be1e250f2b518a2"));
+        assert!(text.ends_with("061b 0f\n0000 08\n"));
+        assert_eq!(TraceFile::read(&buf[..]).unwrap(), trace);
+        assert_eq!(trace.input_stimulus_verilog().lines().nth(1), Some("61b"));
+        // A file from a later version is rejected
+        let text = text.replacen("rhdl-trace 1", "rhdl-trace 2", 1);
+        assert!(TraceFile::read(text.as_bytes()).is_err());
+    }
+}
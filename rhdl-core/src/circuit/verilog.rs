@@ -1,54 +1,95 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use std::ops::Range;
 
-use crate::circuit::circuit_impl::Tristate;
+use crate::codegen::verilog::{keep_attribute, probes_concat, verilog_attribute};
+use crate::compiler::driver::installed_options;
 use crate::path::{bit_range, Path};
 use crate::types::digital::Digital;
 use crate::types::digital_fn::DigitalFn;
-use crate::{compile_design, generate_verilog, KernelFnKind};
+use crate::{
+    compile_design, concat_verilog_functions, generate_verilog_probes, generate_verilog_split,
+    KernelFnKind,
+};
 
 use super::{
-    circuit_descriptor::CircuitDescriptor, circuit_impl::Circuit, hdl_descriptor::HDLDescriptor,
+    circuit_descriptor::CircuitDescriptor,
+    circuit_impl::Circuit,
+    hdl_descriptor::{circuit_ports, HDLDescriptor, HDLPort, HDLPortDirection},
 };
 
 pub fn root_verilog<C: Circuit>(t: &C) -> Result<HDLDescriptor> {
     // Start with the module declaration for the circuit.
     let descriptor = t.descriptor();
+    descriptor.check_size(installed_options().max_kind_bits)?;
+    descriptor.check_update_signature()?;
     let input_bits = C::I::bits();
     let outputs = C::O::bits();
 
     let module_name = &descriptor.unique_name;
     // module top(input wire clk, input wire[0:0] top_in, output reg[3:0] top_out);
 
-    let io_decl = if C::Z::N != 0 {
-        format!(
-            ", inout wire[{IO_BITS}:0] io",
-            IO_BITS = C::Z::N.saturating_sub(1)
-        )
-    } else {
-        Default::default()
-    };
-
+    // Zero width values have no representation in Verilog, so any
+    // port or wire that would carry one is left out of the module.
+    let names = &descriptor.port_names;
+    names.check()?;
+    let ports = circuit_ports::<C>()
+        .into_iter()
+        .map(|port| HDLPort {
+            name: match port.direction {
+                HDLPortDirection::Input => names.input.clone(),
+                HDLPortDirection::Output => names.output.clone(),
+                HDLPortDirection::InOut => port.name,
+            },
+            ..port
+        })
+        .collect::<Vec<_>>();
     let module_decl = format!(
-        "module {module_name}(input wire[{INPUT_BITS}:0] i, output wire[{OUTPUT_BITS}:0] o{io_decl});",
-        module_name = module_name,
-        INPUT_BITS = input_bits.saturating_sub(1),
-        OUTPUT_BITS = outputs.saturating_sub(1)
+        "module {module_name}({});",
+        ports
+            .iter()
+            .map(HDLPort::decl)
+            .collect::<Vec<_>>()
+            .join(", ")
     );
 
-    let o_d_bits = C::O::bits() + C::D::bits();
-    // Next declare the D and Q wires
-    let od_decl = format!(
-        "wire[{OD_BITS}:0] od;",
-        OD_BITS = o_d_bits.saturating_sub(1)
-    );
-    let d_decl = format!(
-        "wire[{D_BITS}:0] d;",
-        D_BITS = C::D::bits().saturating_sub(1)
-    );
-    let q_decl = format!(
-        "wire[{Q_BITS}:0] q;",
-        Q_BITS = C::Q::bits().saturating_sub(1)
-    );
+    let d_bits = C::D::bits();
+    let q_bits = C::Q::bits();
+    let o_d_bits = outputs + d_bits;
+    // Next declare the D and Q wires.  They are kept through synthesis
+    // if the timing constraints of the circuit refer to them.
+    let keep = |port: &str| {
+        if descriptor.timing.iter().any(|timing| timing.port == port) {
+            verilog_attribute("keep", None)
+        } else {
+            String::new()
+        }
+    };
+    let mut wires = vec![];
+    // Ports with other names are connected to `i` and `o`, which the rest
+    // of the module is written in terms of.
+    if input_bits != 0 && names.input != "i" {
+        wires.push(format!("wire[{}:0] i;", input_bits - 1));
+        wires.push(format!("assign i = {};", names.input));
+    }
+    if outputs != 0 && names.output != "o" {
+        wires.push(format!("wire[{}:0] o;", outputs - 1));
+        wires.push(format!("assign {} = o;", names.output));
+    }
+    if o_d_bits != 0 {
+        wires.push(format!("wire[{}:0] od;", o_d_bits - 1));
+    }
+    if d_bits != 0 {
+        wires.push(format!("{}wire[{}:0] d;", keep("d"), d_bits - 1));
+    }
+    if q_bits != 0 {
+        wires.push(format!("{}wire[{}:0] q;", keep("q"), q_bits - 1));
+    }
+    if outputs != 0 {
+        wires.push(format!("assign o = od[{}:0];", outputs - 1));
+    }
+    if d_bits != 0 {
+        wires.push(format!("assign d = od[{}:{}];", o_d_bits - 1, outputs));
+    }
 
     // Next, for each sub-component, we need to determine it's input range from the Q and D types.
     // Loop over the components.
@@ -56,28 +97,68 @@ pub fn root_verilog<C: Circuit>(t: &C) -> Result<HDLDescriptor> {
         .children
         .iter()
         .enumerate()
-        .map(|(ndx, (local, desc))| component_decl::<C>(ndx, local, desc))
+        .map(|(ndx, (local, desc))| {
+            component_decl::<C>(ndx, local, desc, descriptor.named_child_wires, &mut wires)
+        })
         .collect::<Result<Vec<_>>>()?
         .join("\n");
     let Some(KernelFnKind::Kernel(kernel)) = C::Update::kernel_fn() else {
         return Err(anyhow::anyhow!("No kernel function for {}", t.name()));
     };
-    let verilog = generate_verilog(&compile_design(kernel)?)?;
-    let fn_call = format!("assign od = {fn_name}(i, q);", fn_name = &verilog.name);
-    let fn_body = &verilog.body;
-    let o_bind = format!("assign o = od[{}:{}];", outputs.saturating_sub(1), 0);
-    let d_bind = format!(
-        "assign d = od[{}:{}];",
-        C::D::bits().saturating_sub(1),
-        outputs
+    let design = compile_design(kernel)?;
+    let mut functions = generate_verilog_split(&design)?;
+    // Zero width arguments are passed as a placeholder bit, and if the
+    // update function has nothing to return, there is nothing to assign.
+    let fn_args = format!(
+        "{i}, {q}",
+        i = if input_bits != 0 { "i" } else { "1'b0" },
+        q = if q_bits != 0 { "q" } else { "1'b0" },
     );
+    let mut fn_call = if o_d_bits != 0 {
+        format!(
+            "assign od = {fn_name}({fn_args});",
+            fn_name = design.func_name(design.top)?,
+        )
+    } else {
+        Default::default()
+    };
+    // The probes of the update kernel are brought out as named wires,
+    // computed by a copy of the kernel that returns them.
+    if let Some(probes_fn) = generate_verilog_probes(&design)? {
+        let obj = &design.objects[&design.top];
+        for probe in &obj.probes {
+            if wires
+                .iter()
+                .any(|wire| wire_name(wire) == Some(&probe.name))
+                || ["i", "o", "io", "od", "d", "q"].contains(&probe.name.as_str())
+                || [&names.input, &names.output].contains(&&probe.name)
+            {
+                bail!(
+                    "The probe `{}` in {} clashes with a wire of the generated module",
+                    probe.name,
+                    obj.name
+                );
+            }
+            let width = obj.kind[&probe.slot].bits();
+            wires.push(format!(
+                "{}wire[{}:0] {};",
+                keep_attribute(probe),
+                width - 1,
+                probe.name
+            ));
+        }
+        fn_call.push_str(&format!(
+            "\nassign {} = {}({fn_args});",
+            probes_concat(obj),
+            probes_fn.name
+        ));
+        functions.push(probes_fn);
+    }
+    let wire_decls = wires.join("\n");
+    let fn_body = concat_verilog_functions(&functions)?;
     let code = format!(
         "{module_decl}
-{od_decl}
-{d_decl}
-{q_decl}
-{o_bind}
-{d_bind}
+{wire_decls}
 
 {component_decls}
 
@@ -92,14 +173,27 @@ endmodule
     Ok(HDLDescriptor {
         name: module_name.into(),
         body: code,
+        ports,
         children: Default::default(),
+        initial: vec![],
     })
 }
 
+// The name declared by a `wire[..] name;` line.
+fn wire_name(wire: &str) -> Option<&str> {
+    wire.trim_start_matches("(* keep *) ")
+        .strip_prefix("wire[")?
+        .split_once("] ")?
+        .1
+        .strip_suffix(';')
+}
+
 fn component_decl<C: Circuit>(
     ndx: usize,
     local_name: &str,
     desc: &CircuitDescriptor,
+    named_wires: bool,
+    wires: &mut Vec<String>,
 ) -> Result<String> {
     // instantiate the component with name components.name.
     // give it a unique instance name of c{ndx}
@@ -112,13 +206,45 @@ fn component_decl<C: Circuit>(
     eprintln!("local_name: {local_name}");
     let (d_range, _) = bit_range(d_kind, &Path::default().field(local_name))?;
     let (q_range, _) = bit_range(q_kind, &Path::default().field(local_name))?;
+    // Ports of zero width are omitted from the child module, so they
+    // must not be connected here either.
+    let mut connections = vec![];
+    let slice =
+        |name: &str, range: &Range<usize>| format!("{name}[{}:{}]", range.end - 1, range.start);
+    if named_wires {
+        // Name the slices of d and q for the child, so that they show up
+        // as (say) `lane3_d` and `lane3_q` in a waveform viewer.
+        if !d_range.is_empty() {
+            let wire = format!("{local_name}_d");
+            wires.push(format!("wire[{}:0] {wire};", d_range.len() - 1));
+            wires.push(format!("assign {wire} = {};", slice("d", &d_range)));
+            connections.push(format!(".{}({wire})", desc.port_names.input));
+        }
+        if !q_range.is_empty() {
+            let wire = format!("{local_name}_q");
+            wires.push(format!("wire[{}:0] {wire};", q_range.len() - 1));
+            wires.push(format!("assign {} = {wire};", slice("q", &q_range)));
+            connections.push(format!(".{}({wire})", desc.port_names.output));
+        }
+    } else {
+        if !d_range.is_empty() {
+            connections.push(format!(
+                ".{}({})",
+                desc.port_names.input,
+                slice("d", &d_range)
+            ));
+        }
+        if !q_range.is_empty() {
+            connections.push(format!(
+                ".{}({})",
+                desc.port_names.output,
+                slice("q", &q_range)
+            ));
+        }
+    }
     Ok(format!(
-        "{component_name} c{ndx} (.i(d[{d_msb}:{d_lsb}]),.o(q[{q_msb}:{q_lsb}]));",
+        "{component_name} c{ndx} ({connections});",
         component_name = desc.unique_name,
-        ndx = ndx,
-        d_msb = d_range.end.saturating_sub(1),
-        d_lsb = d_range.start,
-        q_msb = q_range.end.saturating_sub(1),
-        q_lsb = q_range.start
+        connections = connections.join(",")
     ))
 }
@@ -1 +1,2 @@
+pub mod mux;
 pub mod verilog;
@@ -0,0 +1,186 @@
+use std::ops::Add;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    path::{sub_kind, Path, PathElement},
+    rhif::spec::{Index, OpCode},
+    rhif::Object,
+    Kind,
+};
+
+// How a dynamic index into an array (like `table[ndx]`) is written in
+// the generated Verilog.  The result is the same for every index that is
+// in range, but the hardware is not, and different targets (and timing
+// budgets) prefer different forms.
+//
+// - `PartSelect` computes the bit offset of the element from the index,
+//   and reads it with an indexed part select (`a[offset +: width]`).
+//   Large array arguments are read from a Verilog memory instead.  This
+//   leaves the choice of structure to the synthesis tool.
+// - `Tree` is a balanced tree of 2:1 muxes, one level per bit of the
+//   index, written as nested ternaries.
+// - `OneHot` decodes the index into one select line per element, and
+//   ORs together the elements ANDed with their select lines.
+//
+// An index past the end of the array (which panics in Rust) reads an
+// unspecified element with `Tree`, and zero with `OneHot`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MuxStrategy {
+    #[default]
+    PartSelect,
+    Tree,
+    OneHot,
+}
+
+// A rough count of the two-input gates a dynamic index becomes, and the
+// depth of the logic in gates, before the synthesis tool has had a go at
+// it.  It is only good for comparing the strategies with each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MuxEstimate {
+    pub mux2: usize,
+    pub and2: usize,
+    pub or2: usize,
+    pub depth: usize,
+}
+
+impl Add for MuxEstimate {
+    type Output = MuxEstimate;
+    fn add(self, other: MuxEstimate) -> MuxEstimate {
+        MuxEstimate {
+            mux2: self.mux2 + other.mux2,
+            and2: self.and2 + other.and2,
+            or2: self.or2 + other.or2,
+            depth: self.depth.max(other.depth),
+        }
+    }
+}
+
+fn clog2(x: usize) -> usize {
+    x.next_power_of_two().trailing_zeros() as usize
+}
+
+impl MuxStrategy {
+    // The estimate for picking one of `entries` elements of `width` bits.
+    pub fn estimate(self, entries: usize, width: usize) -> MuxEstimate {
+        if entries < 2 || width == 0 {
+            return MuxEstimate::default();
+        }
+        let levels = clog2(entries);
+        match self {
+            // A logarithmic shifter over the whole array, with one stage
+            // per bit of the index.
+            MuxStrategy::PartSelect => MuxEstimate {
+                mux2: levels * entries * width,
+                depth: levels,
+                ..Default::default()
+            },
+            // One mux per element (less one) per bit of the result.
+            MuxStrategy::Tree => MuxEstimate {
+                mux2: (entries - 1) * width,
+                depth: levels,
+                ..Default::default()
+            },
+            // A decoder (an AND of the index bits per element), the mask
+            // of each element, and an OR tree per bit of the result.
+            MuxStrategy::OneHot => MuxEstimate {
+                and2: entries * (levels - 1) + entries * width,
+                or2: (entries - 1) * width,
+                depth: clog2(levels) + 1 + levels,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+// The estimate for all of the dynamic indices of an object, as they
+// would be generated with the given strategy.
+pub fn estimate_dynamic_indexing(obj: &Object, strategy: MuxStrategy) -> Result<MuxEstimate> {
+    let mut estimate = MuxEstimate::default();
+    for op in &obj.ops {
+        let OpCode::Index(Index { arg, path, .. }) = op else {
+            continue;
+        };
+        if !path.any_dynamic() {
+            continue;
+        }
+        let kind = obj.kind.get(arg).ok_or(anyhow::anyhow!(
+            "No type for slot {arg} in function {}",
+            obj.name
+        ))?;
+        let entries = dynamic_index_sizes(kind, path)?
+            .into_iter()
+            .product::<usize>();
+        let element = sub_kind(kind.clone(), &resolve_dynamic_indices(path, &[]))?;
+        estimate = estimate + strategy.estimate(entries, element.bits());
+    }
+    Ok(estimate)
+}
+
+// The size of the array that each dynamic index of the path indexes, in
+// the order of the indices.
+pub(crate) fn dynamic_index_sizes(kind: &Kind, path: &Path) -> Result<Vec<usize>> {
+    let mut sizes = vec![];
+    for (ndx, element) in path.elements.iter().enumerate() {
+        if let PathElement::DynamicIndex(_) = element {
+            let prefix = Path {
+                elements: path.elements[..ndx].into(),
+            };
+            let Kind::Array(array) =
+                sub_kind(kind.clone(), &resolve_dynamic_indices(&prefix, &[]))?
+            else {
+                anyhow::bail!("Dynamic index on non-array type");
+            };
+            sizes.push(array.size);
+        }
+    }
+    Ok(sizes)
+}
+
+// Replace the dynamic indices of the path with the given values, in
+// order.  Indices without a value become 0.
+pub(crate) fn resolve_dynamic_indices(path: &Path, values: &[usize]) -> Path {
+    let mut values = values.iter();
+    Path {
+        elements: path
+            .elements
+            .iter()
+            .map(|element| match element {
+                PathElement::DynamicIndex(_) => {
+                    PathElement::Index(values.next().copied().unwrap_or_default())
+                }
+                _ => element.clone(),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates_differ_by_strategy() {
+        // A 64 entry table of 32 bit words
+        let part_select = MuxStrategy::PartSelect.estimate(64, 32);
+        let tree = MuxStrategy::Tree.estimate(64, 32);
+        let one_hot = MuxStrategy::OneHot.estimate(64, 32);
+        assert_eq!(tree.mux2, 63 * 32);
+        assert_eq!(tree.depth, 6);
+        assert_eq!(part_select.mux2, 6 * 64 * 32);
+        assert_eq!(part_select.depth, 6);
+        assert_eq!(one_hot.mux2, 0);
+        assert_eq!(one_hot.and2, 64 * 5 + 64 * 32);
+        assert_eq!(one_hot.or2, 63 * 32);
+        assert_eq!(one_hot.depth, 3 + 1 + 6);
+        // Nothing to choose between with a single element
+        for strategy in [
+            MuxStrategy::PartSelect,
+            MuxStrategy::Tree,
+            MuxStrategy::OneHot,
+        ] {
+            assert_eq!(strategy.estimate(1, 32), MuxEstimate::default());
+        }
+    }
+}
@@ -1,17 +1,20 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
 
+use crate::codegen::mux::{dynamic_index_sizes, resolve_dynamic_indices, MuxStrategy};
 use crate::kernel::ExternalKernelDef;
 use crate::path::{bit_range, Path, PathElement};
+use crate::rhif::object::Probe;
 use crate::rhif::spec::{
-    AluBinary, AluUnary, Array, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
+    AluBinary, AluUnary, Array, Assert, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
     ExternalFunctionCode, Index, Member, OpCode, Repeat, Select, Slot, Splice, Struct, Tuple,
     Unary,
 };
 use crate::test_module::VerilogDescriptor;
 use crate::util::binary_string;
-use crate::{ast::ast_impl::FunctionId, rhif::Object, Module, TypedBits};
+use crate::{ast::ast_impl::FunctionId, render_typed_bits, rhif::Object, Kind, Module, TypedBits};
 use anyhow::Result;
-use anyhow::{anyhow, ensure};
+use anyhow::{anyhow, bail, ensure};
 
 #[derive(Default, Clone, Debug)]
 pub struct VerilogModule {
@@ -25,13 +28,81 @@ impl VerilogModule {
     }
 }
 
+// The Verilog for a single kernel of a design.  `calls` lists the
+// kernels it calls (in the order they are first called), and `externs`
+// holds the bodies of the external functions it calls, which are not
+// kernels of the design and so have no id of their own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionVerilog {
+    pub fn_id: FunctionId,
+    pub name: String,
+    pub text: String,
+    pub calls: Vec<FunctionId>,
+    pub externs: Vec<String>,
+}
+
 struct TranslationContext<'a> {
     body: &'a mut String,
-    kernels: Vec<VerilogModule>,
+    calls: Vec<FunctionId>,
+    externs: Vec<String>,
+    memories: &'a HashMap<Slot, Memory>,
     design: &'a Module,
     obj: &'a Object,
 }
 
+// Array arguments with at least this many elements are copied into a
+// Verilog memory, so that a dynamic index into them is a memory read
+// rather than a computed part select of the whole array.
+const MEMORY_MIN_ELEMENTS: usize = 16;
+
+// The memory `m{n}` holding the elements of the array argument `r{n}`.
+// Element `i` is found at bits `base + i * stride` of the argument.
+struct Memory {
+    name: String,
+    element: Kind,
+    size: usize,
+    base: usize,
+    stride: usize,
+}
+
+fn argument_memory(obj: &Object, slot: &Slot) -> Result<Option<Memory>> {
+    let Some(Kind::Array(array)) = obj.kind.get(slot) else {
+        return Ok(None);
+    };
+    if array.size < MEMORY_MIN_ELEMENTS || array.base.bits() == 0 {
+        return Ok(None);
+    }
+    let kind = Kind::Array(array.clone());
+    let (first, element) = bit_range(kind.clone(), &Path::default().index(0))?;
+    let (second, _) = bit_range(kind, &Path::default().index(1))?;
+    Ok(Some(Memory {
+        name: format!("m{}", slot.reg()?),
+        element,
+        size: array.size,
+        base: first.start,
+        stride: second.start - first.start,
+    }))
+}
+
+// Values of zero width (like `()`, or a struct made only of empty
+// fields) have no representation in Verilog.  Operations that produce
+// them are dropped, and where a function signature needs a placeholder,
+// a single bit is used instead.
+fn is_zero_width(obj: &Object, slot: &Slot) -> bool {
+    match slot {
+        Slot::Empty => true,
+        Slot::Literal(_) => obj.literals.get(slot).is_some_and(|x| x.bits.is_empty()),
+        Slot::Register(_) => obj.kind.get(slot).is_some_and(|x| x.bits() == 0),
+    }
+}
+
+// The number of levels in a mux tree over the first of the indices.
+fn tree_levels(indices: &[(Slot, usize)]) -> usize {
+    indices.first().map_or(0, |(_, size)| {
+        size.next_power_of_two().trailing_zeros() as usize
+    })
+}
+
 fn compute_base_offset_path(path: &Path) -> Path {
     Path {
         elements: path
@@ -66,6 +137,21 @@ fn compute_stride_path_for_slot(path: &Path, slot: &Slot) -> Path {
 }
 
 impl<'a> TranslationContext<'a> {
+    fn is_zero_width(&self, slot: &Slot) -> bool {
+        is_zero_width(self.obj, slot)
+    }
+
+    fn is_signed(&self, slot: &Slot) -> bool {
+        match slot {
+            Slot::Literal(_) => self
+                .obj
+                .literals
+                .get(slot)
+                .is_some_and(|x| x.kind.is_signed()),
+            _ => self.obj.kind.get(slot).is_some_and(|x| x.is_signed()),
+        }
+    }
+
     fn compute_dynamic_index_expression(&self, target: &Slot, path: &Path) -> Result<String> {
         ensure!(path.any_dynamic());
         // Collect the list of dynamic index registers
@@ -136,8 +222,137 @@ impl<'a> TranslationContext<'a> {
         Ok(())
     }
 
+    fn slot_bits(&self, slot: &Slot) -> usize {
+        match slot {
+            Slot::Literal(_) => self.obj.literals.get(slot).map_or(0, |x| x.bits.len()),
+            _ => self.obj.kind.get(slot).map_or(0, |x| x.bits()),
+        }
+    }
+
+    // The bits of `arg` at the path, with its dynamic indices replaced
+    // by the given values, and their width.
+    fn element_select(&self, arg: &Slot, path: &Path, values: &[usize]) -> Result<(String, usize)> {
+        let arg_kind = self.obj.kind.get(arg).ok_or(anyhow!(
+            "No type for slot {} in function {}",
+            arg,
+            self.obj.name
+        ))?;
+        let (range, _) = bit_range(arg_kind.clone(), &resolve_dynamic_indices(path, values))?;
+        Ok((
+            format!("{arg}[{}:{}]", range.end - 1, range.start),
+            range.len(),
+        ))
+    }
+
+    // A balanced tree of muxes over the elements `lo..lo + 2^level` of
+    // the array indexed by the first of `indices`, selecting on the bits
+    // of the index from the top down.  The leaves are trees over the
+    // remaining indices.  Halves that are past the end of the array (or
+    // that the index is too narrow to reach) are left out.
+    fn mux_tree(
+        &self,
+        arg: &Slot,
+        path: &Path,
+        indices: &[(Slot, usize)],
+        values: &mut Vec<usize>,
+        lo: usize,
+        level: usize,
+    ) -> Result<String> {
+        let Some(((slot, size), rest)) = indices.split_first() else {
+            return Ok(self.element_select(arg, path, values)?.0);
+        };
+        if level == 0 {
+            values.push(lo);
+            let leaf = self.mux_tree(arg, path, rest, values, 0, tree_levels(rest))?;
+            values.pop();
+            return Ok(leaf);
+        }
+        let bit = level - 1;
+        let low = self.mux_tree(arg, path, indices, values, lo, bit)?;
+        let mid = lo + (1 << bit);
+        if mid >= *size || bit >= self.slot_bits(slot) {
+            return Ok(low);
+        }
+        let high = self.mux_tree(arg, path, indices, values, mid, bit)?;
+        Ok(format!("({slot}[{bit}] ? {high} : {low})"))
+    }
+
+    // The elements of the array ANDed with the decoded index, and ORed
+    // together.  Every combination of the dynamic indices gets a term.
+    fn one_hot_terms(
+        &self,
+        arg: &Slot,
+        path: &Path,
+        indices: &[(Slot, usize)],
+    ) -> Result<Vec<String>> {
+        let mut terms = vec![];
+        let mut values = vec![0; indices.len()];
+        loop {
+            let (element, width) = self.element_select(arg, path, &values)?;
+            let select = indices
+                .iter()
+                .zip(&values)
+                .map(|((slot, _), value)| format!("({slot} == {value})"))
+                .collect::<Vec<_>>()
+                .join(" & ");
+            terms.push(format!("({{{width}{{{select}}}}} & {element})"));
+            // Count through the combinations, with the last index fastest
+            let Some(ndx) = (0..indices.len())
+                .rev()
+                .find(|ndx| values[*ndx] + 1 < indices[*ndx].1)
+            else {
+                return Ok(terms);
+            };
+            values[ndx] += 1;
+            values[ndx + 1..].iter_mut().for_each(|value| *value = 0);
+        }
+    }
+
     fn translate_dynamic_index(&mut self, lhs: &Slot, arg: &Slot, path: &Path) -> Result<()> {
         ensure!(path.any_dynamic());
+        if self.obj.mux_strategy != MuxStrategy::PartSelect {
+            let arg_kind = self.obj.kind.get(arg).ok_or(anyhow!(
+                "No type for slot {} in function {}",
+                arg,
+                self.obj.name
+            ))?;
+            let indices = path
+                .dynamic_slots()
+                .copied()
+                .zip(dynamic_index_sizes(arg_kind, path)?)
+                .collect::<Vec<_>>();
+            let expression = if self.obj.mux_strategy == MuxStrategy::Tree {
+                let levels = tree_levels(&indices);
+                self.mux_tree(arg, path, &indices, &mut vec![], 0, levels)?
+            } else {
+                self.one_hot_terms(arg, path, &indices)?
+                    .join("\n        | ")
+            };
+            self.body.push_str(&format!("    {lhs} = {expression};\n"));
+            return Ok(());
+        }
+        // An array argument held in a memory is read from the memory when
+        // the index into the array is the only dynamic one.
+        if let (Some(memory), [PathElement::DynamicIndex(ndx), rest @ ..]) =
+            (self.memories.get(arg), &path.elements[..])
+        {
+            let rest = Path {
+                elements: rest.into(),
+            };
+            if !rest.any_dynamic() {
+                let (range, _) = bit_range(memory.element.clone(), &rest)?;
+                let select = if range.len() == memory.element.bits() {
+                    String::new()
+                } else {
+                    format!("[{}:{}]", range.end - 1, range.start)
+                };
+                self.body.push_str(&format!(
+                    "    {lhs} = {name}[{ndx}]{select};\n",
+                    name = memory.name
+                ));
+                return Ok(());
+            }
+        }
         let index_expression = self.compute_dynamic_index_expression(arg, path)?;
         self.body
             .push_str(&format!("    {lhs} = {arg}[{index_expression}];\n",));
@@ -193,6 +408,11 @@ impl<'a> TranslationContext<'a> {
     }
 
     fn translate_op(&mut self, op: &OpCode) -> Result<()> {
+        if let Some(lhs) = op_lhs(op) {
+            if self.is_zero_width(lhs) {
+                return Ok(());
+            }
+        }
         match op {
             OpCode::Noop => {}
             OpCode::Binary(Binary {
@@ -201,10 +421,34 @@ impl<'a> TranslationContext<'a> {
                 arg1,
                 arg2,
             }) => {
-                self.body.push_str(&format!(
-                    "    {lhs} = {arg1} {op} {arg2};\n",
-                    op = verilog_binop(op)
-                ));
+                // A right shift of a signed value must be arithmetic, and
+                // `>>>` is only arithmetic if its left operand is signed.
+                // Make that explicit, rather than relying on the declaration.
+                let ordering = matches!(
+                    op,
+                    AluBinary::Lt | AluBinary::Le | AluBinary::Gt | AluBinary::Ge
+                );
+                if matches!(op, AluBinary::Shr) && self.is_signed(arg1) {
+                    self.body
+                        .push_str(&format!("    {lhs} = $signed({arg1}) >>> {arg2};\n"));
+                } else if ordering && (self.is_signed(arg1) || self.is_signed(arg2)) {
+                    // Verilog compares as unsigned unless both operands are
+                    // signed, so mixing them would silently give an unsigned
+                    // comparison.  The type checker should have rejected it.
+                    ensure!(
+                        self.is_signed(arg1) && self.is_signed(arg2),
+                        "ICE comparison of {arg1} and {arg2} mixes signed and unsigned operands"
+                    );
+                    self.body.push_str(&format!(
+                        "    {lhs} = $signed({arg1}) {op} $signed({arg2});\n",
+                        op = verilog_binop(op)
+                    ));
+                } else {
+                    self.body.push_str(&format!(
+                        "    {lhs} = {arg1} {op} {arg2};\n",
+                        op = verilog_binop(op)
+                    ));
+                }
             }
             OpCode::Unary(Unary { op, lhs, arg1 }) => {
                 self.body.push_str(&format!(
@@ -218,11 +462,9 @@ impl<'a> TranslationContext<'a> {
                 true_value,
                 false_value,
             }) => {
-                if !lhs.is_empty() {
-                    self.body.push_str(&format!(
-                        "    {lhs} = {cond} ? {true_value} : {false_value};\n",
-                    ));
-                }
+                self.body.push_str(&format!(
+                    "    {lhs} = {cond} ? {true_value} : {false_value};\n",
+                ));
             }
             OpCode::Index(Index { lhs, arg, path }) => {
                 if path.any_dynamic() {
@@ -246,6 +488,14 @@ impl<'a> TranslationContext<'a> {
                     self.translate_splice(lhs, orig, path, subst)?;
                 }
             }
+            OpCode::Assert(Assert { cond, message }) => {
+                // Assertions are for simulation only, and are hidden from
+                // synthesis tools.
+                let message = message.replace('\\', "\\\\").replace('"', "\\\"");
+                self.body.push_str(&format!(
+                    "    // synthesis translate_off\n    if (!{cond}) $display(\"ASSERTION FAILED: {message}\");\n    // synthesis translate_on\n"
+                ));
+            }
             OpCode::Comment(s) => {
                 self.body.push_str(&format!(
                     "    // {}\n",
@@ -258,7 +508,7 @@ impl<'a> TranslationContext<'a> {
                     fields
                         .iter()
                         .rev()
-                        .filter(|x| !x.is_empty())
+                        .filter(|x| !self.is_zero_width(x))
                         .map(|x| x.to_string())
                         .collect::<Vec<_>>()
                         .join(", ")
@@ -296,6 +546,9 @@ impl<'a> TranslationContext<'a> {
                         Member::Named(name) => Path::default().field(name),
                     };
                     let (bit_range, _) = bit_range(kind.clone(), &path)?;
+                    if bit_range.is_empty() {
+                        continue;
+                    }
                     self.body.push_str(&format!(
                         "    {lhs}[{}:{}] = {};\n",
                         bit_range.end - 1,
@@ -322,6 +575,9 @@ impl<'a> TranslationContext<'a> {
                         Member::Named(name) => base_path.field(name),
                     };
                     let (bit_range, _) = bit_range(kind.clone(), &path)?;
+                    if bit_range.is_empty() {
+                        continue;
+                    }
                     self.body.push_str(&format!(
                         "    {lhs}[{}:{}] = {};\n",
                         bit_range.end - 1,
@@ -335,9 +591,6 @@ impl<'a> TranslationContext<'a> {
                 discriminant,
                 table,
             }) => {
-                if lhs.is_empty() {
-                    return Ok(());
-                }
                 self.body
                     .push_str(&format!("    case ({})\n", discriminant));
                 for (cond, slot) in table {
@@ -359,14 +612,22 @@ impl<'a> TranslationContext<'a> {
                 let func = &self.obj.externals[id.0];
                 let args = args
                     .iter()
-                    .map(|x| x.to_string())
+                    .map(|x| {
+                        if self.is_zero_width(x) {
+                            "1'b0".to_string()
+                        } else {
+                            x.to_string()
+                        }
+                    })
                     .collect::<Vec<_>>()
                     .join(", ");
                 match &func.code {
                     ExternalFunctionCode::Kernel(kernel) => {
-                        let func_name = self.design.func_name(kernel.inner().fn_id)?;
-                        let kernel = translate(self.design, kernel.inner().fn_id)?;
-                        self.kernels.push(kernel);
+                        let fn_id = kernel.inner().fn_id;
+                        let func_name = self.design.func_name(fn_id)?;
+                        if !self.calls.contains(&fn_id) {
+                            self.calls.push(fn_id);
+                        }
                         self.body
                             .push_str(&format!("    {lhs} = {func_name}({args});\n"));
                     }
@@ -377,9 +638,7 @@ impl<'a> TranslationContext<'a> {
                     }) => {
                         self.body
                             .push_str(&format!("    {lhs} = {name}({args});\n"));
-                        self.kernels.push(VerilogModule {
-                            functions: vec![body.clone()],
-                        });
+                        self.externs.push(body.clone());
                     }
                 }
             }
@@ -407,7 +666,39 @@ impl<'a> TranslationContext<'a> {
     }
 }
 
-fn translate(design: &Module, fn_id: FunctionId) -> Result<VerilogModule> {
+pub(crate) fn op_lhs(op: &OpCode) -> Option<&Slot> {
+    match op {
+        OpCode::Noop | OpCode::Comment(_) | OpCode::Assert(_) => None,
+        OpCode::Binary(Binary { lhs, .. })
+        | OpCode::Unary(Unary { lhs, .. })
+        | OpCode::Select(Select { lhs, .. })
+        | OpCode::Index(Index { lhs, .. })
+        | OpCode::Assign(Assign { lhs, .. })
+        | OpCode::Splice(Splice { lhs, .. })
+        | OpCode::Tuple(Tuple { lhs, .. })
+        | OpCode::Array(Array { lhs, .. })
+        | OpCode::Struct(Struct { lhs, .. })
+        | OpCode::Enum(Enum { lhs, .. })
+        | OpCode::Case(Case { lhs, .. })
+        | OpCode::Exec(Exec { lhs, .. })
+        | OpCode::Repeat(Repeat { lhs, .. })
+        | OpCode::AsBits(Cast { lhs, .. })
+        | OpCode::AsSigned(Cast { lhs, .. }) => Some(lhs),
+    }
+}
+
+fn translate(design: &Module, fn_id: FunctionId) -> Result<FunctionVerilog> {
+    translate_with_return(design, fn_id, false)
+}
+
+// Translate a kernel into a Verilog function.  If `probes` is set, the
+// function returns the probes of the kernel (with the first probe in the
+// LSBs) instead of its return value, and is named `<kernel>_probes`.
+fn translate_with_return(
+    design: &Module,
+    fn_id: FunctionId,
+    probes: bool,
+) -> Result<FunctionVerilog> {
     let obj = design
         .objects
         .get(&fn_id)
@@ -418,7 +709,7 @@ fn translate(design: &Module, fn_id: FunctionId) -> Result<VerilogModule> {
         .iter()
         .enumerate()
         .map(|(ndx, a)| {
-            if a.is_empty() {
+            if is_zero_width(obj, a) {
                 Ok(format!("__empty{}", ndx))
             } else {
                 decl(a, obj)
@@ -428,16 +719,33 @@ fn translate(design: &Module, fn_id: FunctionId) -> Result<VerilogModule> {
         .iter()
         .map(|x| format!("input {}", x))
         .collect::<Vec<_>>();
-    let ret_ty = obj.kind.get(&obj.return_slot).ok_or(anyhow!(
-        "No type for return slot {} in function {fn_id}",
-        obj.return_slot
-    ))?;
-    let ret_size = ret_ty.bits();
-    let ret_signed = if ret_ty.is_signed() { "signed" } else { "" };
-    if ret_size == 0 {
-        return Err(anyhow!("Function {fn_id} has no return value"));
-    }
-    let func_name = design.func_name(fn_id)?;
+    // A function returning a zero width value still needs a return
+    // value in Verilog, so it returns a single (ignored) bit.
+    let ret_empty = is_zero_width(obj, &obj.return_slot);
+    let ret_ty = if ret_empty {
+        Kind::Empty
+    } else {
+        obj.kind
+            .get(&obj.return_slot)
+            .ok_or(anyhow!(
+                "No type for return slot {} in function {fn_id}",
+                obj.return_slot
+            ))?
+            .clone()
+    };
+    let (ret_size, ret_signed) = if probes {
+        (probes_width(obj)?, "")
+    } else {
+        (
+            ret_ty.bits().max(1),
+            if ret_ty.is_signed() { "signed" } else { "" },
+        )
+    };
+    let func_name = if probes {
+        probes_func_name(design, fn_id)?
+    } else {
+        design.func_name(fn_id)?
+    };
     let mut func = format!(
         "\nfunction {ret_signed} [{}:0] {}({});\n",
         ret_size - 1,
@@ -450,9 +758,39 @@ fn translate(design: &Module, fn_id: FunctionId) -> Result<VerilogModule> {
         .keys()
         .filter(|x| !obj.arguments.contains(x))
         .filter(|x| x.is_reg())
+        .filter(|x| !is_zero_width(obj, x))
     {
         func.push_str(&format!("    {};\n", decl(reg, obj)?));
     }
+    if !obj.probes.is_empty() {
+        func.push_str("    // Probes\n");
+        for probe in &obj.probes {
+            func.push_str(&format!("    {};\n", probe_decl(probe, obj)?));
+        }
+    }
+    // Only the part select strategy reads arrays from memories
+    let mut memories = HashMap::new();
+    if obj.mux_strategy == MuxStrategy::PartSelect {
+        for arg in &obj.arguments {
+            if let Some(memory) = argument_memory(obj, arg)? {
+                memories.insert(*arg, memory);
+            }
+        }
+    }
+    if !memories.is_empty() {
+        func.push_str("    // Memories\n");
+        func.push_str("    integer m_ndx;\n");
+        for arg in &obj.arguments {
+            if let Some(memory) = memories.get(arg) {
+                func.push_str(&format!(
+                    "    reg [{}:0] {} [0:{}];\n",
+                    memory.element.bits() - 1,
+                    memory.name,
+                    memory.size - 1
+                ));
+            }
+        }
+    }
     func.push_str("    // Literals\n");
     // Allocate the literals
     for (&slot, lit) in obj.literals.iter() {
@@ -461,32 +799,253 @@ fn translate(design: &Module, fn_id: FunctionId) -> Result<VerilogModule> {
         }
         if let Slot::Literal(i) = slot {
             func.push_str(&format!(
-                "    localparam l{i} = {};\n",
-                as_verilog_literal(lit)
+                "    localparam l{i} = {};{}\n",
+                as_verilog_literal(lit),
+                literal_comment(lit)
             ));
         }
     }
     func.push_str("    // Body\n");
     func.push_str("begin\n");
-    let kernels = {
+    for arg in &obj.arguments {
+        if let Some(memory) = memories.get(arg) {
+            func.push_str(&format!(
+                "    for (m_ndx = 0; m_ndx < {size}; m_ndx = m_ndx + 1) {name}[m_ndx] = {arg}[{base} + m_ndx * {stride} +: {width}];\n",
+                size = memory.size,
+                name = memory.name,
+                base = memory.base,
+                stride = memory.stride,
+                width = memory.element.bits(),
+            ));
+        }
+    }
+    let (calls, externs) = {
         let mut context = TranslationContext {
-            kernels: Vec::new(),
             body: &mut func,
+            calls: Vec::new(),
+            externs: Vec::new(),
+            memories: &memories,
             design,
             obj,
         };
         context.translate_block(&obj.ops)?;
-        context.kernels
+        (context.calls, context.externs)
     };
-    func.push_str(&format!("    {} = {};\n", func_name, obj.return_slot));
+    for probe in &obj.probes {
+        func.push_str(&format!("    {} = {};\n", probe.name, probe.slot));
+    }
+    if probes {
+        func.push_str(&format!("    {} = {};\n", func_name, probes_concat(obj)));
+    } else if ret_empty {
+        func.push_str(&format!("    {} = 1'b0;\n", func_name));
+    } else {
+        func.push_str(&format!("    {} = {};\n", func_name, obj.return_slot));
+    }
     func.push_str("end\n");
     func.push_str("endfunction\n");
-    let mut module = VerilogModule::default();
-    for kernel in kernels {
-        module.functions.extend(kernel.functions);
+    Ok(FunctionVerilog {
+        fn_id,
+        name: func_name,
+        text: rename_bindings(func, obj),
+        calls,
+        externs,
+    })
+}
+
+// The reserved words of Verilog (IEEE 1364-2005, Annex B), which a
+// binding cannot be named
+const VERILOG_KEYWORDS: &[&str] = &[
+    "always",
+    "and",
+    "assign",
+    "automatic",
+    "begin",
+    "buf",
+    "bufif0",
+    "bufif1",
+    "case",
+    "casex",
+    "casez",
+    "cell",
+    "cmos",
+    "config",
+    "deassign",
+    "default",
+    "defparam",
+    "design",
+    "disable",
+    "edge",
+    "else",
+    "end",
+    "endcase",
+    "endconfig",
+    "endfunction",
+    "endgenerate",
+    "endmodule",
+    "endprimitive",
+    "endspecify",
+    "endtable",
+    "endtask",
+    "event",
+    "for",
+    "force",
+    "forever",
+    "fork",
+    "function",
+    "generate",
+    "genvar",
+    "highz0",
+    "highz1",
+    "if",
+    "ifnone",
+    "incdir",
+    "include",
+    "initial",
+    "inout",
+    "input",
+    "instance",
+    "integer",
+    "join",
+    "large",
+    "liblist",
+    "library",
+    "localparam",
+    "macromodule",
+    "medium",
+    "module",
+    "nand",
+    "negedge",
+    "nmos",
+    "nor",
+    "noshowcancelled",
+    "not",
+    "notif0",
+    "notif1",
+    "or",
+    "output",
+    "parameter",
+    "pmos",
+    "posedge",
+    "primitive",
+    "pull0",
+    "pull1",
+    "pulldown",
+    "pullup",
+    "pulsestyle_ondetect",
+    "pulsestyle_onevent",
+    "rcmos",
+    "real",
+    "realtime",
+    "reg",
+    "release",
+    "repeat",
+    "rnmos",
+    "rpmos",
+    "rtran",
+    "rtranif0",
+    "rtranif1",
+    "scalared",
+    "showcancelled",
+    "signed",
+    "small",
+    "specify",
+    "specparam",
+    "strong0",
+    "strong1",
+    "supply0",
+    "supply1",
+    "table",
+    "task",
+    "time",
+    "tran",
+    "tranif0",
+    "tranif1",
+    "tri",
+    "tri0",
+    "tri1",
+    "triand",
+    "trior",
+    "trireg",
+    "unsigned",
+    "use",
+    "uwire",
+    "vectored",
+    "wait",
+    "wand",
+    "weak0",
+    "weak1",
+    "while",
+    "wire",
+    "wor",
+    "xnor",
+    "xor",
+];
+
+// A Rust identifier can hold characters that a (simple) Verilog
+// identifier cannot, i.e., anything outside of ASCII.  Each of them is
+// replaced with an underscore.
+fn sanitize_binding(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// Name the registers of a function after the `let` bindings that they
+// hold (see `CompileOptions::preserve_names`).  A name that is a Verilog
+// keyword, or that is already used in the function (e.g., by a probe or
+// an earlier binding of the same name), is given a numeric suffix.
+fn rename_bindings(func: String, obj: &Object) -> String {
+    if obj.symbols.slot_names.is_empty() {
+        return func;
     }
-    module.functions.push(func);
-    Ok(module)
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    // The identifiers in the code, leaving out the comments (which quote
+    // the kernel, and so its bindings)
+    let mut used = func
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .map(|line| match (line.find("/*"), line.find("*/")) {
+            (Some(start), Some(end)) if start < end => {
+                format!("{} {}", &line[..start], &line[end + 2..])
+            }
+            _ => line.to_string(),
+        })
+        .flat_map(|line| {
+            line.split(|c| !is_ident(c))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .collect::<BTreeSet<_>>();
+    let mut renames = HashMap::new();
+    for (slot, name) in &obj.symbols.slot_names {
+        if obj.arguments.contains(slot) || !used.contains(&slot.to_string()) {
+            continue;
+        }
+        let name = sanitize_binding(name);
+        let mut candidate = name.clone();
+        let mut suffix = 0;
+        while used.contains(&candidate) || VERILOG_KEYWORDS.contains(&candidate.as_str()) {
+            suffix += 1;
+            candidate = format!("{name}_{suffix}");
+        }
+        used.insert(candidate.clone());
+        renames.insert(slot.to_string(), candidate);
+    }
+    // Replace whole identifiers only, so that `r1` does not match `r12`
+    let mut result = String::with_capacity(func.len());
+    let mut token = String::new();
+    for c in func.chars().chain(std::iter::once('\0')) {
+        if is_ident(c) {
+            token.push(c);
+            continue;
+        }
+        result.push_str(renames.get(&token).unwrap_or(&token));
+        token.clear();
+        if c != '\0' {
+            result.push(c);
+        }
+    }
+    result
 }
 
 pub fn as_verilog_literal(tb: &TypedBits) -> String {
@@ -495,6 +1054,19 @@ pub fn as_verilog_literal(tb: &TypedBits) -> String {
     format!("{}'{}b{}", width, signed, binary_string(&tb.bits))
 }
 
+// A comment showing the value of a literal, for the kinds whose bits are
+// hard to read.  Long values (e.g., big structs) are left out.
+fn literal_comment(tb: &TypedBits) -> String {
+    if matches!(tb.kind, Kind::Bits(_) | Kind::Empty) {
+        return String::new();
+    }
+    let text = render_typed_bits(&tb.kind, &tb.bits);
+    if text.len() > 60 {
+        return String::new();
+    }
+    format!(" // {text}")
+}
+
 fn decl(slot: &Slot, obj: &Object) -> Result<String> {
     let ty = obj
         .kind
@@ -505,16 +1077,341 @@ fn decl(slot: &Slot, obj: &Object) -> Result<String> {
     Ok(format!("reg {} [{}:0] r{}", signed, width - 1, slot.reg()?))
 }
 
+fn probe_decl(probe: &Probe, obj: &Object) -> Result<String> {
+    let ty = obj
+        .kind
+        .get(&probe.slot)
+        .ok_or(anyhow!("No type for probe {}", probe.name))?;
+    let signed = if ty.is_signed() { "signed" } else { "" };
+    Ok(format!(
+        "{}reg {} [{}:0] {}",
+        keep_attribute(probe),
+        signed,
+        ty.bits() - 1,
+        probe.name
+    ))
+}
+
+// A Verilog attribute, like `(* keep *)` or `(* ASYNC_REG = "TRUE" *)`,
+// to put in front of a declaration.  Synthesis tools use these for hints
+// that do not change the logic.
+pub fn verilog_attribute(name: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("(* {name} = \"{value}\" *) "),
+        None => format!("(* {name} *) "),
+    }
+}
+
+// How registers are assigned in a clocked `always` block.  Non-blocking
+// assignments all read the values from before the clock edge, which is
+// what the hardware does.  Blocking assignments take effect in order, so
+// a register that is read after it is written sees the new value, and
+// simulation no longer matches synthesis.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AssignmentStyle {
+    #[default]
+    NonBlocking,
+    Blocking,
+}
+
+impl AssignmentStyle {
+    pub fn operator(self) -> &'static str {
+        match self {
+            AssignmentStyle::NonBlocking => "<=",
+            AssignmentStyle::Blocking => "=",
+        }
+    }
+}
+
+// An `always` block that assigns each `(lhs, rhs)` pair on the rising edge
+// of `clock`.  Combinational logic belongs in continuous `assign`s outside
+// of it.
+pub fn clocked_always_block(
+    clock: &str,
+    assignments: &[(String, String)],
+    style: AssignmentStyle,
+) -> String {
+    let op = style.operator();
+    let body = assignments
+        .iter()
+        .map(|(lhs, rhs)| format!("        {lhs} {op} {rhs};\n"))
+        .collect::<String>();
+    format!("    always @(posedge {clock}) begin\n{body}    end\n")
+}
+
+// The Yosys attribute that stops a kept probe from being optimized away
+pub(crate) fn keep_attribute(probe: &Probe) -> String {
+    if probe.keep {
+        verilog_attribute("keep", None)
+    } else {
+        String::new()
+    }
+}
+
+fn probes_width(obj: &Object) -> Result<usize> {
+    obj.probes
+        .iter()
+        .map(|probe| {
+            obj.kind
+                .get(&probe.slot)
+                .map(Kind::bits)
+                .ok_or(anyhow!("No type for probe {}", probe.name))
+        })
+        .sum()
+}
+
+// The probes of a kernel concatenated together, with the first probe in
+// the LSBs.
+pub(crate) fn probes_concat(obj: &Object) -> String {
+    let names = obj
+        .probes
+        .iter()
+        .rev()
+        .map(|probe| probe.name.as_str())
+        .collect::<Vec<_>>();
+    format!("{{{}}}", names.join(", "))
+}
+
+fn probes_func_name(design: &Module, fn_id: FunctionId) -> Result<String> {
+    Ok(format!("{}_probes", design.func_name(fn_id)?))
+}
+
+// Every kernel and extern function in the design is emitted as a top
+// level Verilog function.  Two distinct functions that end up with the
+// same name would produce Verilog that fails to elaborate, so catch that
+// before generating any code.
+fn check_identifier_collisions(design: &Module) -> Result<()> {
+    #[derive(PartialEq)]
+    enum Source {
+        Kernel(FunctionId),
+        Extern(String),
+    }
+    let mut identifiers: HashMap<String, (Source, String)> = HashMap::new();
+    let mut claim = |name: String, source: Source, who: String| -> Result<()> {
+        if let Some((prev_source, prev_who)) = identifiers.get(&name) {
+            if *prev_source != source {
+                bail!(
+                    "Verilog identifier collision: `{name}` is defined by both {prev_who} and {who}"
+                );
+            }
+        }
+        identifiers.insert(name, (source, who));
+        Ok(())
+    };
+    for (fn_id, obj) in &design.objects {
+        claim(
+            design.func_name(*fn_id)?,
+            Source::Kernel(*fn_id),
+            format!("kernel `{}`", obj.name),
+        )?;
+        for func in &obj.externals {
+            if let ExternalFunctionCode::Extern(ExternalKernelDef { name, body, .. }) = &func.code {
+                claim(
+                    name.clone(),
+                    Source::Extern(body.clone()),
+                    format!("external function `{}`", func.path),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The Verilog function generated for each kernel of the design, keyed
+// by the function it was generated from.
+pub(crate) fn generate_verilog_functions(design: &Module) -> Result<HashMap<FunctionId, String>> {
+    design
+        .objects
+        .keys()
+        .map(|fn_id| Ok((*fn_id, translate(design, *fn_id)?.text)))
+        .collect()
+}
+
+// The Verilog for each kernel reachable from the top of the design, with
+// every kernel after the kernels it calls.
+pub fn generate_verilog_split(design: &Module) -> Result<Vec<FunctionVerilog>> {
+    fn visit(
+        design: &Module,
+        fn_id: FunctionId,
+        visited: &mut BTreeSet<FunctionId>,
+        functions: &mut Vec<FunctionVerilog>,
+    ) -> Result<()> {
+        if !visited.insert(fn_id) {
+            return Ok(());
+        }
+        let func = translate(design, fn_id)?;
+        for callee in &func.calls {
+            visit(design, *callee, visited, functions)?;
+        }
+        functions.push(func);
+        Ok(())
+    }
+    check_identifier_collisions(design)?;
+    let mut functions = vec![];
+    visit(design, design.top, &mut BTreeSet::new(), &mut functions)?;
+    Ok(functions)
+}
+
+// A function computing the probes of the top kernel of the design, so
+// that they can be brought out as named wires of a module.  There is no
+// such function if the top kernel has no probes.  The kernels it calls
+// are the ones returned by `generate_verilog_split`.
+pub fn generate_verilog_probes(design: &Module) -> Result<Option<FunctionVerilog>> {
+    let obj = design
+        .objects
+        .get(&design.top)
+        .ok_or(anyhow!("Top function {} not found", design.top))?;
+    if obj.probes.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(translate_with_return(design, design.top, true)?))
+}
+
+// Join split functions (and the external functions they call) into a
+// single body, as `generate_verilog` does.  Identical functions are only
+// emitted once, so functions from several designs can be combined.
+pub fn concat_verilog_functions(functions: &[FunctionVerilog]) -> Result<String> {
+    let module = VerilogModule {
+        functions: functions
+            .iter()
+            .flat_map(|func| func.externs.iter().chain(std::iter::once(&func.text)))
+            .cloned()
+            .collect(),
+    };
+    Ok(module.deduplicate()?.functions.join("\n"))
+}
+
 pub fn generate_verilog(design: &Module) -> Result<VerilogDescriptor> {
-    let module = translate(design, design.top)?;
-    let module = module.deduplicate()?;
-    let body = module.functions.join("\n");
+    let functions = generate_verilog_split(design)?;
     Ok(VerilogDescriptor {
         name: design.func_name(design.top)?,
-        body,
+        body: concat_verilog_functions(&functions)?,
     })
 }
 
+// How the return value of the top kernel leaves the module made by
+// `generate_verilog_module`.  `Packed` brings it out as the single port
+// `o`.  `Split` gives each element of a returned tuple (or field of a
+// returned struct) a port of its own, named `o_<element>`, so that it
+// can be wired to modules that expect separate ports.  Any other return
+// value is brought out as `o` either way.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputPorts {
+    #[default]
+    Packed,
+    Split,
+}
+
+// The ports that carry a return value of the given kind, as the name of
+// each port and the bits of the return value it carries.  Elements with
+// no bits get no port.
+fn output_ports(kind: &Kind, ports: OutputPorts) -> Result<Vec<(String, Range<usize>)>> {
+    let paths = match (ports, kind) {
+        (OutputPorts::Split, Kind::Tuple(tuple)) => (0..tuple.elements.len())
+            .map(|ndx| (format!("o_{ndx}"), Path::default().index(ndx)))
+            .collect(),
+        (OutputPorts::Split, Kind::Struct(strukt)) => strukt
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    format!("o_{}", field.name),
+                    Path::default().field(&field.name),
+                )
+            })
+            .collect(),
+        _ => vec![("o".to_string(), Path::default())],
+    };
+    let mut ports = vec![];
+    for (name, path) in paths {
+        let (range, _) = bit_range(kind.clone(), &path)?;
+        if !range.is_empty() {
+            ports.push((name, range));
+        }
+    }
+    Ok(ports)
+}
+
+// A module `<kernel>_module` that computes the top kernel of the design.
+// Each argument with any bits has an input port `a<n>`, and the return
+// value is brought out as set by `ports`.  The functions of the design
+// (as returned by `generate_verilog`) are declared inside the module.
+// The parameters of a design compiled with `compile_design_with_params`
+// are declared as local parameters, with the values they were compiled
+// for.  They cannot be overridden where the module is instantiated,
+// since the body was specialized to those values.
+pub fn generate_verilog_module(design: &Module, ports: OutputPorts) -> Result<VerilogDescriptor> {
+    let functions = generate_verilog_split(design)?;
+    let obj = design
+        .objects
+        .get(&design.top)
+        .ok_or(anyhow!("Top function {} not found", design.top))?;
+    let func_name = design.func_name(design.top)?;
+    let name = format!("{func_name}_module");
+    let mut decls = vec![];
+    let mut args = vec![];
+    for (ndx, arg) in obj.arguments.iter().enumerate() {
+        // Zero width arguments are passed as a placeholder bit
+        if is_zero_width(obj, arg) {
+            args.push("1'b0".to_string());
+        } else {
+            let width = obj.kind[arg].bits();
+            decls.push(format!("input wire[{}:0] a{ndx}", width - 1));
+            args.push(format!("a{ndx}"));
+        }
+    }
+    let ret_kind = if is_zero_width(obj, &obj.return_slot) {
+        Kind::Empty
+    } else {
+        obj.kind
+            .get(&obj.return_slot)
+            .ok_or(anyhow!("No type for return slot {}", obj.return_slot))?
+            .clone()
+    };
+    let mut assigns = vec![];
+    let outputs = output_ports(&ret_kind, ports)?;
+    if !outputs.is_empty() {
+        assigns.push(format!("   wire[{}:0] ret;", ret_kind.bits() - 1));
+        assigns.push(format!("   assign ret = {func_name}({});", args.join(", ")));
+    }
+    for (port, range) in outputs {
+        decls.push(format!("output wire[{}:0] {port}", range.len() - 1));
+        assigns.push(format!(
+            "   assign {port} = ret[{}:{}];",
+            range.end - 1,
+            range.start
+        ));
+    }
+    // The body was specialized to the values of the parameters when it
+    // was compiled, so they are only declared to record those values.
+    let params = obj
+        .params
+        .iter()
+        .filter(|(_, value)| !value.bits.is_empty())
+        .map(|(param, value)| {
+            format!(
+                "   localparam [{}:0] {param} = {};\n",
+                value.bits.len() - 1,
+                as_verilog_literal(value)
+            )
+        })
+        .collect::<String>();
+    let body = format!(
+        "
+module {name}({});
+{params}{}
+
+{}
+
+endmodule
+",
+        decls.join(", "),
+        assigns.join("\n"),
+        concat_verilog_functions(&functions)?,
+    );
+    Ok(VerilogDescriptor { name, body })
+}
+
 fn verilog_binop(op: &AluBinary) -> &'static str {
     match op {
         AluBinary::Add => "+",
@@ -537,7 +1434,8 @@ fn verilog_binop(op: &AluBinary) -> &'static str {
 fn verilog_unop(op: &AluUnary) -> &'static str {
     match op {
         AluUnary::Neg => "-",
-        AluUnary::Not => "!",
+        // `!` in Verilog is a logical not, which reduces to a single bit
+        AluUnary::Not => "~",
         AluUnary::All => "&",
         AluUnary::Any => "|",
         AluUnary::Xor => "^",
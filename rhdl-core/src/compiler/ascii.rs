@@ -122,11 +122,21 @@ impl<'a> AsciiRenderer<'a> {
             ArmKind::Constant(constant) => {
                 self.push(&format!("{}", constant.value));
             }
+            ArmKind::Range(range) => {
+                self.push(&format!("{}", range));
+            }
+            ArmKind::Or(or) => {
+                self.push(&format!("{}", or));
+            }
             ArmKind::Enum(enum_arm) => {
                 self.render_pat(&enum_arm.pat)?;
                 self.push(&format!("#{}", &enum_arm.template));
             }
         }
+        if let Some(guard) = &arm.guard {
+            self.push(" if ");
+            self.render_expr(guard)?;
+        }
         self.push(" => ");
         self.render_expr(&arm.body)?;
         self.indent -= 1;
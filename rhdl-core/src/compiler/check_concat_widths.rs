@@ -0,0 +1,168 @@
+// Check that the operands of each aggregate (concatenating) opcode add up
+// to the width of its result.  The type checker works on kinds, so a
+// mis-sized field would otherwise only show up when the generated Verilog
+// is elaborated.
+
+use crate::{
+    path::{sub_kind, Path},
+    rhif::{
+        spec::{Array, Enum, FieldValue, Member, OpCode, Repeat, Slot, Struct, Tuple},
+        Object,
+    },
+    Kind,
+};
+use anyhow::{anyhow, bail, Result};
+
+use super::pass::Pass;
+
+pub struct CheckConcatWidthsPass;
+
+impl Pass for CheckConcatWidthsPass {
+    fn name(&self) -> &'static str {
+        "check_concat_widths"
+    }
+    fn description(&self) -> &'static str {
+        "Check that the operands of aggregate opcodes fill their result exactly"
+    }
+    fn run(input: Object) -> Result<Object> {
+        check_concat_widths(&input)?;
+        Ok(input)
+    }
+}
+
+fn check_concat_widths(obj: &Object) -> Result<()> {
+    let kind = |slot: &Slot| -> Result<Kind> {
+        if matches!(slot, Slot::Empty) {
+            return Ok(Kind::Empty);
+        }
+        obj.kind
+            .get(slot)
+            .cloned()
+            .ok_or(anyhow!("slot {slot} not found"))
+    };
+    let width = |slot: &Slot| kind(slot).map(|x| x.bits());
+    let sum = |slots: &[Slot]| -> Result<usize> { slots.iter().map(width).sum() };
+    // The width of each field of a struct or enum payload must match
+    // the width of the member it is written to.
+    let fields_fit = |base: &Kind, fields: &[FieldValue]| -> Result<Option<(usize, usize)>> {
+        for field in fields {
+            let path = match &field.member {
+                Member::Named(name) => Path::default().field(name),
+                Member::Unnamed(ndx) => Path::default().index(*ndx as usize),
+            };
+            let expected = sub_kind(base.clone(), &path)?.bits();
+            let actual = width(&field.value)?;
+            if expected != actual {
+                return Ok(Some((actual, expected)));
+            }
+        }
+        Ok(None)
+    };
+    for (ndx, op) in obj.ops.iter().enumerate() {
+        let mismatch = match op {
+            OpCode::Tuple(Tuple { lhs, fields }) => Some((sum(fields)?, width(lhs)?)),
+            OpCode::Array(Array { lhs, elements }) => Some((sum(elements)?, width(lhs)?)),
+            OpCode::Repeat(Repeat { lhs, value, len }) => Some((width(value)? * len, width(lhs)?)),
+            OpCode::Struct(Struct {
+                lhs,
+                fields,
+                rest,
+                template,
+            }) => {
+                let lhs_kind = kind(lhs)?;
+                match rest {
+                    Some(rest) if width(rest)? != lhs_kind.bits() => {
+                        Some((width(rest)?, lhs_kind.bits()))
+                    }
+                    _ if template.bits.len() != lhs_kind.bits() => {
+                        Some((template.bits.len(), lhs_kind.bits()))
+                    }
+                    _ => fields_fit(&lhs_kind, fields)?,
+                }
+            }
+            OpCode::Enum(Enum {
+                lhs,
+                fields,
+                template,
+            }) => {
+                let lhs_kind = kind(lhs)?;
+                if template.bits.len() != lhs_kind.bits() {
+                    Some((template.bits.len(), lhs_kind.bits()))
+                } else {
+                    let discriminant = template.discriminant()?.as_i64()?;
+                    fields_fit(&lhs_kind.lookup_variant(discriminant)?, fields)?
+                }
+            }
+            _ => None,
+        };
+        if let Some((actual, expected)) = mismatch.filter(|(a, e)| a != e) {
+            bail!(
+                "Width mismatch in `{op}`: the operands are {actual} bits wide, but {expected} bits are expected{}",
+                describe_location(obj, ndx)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn describe_location(obj: &Object, ndx: usize) -> String {
+    let source = &obj.symbols.source;
+    obj.symbols
+        .opcode_map
+        .get(ndx)
+        .and_then(|location| source.span_map.get(&location.node))
+        .map(|span| {
+            format!(
+                "\n  at `{}` ({} {}..{})",
+                &source.source[span.clone()],
+                source.name,
+                span.start,
+                span.end
+            )
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::ast_impl::NodeId, rhif::object::ObjectBuilder};
+
+    // Packs a b4 and a b3 into a (b4, b4), which is one bit too wide.
+    fn mis_sized_pack() -> Object {
+        let source = "fn pack(a: b4, b: b3) -> (b4, b4) { (a, b) }";
+        let span_start = source.find("(a, b)").unwrap();
+        ObjectBuilder::new("pack", source)
+            .registers([
+                Kind::make_bits(4),
+                Kind::make_bits(3),
+                Kind::make_tuple(vec![Kind::make_bits(4), Kind::make_bits(4)]),
+            ])
+            .arguments([Slot::Register(0), Slot::Register(1)])
+            .op_with_span(
+                OpCode::Tuple(Tuple {
+                    lhs: Slot::Register(2),
+                    fields: vec![Slot::Register(0), Slot::Register(1)],
+                }),
+                NodeId::new(1),
+                span_start..span_start + 6,
+            )
+            .returns(Slot::Register(2))
+            .build()
+    }
+
+    #[test]
+    fn test_mis_sized_concat_is_rejected() {
+        let mut obj = mis_sized_pack();
+        let err = CheckConcatWidthsPass::run(obj.clone()).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("operands are 7 bits wide, but 8 bits are expected"));
+        assert!(msg.contains("at `(a, b)` (pack 36..42)"));
+        // Sizing the result correctly fixes it
+        obj.kind.insert(
+            Slot::Register(2),
+            Kind::make_tuple(vec![Kind::make_bits(4), Kind::make_bits(3)]),
+        );
+        assert!(CheckConcatWidthsPass::run(obj).is_ok());
+    }
+}
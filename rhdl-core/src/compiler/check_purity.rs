@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use crate::{
+    ast::ast_impl::FunctionId, kernel::ExternalKernelDef, rhif::spec::ExternalFunctionCode, Module,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+// Verilog system functions that only compute a value.  Any other
+// system task (like `$display` or `$finish`) is a side effect.
+const PURE_SYSTEM_FUNCTIONS: &[&str] = &["signed", "unsigned", "clog2", "bits"];
+
+fn system_task(body: &str) -> Option<&str> {
+    body.split('$').skip(1).find_map(|tail| {
+        let end = tail
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(tail.len());
+        let name = &tail[..end];
+        (!name.is_empty() && !PURE_SYSTEM_FUNCTIONS.contains(&name)).then_some(name)
+    })
+}
+
+// Find a reason why the given function is not free of side effects,
+// by looking through everything it calls.
+fn side_effect(
+    design: &Module,
+    fn_id: FunctionId,
+    visited: &mut HashSet<FunctionId>,
+) -> Result<Option<String>> {
+    if !visited.insert(fn_id) {
+        return Ok(None);
+    }
+    let obj = design
+        .objects
+        .get(&fn_id)
+        .ok_or(anyhow!("Function {fn_id} not found"))?;
+    for func in &obj.externals {
+        match &func.code {
+            ExternalFunctionCode::Extern(ExternalKernelDef { name, body, .. }) => {
+                if let Some(task) = system_task(body) {
+                    return Ok(Some(format!("`{name}` uses the system task `${task}`")));
+                }
+            }
+            ExternalFunctionCode::Kernel(kernel) => {
+                if let Some(reason) = side_effect(design, kernel.inner().fn_id, visited)? {
+                    return Ok(Some(reason));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Kernels marked with `#[kernel(pure)]` promise that their result
+// depends only on their arguments, which lets the interpreter cache
+// their results.  Check that promise against everything the kernel
+// calls, since an external function could print or stop a simulation.
+// Kernels marked with `#[kernel(const_eval)]` are evaluated when their
+// callers are compiled, so they make the same promise.
+pub(crate) fn check_purity(design: &Module) -> Result<()> {
+    for obj in design
+        .objects
+        .values()
+        .filter(|obj| obj.pure || obj.const_eval)
+    {
+        if let Some(reason) = side_effect(design, obj.fn_id, &mut HashSet::new())? {
+            let flag = if obj.pure { "pure" } else { "const_eval" };
+            bail!(
+                "Kernel `{}` is marked {flag}, but it has side effects: {reason}",
+                obj.name
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_task_detection() {
+        assert_eq!(
+            system_task("function f(input a); f = $signed(a); endfunction"),
+            None
+        );
+        assert_eq!(
+            system_task("function f(input a); $display(\"%d\", a); f = a; endfunction"),
+            Some("display")
+        );
+        assert_eq!(system_task("function f(input a); f = a; endfunction"), None);
+    }
+}
@@ -2,8 +2,8 @@ use std::collections::HashSet;
 
 use crate::{
     rhif::spec::{
-        Array, Assign, Binary, Case, Cast, Enum, Exec, Index, OpCode, Repeat, Select, Slot, Splice,
-        Struct, Tuple, Unary,
+        Array, Assert, Assign, Binary, Case, Cast, Enum, Exec, Index, OpCode, Repeat, Select, Slot,
+        Splice, Struct, Tuple, Unary,
     },
     rhif::Object,
 };
@@ -167,6 +167,9 @@ fn check_flow(obj: &Object, mut init_set: InitSet) -> Result<InitSet> {
                 init_set.write(lhs)?;
             }
             OpCode::Comment(_) => {}
+            OpCode::Assert(Assert { cond, .. }) => {
+                init_set.read(cond)?;
+            }
             OpCode::Case(Case {
                 lhs,
                 discriminant,
@@ -7,8 +7,8 @@ use crate::{
     rhif::{
         self,
         spec::{
-            AluBinary, AluUnary, Array, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
-            Index, OpCode, Repeat, Select, Slot, Splice, Struct, Tuple, Unary,
+            AluBinary, AluUnary, Array, Assert, Assign, Binary, Case, CaseArgument, Cast, Enum,
+            Exec, Index, OpCode, Repeat, Select, Slot, Splice, Struct, Tuple, Unary,
         },
         Object,
     },
@@ -252,6 +252,9 @@ fn check_type_correctness(obj: &Object) -> Result<()> {
                 eq_kinds(ty.clone(), Kind::make_array(*array_ty.base.clone(), *len))?;
             }
             OpCode::Comment(_) => {}
+            OpCode::Assert(Assert { cond, .. }) => {
+                eq_kinds(slot_type(cond)?, Kind::make_bool())?;
+            }
             OpCode::Case(Case {
                 lhs,
                 discriminant: expr,
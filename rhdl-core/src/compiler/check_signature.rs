@@ -0,0 +1,101 @@
+// Check that the kinds of the arguments and return value of a compiled
+// kernel match the ones declared in its signature.  Type inference starts
+// from the declared kinds, so a mismatch means that the macro or the
+// compiler lost track of them somewhere.
+use crate::{
+    rhif::{spec::Slot, Object},
+    DigitalSignature, Kind,
+};
+use anyhow::{anyhow, ensure, Result};
+
+fn slot_kind(obj: &Object, slot: Slot) -> Result<Kind> {
+    match obj.kind.get(&slot) {
+        Some(kind) => Ok(kind.clone()),
+        // A zero width literal may only be kept in the literal table
+        None if obj.literals.contains_key(&slot) => Ok(obj.literals[&slot].kind.clone()),
+        None if slot.is_empty() => Ok(Kind::Empty),
+        None => Err(anyhow!(
+            "ICE - slot {slot:?} of kernel {} has no kind",
+            obj.name
+        )),
+    }
+}
+
+pub(crate) fn check_signature(obj: &Object, signature: &DigitalSignature) -> Result<()> {
+    ensure!(
+        obj.arguments.len() == signature.arguments.len(),
+        "ICE - kernel {} is declared with {} arguments, but compiled with {}",
+        obj.name,
+        signature.arguments.len(),
+        obj.arguments.len()
+    );
+    for (ndx, (slot, declared)) in obj.arguments.iter().zip(&signature.arguments).enumerate() {
+        let compiled = slot_kind(obj, *slot)?;
+        ensure!(
+            compiled == *declared,
+            "ICE - argument {ndx} of kernel {} is declared as {declared}, but compiled as {compiled}",
+            obj.name
+        );
+    }
+    let compiled = slot_kind(obj, obj.return_slot)?;
+    ensure!(
+        compiled == signature.ret,
+        "ICE - kernel {} is declared to return {}, but compiled to return {compiled}",
+        obj.name,
+        signature.ret
+    );
+    Ok(())
+}
+
+// The signature that the kernel was compiled to
+pub(crate) fn compiled_signature(obj: &Object) -> Result<DigitalSignature> {
+    Ok(DigitalSignature {
+        arguments: obj
+            .arguments
+            .iter()
+            .map(|slot| slot_kind(obj, *slot))
+            .collect::<Result<_>>()?,
+        ret: slot_kind(obj, obj.return_slot)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rhif::{
+        object::ObjectBuilder,
+        spec::{AluBinary, Binary, OpCode},
+    };
+
+    // Adds two b4s, with the return slot corrupted to a b5.
+    fn corrupted_add() -> Object {
+        ObjectBuilder::new("add", "fn add(a: b4, b: b4) -> b4 { a + b }")
+            .registers([Kind::make_bits(4), Kind::make_bits(4), Kind::make_bits(5)])
+            .arguments([Slot::Register(0), Slot::Register(1)])
+            .op(OpCode::Binary(Binary {
+                op: AluBinary::Add,
+                lhs: Slot::Register(2),
+                arg1: Slot::Register(0),
+                arg2: Slot::Register(1),
+            }))
+            .returns(Slot::Register(2))
+            .build()
+    }
+
+    #[test]
+    fn test_corrupted_object_fails_signature_check() {
+        let signature = DigitalSignature {
+            arguments: vec![Kind::make_bits(4), Kind::make_bits(4)],
+            ret: Kind::make_bits(4),
+        };
+        let mut obj = corrupted_add();
+        let err = check_signature(&obj, &signature).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("kernel add is declared to return b4, but compiled to return b5"));
+        obj.kind.insert(Slot::Register(2), Kind::make_bits(4));
+        assert!(check_signature(&obj, &signature).is_ok());
+        obj.arguments.pop();
+        assert!(check_signature(&obj, &signature).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::rhif::{spec::Slot, Object};
+use anyhow::Result;
+
+use super::{pass::Pass, utils::remap_slots};
+
+#[derive(Default, Debug, Clone)]
+pub struct CompactSlotsPass {}
+
+impl Pass for CompactSlotsPass {
+    fn name(&self) -> &'static str {
+        "compact_slots"
+    }
+    fn description(&self) -> &'static str {
+        "Renumber the registers and literals so that they are dense (0..n), and keep their relative order"
+    }
+    fn run(mut input: Object) -> Result<Object> {
+        let mut registers: BTreeSet<usize> = Default::default();
+        let mut literals: BTreeSet<usize> = Default::default();
+        let mut collect = |slot: Slot| {
+            match slot {
+                Slot::Register(ndx) => {
+                    registers.insert(ndx);
+                }
+                Slot::Literal(ndx) => {
+                    literals.insert(ndx);
+                }
+                Slot::Empty => {}
+            }
+            slot
+        };
+        // The kind map can still hold entries for literals that have
+        // been removed, so only the registers are taken from it.
+        input
+            .kind
+            .keys()
+            .copied()
+            .filter(|slot| slot.is_reg())
+            .for_each(|slot| {
+                collect(slot);
+            });
+        input.literals.keys().copied().for_each(|slot| {
+            collect(slot);
+        });
+        input.arguments.iter().copied().for_each(|slot| {
+            collect(slot);
+        });
+        collect(input.return_slot);
+        input.probes.iter().for_each(|probe| {
+            collect(probe.slot);
+        });
+        for op in &input.ops {
+            remap_slots(op.clone(), &mut collect);
+        }
+        let dense = |used: BTreeSet<usize>| -> BTreeMap<usize, usize> {
+            used.into_iter()
+                .enumerate()
+                .map(|(new, old)| (old, new))
+                .collect()
+        };
+        let registers = dense(registers);
+        let literals = dense(literals);
+        let in_use = |slot: &Slot| match slot {
+            Slot::Register(ndx) => registers.contains_key(ndx),
+            Slot::Literal(ndx) => literals.contains_key(ndx),
+            Slot::Empty => true,
+        };
+        let remap = |slot: Slot| match slot {
+            Slot::Register(ndx) => Slot::Register(registers[&ndx]),
+            Slot::Literal(ndx) => Slot::Literal(literals[&ndx]),
+            Slot::Empty => Slot::Empty,
+        };
+        input.ops = input
+            .ops
+            .into_iter()
+            .map(|op| remap_slots(op, remap))
+            .collect();
+        input.kind = input
+            .kind
+            .into_iter()
+            .filter(|(slot, _)| in_use(slot))
+            .map(|(slot, kind)| (remap(slot), kind))
+            .collect();
+        input.literals = input
+            .literals
+            .into_iter()
+            .map(|(slot, value)| (remap(slot), value))
+            .collect();
+        input.symbols.slot_map = input
+            .symbols
+            .slot_map
+            .into_iter()
+            .filter(|(slot, _)| in_use(slot))
+            .map(|(slot, location)| (remap(slot), location))
+            .collect();
+        input.symbols.slot_names = input
+            .symbols
+            .slot_names
+            .into_iter()
+            .filter(|(slot, _)| in_use(slot))
+            .map(|(slot, name)| (remap(slot), name))
+            .collect();
+        input.arguments = input.arguments.into_iter().map(remap).collect();
+        input.return_slot = remap(input.return_slot);
+        for probe in &mut input.probes {
+            probe.slot = remap(probe.slot);
+        }
+        Ok(input)
+    }
+}
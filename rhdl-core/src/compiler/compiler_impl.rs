@@ -2,7 +2,7 @@ use crate::{
     ast::{
         ast_impl::{
             self, ArmKind, BinOp, Expr, ExprBinary, ExprIf, ExprKind, ExprLit, ExprTuple,
-            ExprTypedBits, FieldValue, FunctionId, Local, NodeId, Pat, PatKind, Path,
+            ExprTypedBits, FieldValue, FunctionId, Local, NodeId, Pat, PatKind, Path, RangeLimits,
             INVALID_NODE_ID,
         },
         visit::Visitor,
@@ -12,12 +12,12 @@ use crate::{
         UnifyContext,
     },
     rhif::{
-        object::SymbolMap,
+        object::{Probe, SymbolMap},
         rhif_builder::{
             op_array, op_as_bits, op_as_signed, op_assign, op_binary, op_case, op_comment, op_enum,
             op_exec, op_index, op_repeat, op_select, op_splice, op_struct, op_tuple, op_unary,
         },
-        spanned_source::build_spanned_source_for_kernel,
+        spanned_source::{build_spanned_source_for_kernel, SpannedSource},
         spec::{
             self, AluBinary, AluUnary, CaseArgument, ExternalFunction, ExternalFunctionCode,
             FuncId, Member, OpCode, Slot,
@@ -27,7 +27,7 @@ use crate::{
     types::typed_bits::TypedBits,
     Digital, KernelFnKind, Kind,
 };
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use super::{display_ast::pretty_print_statement, infer_types::id_to_var, ty::ty_bool};
@@ -63,8 +63,15 @@ pub struct CompilerContext {
     stash: Vec<ExternalFunction>,
     return_node: NodeId,
     arguments: Vec<Slot>,
+    probes: Vec<Probe>,
+    names: BTreeMap<Slot, String>,
     fn_id: FunctionId,
     name: String,
+    // The number of loop iterations unrolled so far (counting each copy of
+    // the body of a nested loop), and the most that are allowed.
+    unrolled: usize,
+    max_unroll: usize,
+    source: SpannedSource,
 }
 
 impl std::fmt::Display for CompilerContext {
@@ -105,7 +112,7 @@ fn collapse_path(path: &Path) -> String {
 }
 
 impl CompilerContext {
-    fn new(type_context: UnifyContext) -> Self {
+    fn new(type_context: UnifyContext, max_unroll: usize, source: SpannedSource) -> Self {
         Self {
             literals: vec![],
             reg_count: 0,
@@ -117,9 +124,14 @@ impl CompilerContext {
             return_node: INVALID_NODE_ID,
             ops: Default::default(),
             arguments: Default::default(),
+            probes: Default::default(),
+            names: Default::default(),
             fn_id: Default::default(),
             name: Default::default(),
             opcode_source_map: Default::default(),
+            unrolled: 0,
+            max_unroll,
+            source,
         }
     }
     fn node_ty(&self, id: NodeId) -> Result<Ty> {
@@ -211,6 +223,9 @@ impl CompilerContext {
         if self.locals.insert(id.into(), reg).is_some() {
             bail!("Duplicate local variable binding for {:?}", id)
         }
+        if reg.is_reg() {
+            self.names.insert(reg, name.into());
+        }
         Ok(())
     }
     fn rebind(&mut self, id: NodeId) -> Result<Rebind> {
@@ -243,7 +258,20 @@ impl CompilerContext {
             .cloned()
             .ok_or(anyhow::anyhow!("No local variable for {:?}", id))
     }
+    // The kernel compiler only knows the operators of the integer types.
+    // A newtype derived with `#[rhdl(transparent_ops)]` has the operators
+    // of its field, but any other impl of an operator on a composite type
+    // would be silently ignored, so it is rejected instead.
+    fn check_operand(&self, op: impl std::fmt::Display, operand: &Expr) -> Result<()> {
+        let kind: Kind = self.ty(operand.id)?.try_into()?;
+        ensure!(
+            !kind.is_composite(),
+            "The operator {op} is not supported on {kind} in a kernel.  Derive Digital with #[rhdl(transparent_ops)] on a newtype to use the operators of its field"
+        );
+        Ok(())
+    }
     fn unop(&mut self, id: NodeId, unary: &ast_impl::ExprUnary) -> Result<Slot> {
+        self.check_operand(&unary.op, &unary.expr)?;
         let arg = self.expr(&unary.expr)?;
         let result = self.reg(id)?;
         let op = match unary.op {
@@ -367,9 +395,43 @@ impl CompilerContext {
         if let Some(init) = &local.init {
             let rhs = self.expr(init)?;
             self.initialize_local(&local.pat, rhs)?;
+            if let Some(name) = &local.probe {
+                self.probe(local.id, name, rhs, local.keep)?;
+            }
+        } else if let Some(name) = &local.probe {
+            bail!("The probe `{name}` must be initialized where it is declared");
         }
         Ok(())
     }
+    // Copy the value into a register of its own, which is kept (and named)
+    // in the generated code.  The copy is made where the binding is, so a
+    // probe inside a branch shows the value computed by that branch,
+    // whether or not the branch is taken.
+    fn probe(&mut self, id: NodeId, name: &str, rhs: Slot, keep: bool) -> Result<()> {
+        ensure!(
+            is_probe_name(name),
+            "The probe name `{name}` is not a valid identifier"
+        );
+        ensure!(
+            !self.probes.iter().any(|probe| probe.name == name),
+            "The probe `{name}` is defined more than once in {}",
+            self.name
+        );
+        let ty = self
+            .ty
+            .get(&rhs)
+            .cloned()
+            .ok_or(anyhow!("No type for probe `{name}`"))?;
+        let slot = self.reg_with_type_and_node(ty, id)?;
+        ensure!(!slot.is_empty(), "The probe `{name}` has no bits to probe");
+        self.op(op_assign(slot, rhs), id);
+        self.probes.push(Probe {
+            name: name.into(),
+            slot,
+            keep,
+        });
+        Ok(())
+    }
     fn block(&mut self, block_result: Slot, block: &ast_impl::Block) -> Result<()> {
         let statement_count = block.stmts.len();
         for (ndx, statement) in block.stmts.iter().enumerate() {
@@ -495,7 +557,7 @@ impl CompilerContext {
         let lhs = self.reg(id)?;
         let target_ty = self.ty(_match.expr.id)?;
         let target = self.expr(&_match.expr)?;
-        let discriminant = if let Ty::Enum(enum_ty) = target_ty {
+        let mut discriminant = if let Ty::Enum(enum_ty) = target_ty {
             let disc_reg =
                 self.reg_with_type_and_node(*enum_ty.discriminant.clone(), _match.expr.id)?;
             self.op(
@@ -512,18 +574,37 @@ impl CompilerContext {
         };
         // Need to handle local rebindings in the bodies of the arms.
         let locals_prior_to_match = self.locals.clone();
-        let mut arguments = vec![];
+        let mut arm_values = vec![];
+        let mut arm_guards = vec![];
         let mut arm_locals = vec![];
         let mut arm_lhs = vec![];
         for arm in &_match.arms {
             self.locals = locals_prior_to_match.clone();
             let lhs = self.reg(id)?;
-            let disc = self.expr_arm(target, lhs, arm)?;
+            let (values, guard) = self.expr_arm(target, lhs, arm)?;
             arm_lhs.push(lhs);
-            arguments.push(disc);
+            arm_values.push(values);
+            arm_guards.push(guard);
             arm_locals.push(self.locals.clone());
         }
         self.locals = locals_prior_to_match.clone();
+        let arguments = if arm_guards.iter().any(Option::is_some) {
+            let selector = self.guarded_arm_selector(id, discriminant, &arm_values, &arm_guards)?;
+            discriminant = selector.0;
+            selector.1
+        } else {
+            // Values matched by an earlier arm belong to that arm, so
+            // they are dropped from the case table of this one.
+            let mut arguments: Vec<Vec<CaseArgument>> = vec![];
+            for values in arm_values {
+                let values = values
+                    .into_iter()
+                    .filter(|x| !arguments.iter().flatten().any(|y| y == x))
+                    .collect();
+                arguments.push(values);
+            }
+            arguments
+        };
         let mut rebound_locals = BTreeSet::new();
         for branch_locals in &arm_locals {
             let branch_rebindings = get_locals_changed(&self.locals, branch_locals)?;
@@ -546,16 +627,89 @@ impl CompilerContext {
                 .collect::<Result<Vec<_>>>()?;
             let cases = arguments
                 .iter()
-                .cloned()
-                .zip(arm_bindings.into_iter().cloned())
+                .zip(arm_bindings)
+                .flat_map(|(args, binding)| args.iter().cloned().map(|arg| (arg, *binding)))
                 .collect::<Vec<_>>();
             let new_binding = rebind.to;
             self.op(op_case(new_binding, discriminant, cases), id);
         }
-        let match_expr_table = arguments.iter().cloned().zip(arm_lhs).collect::<Vec<_>>();
+        let match_expr_table = arguments
+            .iter()
+            .zip(arm_lhs)
+            .flat_map(|(args, lhs)| args.iter().cloned().map(move |arg| (arg, lhs)))
+            .collect::<Vec<_>>();
         self.op(op_case(lhs, discriminant, match_expr_table), id);
         Ok(lhs)
     }
+    // A match with guards cannot be decided on the value of the
+    // discriminant alone.  Instead, each arm gets a condition that holds
+    // when its pattern matches and its guard (if any) passes, and a chain
+    // of selects picks the index of the first arm whose condition holds.
+    // The case tables of the match then switch on that index, so that
+    // the arms keep the priority they have in the source.
+    fn guarded_arm_selector(
+        &mut self,
+        id: NodeId,
+        discriminant: Slot,
+        arm_values: &[Vec<CaseArgument>],
+        arm_guards: &[Option<Slot>],
+    ) -> Result<(Slot, Vec<Vec<CaseArgument>>)> {
+        let literal_true = self.literal_from_typed_bits(&true.typed_bits())?;
+        let mut conditions = vec![];
+        for (values, guard) in arm_values.iter().zip(arm_guards) {
+            let mut condition = None;
+            for value in values {
+                let CaseArgument::Constant(value) = value else {
+                    condition = Some(literal_true);
+                    break;
+                };
+                let value = self.literal_from_typed_bits(value)?;
+                let matched = self.reg_with_type_and_node(ty_bool(), id)?;
+                self.op(op_binary(AluBinary::Eq, matched, discriminant, value), id);
+                condition = Some(match condition {
+                    None => matched,
+                    Some(prior) => {
+                        let any = self.reg_with_type_and_node(ty_bool(), id)?;
+                        self.op(op_binary(AluBinary::BitOr, any, prior, matched), id);
+                        any
+                    }
+                });
+            }
+            let mut condition = condition.unwrap_or(literal_true);
+            if let Some(guard) = guard {
+                let guarded = self.reg_with_type_and_node(ty_bool(), id)?;
+                self.op(op_binary(AluBinary::BitAnd, guarded, condition, *guard), id);
+                condition = guarded;
+            }
+            conditions.push(condition);
+        }
+        // The last arm is taken if no other arm is, which leaves it
+        // as the default of the case tables.
+        let count = conditions.len();
+        let width = (usize::BITS - count.saturating_sub(1).leading_zeros()).max(1) as usize;
+        let indices = (0..count)
+            .map(|ndx| (ndx as u128).typed_bits().unsigned_cast(width))
+            .collect::<Result<Vec<_>>>()?;
+        let mut selector = self.literal_from_typed_bits(&indices[count - 1])?;
+        for ndx in (0..count - 1).rev() {
+            let index = self.literal_from_typed_bits(&indices[ndx])?;
+            let chosen = self.reg_with_type_and_node(Kind::make_bits(width).into(), id)?;
+            self.op(op_select(chosen, conditions[ndx], index, selector), id);
+            selector = chosen;
+        }
+        let arguments = indices
+            .into_iter()
+            .enumerate()
+            .map(|(ndx, index)| {
+                if ndx == count - 1 {
+                    vec![CaseArgument::Wild]
+                } else {
+                    vec![CaseArgument::Constant(index)]
+                }
+            })
+            .collect();
+        Ok((selector, arguments))
+    }
     fn bind_arm_pattern(&mut self, pattern: &Pat) -> Result<()> {
         match &pattern.kind {
             PatKind::Ident(ident) => self.bind(pattern.id, &ident.name),
@@ -590,18 +744,62 @@ impl CompilerContext {
         }
     }
 
-    fn expr_arm(&mut self, target: Slot, lhs: Slot, arm: &ast_impl::Arm) -> Result<CaseArgument> {
+    // Compile an arm into `lhs`, returning the values of the
+    // discriminant that it matches, and the result of its guard (if it
+    // has one).
+    fn expr_arm(
+        &mut self,
+        target: Slot,
+        lhs: Slot,
+        arm: &ast_impl::Arm,
+    ) -> Result<(Vec<CaseArgument>, Option<Slot>)> {
         match &arm.kind {
             ArmKind::Wild => {
+                let guard = self.arm_guard(arm)?;
                 self.wrap_expr_in_block(lhs, &arm.body)?;
-                Ok(CaseArgument::Wild)
+                Ok((vec![CaseArgument::Wild], guard))
             }
-            ArmKind::Constant(constant) => {
+            ArmKind::Constant(_) | ArmKind::Range(_) | ArmKind::Or(_) => {
+                let guard = self.arm_guard(arm)?;
                 self.wrap_expr_in_block(lhs, &arm.body)?;
-                let value =
-                    cast_literal_to_inferred_type(constant.value.clone(), self.node_ty(arm.id)?)?
-                        .discriminant()?;
-                Ok(CaseArgument::Constant(value))
+                let ty = self.node_ty(arm.id)?;
+                let values = arm_values(&arm.kind, &ty).with_context(|| {
+                    let span = self.source.span(arm.id);
+                    format!(
+                        "in match arm `{}` ({} {}..{})",
+                        arm.kind, self.source.name, span.start, span.end
+                    )
+                })?;
+                let constants = values
+                    .iter()
+                    .map(|x| match x {
+                        ArmValue::Value(value) => Some(value),
+                        ArmValue::Range(..) => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(constants) = constants {
+                    let values = constants
+                        .into_iter()
+                        .map(|value| value.discriminant().map(CaseArgument::Constant))
+                        .collect::<Result<_>>()?;
+                    return Ok((values, guard));
+                }
+                // A range is matched by comparing against its bounds, so
+                // the arm is taken when its pattern (and guard) holds,
+                // as if it were a guarded wildcard.
+                let condition = self.arm_pattern_condition(arm.id, target, &values)?;
+                let condition = match guard {
+                    Some(guard) => {
+                        let guarded = self.reg_with_type_and_node(ty_bool(), arm.id)?;
+                        self.op(
+                            op_binary(AluBinary::BitAnd, guarded, condition, guard),
+                            arm.id,
+                        );
+                        guarded
+                    }
+                    None => condition,
+                };
+                Ok((vec![CaseArgument::Wild], Some(condition)))
             }
             ArmKind::Enum(arm_enum) => {
                 // Allocate the local bindings for the match pattern
@@ -615,12 +813,58 @@ impl CompilerContext {
                 )?;
                 self.op(op_index(payload, target, path), arm_enum.pat.id);
                 self.initialize_local(&arm_enum.pat, payload)?;
+                let guard = self.arm_guard(arm)?;
                 let result = self.expr(&arm.body)?;
                 self.op(op_assign(lhs, result), arm_enum.pat.id);
-                Ok(CaseArgument::Constant(discriminant))
+                Ok((vec![CaseArgument::Constant(discriminant)], guard))
             }
         }
     }
+    // The condition under which the scrutinee matches one of the values
+    // (or ranges of values) of an arm.
+    fn arm_pattern_condition(
+        &mut self,
+        id: NodeId,
+        target: Slot,
+        values: &[ArmValue],
+    ) -> Result<Slot> {
+        let mut condition = None;
+        for value in values {
+            let matched = match value {
+                ArmValue::Value(value) => {
+                    let value = self.literal_from_typed_bits(value)?;
+                    let matched = self.reg_with_type_and_node(ty_bool(), id)?;
+                    self.op(op_binary(AluBinary::Eq, matched, target, value), id);
+                    matched
+                }
+                ArmValue::Range(start, end) => {
+                    let start = self.literal_from_typed_bits(start)?;
+                    let end = self.literal_from_typed_bits(end)?;
+                    let above = self.reg_with_type_and_node(ty_bool(), id)?;
+                    self.op(op_binary(AluBinary::Ge, above, target, start), id);
+                    let below = self.reg_with_type_and_node(ty_bool(), id)?;
+                    self.op(op_binary(AluBinary::Le, below, target, end), id);
+                    let matched = self.reg_with_type_and_node(ty_bool(), id)?;
+                    self.op(op_binary(AluBinary::BitAnd, matched, above, below), id);
+                    matched
+                }
+            };
+            condition = Some(match condition {
+                None => matched,
+                Some(prior) => {
+                    let any = self.reg_with_type_and_node(ty_bool(), id)?;
+                    self.op(op_binary(AluBinary::BitOr, any, prior, matched), id);
+                    any
+                }
+            });
+        }
+        condition.ok_or(anyhow!("ICE - match arm with no values"))
+    }
+    // The guard sees the bindings of the pattern, but not the changes
+    // made by the body of the arm.
+    fn arm_guard(&mut self, arm: &ast_impl::Arm) -> Result<Option<Slot>> {
+        arm.guard.as_ref().map(|guard| self.expr(guard)).transpose()
+    }
     fn return_expr(&mut self, id: NodeId, _return: &ast_impl::ExprRet) -> Result<Slot> {
         // An early return of the type "return <expr>" is transformed
         // into the following equivalent expression
@@ -692,7 +936,20 @@ impl CompilerContext {
         Ok(Slot::Empty)
     }
     fn method_call(&mut self, id: NodeId, method_call: &ast_impl::ExprMethodCall) -> Result<Slot> {
-        // First handle unary ops only
+        // The shift of a signed value is arithmetic, so this is `>>`
+        if method_call.method == "arithmetic_shr" {
+            let target_ty = self.ty(method_call.receiver.id)?;
+            ensure!(
+                matches!(target_ty, Ty::Const(Bits::Signed(_))),
+                "arithmetic_shr is only defined for SignedBits, not {target_ty:?}"
+            );
+            let lhs = self.reg(id)?;
+            let arg = self.expr(&method_call.receiver)?;
+            let amount = self.expr(&method_call.args[0])?;
+            self.op(op_binary(AluBinary::Shr, lhs, arg, amount), id);
+            return Ok(lhs);
+        }
+        // Otherwise handle unary ops only
         let op = match method_call.method.as_str() {
             "any" => AluUnary::Any,
             "all" => AluUnary::All,
@@ -788,6 +1045,9 @@ impl CompilerContext {
     }
     fn binop(&mut self, id: NodeId, bin: &ExprBinary) -> Result<Slot> {
         let op = &bin.op;
+        if !matches!(op, BinOp::Eq | BinOp::Ne) {
+            self.check_operand(op, &bin.lhs)?;
+        }
         let self_assign = matches!(
             op,
             BinOp::AddAssign
@@ -825,7 +1085,7 @@ impl CompilerContext {
         self.op(op_binary(alu, result, lhs, rhs), id);
         Ok(result)
     }
-    fn for_loop(&mut self, for_loop: &ast_impl::ExprForLoop) -> Result<Slot> {
+    fn for_loop(&mut self, id: NodeId, for_loop: &ast_impl::ExprForLoop) -> Result<Slot> {
         self.bind_pattern(&for_loop.pat)?;
         // Determine the loop type
         let index_reg = self.resolve_local(for_loop.pat.id)?;
@@ -858,6 +1118,21 @@ impl CompilerContext {
         };
         let start_lit = start_lit.parse::<i32>()?;
         let end_lit = end_lit.parse::<i32>()?;
+        // Check the bound before unrolling, since an unrolled loop that is
+        // too long takes the compiler a very long time to fail.
+        self.unrolled += (start_lit..end_lit).len();
+        if self.unrolled > self.max_unroll {
+            let span = self.source.span(id);
+            let text = &self.source.source[span.clone()];
+            let header = text.split('{').next().unwrap_or(text).trim();
+            bail!(
+                "loop unrolled beyond {} iterations at `{header}` ({} {}..{})",
+                self.max_unroll,
+                self.source.name,
+                span.start,
+                span.end
+            );
+        }
         for ndx in start_lit..end_lit {
             let value = self.literal_from_type_and_int(&index_ty, ndx)?;
             self.rebind(for_loop.pat.id)?;
@@ -936,7 +1211,7 @@ impl CompilerContext {
             ExprKind::Unary(unary) => self.unop(expr.id, unary),
             ExprKind::Match(_match) => self.match_expr(expr.id, _match),
             ExprKind::Ret(_return) => self.return_expr(expr.id, _return),
-            ExprKind::ForLoop(for_loop) => self.for_loop( for_loop),
+            ExprKind::ForLoop(for_loop) => self.for_loop(expr.id, for_loop),
             ExprKind::Assign(assign) => self.assign(expr.id, assign),
             ExprKind::Range(_) => bail!("Ranges are only supported in for loops"),
             ExprKind::Let(_) => bail!("Fallible let expressions are not currently supported in rhdl.  Use a match instead"),
@@ -1059,8 +1334,9 @@ impl Visitor for CompilerContext {
     }
 }
 
-pub fn compile(func: &ast_impl::KernelFn, ctx: UnifyContext) -> Result<Object> {
-    let mut compiler = CompilerContext::new(ctx);
+pub fn compile(func: &ast_impl::KernelFn, ctx: UnifyContext, max_unroll: usize) -> Result<Object> {
+    let source = build_spanned_source_for_kernel(func);
+    let mut compiler = CompilerContext::new(ctx, max_unroll, source);
     compiler.visit_kernel_fn(func)?;
     // Get the final name for the return value
     let return_slot = compiler.resolve_local(compiler.return_node)?;
@@ -1101,7 +1377,7 @@ pub fn compile(func: &ast_impl::KernelFn, ctx: UnifyContext) -> Result<Object> {
         .into_iter()
         .map(|node| (compiler.fn_id, node).into())
         .collect();
-    let source = build_spanned_source_for_kernel(func);
+    let source = compiler.source;
     let literals = literals
         .into_iter()
         .enumerate()
@@ -1112,6 +1388,7 @@ pub fn compile(func: &ast_impl::KernelFn, ctx: UnifyContext) -> Result<Object> {
             slot_map,
             opcode_map,
             source,
+            slot_names: compiler.names,
         },
         literals,
         kind,
@@ -1121,9 +1398,52 @@ pub fn compile(func: &ast_impl::KernelFn, ctx: UnifyContext) -> Result<Object> {
         arguments: compiler.arguments,
         fn_id: compiler.fn_id,
         name: compiler.name,
+        pure: func.pure,
+        const_eval: func.const_eval,
+        probes: compiler.probes,
+        flags: Default::default(),
+        params: Default::default(),
+        mux_strategy: Default::default(),
     })
 }
 
+// Probes become Verilog identifiers.  Names that look like the registers,
+// literals and child instances of the generated code (`r3`, `l0`, `c1`)
+// are not allowed, and
+// neither are the Verilog keywords that are also valid Rust identifiers.
+fn is_probe_name(name: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "always",
+        "assign",
+        "begin",
+        "case",
+        "default",
+        "end",
+        "function",
+        "initial",
+        "inout",
+        "input",
+        "integer",
+        "localparam",
+        "module",
+        "output",
+        "parameter",
+        "reg",
+        "signed",
+        "unsigned",
+        "wire",
+    ];
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let generated = matches!(name.chars().next(), Some('r' | 'l' | 'm' | 'c'))
+        && name.len() > 1
+        && name[1..].chars().all(|c| c.is_ascii_digit());
+    valid && !generated && !KEYWORDS.contains(&name)
+}
+
 fn cast_literal_to_inferred_type(t: ExprLit, ty: Ty) -> Result<TypedBits> {
     match t {
         ExprLit::TypedBits(t) => {
@@ -1149,6 +1469,9 @@ fn cast_literal_to_inferred_type(t: ExprLit, ty: Ty) -> Result<TypedBits> {
         }
         ExprLit::Int(x) => {
             if ty.is_unsigned() {
+                if x.starts_with('-') {
+                    bail!("Negative literal {x} cannot have the unsigned type {ty:?}");
+                }
                 let x_as_u128 = if let Some(x) = x.strip_prefix("0b") {
                     u128::from_str_radix(x, 2)?
                 } else if let Some(x) = x.strip_prefix("0o") {
@@ -1160,17 +1483,74 @@ fn cast_literal_to_inferred_type(t: ExprLit, ty: Ty) -> Result<TypedBits> {
                 };
                 x_as_u128.typed_bits().unsigned_cast(ty.unsigned_bits()?)
             } else {
-                let x_as_i128 = if let Some(x) = x.strip_prefix("0b") {
-                    i128::from_str_radix(x, 2)?
-                } else if let Some(x) = x.strip_prefix("0o") {
-                    i128::from_str_radix(x, 8)?
-                } else if let Some(x) = x.strip_prefix("0x") {
-                    i128::from_str_radix(x, 16)?
-                } else {
-                    x.parse::<i128>()?
-                };
-                x_as_i128.typed_bits().signed_cast(ty.signed_bits()?)
+                int_literal_value(&x)?
+                    .typed_bits()
+                    .signed_cast(ty.signed_bits()?)
+            }
+        }
+    }
+}
+
+fn int_literal_value(x: &str) -> Result<i128> {
+    if let Some(x) = x.strip_prefix('-') {
+        return int_literal_value(x)?
+            .checked_neg()
+            .ok_or(anyhow!("Literal -{x} is out of range"));
+    }
+    Ok(if let Some(x) = x.strip_prefix("0b") {
+        i128::from_str_radix(x, 2)?
+    } else if let Some(x) = x.strip_prefix("0o") {
+        i128::from_str_radix(x, 8)?
+    } else if let Some(x) = x.strip_prefix("0x") {
+        i128::from_str_radix(x, 16)?
+    } else {
+        x.parse::<i128>()?
+    })
+}
+
+// A value, or an (inclusive) range of values, matched by an arm.
+enum ArmValue {
+    Value(TypedBits),
+    Range(TypedBits, TypedBits),
+}
+
+// Collect the values matched by a constant, range or or-pattern arm, each
+// cast to the type of the scrutinee.  A literal that does not fit in the
+// scrutinee is an error.
+fn arm_values(kind: &ArmKind, ty: &Ty) -> Result<Vec<ArmValue>> {
+    match kind {
+        ArmKind::Constant(constant) => Ok(vec![ArmValue::Value(cast_literal_to_inferred_type(
+            constant.value.clone(),
+            ty.clone(),
+        )?)]),
+        ArmKind::Range(range) => {
+            let (ExprLit::Int(start), ExprLit::Int(end)) = (&range.start, &range.end) else {
+                bail!("Range patterns must have integer literal bounds");
+            };
+            cast_literal_to_inferred_type(range.start.clone(), ty.clone())?;
+            cast_literal_to_inferred_type(range.end.clone(), ty.clone())?;
+            let start = int_literal_value(start)?;
+            let end = match range.limits {
+                RangeLimits::HalfOpen => int_literal_value(end)? - 1,
+                RangeLimits::Closed => int_literal_value(end)?,
+            };
+            if start > end {
+                bail!("Range pattern is empty");
             }
+            let bound =
+                |x: i128| cast_literal_to_inferred_type(ExprLit::Int(x.to_string()), ty.clone());
+            Ok(vec![ArmValue::Range(bound(start)?, bound(end)?)])
+        }
+        ArmKind::Or(or) => Ok(or
+            .cases
+            .iter()
+            .map(|case| arm_values(case, ty))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect()),
+        ArmKind::Wild | ArmKind::Enum(_) => {
+            bail!("ICE - arm_values called on a wild or enum arm")
         }
     }
 }
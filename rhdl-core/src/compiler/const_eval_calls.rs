@@ -0,0 +1,174 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::{
+    ast::ast_impl::FunctionId,
+    rhif::{
+        spec::{Assign, Exec, ExternalFunctionCode, FuncId, OpCode, Slot},
+        vm::execute_kernel,
+        Object,
+    },
+    Module,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use super::{
+    check_rhif_flow::DataFlowCheckPass, check_rhif_type::TypeCheckPass,
+    compact_slots::CompactSlotsPass, pass::Pass, remove_extra_registers::RemoveExtraRegistersPass,
+    remove_unused_literals::RemoveUnusedLiterals,
+};
+
+// A kernel can only be evaluated at compile time if the interpreter can
+// run everything that it calls.  Kernels that reach an external function
+// are left alone, even if they are marked `const_eval`, since the
+// external function may have no stub for the interpreter, or may only
+// make sense in hardware.
+fn calls_extern(
+    design: &Module,
+    fn_id: FunctionId,
+    visited: &mut HashSet<FunctionId>,
+) -> Result<bool> {
+    if !visited.insert(fn_id) {
+        return Ok(false);
+    }
+    let obj = design
+        .objects
+        .get(&fn_id)
+        .ok_or(anyhow!("Function {fn_id} not found"))?;
+    for func in &obj.externals {
+        match &func.code {
+            ExternalFunctionCode::Extern(_) => return Ok(true),
+            ExternalFunctionCode::Kernel(kernel) => {
+                if calls_extern(design, kernel.inner().fn_id, visited)? {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn is_const_eval(design: &Module, fn_id: FunctionId) -> Result<bool> {
+    let obj = design
+        .objects
+        .get(&fn_id)
+        .ok_or(anyhow!("Function {fn_id} not found"))?;
+    Ok(obj.const_eval && !calls_extern(design, fn_id, &mut HashSet::new())?)
+}
+
+// Replace each call to a `const_eval` kernel whose arguments are all
+// literals with an assignment of its result, which is computed by the
+// interpreter.  Returns true if any call was replaced.
+fn fold_calls(design: &Module, obj: &mut Object) -> Result<bool> {
+    let mut folded = false;
+    let mut next_literal = obj.literal_max_index() + 1;
+    for ndx in 0..obj.ops.len() {
+        let OpCode::Exec(Exec { lhs, id, args }) = &obj.ops[ndx] else {
+            continue;
+        };
+        let ExternalFunctionCode::Kernel(kernel) = &obj.externals[id.0].code else {
+            continue;
+        };
+        let fn_id = kernel.inner().fn_id;
+        if !args.iter().all(|arg| arg.is_literal()) || !is_const_eval(design, fn_id)? {
+            continue;
+        }
+        let arguments = args
+            .iter()
+            .map(|arg| {
+                obj.literals
+                    .get(arg)
+                    .cloned()
+                    .ok_or(anyhow!("ICE literal {arg} not found"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let value = execute_kernel(design, fn_id, arguments).with_context(|| {
+            format!(
+                "While evaluating the call to `{}` in `{}` at compile time",
+                kernel.inner().name,
+                obj.name
+            )
+        })?;
+        let lhs = *lhs;
+        obj.ops[ndx] = if lhs.is_empty() {
+            OpCode::Noop
+        } else {
+            let literal = Slot::Literal(next_literal);
+            next_literal += 1;
+            obj.kind.insert(literal, value.kind.clone());
+            obj.literals.insert(literal, value);
+            OpCode::Assign(Assign { lhs, rhs: literal })
+        };
+        folded = true;
+    }
+    Ok(folded)
+}
+
+// Drop the external functions that are no longer called, and renumber
+// the calls to the rest.
+pub(super) fn remove_unused_externals(obj: &mut Object) {
+    let used = obj
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            OpCode::Exec(exec) => Some(exec.id.0),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+    let renumber = |id: usize| used.iter().position(|ndx| *ndx == id).unwrap();
+    for op in obj.ops.iter_mut() {
+        if let OpCode::Exec(exec) = op {
+            exec.id = FuncId(renumber(exec.id.0));
+        }
+    }
+    obj.externals = std::mem::take(&mut obj.externals)
+        .into_iter()
+        .enumerate()
+        .filter(|(ndx, _)| used.contains(ndx))
+        .map(|(_, func)| func)
+        .collect();
+}
+
+fn reachable(design: &Module, fn_id: FunctionId, visited: &mut HashSet<FunctionId>) {
+    if !visited.insert(fn_id) {
+        return;
+    }
+    let Some(obj) = design.objects.get(&fn_id) else {
+        return;
+    };
+    for func in &obj.externals {
+        if let ExternalFunctionCode::Kernel(kernel) = &func.code {
+            reachable(design, kernel.inner().fn_id, visited);
+        }
+    }
+}
+
+// Kernels marked with `#[kernel(const_eval)]` are evaluated when they are
+// called with constant arguments, and the call is replaced with its
+// result.  A result that feeds another such call is folded in turn.
+// Kernels that are no longer called by anything are dropped from the
+// design.
+pub(crate) fn const_eval_calls(design: &mut Module) -> Result<()> {
+    let fn_ids = design.objects.keys().copied().collect::<Vec<_>>();
+    for fn_id in fn_ids {
+        let mut obj = design.objects[&fn_id].clone();
+        let mut folded = false;
+        while fold_calls(design, &mut obj)? {
+            folded = true;
+            obj = RemoveExtraRegistersPass::run(obj)?;
+        }
+        if !folded {
+            continue;
+        }
+        remove_unused_externals(&mut obj);
+        obj = RemoveUnusedLiterals::run(obj)?;
+        obj = TypeCheckPass::run(obj)?;
+        obj = DataFlowCheckPass::run(obj)?;
+        obj = CompactSlotsPass::run(obj)?;
+        design.objects.insert(fn_id, obj);
+    }
+    let mut live = HashSet::new();
+    reachable(design, design.top, &mut live);
+    design.objects.retain(|fn_id, _| live.contains(fn_id));
+    Ok(())
+}
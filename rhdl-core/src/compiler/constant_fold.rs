@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use crate::{
+    rhif::{
+        spec::{Binary, OpCode, Select, Slot, Unary},
+        vm::{binary, unary},
+        Object,
+    },
+    TypedBits,
+};
+use anyhow::Result;
+
+use super::{pass::Pass, utils::remap_slots};
+
+#[derive(Default, Debug, Clone)]
+pub struct ConstantFoldPass {}
+
+enum Folded {
+    // The result is a new literal
+    Value(TypedBits),
+    // The result is one of the arguments (of a select)
+    Slot(Slot),
+}
+
+// The result of the operation, if it can be computed now.  The ALU
+// operations are evaluated as the interpreter would.
+fn fold(input: &Object, op: &OpCode) -> Option<(Slot, Folded)> {
+    let literal = |slot: &Slot| input.literals.get(slot).cloned();
+    let (lhs, folded) = match op {
+        OpCode::Binary(Binary {
+            op,
+            lhs,
+            arg1,
+            arg2,
+        }) => (
+            *lhs,
+            Folded::Value(binary(op, literal(arg1)?, literal(arg2)?).ok()?),
+        ),
+        OpCode::Unary(Unary { op, lhs, arg1 }) => {
+            (*lhs, Folded::Value(unary(op, literal(arg1)?).ok()?))
+        }
+        OpCode::Select(Select {
+            lhs,
+            cond,
+            true_value,
+            false_value,
+        }) => {
+            let cond = literal(cond)?.any().as_bool().ok()?;
+            (
+                *lhs,
+                Folded::Slot(if cond { *true_value } else { *false_value }),
+            )
+        }
+        _ => return None,
+    };
+    // A probe needs a register of its own
+    if input.is_probed(lhs) {
+        return None;
+    }
+    if let Folded::Value(value) = &folded {
+        if input.kind.get(&lhs) != Some(&value.kind) {
+            return None;
+        }
+    }
+    Some((lhs, folded))
+}
+
+impl Pass for ConstantFoldPass {
+    fn name(&self) -> &'static str {
+        "constant_fold"
+    }
+    fn description(&self) -> &'static str {
+        "Evaluate the operations whose arguments are literals, and use the results in place of their registers"
+    }
+    fn run(mut input: Object) -> Result<Object> {
+        // Registers are only written once, so a single pass in order also
+        // folds the operations that use the results of folded ones.  The
+        // folded operations are left as no-ops, so that the opcode map
+        // still lines up.
+        let mut folded: HashMap<Slot, Slot> = Default::default();
+        let mut next_literal = input
+            .literals
+            .keys()
+            .chain(input.kind.keys())
+            .filter_map(|slot| match slot {
+                Slot::Literal(ndx) => Some(ndx + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        let ops = std::mem::take(&mut input.ops);
+        for op in ops {
+            let op = remap_slots(op, |slot| folded.get(&slot).copied().unwrap_or(slot));
+            match fold(&input, &op) {
+                Some((lhs, Folded::Value(value))) => {
+                    let literal = Slot::Literal(next_literal);
+                    next_literal += 1;
+                    input.kind.insert(literal, value.kind.clone());
+                    input.literals.insert(literal, value);
+                    input.kind.remove(&lhs);
+                    input.symbols.slot_map.remove(&lhs);
+                    input.symbols.slot_names.remove(&lhs);
+                    folded.insert(lhs, literal);
+                    input.ops.push(OpCode::Noop);
+                }
+                Some((lhs, Folded::Slot(slot))) => {
+                    input.kind.remove(&lhs);
+                    input.symbols.slot_map.remove(&lhs);
+                    input.symbols.slot_names.remove(&lhs);
+                    folded.insert(lhs, slot);
+                    input.ops.push(OpCode::Noop);
+                }
+                None => input.ops.push(op),
+            }
+        }
+        input.return_slot = folded
+            .get(&input.return_slot)
+            .copied()
+            .unwrap_or(input.return_slot);
+        Ok(input)
+    }
+}
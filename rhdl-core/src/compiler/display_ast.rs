@@ -140,6 +140,9 @@ impl<'a> PrettyPrinter<'a> {
     fn print_stmt(&mut self, stmt: &Stmt) -> Result<()> {
         match &stmt.kind {
             StmtKind::Local(local) => {
+                if let Some(name) = &local.probe {
+                    self.push(&format!("#[rhdl(probe = \"{name}\")] "));
+                }
                 self.push("let ");
                 self.print_pattern(&local.pat)?;
                 if let Some(init) = &local.init {
@@ -164,6 +167,7 @@ impl<'a> PrettyPrinter<'a> {
             Kind::Empty => self.push("()"),
             Kind::Signed(n) => self.push(&format!("s{}", n)),
             Kind::Bits(n) => self.push(&format!("b{}", n)),
+            Kind::Ranged(r) => self.print_kind(&r.base)?,
             Kind::Tuple(kinds) => {
                 self.push("(");
                 for kind in &kinds.elements {
@@ -315,11 +319,17 @@ impl<'a> PrettyPrinter<'a> {
                         ArmKind::Constant(constant) => {
                             self.push(&format!("const {}", constant.value))
                         }
+                        ArmKind::Range(range) => self.push(&format!("const {}", range)),
+                        ArmKind::Or(or) => self.push(&format!("const {}", or)),
                         ArmKind::Enum(enum_arm) => {
                             self.print_pattern(&enum_arm.pat)?;
                             self.push(&format!("#{}", enum_arm.template));
                         }
                     }
+                    if let Some(guard) = &arm.guard {
+                        self.push(" if ");
+                        self.print_expr(guard)?;
+                    }
                     self.push(" => ");
                     self.print_expr(&arm.body)?;
                     self.push(",\n");
@@ -411,6 +421,39 @@ impl Display for ExprLit {
     }
 }
 
+impl Display for ArmRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.limits {
+            RangeLimits::HalfOpen => write!(f, "{}..{}", self.start, self.end),
+            RangeLimits::Closed => write!(f, "{}..={}", self.start, self.end),
+        }
+    }
+}
+
+impl Display for ArmOr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (ndx, case) in self.cases.iter().enumerate() {
+            if ndx > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", case)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ArmKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArmKind::Wild => write!(f, "_"),
+            ArmKind::Constant(constant) => write!(f, "{}", constant.value),
+            ArmKind::Range(range) => write!(f, "{}", range),
+            ArmKind::Or(or) => write!(f, "{}", or),
+            ArmKind::Enum(enum_arm) => write!(f, "#{}", enum_arm.template),
+        }
+    }
+}
+
 impl Display for Path {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let segments = self
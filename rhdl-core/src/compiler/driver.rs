@@ -1,27 +1,261 @@
 use crate::{
+    codegen::mux::MuxStrategy,
     compiler::{
-        ascii::render_ast_to_string, assign_node_ids, check_inference::check_inference,
-        check_rhif_flow::DataFlowCheckPass, check_rhif_type::TypeCheckPass, compile, infer,
+        ascii::render_ast_to_string, assign_node_ids, check_concat_widths::CheckConcatWidthsPass,
+        check_inference::check_inference, check_purity::check_purity,
+        check_rhif_flow::DataFlowCheckPass, check_rhif_type::TypeCheckPass,
+        check_signature::check_signature, compact_slots::CompactSlotsPass, compile,
+        const_eval_calls::const_eval_calls, infer, insert_range_checks::InsertRangeChecksPass,
         pass::Pass, pre_cast_literals::PreCastLiterals,
         remove_extra_registers::RemoveExtraRegistersPass,
         remove_unneeded_muxes::RemoveUnneededMuxesPass,
         remove_unused_literals::RemoveUnusedLiterals, remove_useless_casts::RemoveUselessCastsPass,
+        strip_cfg::strip_cfg, sub_to_add::SubToAddPass,
     },
     kernel::Kernel,
     rhif::{spec::ExternalFunctionCode, Object},
-    Module,
+    Module, TypedBits,
 };
 
-use anyhow::Result;
+use super::specialize_calls::{bind_arguments, fold_constants, specialize_calls};
 
-pub fn compile_kernel(mut kernel: Kernel) -> Result<Object> {
+use anyhow::{anyhow, ensure, Result};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+// Compiled kernels, keyed by a hash of their AST.  A cache is only
+// consulted while it is installed with `with_compile_cache`, so that
+// repeated builds of a design (e.g., by a `DesignWatcher`) can reuse the
+// kernels that did not change.
+#[derive(Default, Debug, Clone)]
+pub struct CompileCache {
+    objects: HashMap<u64, Object>,
+    hits: usize,
+    misses: usize,
+}
+
+impl CompileCache {
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+thread_local! {
+    static CACHE: RefCell<Option<CompileCache>> = const { RefCell::new(None) };
+}
+
+// Run `f` with the given cache installed for this thread.  Any kernels
+// compiled by `f` are looked up in (and added to) the cache.
+pub fn with_compile_cache<T>(cache: &mut CompileCache, f: impl FnOnce() -> T) -> T {
+    let previous = CACHE.with(|c| c.replace(Some(std::mem::take(cache))));
+    let result = f();
+    *cache = CACHE.with(|c| c.replace(previous)).unwrap_or_default();
+    result
+}
+
+// The default for `CompileOptions::max_unroll`.
+pub const DEFAULT_MAX_UNROLL: usize = 65_536;
+
+// The default for `CompileOptions::max_kind_bits`.
+pub const DEFAULT_MAX_KIND_BITS: usize = 1 << 20;
+
+// Options that change the code generated for a design.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompileOptions {
+    // Assert (in simulation) that the values written to ranged fields
+    // are within their bounds.
+    pub range_checks: bool,
+    // The flags that kernels may test with `#[rhdl(cfg(flag = "..."))]`,
+    // and whether each is enabled.  A kernel that tests any other flag
+    // fails to compile.
+    pub flags: BTreeMap<String, bool>,
+    // Name the wires in the generated Verilog after the `let` bindings
+    // of the kernels that they hold, instead of after their registers.
+    pub preserve_names: bool,
+    // The most loop iterations that a kernel may unroll to, over all of
+    // its `for` loops (with each copy of the body of a nested loop
+    // counting separately).  Kernels that unroll further fail to compile.
+    pub max_unroll: usize,
+    // The widest that a single type may be, in bits.  The arguments and
+    // results of kernels, and the ports of circuits (as well as each
+    // circuit and the design as a whole), are checked against it, so
+    // that a typo in the size of an array fails quickly, with the name
+    // of the field, instead of running out of memory.
+    pub max_kind_bits: usize,
+    // How dynamic indices into arrays are written in the Verilog, for the
+    // kernels that do not choose with `#[kernel(mux = "...")]`.
+    pub mux_strategy: MuxStrategy,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            range_checks: false,
+            flags: BTreeMap::new(),
+            preserve_names: false,
+            max_unroll: DEFAULT_MAX_UNROLL,
+            max_kind_bits: DEFAULT_MAX_KIND_BITS,
+            mux_strategy: MuxStrategy::default(),
+        }
+    }
+}
+
+impl CompileOptions {
+    pub fn with_flag(mut self, flag: &str, enabled: bool) -> Self {
+        self.flags.insert(flag.into(), enabled);
+        self
+    }
+    pub fn with_max_unroll(mut self, max_unroll: usize) -> Self {
+        self.max_unroll = max_unroll;
+        self
+    }
+    pub fn with_max_kind_bits(mut self, max_kind_bits: usize) -> Self {
+        self.max_kind_bits = max_kind_bits;
+        self
+    }
+    pub fn with_mux_strategy(mut self, mux_strategy: MuxStrategy) -> Self {
+        self.mux_strategy = mux_strategy;
+        self
+    }
+}
+
+thread_local! {
+    static OPTIONS: RefCell<Option<CompileOptions>> = const { RefCell::new(None) };
+}
+
+// Run `f` with the given options installed for this thread.  Designs
+// compiled by `compile_design` (and so the descriptors of circuits) use
+// them, as do the Rust versions of kernels when they test a flag.
+// The previous options are put back even if `f` panics.
+pub fn with_compile_options<T>(options: &CompileOptions, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<CompileOptions>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            OPTIONS.with(|o| o.replace(self.0.take()));
+        }
+    }
+    let _restore = Restore(OPTIONS.with(|o| o.replace(Some(options.clone()))));
+    f()
+}
+
+pub(crate) fn installed_options() -> CompileOptions {
+    OPTIONS.with(|o| o.borrow().clone()).unwrap_or_default()
+}
+
+// The `max_kind_bits` of the installed options, without copying them.
+pub(crate) fn installed_max_kind_bits() -> usize {
+    OPTIONS.with(|o| {
+        o.borrow()
+            .as_ref()
+            .map_or(DEFAULT_MAX_KIND_BITS, |options| options.max_kind_bits)
+    })
+}
+
+// Used by the Rust version of a kernel to decide whether to run the code
+// marked `#[rhdl(cfg(flag = "..."))]`.
+pub fn compile_flag(flag: &str) -> bool {
+    OPTIONS.with(|o| {
+        o.borrow()
+            .as_ref()
+            .is_some_and(|options| options.flags.get(flag) == Some(&true))
+    })
+}
+
+// As `compile_flag`, but a flag that is not in the installed options is
+// an error.  Used by circuits with children marked
+// `#[rhdl(cfg(flag = "..."))]`.
+pub fn known_compile_flag(flag: &str) -> Result<bool> {
+    installed_options()
+        .flags
+        .get(flag)
+        .copied()
+        .ok_or_else(|| anyhow!("Unknown flag {flag} in #[rhdl(cfg)] attribute"))
+}
+
+// As `known_compile_flag`, for the code generated by the `Digital` and
+// `Circuit` derives (e.g., `static_kind`), which cannot return an error.
+// So an unknown flag panics.
+pub fn expect_compile_flag(flag: &str) -> bool {
+    known_compile_flag(flag).unwrap_or_else(|err| panic!("{err}"))
+}
+
+fn kernel_hash(kernel: &Kernel, options: &CompileOptions) -> Result<u64> {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(serde_json::to_string(kernel)?.as_bytes());
+    options.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+pub fn compile_kernel(kernel: Kernel, options: &CompileOptions) -> Result<Object> {
+    if !CACHE.with(|c| c.borrow().is_some()) {
+        return compile_kernel_uncached(kernel, options);
+    }
+    let key = kernel_hash(&kernel, options)?;
+    let cached = CACHE.with(|c| {
+        let mut c = c.borrow_mut();
+        let cache = c.as_mut()?;
+        let obj = cache.objects.get(&key).cloned();
+        if obj.is_some() {
+            cache.hits += 1;
+        }
+        obj
+    });
+    if let Some(obj) = cached {
+        return Ok(obj);
+    }
+    let obj = compile_kernel_uncached(kernel, options)?;
+    CACHE.with(|c| {
+        if let Some(cache) = c.borrow_mut().as_mut() {
+            cache.misses += 1;
+            cache.objects.insert(key, obj.clone());
+        }
+    });
+    Ok(obj)
+}
+
+// Lower a kernel to RHIF without running any of the passes.  This is
+// the object that the optimizer starts from, so it is also what the pass
+// verification harness checks the passes against.
+pub fn compile_kernel_unoptimized(mut kernel: Kernel, options: &CompileOptions) -> Result<Object> {
+    check_kernel_widths(&kernel, options.max_kind_bits)?;
+    let flags = strip_cfg(&mut kernel, &options.flags)?;
     assign_node_ids(&mut kernel)?;
     let ctx = infer(&kernel)?;
     let _ast_ascii = render_ast_to_string(&kernel, &ctx).unwrap();
     check_inference(&kernel, &ctx)?;
-    let mut obj = compile(kernel.inner(), ctx)?;
+    let mut obj = compile(kernel.inner(), ctx, options.max_unroll)?;
+    obj.flags = flags;
+    Ok(obj)
+}
+
+// The types of the arguments and result of a kernel are laid out (many
+// times over) by the compiler, so check their widths before starting.
+fn check_kernel_widths(kernel: &Kernel, limit: usize) -> Result<()> {
+    let name = &kernel.inner().name;
+    let signature = kernel.signature();
+    for (ndx, kind) in signature.arguments.iter().enumerate() {
+        kind.checked_bits(limit)
+            .map_err(|err| anyhow!("Argument {ndx} of kernel {name} is too wide: {err}"))?;
+    }
+    signature
+        .ret
+        .checked_bits(limit)
+        .map_err(|err| anyhow!("The result of kernel {name} is too wide: {err}"))?;
+    Ok(())
+}
+
+fn compile_kernel_uncached(kernel: Kernel, options: &CompileOptions) -> Result<Object> {
+    let signature = kernel.signature();
+    let mux_strategy = kernel.inner().mux_strategy.unwrap_or(options.mux_strategy);
+    let mut obj = compile_kernel_unoptimized(kernel, options)?;
+    let flags = std::mem::take(&mut obj.flags);
     eprintln!("{}", obj);
     for _pass in 0..2 {
+        obj = SubToAddPass::run(obj)?;
         obj = RemoveExtraRegistersPass::run(obj)?;
         obj = RemoveUnneededMuxesPass::run(obj)?;
         obj = RemoveExtraRegistersPass::run(obj)?;
@@ -29,12 +263,23 @@ pub fn compile_kernel(mut kernel: Kernel) -> Result<Object> {
         obj = PreCastLiterals::run(obj)?;
         obj = RemoveUselessCastsPass::run(obj)?;
     }
+    if options.range_checks {
+        obj = InsertRangeChecksPass::run(obj)?;
+    }
+    let obj = CheckConcatWidthsPass::run(obj)?;
     let obj = TypeCheckPass::run(obj)?;
     let obj = DataFlowCheckPass::run(obj)?;
+    let mut obj = CompactSlotsPass::run(obj)?;
+    check_signature(&obj, &signature)?;
+    if !options.preserve_names {
+        obj.symbols.slot_names.clear();
+    }
+    obj.flags = flags;
+    obj.mux_strategy = mux_strategy;
     Ok(obj)
 }
 
-fn elaborate_design(design: &mut Module) -> Result<()> {
+fn elaborate_design(design: &mut Module, options: &CompileOptions) -> Result<()> {
     // Check for any uncompiled kernels
     let external_kernels = design
         .objects
@@ -50,30 +295,90 @@ fn elaborate_design(design: &mut Module) -> Result<()> {
         .cloned()
         .collect::<Vec<_>>();
     for kernel in external_kernels {
-        if let std::collections::hash_map::Entry::Vacant(e) =
+        if let std::collections::btree_map::Entry::Vacant(e) =
             design.objects.entry(kernel.inner().fn_id)
         {
             eprintln!("Compiling kernel {}", kernel.inner().fn_id);
-            let obj = compile_kernel(kernel.clone())?;
+            let obj = compile_kernel(kernel.clone(), options)?;
             e.insert(obj);
         }
     }
     Ok(())
 }
 
+// Compile a design with the options installed by `with_compile_options`,
+// or the default options if there are none.
 pub fn compile_design(top: Kernel) -> Result<Module> {
-    let main = compile_kernel(top)?;
+    compile_design_with_options(top, installed_options())
+}
+
+pub fn compile_design_with_options(top: Kernel, options: CompileOptions) -> Result<Module> {
+    let main = compile_kernel(top, &options)?;
+    elaborate_from(main, options)
+}
+
+fn elaborate_from(main: Object, options: CompileOptions) -> Result<Module> {
     let mut design = Module {
         objects: [(main.fn_id, main.clone())].into_iter().collect(),
         top: main.fn_id,
     };
     let mut object_count = design.objects.len();
     loop {
-        elaborate_design(&mut design)?;
+        elaborate_design(&mut design, &options)?;
         if design.objects.len() == object_count {
             break;
         }
         object_count = design.objects.len();
     }
+    check_purity(&design)?;
+    specialize_calls(&mut design)?;
+    const_eval_calls(&mut design)?;
     Ok(design)
 }
+
+// Replace the arguments of the top kernel that are marked as parameters
+// with literals holding their values, and record the values in the
+// object, so that they can be emitted as module parameters.
+fn specialize(mut obj: Object, kernel: &Kernel, values: &[(&str, TypedBits)]) -> Result<Object> {
+    let params = kernel.params();
+    for (name, _) in values {
+        ensure!(
+            params.iter().any(|(_, param)| param == name),
+            "Kernel {} has no parameter named {name}",
+            obj.name
+        );
+    }
+    let mut bound = vec![];
+    for (ndx, name) in params {
+        let value = values
+            .iter()
+            .find(|(value, _)| *value == name)
+            .map(|(_, value)| value.clone())
+            .ok_or(anyhow!(
+                "No value given for parameter {name} of kernel {}",
+                obj.name
+            ))?;
+        let kind = kernel.signature().arguments[ndx].clone();
+        ensure!(
+            value.kind == kind,
+            "Parameter {name} of kernel {} is a {kind}, but was given a {}",
+            obj.name,
+            value.kind
+        );
+        obj.params.push((name, value.clone()));
+        bound.push((ndx, value));
+    }
+    Ok(bind_arguments(obj, &bound))
+}
+
+// Compile a design with the parameters of the top kernel (the inputs
+// marked with `#[rhdl(param)]`) fixed to the given values.  They are no
+// longer inputs of the design, and the operations that depend only on
+// them are folded into literals.  The kernels that it calls are compiled
+// as usual.
+pub fn compile_design_with_params(top: Kernel, values: &[(&str, TypedBits)]) -> Result<Module> {
+    let options = installed_options();
+    let obj = compile_kernel(top.clone(), &options)?;
+    let obj = fold_constants(specialize(obj, &top, values)?)?;
+    elaborate_from(obj, options)
+}
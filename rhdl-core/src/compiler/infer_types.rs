@@ -305,6 +305,16 @@ impl TypeInference {
                     self.unify(my_ty, ty_bits(len))?;
                 }
             }
+            // Signature is arithmetic_shr(self, amount: Bits<M>) -> Self
+            "arithmetic_shr" => {
+                if call.args.len() != 1 {
+                    bail!(
+                        "Wrong number of arguments to arithmetic_shr: {}",
+                        call.args.len()
+                    );
+                }
+                self.unify(my_ty, id_to_var(call.receiver.id)?)?;
+            }
             _ => {
                 bail!("Unsupported method call: {}", method_name);
             }
@@ -591,6 +601,9 @@ impl Visitor for TypeInference {
             ExprKind::ForLoop(for_loop) => {
                 self.new_scope();
                 self.bind_pattern(&for_loop.pat)?;
+                // The loop variable takes the type of the range, so that
+                // it can be used as an index without any other constraint
+                self.unify(id_to_var(for_loop.pat.id)?, id_to_var(for_loop.expr.id)?)?;
                 self.unify(my_ty, ty_empty())?;
                 visit::visit_expr(self, node)?;
                 self.end_scope();
@@ -618,6 +631,9 @@ impl Visitor for TypeInference {
             eprintln!("arm pattern binding");
             self.bind_arm_pattern(&arm_enum.pat)?;
         }
+        if let Some(guard) = &node.guard {
+            self.unify(id_to_var(guard.id)?, ty_bool())?;
+        }
         eprintln!("handle body");
         visit::visit_match_arm(self, node)?;
         eprintln!("end scope");
@@ -652,6 +668,14 @@ impl<'a> Visitor for InferenceForGenericIntegers<'a> {
                 self.context.unify(my_ty, ty_integer())?;
             }
         }
+        // A loop variable that is only used to index arrays is
+        // a usize, as it would be in Rust
+        if let ExprKind::ForLoop(for_loop) = &node.kind {
+            let pat_ty = id_to_var(for_loop.pat.id)?;
+            if let Ty::Var(_) = self.context.apply(pat_ty.clone()) {
+                self.context.unify(pat_ty, ty_usize())?;
+            }
+        }
         visit::visit_expr(self, node)
     }
     fn visit_pat(&mut self, node: &ast_impl::Pat) -> Result<()> {
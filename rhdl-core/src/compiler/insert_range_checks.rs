@@ -0,0 +1,207 @@
+// Insert assertions that the values written to ranged fields (see
+// `Kind::with_range`) lie within their bounds.  The checks are only
+// evaluated in simulation, and are enabled with the `range_checks`
+// compile option.
+
+use crate::{
+    path::{path_star, range_bounds, Path},
+    rhif::{
+        object::SourceLocation,
+        spec::{AluBinary, Assert, Binary, Enum, FieldValue, Member, OpCode, Slot, Splice, Struct},
+        Object,
+    },
+    Kind, TypedBits,
+};
+use anyhow::Result;
+
+use super::pass::Pass;
+
+pub struct InsertRangeChecksPass;
+
+impl Pass for InsertRangeChecksPass {
+    fn name(&self) -> &'static str {
+        "insert_range_checks"
+    }
+    fn description(&self) -> &'static str {
+        "Assert that values written to ranged fields are within their bounds"
+    }
+    fn run(input: Object) -> Result<Object> {
+        insert_range_checks(input)
+    }
+}
+
+// A write of `value` to the ranged field at `path` of a value of `kind`.
+struct RangedWrite {
+    kind: Kind,
+    path: Path,
+    value: Slot,
+}
+
+fn member_path(base: Path, member: &Member) -> Path {
+    match member {
+        Member::Unnamed(ndx) => base.index(*ndx as usize),
+        Member::Named(name) => base.field(name),
+    }
+}
+
+fn literal(value: i128, kind: &Kind) -> TypedBits {
+    TypedBits {
+        bits: (0..kind.bits())
+            .map(|i| (value >> i.min(127)) & 1 == 1)
+            .collect(),
+        kind: kind.clone(),
+    }
+}
+
+struct Checker {
+    obj: Object,
+    next_register: usize,
+    next_literal: usize,
+}
+
+impl Checker {
+    fn kind(&self, slot: Slot) -> Result<Kind> {
+        self.obj
+            .kind
+            .get(&slot)
+            .cloned()
+            .ok_or(anyhow::anyhow!("slot {slot} not found"))
+    }
+    fn writes(&self, op: &OpCode) -> Result<Vec<RangedWrite>> {
+        let fields = |kind: Kind, base: Path, fields: &[FieldValue]| {
+            fields
+                .iter()
+                .map(|field| RangedWrite {
+                    kind: kind.clone(),
+                    path: member_path(base.clone(), &field.member),
+                    value: field.value,
+                })
+                .collect::<Vec<_>>()
+        };
+        Ok(match op {
+            OpCode::Struct(Struct {
+                lhs, fields: vals, ..
+            }) => fields(self.kind(*lhs)?, Path::default(), vals),
+            OpCode::Enum(Enum {
+                lhs,
+                fields: vals,
+                template,
+            }) => {
+                let base = Path::default().payload_by_value(template.discriminant()?.as_i64()?);
+                fields(self.kind(*lhs)?, base, vals)
+            }
+            OpCode::Splice(Splice {
+                lhs, path, subst, ..
+            }) => {
+                // All the elements of an array share a kind, so any one of
+                // the paths a dynamic index can take gives the bounds.
+                let kind = self.kind(*lhs)?;
+                match path_star(&kind, path)?.into_iter().next() {
+                    Some(path) => vec![RangedWrite {
+                        kind,
+                        path,
+                        value: *subst,
+                    }],
+                    None => vec![],
+                }
+            }
+            _ => vec![],
+        })
+    }
+    fn register(&mut self, kind: Kind) -> Slot {
+        let slot = Slot::Register(self.next_register);
+        self.next_register += 1;
+        self.obj.kind.insert(slot, kind);
+        slot
+    }
+    fn literal(&mut self, value: TypedBits) -> Slot {
+        let slot = Slot::Literal(self.next_literal);
+        self.next_literal += 1;
+        self.obj.kind.insert(slot, value.kind.clone());
+        self.obj.literals.insert(slot, value);
+        slot
+    }
+    // The opcodes that check a single write, if the field written is
+    // ranged and the bounds are narrower than those of its kind.
+    fn check(&mut self, write: RangedWrite) -> Result<Vec<OpCode>> {
+        let Some((min, max)) = range_bounds(&write.kind, &write.path)? else {
+            return Ok(vec![]);
+        };
+        let value_kind = self.kind(write.value)?;
+        let Some((lo, hi)) = value_kind.integer_bounds() else {
+            return Ok(vec![]);
+        };
+        let mut ops = vec![];
+        let mut conds = vec![];
+        for (bound, op, needed) in [
+            (min, AluBinary::Ge, min > lo),
+            (max, AluBinary::Le, max < hi),
+        ] {
+            if needed {
+                let arg2 = self.literal(literal(bound, &value_kind));
+                let lhs = self.register(Kind::make_bool());
+                ops.push(OpCode::Binary(Binary {
+                    op,
+                    lhs,
+                    arg1: write.value,
+                    arg2,
+                }));
+                conds.push(lhs);
+            }
+        }
+        let cond = match conds[..] {
+            [] => return Ok(vec![]),
+            [cond] => cond,
+            [arg1, arg2] => {
+                let lhs = self.register(Kind::make_bool());
+                ops.push(OpCode::Binary(Binary {
+                    op: AluBinary::BitAnd,
+                    lhs,
+                    arg1,
+                    arg2,
+                }));
+                lhs
+            }
+            _ => unreachable!(),
+        };
+        ops.push(OpCode::Assert(Assert {
+            cond,
+            message: format!(
+                "{}{} is outside of the range {min}..={max}",
+                write.kind.get_name(),
+                write.path
+            ),
+        }));
+        Ok(ops)
+    }
+}
+
+fn insert_range_checks(obj: Object) -> Result<Object> {
+    let next_register = obj.reg_max_index() + 1;
+    let next_literal = obj.literal_max_index() + 1;
+    let mut checker = Checker {
+        obj,
+        next_register,
+        next_literal,
+    };
+    let ops = std::mem::take(&mut checker.obj.ops);
+    let opcode_map = std::mem::take(&mut checker.obj.symbols.opcode_map);
+    let mut new_ops = vec![];
+    let mut new_map: Vec<SourceLocation> = vec![];
+    for (ndx, op) in ops.into_iter().enumerate() {
+        let location = opcode_map.get(ndx).copied();
+        let mut checks = vec![];
+        for write in checker.writes(&op)? {
+            checks.extend(checker.check(write)?);
+        }
+        // The checks come after the op, so that they are next to the
+        // write in the listing.
+        for op in std::iter::once(op).chain(checks) {
+            new_ops.push(op);
+            new_map.extend(location);
+        }
+    }
+    checker.obj.ops = new_ops;
+    checker.obj.symbols.opcode_map = new_map;
+    Ok(checker.obj)
+}
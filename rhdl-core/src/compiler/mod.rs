@@ -3,6 +3,13 @@ pub use compiler_impl::compile;
 pub mod driver;
 mod infer_types;
 pub use driver::compile_design;
+pub use driver::compile_design_with_options;
+pub use driver::compile_design_with_params;
+pub use driver::CompileOptions;
+pub use driver::compile_flag;
+pub use driver::expect_compile_flag;
+pub use driver::known_compile_flag;
+pub use driver::with_compile_options;
 pub(crate) use infer_types::infer;
 pub mod ty;
 mod unify;
@@ -10,10 +17,17 @@ pub(crate) use unify::UnifyContext;
 mod assign_node;
 pub(crate) use assign_node::assign_node_ids;
 mod ascii;
+mod check_concat_widths;
 pub(crate) mod check_inference;
+mod check_purity;
+pub(crate) mod check_signature;
 pub(crate) mod check_rhif_flow;
 pub(crate) mod check_rhif_type;
+mod compact_slots;
+mod const_eval_calls;
+mod constant_fold;
 mod display_ast;
+mod insert_range_checks;
 mod lower_index_to_copy;
 mod pass;
 mod pre_cast_literals;
@@ -21,4 +35,8 @@ mod remove_extra_registers;
 mod remove_unneeded_muxes;
 mod remove_unused_literals;
 mod remove_useless_casts;
-mod utils;
+mod specialize_calls;
+mod strip_cfg;
+mod sub_to_add;
+pub(crate) mod utils;
+pub mod verify_pass;
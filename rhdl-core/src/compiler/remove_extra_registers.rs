@@ -12,9 +12,12 @@ use super::pass::Pass;
 #[derive(Default, Debug, Clone)]
 pub struct RemoveExtraRegistersPass {}
 
-fn find_assign_op(ops: &[OpCode]) -> Option<OpCode> {
-    ops.iter()
-        .find(|op| matches!(op, OpCode::Assign(_)))
+// Assignments to a probe are kept, since the probe needs a register of
+// its own.
+fn find_assign_op(obj: &Object) -> Option<OpCode> {
+    obj.ops
+        .iter()
+        .find(|op| matches!(op, OpCode::Assign(assign) if !obj.is_probed(assign.lhs)))
         .cloned()
 }
 
@@ -26,7 +29,7 @@ impl Pass for RemoveExtraRegistersPass {
         "Remove extra registers (any instance of r3 <- r2, is replaced with renaming all instances of r3 to r2)"
     }
     fn run(mut input: Object) -> Result<Object> {
-        while let Some(op) = find_assign_op(&input.ops) {
+        while let Some(op) = find_assign_op(&input) {
             eprintln!("Found assign op {}", op);
             if let OpCode::Assign(assign) = op {
                 input.ops = input
@@ -58,6 +61,13 @@ impl Pass for RemoveExtraRegistersPass {
                         _ => op,
                     })
                     .collect();
+                // The name of a binding moves to the register it was
+                // assigned from, if that has none of its own
+                if let Some(name) = input.symbols.slot_names.remove(&assign.lhs) {
+                    if assign.rhs.is_reg() && !input.arguments.contains(&assign.rhs) {
+                        input.symbols.slot_names.entry(assign.rhs).or_insert(name);
+                    }
+                }
                 // Delete the register from the register map
                 input.symbols.slot_map.remove(&assign.lhs);
                 input.kind.remove(&assign.lhs);
@@ -0,0 +1,184 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    ast::ast_impl::FunctionId,
+    rhif::{
+        spec::{Exec, ExternalFunction, ExternalFunctionCode, FuncId, OpCode, Slot},
+        Object,
+    },
+    DigitalSignature, Module, TypedBits,
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    check_rhif_flow::DataFlowCheckPass, check_rhif_type::TypeCheckPass,
+    compact_slots::CompactSlotsPass, const_eval_calls::remove_unused_externals,
+    constant_fold::ConstantFoldPass, pass::Pass, remove_extra_registers::RemoveExtraRegistersPass,
+    remove_unneeded_muxes::RemoveUnneededMuxesPass, remove_unused_literals::RemoveUnusedLiterals,
+    sub_to_add::SubToAddPass, utils::remap_slots,
+};
+
+// Replace the arguments of an object at the given positions with
+// literals holding the given values.  The arguments are removed from the
+// object, and the rest keep their order.
+pub(crate) fn bind_arguments(mut obj: Object, values: &[(usize, TypedBits)]) -> Object {
+    let mut next_literal = obj
+        .literals
+        .keys()
+        .chain(obj.kind.keys())
+        .filter_map(|slot| match slot {
+            Slot::Literal(ndx) => Some(ndx + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or_default();
+    let mut renames: HashMap<Slot, Slot> = Default::default();
+    let mut arguments = vec![];
+    for (ndx, slot) in obj.arguments.clone().into_iter().enumerate() {
+        let Some((_, value)) = values.iter().find(|(position, _)| *position == ndx) else {
+            arguments.push(slot);
+            continue;
+        };
+        if !slot.is_empty() {
+            let literal = Slot::Literal(next_literal);
+            next_literal += 1;
+            obj.kind.remove(&slot);
+            obj.symbols.slot_map.remove(&slot);
+            obj.symbols.slot_names.remove(&slot);
+            obj.kind.insert(literal, value.kind.clone());
+            obj.literals.insert(literal, value.clone());
+            renames.insert(slot, literal);
+        }
+    }
+    obj.arguments = arguments;
+    let rename = |slot: Slot| renames.get(&slot).copied().unwrap_or(slot);
+    obj.ops = std::mem::take(&mut obj.ops)
+        .into_iter()
+        .map(|op| remap_slots(op, rename))
+        .collect();
+    obj.return_slot = rename(obj.return_slot);
+    obj
+}
+
+// Fold the operations of an object that depend only on its literals.
+pub(crate) fn fold_constants(mut obj: Object) -> Result<Object> {
+    obj = SubToAddPass::run(obj)?;
+    obj = ConstantFoldPass::run(obj)?;
+    obj = RemoveUnneededMuxesPass::run(obj)?;
+    obj = RemoveExtraRegistersPass::run(obj)?;
+    obj = RemoveUnusedLiterals::run(obj)?;
+    let obj = TypeCheckPass::run(obj)?;
+    let obj = DataFlowCheckPass::run(obj)?;
+    CompactSlotsPass::run(obj)
+}
+
+// The specialized copy of a kernel for the given argument values is
+// keyed by the kernel and the values, so that calls with the same
+// constants share it.
+fn specialized_id(fn_id: FunctionId, values: &[(usize, TypedBits)]) -> FunctionId {
+    let mut hasher = fnv::FnvHasher::default();
+    fn_id.hash(&mut hasher);
+    values.hash(&mut hasher);
+    hasher.finish().into()
+}
+
+// Redirect the calls of the object that pass literal arguments to copies
+// of the kernels they call, specialized for those literals.  Returns true
+// if any call was changed.
+fn specialize_object_calls(design: &mut Module, obj: &mut Object) -> Result<bool> {
+    let mut changed = false;
+    for ndx in 0..obj.ops.len() {
+        let OpCode::Exec(Exec { lhs, id, args }) = obj.ops[ndx].clone() else {
+            continue;
+        };
+        let func = obj.externals[id.0].clone();
+        let ExternalFunctionCode::Kernel(kernel) = &func.code else {
+            continue;
+        };
+        let values = args
+            .iter()
+            .enumerate()
+            .filter(|(_, arg)| arg.is_literal())
+            .map(|(position, arg)| Ok((position, obj.literal(*arg)?.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        // A call with only literal arguments is left to `const_eval`,
+        // which decides if it can be evaluated at compile time.  (And a
+        // Verilog function needs at least one input.)
+        if values.is_empty() || values.len() == args.len() {
+            continue;
+        }
+        let callee_id = kernel.inner().fn_id;
+        let fn_id = specialized_id(callee_id, &values);
+        if !design.objects.contains_key(&fn_id) {
+            let callee = design
+                .objects
+                .get(&callee_id)
+                .ok_or(anyhow!("Function {callee_id} not found"))?
+                .clone();
+            let mut specialized = fold_constants(bind_arguments(callee, &values))?;
+            specialized.fn_id = fn_id;
+            design.objects.insert(fn_id, specialized);
+        }
+        let mut kernel = kernel.clone();
+        kernel.inner_mut().fn_id = fn_id;
+        let signature = DigitalSignature {
+            arguments: func
+                .signature
+                .arguments
+                .iter()
+                .zip(&args)
+                .filter(|(_, arg)| !arg.is_literal())
+                .map(|(kind, _)| kind.clone())
+                .collect(),
+            ret: func.signature.ret.clone(),
+        };
+        obj.externals.push(ExternalFunction {
+            path: func.path.clone(),
+            code: ExternalFunctionCode::Kernel(kernel),
+            signature,
+        });
+        obj.ops[ndx] = OpCode::Exec(Exec {
+            lhs,
+            id: FuncId(obj.externals.len() - 1),
+            args: args.into_iter().filter(|arg| !arg.is_literal()).collect(),
+        });
+        changed = true;
+    }
+    Ok(changed)
+}
+
+// Constants passed from one kernel to another are propagated into the
+// callee: a call with literal arguments calls a copy of the kernel in
+// which those arguments are literals, and the logic that depends only on
+// them is folded.  The copies are specialized in turn, so constants are
+// carried down the call tree.  The kernels that are no longer called are
+// left in the design, to be removed with the rest of the dead kernels.
+pub(crate) fn specialize_calls(design: &mut Module) -> Result<()> {
+    let mut queue = vec![design.top];
+    let mut visited = HashSet::new();
+    while let Some(fn_id) = queue.pop() {
+        if !visited.insert(fn_id) {
+            continue;
+        }
+        let mut obj = design
+            .objects
+            .get(&fn_id)
+            .ok_or(anyhow!("Function {fn_id} not found"))?
+            .clone();
+        if specialize_object_calls(design, &mut obj)? {
+            remove_unused_externals(&mut obj);
+            obj = RemoveUnusedLiterals::run(obj)?;
+            obj = TypeCheckPass::run(obj)?;
+            obj = DataFlowCheckPass::run(obj)?;
+            obj = CompactSlotsPass::run(obj)?;
+            design.objects.insert(fn_id, obj.clone());
+        }
+        queue.extend(obj.externals.iter().filter_map(|func| match &func.code {
+            ExternalFunctionCode::Kernel(kernel) => Some(kernel.inner().fn_id),
+            _ => None,
+        }));
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+// Remove the statements and match arms marked `#[rhdl(cfg(flag = "..."))]`
+// whose flag is not enabled.  This runs before type inference, so the
+// code that is removed has only been checked by the Rust compiler.
+use std::collections::BTreeMap;
+
+use crate::{
+    ast::ast_impl::{Block, Expr, ExprKind},
+    ast::visit_mut::{self, VisitorMut},
+    kernel::Kernel,
+};
+use anyhow::{anyhow, Result};
+
+struct StripCfg<'a> {
+    flags: &'a BTreeMap<String, bool>,
+    used: BTreeMap<String, bool>,
+}
+
+impl StripCfg<'_> {
+    fn keep(&mut self, cfg: &Option<String>) -> Result<bool> {
+        let Some(flag) = cfg else {
+            return Ok(true);
+        };
+        let enabled = *self
+            .flags
+            .get(flag)
+            .ok_or_else(|| anyhow!("Unknown flag {flag} in #[rhdl(cfg)] attribute"))?;
+        self.used.insert(flag.clone(), enabled);
+        Ok(enabled)
+    }
+}
+
+impl VisitorMut for StripCfg<'_> {
+    fn visit_mut_block(&mut self, node: &mut Block) -> Result<()> {
+        let mut stmts = vec![];
+        for stmt in std::mem::take(&mut node.stmts) {
+            if self.keep(&stmt.cfg)? {
+                stmts.push(stmt);
+            }
+        }
+        node.stmts = stmts;
+        visit_mut::visit_mut_block(self, node)
+    }
+    fn visit_mut_expr(&mut self, node: &mut Expr) -> Result<()> {
+        if let ExprKind::Match(expr_match) = &mut node.kind {
+            let mut arms = vec![];
+            for arm in std::mem::take(&mut expr_match.arms) {
+                if self.keep(&arm.cfg)? {
+                    arms.push(arm);
+                }
+            }
+            expr_match.arms = arms;
+        }
+        visit_mut::visit_mut_expr(self, node)
+    }
+}
+
+// Strip the kernel for the given flags, and return the flags that it
+// refers to, along with whether each was enabled.  Flags that are not
+// in `flags` are an error.
+pub(crate) fn strip_cfg(
+    kernel: &mut Kernel,
+    flags: &BTreeMap<String, bool>,
+) -> Result<BTreeMap<String, bool>> {
+    let mut stripper = StripCfg {
+        flags,
+        used: BTreeMap::new(),
+    };
+    stripper.visit_mut_kernel_fn(kernel.inner_mut())?;
+    Ok(stripper.used)
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+
+use crate::{
+    rhif::{
+        spec::{AluBinary, Binary, OpCode, Slot},
+        Object,
+    },
+    TypedBits,
+};
+
+use super::pass::Pass;
+
+#[derive(Default, Debug, Clone)]
+pub struct SubToAddPass {}
+
+// The two's complement of the literal, in the same number of bits (so
+// that `x - c` and `x + (-c)` wrap the same way)
+fn negate(value: &TypedBits) -> TypedBits {
+    let mut carry = true;
+    let bits = value
+        .bits
+        .iter()
+        .map(|bit| {
+            let sum = !bit ^ carry;
+            carry &= !bit;
+            sum
+        })
+        .collect();
+    TypedBits {
+        bits,
+        kind: value.kind.clone(),
+    }
+}
+
+impl Pass for SubToAddPass {
+    fn name(&self) -> &'static str {
+        "sub_to_add"
+    }
+    fn description(&self) -> &'static str {
+        "Replace the subtraction of a literal with the addition of its negative"
+    }
+    fn run(mut input: Object) -> Result<Object> {
+        let mut next_literal = input
+            .literals
+            .keys()
+            .chain(input.kind.keys())
+            .filter_map(|slot| match slot {
+                Slot::Literal(ndx) => Some(ndx + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        for op in input.ops.iter_mut() {
+            let OpCode::Binary(Binary {
+                op: AluBinary::Sub,
+                lhs,
+                arg1,
+                arg2,
+            }) = op
+            else {
+                continue;
+            };
+            let Some(value) = input.literals.get(arg2) else {
+                continue;
+            };
+            if !value.kind.is_unsigned() && !value.kind.is_signed() {
+                continue;
+            }
+            let value = negate(value);
+            let literal = Slot::Literal(next_literal);
+            next_literal += 1;
+            input.kind.insert(literal, value.kind.clone());
+            input.literals.insert(literal, value);
+            *op = OpCode::Binary(Binary {
+                op: AluBinary::Add,
+                lhs: *lhs,
+                arg1: *arg1,
+                arg2: literal,
+            });
+        }
+        Ok(input)
+    }
+}
@@ -255,6 +255,8 @@ impl From<Kind> for Ty {
             Kind::Bits(width) => ty_bits(width),
             Kind::Signed(width) => ty_signed(width),
             Kind::Empty => ty_empty(),
+            // Ranges are not part of the type, only of the layout metadata
+            Kind::Ranged(r) => (*r.base).into(),
             Kind::Struct(struct_) => Ty::Struct(TyMap {
                 name: struct_.name,
                 fields: struct_
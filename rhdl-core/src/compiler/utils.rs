@@ -1,6 +1,6 @@
 use crate::rhif::spec::{
-    Array, Assign, Binary, Case, Cast, Enum, Exec, FieldValue, Index, OpCode, Repeat, Select, Slot,
-    Splice, Struct, Tuple, Unary,
+    Array, Assert, Assign, Binary, Case, Cast, Enum, Exec, FieldValue, Index, OpCode, Repeat,
+    Select, Slot, Splice, Struct, Tuple, Unary,
 };
 
 pub fn remap_slots<F: FnMut(Slot) -> Slot>(op: OpCode, mut f: F) -> OpCode {
@@ -124,6 +124,10 @@ pub fn remap_slots<F: FnMut(Slot) -> Slot>(op: OpCode, mut f: F) -> OpCode {
             arg: f(arg),
             len,
         }),
+        OpCode::Assert(Assert { cond, message }) => OpCode::Assert(Assert {
+            cond: f(cond),
+            message,
+        }),
         _ => op,
     }
 }
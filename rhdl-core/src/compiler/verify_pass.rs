@@ -0,0 +1,295 @@
+// Differential testing of the passes.  A pass must not change what an
+// object computes, so running the object through the RHIF interpreter
+// before and after the pass must give the same outputs for the same
+// arguments.
+//
+// The corpus in `PASS_CORPUS` holds designs taken from real kernels,
+// including the ones behind earlier optimizer bugs.  Each entry is a
+// design in JSON, whose top object is the RHIF of the kernel as the
+// optimizer first sees it (from `compile_kernel_unoptimized`), so the
+// entries do not change when the lowering from the AST does.  Every pass
+// is run on the top object in the order that the driver uses, and each
+// step is checked against randomized arguments.  To add to the corpus,
+// add the kernel to `rhdl/src/test_passes.rs` and run its ignored
+// `update_pass_corpus` test.
+use crate::{
+    compiler::{
+        check_concat_widths::CheckConcatWidthsPass,
+        check_rhif_flow::DataFlowCheckPass,
+        check_rhif_type::TypeCheckPass,
+        compact_slots::CompactSlotsPass,
+        driver::{compile_design_with_options, compile_kernel_unoptimized},
+        insert_range_checks::InsertRangeChecksPass,
+        lower_index_to_copy::LowerIndexToCopy,
+        pass::Pass,
+        pre_cast_literals::PreCastLiterals,
+        remove_extra_registers::RemoveExtraRegistersPass,
+        remove_unneeded_muxes::RemoveUnneededMuxesPass,
+        remove_unused_literals::RemoveUnusedLiterals,
+        remove_useless_casts::RemoveUselessCastsPass,
+        sub_to_add::SubToAddPass,
+        CompileOptions,
+    },
+    kernel::Kernel,
+    rhif::{vm::execute_function, Object},
+    Module, TypedBits,
+};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+pub const PASS_CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/corpus/passes");
+
+type PassFn = fn(Object) -> Result<Object>;
+
+// The passes in the order that `compile_kernel_uncached` runs them.  The
+// range checks are always inserted here, since they must not change the
+// outputs for arguments that are in range.  Index lowering is not part
+// of the pipeline yet, so it runs last.
+fn pipeline() -> Vec<(&'static str, PassFn)> {
+    let optimize: [(&'static str, PassFn); 7] = [
+        ("SubToAddPass", SubToAddPass::run),
+        ("RemoveExtraRegistersPass", RemoveExtraRegistersPass::run),
+        ("RemoveUnneededMuxesPass", RemoveUnneededMuxesPass::run),
+        ("RemoveExtraRegistersPass", RemoveExtraRegistersPass::run),
+        ("RemoveUnusedLiterals", RemoveUnusedLiterals::run),
+        ("PreCastLiterals", PreCastLiterals::run),
+        ("RemoveUselessCastsPass", RemoveUselessCastsPass::run),
+    ];
+    let mut passes = [optimize, optimize].concat();
+    passes.extend([
+        (
+            "InsertRangeChecksPass",
+            InsertRangeChecksPass::run as PassFn,
+        ),
+        ("CheckConcatWidthsPass", CheckConcatWidthsPass::run),
+        ("TypeCheckPass", TypeCheckPass::run),
+        ("DataFlowCheckPass", DataFlowCheckPass::run),
+        ("CompactSlotsPass", CompactSlotsPass::run),
+        ("LowerIndexToCopy", LowerIndexToCopy::run),
+    ]);
+    passes
+}
+
+fn show(args: &[TypedBits]) -> String {
+    args.iter()
+        .map(|arg| arg.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn verify_step(
+    name: &str,
+    run: PassFn,
+    design: &Module,
+    inputs: impl Iterator<Item = Vec<TypedBits>>,
+) -> Result<Module> {
+    let obj = design
+        .objects
+        .get(&design.top)
+        .ok_or(anyhow!("Top function {} not found", design.top))?;
+    let func = obj.name.clone();
+    let optimized = run(obj.clone()).with_context(|| format!("Pass {name} failed on {func}"))?;
+    let mut after = design.clone();
+    after.objects.insert(design.top, optimized);
+    for args in inputs {
+        let expected = execute_function(design, args.clone());
+        let actual = execute_function(&after, args.clone());
+        match (expected, actual) {
+            (Ok(expected), Ok(actual)) => ensure!(
+                expected == actual,
+                "Pass {name} changed the result of {func}({}) from {expected} to {actual}",
+                show(&args)
+            ),
+            (Err(_), Err(_)) => {}
+            (Ok(expected), Err(err)) => bail!(
+                "Pass {name} made {func}({}) fail with {err}, rather than return {expected}",
+                show(&args)
+            ),
+            (Err(err), Ok(actual)) => bail!(
+                "Pass {name} made {func}({}) return {actual}, rather than fail with {err}",
+                show(&args)
+            ),
+        }
+    }
+    Ok(after)
+}
+
+// Check that running `P` on the object does not change its result for
+// any of the given arguments.  The object must not call other kernels,
+// use `verify_pass_in_design` for those.
+pub fn verify_pass<P: Pass>(
+    obj: &Object,
+    inputs: impl Iterator<Item = Vec<TypedBits>>,
+) -> Result<()> {
+    let design = Module {
+        objects: [(obj.fn_id, obj.clone())].into_iter().collect(),
+        top: obj.fn_id,
+    };
+    verify_pass_in_design::<P>(&design, inputs).map(|_| ())
+}
+
+// As `verify_pass`, but for the top object of a design, so that the
+// kernels it calls can be executed too.  Returns the design with the
+// pass applied to the top object.
+pub fn verify_pass_in_design<P: Pass>(
+    design: &Module,
+    inputs: impl Iterator<Item = Vec<TypedBits>>,
+) -> Result<Module> {
+    verify_step(std::any::type_name::<P>(), P::run, design, inputs)
+}
+
+// Run the whole pipeline on the top object of the design, checking each
+// pass against the given arguments.
+pub fn verify_passes(design: &Module, inputs: &[Vec<TypedBits>]) -> Result<()> {
+    let mut design = design.clone();
+    for (name, run) in pipeline() {
+        design = verify_step(name, run, &design, inputs.iter().cloned())?;
+    }
+    Ok(())
+}
+
+// The design for a kernel as the optimizer first sees it.  The kernels
+// that it calls are compiled as usual.
+pub fn unoptimized_design(kernel: Kernel) -> Result<Module> {
+    let options = CompileOptions::default();
+    let mut design = compile_design_with_options(kernel.clone(), options.clone())?;
+    let obj = compile_kernel_unoptimized(kernel, &options)?;
+    design.objects.insert(design.top, obj);
+    Ok(design)
+}
+
+// Arguments for the object, drawn at random (but the same on every run)
+// from the kinds of its arguments.
+#[cfg(feature = "proptest")]
+pub fn random_arguments(obj: &Object, count: usize) -> Result<Vec<Vec<TypedBits>>> {
+    use crate::types::arbitrary::arbitrary_typed_bits;
+    use proptest::strategy::{Strategy, ValueTree};
+    use proptest::test_runner::TestRunner;
+
+    let kinds = obj
+        .arguments
+        .iter()
+        .map(|slot| {
+            obj.kind
+                .get(slot)
+                .cloned()
+                .ok_or(anyhow!("No kind for argument {slot} in {}", obj.name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut runner = TestRunner::deterministic();
+    (0..count)
+        .map(|_| {
+            kinds
+                .iter()
+                .map(|kind| {
+                    arbitrary_typed_bits(kind.clone())
+                        .new_tree(&mut runner)
+                        .map(|tree| tree.current())
+                        .map_err(|err| anyhow!("{err}"))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Run every pass on a corpus entry with `count` random arguments.
+#[cfg(feature = "proptest")]
+pub fn verify_corpus_entry(design: &Module, count: usize) -> Result<()> {
+    let top = design
+        .objects
+        .get(&design.top)
+        .ok_or(anyhow!("Top function {} not found", design.top))?;
+    let inputs = random_arguments(top, count)?;
+    verify_passes(design, &inputs).with_context(|| format!("In corpus entry {}", top.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        rhif::{
+            object::ObjectBuilder,
+            spec::{AluBinary, Binary, OpCode, Slot},
+        },
+        Digital, Kind,
+    };
+    use rhdl_bits::Bits;
+
+    // fn diff(a: b4, b: b4) -> b4 { a - b }
+    fn diff() -> Object {
+        ObjectBuilder::new("diff", "fn diff(a: b4, b: b4) -> b4 { a - b }")
+            .registers(vec![Kind::make_bits(4); 3])
+            .arguments([Slot::Register(0), Slot::Register(1)])
+            .op(OpCode::Binary(Binary {
+                op: AluBinary::Sub,
+                lhs: Slot::Register(2),
+                arg1: Slot::Register(0),
+                arg2: Slot::Register(1),
+            }))
+            .returns(Slot::Register(2))
+            .build()
+    }
+
+    fn exhaustive() -> impl Iterator<Item = Vec<TypedBits>> {
+        (0..16).flat_map(|a| {
+            (0..16).map(move |b| vec![Bits::<4>(a).typed_bits(), Bits::<4>(b).typed_bits()])
+        })
+    }
+
+    // A mutant of an optimizing pass, that gets the operation wrong.
+    struct SubToAddPass;
+
+    impl Pass for SubToAddPass {
+        fn name(&self) -> &'static str {
+            "sub_to_add"
+        }
+        fn description(&self) -> &'static str {
+            "Replace subtraction with addition (deliberately broken)"
+        }
+        fn run(mut input: Object) -> Result<Object> {
+            for op in input.ops.iter_mut() {
+                if let OpCode::Binary(binary) = op {
+                    if binary.op == AluBinary::Sub {
+                        binary.op = AluBinary::Add;
+                    }
+                }
+            }
+            Ok(input)
+        }
+    }
+
+    #[test]
+    fn test_broken_pass_is_caught() {
+        let obj = diff();
+        // The real passes leave it alone
+        let design = Module {
+            objects: [(obj.fn_id, obj.clone())].into_iter().collect(),
+            top: obj.fn_id,
+        };
+        verify_passes(&design, &exhaustive().collect::<Vec<_>>()).unwrap();
+        let err = verify_pass::<SubToAddPass>(&obj, exhaustive())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("SubToAddPass changed the result of"));
+        // 0 - 0 and 0 + 0 agree, so it is the second case that fails
+        assert!(err.contains("diff(0_b4, 1_b4) from f_b4 to 1_b4"), "{err}");
+    }
+
+    #[cfg(feature = "proptest")]
+    #[test]
+    fn test_pass_corpus() {
+        let mut entries = std::fs::read_dir(PASS_CORPUS)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert!(!entries.is_empty());
+        for path in entries {
+            let json = std::fs::read_to_string(&path).unwrap();
+            let design: Module = serde_json::from_str(&json).unwrap();
+            if let Err(err) = verify_corpus_entry(&design, 64) {
+                panic!("{}: {err:#}", path.display());
+            }
+        }
+    }
+}
@@ -1,8 +1,15 @@
-use miette::Report;
+use miette::{MietteDiagnostic, Report};
 
+use super::pin_is_clocked::get_clock_id_for_pin;
+
+// The output of a clocked component changes only on its clock, whatever
+// the timing of its inputs.  So it starts a new synchronous source, and
+// everything downstream of it is in the domain of that clock.
 pub(crate) fn check_output_is_synchronous(
-    is: &crate::crusty::index::IndexedSchematic,
+    is: &mut crate::crusty::index::IndexedSchematic,
     c: &crate::schematic::constraints::OutputSynchronousConstraint,
 ) -> Result<(), Report> {
-    Ok(())
+    let clock_id = get_clock_id_for_pin(is, &c.clock)?;
+    is.add_synchronous_source(c.output.clone(), clock_id)
+        .map_err(|err| Report::new(MietteDiagnostic::new(err.to_string())))
 }
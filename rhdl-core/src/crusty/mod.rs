@@ -43,6 +43,10 @@ pub fn check_schematic(is: &mut IndexedSchematic) -> Vec<miette::Report> {
                 Constraint::InputSynchronous(c) => {
                     checks::input_is_synchronous::check_input_is_synchronous(is, c)
                 }
+                // The input may have any timing, so only the clock is checked
+                Constraint::DomainCrossing(c) => {
+                    checks::pin_is_clocked::get_clock_id_for_pin(is, &c.clock).map(|_| ())
+                }
             }
             .err()
         })
@@ -24,7 +24,6 @@ fn upstream_array(array: &ArrayComponent, output: PinPath) -> Result<Vec<PinPath
             pin: *upstream.1,
             path: output
                 .path
-                .clone()
                 .strip_prefix(&Path::default().index(upstream.0))?,
         }])
     } else {
@@ -81,7 +80,7 @@ fn upstream_enum(e: &EnumComponent, output: PinPath) -> Result<Vec<PinPath>> {
     }) {
         Ok(vec![PinPath {
             pin: field.pin,
-            path: output.path.clone().strip_prefix(&path_with_member(
+            path: output.path.strip_prefix(&path_with_member(
                 Path::default().payload_by_value(discriminant),
                 &field.member,
             ))?,
@@ -107,10 +106,7 @@ fn upstream_repeat(r: &RepeatComponent, output: PinPath) -> Result<Vec<PinPath>>
     {
         Ok(vec![PinPath {
             pin: r.value,
-            path: output
-                .path
-                .clone()
-                .strip_prefix(&Path::default().index(pin))?,
+            path: output.path.strip_prefix(&Path::default().index(pin))?,
         }])
     } else {
         Ok(vec![])
@@ -141,7 +137,7 @@ fn upstream_splice(s: &SpliceComponent, output: PinPath) -> Result<Vec<PinPath>>
         if output_path_in_replacement {
             upstreams.push(PinPath {
                 pin: s.subst,
-                path: output.path.clone().strip_prefix(&s_path)?,
+                path: output.path.strip_prefix(&s_path)?,
             });
         }
     }
@@ -189,10 +185,7 @@ fn upstream_tuple(t: &TupleComponent, output: PinPath) -> Result<Vec<PinPath>> {
     {
         Ok(vec![PinPath {
             pin: *field.1,
-            path: output
-                .path
-                .clone()
-                .strip_prefix(&Path::default().index(field.0))?,
+            path: output.path.strip_prefix(&Path::default().index(field.0))?,
         }])
     } else {
         Ok(vec![])
@@ -0,0 +1,172 @@
+// Support for a quick edit-compile-check loop.  A `DesignWatcher` rebuilds
+// a design on request (reusing the kernels that did not change), and
+// reports how the new design differs from the previous build.  Deciding
+// when to rebuild (e.g., watching the source files) is left to the caller.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+
+use anyhow::Result;
+
+use crate::codegen::verilog::generate_verilog_functions;
+use crate::compiler::driver::{with_compile_cache, CompileCache};
+use crate::rhif::spec::ExternalFunctionCode;
+use crate::rhif::Object;
+use crate::Module;
+
+// The difference between two builds of a design.  Functions are
+// identified by name, and are considered changed when their compiled
+// RHIF differs.  `verilog` names the generated Verilog functions whose
+// text is new in this build.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DesignDelta {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub verilog: Vec<String>,
+}
+
+impl DesignDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.verilog.is_empty()
+    }
+}
+
+impl std::fmt::Display for DesignDelta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No changes");
+        }
+        for (label, names) in [
+            ("Added", &self.added),
+            ("Removed", &self.removed),
+            ("Changed", &self.changed),
+            ("Verilog", &self.verilog),
+        ] {
+            if !names.is_empty() {
+                writeln!(f, "{label}: {}", names.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A hash of the compiled code of a function.  Calls to other kernels are
+// hashed by name only, so that a change to a callee does not show up as a
+// change to its callers.
+pub(crate) fn content_hash(obj: &Object) -> u64 {
+    let mut hasher = fnv::FnvHasher::default();
+    format!("{:?}", obj.ops).hash(&mut hasher);
+    format!("{:?}", obj.kind).hash(&mut hasher);
+    format!("{:?}", obj.literals).hash(&mut hasher);
+    format!("{:?}", obj.arguments).hash(&mut hasher);
+    format!("{:?}", obj.return_slot).hash(&mut hasher);
+    obj.pure.hash(&mut hasher);
+    obj.const_eval.hash(&mut hasher);
+    obj.mux_strategy.hash(&mut hasher);
+    for func in &obj.externals {
+        func.path.hash(&mut hasher);
+        match &func.code {
+            ExternalFunctionCode::Kernel(kernel) => kernel.inner().name.hash(&mut hasher),
+            ExternalFunctionCode::Extern(def) => {
+                def.name.hash(&mut hasher);
+                def.body.hash(&mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+#[derive(Default)]
+struct Snapshot {
+    // Content hashes of the functions with each name.  Generic kernels
+    // can give several functions with the same name.
+    functions: BTreeMap<String, Vec<u64>>,
+    // The generated Verilog, keyed by the Verilog function name.
+    verilog: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    fn new(design: &Module) -> Result<Self> {
+        let mut snapshot = Snapshot::default();
+        for obj in design.objects.values() {
+            snapshot
+                .functions
+                .entry(obj.name.clone())
+                .or_default()
+                .push(content_hash(obj));
+        }
+        for hashes in snapshot.functions.values_mut() {
+            hashes.sort();
+        }
+        for (fn_id, text) in generate_verilog_functions(design)? {
+            snapshot.verilog.insert(design.func_name(fn_id)?, text);
+        }
+        Ok(snapshot)
+    }
+}
+
+fn diff(previous: &Snapshot, current: &Snapshot) -> DesignDelta {
+    let mut delta = DesignDelta::default();
+    for (name, hashes) in &current.functions {
+        match previous.functions.get(name) {
+            None => delta.added.push(name.clone()),
+            Some(old) if old != hashes => delta.changed.push(name.clone()),
+            _ => {}
+        }
+    }
+    delta.removed = previous
+        .functions
+        .keys()
+        .filter(|name| !current.functions.contains_key(*name))
+        .cloned()
+        .collect();
+    let old_verilog = previous.verilog.values().collect::<BTreeSet<_>>();
+    delta.verilog = current
+        .verilog
+        .iter()
+        .filter(|(_, text)| !old_verilog.contains(text))
+        .map(|(name, _)| name.clone())
+        .collect();
+    delta
+}
+
+pub struct DesignWatcher {
+    build_fn: Box<dyn Fn() -> Result<Module>>,
+    cache: CompileCache,
+    previous: Option<Snapshot>,
+    design: Option<Module>,
+}
+
+impl DesignWatcher {
+    pub fn new(build_fn: impl Fn() -> Result<Module> + 'static) -> Self {
+        Self {
+            build_fn: Box::new(build_fn),
+            cache: CompileCache::default(),
+            previous: None,
+            design: None,
+        }
+    }
+    // Rebuild the design, and report how it differs from the previous
+    // build.  On the first call, every function is reported as added.
+    // If the build fails, the previous build is kept for comparison.
+    pub fn check_changed(&mut self) -> Result<DesignDelta> {
+        let design = with_compile_cache(&mut self.cache, &self.build_fn)?;
+        let current = Snapshot::new(&design)?;
+        let empty = Snapshot::default();
+        let delta = diff(self.previous.as_ref().unwrap_or(&empty), &current);
+        self.previous = Some(current);
+        self.design = Some(design);
+        Ok(delta)
+    }
+    // The most recent successful build.
+    pub fn design(&self) -> Option<&Module> {
+        self.design.as_ref()
+    }
+    pub fn cache(&self) -> &CompileCache {
+        &self.cache
+    }
+}
@@ -0,0 +1,539 @@
+// Compare two compiled designs (say, before and after upgrading the
+// compiler, or changing the compile options), to tell whether the
+// generated design behaves differently, or only looks different.
+//
+// The functions of the two designs are matched by id, and those left
+// over by name and signature (ids come from the Rust types of the
+// kernels, so they are not stable from one build of the crate to the
+// next).  A matched pair is then
+//
+// - identical, if the compiled RHIF is the same;
+// - cosmetic, if it differs only in the numbering of its slots, the
+//   names of its wires, comments, or the order of operations that do
+//   not depend on each other;
+// - structural, if the data flow from the arguments to the result (or
+//   to an assertion or probe) is different.
+//
+// The data flow is compared by hashing each value as the operation that
+// produces it and the hashes of its operands, so two functions with the
+// same hash compute the same thing.  The converse does not hold: a
+// rewrite of `a + a` as `a << 1` is structural.  So structural changes
+// can also be checked by running both versions in the RHIF interpreter
+// on a sample of arguments (see `DiffOptions`).  Calls are compared by
+// the name and signature of the callee, so a change to a kernel shows up
+// in that kernel, and not in its callers.
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+use serde::Serialize;
+
+use crate::{
+    ast::ast_impl::FunctionId,
+    codegen::verilog::op_lhs,
+    compiler::utils::remap_slots,
+    devloop::content_hash,
+    rhif::{
+        spec::{Exec, ExternalFunction, ExternalFunctionCode, FuncId, OpCode, Slot},
+        vm::execute_function,
+        Object,
+    },
+    Kind, Module, TypedBits,
+};
+
+// How a structurally changed function behaved when both versions were
+// run on the same arguments.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Behavior {
+    // The results agreed on all of this many arguments.
+    Same { samples: usize },
+    // The first arguments that the results differ on, and the results.
+    Differs { args: String, a: String, b: String },
+    // The two versions take (or return) different types.
+    Incomparable,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Change {
+    Identical,
+    Cosmetic,
+    // The behavior is only checked when `DiffOptions::samples` is set.
+    Structural(Option<Behavior>),
+    Added,
+    Removed,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FunctionDiff {
+    pub name: String,
+    pub a: Option<FunctionId>,
+    pub b: Option<FunctionId>,
+    pub change: Change,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ModuleDiff {
+    pub functions: Vec<FunctionDiff>,
+}
+
+// The counts of each kind of change, for tools that want to act on the
+// diff rather than show it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DiffSummary {
+    pub identical: usize,
+    pub cosmetic: usize,
+    pub structural: usize,
+    pub added: usize,
+    pub removed: usize,
+    // The structural changes that were seen to change the results.
+    pub behavior_differs: usize,
+    // The names of the functions with structural changes.
+    pub structural_functions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    // The number of random arguments (beyond all zeros and all ones) to
+    // run each structurally changed function on.  Zero turns the check
+    // off.
+    pub samples: usize,
+    pub seed: u64,
+}
+
+impl DiffOptions {
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+// The sampled arguments come from a splitmix64 generator seeded with
+// `DiffOptions::seed`, so a diff gives the same answer on every run (and
+// does not need the rand crate).
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl ModuleDiff {
+    // True if the two designs are the same, without even cosmetic changes.
+    pub fn is_clean(&self) -> bool {
+        self.functions
+            .iter()
+            .all(|func| func.change == Change::Identical)
+    }
+    // True if no function changed more than cosmetically.
+    pub fn is_cosmetic(&self) -> bool {
+        self.functions
+            .iter()
+            .all(|func| matches!(func.change, Change::Identical | Change::Cosmetic))
+    }
+    pub fn function(&self, name: &str) -> Option<&FunctionDiff> {
+        self.functions.iter().find(|func| func.name == name)
+    }
+    pub fn summary(&self) -> DiffSummary {
+        let mut summary = DiffSummary::default();
+        for func in &self.functions {
+            match &func.change {
+                Change::Identical => summary.identical += 1,
+                Change::Cosmetic => summary.cosmetic += 1,
+                Change::Structural(behavior) => {
+                    summary.structural += 1;
+                    if matches!(behavior, Some(Behavior::Differs { .. })) {
+                        summary.behavior_differs += 1;
+                    }
+                    summary.structural_functions.push(func.name.clone());
+                }
+                Change::Added => summary.added += 1,
+                Change::Removed => summary.removed += 1,
+            }
+        }
+        summary
+    }
+}
+
+impl std::fmt::Display for ModuleDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let summary = self.summary();
+        writeln!(
+            f,
+            "{} identical, {} cosmetic, {} structural, {} added, {} removed",
+            summary.identical, summary.cosmetic, summary.structural, summary.added, summary.removed
+        )?;
+        for func in &self.functions {
+            let name = &func.name;
+            match &func.change {
+                Change::Identical => {}
+                Change::Cosmetic => writeln!(f, "  {name}: cosmetic changes only")?,
+                Change::Structural(None) => writeln!(f, "  {name}: structural change")?,
+                Change::Structural(Some(Behavior::Same { samples })) => writeln!(
+                    f,
+                    "  {name}: structural change, with the same results for {samples} arguments"
+                )?,
+                Change::Structural(Some(Behavior::Differs { args, a, b })) => writeln!(
+                    f,
+                    "  {name}: structural change, and {name}({args}) gives {a} before and {b} after"
+                )?,
+                Change::Structural(Some(Behavior::Incomparable)) => writeln!(
+                    f,
+                    "  {name}: structural change, to the types of its arguments or result"
+                )?,
+                Change::Added => writeln!(f, "  {name}: added")?,
+                Change::Removed => writeln!(f, "  {name}: removed")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hash_of(value: impl Hash) -> u64 {
+    let mut hasher = fnv::FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn kind_of(obj: &Object, slot: &Slot) -> Kind {
+    obj.kind.get(slot).cloned().unwrap_or(Kind::Empty)
+}
+
+fn signature(obj: &Object) -> (Vec<Kind>, Kind) {
+    (
+        obj.arguments
+            .iter()
+            .map(|slot| kind_of(obj, slot))
+            .collect(),
+        kind_of(obj, &obj.return_slot),
+    )
+}
+
+fn callee(func: &ExternalFunction) -> String {
+    match &func.code {
+        ExternalFunctionCode::Kernel(kernel) => {
+            format!("{} {:?}", kernel.inner().name, func.signature)
+        }
+        ExternalFunctionCode::Extern(def) => format!("{} {}", def.name, def.body),
+    }
+}
+
+// The hash of the compiled RHIF as it stands, including the names of
+// the wires.
+fn exact_hash(obj: &Object) -> u64 {
+    hash_of((
+        content_hash(obj),
+        format!("{:?}", obj.symbols.slot_names),
+        format!("{:?}", obj.probes),
+    ))
+}
+
+// The hash of the data flow of the function, which leaves out everything
+// that does not change what the function computes.
+fn dataflow_hash(obj: &Object) -> u64 {
+    let mut values: HashMap<Slot, u64> = obj
+        .arguments
+        .iter()
+        .enumerate()
+        .map(|(ndx, slot)| (*slot, hash_of(("argument", ndx))))
+        .collect();
+    let value = |values: &HashMap<Slot, u64>, slot: Slot| match slot {
+        Slot::Literal(_) => hash_of(("literal", obj.literals.get(&slot))),
+        Slot::Empty => hash_of("empty"),
+        Slot::Register(_) => values
+            .get(&slot)
+            .copied()
+            .unwrap_or_else(|| hash_of("undefined")),
+    };
+    let mut asserts = vec![];
+    for op in &obj.ops {
+        if matches!(op, OpCode::Noop | OpCode::Comment(_)) {
+            continue;
+        }
+        let lhs = op_lhs(op).copied();
+        // The slots of the operation are replaced by their position, and
+        // the values they hold are hashed alongside
+        let mut operands = vec![];
+        let shape = remap_slots(op.clone(), |slot| {
+            if Some(slot) == lhs {
+                return Slot::Empty;
+            }
+            operands.push(value(&values, slot));
+            Slot::Register(operands.len() - 1)
+        });
+        let (shape, callee) = match shape {
+            OpCode::Exec(Exec { lhs, id, args }) => (
+                OpCode::Exec(Exec {
+                    lhs,
+                    id: FuncId(0),
+                    args,
+                }),
+                obj.externals.get(id.0).map(callee),
+            ),
+            shape => (shape, None),
+        };
+        let hash = hash_of((
+            format!("{shape:?}"),
+            operands,
+            callee,
+            lhs.map(|lhs| format!("{:?}", kind_of(obj, &lhs))),
+        ));
+        match lhs {
+            Some(lhs) => {
+                values.insert(lhs, hash);
+            }
+            None => asserts.push(hash),
+        }
+    }
+    asserts.sort();
+    let mut probes = obj
+        .probes
+        .iter()
+        .map(|probe| (probe.name.clone(), value(&values, probe.slot)))
+        .collect::<Vec<_>>();
+    probes.sort();
+    hash_of((
+        value(&values, obj.return_slot),
+        asserts,
+        probes,
+        format!("{:?}", signature(obj)),
+    ))
+}
+
+fn show(args: &[TypedBits]) -> String {
+    args.iter()
+        .map(|arg| arg.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Run both versions of a function on the same arguments: all zeros, all
+// ones, and then random ones.  Arguments that both versions reject (such
+// as an enum discriminant with no variant) are skipped.
+fn compare_behavior(
+    a: &Module,
+    a_id: FunctionId,
+    b: &Module,
+    b_id: FunctionId,
+    options: &DiffOptions,
+) -> Behavior {
+    let kinds = signature(&a.objects[&a_id]);
+    if kinds != signature(&b.objects[&b_id]) {
+        return Behavior::Incomparable;
+    }
+    let a = Module {
+        objects: a.objects.clone(),
+        top: a_id,
+    };
+    let b = Module {
+        objects: b.objects.clone(),
+        top: b_id,
+    };
+    let mut rng = options.seed;
+    let mut samples = 0;
+    for ndx in 0..options.samples + 2 {
+        let args = kinds
+            .0
+            .iter()
+            .map(|kind| TypedBits {
+                bits: (0..kind.bits())
+                    .map(|_| match ndx {
+                        0 => false,
+                        1 => true,
+                        _ => splitmix64(&mut rng) & 1 == 1,
+                    })
+                    .collect(),
+                kind: kind.clone(),
+            })
+            .collect::<Vec<_>>();
+        let results = (
+            execute_function(&a, args.clone()),
+            execute_function(&b, args.clone()),
+        );
+        let (a, b) = match results {
+            (Err(_), Err(_)) => continue,
+            (Ok(a), Ok(b)) if a == b => {
+                samples += 1;
+                continue;
+            }
+            (a, b) => (a, b),
+        };
+        let show_result = |result: anyhow::Result<TypedBits>| match result {
+            Ok(value) => value.to_string(),
+            Err(err) => format!("an error ({err})"),
+        };
+        return Behavior::Differs {
+            args: show(&args),
+            a: show_result(a),
+            b: show_result(b),
+        };
+    }
+    Behavior::Same { samples }
+}
+
+// Compare the two designs, without running them.
+pub fn module_diff(a: &Module, b: &Module) -> ModuleDiff {
+    module_diff_with_options(a, b, &DiffOptions::default())
+}
+
+pub fn module_diff_with_options(a: &Module, b: &Module, options: &DiffOptions) -> ModuleDiff {
+    // Pair up the functions, first by id and then by name and signature
+    let mut pairs = vec![];
+    let mut unmatched_b = b
+        .objects
+        .keys()
+        .filter(|fn_id| !a.objects.contains_key(fn_id))
+        .copied()
+        .collect::<Vec<_>>();
+    let mut removed = vec![];
+    for (fn_id, obj) in &a.objects {
+        if b.objects.contains_key(fn_id) {
+            pairs.push((*fn_id, *fn_id));
+            continue;
+        }
+        let key = (&obj.name, signature(obj));
+        match unmatched_b
+            .iter()
+            .position(|id| (&b.objects[id].name, signature(&b.objects[id])) == key)
+        {
+            Some(ndx) => pairs.push((*fn_id, unmatched_b.remove(ndx))),
+            None => removed.push(*fn_id),
+        }
+    }
+    let mut functions = BTreeMap::<(String, usize), FunctionDiff>::new();
+    let mut add = |diff: FunctionDiff| {
+        let count = functions
+            .keys()
+            .filter(|(name, _)| *name == diff.name)
+            .count();
+        functions.insert((diff.name.clone(), count), diff);
+    };
+    for (a_id, b_id) in pairs {
+        let (a_obj, b_obj) = (&a.objects[&a_id], &b.objects[&b_id]);
+        let change = if exact_hash(a_obj) == exact_hash(b_obj) {
+            Change::Identical
+        } else if dataflow_hash(a_obj) == dataflow_hash(b_obj) {
+            Change::Cosmetic
+        } else if options.samples > 0 {
+            Change::Structural(Some(compare_behavior(a, a_id, b, b_id, options)))
+        } else {
+            Change::Structural(None)
+        };
+        add(FunctionDiff {
+            name: a_obj.name.clone(),
+            a: Some(a_id),
+            b: Some(b_id),
+            change,
+        });
+    }
+    for fn_id in removed {
+        add(FunctionDiff {
+            name: a.objects[&fn_id].name.clone(),
+            a: Some(fn_id),
+            b: None,
+            change: Change::Removed,
+        });
+    }
+    for fn_id in unmatched_b {
+        add(FunctionDiff {
+            name: b.objects[&fn_id].name.clone(),
+            a: None,
+            b: Some(fn_id),
+            change: Change::Added,
+        });
+    }
+    ModuleDiff {
+        functions: functions.into_values().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        rhif::{
+            object::ObjectBuilder,
+            spec::{AluBinary, Binary},
+        },
+        Digital,
+    };
+    use rhdl_bits::Bits;
+
+    // fn mac(a: b4, b: b4, c: b4) -> b4 { (a + b) ^ (b + c) }, with
+    // the registers numbered from `base`, and the two adds in either
+    // order
+    fn mac(base: usize, swap: bool, op: AluBinary) -> Module {
+        let r = |ndx: usize| Slot::Register(base + ndx);
+        let add = |lhs, arg1, arg2| {
+            OpCode::Binary(Binary {
+                op: AluBinary::Add,
+                lhs,
+                arg1,
+                arg2,
+            })
+        };
+        let mut ops = vec![add(r(3), r(0), r(1)), add(r(4), r(1), r(2))];
+        if swap {
+            ops.reverse();
+        }
+        ops.push(OpCode::Binary(Binary {
+            op,
+            lhs: r(5),
+            arg1: r(3),
+            arg2: r(4),
+        }));
+        let builder = (0..6).fold(ObjectBuilder::new("mac", ""), |builder, ndx| {
+            builder.slot(r(ndx), Kind::make_bits(4))
+        });
+        ops.into_iter()
+            .fold(builder, ObjectBuilder::op)
+            .arguments([r(0), r(1), r(2)])
+            .returns(r(5))
+            .build_module()
+    }
+
+    #[test]
+    fn test_renumbered_and_reordered_is_cosmetic() {
+        let original = mac(0, false, AluBinary::BitXor);
+        assert!(module_diff(&original, &original).is_clean());
+        for changed in [
+            mac(10, false, AluBinary::BitXor),
+            mac(0, true, AluBinary::BitXor),
+        ] {
+            let diff = module_diff(&original, &changed);
+            assert!(!diff.is_clean());
+            assert!(diff.is_cosmetic(), "{diff}");
+            assert_eq!(diff.summary().cosmetic, 1);
+        }
+    }
+
+    #[test]
+    fn test_changed_operation_is_structural() {
+        let original = mac(0, false, AluBinary::BitXor);
+        let changed = mac(0, true, AluBinary::BitOr);
+        let diff = module_diff(&original, &changed);
+        assert_eq!(
+            diff.function("mac").unwrap().change,
+            Change::Structural(None)
+        );
+        let diff = module_diff_with_options(
+            &original,
+            &changed,
+            &DiffOptions::default().with_samples(64),
+        );
+        let Change::Structural(Some(Behavior::Differs { args, a, b })) =
+            &diff.function("mac").unwrap().change
+        else {
+            panic!("{diff}");
+        };
+        // The first difference is for all ones, where (a + b) and
+        // (b + c) are equal
+        assert_eq!(args, &show(&vec![Bits::<4>(15).typed_bits(); 3]));
+        assert_eq!(a, &Bits::<4>(0).typed_bits().to_string());
+        assert_eq!(b, &Bits::<4>(14).typed_bits().to_string());
+        assert_eq!(diff.summary().behavior_differs, 1);
+    }
+}
@@ -0,0 +1,544 @@
+// Human readable documentation of a generated design, rendered from its
+// manifest as Markdown or as a standalone HTML page.  For each module of
+// the hierarchy, the documentation gives the doc comment on the circuit
+// and lists the bits of its ports down to the leaves of their kinds
+// (described by the doc comments on their fields), along with the
+// probes, flags and timing exceptions of the module.  The encodings of
+// the enums that appear on any port are listed once for the whole
+// design.  There are no resource estimates in the manifest, so there
+// are none here.
+//
+// The output only depends on the manifest (whose children are already
+// sorted by name), so it is stable from run to run and can be checked in
+// next to the generated HDL.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::circuit::manifest::{DesignManifest, ModuleManifest, PortManifest, TimingManifest};
+use crate::types::kind::{DiscriminantAlignment, DiscriminantType, Enum};
+use crate::{Kind, TimingException};
+
+enum Cell {
+    Code(String),
+    Text(String),
+}
+
+struct Table {
+    headers: &'static [&'static str],
+    rows: Vec<Vec<Cell>>,
+}
+
+// A titled table in the documentation of a module (or of the design).
+struct Section {
+    title: String,
+    intro: Option<String>,
+    table: Table,
+}
+
+fn code(text: impl ToString) -> Cell {
+    Cell::Code(text.to_string())
+}
+
+fn text(text: impl ToString) -> Cell {
+    Cell::Text(text.to_string())
+}
+
+// Bit ranges are given MSB first, as they are written in Verilog.
+fn bits_label(bits: &Range<usize>) -> String {
+    if bits.len() == 1 {
+        format!("[{}]", bits.start)
+    } else {
+        format!("[{}:{}]", bits.end - 1, bits.start)
+    }
+}
+
+fn exception_label(exception: &TimingException) -> String {
+    match exception {
+        TimingException::Multicycle(cycles) => format!("multicycle ({cycles} cycles)"),
+        TimingException::FalsePath => "false path".into(),
+    }
+}
+
+fn port_label(name: &str) -> &'static str {
+    match name {
+        "i" => "input",
+        "o" => "output",
+        "d" => "inputs of the children",
+        "q" => "outputs of the children",
+        _ => "",
+    }
+}
+
+fn port_rows(port: &PortManifest, timing: &[TimingManifest]) -> Vec<Vec<Cell>> {
+    if port.width == 0 {
+        return vec![];
+    }
+    let mut rows = vec![vec![
+        code(&port.name),
+        code(bits_label(&(0..port.width))),
+        code(&port.kind),
+        text(port_label(&port.name)),
+    ]];
+    for leaf in &port.leaves {
+        // Leaves with no bits (such as the payload of a variant that has
+        // none) do not appear in the HDL
+        if leaf.bits.is_empty() {
+            continue;
+        }
+        // A table cell holds a single line
+        let mut notes = leaf
+            .doc
+            .iter()
+            .map(|doc| doc.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>();
+        if leaf.path.ends_with('#') {
+            notes.push("discriminant".to_string());
+        }
+        if let Some(range) = &leaf.range {
+            notes.push(format!("holds {}..={}", range.start(), range.end()));
+        }
+        notes.extend(
+            timing
+                .iter()
+                .filter(|t| {
+                    t.port == port.name
+                        && t.bits.start < leaf.bits.end
+                        && leaf.bits.start < t.bits.end
+                })
+                .map(|t| exception_label(&t.exception)),
+        );
+        // The port itself covers a leaf with an empty path
+        if leaf.path.is_empty() {
+            if let Some(Cell::Text(description)) = rows[0].last_mut() {
+                for note in notes {
+                    description.push_str("; ");
+                    description.push_str(&note);
+                }
+            }
+            continue;
+        }
+        rows.push(vec![
+            code(format!("{}{}", port.name, leaf.path)),
+            code(bits_label(&leaf.bits)),
+            code(&leaf.kind),
+            text(notes.join("; ")),
+        ]);
+    }
+    rows
+}
+
+fn module_sections(module: &ModuleManifest) -> Vec<Section> {
+    let mut sections = vec![Section {
+        title: "Ports".into(),
+        intro: None,
+        table: Table {
+            headers: &["Signal", "Bits", "Kind", "Description"],
+            rows: module
+                .ports
+                .iter()
+                .flat_map(|port| port_rows(port, &module.timing))
+                .collect(),
+        },
+    }];
+    if !module.probes.is_empty() {
+        sections.push(Section {
+            title: "Probes".into(),
+            intro: None,
+            table: Table {
+                headers: &["Probe", "Kernel", "Width", "Kind"],
+                rows: module
+                    .probes
+                    .iter()
+                    .map(|probe| {
+                        vec![
+                            code(&probe.name),
+                            code(&probe.function),
+                            text(probe.width),
+                            code(&probe.kind),
+                        ]
+                    })
+                    .collect(),
+            },
+        });
+    }
+    if !module.flags.is_empty() {
+        sections.push(Section {
+            title: "Flags".into(),
+            intro: None,
+            table: Table {
+                headers: &["Flag", "Enabled"],
+                rows: module
+                    .flags
+                    .iter()
+                    .map(|(flag, enabled)| {
+                        vec![code(flag), text(if *enabled { "yes" } else { "no" })]
+                    })
+                    .collect(),
+            },
+        });
+    }
+    if !module.timing.is_empty() {
+        sections.push(Section {
+            title: "Timing exceptions".into(),
+            intro: None,
+            table: Table {
+                headers: &["Signal", "Bits", "Exception"],
+                rows: module
+                    .timing
+                    .iter()
+                    .map(|timing| {
+                        vec![
+                            code(format!("{}{}", timing.port, timing.path)),
+                            code(bits_label(&timing.bits)),
+                            text(exception_label(&timing.exception)),
+                        ]
+                    })
+                    .collect(),
+            },
+        });
+    }
+    sections
+}
+
+fn collect_enums(kind: &Kind, enums: &mut BTreeMap<String, Enum>) {
+    match kind {
+        Kind::Array(array) => collect_enums(&array.base, enums),
+        Kind::Tuple(tuple) => tuple.elements.iter().for_each(|k| collect_enums(k, enums)),
+        Kind::Struct(s) => s.fields.iter().for_each(|f| collect_enums(&f.kind, enums)),
+        Kind::Enum(e) => {
+            enums.entry(e.name.clone()).or_insert_with(|| e.clone());
+            e.variants
+                .iter()
+                .for_each(|v| collect_enums(&v.kind, enums));
+        }
+        Kind::Ranged(r) => collect_enums(&r.base, enums),
+        Kind::Bits(_) | Kind::Signed(_) | Kind::Empty => {}
+    }
+}
+
+fn collect_module_enums(module: &ModuleManifest, enums: &mut BTreeMap<String, Enum>) {
+    for port in &module.ports {
+        collect_enums(&port.kind, enums);
+    }
+    for probe in &module.probes {
+        collect_enums(&probe.kind, enums);
+    }
+    for child in &module.children {
+        collect_module_enums(child, enums);
+    }
+}
+
+// The bits of the discriminant, MSB first (in two's complement if it is
+// signed).
+fn discriminant_encoding(discriminant: i64, width: usize) -> String {
+    (0..width)
+        .rev()
+        .map(|bit| {
+            if bit < 64 && (discriminant >> bit) & 1 == 1 || bit >= 64 && discriminant < 0 {
+                '1'
+            } else {
+                '0'
+            }
+        })
+        .collect()
+}
+
+fn enum_section(e: &Enum) -> Section {
+    let layout = &e.discriminant_layout;
+    let intro = format!(
+        "{} bit {} discriminant in the {} significant bits.",
+        layout.width,
+        match layout.ty {
+            DiscriminantType::Unsigned => "unsigned",
+            DiscriminantType::Signed => "signed",
+        },
+        match layout.alignment {
+            DiscriminantAlignment::Msb => "most",
+            DiscriminantAlignment::Lsb => "least",
+        }
+    );
+    Section {
+        title: format!("Enum {}", e.name),
+        intro: Some(intro),
+        table: Table {
+            headers: &["Variant", "Discriminant", "Encoding", "Payload"],
+            rows: e
+                .variants
+                .iter()
+                .map(|variant| {
+                    let default = e.default_variant.as_deref() == Some(variant.name.as_str());
+                    vec![
+                        code(&variant.name),
+                        text(variant.discriminant),
+                        code(format!(
+                            "0b{}",
+                            discriminant_encoding(variant.discriminant, layout.width)
+                        )),
+                        match (&variant.kind, default) {
+                            (Kind::Empty, false) => text(""),
+                            (Kind::Empty, true) => text("(default)"),
+                            (kind, false) => code(kind),
+                            (kind, true) => code(format!("{kind} (default)")),
+                        },
+                    ]
+                })
+                .collect(),
+        },
+    }
+}
+
+fn design_enums(manifest: &DesignManifest) -> Vec<Section> {
+    let mut enums = BTreeMap::new();
+    collect_module_enums(&manifest.top, &mut enums);
+    enums.values().map(enum_section).collect()
+}
+
+fn generated_by(manifest: &DesignManifest) -> String {
+    format!(
+        "Generated by {} {} from a version {} manifest.",
+        manifest.tool, manifest.tool_version, manifest.version
+    )
+}
+
+fn markdown_cell(cell: &Cell) -> String {
+    let escape = |s: &str| s.replace('|', "\\|");
+    match cell {
+        Cell::Code(s) => format!("`{}`", escape(s)),
+        Cell::Text(s) => escape(s),
+    }
+}
+
+fn markdown_table(table: &Table) -> String {
+    let mut ret = String::new();
+    let _ = writeln!(ret, "| {} |", table.headers.join(" | "));
+    let _ = writeln!(ret, "|{}", " --- |".repeat(table.headers.len()));
+    for row in &table.rows {
+        let cells = row.iter().map(markdown_cell).collect::<Vec<_>>();
+        let _ = writeln!(ret, "| {} |", cells.join(" | "));
+    }
+    ret
+}
+
+fn markdown_section(out: &mut String, level: &str, section: &Section) {
+    let _ = writeln!(out, "{level} {}\n", section.title);
+    if let Some(intro) = &section.intro {
+        let _ = writeln!(out, "{intro}\n");
+    }
+    let _ = writeln!(out, "{}", markdown_table(&section.table));
+}
+
+fn markdown_tree(out: &mut String, module: &ModuleManifest, depth: usize) {
+    let _ = writeln!(
+        out,
+        "{}- `{}`: `{}`",
+        "  ".repeat(depth),
+        module.instance_name,
+        module.unique_name
+    );
+    for child in &module.children {
+        markdown_tree(out, child, depth + 1);
+    }
+}
+
+fn markdown_modules(out: &mut String, module: &ModuleManifest, path: &str) {
+    let _ = writeln!(out, "## Module `{path}` (`{}`)\n", module.unique_name);
+    if let Some(doc) = &module.doc {
+        let _ = writeln!(out, "{doc}\n");
+    }
+    for section in module_sections(module) {
+        markdown_section(out, "###", &section);
+    }
+    for child in &module.children {
+        markdown_modules(out, child, &format!("{path}.{}", child.instance_name));
+    }
+}
+
+pub fn render_markdown(manifest: &DesignManifest) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Design `{}`\n", manifest.top.unique_name);
+    let _ = writeln!(out, "{}\n", generated_by(manifest));
+    let _ = writeln!(out, "## Instances\n");
+    markdown_tree(&mut out, &manifest.top, 0);
+    out.push('\n');
+    markdown_modules(&mut out, &manifest.top, &manifest.top.instance_name);
+    let enums = design_enums(manifest);
+    if !enums.is_empty() {
+        let _ = writeln!(out, "## Enums\n");
+        for section in &enums {
+            markdown_section(&mut out, "###", section);
+        }
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_cell(cell: &Cell) -> String {
+    match cell {
+        Cell::Code(s) => format!("<code>{}</code>", escape_html(s)),
+        Cell::Text(s) => escape_html(s),
+    }
+}
+
+fn html_section(out: &mut String, level: usize, section: &Section) {
+    let _ = writeln!(out, "<h{level}>{}</h{level}>", escape_html(&section.title));
+    if let Some(intro) = &section.intro {
+        let _ = writeln!(out, "<p>{}</p>", escape_html(intro));
+    }
+    let _ = writeln!(out, "<table>");
+    let headers = section
+        .table
+        .headers
+        .iter()
+        .map(|h| format!("<th>{h}</th>"))
+        .collect::<String>();
+    let _ = writeln!(out, "<tr>{headers}</tr>");
+    for row in &section.table.rows {
+        let cells = row
+            .iter()
+            .map(|cell| format!("<td>{}</td>", html_cell(cell)))
+            .collect::<String>();
+        let _ = writeln!(out, "<tr>{cells}</tr>");
+    }
+    let _ = writeln!(out, "</table>");
+}
+
+// Each module is a collapsible block holding its tables, followed by the
+// blocks of its children.
+fn html_module(out: &mut String, module: &ModuleManifest) {
+    let _ = writeln!(
+        out,
+        "<details open>\n<summary><code>{}</code>: <code>{}</code></summary>",
+        escape_html(&module.instance_name),
+        escape_html(&module.unique_name)
+    );
+    if let Some(doc) = &module.doc {
+        let _ = writeln!(out, "<p>{}</p>", escape_html(doc));
+    }
+    for section in module_sections(module) {
+        html_section(out, 3, &section);
+    }
+    for child in &module.children {
+        html_module(out, child);
+    }
+    let _ = writeln!(out, "</details>");
+}
+
+pub fn render_html(manifest: &DesignManifest) -> String {
+    let title = format!("Design {}", escape_html(&manifest.top.unique_name));
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+    );
+    let _ = writeln!(out, "<title>{title}</title>\n</head>\n<body>");
+    let _ = writeln!(out, "<h1>{title}</h1>");
+    let _ = writeln!(out, "<p>{}</p>", escape_html(&generated_by(manifest)));
+    let _ = writeln!(out, "<h2>Modules</h2>");
+    html_module(&mut out, &manifest.top);
+    let enums = design_enums(manifest);
+    if !enums.is_empty() {
+        let _ = writeln!(out, "<h2>Enums</h2>");
+        for section in &enums {
+            html_section(&mut out, 3, section);
+        }
+    }
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::circuit_descriptor::two_level_design;
+    use crate::path::{bit_range, leaf_paths, Path};
+
+    // The row of the ports table for the leaf at `path` of the port `port`
+    fn leaf_row(port: &str, kind: &Kind, path: &Path) -> String {
+        let (bits, leaf_kind) = bit_range(kind.clone(), path).unwrap();
+        format!(
+            "| `{port}{path}` | `[{}:{}]` | `{leaf_kind}` |",
+            bits.end - 1,
+            bits.start
+        )
+        .replace(
+            &format!("[{0}:{0}]", bits.start),
+            &format!("[{}]", bits.start),
+        )
+    }
+
+    #[test]
+    fn test_markdown_lists_every_leaf() {
+        let design = two_level_design();
+        let markdown = render_markdown(&DesignManifest::from_descriptor(&design).unwrap());
+        let inner = &design.children["inner"];
+        for (port, kind) in [
+            ("i", &inner.input_kind),
+            ("o", &inner.output_kind),
+            ("d", &design.d_kind),
+            ("q", &design.q_kind),
+        ] {
+            for path in leaf_paths(kind, Path::default()) {
+                if bit_range(kind.clone(), &path).unwrap().0.is_empty() {
+                    continue;
+                }
+                let row = leaf_row(port, kind, &path);
+                assert!(markdown.contains(&row), "missing {row} in\n{markdown}");
+            }
+        }
+        assert!(markdown.contains("- `top`: `top_5678`\n  - `inner`: `inner_1234`\n"));
+        assert!(markdown
+            .contains("## Module `top.inner` (`inner_1234`)\n\nCounts one decimal digit.\n"));
+        assert!(markdown.contains("| `i` | `[10:0]` | `Inner` | input |"));
+        assert!(markdown.contains("| `i` | `[7:0]` | `b8` | input |"));
+        assert!(markdown.contains("| `i.clock` | `[0]` | `b1` |  |"));
+        assert!(markdown.contains("| `i.data[1]` | `[6:4]` | `b3` | multicycle (2 cycles) |"));
+        assert!(markdown
+            .contains("| `i.digit` | `[10:7]` | `b4` | The digit to count from; holds 0..=9 |"));
+        assert!(markdown.contains("| `o#` | `[5:4]` | `b2` | discriminant |"));
+        assert!(markdown.contains("| `debug_counters` | yes |"));
+        // The enum appears on three ports, but is only listed once
+        assert_eq!(markdown.matches("### Enum State").count(), 1);
+        assert!(markdown.contains("2 bit unsigned discriminant in the most significant bits."));
+        assert!(markdown.contains("| `Idle` | 0 | `0b00` | (default) |"));
+        assert!(markdown.contains("| `Run` | 1 | `0b01` | `b4` |"));
+        assert!(markdown.contains("| `Done` | 2 | `0b10` | `(b1, s3)` |"));
+        // Ports with no bits are left out
+        let (top, inner) = markdown.split_once("## Module `top.inner`").unwrap();
+        assert!(top.contains("| `d` | `[10:0]` | `TopD` | inputs of the children |"));
+        assert!(!inner.contains("| `d` | "));
+    }
+
+    #[test]
+    fn test_html_nests_the_hierarchy() {
+        let manifest = DesignManifest::from_descriptor(&two_level_design()).unwrap();
+        let html = render_html(&manifest);
+        let top = html
+            .find("<summary><code>top</code>: <code>top_5678</code></summary>")
+            .unwrap();
+        let inner = html
+            .find("<summary><code>inner</code>: <code>inner_1234</code></summary>")
+            .unwrap();
+        assert!(top < inner);
+        // The inner module closes before the top one
+        assert!(html[inner..].matches("</details>").count() == 2);
+        assert!(html.contains(
+            "<tr><td><code>i.digit</code></td><td><code>[10:7]</code></td><td><code>b4</code></td><td>The digit to count from; holds 0..=9</td></tr>"
+        ));
+        assert!(html[inner..].starts_with(
+            "<summary><code>inner</code>: <code>inner_1234</code></summary>\n<p>Counts one decimal digit.</p>\n"
+        ));
+        assert!(html.contains("<h3>Enum State</h3>"));
+        // The output is the same from run to run
+        assert_eq!(html, render_html(&manifest));
+        assert_eq!(render_markdown(&manifest), render_markdown(&manifest));
+    }
+}
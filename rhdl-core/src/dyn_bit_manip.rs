@@ -22,6 +22,17 @@ pub(crate) fn full_add(a: &[bool], b: &[bool]) -> Vec<bool> {
         .collect()
 }
 
+// The product, truncated to the width of the arguments.  In two's
+// complement, this is the same for signed and unsigned values.
+pub(crate) fn full_mul(a: &[bool], b: &[bool]) -> Vec<bool> {
+    b.iter()
+        .enumerate()
+        .filter(|(_, bit)| **bit)
+        .fold(vec![false; a.len()], |acc, (ndx, _)| {
+            full_add(&acc, &bits_shl(a, ndx as i64))
+        })
+}
+
 pub(crate) fn bit_not(a: &[bool]) -> Vec<bool> {
     a.iter().map(|b| !b).collect()
 }
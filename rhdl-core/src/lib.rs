@@ -2,26 +2,68 @@ pub use types::kind::Kind;
 pub mod clock_details;
 
 pub use circuit::bitz::BitZ;
+pub use circuit::check::check_circuit;
 pub use circuit::circuit_descriptor::root_descriptor;
+pub use circuit::circuit_descriptor::ClockDomainDescriptor;
 pub use circuit::circuit_descriptor::CircuitDescriptor;
+pub use circuit::circuit_descriptor::PortNames;
+pub use circuit::circuit_descriptor::ProbeDescriptor;
+pub use circuit::circuit_descriptor::TimingDescriptor;
+pub use circuit::circuit_descriptor::DocDescriptor;
+pub use circuit::circuit_impl::child_state_signals;
 pub use circuit::circuit_impl::Circuit;
 pub use circuit::circuit_impl::CircuitIO;
 pub use circuit::circuit_impl::HDLKind;
 pub use circuit::circuit_impl::NoUpdateFn;
 pub use circuit::circuit_impl::Tristate;
+pub use circuit::clocked_testbench::ClockSpec;
+pub use circuit::clocked_testbench::ClockedTestbench;
+pub use circuit::clocked_testbench::OutputSampling;
+pub use circuit::hdl_descriptor::circuit_ports;
+pub use circuit::hdl_descriptor::example_top;
+pub use circuit::hdl_descriptor::initial_block;
 pub use circuit::hdl_descriptor::root_hdl;
+pub use circuit::hdl_descriptor::ExampleTopOptions;
 pub use circuit::hdl_descriptor::HDLDescriptor;
+pub use circuit::hdl_descriptor::HDLInitial;
+pub use circuit::hdl_descriptor::HDLPort;
+pub use circuit::hdl_descriptor::HDLPortDirection;
+pub use circuit::initial_state::verify_initial_state;
+pub use circuit::manifest::DesignManifest;
+pub use circuit::output_mask::MaskStats;
+pub use circuit::output_mask::OutputMask;
+pub use circuit::primitive::Primitive;
+pub use circuit::primitive::PrimitiveCircuit;
+pub use circuit::primitive::PrimitivePort;
+pub use circuit::sdc::root_sdc;
+pub use circuit::sdc::sdc_constraints;
+pub use circuit::stats::collect_stats;
+pub use circuit::stats::LeafStats;
+pub use circuit::stats::StatsCollector;
+pub use circuit::stats::StatsLeaves;
+pub use circuit::stats::StatsReport;
+pub use circuit::trace::ReplayReport;
+pub use circuit::trace::TraceFile;
+pub use circuit::trace::TraceRecorder;
 pub use circuit::verilog::root_verilog;
 pub use clock_details::ClockDetails;
 pub use crusty::check_schematic;
+pub use types::convert::convert;
+pub use types::convert::Compatibility;
 pub use types::digital::Digital;
+pub use types::digital::TimingException;
 pub use types::digital_fn::DigitalFn;
+pub use types::digital_fn::DigitalFnSignature;
 pub use types::kernel::KernelFnKind;
 #[cfg(feature = "svg")]
 pub use types::kind::kind_svg::svg_grid;
 #[cfg(feature = "svg")]
 pub use types::kind::kind_svg::svg_grid_vertical;
 pub use types::kind::DiscriminantAlignment;
+pub use types::kind::PayloadAlignment;
+pub use types::kind_registry::register_kind;
+pub use types::kind_registry::KindRegistry;
+pub use types::note::BitString;
 pub use types::note::Notable;
 
 pub use types::kind::text_grid;
@@ -30,6 +72,9 @@ pub mod circuit;
 pub mod codegen;
 pub mod compiler;
 pub mod crusty;
+pub mod devloop;
+pub mod diff;
+pub mod docs;
 //pub mod diagnostic;
 pub mod dyn_bit_manip;
 pub mod note_db;
@@ -39,10 +84,29 @@ pub mod test_module;
 pub mod types;
 pub mod util;
 
+pub use codegen::mux::estimate_dynamic_indexing;
+pub use codegen::mux::MuxEstimate;
+pub use codegen::mux::MuxStrategy;
 pub use codegen::verilog::as_verilog_literal;
+pub use codegen::verilog::clocked_always_block;
+pub use codegen::verilog::AssignmentStyle;
+pub use codegen::verilog::concat_verilog_functions;
 pub use codegen::verilog::generate_verilog;
+pub use codegen::verilog::generate_verilog_module;
+pub use codegen::verilog::generate_verilog_probes;
+pub use codegen::verilog::verilog_attribute;
+pub use codegen::verilog::generate_verilog_split;
+pub use codegen::verilog::FunctionVerilog;
+pub use codegen::verilog::OutputPorts;
 pub use codegen::verilog::VerilogModule;
 pub use compiler::compile_design;
+pub use compiler::compile_design_with_options;
+pub use compiler::compile_design_with_params;
+pub use compiler::CompileOptions;
+pub use compiler::compile_flag;
+pub use compiler::expect_compile_flag;
+pub use compiler::known_compile_flag;
+pub use compiler::with_compile_options;
 pub use note_db::note;
 pub use note_db::note_init_db;
 pub use note_db::note_pop_path;
@@ -50,8 +114,10 @@ pub use note_db::note_push_path;
 pub use note_db::note_take;
 pub use note_db::note_time;
 pub use note_db::NoteDB;
+pub use note_db::VcdNoteWriter;
 pub use schematic::components::BlackBoxComponent;
 pub use schematic::components::BlackBoxTrait;
+pub use schematic::constraints::constraint_domain_crossing;
 pub use schematic::constraints::constraint_input_synchronous;
 pub use schematic::constraints::constraint_must_clock;
 pub use schematic::constraints::constraint_not_constant_valued;
@@ -59,14 +125,23 @@ pub use schematic::constraints::constraint_output_synchronous;
 pub use schematic::constraints::Constraint;
 pub use schematic::constraints::EdgeType;
 pub use test_module::test_kernel_vm_and_verilog;
+pub use test_module::test_kernel_vm_and_verilog_with_options;
+pub use test_module::test_kernel_vm_with_coverage;
+pub use test_module::verilog_semantic_eq;
+pub use test_module::TestDisplay;
+pub use test_module::TestModuleOptions;
 #[cfg(feature = "iverilog")]
 pub use test_module::test_with_iverilog;
 pub use types::kind::DiscriminantType;
 pub use types::note::NoteKey;
 pub use types::note::NoteWriter;
+pub use types::typed_bits::render_typed_bits;
 pub use types::typed_bits::TypedBits;
+pub use types::x_typed_bits::XTypedBits;
 pub mod rhif;
 pub use ast::ast_builder;
+pub use rhif::coverage::assert_coverage_at_least;
+pub use rhif::coverage::CoverageMap;
 pub use rhif::module::Module;
 pub use types::digital_fn;
 pub use types::digital_fn::DigitalSignature;
@@ -535,13 +535,72 @@ pub fn note_take() -> Option<NoteDB> {
     DB.with(|db| db.borrow_mut().take())
 }
 
+// A NoteWriter that collects the notes made during a simulation run,
+// and writes them out as a VCD.  Values can be written to it directly
+// (with `Notable::note`), or it can capture the notes that a circuit
+// makes through the global `note` functions while it is simulated.
+#[derive(Default)]
+pub struct VcdNoteWriter {
+    db: NoteDB,
+    clocks: Vec<ClockDetails>,
+}
+
+impl NoteWriter for VcdNoteWriter {
+    fn write_bool(&mut self, key: impl NoteKey, value: bool) {
+        self.db.write_bool(key, value);
+    }
+
+    fn write_bits(&mut self, key: impl NoteKey, value: u128, len: u8) {
+        self.db.write_bits(key, value, len);
+    }
+
+    fn write_signed(&mut self, key: impl NoteKey, value: i128, len: u8) {
+        self.db.write_signed(key, value, len);
+    }
+
+    fn write_string(&mut self, key: impl NoteKey, value: &'static str) {
+        self.db.write_string(key, value);
+    }
+
+    fn write_tristate(&mut self, key: impl NoteKey, value: u128, mask: u128, size: u8) {
+        self.db.write_tristate(key, value, mask, size);
+    }
+}
+
+impl VcdNoteWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Add a clock signal to the VCD.  Its edges are generated from the
+    // clock details, and do not need to be noted.
+    pub fn with_clock(mut self, clock: ClockDetails) -> Self {
+        self.clocks.push(clock);
+        self
+    }
+    pub fn set_time(&mut self, time: u64) {
+        self.db.time = time;
+    }
+    // Run `f` with this writer collecting the notes made through the
+    // global `note` functions (including `note_time`).  Any database set
+    // up with `note_init_db` is restored afterwards.
+    pub fn capture<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let previous = DB.replace(Some(std::mem::take(&mut self.db)));
+        let ret = f();
+        self.db = DB.replace(previous).unwrap_or_default();
+        ret
+    }
+    pub fn write_vcd<W: Write>(&self, w: W) -> anyhow::Result<()> {
+        self.db.dump_vcd(&self.clocks, w)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::iter::repeat;
 
     use rhdl_bits::Bits;
 
-    use crate::{types::kind::Variant, Digital, DiscriminantAlignment, Kind};
+    use crate::{types::kind::Variant, Digital, DiscriminantAlignment, Kind, PayloadAlignment};
 
     use super::*;
 
@@ -584,21 +643,25 @@ mod tests {
                             name: "None".to_string(),
                             discriminant: 0,
                             kind: Kind::Empty,
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Bool".to_string(),
                             discriminant: 1,
                             kind: Kind::make_bits(1),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Tuple".to_string(),
                             discriminant: 2,
                             kind: Kind::make_tuple(vec![Kind::make_bits(1), Kind::make_bits(3)]),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Array".to_string(),
                             discriminant: 3,
                             kind: Kind::make_array(Kind::make_bits(1), 3),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Strct".to_string(),
@@ -610,6 +673,7 @@ mod tests {
                                     Kind::make_field("b", Kind::make_bits(3)),
                                 ],
                             ),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                     ],
                     Kind::make_discriminant_layout(
@@ -621,27 +685,27 @@ mod tests {
             }
             fn bin(self) -> Vec<bool> {
                 let raw = match self {
-                    Self::None => rhdl_bits::bits::<3>(0).to_bools(),
+                    Self::None => rhdl_bits::bits::<3>(0).to_bools().to_vec(),
                     Self::Bool(b) => {
-                        let mut v = rhdl_bits::bits::<3>(1).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(1).to_bools().to_vec();
                         v.extend(b.bin());
                         v
                     }
                     Self::Tuple(b, c) => {
-                        let mut v = rhdl_bits::bits::<3>(2).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(2).to_bools().to_vec();
                         v.extend(b.bin());
                         v.extend(c.bin());
                         v
                     }
                     Self::Array([b, c, d]) => {
-                        let mut v = rhdl_bits::bits::<3>(3).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(3).to_bools().to_vec();
                         v.extend(b.bin());
                         v.extend(c.bin());
                         v.extend(d.bin());
                         v
                     }
                     Self::Strct { a, b } => {
-                        let mut v = rhdl_bits::bits::<3>(4).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(4).to_bools().to_vec();
                         v.extend(a.bin());
                         v.extend(b.bin());
                         v
@@ -730,4 +794,33 @@ mod tests {
         db.dump_vcd(&[clock], &mut vcd).unwrap();
         std::fs::write("test_nested_paths.vcd", vcd).unwrap();
     }
+
+    #[test]
+    fn test_vcd_with_bitstring() {
+        note_init_db();
+        for i in 0..4 {
+            note_time(i * 1000);
+            let flags = [i % 2 == 1, i >= 2, true];
+            note("flags", crate::BitString(flags));
+            note("bools", flags);
+        }
+        let mut vcd = vec![];
+        let clock = ClockDetails::new("clk", 500, 0, false);
+        let db = note_take().unwrap();
+        db.dump_vcd(&[clock], &mut vcd).unwrap();
+        let vcd = String::from_utf8(vcd).unwrap();
+        // The bitstring is one 3 bit signal, with element 0 as the LSB,
+        // while the plain array is still noted element by element
+        let id_of = |width: usize, name: &str| {
+            vcd.lines()
+                .find_map(|line| {
+                    line.strip_prefix(&format!("$var wire {width} "))?
+                        .strip_suffix(&format!(" {name} $end"))
+                })
+                .unwrap_or_else(|| panic!("No {width} bit signal {name} in:\n{vcd}"))
+        };
+        let flags = id_of(3, "__flags");
+        assert!(vcd.contains(&format!("\nb101 {flags}\n")));
+        id_of(1, "__bools__0");
+    }
 }
@@ -3,13 +3,14 @@ use std::ops::Range;
 
 use anyhow::bail;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 use crate::ast::ast_impl::Member;
 use crate::rhif::spec::Slot;
 use crate::DiscriminantAlignment;
 use crate::Kind;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PathElement {
     Index(usize),
     Field(String),
@@ -19,7 +20,7 @@ pub enum PathElement {
     DynamicIndex(Slot),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct Path {
     pub elements: Vec<PathElement>,
 }
@@ -90,7 +91,7 @@ impl Path {
         self
     }
     pub fn join(mut self, other: &Path) -> Self {
-        self.elements.extend(other.elements.clone());
+        self.elements.extend_from_slice(&other.elements);
         self
     }
     pub fn is_empty(&self) -> bool {
@@ -119,12 +120,7 @@ impl Path {
         }
     }
     pub fn is_prefix_of(&self, other: &Path) -> bool {
-        self.elements.len() <= other.elements.len()
-            && self
-                .elements
-                .iter()
-                .zip(other.elements.iter())
-                .all(|(a, b)| a == b)
+        other.elements.starts_with(&self.elements)
     }
     pub fn strip_prefix(&self, prefix: &Path) -> Result<Path> {
         if !prefix.is_prefix_of(self) {
@@ -179,10 +175,33 @@ pub fn leaf_paths(kind: &Kind, base: Path) -> Vec<Path> {
             })
             .chain(once(base.clone().discriminant()))
             .collect(),
-        Kind::Bits(_) | Kind::Signed(_) | Kind::Empty => vec![base.clone()],
+        Kind::Bits(_) | Kind::Signed(_) | Kind::Empty | Kind::Ranged(_) => vec![base.clone()],
     }
 }
 
+// Given two values of the same kind (as bits), list the leaf paths
+// whose bits differ between them.  For enums, the payloads of all
+// variants are compared, so a change of variant shows up as both a
+// change in the discriminant and in any overlapping payloads.
+pub fn diff_paths(kind: &Kind, a: &[bool], b: &[bool]) -> Result<Vec<Path>> {
+    if a.len() != kind.bits() || b.len() != kind.bits() {
+        bail!(
+            "Cannot diff values of {} bits and {} bits as {kind} ({} bits)",
+            a.len(),
+            b.len(),
+            kind.bits()
+        );
+    }
+    let mut paths = vec![];
+    for path in leaf_paths(kind, Path::default()) {
+        let (range, _) = bit_range(kind.clone(), &path)?;
+        if a[range.clone()] != b[range] {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
 // Given a path and a kind, computes all possible paths that can be
 // generated from the base path using legal values for the dynamic
 // indices.
@@ -226,9 +245,27 @@ pub fn sub_kind(kind: Kind, path: &Path) -> Result<Kind> {
     bit_range(kind, path).map(|(_, kind)| kind)
 }
 
+// The bits of the endpoint of the path, without its kind.
+pub fn bit_offset(kind: &Kind, path: &Path) -> Result<Range<usize>> {
+    annotated_bit_range(kind.clone(), path).map(|(range, _)| range)
+}
+
+// The bounds of the (ranged) integer at the endpoint of the path, if it
+// has any.
+pub fn range_bounds(kind: &Kind, path: &Path) -> Result<Option<(i128, i128)>> {
+    annotated_bit_range(kind.clone(), path).map(|(_, kind)| kind.range())
+}
+
 // Given a Kind and a Vec<Path>, compute the bit offsets of
 // the endpoint of the path within the original data structure.
+// Any range attached to the endpoint is dropped, since it is not
+// part of the type of the value.
 pub fn bit_range(kind: Kind, path: &Path) -> Result<(Range<usize>, Kind)> {
+    let (range, kind) = annotated_bit_range(kind, path)?;
+    Ok((range, kind.unranged().clone()))
+}
+
+fn annotated_bit_range(kind: Kind, path: &Path) -> Result<(Range<usize>, Kind)> {
     let mut range = 0..kind.bits();
     let mut kind = kind;
     for p in &path.elements {
@@ -313,41 +350,27 @@ pub fn bit_range(kind: Kind, path: &Path) -> Result<(Range<usize>, Kind)> {
             },
             PathElement::EnumPayload(name) => match &kind {
                 Kind::Enum(enumerate) => {
-                    let field = enumerate
+                    let variant = enumerate
                         .variants
                         .iter()
                         .find(|f| &f.name == name)
-                        .ok_or_else(|| anyhow::anyhow!("Enum payload not found"))?
-                        .kind
-                        .clone();
-                    range = match enumerate.discriminant_layout.alignment {
-                        DiscriminantAlignment::Lsb => {
-                            range.start + enumerate.discriminant_layout.width
-                                ..range.start + enumerate.discriminant_layout.width + field.bits()
-                        }
-                        DiscriminantAlignment::Msb => range.start..range.start + field.bits(),
-                    };
-                    kind = field;
+                        .ok_or_else(|| anyhow::anyhow!("Enum payload not found"))?;
+                    let payload = enumerate.payload_range(variant);
+                    range = range.start + payload.start..range.start + payload.end;
+                    kind = variant.kind.clone();
                 }
                 _ => bail!("Enum payload not valid for non-enum types"),
             },
             PathElement::EnumPayloadByValue(disc) => match &kind {
                 Kind::Enum(enumerate) => {
-                    let field = enumerate
+                    let variant = enumerate
                         .variants
                         .iter()
                         .find(|f| f.discriminant == *disc)
-                        .ok_or_else(|| anyhow::anyhow!("Enum payload not found"))?
-                        .kind
-                        .clone();
-                    range = match enumerate.discriminant_layout.alignment {
-                        DiscriminantAlignment::Lsb => {
-                            range.start + enumerate.discriminant_layout.width
-                                ..range.start + enumerate.discriminant_layout.width + field.bits()
-                        }
-                        DiscriminantAlignment::Msb => range.start..range.start + field.bits(),
-                    };
-                    kind = field;
+                        .ok_or_else(|| anyhow::anyhow!("Enum payload not found"))?;
+                    let payload = enumerate.payload_range(variant);
+                    range = range.start + payload.start..range.start + payload.end;
+                    kind = variant.kind.clone();
                 }
                 _ => bail!("Enum payload not valid for non-enum types"),
             },
@@ -361,7 +384,12 @@ pub fn bit_range(kind: Kind, path: &Path) -> Result<(Range<usize>, Kind)> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{path::path_star, rhif::spec::Slot, types::kind::DiscriminantLayout, Kind};
+    use crate::{
+        path::path_star,
+        rhif::spec::Slot,
+        types::kind::{DiscriminantLayout, PayloadAlignment},
+        DiscriminantAlignment, Kind,
+    };
 
     use super::{leaf_paths, Path};
 
@@ -404,6 +432,88 @@ mod tests {
         assert!(bit_mask.iter().all(|b| *b));
     }
 
+    #[test]
+    fn test_bit_offset_matches_bit_range() {
+        let inner = Kind::make_struct(
+            "inner",
+            vec![
+                Kind::make_field("a", Kind::make_bits(3)),
+                Kind::make_field("b", Kind::make_signed(5)),
+            ],
+        );
+        let kind = Kind::make_tuple(vec![
+            Kind::make_bits(4),
+            Kind::make_array(inner.clone(), 3),
+            Kind::make_enum(
+                "choice",
+                vec![
+                    Kind::make_variant("None", Kind::Empty, 0),
+                    Kind::make_variant("Some", inner, 1),
+                ],
+                DiscriminantLayout {
+                    width: 1,
+                    alignment: crate::DiscriminantAlignment::Msb,
+                    ty: crate::DiscriminantType::Unsigned,
+                },
+            ),
+        ]);
+        let paths = [
+            Path::default(),
+            Path::default().index(0),
+            Path::default().index(1).index(2).field("b"),
+            Path::default().index(2).discriminant(),
+            Path::default().index(2).payload("Some").field("a"),
+        ];
+        for path in paths
+            .iter()
+            .chain(leaf_paths(&kind, Path::default()).iter())
+        {
+            let (range, _) = super::bit_range(kind.clone(), path).unwrap();
+            assert_eq!(super::bit_offset(&kind, path).unwrap(), range);
+        }
+        assert_eq!(
+            super::bit_offset(&kind, &Path::default().index(1).index(2).field("b")).unwrap(),
+            23..28
+        );
+        assert!(super::bit_offset(&kind, &Path::default().index(3)).is_err());
+    }
+
+    #[test]
+    fn test_bit_range_mixed_payload_alignment() {
+        // The payload region is the 8 bits not used by the discriminant.
+        // A narrow payload aligned to the MSB sits at the top of the
+        // region, and one aligned to the LSB sits at the bottom.
+        let variants = vec![
+            Kind::make_variant("Wide", Kind::make_bits(8), 0),
+            Kind::make_variant("High", Kind::make_bits(3), 1)
+                .with_payload_alignment(PayloadAlignment::Msb),
+            Kind::make_variant("Low", Kind::make_bits(3), 2),
+        ];
+        let layout = |alignment| DiscriminantLayout {
+            width: 2,
+            alignment,
+            ty: crate::DiscriminantType::Unsigned,
+        };
+        let kind = Kind::make_enum(
+            "mixed",
+            variants.clone(),
+            layout(DiscriminantAlignment::Msb),
+        );
+        let range = |path: Path| super::bit_range(kind.clone(), &path).unwrap().0;
+        assert_eq!(range(Path::default().discriminant()), 8..10);
+        assert_eq!(range(Path::default().payload("Wide")), 0..8);
+        assert_eq!(range(Path::default().payload("High")), 5..8);
+        assert_eq!(range(Path::default().payload("Low")), 0..3);
+        assert_eq!(range(Path::default().payload_by_value(1)), 5..8);
+        let kind = Kind::make_enum("mixed", variants, layout(DiscriminantAlignment::Lsb));
+        let range = |path: Path| super::bit_range(kind.clone(), &path).unwrap().0;
+        assert_eq!(range(Path::default().discriminant()), 0..2);
+        assert_eq!(range(Path::default().payload("Wide")), 2..10);
+        assert_eq!(range(Path::default().payload("High")), 7..10);
+        assert_eq!(range(Path::default().payload("Low")), 2..5);
+        assert_eq!(range(Path::default().payload_by_value(1)), 7..10);
+    }
+
     #[test]
     fn test_path_star() {
         let base_struct = Kind::make_struct(
@@ -0,0 +1,1518 @@
+// A faster engine for the RHIF of a design, for long simulations of big
+// designs.  The interpreter in `vm` walks the opcodes of an object, and
+// allocates a `TypedBits` (a vector of bools and a kind) for each value
+// that it computes.  Here the objects are instead flattened ahead of time
+// into a linear list of operations on the cells of a frame.  Each cell
+// holds a value as a single `u128` word, and the slots, paths, widths and
+// masks of an operation are all resolved when it is compiled, so that
+// running an operation is a few integer instructions.
+//
+// Only objects whose values all fit in 128 bits can be compiled, and
+// anything that the interpreter would reject whatever the arguments (say,
+// adding values of different kinds) is refused rather than compiled.  In
+// both cases `Program::compile` fails, and the design has to run on the
+// interpreter instead.  Results of pure kernels are not memoized, and
+// coverage is not collected, as neither is needed for speed.
+//
+// The `Simulator` picks the engine for a design: by default it compiles
+// designs of at least `BYTECODE_THRESHOLD` opcodes, and interprets the
+// rest.  `Engine::Checked` runs both engines on every call and fails if
+// they disagree, which is how the engine is tested.
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use smallvec::SmallVec;
+
+use crate::ast::ast_impl::FunctionId;
+use crate::kernel::ExternalKernelDef;
+use crate::path::{bit_range, Path, PathElement};
+use crate::rhif::spec::{
+    AluBinary, AluUnary, Array, Assert, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
+    ExternalFunctionCode, Index, Member, OpCode, Repeat, Select, Slot, Splice, Struct, Tuple,
+    Unary,
+};
+use crate::rhif::vm::execute_function;
+use crate::rhif::{Module, Object};
+use crate::{Kind, TypedBits};
+
+// Designs with fewer opcodes than this (over all of their objects, and
+// not counting comments) are interpreted, since compiling them costs
+// more than it saves.
+pub const BYTECODE_THRESHOLD: usize = 64;
+
+type VMFunction = fn(&[TypedBits]) -> Result<TypedBits>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// The index of a cell in the frame of a function.
+type Cell = usize;
+
+// A dynamic index into an array: the cell holding the index, the number
+// of bits in each element, and the number of elements.
+type Stride = (Cell, u32, usize);
+
+#[derive(Debug, Clone)]
+enum Op {
+    Copy {
+        lhs: Cell,
+        arg: Cell,
+    },
+    Add {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+        mask: u128,
+    },
+    Sub {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+        mask: u128,
+    },
+    Mul {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+        mask: u128,
+    },
+    And {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+    },
+    Or {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+    },
+    Xor {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+    },
+    Eq {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+    },
+    Ne {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+    },
+    // Signed values are compared after moving their sign bit to the top
+    // of the word, by `shift` bits.
+    Compare {
+        lhs: Cell,
+        arg1: Cell,
+        arg2: Cell,
+        compare: Compare,
+        signed: bool,
+        shift: u32,
+    },
+    Shl {
+        lhs: Cell,
+        arg: Cell,
+        amount: Cell,
+        width: u32,
+        mask: u128,
+    },
+    Shr {
+        lhs: Cell,
+        arg: Cell,
+        amount: Cell,
+        width: u32,
+    },
+    Sar {
+        lhs: Cell,
+        arg: Cell,
+        amount: Cell,
+        width: u32,
+        mask: u128,
+    },
+    Not {
+        lhs: Cell,
+        arg: Cell,
+        mask: u128,
+    },
+    Neg {
+        lhs: Cell,
+        arg: Cell,
+        mask: u128,
+    },
+    Any {
+        lhs: Cell,
+        arg: Cell,
+    },
+    All {
+        lhs: Cell,
+        arg: Cell,
+        mask: u128,
+    },
+    Parity {
+        lhs: Cell,
+        arg: Cell,
+    },
+    Select {
+        lhs: Cell,
+        cond: Cell,
+        true_value: Cell,
+        false_value: Cell,
+    },
+    Extract {
+        lhs: Cell,
+        arg: Cell,
+        offset: u32,
+        mask: u128,
+    },
+    Insert {
+        lhs: Cell,
+        orig: Cell,
+        subst: Cell,
+        offset: u32,
+        mask: u128,
+    },
+    DynamicExtract {
+        lhs: Cell,
+        arg: Cell,
+        offset: u32,
+        strides: Box<[Stride]>,
+        mask: u128,
+    },
+    DynamicInsert {
+        lhs: Cell,
+        orig: Cell,
+        subst: Cell,
+        offset: u32,
+        strides: Box<[Stride]>,
+        mask: u128,
+    },
+    // Pack the cells into one value, each at its offset.
+    Concat {
+        lhs: Cell,
+        fields: Box<[(Cell, u32)]>,
+    },
+    Repeat {
+        lhs: Cell,
+        arg: Cell,
+        width: u32,
+        count: usize,
+    },
+    // The first arm whose constant matches the discriminant (or that has
+    // no constant) gives the value.
+    Case {
+        lhs: Cell,
+        discriminant: Cell,
+        table: Box<[(Option<u128>, Cell)]>,
+    },
+    // The kind of the argument is only needed to report a failed cast.
+    UnsignedCast {
+        lhs: Cell,
+        arg: Cell,
+        width: u32,
+        len: u32,
+        kind: Box<Kind>,
+    },
+    SignedCast {
+        lhs: Cell,
+        arg: Cell,
+        width: u32,
+        len: u32,
+        kind: Box<Kind>,
+    },
+    Call {
+        lhs: Cell,
+        func: usize,
+        args: Box<[Cell]>,
+    },
+    Extern {
+        lhs: Cell,
+        name: Box<str>,
+        stub: VMFunction,
+        args: Box<[(Cell, Kind)]>,
+        ret: Box<Kind>,
+    },
+    Assert {
+        cond: Cell,
+        message: Box<str>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+    name: String,
+    ops: Vec<Op>,
+    // The cells as a call starts, with the literals (and the templates of
+    // structs and enums) filled in.
+    frame: Vec<u128>,
+    arguments: Vec<(Cell, Kind)>,
+    result: Cell,
+    result_kind: Kind,
+}
+
+// A design compiled for the bytecode engine.
+#[derive(Debug, Clone)]
+pub struct Program {
+    functions: Vec<Function>,
+    top: usize,
+}
+
+fn mask(width: u32) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+fn width(kind: &Kind) -> Result<u32> {
+    let bits = kind.bits();
+    ensure!(
+        bits <= 128,
+        "The bytecode engine cannot hold a {kind}, as it has {bits} bits (rather than at most 128)"
+    );
+    Ok(bits as u32)
+}
+
+fn to_word(value: &TypedBits) -> u128 {
+    value
+        .bits
+        .iter()
+        .rev()
+        .fold(0, |word, bit| (word << 1) | (*bit as u128))
+}
+
+fn to_typed_bits(word: u128, kind: &Kind) -> TypedBits {
+    TypedBits {
+        bits: (0..kind.bits()).map(|ndx| (word >> ndx) & 1 != 0).collect(),
+        kind: kind.clone(),
+    }
+}
+
+fn bit(word: u128, ndx: u32) -> bool {
+    ndx < 128 && (word >> ndx) & 1 != 0
+}
+
+struct Compiler<'a> {
+    design: &'a Module,
+    // `None` while a function is being compiled, so that recursion can be
+    // caught.
+    functions: Vec<Option<Function>>,
+    index: HashMap<FunctionId, usize>,
+}
+
+impl<'a> Compiler<'a> {
+    fn function(&mut self, fn_id: FunctionId) -> Result<usize> {
+        if let Some(func) = self.index.get(&fn_id) {
+            ensure!(
+                self.functions[*func].is_some(),
+                "Function {fn_id} calls itself"
+            );
+            return Ok(*func);
+        }
+        let obj = self
+            .design
+            .objects
+            .get(&fn_id)
+            .ok_or(anyhow!("Function {fn_id} not found"))?;
+        let func = self.functions.len();
+        self.functions.push(None);
+        self.index.insert(fn_id, func);
+        let compiled = FunctionBuilder::new(obj)?.build(self)?;
+        self.functions[func] = Some(compiled);
+        Ok(func)
+    }
+}
+
+struct FunctionBuilder<'a> {
+    obj: &'a Object,
+    frame: Vec<u128>,
+    // The kind of the value that each cell holds at this point of the
+    // function (as the interpreter would compute it), or `None` if it has
+    // not been written yet.
+    kinds: Vec<Option<Kind>>,
+    literals: HashMap<Slot, Cell>,
+    empty: Cell,
+    ops: Vec<Op>,
+}
+
+impl<'a> FunctionBuilder<'a> {
+    fn new(obj: &'a Object) -> Result<Self> {
+        let registers = obj.reg_max_index() + 1;
+        let mut builder = FunctionBuilder {
+            obj,
+            frame: vec![0; registers],
+            kinds: vec![None; registers],
+            literals: HashMap::new(),
+            empty: 0,
+            ops: vec![],
+        };
+        builder.empty = builder.constant(&TypedBits::EMPTY)?;
+        for (slot, value) in &obj.literals {
+            let cell = builder.constant(value)?;
+            builder.literals.insert(*slot, cell);
+        }
+        for slot in &obj.arguments {
+            let kind = obj
+                .kind
+                .get(slot)
+                .ok_or(anyhow!("ICE argument {slot} type not found in object"))?;
+            width(kind)?;
+            if let Slot::Register(r) = slot {
+                builder.kinds[*r] = Some(kind.clone());
+            }
+        }
+        Ok(builder)
+    }
+    fn constant(&mut self, value: &TypedBits) -> Result<Cell> {
+        width(&value.kind)?;
+        self.frame.push(to_word(value));
+        self.kinds.push(Some(value.kind.clone()));
+        Ok(self.frame.len() - 1)
+    }
+    fn cell(&self, slot: Slot) -> Result<Cell> {
+        match slot {
+            Slot::Register(r) if r < self.frame.len() => Ok(r),
+            Slot::Register(r) => bail!("ICE Register {r} not found in register stack"),
+            Slot::Literal(l) => self
+                .literals
+                .get(&slot)
+                .copied()
+                .ok_or(anyhow!("ICE Literal {l} not found in object")),
+            Slot::Empty => Ok(self.empty),
+        }
+    }
+    fn read(&self, slot: Slot) -> Result<(Cell, Kind)> {
+        let cell = self.cell(slot)?;
+        let kind = self.kinds[cell]
+            .clone()
+            .ok_or(anyhow!("ICE Register {slot} is not initialized"))?;
+        Ok((cell, kind))
+    }
+    fn write(&mut self, slot: Slot, kind: Kind) -> Result<Cell> {
+        width(&kind)?;
+        match slot {
+            Slot::Literal(_) => bail!("ICE Cannot write to literal"),
+            Slot::Empty => {
+                ensure!(
+                    kind.is_empty(),
+                    "ICE Cannot write non-empty value to empty slot"
+                );
+                Ok(self.empty)
+            }
+            Slot::Register(_) => {
+                let cell = self.cell(slot)?;
+                self.kinds[cell] = Some(kind);
+                Ok(cell)
+            }
+        }
+    }
+    // Resolve a path into a value of the given kind to the offset of the
+    // bits it names, the dynamic indices that move it, and the kind of
+    // the value there.  The offsets and kinds come from `bit_range`, with
+    // each dynamic index set to zero, so that they match the interpreter.
+    fn place(&self, kind: &Kind, path: &Path) -> Result<(u32, Box<[Stride]>, Kind)> {
+        let mut prefix = Path::default();
+        let mut strides = vec![];
+        for element in &path.elements {
+            if let PathElement::DynamicIndex(slot) = element {
+                let (cell, index_kind) = self.read(*slot)?;
+                let Kind::Bits(bits) = index_kind else {
+                    bail!("Cannot index with a {index_kind}");
+                };
+                ensure!(bits <= 64, "Cannot index with a {index_kind}");
+                let (_, array) = bit_range(kind.clone(), &prefix)?;
+                let Kind::Array(array) = array else {
+                    bail!("Dynamic index on non-array type")
+                };
+                strides.push((cell, width(&array.base)?, array.size));
+                prefix.elements.push(PathElement::Index(0));
+            } else {
+                prefix.elements.push(element.clone());
+            }
+        }
+        let (range, kind) = bit_range(kind.clone(), &prefix)?;
+        Ok((range.start as u32, strides.into(), kind))
+    }
+    fn build(mut self, compiler: &mut Compiler) -> Result<Function> {
+        for op in &self.obj.ops {
+            self.op(op, compiler)?;
+        }
+        let (result, result_kind) = self.read(self.obj.return_slot)?;
+        let arguments = self
+            .obj
+            .arguments
+            .iter()
+            .map(|slot| Ok((self.cell(*slot)?, self.obj.kind[slot].clone())))
+            .collect::<Result<_>>()?;
+        Ok(Function {
+            name: self.obj.name.clone(),
+            ops: self.ops,
+            frame: self.frame,
+            arguments,
+            result,
+            result_kind,
+        })
+    }
+    fn binary(&mut self, binary: &Binary) -> Result<()> {
+        let Binary {
+            op,
+            lhs,
+            arg1,
+            arg2,
+        } = binary;
+        let (arg1, kind1) = self.read(*arg1)?;
+        let (arg2, kind2) = self.read(*arg2)?;
+        let bits = width(&kind1)?;
+        if matches!(op, AluBinary::Shl | AluBinary::Shr) {
+            ensure!(!kind1.is_composite(), "Cannot shift composite {kind1}");
+            ensure!(
+                matches!(kind2, Kind::Bits(bits) if bits <= 64),
+                "Cannot shift by a {kind2}"
+            );
+        } else {
+            ensure!(
+                kind1 == kind2,
+                "Cannot combine {kind1} with {kind2}, as they are different types"
+            );
+        }
+        if matches!(
+            op,
+            AluBinary::BitXor | AluBinary::BitAnd | AluBinary::BitOr | AluBinary::Mul
+        ) {
+            ensure!(!kind1.is_composite(), "Cannot combine composite {kind1}");
+        }
+        let signed = !kind1.is_unsigned() && bits > 0;
+        let compare = |compare| Op::Compare {
+            lhs: 0,
+            arg1,
+            arg2,
+            compare,
+            signed,
+            shift: 128 - bits.max(1),
+        };
+        let (op, kind) = match op {
+            AluBinary::Add => (
+                Op::Add {
+                    lhs: 0,
+                    arg1,
+                    arg2,
+                    mask: mask(bits),
+                },
+                kind1,
+            ),
+            AluBinary::Sub => (
+                Op::Sub {
+                    lhs: 0,
+                    arg1,
+                    arg2,
+                    mask: mask(bits),
+                },
+                kind1,
+            ),
+            AluBinary::BitXor => (Op::Xor { lhs: 0, arg1, arg2 }, kind1),
+            AluBinary::BitAnd => (Op::And { lhs: 0, arg1, arg2 }, kind1),
+            AluBinary::BitOr => (Op::Or { lhs: 0, arg1, arg2 }, kind1),
+            AluBinary::Eq => (Op::Eq { lhs: 0, arg1, arg2 }, Kind::make_bool()),
+            AluBinary::Ne => (Op::Ne { lhs: 0, arg1, arg2 }, Kind::make_bool()),
+            AluBinary::Lt => (compare(Compare::Lt), Kind::make_bool()),
+            AluBinary::Le => (compare(Compare::Le), Kind::make_bool()),
+            AluBinary::Gt => (compare(Compare::Gt), Kind::make_bool()),
+            AluBinary::Ge => (compare(Compare::Ge), Kind::make_bool()),
+            AluBinary::Shl => (
+                Op::Shl {
+                    lhs: 0,
+                    arg: arg1,
+                    amount: arg2,
+                    width: bits,
+                    mask: mask(bits),
+                },
+                kind1,
+            ),
+            AluBinary::Shr if kind1.is_signed() => (
+                Op::Sar {
+                    lhs: 0,
+                    arg: arg1,
+                    amount: arg2,
+                    width: bits,
+                    mask: mask(bits),
+                },
+                kind1,
+            ),
+            AluBinary::Shr => (
+                Op::Shr {
+                    lhs: 0,
+                    arg: arg1,
+                    amount: arg2,
+                    width: bits,
+                },
+                kind1,
+            ),
+            AluBinary::Mul => (
+                Op::Mul {
+                    lhs: 0,
+                    arg1,
+                    arg2,
+                    mask: mask(bits),
+                },
+                kind1,
+            ),
+        };
+        let lhs = self.write(*lhs, kind)?;
+        self.emit(op, lhs);
+        Ok(())
+    }
+    fn unary(&mut self, unary: &Unary) -> Result<()> {
+        let Unary { op, lhs, arg1 } = unary;
+        let (arg, kind) = self.read(*arg1)?;
+        let bits = width(&kind)?;
+        let (op, kind) = match op {
+            AluUnary::Not => {
+                ensure!(!kind.is_composite(), "Cannot negate composite {kind}");
+                (
+                    Op::Not {
+                        lhs: 0,
+                        arg,
+                        mask: mask(bits),
+                    },
+                    kind,
+                )
+            }
+            AluUnary::Neg => {
+                ensure!(kind.is_signed(), "Only signed values can be negated");
+                (
+                    Op::Neg {
+                        lhs: 0,
+                        arg,
+                        mask: mask(bits),
+                    },
+                    kind,
+                )
+            }
+            AluUnary::All => (
+                Op::All {
+                    lhs: 0,
+                    arg,
+                    mask: mask(bits),
+                },
+                Kind::make_bool(),
+            ),
+            AluUnary::Any => (Op::Any { lhs: 0, arg }, Kind::make_bool()),
+            AluUnary::Xor => (Op::Parity { lhs: 0, arg }, Kind::make_bool()),
+            AluUnary::Signed => {
+                let Kind::Bits(bits) = kind else {
+                    bail!("Cannot cast {kind} to signed")
+                };
+                (Op::Copy { lhs: 0, arg }, Kind::make_signed(bits))
+            }
+            AluUnary::Unsigned => {
+                let Kind::Signed(bits) = kind else {
+                    bail!("Cannot cast {kind} to unsigned")
+                };
+                (Op::Copy { lhs: 0, arg }, Kind::make_bits(bits))
+            }
+        };
+        let lhs = self.write(*lhs, kind)?;
+        self.emit(op, lhs);
+        Ok(())
+    }
+    // Fill in the destination of an operation, which is only known once
+    // the kind of its result is.
+    fn emit(&mut self, mut op: Op, cell: Cell) {
+        match &mut op {
+            Op::Copy { lhs, .. }
+            | Op::Add { lhs, .. }
+            | Op::Sub { lhs, .. }
+            | Op::Mul { lhs, .. }
+            | Op::And { lhs, .. }
+            | Op::Or { lhs, .. }
+            | Op::Xor { lhs, .. }
+            | Op::Eq { lhs, .. }
+            | Op::Ne { lhs, .. }
+            | Op::Compare { lhs, .. }
+            | Op::Shl { lhs, .. }
+            | Op::Shr { lhs, .. }
+            | Op::Sar { lhs, .. }
+            | Op::Not { lhs, .. }
+            | Op::Neg { lhs, .. }
+            | Op::Any { lhs, .. }
+            | Op::All { lhs, .. }
+            | Op::Parity { lhs, .. }
+            | Op::Select { lhs, .. }
+            | Op::Extract { lhs, .. }
+            | Op::Insert { lhs, .. }
+            | Op::DynamicExtract { lhs, .. }
+            | Op::DynamicInsert { lhs, .. }
+            | Op::Concat { lhs, .. }
+            | Op::Repeat { lhs, .. }
+            | Op::Case { lhs, .. }
+            | Op::UnsignedCast { lhs, .. }
+            | Op::SignedCast { lhs, .. }
+            | Op::Call { lhs, .. }
+            | Op::Extern { lhs, .. } => *lhs = cell,
+            Op::Assert { .. } => {}
+        }
+        self.ops.push(op);
+    }
+    // Build a struct or enum by splicing the fields into `base`.
+    fn splice_fields(
+        &mut self,
+        lhs: Slot,
+        base: Cell,
+        kind: Kind,
+        fields: &[(Path, Slot)],
+    ) -> Result<()> {
+        let values = fields
+            .iter()
+            .map(|(path, value)| {
+                let (value, value_kind) = self.read(*value)?;
+                let (offset, strides, field_kind) = self.place(&kind, path)?;
+                ensure!(strides.is_empty(), "ICE dynamic path in a field");
+                ensure!(
+                    field_kind == value_kind,
+                    "Cannot update {kind}{path} with a {value_kind}"
+                );
+                Ok((value, offset, width(&field_kind)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let lhs = self.write(lhs, kind)?;
+        ensure!(
+            values.iter().all(|(value, _, _)| *value != lhs),
+            "ICE field value overwritten by its struct"
+        );
+        self.ops.push(Op::Copy { lhs, arg: base });
+        for (subst, offset, bits) in values {
+            self.ops.push(Op::Insert {
+                lhs,
+                orig: lhs,
+                subst,
+                offset,
+                mask: mask(bits),
+            });
+        }
+        Ok(())
+    }
+    fn op(&mut self, op: &OpCode, compiler: &mut Compiler) -> Result<()> {
+        match op {
+            OpCode::Noop | OpCode::Comment(_) => {}
+            OpCode::Assert(Assert { cond, message }) => {
+                let (cond, _) = self.read(*cond)?;
+                self.ops.push(Op::Assert {
+                    cond,
+                    message: message.as_str().into(),
+                });
+            }
+            OpCode::Binary(binary) => self.binary(binary)?,
+            OpCode::Unary(unary) => self.unary(unary)?,
+            OpCode::Select(Select {
+                lhs,
+                cond,
+                true_value,
+                false_value,
+            }) => {
+                let (cond, _) = self.read(*cond)?;
+                let (true_value, kind) = self.read(*true_value)?;
+                let (false_value, false_kind) = self.read(*false_value)?;
+                ensure!(
+                    kind == false_kind,
+                    "Cannot select between a {kind} and a {false_kind}"
+                );
+                let lhs = self.write(*lhs, kind)?;
+                self.ops.push(Op::Select {
+                    lhs,
+                    cond,
+                    true_value,
+                    false_value,
+                });
+            }
+            OpCode::Index(Index { lhs, arg, path }) => {
+                let (arg, kind) = self.read(*arg)?;
+                let (offset, strides, kind) = self.place(&kind, path)?;
+                let mask = mask(width(&kind)?);
+                let lhs = self.write(*lhs, kind)?;
+                self.ops.push(if strides.is_empty() {
+                    Op::Extract {
+                        lhs,
+                        arg,
+                        offset,
+                        mask,
+                    }
+                } else {
+                    Op::DynamicExtract {
+                        lhs,
+                        arg,
+                        offset,
+                        strides,
+                        mask,
+                    }
+                });
+            }
+            OpCode::Splice(Splice {
+                lhs,
+                orig,
+                path,
+                subst,
+            }) => {
+                let (orig, kind) = self.read(*orig)?;
+                let (subst, subst_kind) = self.read(*subst)?;
+                let (offset, strides, field_kind) = self.place(&kind, path)?;
+                ensure!(
+                    field_kind == subst_kind,
+                    "Cannot update {kind}{path} with a {subst_kind}"
+                );
+                let mask = mask(width(&field_kind)?);
+                let lhs = self.write(*lhs, kind)?;
+                self.ops.push(if strides.is_empty() {
+                    Op::Insert {
+                        lhs,
+                        orig,
+                        subst,
+                        offset,
+                        mask,
+                    }
+                } else {
+                    Op::DynamicInsert {
+                        lhs,
+                        orig,
+                        subst,
+                        offset,
+                        strides,
+                        mask,
+                    }
+                });
+            }
+            OpCode::Assign(Assign { lhs, rhs }) => {
+                let (arg, kind) = self.read(*rhs)?;
+                let lhs = self.write(*lhs, kind)?;
+                self.ops.push(Op::Copy { lhs, arg });
+            }
+            OpCode::Tuple(Tuple { lhs, fields }) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| self.read(*field))
+                    .collect::<Result<Vec<_>>>()?;
+                let kind = Kind::make_tuple(fields.iter().map(|(_, kind)| kind.clone()).collect());
+                self.concat(*lhs, kind, fields)?;
+            }
+            OpCode::Array(Array { lhs, elements }) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.read(*element))
+                    .collect::<Result<Vec<_>>>()?;
+                let Some((_, base)) = elements.first() else {
+                    bail!("Cannot build an array with no elements");
+                };
+                let kind = Kind::make_array(base.clone(), elements.len());
+                self.concat(*lhs, kind, elements)?;
+            }
+            OpCode::Struct(Struct {
+                lhs,
+                fields,
+                rest,
+                template,
+            }) => {
+                let (base, kind) = match rest {
+                    Some(rest) => self.read(*rest)?,
+                    None => (self.constant(template)?, template.kind.clone()),
+                };
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        let path = match &field.member {
+                            Member::Unnamed(ndx) => Path::default().index(*ndx as usize),
+                            Member::Named(name) => Path::default().field(name),
+                        };
+                        (path, field.value)
+                    })
+                    .collect::<Vec<_>>();
+                self.splice_fields(*lhs, base, kind, &fields)?;
+            }
+            OpCode::Enum(Enum {
+                lhs,
+                fields,
+                template,
+            }) => {
+                let base = self.constant(template)?;
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        let discriminant = template.discriminant()?.as_i64()?;
+                        let base_path = Path::default().payload_by_value(discriminant);
+                        let path = match &field.member {
+                            Member::Unnamed(ndx) => base_path.index(*ndx as usize),
+                            Member::Named(name) => base_path.field(name),
+                        };
+                        Ok((path, field.value))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.splice_fields(*lhs, base, template.kind.clone(), &fields)?;
+            }
+            OpCode::Case(Case {
+                lhs,
+                discriminant,
+                table,
+            }) => {
+                let (discriminant, discriminant_kind) = self.read(*discriminant)?;
+                let mut arms = vec![];
+                let mut kind = None;
+                for (argument, value) in table {
+                    let constant = match argument {
+                        // A constant of another kind never equals the
+                        // discriminant.
+                        CaseArgument::Constant(constant) if constant.kind != discriminant_kind => {
+                            continue
+                        }
+                        CaseArgument::Constant(constant) => Some(to_word(constant)),
+                        CaseArgument::Wild => None,
+                    };
+                    let (value, value_kind) = self.read(*value)?;
+                    let arm_kind = kind.get_or_insert_with(|| value_kind.clone());
+                    ensure!(
+                        *arm_kind == value_kind,
+                        "Cannot match to both a {arm_kind} and a {value_kind}"
+                    );
+                    arms.push((constant, value));
+                    if constant.is_none() {
+                        break;
+                    }
+                }
+                let kind = kind.ok_or(anyhow!("ICE Case was not exhaustive"))?;
+                let lhs = self.write(*lhs, kind)?;
+                self.ops.push(Op::Case {
+                    lhs,
+                    discriminant,
+                    table: arms.into(),
+                });
+            }
+            OpCode::AsBits(Cast { lhs, arg, len }) | OpCode::AsSigned(Cast { lhs, arg, len }) => {
+                let (arg, kind) = self.read(*arg)?;
+                let bits = width(&kind)?;
+                let signed = matches!(op, OpCode::AsSigned(_));
+                let result = if signed {
+                    Kind::make_signed(*len)
+                } else {
+                    Kind::make_bits(*len)
+                };
+                let len = width(&result)?;
+                let lhs = self.write(*lhs, result)?;
+                let kind = Box::new(kind);
+                self.ops.push(if signed {
+                    Op::SignedCast {
+                        lhs,
+                        arg,
+                        width: bits,
+                        len,
+                        kind,
+                    }
+                } else {
+                    Op::UnsignedCast {
+                        lhs,
+                        arg,
+                        width: bits,
+                        len,
+                        kind,
+                    }
+                });
+            }
+            OpCode::Exec(Exec { lhs, id, args }) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.read(*arg))
+                    .collect::<Result<Vec<_>>>()?;
+                let external = &self.obj.externals[id.0];
+                match &external.code {
+                    ExternalFunctionCode::Kernel(kernel) => {
+                        let func = compiler.function(kernel.inner().fn_id)?;
+                        let callee = compiler.functions[func]
+                            .as_ref()
+                            .ok_or(anyhow!("ICE function {} is not compiled", external.path))?;
+                        ensure!(
+                            callee.arguments.len() == args.len()
+                                && callee
+                                    .arguments
+                                    .iter()
+                                    .zip(&args)
+                                    .all(|((_, expected), (_, kind))| expected == kind),
+                            "Function {} called with the wrong arguments",
+                            external.path
+                        );
+                        let kind = callee.result_kind.clone();
+                        let lhs = self.write(*lhs, kind)?;
+                        self.ops.push(Op::Call {
+                            lhs,
+                            func,
+                            args: args.into_iter().map(|(cell, _)| cell).collect(),
+                        });
+                    }
+                    ExternalFunctionCode::Extern(ExternalKernelDef {
+                        name,
+                        body: _,
+                        vm_stub,
+                    }) => {
+                        let Some(stub) = vm_stub else {
+                            bail!("No VM stub for {name}")
+                        };
+                        let ret = external.signature.ret.clone();
+                        let lhs = self.write(*lhs, ret.clone())?;
+                        self.ops.push(Op::Extern {
+                            lhs,
+                            name: name.as_str().into(),
+                            stub: *stub,
+                            args: args.into(),
+                            ret: Box::new(ret),
+                        });
+                    }
+                }
+            }
+            OpCode::Repeat(Repeat { lhs, value, len }) => {
+                let (arg, kind) = self.read(*value)?;
+                let bits = width(&kind)?;
+                let lhs = self.write(*lhs, Kind::make_array(kind, *len))?;
+                self.ops.push(Op::Repeat {
+                    lhs,
+                    arg,
+                    width: bits,
+                    count: *len,
+                });
+            }
+        }
+        Ok(())
+    }
+    fn concat(&mut self, lhs: Slot, kind: Kind, fields: Vec<(Cell, Kind)>) -> Result<()> {
+        let mut offset = 0;
+        let fields = fields
+            .into_iter()
+            .map(|(cell, kind)| {
+                let field = (cell, offset);
+                offset += width(&kind)?;
+                Ok(field)
+            })
+            .collect::<Result<_>>()?;
+        let lhs = self.write(lhs, kind)?;
+        self.ops.push(Op::Concat { lhs, fields });
+        Ok(())
+    }
+}
+
+// The bit offset of a place with dynamic indices, or an error if one of
+// them is out of bounds.
+fn dynamic_offset(frame: &[u128], offset: u32, strides: &[Stride]) -> Result<u32> {
+    strides
+        .iter()
+        .try_fold(offset, |offset, (cell, stride, size)| {
+            let index = frame[*cell];
+            ensure!(index < *size as u128, "Array index out of bounds");
+            Ok(offset + stride * index as u32)
+        })
+}
+
+fn extract(value: u128, offset: u32, mask: u128) -> u128 {
+    value.checked_shr(offset).unwrap_or(0) & mask
+}
+
+fn insert(orig: u128, subst: u128, offset: u32, mask: u128) -> u128 {
+    let shifted = mask.checked_shl(offset).unwrap_or(0);
+    (orig & !shifted) | ((subst & mask).checked_shl(offset).unwrap_or(0))
+}
+
+impl Program {
+    // Compile the design, starting from its top function.  Fails if any
+    // of the functions cannot be compiled.
+    pub fn compile(design: &Module) -> Result<Program> {
+        let mut compiler = Compiler {
+            design,
+            functions: vec![],
+            index: HashMap::new(),
+        };
+        let top = compiler.function(design.top)?;
+        let functions = compiler
+            .functions
+            .into_iter()
+            .map(|func| func.ok_or(anyhow!("ICE function was not compiled")))
+            .collect::<Result<_>>()?;
+        Ok(Program { functions, top })
+    }
+    // The number of operations over all of the functions.
+    pub fn len(&self) -> usize {
+        self.functions.iter().map(|func| func.ops.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // As `execute_function`, but on the compiled design.
+    pub fn execute(&self, arguments: &[TypedBits]) -> Result<TypedBits> {
+        let func = &self.functions[self.top];
+        ensure!(
+            func.arguments.len() == arguments.len(),
+            "Function {} expected {} arguments, got {}",
+            func.name,
+            func.arguments.len(),
+            arguments.len()
+        );
+        let mut words = SmallVec::<[u128; 8]>::new();
+        for (ndx, ((_, expected), arg)) in func.arguments.iter().zip(arguments).enumerate() {
+            ensure!(
+                *expected == arg.kind,
+                "Function {} argument {ndx} expected {expected}, got {}",
+                func.name,
+                arg.kind
+            );
+            words.push(to_word(arg));
+        }
+        let result = self.run(self.top, &words)?;
+        Ok(to_typed_bits(result, &func.result_kind))
+    }
+    fn run(&self, func: usize, args: &[u128]) -> Result<u128> {
+        let func = &self.functions[func];
+        let mut frame = func.frame.clone();
+        for ((cell, _), arg) in func.arguments.iter().zip(args) {
+            frame[*cell] = *arg;
+        }
+        for op in &func.ops {
+            match op {
+                Op::Copy { lhs, arg } => frame[*lhs] = frame[*arg],
+                Op::Add {
+                    lhs,
+                    arg1,
+                    arg2,
+                    mask,
+                } => frame[*lhs] = frame[*arg1].wrapping_add(frame[*arg2]) & mask,
+                Op::Sub {
+                    lhs,
+                    arg1,
+                    arg2,
+                    mask,
+                } => frame[*lhs] = frame[*arg1].wrapping_sub(frame[*arg2]) & mask,
+                Op::Mul {
+                    lhs,
+                    arg1,
+                    arg2,
+                    mask,
+                } => frame[*lhs] = frame[*arg1].wrapping_mul(frame[*arg2]) & mask,
+                Op::And { lhs, arg1, arg2 } => frame[*lhs] = frame[*arg1] & frame[*arg2],
+                Op::Or { lhs, arg1, arg2 } => frame[*lhs] = frame[*arg1] | frame[*arg2],
+                Op::Xor { lhs, arg1, arg2 } => frame[*lhs] = frame[*arg1] ^ frame[*arg2],
+                Op::Eq { lhs, arg1, arg2 } => frame[*lhs] = (frame[*arg1] == frame[*arg2]) as u128,
+                Op::Ne { lhs, arg1, arg2 } => frame[*lhs] = (frame[*arg1] != frame[*arg2]) as u128,
+                Op::Compare {
+                    lhs,
+                    arg1,
+                    arg2,
+                    compare,
+                    signed,
+                    shift,
+                } => {
+                    let ordering = if *signed {
+                        let arg1 = (frame[*arg1] << shift) as i128;
+                        let arg2 = (frame[*arg2] << shift) as i128;
+                        arg1.cmp(&arg2)
+                    } else {
+                        frame[*arg1].cmp(&frame[*arg2])
+                    };
+                    frame[*lhs] = match compare {
+                        Compare::Lt => ordering.is_lt(),
+                        Compare::Le => ordering.is_le(),
+                        Compare::Gt => ordering.is_gt(),
+                        Compare::Ge => ordering.is_ge(),
+                    } as u128;
+                }
+                Op::Shl {
+                    lhs,
+                    arg,
+                    amount,
+                    width,
+                    mask,
+                } => {
+                    let amount = frame[*amount];
+                    frame[*lhs] = if amount >= *width as u128 {
+                        0
+                    } else {
+                        (frame[*arg] << amount) & mask
+                    };
+                }
+                Op::Shr {
+                    lhs,
+                    arg,
+                    amount,
+                    width,
+                } => {
+                    let amount = frame[*amount];
+                    frame[*lhs] = if amount >= *width as u128 {
+                        0
+                    } else {
+                        frame[*arg] >> amount
+                    };
+                }
+                Op::Sar {
+                    lhs,
+                    arg,
+                    amount,
+                    width,
+                    mask,
+                } => {
+                    let value = frame[*arg];
+                    let amount = frame[*amount].min(*width as u128) as u32;
+                    let shift = 128 - (*width).max(1);
+                    let extended = ((value << shift) as i128) >> shift;
+                    frame[*lhs] = if *width == 0 {
+                        0
+                    } else {
+                        (extended >> amount.min(127)) as u128 & mask
+                    };
+                }
+                Op::Not { lhs, arg, mask } => frame[*lhs] = !frame[*arg] & mask,
+                Op::Neg { lhs, arg, mask } => frame[*lhs] = frame[*arg].wrapping_neg() & mask,
+                Op::Any { lhs, arg } => frame[*lhs] = (frame[*arg] != 0) as u128,
+                Op::All { lhs, arg, mask } => frame[*lhs] = (frame[*arg] == *mask) as u128,
+                Op::Parity { lhs, arg } => frame[*lhs] = (frame[*arg].count_ones() & 1) as u128,
+                Op::Select {
+                    lhs,
+                    cond,
+                    true_value,
+                    false_value,
+                } => {
+                    frame[*lhs] = if frame[*cond] != 0 {
+                        frame[*true_value]
+                    } else {
+                        frame[*false_value]
+                    }
+                }
+                Op::Extract {
+                    lhs,
+                    arg,
+                    offset,
+                    mask,
+                } => frame[*lhs] = extract(frame[*arg], *offset, *mask),
+                Op::Insert {
+                    lhs,
+                    orig,
+                    subst,
+                    offset,
+                    mask,
+                } => frame[*lhs] = insert(frame[*orig], frame[*subst], *offset, *mask),
+                Op::DynamicExtract {
+                    lhs,
+                    arg,
+                    offset,
+                    strides,
+                    mask,
+                } => {
+                    let offset = dynamic_offset(&frame, *offset, strides)?;
+                    frame[*lhs] = extract(frame[*arg], offset, *mask);
+                }
+                Op::DynamicInsert {
+                    lhs,
+                    orig,
+                    subst,
+                    offset,
+                    strides,
+                    mask,
+                } => {
+                    let offset = dynamic_offset(&frame, *offset, strides)?;
+                    frame[*lhs] = insert(frame[*orig], frame[*subst], offset, *mask);
+                }
+                Op::Concat { lhs, fields } => {
+                    frame[*lhs] = fields.iter().fold(0, |value, (cell, offset)| {
+                        value | frame[*cell].checked_shl(*offset).unwrap_or(0)
+                    });
+                }
+                Op::Repeat {
+                    lhs,
+                    arg,
+                    width,
+                    count,
+                } => {
+                    let value = frame[*arg];
+                    frame[*lhs] = (0..*count as u32).fold(0, |acc, ndx| {
+                        acc | value.checked_shl(ndx * width).unwrap_or(0)
+                    });
+                }
+                Op::Case {
+                    lhs,
+                    discriminant,
+                    table,
+                } => {
+                    let discriminant = frame[*discriminant];
+                    let (_, value) = table
+                        .iter()
+                        .find(|(constant, _)| constant.is_none_or(|c| c == discriminant))
+                        .ok_or(anyhow!("ICE Case was not exhaustive"))?;
+                    frame[*lhs] = frame[*value];
+                }
+                Op::UnsignedCast {
+                    lhs,
+                    arg,
+                    width,
+                    len,
+                    kind,
+                } => {
+                    let value = frame[*arg];
+                    if len < width && value >> len != 0 {
+                        bail!(
+                            "Unsigned cast failed: {} is not representable in {len} bits",
+                            to_typed_bits(value, kind)
+                        );
+                    }
+                    frame[*lhs] = value;
+                }
+                Op::SignedCast {
+                    lhs,
+                    arg,
+                    width,
+                    len,
+                    kind,
+                } => {
+                    let value = frame[*arg];
+                    frame[*lhs] = if len > width {
+                        if *width > 0 && bit(value, width - 1) {
+                            value | (mask(*len) & !mask(*width))
+                        } else {
+                            value
+                        }
+                    } else {
+                        let sign = *len > 0 && bit(value, len - 1);
+                        let rest = value.checked_shr(*len).unwrap_or(0);
+                        let expected = if sign { mask(width - len) } else { 0 };
+                        if rest != expected {
+                            bail!(
+                                "Signed cast failed: {} is not representable in {len} bits",
+                                to_typed_bits(value, kind)
+                            );
+                        }
+                        value & mask(*len)
+                    };
+                }
+                Op::Call { lhs, func, args } => {
+                    let args = args
+                        .iter()
+                        .map(|cell| frame[*cell])
+                        .collect::<SmallVec<[u128; 8]>>();
+                    frame[*lhs] = self.run(*func, &args)?;
+                }
+                Op::Extern {
+                    lhs,
+                    name,
+                    stub,
+                    args,
+                    ret,
+                } => {
+                    let args = args
+                        .iter()
+                        .map(|(cell, kind)| to_typed_bits(frame[*cell], kind))
+                        .collect::<Vec<_>>();
+                    let result = stub(&args)?;
+                    ensure!(
+                        result.kind == **ret,
+                        "The VM stub for {name} returned a {} rather than a {ret}",
+                        result.kind
+                    );
+                    frame[*lhs] = to_word(&result);
+                }
+                Op::Assert { cond, message } => {
+                    if frame[*cond] == 0 {
+                        bail!("Assertion failed: {message}");
+                    }
+                }
+            }
+        }
+        Ok(frame[func.result])
+    }
+}
+
+// The engine that a `Simulator` runs its design on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    // Bytecode for designs of at least `BYTECODE_THRESHOLD` opcodes that
+    // can be compiled, the interpreter otherwise.
+    #[default]
+    Auto,
+    Interpreter,
+    Bytecode,
+    // Both, failing if they ever disagree.
+    Checked,
+}
+
+// Runs a design on the engine that suits it.  The design is compiled (if
+// at all) once, when the simulator is made.
+pub struct Simulator<'a> {
+    design: &'a Module,
+    program: Option<Program>,
+    checked: bool,
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(design: &'a Module) -> Self {
+        let size = design
+            .objects
+            .values()
+            .flat_map(|obj| &obj.ops)
+            .filter(|op| !matches!(op, OpCode::Noop | OpCode::Comment(_)))
+            .count();
+        let program = if size >= BYTECODE_THRESHOLD {
+            Program::compile(design).ok()
+        } else {
+            None
+        };
+        Simulator {
+            design,
+            program,
+            checked: false,
+        }
+    }
+    pub fn with_engine(design: &'a Module, engine: Engine) -> Result<Self> {
+        let program = match engine {
+            Engine::Auto => return Ok(Self::new(design)),
+            Engine::Interpreter => None,
+            Engine::Bytecode | Engine::Checked => Some(Program::compile(design)?),
+        };
+        Ok(Simulator {
+            design,
+            program,
+            checked: engine == Engine::Checked,
+        })
+    }
+    pub fn engine(&self) -> Engine {
+        match (&self.program, self.checked) {
+            (None, _) => Engine::Interpreter,
+            (Some(_), false) => Engine::Bytecode,
+            (Some(_), true) => Engine::Checked,
+        }
+    }
+    pub fn execute(&self, arguments: Vec<TypedBits>) -> Result<TypedBits> {
+        let Some(program) = &self.program else {
+            return execute_function(self.design, arguments);
+        };
+        if !self.checked {
+            return program.execute(&arguments);
+        }
+        let fast = program.execute(&arguments);
+        let show = arguments
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let name = self.design.func_name(self.design.top)?;
+        match (fast, execute_function(self.design, arguments)) {
+            (Ok(fast), Ok(slow)) => {
+                ensure!(
+                    fast == slow,
+                    "The bytecode engine gives {fast} for {name}({show}), but the interpreter gives {slow}"
+                );
+                Ok(fast)
+            }
+            (Err(_), Err(err)) => Err(err),
+            (Ok(fast), Err(err)) => bail!(
+                "The bytecode engine gives {fast} for {name}({show}), but the interpreter fails with {err}"
+            ),
+            (Err(err), Ok(slow)) => bail!(
+                "The bytecode engine fails on {name}({show}) with {err}, but the interpreter gives {slow}"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rhif::object::ObjectBuilder, Digital};
+    use rhdl_bits::Bits;
+
+    // fn wide(a: b200, b: b200) -> b200 { a ^ b }
+    fn wide() -> Module {
+        ObjectBuilder::new("wide", "fn wide(a: b200, b: b200) -> b200 { a ^ b }")
+            .registers(vec![Kind::make_bits(200); 3])
+            .arguments([Slot::Register(0), Slot::Register(1)])
+            .op(OpCode::Binary(Binary {
+                op: AluBinary::BitXor,
+                lhs: Slot::Register(2),
+                arg1: Slot::Register(0),
+                arg2: Slot::Register(1),
+            }))
+            .returns(Slot::Register(2))
+            .build_module()
+    }
+
+    #[test]
+    fn test_wide_values_are_interpreted() {
+        let design = wide();
+        let err = Program::compile(&design).unwrap_err().to_string();
+        assert!(err.contains("cannot hold a b200"), "{err}");
+        assert!(Simulator::with_engine(&design, Engine::Bytecode).is_err());
+        let simulator = Simulator::new(&design);
+        assert_eq!(simulator.engine(), Engine::Interpreter);
+        let a = TypedBits {
+            bits: (0..200).map(|ndx| ndx % 3 == 0).collect(),
+            kind: Kind::make_bits(200),
+        };
+        let zero = TypedBits {
+            bits: vec![false; 200],
+            kind: Kind::make_bits(200),
+        };
+        assert_eq!(simulator.execute(vec![a.clone(), zero]).unwrap(), a);
+    }
+
+    #[test]
+    fn test_words_round_trip() {
+        let value = Bits::<100>(0x0001_2345_6789_abcd_ef01_2345_6789).typed_bits();
+        let word = to_word(&value);
+        assert_eq!(word, 0x0001_2345_6789_abcd_ef01_2345_6789);
+        assert_eq!(to_typed_bits(word, &value.kind), value);
+        assert_eq!(insert(0xffff, 0x5, 4, 0xf), 0xff5f);
+        assert_eq!(extract(0xff5f, 4, 0xf), 0x5);
+        // Values with no bits sit past the top of the word
+        assert_eq!(insert(0xffff, 0, 128, 0), 0xffff);
+        assert_eq!(extract(0xffff, 128, 0), 0);
+    }
+
+    // Every kernel of the pass corpus must compile, and agree with the
+    // interpreter on random arguments, both before and after the passes.
+    #[cfg(feature = "proptest")]
+    #[test]
+    fn test_bytecode_matches_interpreter_on_corpus() {
+        use crate::compiler::driver::compile_design;
+        use crate::compiler::verify_pass::{random_arguments, unoptimized_design, PASS_CORPUS};
+        use crate::kernel::Kernel;
+
+        let mut entries = std::fs::read_dir(PASS_CORPUS)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert!(!entries.is_empty());
+        for path in entries {
+            let json = std::fs::read_to_string(&path).unwrap();
+            let kernel: Kernel = serde_json::from_str(&json).unwrap();
+            let designs = [
+                compile_design(kernel.clone()).unwrap(),
+                unoptimized_design(kernel).unwrap(),
+            ];
+            for design in &designs {
+                let inputs = random_arguments(&design.objects[&design.top], 256).unwrap();
+                // The stubs of extern functions are not kept in the corpus,
+                // so that neither engine can run the kernels that call them
+                let Ok(simulator) = Simulator::with_engine(design, Engine::Checked) else {
+                    assert!(inputs
+                        .into_iter()
+                        .all(|args| execute_function(design, args).is_err()));
+                    continue;
+                };
+                for args in inputs {
+                    if let Err(err) = simulator.execute(args) {
+                        assert!(
+                            !err.to_string().contains("bytecode engine"),
+                            "{}: {err:#}",
+                            path.display()
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+// Bit level cone of influence of the outputs of a function.  The bits of
+// the arguments are numbered in order, as if the arguments were
+// concatenated (so the first bit of the second argument follows the last
+// bit of the first).  The dependencies are computed op by op, and are
+// conservative: a bit is only left out of a cone if it cannot affect the
+// output bit.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::path::{bit_range, path_star, Path};
+use crate::rhif::spec::{
+    AluBinary, AluUnary, Array, Assign, Binary, Case, Cast, Enum, Exec, FieldValue, Index, Member,
+    OpCode, Repeat, Select, Slot, Splice, Struct, Tuple, Unary,
+};
+use crate::rhif::Object;
+use crate::Kind;
+
+// The argument bits that each bit of a value depends on.
+type BitDeps = Vec<BTreeSet<usize>>;
+
+fn union<'a>(sets: impl IntoIterator<Item = &'a BTreeSet<usize>>) -> BTreeSet<usize> {
+    sets.into_iter().flatten().copied().collect()
+}
+
+struct ConeBuilder<'a> {
+    obj: &'a Object,
+    deps: HashMap<Slot, BitDeps>,
+}
+
+impl<'a> ConeBuilder<'a> {
+    fn kind(&self, slot: Slot) -> Result<Kind> {
+        match slot {
+            Slot::Empty => Ok(Kind::Empty),
+            Slot::Literal(_) => Ok(self.obj.literal(slot)?.kind.clone()),
+            Slot::Register(_) => self.obj.kind.get(&slot).cloned().ok_or(anyhow!(
+                "No type for slot {slot} in function {}",
+                self.obj.name
+            )),
+        }
+    }
+    fn width(&self, slot: Slot) -> Result<usize> {
+        Ok(self.kind(slot)?.bits())
+    }
+    // Literals (and slots not yet written) depend on no argument bits.
+    fn bits(&self, slot: Slot) -> Result<BitDeps> {
+        match self.deps.get(&slot) {
+            Some(deps) => Ok(deps.clone()),
+            None => Ok(vec![BTreeSet::new(); self.width(slot)?]),
+        }
+    }
+    fn all(&self, slot: Slot) -> Result<BTreeSet<usize>> {
+        Ok(union(&self.bits(slot)?))
+    }
+    fn literal_value(&self, slot: Slot) -> Option<usize> {
+        self.obj
+            .literals
+            .get(&slot)
+            .and_then(|lit| lit.as_i64().ok())
+            .and_then(|x| usize::try_from(x).ok())
+    }
+    // Each bit of the result depends on the same bit of the arguments.
+    fn bitwise(&self, lhs: Slot, args: &[Slot]) -> Result<BitDeps> {
+        let args = args
+            .iter()
+            .map(|x| self.bits(*x))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((0..self.width(lhs)?)
+            .map(|j| union(args.iter().filter_map(|arg| arg.get(j))))
+            .collect())
+    }
+    // Each bit of the result depends on the same and all lower bits of
+    // the arguments (i.e., through a carry).
+    fn carried(&self, lhs: Slot, args: &[Slot]) -> Result<BitDeps> {
+        let mut bitwise = self.bitwise(lhs, args)?;
+        for j in 1..bitwise.len() {
+            let lower = bitwise[j - 1].clone();
+            bitwise[j].extend(lower);
+        }
+        Ok(bitwise)
+    }
+    // Every bit of the result depends on every bit of the arguments.
+    fn total(&self, lhs: Slot, args: &[Slot]) -> Result<BitDeps> {
+        let all = args
+            .iter()
+            .map(|x| self.all(*x))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(vec![union(&all); self.width(lhs)?])
+    }
+    fn shift(&self, op: &AluBinary, lhs: Slot, arg1: Slot, arg2: Slot) -> Result<BitDeps> {
+        let value = self.bits(arg1)?;
+        let width = self.width(lhs)?;
+        let signed = self.kind(arg1)?.is_signed();
+        let msb = value.last().cloned().unwrap_or_default();
+        let Some(amount) = self.literal_value(arg2) else {
+            // A dynamic shift can move any bit toward the result bit
+            let amount = self.all(arg2)?;
+            return Ok((0..width)
+                .map(|j| {
+                    let range = match op {
+                        AluBinary::Shl => 0..(j + 1).min(value.len()),
+                        _ => j.min(value.len())..value.len(),
+                    };
+                    let mut deps = union(&value[range]);
+                    deps.extend(amount.iter().copied());
+                    deps
+                })
+                .collect());
+        };
+        Ok((0..width)
+            .map(|j| match op {
+                AluBinary::Shl => j
+                    .checked_sub(amount)
+                    .and_then(|k| value.get(k))
+                    .cloned()
+                    .unwrap_or_default(),
+                _ => match value.get(j.saturating_add(amount)) {
+                    Some(deps) => deps.clone(),
+                    None if signed => msb.clone(),
+                    None => BTreeSet::new(),
+                },
+            })
+            .collect())
+    }
+    // Write the dependencies of `value` into those of `target` at `range`.
+    fn place(target: &mut BitDeps, range: Range<usize>, value: &BitDeps) {
+        for (bit, deps) in range.zip(value) {
+            target[bit] = deps.clone();
+        }
+    }
+    fn fields(
+        &self,
+        lhs: Slot,
+        base: Path,
+        fields: &[FieldValue],
+        mut deps: BitDeps,
+    ) -> Result<BitDeps> {
+        let kind = self.kind(lhs)?;
+        for field in fields {
+            let path = match &field.member {
+                Member::Unnamed(ndx) => base.clone().index(*ndx as usize),
+                Member::Named(name) => base.clone().field(name),
+            };
+            let (range, _) = bit_range(kind.clone(), &path)?;
+            Self::place(&mut deps, range, &self.bits(field.value)?);
+        }
+        Ok(deps)
+    }
+    fn elements(&self, lhs: Slot, elements: &[Slot]) -> Result<BitDeps> {
+        let kind = self.kind(lhs)?;
+        let mut deps = vec![BTreeSet::new(); kind.bits()];
+        for (ndx, element) in elements.iter().enumerate() {
+            let (range, _) = bit_range(kind.clone(), &Path::default().index(ndx))?;
+            Self::place(&mut deps, range, &self.bits(*element)?);
+        }
+        Ok(deps)
+    }
+    // The bit ranges a (possibly dynamic) path can refer to, and the bits
+    // of the index registers used by the path.
+    fn ranges(&self, slot: Slot, path: &Path) -> Result<(Vec<Range<usize>>, BTreeSet<usize>)> {
+        let kind = self.kind(slot)?;
+        let ranges = path_star(&kind, path)?
+            .iter()
+            .map(|path| Ok(bit_range(kind.clone(), path)?.0))
+            .collect::<Result<Vec<_>>>()?;
+        let index = path
+            .dynamic_slots()
+            .map(|x| self.all(*x))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((ranges, union(&index)))
+    }
+    fn op(&mut self, op: &OpCode) -> Result<()> {
+        let (lhs, deps) = match op {
+            OpCode::Noop | OpCode::Comment(_) | OpCode::Assert(_) => return Ok(()),
+            OpCode::Binary(Binary {
+                op,
+                lhs,
+                arg1,
+                arg2,
+            }) => {
+                let deps = match op {
+                    AluBinary::BitXor | AluBinary::BitAnd | AluBinary::BitOr => {
+                        self.bitwise(*lhs, &[*arg1, *arg2])?
+                    }
+                    AluBinary::Add | AluBinary::Sub | AluBinary::Mul => {
+                        self.carried(*lhs, &[*arg1, *arg2])?
+                    }
+                    AluBinary::Shl | AluBinary::Shr => self.shift(op, *lhs, *arg1, *arg2)?,
+                    AluBinary::Eq
+                    | AluBinary::Lt
+                    | AluBinary::Le
+                    | AluBinary::Ne
+                    | AluBinary::Ge
+                    | AluBinary::Gt => self.total(*lhs, &[*arg1, *arg2])?,
+                };
+                (*lhs, deps)
+            }
+            OpCode::Unary(Unary { op, lhs, arg1 }) => {
+                let deps = match op {
+                    AluUnary::Not | AluUnary::Signed | AluUnary::Unsigned => {
+                        self.bitwise(*lhs, &[*arg1])?
+                    }
+                    AluUnary::Neg => self.carried(*lhs, &[*arg1])?,
+                    AluUnary::All | AluUnary::Any | AluUnary::Xor => self.total(*lhs, &[*arg1])?,
+                };
+                (*lhs, deps)
+            }
+            OpCode::Select(Select {
+                lhs,
+                cond,
+                true_value,
+                false_value,
+            }) => {
+                let cond = self.all(*cond)?;
+                let mut deps = self.bitwise(*lhs, &[*true_value, *false_value])?;
+                for bit in &mut deps {
+                    bit.extend(cond.iter().copied());
+                }
+                (*lhs, deps)
+            }
+            OpCode::Index(Index { lhs, arg, path }) => {
+                let value = self.bits(*arg)?;
+                let (ranges, index) = self.ranges(*arg, path)?;
+                let mut deps = vec![index; self.width(*lhs)?];
+                for range in ranges {
+                    for (bit, src) in deps.iter_mut().zip(&value[range]) {
+                        bit.extend(src.iter().copied());
+                    }
+                }
+                (*lhs, deps)
+            }
+            OpCode::Assign(Assign { lhs, rhs }) => (*lhs, self.bits(*rhs)?),
+            OpCode::Splice(Splice {
+                lhs,
+                orig,
+                path,
+                subst,
+            }) => {
+                let mut deps = self.bits(*orig)?;
+                let subst = self.bits(*subst)?;
+                let (ranges, index) = self.ranges(*orig, path)?;
+                if ranges.len() == 1 {
+                    Self::place(&mut deps, ranges[0].clone(), &subst);
+                } else {
+                    // Each of the places the value may be written keeps
+                    // its old value if the index points elsewhere
+                    for range in ranges {
+                        for (bit, src) in range.zip(&subst) {
+                            deps[bit].extend(src.iter().copied());
+                            deps[bit].extend(index.iter().copied());
+                        }
+                    }
+                }
+                (*lhs, deps)
+            }
+            OpCode::Repeat(Repeat { lhs, value, .. }) => {
+                let value = self.bits(*value)?;
+                let deps = (0..self.width(*lhs)?)
+                    .map(|j| value[j % value.len()].clone())
+                    .collect();
+                (*lhs, deps)
+            }
+            OpCode::Struct(Struct {
+                lhs, fields, rest, ..
+            }) => {
+                let base = match rest {
+                    Some(rest) => self.bits(*rest)?,
+                    None => vec![BTreeSet::new(); self.width(*lhs)?],
+                };
+                (*lhs, self.fields(*lhs, Path::default(), fields, base)?)
+            }
+            OpCode::Enum(Enum {
+                lhs,
+                fields,
+                template,
+            }) => {
+                let base = Path::default().payload_by_value(template.discriminant()?.as_i64()?);
+                let empty = vec![BTreeSet::new(); self.width(*lhs)?];
+                (*lhs, self.fields(*lhs, base, fields, empty)?)
+            }
+            OpCode::Tuple(Tuple { lhs, fields }) => (*lhs, self.elements(*lhs, fields)?),
+            OpCode::Array(Array { lhs, elements }) => (*lhs, self.elements(*lhs, elements)?),
+            OpCode::Case(Case {
+                lhs,
+                discriminant,
+                table,
+            }) => {
+                let values = table.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+                let discriminant = self.all(*discriminant)?;
+                let mut deps = self.bitwise(*lhs, &values)?;
+                for bit in &mut deps {
+                    bit.extend(discriminant.iter().copied());
+                }
+                (*lhs, deps)
+            }
+            // The called function is not available here, so every bit of
+            // the result may depend on every bit of the arguments
+            OpCode::Exec(Exec { lhs, args, .. }) => (*lhs, self.total(*lhs, args)?),
+            OpCode::AsBits(Cast { lhs, arg, len }) | OpCode::AsSigned(Cast { lhs, arg, len }) => {
+                let value = self.bits(*arg)?;
+                let extension = match op {
+                    OpCode::AsSigned(_) => value.last().cloned().unwrap_or_default(),
+                    _ => BTreeSet::new(),
+                };
+                let deps = (0..*len)
+                    .map(|j| value.get(j).cloned().unwrap_or_else(|| extension.clone()))
+                    .collect();
+                (*lhs, deps)
+            }
+        };
+        self.deps.insert(lhs, deps);
+        Ok(())
+    }
+}
+
+impl Object {
+    // The argument bits that bit `output_bit` of the return value of this
+    // function may depend on.
+    pub fn output_cone(&self, output_bit: usize) -> Result<HashSet<usize>> {
+        let mut builder = ConeBuilder {
+            obj: self,
+            deps: HashMap::new(),
+        };
+        let mut offset = 0;
+        for arg in &self.arguments {
+            let width = builder.width(*arg)?;
+            builder
+                .deps
+                .insert(*arg, (offset..offset + width).map(|x| [x].into()).collect());
+            offset += width;
+        }
+        for op in &self.ops {
+            builder.op(op)?;
+        }
+        let output = builder.bits(self.return_slot)?;
+        let Some(deps) = output.get(output_bit) else {
+            bail!(
+                "Output bit {output_bit} is out of range for the {} bit return value of {}",
+                output.len(),
+                self.name
+            );
+        };
+        Ok(deps.iter().copied().collect())
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+
+use crate::ast::ast_impl::FunctionId;
+use crate::rhif::object::SourceLocation;
+use crate::rhif::spanned_source::SpannedSource;
+use crate::rhif::spec::{Case, OpCode, Select, Slot};
+use crate::Module;
+
+// A place in a design that the interpreter can be seen to exercise.
+// Every opcode is a point, and so is every arm of a `Case` opcode and
+// both sides of a `Select` (arm 0 is taken when the condition is true).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CoveragePoint {
+    Op {
+        func: FunctionId,
+        index: usize,
+    },
+    Arm {
+        func: FunctionId,
+        index: usize,
+        arm: usize,
+    },
+}
+
+impl CoveragePoint {
+    pub fn func(&self) -> FunctionId {
+        match self {
+            CoveragePoint::Op { func, .. } | CoveragePoint::Arm { func, .. } => *func,
+        }
+    }
+}
+
+impl std::fmt::Display for CoveragePoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoveragePoint::Op { index, .. } => write!(f, "op {index}"),
+            CoveragePoint::Arm { index, arm, .. } => write!(f, "arm {arm} of op {index}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CoverageEntry {
+    hits: usize,
+    location: Option<SourceLocation>,
+}
+
+// Hit counts for each coverage point of a design, collected by running
+// the design through the RHIF interpreter with
+// `execute_function_with_coverage`.  All the points of the design are
+// present (with a count of zero) from the start, so that the points that
+// are never reached show up in the report.  Maps for the same design
+// can be merged to combine the coverage of many test runs.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageMap {
+    points: BTreeMap<CoveragePoint, CoverageEntry>,
+    sources: BTreeMap<FunctionId, SpannedSource>,
+}
+
+impl CoverageMap {
+    pub fn new(design: &Module) -> Self {
+        let mut map = CoverageMap::default();
+        for (fn_id, obj) in &design.objects {
+            let func = *fn_id;
+            map.sources.insert(func, obj.symbols.source.clone());
+            let op_location = |index: usize| obj.symbols.opcode_map.get(index).copied();
+            let slot_location = |index: usize, slot: &Slot| {
+                obj.symbols
+                    .slot_map
+                    .get(slot)
+                    .copied()
+                    .or_else(|| op_location(index))
+            };
+            for (index, op) in obj.ops.iter().enumerate() {
+                map.add(CoveragePoint::Op { func, index }, op_location(index));
+                let arms = match op {
+                    OpCode::Case(Case { table, .. }) => {
+                        table.iter().map(|(_, slot)| *slot).collect()
+                    }
+                    OpCode::Select(Select {
+                        true_value,
+                        false_value,
+                        ..
+                    }) => vec![*true_value, *false_value],
+                    _ => vec![],
+                };
+                for (arm, slot) in arms.iter().enumerate() {
+                    map.add(
+                        CoveragePoint::Arm { func, index, arm },
+                        slot_location(index, slot),
+                    );
+                }
+            }
+        }
+        map
+    }
+    fn add(&mut self, point: CoveragePoint, location: Option<SourceLocation>) {
+        self.points
+            .insert(point, CoverageEntry { hits: 0, location });
+    }
+    pub(crate) fn hit(&mut self, point: CoveragePoint) {
+        self.points
+            .entry(point)
+            .or_insert(CoverageEntry {
+                hits: 0,
+                location: None,
+            })
+            .hits += 1;
+    }
+    pub fn hits(&self, point: CoveragePoint) -> usize {
+        self.points.get(&point).map(|x| x.hits).unwrap_or(0)
+    }
+    pub fn merge(&mut self, other: &CoverageMap) {
+        for (point, entry) in &other.points {
+            let mine = self.points.entry(*point).or_insert(CoverageEntry {
+                hits: 0,
+                location: entry.location,
+            });
+            mine.hits += entry.hits;
+            mine.location = mine.location.or(entry.location);
+        }
+        for (fn_id, source) in &other.sources {
+            self.sources.entry(*fn_id).or_insert_with(|| source.clone());
+        }
+    }
+    pub fn total(&self) -> usize {
+        self.points.len()
+    }
+    pub fn covered(&self) -> usize {
+        self.points.values().filter(|x| x.hits > 0).count()
+    }
+    // The fraction of coverage points that were hit at least once.  An
+    // empty map is considered fully covered.
+    pub fn fraction(&self) -> f64 {
+        if self.points.is_empty() {
+            1.0
+        } else {
+            self.covered() as f64 / self.total() as f64
+        }
+    }
+    pub fn uncovered(&self) -> Vec<CoveragePoint> {
+        self.points
+            .iter()
+            .filter(|(_, entry)| entry.hits == 0)
+            .map(|(point, _)| *point)
+            .collect()
+    }
+    // The source text of the point, if it is known.
+    pub fn source_text(&self, point: CoveragePoint) -> Option<&str> {
+        let location = self.points.get(&point)?.location?;
+        let source = self.sources.get(&location.func)?;
+        let span = source.span_map.get(&location.node)?;
+        source.source.get(span.clone())
+    }
+    fn describe(&self, point: CoveragePoint) -> String {
+        let name = self
+            .sources
+            .get(&point.func())
+            .map(|x| x.name.as_str())
+            .unwrap_or("?");
+        match self.source_text(point) {
+            Some(text) => format!("{name} {point}: {}", first_line(text)),
+            None => format!("{name} {point}"),
+        }
+    }
+    // Render the source of each function, with the hit counts of the arms
+    // that start on each line in the margin, followed by a list of the
+    // points that were never reached.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        writeln!(
+            report,
+            "Coverage: {} of {} points ({:.1}%)",
+            self.covered(),
+            self.total(),
+            self.fraction() * 100.0
+        )
+        .unwrap();
+        for (fn_id, source) in &self.sources {
+            writeln!(report, "\nfn {} ({fn_id}):", source.name).unwrap();
+            let mut margin: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for (point, entry) in &self.points {
+                if !matches!(point, CoveragePoint::Arm { .. }) {
+                    continue;
+                }
+                let Some(location) = entry.location.filter(|x| x.func == *fn_id) else {
+                    continue;
+                };
+                if let Some(span) = source.span_map.get(&location.node) {
+                    margin.entry(span.start).or_default().push(entry.hits);
+                }
+            }
+            let mut offset = 0;
+            for line in source.source.lines() {
+                let end = offset + line.len();
+                let hits = margin
+                    .range(offset..=end)
+                    .flat_map(|(_, hits)| hits.iter().map(|x| x.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                writeln!(report, "{hits:>10} | {line}").unwrap();
+                offset = end + 1;
+            }
+        }
+        let uncovered = self.uncovered();
+        if !uncovered.is_empty() {
+            writeln!(report, "\nNever reached:").unwrap();
+            for point in uncovered {
+                writeln!(report, "  {}", self.describe(point)).unwrap();
+            }
+        }
+        report
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default().trim()
+}
+
+// Fail (with the coverage report) if less than the given fraction of the
+// coverage points were reached.
+pub fn assert_coverage_at_least(map: &CoverageMap, threshold: f64) -> Result<()> {
+    if map.fraction() < threshold {
+        bail!(
+            "Coverage of {:.1}% is below the required {:.1}%\n{}",
+            map.fraction() * 100.0,
+            threshold * 100.0,
+            map.report()
+        );
+    }
+    Ok(())
+}
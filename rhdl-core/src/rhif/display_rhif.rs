@@ -2,7 +2,7 @@ use std::fmt::Display;
 
 use crate::{
     rhif::spec::{
-        AluBinary, AluUnary, Array, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
+        AluBinary, AluUnary, Array, Assert, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
         FieldValue, FuncId, Index, Member, OpCode, Repeat, Slot, Splice, Struct, Tuple, Unary,
     },
     util::splice,
@@ -85,6 +85,9 @@ impl Display for OpCode {
             OpCode::Repeat(Repeat { lhs, value, len }) => {
                 write!(f, " {} <- [{}; {}]", lhs, value, len)
             }
+            OpCode::Assert(Assert { cond, message }) => {
+                write!(f, " assert {} {:?}", cond, message)
+            }
             OpCode::Comment(s) => write!(f, " # {}", s.trim_end().replace('\n', "\n   # ")),
             OpCode::Enum(Enum {
                 lhs,
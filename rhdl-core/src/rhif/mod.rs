@@ -1,7 +1,11 @@
+pub mod bytecode;
+pub mod cone;
+pub mod coverage;
 pub mod object;
 pub mod rhif_builder;
 pub mod spec;
 pub mod vm;
+pub mod xvm;
 pub use object::Object;
 pub mod module;
 pub use module::Module;
@@ -1,12 +1,20 @@
-use crate::{ast::ast_impl::FunctionId, rhif::Object};
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::{
+    ast::ast_impl::FunctionId,
+    path::Path,
+    rhif::{vm::execute_function, Object},
+    Kind, TypedBits,
+};
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 use super::spanned_source::SpannedSource;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Module {
-    pub objects: HashMap<FunctionId, Object>,
+    // Ordered by function id, so that anything generated by walking the
+    // objects comes out the same from run to run.
+    pub objects: BTreeMap<FunctionId, Object>,
     pub top: FunctionId,
 }
 
@@ -34,4 +42,79 @@ impl Module {
             .map(|(fn_id, obj)| (*fn_id, obj.symbols.source.clone()))
             .collect()
     }
+    fn top_object(&self) -> Result<&Object> {
+        self.objects
+            .get(&self.top)
+            .ok_or(anyhow!("Top function {} not found", self.top))
+    }
+    // The kinds of the arguments and of the return value of the top function.
+    fn signature(&self) -> Result<(Vec<Kind>, Kind)> {
+        let obj = self.top_object()?;
+        let kind = |slot| {
+            obj.kind
+                .get(slot)
+                .cloned()
+                .ok_or(anyhow!("No kind for slot {slot} in {}", obj.name))
+        };
+        let arguments = obj.arguments.iter().map(kind).collect::<Result<_>>()?;
+        Ok((arguments, kind(&obj.return_slot)?))
+    }
+    // Split an input into the arguments of the top function.  A function of
+    // a single argument takes the input as is, otherwise the input must be a
+    // tuple holding the arguments.
+    fn split_arguments(&self, input: &TypedBits) -> Result<Vec<TypedBits>> {
+        let count = self.top_object()?.arguments.len();
+        if count == 1 {
+            return Ok(vec![input.clone()]);
+        }
+        let Kind::Tuple(tuple) = &input.kind else {
+            bail!(
+                "Expected a tuple of {count} arguments, but got {}",
+                input.kind
+            );
+        };
+        ensure!(
+            tuple.elements.len() == count,
+            "Expected a tuple of {count} arguments, but got {}",
+            input.kind
+        );
+        (0..count)
+            .map(|ndx| input.path(&Path::default().index(ndx)))
+            .collect()
+    }
+    // Check that this module computes the same function as `other` on each
+    // of the given inputs, by running both through the RHIF interpreter.
+    // This is only practical for small input spaces.
+    pub fn equivalent_to(
+        &self,
+        other: &Module,
+        inputs: impl Iterator<Item = TypedBits>,
+    ) -> Result<bool> {
+        Ok(self.first_difference(other, inputs)?.is_none())
+    }
+    // As `equivalent_to`, but returns the first input on which the two
+    // modules disagree (or `None` if they agree on all of them).
+    pub fn first_difference(
+        &self,
+        other: &Module,
+        inputs: impl Iterator<Item = TypedBits>,
+    ) -> Result<Option<TypedBits>> {
+        let (my_args, my_ret) = self.signature()?;
+        let (other_args, other_ret) = other.signature()?;
+        ensure!(
+            my_args == other_args && my_ret == other_ret,
+            "Cannot compare {} with {}, as their signatures differ",
+            self.func_name(self.top)?,
+            other.func_name(other.top)?
+        );
+        for input in inputs {
+            let arguments = self.split_arguments(&input)?;
+            let mine = execute_function(self, arguments.clone())?;
+            let theirs = execute_function(other, arguments)?;
+            if mine != theirs {
+                return Ok(Some(input));
+            }
+        }
+        Ok(None)
+    }
 }
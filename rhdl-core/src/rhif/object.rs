@@ -1,16 +1,18 @@
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::fmt::Write;
 
 use crate::{
     ast::ast_impl::{FunctionId, NodeId},
+    codegen::mux::MuxStrategy,
     rhif::spec::{ExternalFunction, Slot},
     Kind, TypedBits,
 };
 
 use super::{spanned_source::SpannedSource, spec::OpCode};
 
-#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Serialize, Deserialize)]
 pub struct SourceLocation {
     pub func: FunctionId,
     pub node: NodeId,
@@ -22,14 +24,38 @@ impl From<(FunctionId, NodeId)> for SourceLocation {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SymbolMap {
     pub source: SpannedSource,
     pub slot_map: BTreeMap<Slot, SourceLocation>,
     pub opcode_map: Vec<SourceLocation>,
+    // The names of the `let` bindings that registers hold, which are kept
+    // (when `CompileOptions::preserve_names` is set) so that the wires in
+    // the generated Verilog can be named after them
+    pub slot_names: BTreeMap<Slot, String>,
 }
 
-#[derive(Debug, Clone)]
+// A value marked with `#[rhdl(probe)]` in the kernel.  The slot holding
+// it is kept through the optimization passes, so that the value can be
+// given its own named wire in the generated Verilog.  A probe marked
+// with `#[rhdl(keep)]` is declared with a `(* keep *)` attribute, so
+// that synthesis does not remove it either.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Probe {
+    pub name: String,
+    pub slot: Slot,
+    pub keep: bool,
+}
+
+// Whether a slot of an object is a register or a literal, as reported
+// by `Object::slots`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlotClass {
+    Register,
+    Literal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Object {
     pub symbols: SymbolMap,
     pub literals: BTreeMap<Slot, TypedBits>,
@@ -40,14 +66,37 @@ pub struct Object {
     pub arguments: Vec<Slot>,
     pub name: String,
     pub fn_id: FunctionId,
+    pub pure: bool,
+    pub const_eval: bool,
+    pub probes: Vec<Probe>,
+    // The flags tested by `#[rhdl(cfg)]` attributes in the kernel, and
+    // whether each was enabled when it was compiled.
+    pub flags: BTreeMap<String, bool>,
+    // The parameters that the kernel was specialized with, in the order
+    // of its inputs.  They are no longer arguments of the object.
+    pub params: Vec<(String, TypedBits)>,
+    // How dynamic indices are written in the Verilog for the object.
+    pub mux_strategy: MuxStrategy,
 }
 
 impl Object {
+    pub fn is_probed(&self, slot: Slot) -> bool {
+        self.probes.iter().any(|probe| probe.slot == slot)
+    }
     pub fn literal(&self, slot: Slot) -> Result<&TypedBits> {
         self.literals
             .get(&slot)
             .ok_or_else(|| anyhow::anyhow!("Not a literal"))
     }
+    // Every slot of the object with its kind, literals first and then
+    // registers, each in order of their index.
+    pub fn slots(&self) -> impl Iterator<Item = (Slot, &Kind, SlotClass)> {
+        self.kind.iter().filter_map(|(slot, kind)| match slot {
+            Slot::Literal(_) => Some((*slot, kind, SlotClass::Literal)),
+            Slot::Register(_) => Some((*slot, kind, SlotClass::Register)),
+            Slot::Empty => None,
+        })
+    }
     pub fn reg_max_index(&self) -> usize {
         self.kind
             .keys()
@@ -76,6 +125,12 @@ impl std::fmt::Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Object {}", self.name)?;
         writeln!(f, "  fn_id {}", self.fn_id)?;
+        if self.pure {
+            writeln!(f, "  pure")?;
+        }
+        if self.const_eval {
+            writeln!(f, "  const_eval")?;
+        }
         for regs in self.kind.keys() {
             if let Slot::Register(ndx) = regs {
                 writeln!(f, "Reg r{} : {}", ndx, self.kind[regs])?;
@@ -84,6 +139,16 @@ impl std::fmt::Display for Object {
         for (slot, literal) in self.literals.iter() {
             writeln!(f, "Literal {} : {} = {}", slot, self.kind[slot], literal)?;
         }
+        for probe in &self.probes {
+            let keep = if probe.keep { " (keep)" } else { "" };
+            writeln!(f, "Probe {} : {}{}", probe.name, probe.slot, keep)?;
+        }
+        for (flag, enabled) in &self.flags {
+            writeln!(f, "Flag {} : {}", flag, enabled)?;
+        }
+        for (name, value) in &self.params {
+            writeln!(f, "Param {} = {}", name, value)?;
+        }
         for (ndx, func) in self.externals.iter().enumerate() {
             writeln!(
                 f,
@@ -112,3 +177,91 @@ impl std::fmt::Display for Object {
         Ok(())
     }
 }
+
+// Builds an object by hand, for the tests of passes and engines that are
+// simpler to give RHIF directly than to get it from a kernel.  The
+// object starts with no slots or operations.  The source locations of
+// the operations are only recorded by `op_with_span`, so an object
+// should use either that or `op` throughout.
+#[cfg(test)]
+pub(crate) struct ObjectBuilder(Object);
+
+#[cfg(test)]
+impl ObjectBuilder {
+    pub(crate) fn new(name: &str, source: &str) -> Self {
+        ObjectBuilder(Object {
+            symbols: SymbolMap {
+                source: SpannedSource {
+                    source: source.into(),
+                    name: name.into(),
+                    span_map: Default::default(),
+                },
+                slot_map: BTreeMap::new(),
+                slot_names: BTreeMap::new(),
+                opcode_map: vec![],
+            },
+            literals: BTreeMap::new(),
+            kind: BTreeMap::new(),
+            return_slot: Slot::Empty,
+            externals: vec![],
+            ops: vec![],
+            arguments: vec![],
+            name: name.into(),
+            fn_id: FunctionId::default(),
+            pure: false,
+            const_eval: false,
+            probes: vec![],
+            flags: Default::default(),
+            params: Default::default(),
+            mux_strategy: Default::default(),
+        })
+    }
+    pub(crate) fn slot(mut self, slot: Slot, kind: Kind) -> Self {
+        self.0.kind.insert(slot, kind);
+        self
+    }
+    // Registers r0, r1, ... with the given kinds
+    pub(crate) fn registers(self, kinds: impl IntoIterator<Item = Kind>) -> Self {
+        kinds
+            .into_iter()
+            .enumerate()
+            .fold(self, |builder, (ndx, kind)| {
+                builder.slot(Slot::Register(ndx), kind)
+            })
+    }
+    pub(crate) fn arguments(mut self, arguments: impl IntoIterator<Item = Slot>) -> Self {
+        self.0.arguments = arguments.into_iter().collect();
+        self
+    }
+    pub(crate) fn returns(mut self, slot: Slot) -> Self {
+        self.0.return_slot = slot;
+        self
+    }
+    pub(crate) fn op(mut self, op: OpCode) -> Self {
+        self.0.ops.push(op);
+        self
+    }
+    // An operation that came from the `span` of the source, which is
+    // that of the AST node `node`
+    pub(crate) fn op_with_span(
+        mut self,
+        op: OpCode,
+        node: NodeId,
+        span: std::ops::Range<usize>,
+    ) -> Self {
+        self.0.symbols.source.span_map.insert(node, span);
+        self.0.symbols.opcode_map.push((self.0.fn_id, node).into());
+        self.op(op)
+    }
+    pub(crate) fn build(self) -> Object {
+        self.0
+    }
+    // The object as a design of its own
+    pub(crate) fn build_module(self) -> crate::Module {
+        let obj = self.0;
+        crate::Module {
+            top: obj.fn_id,
+            objects: [(obj.fn_id, obj)].into_iter().collect(),
+        }
+    }
+}
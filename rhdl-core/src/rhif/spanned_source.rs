@@ -5,15 +5,37 @@ use crate::{
     util::IndentingFormatter,
     Kind,
 };
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{collections::HashMap, ops::Range};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SpannedSource {
     pub source: String,
     pub name: String,
+    #[serde(serialize_with = "sorted_spans", deserialize_with = "span_list")]
     pub span_map: HashMap<NodeId, Range<usize>>,
 }
 
+// The spans are written as a list ordered by node, since node ids cannot
+// be the keys of a map in JSON, and so that the output is the same on
+// every run.
+fn sorted_spans<S: Serializer>(
+    span_map: &HashMap<NodeId, Range<usize>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut spans = span_map.iter().collect::<Vec<_>>();
+    spans.sort_by_key(|(id, _)| **id);
+    spans.serialize(serializer)
+}
+
+fn span_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<NodeId, Range<usize>>, D::Error> {
+    Ok(Vec::<(NodeId, Range<usize>)>::deserialize(deserializer)?
+        .into_iter()
+        .collect())
+}
+
 impl SpannedSource {
     pub fn span(&self, id: NodeId) -> Range<usize> {
         self.span_map[&id].clone()
@@ -22,6 +44,19 @@ impl SpannedSource {
         let span = self.span(id);
         &self.source[span]
     }
+    // The (1-based) number and text of the line on which the span starts,
+    // without the line ending.
+    pub fn line_for(&self, span: Range<usize>) -> (usize, &str) {
+        let start = span.start.min(self.source.len());
+        let (before, after) = self.source.split_at(start);
+        let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+        let line_end = after
+            .find('\n')
+            .map(|x| start + x)
+            .unwrap_or(self.source.len());
+        let line_number = before.matches('\n').count() + 1;
+        (line_number, &self.source[line_start..line_end])
+    }
 }
 
 #[derive(Default)]
@@ -180,6 +215,7 @@ impl SpannedSourceBuilder {
             Kind::Empty => self.push("()"),
             Kind::Signed(n) => self.push(&format!("s{}", n)),
             Kind::Bits(n) => self.push(&format!("b{}", n)),
+            Kind::Ranged(r) => self.kind(&r.base),
             Kind::Tuple(kinds) => {
                 self.push("(");
                 for kind in &kinds.elements {
@@ -278,15 +314,27 @@ impl SpannedSourceBuilder {
                 self.expr(&expr.expr);
                 self.push(" {\n");
                 for arm in &expr.arms {
+                    let arm_start = self.loc();
                     match &arm.kind {
                         ArmKind::Wild => self.push("_"),
                         ArmKind::Constant(constant) => {
                             self.push(&format!("{}", constant.value));
                         }
+                        ArmKind::Range(range) => {
+                            self.push(&format!("{}", range));
+                        }
+                        ArmKind::Or(or) => {
+                            self.push(&format!("{}", or));
+                        }
                         ArmKind::Enum(enum_arm) => {
                             self.pattern(&enum_arm.pat);
                         }
                     }
+                    self.span_map.insert(arm.id, arm_start..self.loc());
+                    if let Some(guard) = &arm.guard {
+                        self.push(" if ");
+                        self.expr(guard);
+                    }
                     self.push(" => ");
                     self.expr(&arm.body);
                     self.push(",\n");
@@ -1,5 +1,6 @@
 // RHDL Intermediate Form (RHIF).
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     kernel::{ExternalKernelDef, Kernel},
@@ -7,7 +8,7 @@ use crate::{
     DigitalSignature, TypedBits,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OpCode {
     Noop,
     // lhs <- arg1 op arg2
@@ -40,10 +41,12 @@ pub enum OpCode {
     AsBits(Cast),
     // x <- a as signed::<len>
     AsSigned(Cast),
+    // Fail the simulation (with the message) if cond is false
+    Assert(Assert),
     Comment(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Binary {
     pub op: AluBinary,
     pub lhs: Slot,
@@ -51,14 +54,14 @@ pub struct Binary {
     pub arg2: Slot,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Unary {
     pub op: AluUnary,
     pub lhs: Slot,
     pub arg1: Slot,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Select {
     pub lhs: Slot,
     pub cond: Slot,
@@ -66,20 +69,20 @@ pub struct Select {
     pub false_value: Slot,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Index {
     pub lhs: Slot,
     pub arg: Slot,
     pub path: Path,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Assign {
     pub lhs: Slot,
     pub rhs: Slot,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Splice {
     pub lhs: Slot,
     pub orig: Slot,
@@ -87,14 +90,14 @@ pub struct Splice {
     pub subst: Slot,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Repeat {
     pub lhs: Slot,
     pub value: Slot,
     pub len: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Struct {
     pub lhs: Slot,
     pub fields: Vec<FieldValue>,
@@ -102,45 +105,51 @@ pub struct Struct {
     pub template: TypedBits,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assert {
+    pub cond: Slot,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Case {
     pub lhs: Slot,
     pub discriminant: Slot,
     pub table: Vec<(CaseArgument, Slot)>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Array {
     pub lhs: Slot,
     pub elements: Vec<Slot>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tuple {
     pub lhs: Slot,
     pub fields: Vec<Slot>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Exec {
     pub lhs: Slot,
     pub id: FuncId,
     pub args: Vec<Slot>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CaseArgument {
     Constant(TypedBits),
     Wild,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FieldValue {
     pub member: Member,
     pub value: Slot,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AluBinary {
     Add,
     Sub,
@@ -149,6 +158,8 @@ pub enum AluBinary {
     BitAnd,
     BitOr,
     Shl,
+    // The signedness of the shift comes from the kind of the first
+    // argument: signed values shift arithmetically, unsigned logically.
     Shr,
     Eq,
     Lt,
@@ -158,7 +169,7 @@ pub enum AluBinary {
     Gt,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AluUnary {
     Neg,
     Not,
@@ -203,16 +214,41 @@ impl Slot {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// Slots are written as they are displayed (`l3`, `r7` or `()`), so that
+// they can be the keys of maps in JSON.
+impl Serialize for Slot {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Slot {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let index = |digits: &str| {
+            digits
+                .parse()
+                .map_err(|_| serde::de::Error::custom(format!("Invalid slot {text}")))
+        };
+        match text.split_at(text.len().min(1)) {
+            ("l", digits) => Ok(Slot::Literal(index(digits)?)),
+            ("r", digits) => Ok(Slot::Register(index(digits)?)),
+            _ if text == "()" => Ok(Slot::Empty),
+            _ => Err(serde::de::Error::custom(format!("Invalid slot {text}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Member {
     Named(String),
     Unnamed(u32),
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FuncId(pub usize);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExternalFunctionCode {
     Kernel(Kernel),
     Extern(ExternalKernelDef),
@@ -236,22 +272,21 @@ impl std::fmt::Display for ExternalFunctionCode {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExternalFunction {
     pub path: String,
     pub code: ExternalFunctionCode,
     pub signature: DigitalSignature,
 }
 
-
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Enum {
     pub lhs: Slot,
     pub fields: Vec<FieldValue>,
     pub template: TypedBits,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Cast {
     pub lhs: Slot,
     pub arg: Slot,
@@ -1,26 +1,45 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::kernel::ExternalKernelDef;
 use crate::path::Path;
 use crate::rhif::object::Object;
 use crate::rhif::spec::{
-    AluBinary, AluUnary, Array, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec, Index,
-    Member, OpCode, Repeat, Slot, Struct, Tuple, Unary,
+    AluBinary, AluUnary, Array, Assert, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
+    Index, Member, OpCode, Repeat, Slot, Struct, Tuple, Unary,
 };
+use crate::Kind;
 use crate::{ast::ast_impl::FunctionId, rhif::module::Module, TypedBits};
-use crate::{Digital, Kind};
 
 use anyhow::Result;
 
 use anyhow::{anyhow, bail};
 
+use super::coverage::{CoverageMap, CoveragePoint};
 use super::spec::{ExternalFunctionCode, Select, Splice};
 
+// Results of calls to pure kernels, keyed by the function and the
+// bits of its arguments.  Kernels that are not marked pure are always
+// evaluated.  The number of times each kernel was actually evaluated
+// is also recorded.
+#[derive(Default, Debug, Clone)]
+pub struct Memo {
+    cache: HashMap<(FunctionId, Vec<Vec<bool>>), TypedBits>,
+    evaluations: HashMap<FunctionId, usize>,
+}
+
+impl Memo {
+    pub fn evaluations(&self, fn_id: FunctionId) -> usize {
+        self.evaluations.get(&fn_id).copied().unwrap_or(0)
+    }
+}
+
 struct VMState<'a> {
     reg_stack: &'a mut [Option<TypedBits>],
     literals: &'a BTreeMap<Slot, TypedBits>,
     design: &'a Module,
     obj: &'a Object,
+    memo: &'a mut Memo,
+    coverage: Option<&'a mut CoverageMap>,
 }
 
 impl<'a> VMState<'a> {
@@ -70,12 +89,33 @@ impl<'a> VMState<'a> {
         }
         Ok(result)
     }
+    fn cover(&mut self, point: impl FnOnce(FunctionId) -> CoveragePoint) {
+        if let Some(coverage) = self.coverage.as_deref_mut() {
+            coverage.hit(point(self.obj.fn_id));
+        }
+    }
+}
+
+// The ALU operations are shared with the constant folding pass, so that
+// it computes exactly what the interpreter would.
+pub(crate) fn binary(op: &AluBinary, arg1: TypedBits, arg2: TypedBits) -> Result<TypedBits> {
+    arg1.binary(op, arg2)
+}
+
+pub(crate) fn unary(op: &AluUnary, arg1: TypedBits) -> Result<TypedBits> {
+    arg1.unary(op)
 }
 
 fn execute_block(ops: &[OpCode], state: &mut VMState) -> Result<()> {
-    for op in ops {
+    for (index, op) in ops.iter().enumerate() {
+        state.cover(|func| CoveragePoint::Op { func, index });
         match op {
             OpCode::Noop => {}
+            OpCode::Assert(Assert { cond, message }) => {
+                if !state.read(*cond)?.any().as_bool()? {
+                    bail!("Assertion failed: {message}");
+                }
+            }
             OpCode::Binary(Binary {
                 op,
                 lhs,
@@ -84,36 +124,11 @@ fn execute_block(ops: &[OpCode], state: &mut VMState) -> Result<()> {
             }) => {
                 let arg1 = state.read(*arg1)?;
                 let arg2 = state.read(*arg2)?;
-                let result = match op {
-                    AluBinary::Add => (arg1 + arg2)?,
-                    AluBinary::Sub => (arg1 - arg2)?,
-                    AluBinary::BitXor => (arg1 ^ arg2)?,
-                    AluBinary::BitAnd => (arg1 & arg2)?,
-                    AluBinary::BitOr => (arg1 | arg2)?,
-                    AluBinary::Eq => (arg1 == arg2).typed_bits(),
-                    AluBinary::Ne => (arg1 != arg2).typed_bits(),
-                    AluBinary::Shl => (arg1 << arg2)?,
-                    AluBinary::Shr => (arg1 >> arg2)?,
-                    AluBinary::Lt => (arg1 < arg2).typed_bits(),
-                    AluBinary::Le => (arg1 <= arg2).typed_bits(),
-                    AluBinary::Gt => (arg1 > arg2).typed_bits(),
-                    AluBinary::Ge => (arg1 >= arg2).typed_bits(),
-                    _ => todo!(),
-                };
-                state.write(*lhs, result)?;
+                state.write(*lhs, binary(op, arg1, arg2)?)?;
             }
             OpCode::Unary(Unary { op, lhs, arg1 }) => {
                 let arg1 = state.read(*arg1)?;
-                let result = match op {
-                    AluUnary::Not => (!arg1)?,
-                    AluUnary::Neg => (-arg1)?,
-                    AluUnary::All => arg1.all(),
-                    AluUnary::Any => arg1.any(),
-                    AluUnary::Signed => arg1.as_signed()?,
-                    AluUnary::Unsigned => arg1.as_unsigned()?,
-                    AluUnary::Xor => arg1.xor(),
-                };
-                state.write(*lhs, result)?;
+                state.write(*lhs, unary(op, arg1)?)?;
             }
             OpCode::Comment(_) => {}
             OpCode::Select(Select {
@@ -140,8 +155,18 @@ fn execute_block(ops: &[OpCode], state: &mut VMState) -> Result<()> {
                                );
                 */
                 if cond.any().as_bool()? {
+                    state.cover(|func| CoveragePoint::Arm {
+                        func,
+                        index,
+                        arm: 0,
+                    });
                     state.write(*lhs, true_value)?;
                 } else {
+                    state.cover(|func| CoveragePoint::Arm {
+                        func,
+                        index,
+                        arm: 1,
+                    });
                     state.write(*lhs, false_value)?;
                 }
             }
@@ -238,15 +263,16 @@ fn execute_block(ops: &[OpCode], state: &mut VMState) -> Result<()> {
                 table,
             }) => {
                 let discriminant = state.read(*discriminant)?;
-                let arm = table
+                let (arm, (_, value)) = table
                     .iter()
-                    .find(|(disc, _)| match disc {
+                    .enumerate()
+                    .find(|(_, (disc, _))| match disc {
                         CaseArgument::Constant(disc) => discriminant == *disc,
                         CaseArgument::Wild => true,
                     })
-                    .ok_or(anyhow!("ICE Case was not exhaustive"))?
-                    .1;
-                let arm = state.read(arm)?;
+                    .ok_or(anyhow!("ICE Case was not exhaustive"))?;
+                state.cover(|func| CoveragePoint::Arm { func, index, arm });
+                let arm = state.read(*value)?;
                 state.write(*lhs, arm)?;
             }
             OpCode::AsBits(Cast { lhs, arg, len }) => {
@@ -266,9 +292,13 @@ fn execute_block(ops: &[OpCode], state: &mut VMState) -> Result<()> {
                     .collect::<Result<Vec<_>>>()?;
                 let func = &state.obj.externals[id.0];
                 let result = match &func.code {
-                    ExternalFunctionCode::Kernel(kernel) => {
-                        execute(state.design, kernel.inner().fn_id, args)?
-                    }
+                    ExternalFunctionCode::Kernel(kernel) => execute(
+                        state.design,
+                        kernel.inner().fn_id,
+                        args,
+                        state.memo,
+                        state.coverage.as_deref_mut(),
+                    )?,
                     ExternalFunctionCode::Extern(ExternalKernelDef {
                         name,
                         body: _,
@@ -293,12 +323,41 @@ fn execute_block(ops: &[OpCode], state: &mut VMState) -> Result<()> {
     Ok(())
 }
 
-fn execute(design: &Module, fn_id: FunctionId, arguments: Vec<TypedBits>) -> Result<TypedBits> {
+fn execute(
+    design: &Module,
+    fn_id: FunctionId,
+    arguments: Vec<TypedBits>,
+    memo: &mut Memo,
+    coverage: Option<&mut CoverageMap>,
+) -> Result<TypedBits> {
     // Load the object for this function
     let obj = design
         .objects
         .get(&fn_id)
         .ok_or(anyhow::anyhow!("Function {fn_id} not found"))?;
+    let key = obj.pure.then(|| {
+        let bits = arguments.iter().map(|x| x.bits.clone()).collect::<Vec<_>>();
+        (fn_id, bits)
+    });
+    if let Some(result) = key.as_ref().and_then(|key| memo.cache.get(key)) {
+        return Ok(result.clone());
+    }
+    let result = execute_uncached(design, obj, arguments, memo, coverage)?;
+    *memo.evaluations.entry(fn_id).or_default() += 1;
+    if let Some(key) = key {
+        memo.cache.insert(key, result.clone());
+    }
+    Ok(result)
+}
+
+fn execute_uncached(
+    design: &Module,
+    obj: &Object,
+    arguments: Vec<TypedBits>,
+    memo: &mut Memo,
+    coverage: Option<&mut CoverageMap>,
+) -> Result<TypedBits> {
+    let fn_id = obj.fn_id;
     if obj.arguments.len() != arguments.len() {
         bail!(
             "Function {fn_id} expected {expected} arguments, got {got}",
@@ -337,6 +396,8 @@ fn execute(design: &Module, fn_id: FunctionId, arguments: Vec<TypedBits>) -> Res
         literals: &obj.literals,
         design,
         obj,
+        memo,
+        coverage,
     };
     execute_block(&obj.ops, &mut state)?;
     match obj.return_slot {
@@ -357,5 +418,41 @@ fn execute(design: &Module, fn_id: FunctionId, arguments: Vec<TypedBits>) -> Res
 // Given a set of arguments in the form of TypedBits, execute the function described by a Design
 // and then return the result as a TypedBits.
 pub fn execute_function(design: &Module, arguments: Vec<TypedBits>) -> Result<TypedBits> {
-    execute(design, design.top, arguments)
+    execute(design, design.top, arguments, &mut Memo::default(), None)
+}
+
+// As `execute_function`, but for any kernel of the design, rather than
+// the top one.
+pub(crate) fn execute_kernel(
+    design: &Module,
+    fn_id: FunctionId,
+    arguments: Vec<TypedBits>,
+) -> Result<TypedBits> {
+    execute(design, fn_id, arguments, &mut Memo::default(), None)
+}
+
+// As `execute_function`, but results of pure kernels are kept in the
+// given memo table, so that they can be reused across calls.
+pub fn execute_function_memoized(
+    design: &Module,
+    arguments: Vec<TypedBits>,
+    memo: &mut Memo,
+) -> Result<TypedBits> {
+    execute(design, design.top, arguments, memo, None)
+}
+
+// As `execute_function`, but also counts the opcodes and arms that were
+// exercised in the given coverage map.
+pub fn execute_function_with_coverage(
+    design: &Module,
+    arguments: Vec<TypedBits>,
+    coverage: &mut CoverageMap,
+) -> Result<TypedBits> {
+    execute(
+        design,
+        design.top,
+        arguments,
+        &mut Memo::default(),
+        Some(coverage),
+    )
 }
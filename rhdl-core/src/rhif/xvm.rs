@@ -0,0 +1,365 @@
+// A three-valued version of the interpreter in `vm`, in which bits may
+// be unknown (X).  Unknown bits come from the arguments (say, a register
+// that has not been initialized), and are propagated through the kernel
+// the way they would be in hardware, so that reads of values that were
+// never written show up as X in the output.
+use std::collections::BTreeMap;
+
+use crate::kernel::ExternalKernelDef;
+use crate::path::{Path, PathElement};
+use crate::rhif::object::Object;
+use crate::rhif::spec::{
+    AluBinary, AluUnary, Array, Assert, Assign, Binary, Case, CaseArgument, Cast, Enum, Exec,
+    Index, Member, OpCode, Repeat, Slot, Struct, Tuple, Unary,
+};
+use crate::types::x_typed_bits::XTypedBits;
+use crate::{ast::ast_impl::FunctionId, rhif::module::Module, Kind, TypedBits};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::spec::{ExternalFunctionCode, Select, Splice};
+
+struct XVMState<'a> {
+    reg_stack: &'a mut [Option<XTypedBits>],
+    literals: &'a BTreeMap<Slot, TypedBits>,
+    design: &'a Module,
+    obj: &'a Object,
+}
+
+impl<'a> XVMState<'a> {
+    fn read(&self, slot: Slot) -> Result<XTypedBits> {
+        match slot {
+            Slot::Literal(l) => self
+                .literals
+                .get(&Slot::Literal(l))
+                .cloned()
+                .map(XTypedBits::from)
+                .ok_or(anyhow!("ICE Literal {l} not found in object")),
+            Slot::Register(r) => self
+                .reg_stack
+                .get(r)
+                .ok_or(anyhow!("ICE Register {r} not found in register stack"))?
+                .clone()
+                .ok_or(anyhow!("ICE Register {r} is not initialized")),
+            Slot::Empty => Ok(TypedBits::EMPTY.into()),
+        }
+    }
+    fn write(&mut self, slot: Slot, value: XTypedBits) -> Result<()> {
+        match slot {
+            Slot::Literal(_) => bail!("ICE Cannot write to literal"),
+            Slot::Register(r) => {
+                self.reg_stack[r] = Some(value);
+                Ok(())
+            }
+            Slot::Empty => {
+                if value.kind().is_empty() {
+                    Ok(())
+                } else {
+                    bail!("ICE Cannot write non-empty value to empty slot")
+                }
+            }
+        }
+    }
+    fn kind(&self, slot: Slot) -> Result<Kind> {
+        self.obj
+            .kind
+            .get(&slot)
+            .cloned()
+            .ok_or(anyhow!("ICE No kind for slot {slot:?}"))
+    }
+    // Resolve the dynamic indices of a path, or return `None` if one of
+    // them is not known.
+    fn resolve_dynamic_paths(&mut self, path: &Path) -> Result<Option<Path>> {
+        let mut result = Path::default();
+        for element in &path.elements {
+            match element {
+                PathElement::DynamicIndex(slot) => {
+                    let slot = self.read(*slot)?;
+                    if !slot.is_known() {
+                        return Ok(None);
+                    }
+                    let ndx = slot.value.as_i64()?;
+                    result = result.index(ndx as usize);
+                }
+                _ => result.elements.push(element.clone()),
+            }
+        }
+        Ok(Some(result))
+    }
+}
+
+fn execute_block(ops: &[OpCode], state: &mut XVMState) -> Result<()> {
+    for op in ops {
+        match op {
+            OpCode::Noop => {}
+            OpCode::Assert(Assert { cond, message }) => {
+                let cond = state.read(*cond)?.unary(&AluUnary::Any)?;
+                if !cond.is_known() {
+                    bail!("Assertion on an unknown (X) condition: {message}");
+                }
+                if !cond.value.as_bool()? {
+                    bail!("Assertion failed: {message}");
+                }
+            }
+            OpCode::Binary(Binary {
+                op,
+                lhs,
+                arg1,
+                arg2,
+            }) => {
+                let arg1 = state.read(*arg1)?;
+                let arg2 = state.read(*arg2)?;
+                state.write(*lhs, arg1.binary(op, arg2)?)?;
+            }
+            OpCode::Unary(Unary { op, lhs, arg1 }) => {
+                let arg1 = state.read(*arg1)?;
+                state.write(*lhs, arg1.unary(op)?)?;
+            }
+            OpCode::Comment(_) => {}
+            OpCode::Select(Select {
+                lhs,
+                cond,
+                true_value,
+                false_value,
+            }) => {
+                let cond = state.read(*cond)?.unary(&AluUnary::Any)?;
+                let true_value = state.read(*true_value)?;
+                let false_value = state.read(*false_value)?;
+                let result = if !cond.is_known() {
+                    true_value.merge(&false_value)?
+                } else if cond.value.as_bool()? {
+                    true_value
+                } else {
+                    false_value
+                };
+                state.write(*lhs, result)?;
+            }
+            OpCode::Index(Index { lhs, arg, path }) => {
+                let arg = state.read(*arg)?;
+                let result = match state.resolve_dynamic_paths(path)? {
+                    Some(path) => arg.path(&path)?,
+                    None => XTypedBits::unknown(state.kind(*lhs)?),
+                };
+                state.write(*lhs, result)?;
+            }
+            OpCode::Splice(Splice {
+                lhs,
+                orig: rhs,
+                path,
+                subst: arg,
+            }) => {
+                let rhs_val = state.read(*rhs)?;
+                let arg_val = state.read(*arg)?;
+                let result = match state.resolve_dynamic_paths(path)? {
+                    Some(path) => rhs_val.splice(&path, arg_val)?,
+                    None => XTypedBits::unknown(rhs_val.kind().clone()),
+                };
+                state.write(*lhs, result)?;
+            }
+            OpCode::Assign(Assign { lhs, rhs }) => {
+                state.write(*lhs, state.read(*rhs)?)?;
+            }
+            OpCode::Tuple(Tuple { lhs, fields }) => {
+                let fields = fields
+                    .iter()
+                    .map(|x| state.read(*x))
+                    .collect::<Result<Vec<_>>>()?;
+                let kind = Kind::make_tuple(fields.iter().map(|x| x.kind().clone()).collect());
+                state.write(*lhs, concatenate(&fields, kind)?)?;
+            }
+            OpCode::Array(Array { lhs, elements }) => {
+                let elements = elements
+                    .iter()
+                    .map(|x| state.read(*x))
+                    .collect::<Result<Vec<_>>>()?;
+                let kind = Kind::make_array(elements[0].kind().clone(), elements.len());
+                state.write(*lhs, concatenate(&elements, kind)?)?;
+            }
+            OpCode::Struct(Struct {
+                lhs,
+                fields,
+                rest,
+                template,
+            }) => {
+                let mut result = if let Some(rest) = rest {
+                    state.read(*rest)?
+                } else {
+                    template.clone().into()
+                };
+                for field in fields {
+                    let value = state.read(field.value)?;
+                    let path = match &field.member {
+                        Member::Unnamed(ndx) => Path::default().index(*ndx as usize),
+                        Member::Named(name) => Path::default().field(name),
+                    };
+                    result = result.splice(&path, value)?;
+                }
+                state.write(*lhs, result)?;
+            }
+            OpCode::Enum(Enum {
+                lhs,
+                fields,
+                template,
+            }) => {
+                let mut result: XTypedBits = template.clone().into();
+                for field in fields {
+                    let base_path =
+                        Path::default().payload_by_value(template.discriminant()?.as_i64()?);
+                    let value = state.read(field.value)?;
+                    let path = match &field.member {
+                        Member::Unnamed(ndx) => base_path.index(*ndx as usize),
+                        Member::Named(name) => base_path.field(name),
+                    };
+                    result = result.splice(&path, value)?;
+                }
+                state.write(*lhs, result)?;
+            }
+            OpCode::Case(Case {
+                lhs,
+                discriminant,
+                table,
+            }) => {
+                let discriminant = state.read(*discriminant)?;
+                // With an unknown discriminant, any arm whose value agrees
+                // with the known bits may be taken.
+                let mut result: Option<XTypedBits> = None;
+                for (disc, value) in table {
+                    let (matches, certain) = match disc {
+                        CaseArgument::Constant(disc) => {
+                            let eq = discriminant
+                                .clone()
+                                .binary(&AluBinary::Eq, disc.clone().into())?;
+                            (!eq.is_known() || eq.value.as_bool()?, eq.is_known())
+                        }
+                        CaseArgument::Wild => (true, true),
+                    };
+                    if !matches {
+                        continue;
+                    }
+                    let value = state.read(*value)?;
+                    result = Some(match result {
+                        Some(prev) => prev.merge(&value)?,
+                        None => value,
+                    });
+                    if certain {
+                        break;
+                    }
+                }
+                let result = result.ok_or(anyhow!("ICE Case was not exhaustive"))?;
+                state.write(*lhs, result)?;
+            }
+            OpCode::AsBits(Cast { lhs, arg, len }) => {
+                let arg = state.read(*arg)?;
+                state.write(*lhs, arg.unsigned_cast(*len)?)?;
+            }
+            OpCode::AsSigned(Cast { lhs, arg, len }) => {
+                let arg = state.read(*arg)?;
+                state.write(*lhs, arg.signed_cast(*len)?)?;
+            }
+            OpCode::Exec(Exec { lhs, id, args }) => {
+                let args = args
+                    .iter()
+                    .map(|x| state.read(*x))
+                    .collect::<Result<Vec<_>>>()?;
+                let func = &state.obj.externals[id.0];
+                let result = match &func.code {
+                    ExternalFunctionCode::Kernel(kernel) => {
+                        execute(state.design, kernel.inner().fn_id, args)?
+                    }
+                    ExternalFunctionCode::Extern(ExternalKernelDef {
+                        name,
+                        body: _,
+                        vm_stub,
+                    }) => {
+                        let Some(stub) = vm_stub else {
+                            bail!("No VM stub for {name}")
+                        };
+                        // The stub only knows about two-valued bits.
+                        if args.iter().all(|x| x.is_known()) {
+                            let args = args.into_iter().map(|x| x.value).collect::<Vec<_>>();
+                            stub(&args)?.into()
+                        } else {
+                            XTypedBits::unknown(state.kind(*lhs)?)
+                        }
+                    }
+                };
+                state.write(*lhs, result)?;
+            }
+            OpCode::Repeat(Repeat { lhs, value, len }) => {
+                let value = state.read(*value)?;
+                state.write(*lhs, value.repeat(*len))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn concatenate(parts: &[XTypedBits], kind: Kind) -> Result<XTypedBits> {
+    let bits = parts
+        .iter()
+        .flat_map(|x| x.value.bits.iter().cloned())
+        .collect();
+    let known = parts.iter().flat_map(|x| x.known.iter().cloned()).collect();
+    XTypedBits::new(TypedBits { bits, kind }, known)
+}
+
+fn execute(design: &Module, fn_id: FunctionId, arguments: Vec<XTypedBits>) -> Result<XTypedBits> {
+    let obj = design
+        .objects
+        .get(&fn_id)
+        .ok_or(anyhow::anyhow!("Function {fn_id} not found"))?;
+    if obj.arguments.len() != arguments.len() {
+        bail!(
+            "Function {fn_id} expected {expected} arguments, got {got}",
+            expected = obj.arguments.len(),
+            got = arguments.len()
+        );
+    }
+    for (ndx, arg) in arguments.iter().enumerate() {
+        let obj_kind = obj
+            .kind
+            .get(&obj.arguments[ndx])
+            .ok_or(anyhow!("ICE argument {ndx} type not found in object"))?;
+        if obj_kind != arg.kind() {
+            bail!(
+                "Function {fn_id} argument {ndx} expected {obj_kind}, got {got}",
+                got = arg.kind()
+            );
+        }
+    }
+    let max_reg = obj.reg_max_index() + 1;
+    let mut reg_stack = vec![None; max_reg + 1];
+    for (ndx, arg) in arguments.into_iter().enumerate() {
+        if let Slot::Register(r) = obj.arguments[ndx] {
+            reg_stack[r] = Some(arg);
+        }
+    }
+    let mut state = XVMState {
+        reg_stack: &mut reg_stack,
+        literals: &obj.literals,
+        design,
+        obj,
+    };
+    execute_block(&obj.ops, &mut state)?;
+    match obj.return_slot {
+        Slot::Empty => Ok(TypedBits::EMPTY.into()),
+        Slot::Register(r) => reg_stack
+            .get(r)
+            .cloned()
+            .ok_or(anyhow!("return slot not found"))?
+            .ok_or(anyhow!("ICE return slot is not initialized")),
+        Slot::Literal(ndx) => obj
+            .literals
+            .get(&Slot::Literal(ndx))
+            .cloned()
+            .map(XTypedBits::from)
+            .ok_or(anyhow!("return literal not found")),
+    }
+}
+
+// As `vm::execute_function`, but the arguments may contain unknown (X)
+// bits.  The result says which of its bits are known; use
+// `XTypedBits::into_known` to flag an X that reached the output.
+pub fn execute_function_x(design: &Module, arguments: Vec<XTypedBits>) -> Result<XTypedBits> {
+    execute(design, design.top, arguments)
+}
@@ -99,7 +99,8 @@ impl<'a> SchematicBuilder<'a> {
                 }
                 OpCode::Assign(assign) => self.make_assign(assign, Some(location)),
                 OpCode::Exec(exec) => self.make_exec(exec, Some(location)),
-                OpCode::Noop | OpCode::Comment(_) => Ok(()),
+                // Assertions are only checked in simulation
+                OpCode::Noop | OpCode::Comment(_) | OpCode::Assert(_) => Ok(()),
             }?
         }
         self.schematic.output = self.lookup(self.object.return_slot)?;
@@ -313,7 +313,7 @@ impl ComponentKind {
         }
     }
 }
-pub trait BlackBoxTrait: core::fmt::Debug {
+pub trait BlackBoxTrait: core::fmt::Debug + Send + Sync {
     fn name(&self) -> &str;
     fn args(&self) -> Vec<PinIx>;
     fn output(&self) -> PinIx;
@@ -36,11 +36,23 @@ pub fn constraint_input_synchronous(input: PinPath, clock: PinPath, edge: EdgeTy
     })
 }
 
+// The input may come from another clock domain (or from no clock at
+// all), because the component synchronizes it to the clock.  This is how
+// a synchronizer marks itself as a place where signals cross domains.
+pub fn constraint_domain_crossing(input: PinPath, clock: PinPath) -> Constraint {
+    Constraint::DomainCrossing(DomainCrossingConstraint {
+        input,
+        clock,
+        help: "This input is synchronized to the clock".to_string(),
+    })
+}
+
 pub enum Constraint {
     MustClock(MustClockConstraint),
     NotConstantValued(NotConstantValuedConstraint),
     OutputSynchronous(OutputSynchronousConstraint),
     InputSynchronous(InputSynchronousConstraint),
+    DomainCrossing(DomainCrossingConstraint),
 }
 
 pub enum EdgeType {
@@ -71,3 +83,9 @@ pub struct InputSynchronousConstraint {
     pub edge: EdgeType,
     pub help: String,
 }
+
+pub struct DomainCrossingConstraint {
+    pub input: PinPath,
+    pub clock: PinPath,
+    pub help: String,
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use super::{
+    components::{BlackBoxTrait, Component, ComponentKind},
+    schematic_impl::{PinIx, Schematic},
+};
+
+// A JSON netlist of a schematic, for tools (visualizers, equivalence
+// checkers) that do not read DOT.  It is easiest to use on a flattened
+// schematic (see `Schematic::inlined`), as the pins inside of a kernel
+// component are not listed.  The netlist looks like
+//
+//  {
+//    "inputs": ["p0"],
+//    "output": "p3",
+//    "components": [
+//      {"id": "c0", "kind": "buffer", "name": "buffer", "path": [],
+//       "pins": [{"id": "p0", "name": "in", "kind": {..}, "width": 4}, ..]},
+//      ..
+//    ],
+//    "nets": [{"pins": ["p1", "p5"]}, ..]
+//  }
+//
+// where a net is a set of pins that are wired together, and the kind of
+// a pin is the serialized `Kind`.  The ids are the same as those used in
+// the DOT output.
+impl Schematic {
+    pub fn to_json_netlist(&self) -> Value {
+        let components = self
+            .components
+            .iter()
+            .enumerate()
+            .filter(|(_, component)| !component.is_noop())
+            .map(|(ndx, component)| self.json_component(ndx, component))
+            .collect::<Vec<_>>();
+        let pin_ids = |pins: &[PinIx]| pins.iter().map(|pin| pin.to_string()).collect::<Vec<_>>();
+        let nets = self
+            .nets()
+            .iter()
+            .map(|net| json!({ "pins": pin_ids(net) }))
+            .collect::<Vec<_>>();
+        json!({
+            "inputs": pin_ids(&self.inputs),
+            "output": self.output.to_string(),
+            "components": components,
+            "nets": nets,
+        })
+    }
+    fn json_component(&self, ndx: usize, component: &Component) -> Value {
+        let pins = self
+            .pins
+            .iter()
+            .enumerate()
+            .filter(|(_, pin)| usize::from(pin.parent) == ndx)
+            .map(|(pin_ndx, pin)| {
+                json!({
+                    "id": format!("p{pin_ndx}"),
+                    "name": pin.name,
+                    "kind": pin.kind,
+                    "width": pin.kind.bits(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let (kind, name) = json_kind_and_name(&component.kind);
+        json!({
+            "id": format!("c{ndx}"),
+            "kind": kind,
+            "name": name,
+            "path": component.path,
+            "pins": pins,
+        })
+    }
+    // Group the pins into nets, by following the wires between them.
+    // Pins on no-op components (left behind by inlining) are not part
+    // of the netlist.
+    fn nets(&self) -> Vec<Vec<PinIx>> {
+        let mut parent = (0..self.pins.len()).collect::<Vec<_>>();
+        fn root(parent: &mut [usize], mut ndx: usize) -> usize {
+            while parent[ndx] != ndx {
+                parent[ndx] = parent[parent[ndx]];
+                ndx = parent[ndx];
+            }
+            ndx
+        }
+        let live = |pin: PinIx| {
+            let component = usize::from(self.pin(pin).parent);
+            self.components
+                .get(component)
+                .is_some_and(|component| !component.is_noop())
+        };
+        for wire in &self.wires {
+            if !live(wire.source) || !live(wire.dest) {
+                continue;
+            }
+            let source = root(&mut parent, wire.source.into());
+            let dest = root(&mut parent, wire.dest.into());
+            parent[dest] = source;
+        }
+        let mut nets: BTreeMap<usize, Vec<PinIx>> = BTreeMap::new();
+        for wire in &self.wires {
+            if !live(wire.source) || !live(wire.dest) {
+                continue;
+            }
+            for pin in [wire.source, wire.dest] {
+                let net = nets.entry(root(&mut parent, pin.into())).or_default();
+                if !net.contains(&pin) {
+                    net.push(pin);
+                }
+            }
+        }
+        nets.into_values()
+            .map(|mut net| {
+                net.sort_by_key(|pin| usize::from(*pin));
+                net
+            })
+            .collect()
+    }
+}
+
+fn json_kind_and_name(kind: &ComponentKind) -> (&'static str, String) {
+    match kind {
+        ComponentKind::Buffer(_) => ("buffer", "buffer".into()),
+        ComponentKind::Binary(binary) => ("binary", binary.op.to_string()),
+        ComponentKind::Unary(unary) => ("unary", unary.op.to_string()),
+        ComponentKind::Select(_) => ("select", "mux".into()),
+        ComponentKind::Index(index) => ("index", index.path.to_string()),
+        ComponentKind::Splice(splice) => ("splice", splice.path.to_string()),
+        ComponentKind::Repeat(repeat) => ("repeat", format!("repeat {}", repeat.len)),
+        ComponentKind::Struct(structure) => ("struct", structure.kind.get_name()),
+        ComponentKind::Tuple(_) => ("tuple", "tuple".into()),
+        ComponentKind::Case(_) => ("case", "case".into()),
+        ComponentKind::BlackBox(black_box) => ("black_box", black_box.name().into()),
+        ComponentKind::Kernel(kernel) => ("kernel", kernel.name.clone()),
+        ComponentKind::Array(_) => ("array", "array".into()),
+        ComponentKind::Enum(enumerate) => ("enum", enumerate.template.kind.get_name()),
+        ComponentKind::Constant(constant) => ("constant", constant.value.to_string()),
+        ComponentKind::Cast(_) => ("cast", "cast".into()),
+        ComponentKind::Noop => ("noop", "noop".into()),
+    }
+}
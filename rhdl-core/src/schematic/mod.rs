@@ -2,4 +2,5 @@ pub mod builder;
 pub mod components;
 pub mod constraints;
 pub mod dot;
+pub mod json;
 pub mod schematic_impl;
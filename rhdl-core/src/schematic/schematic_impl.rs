@@ -4,10 +4,12 @@ use crate::{
     ast::ast_impl::FunctionId,
     path::Path,
     rhif::{object::SourceLocation, spanned_source::SpannedSource},
-    Kind,
+    Digital, Kind,
 };
 
-use super::components::{BufferComponent, Component, ComponentKind};
+use super::components::{
+    BufferComponent, Component, ComponentKind, ConstantComponent, IndexComponent, TupleComponent,
+};
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub struct PinIx(usize);
@@ -18,6 +20,12 @@ impl PinIx {
     }
 }
 
+impl From<PinIx> for usize {
+    fn from(val: PinIx) -> Self {
+        val.0
+    }
+}
+
 impl std::fmt::Display for PinIx {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "p{}", self.0)
@@ -126,6 +134,62 @@ impl Schematic {
         self.pin_mut(output).parent(buf);
         (input, output)
     }
+    // An index component that picks the named field out of the value on
+    // `source`.  Returns the pin that carries the field.
+    pub fn make_field_tap(&mut self, source: PinIx, field: &str, kind: Kind) -> PinIx {
+        let arg_kind = self.pin(source).kind.clone();
+        let arg = self.make_pin(arg_kind, "i".into(), None);
+        let output = self.make_pin(kind.clone(), field.into(), None);
+        let index = self.make_component(
+            ComponentKind::Index(IndexComponent {
+                arg,
+                path: Path::default().field(field),
+                output,
+                kind,
+                dynamic: vec![],
+            }),
+            None,
+        );
+        self.pin_mut(arg).parent(index);
+        self.pin_mut(output).parent(index);
+        self.wire(source, arg);
+        output
+    }
+    // The update schematic of a circuit returns the pair (O, D).  A hand
+    // written circuit has no children, so its D is empty.  Pair the output
+    // pin with an empty D, and return the pin that carries the pair.
+    pub fn make_update_output(&mut self, output: PinIx) -> PinIx {
+        let output_kind = self.pin(output).kind.clone();
+        let empty = self.make_pin(Kind::Empty, "constant".into(), None);
+        let constant = self.make_component(
+            ComponentKind::Constant(ConstantComponent {
+                value: ().typed_bits(),
+                output: empty,
+            }),
+            None,
+        );
+        self.pin_mut(empty).parent(constant);
+        let o = self.make_pin(output_kind.clone(), "o".into(), None);
+        let d = self.make_pin(Kind::Empty, "d".into(), None);
+        let pair = self.make_pin(
+            Kind::make_tuple(vec![output_kind, Kind::Empty]),
+            "update_out".into(),
+            None,
+        );
+        let tuple = self.make_component(
+            ComponentKind::Tuple(TupleComponent {
+                fields: vec![o, d],
+                output: pair,
+            }),
+            None,
+        );
+        self.pin_mut(o).parent(tuple);
+        self.pin_mut(d).parent(tuple);
+        self.pin_mut(pair).parent(tuple);
+        self.wire(output, o);
+        self.wire(empty, d);
+        pair
+    }
     pub fn make_component(
         &mut self,
         kind: ComponentKind,
@@ -151,6 +215,30 @@ impl Schematic {
     pub fn component(&self, ix: ComponentIx) -> &Component {
         &self.components[ix.0]
     }
+    // The number of wires that drive the pin, and that the pin drives.
+    // Nets with many drivers or sinks are hard to route, so these give a
+    // rough hint of congestion before the design is synthesized.
+    pub fn fanin(&self, pin: PinIx) -> usize {
+        self.wires.iter().filter(|w| w.dest == pin).count()
+    }
+    pub fn fanout(&self, pin: PinIx) -> usize {
+        self.wires.iter().filter(|w| w.source == pin).count()
+    }
+    // The pin with the largest fan-in (or fan-out), and its count.  Ties
+    // go to the first pin.  None if there are no wires.
+    pub fn max_fanin(&self) -> Option<(PinIx, usize)> {
+        self.max_count(self.wires.iter().map(|w| w.dest))
+    }
+    pub fn max_fanout(&self) -> Option<(PinIx, usize)> {
+        self.max_count(self.wires.iter().map(|w| w.source))
+    }
+    fn max_count(&self, pins: impl Iterator<Item = PinIx>) -> Option<(PinIx, usize)> {
+        let mut counts = vec![0; self.pins.len()];
+        pins.for_each(|pin| counts[pin.0] += 1);
+        let max = *counts.iter().max().filter(|max| **max > 0)?;
+        let pin = counts.iter().position(|count| *count == max)?;
+        Some((PinIx(pin), max))
+    }
     // Inline all of the Components that are Kernel invocations into
     // this schematic by replacing the KernelComponent with the
     // sub_schematic.  This can be done recursively, but when the
@@ -288,3 +376,51 @@ impl Trace {
             .chain(self.sinks.iter().cloned())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Kind;
+
+    use super::Schematic;
+
+    #[test]
+    fn test_fanin_and_fanout() {
+        let mut schematic = Schematic::default();
+        let kind = Kind::make_bits(4);
+        let (input_rx, input_tx) = schematic.make_buffer(kind.clone(), None);
+        // The input drives four buffers
+        let sinks = (0..4)
+            .map(|_| schematic.make_buffer(kind.clone(), None))
+            .collect::<Vec<_>>();
+        for (rx, _) in &sinks {
+            schematic.wire(input_tx, *rx);
+        }
+        // Two of which drive the output
+        let (output_rx, output_tx) = schematic.make_buffer(kind, None);
+        schematic.wire(sinks[1].1, output_rx);
+        schematic.wire(sinks[2].1, output_rx);
+        schematic.inputs = vec![input_rx];
+        schematic.output = output_tx;
+        assert_eq!(schematic.fanout(input_tx), 4);
+        assert_eq!(schematic.fanin(input_tx), 0);
+        for (rx, tx) in &sinks {
+            assert_eq!(schematic.fanin(*rx), 1);
+            assert_eq!(schematic.fanout(*rx), 0);
+            assert!(schematic.fanout(*tx) <= 1);
+        }
+        assert_eq!(schematic.fanin(output_rx), 2);
+        assert_eq!(schematic.max_fanout(), Some((input_tx, 4)));
+        assert_eq!(schematic.max_fanin(), Some((output_rx, 2)));
+        assert_eq!(Schematic::default().max_fanin(), None);
+    }
+
+    #[test]
+    fn test_no_fanin_or_fanout_without_wires() {
+        let mut schematic = Schematic::default();
+        let (input_rx, input_tx) = schematic.make_buffer(Kind::make_bits(4), None);
+        schematic.inputs = vec![input_rx];
+        schematic.output = input_tx;
+        assert_eq!(schematic.max_fanin(), None);
+        assert_eq!(schematic.max_fanout(), None);
+    }
+}
@@ -1,7 +1,16 @@
-use crate::rhif::vm::execute_function;
-use crate::TypedBits;
+use crate::circuit::output_mask::{clear_ignored, MaskStats, OutputMask};
+use crate::path::{bit_range, leaf_paths, Path};
+use crate::rhif::bytecode::Simulator;
+use crate::rhif::coverage::CoverageMap;
+use crate::rhif::vm::execute_function_with_coverage;
+use crate::Module;
 use crate::{
-    compile_design, generate_verilog, kernel::ExternalKernelDef, Digital, DigitalFn, KernelFnKind,
+    as_verilog_literal, verify_initial_state, Circuit, CircuitIO, HDLKind, HDLPortDirection,
+    TypedBits,
+};
+use crate::{
+    compile_design, generate_verilog, kernel::ExternalKernelDef, Digital, DigitalFnSignature,
+    DigitalSignature, KernelFnKind,
 };
 use anyhow::Result;
 use anyhow::{bail, ensure};
@@ -59,17 +68,77 @@ impl<T0: Digital, T1: Digital, T2: Digital, T3: Digital, T4: Digital> TestArg
 }
 
 pub trait Testable<Args, T1> {
-    fn test_string(&self, name: &str, args: Args) -> String;
+    // The expected result for the arguments, and the Verilog call to
+    // the function `name` that should give it.
+    fn test_call(&self, name: &str, args: Args) -> (TypedBits, String);
     fn apply(&self, args: Args) -> T1;
 }
 
 fn verilog_binary_string(x: impl Digital) -> String {
-    let q = x.binary_string();
-    if q.is_empty() {
+    verilog_bits_literal(&x.bin())
+}
+
+fn verilog_bits_literal(bits: &[bool]) -> String {
+    if bits.is_empty() {
         "0".to_string()
     } else {
-        format!("{x_bits}'b{q}", x_bits = q.len(), q = q)
+        let q = bits
+            .iter()
+            .rev()
+            .map(|b| if *b { '1' } else { '0' })
+            .collect::<String>();
+        format!("{x_bits}'b{q}", x_bits = bits.len())
+    }
+}
+
+// How a testbench displays the values that it compares.  `Hex` is the
+// most compact.  `Binary` shows every bit.  `Exploded` displays each
+// leaf of the result separately (signed leaves as signed decimals), so
+// that a mismatch names the field that differs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TestDisplay {
+    #[default]
+    Hex,
+    Binary,
+    Exploded,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TestModuleOptions {
+    pub display: TestDisplay,
+}
+
+// The result of the call is held in `result`, so that its fields can be
+// sliced out.  Each field is displayed as its name followed by the
+// expected and the actual value.
+fn exploded_case(expected: &TypedBits, call: &str) -> Result<String> {
+    let mut format = vec![];
+    let mut values = vec![];
+    for path in leaf_paths(&expected.kind, Path::default()) {
+        let (range, kind) = bit_range(expected.kind.clone(), &path)?;
+        if range.is_empty() {
+            continue;
+        }
+        let literal = verilog_bits_literal(&expected.bits[range.clone()]);
+        let slice = format!("result[{}:{}]", range.end - 1, range.start);
+        let name = if path.is_empty() {
+            "value".to_string()
+        } else {
+            path.to_string()
+        };
+        if kind.is_signed() {
+            format.push(format!("{name} %0d %0d"));
+            values.push(format!("$signed({literal}), $signed({slice})"));
+        } else {
+            format.push(format!("{name} 0x%0h 0x%0h"));
+            values.push(format!("{literal}, {slice}"));
+        }
     }
+    Ok(format!(
+        "result = {call}; $display(\"{}\", {});\n",
+        format.join(" "),
+        values.join(", ")
+    ))
 }
 
 impl<F, Q, T0> Testable<(T0,), Q> for F
@@ -78,11 +147,11 @@ where
     T0: Digital,
     Q: Digital,
 {
-    fn test_string(&self, name: &str, args: (T0,)) -> String {
+    fn test_call(&self, name: &str, args: (T0,)) -> (TypedBits, String) {
         let (t0,) = args;
-        let q = verilog_binary_string((*self)(t0));
+        let q = (*self)(t0).typed_bits();
         let t0 = verilog_binary_string(t0);
-        format!("$display(\"0x%0h 0x%0h\", {q}, {name}({t0}));\n")
+        (q, format!("{name}({t0})"))
     }
     fn apply(&self, args: (T0,)) -> Q {
         let (t0,) = args;
@@ -97,12 +166,12 @@ where
     T1: Digital,
     Q: Digital,
 {
-    fn test_string(&self, name: &str, args: (T0, T1)) -> String {
+    fn test_call(&self, name: &str, args: (T0, T1)) -> (TypedBits, String) {
         let (t0, t1) = args;
-        let q = verilog_binary_string((*self)(t0, t1));
+        let q = (*self)(t0, t1).typed_bits();
         let t0 = verilog_binary_string(t0);
         let t1 = verilog_binary_string(t1);
-        format!("$display(\"0x%0h 0x%0h\", {q}, {name}({t0},{t1}));\n")
+        (q, format!("{name}({t0},{t1})"))
     }
     fn apply(&self, args: (T0, T1)) -> Q {
         let (t0, t1) = args;
@@ -118,13 +187,13 @@ where
     T2: Digital,
     Q: Digital,
 {
-    fn test_string(&self, name: &str, args: (T0, T1, T2)) -> String {
+    fn test_call(&self, name: &str, args: (T0, T1, T2)) -> (TypedBits, String) {
         let (t0, t1, t2) = args;
-        let q = verilog_binary_string((*self)(t0, t1, t2));
+        let q = (*self)(t0, t1, t2).typed_bits();
         let t0 = verilog_binary_string(t0);
         let t1 = verilog_binary_string(t1);
         let t2 = verilog_binary_string(t2);
-        format!("$display(\"0x%0h 0x%0h\", {q}, {name}({t0},{t1},{t2}));\n")
+        (q, format!("{name}({t0},{t1},{t2})"))
     }
     fn apply(&self, args: (T0, T1, T2)) -> Q {
         let (t0, t1, t2) = args;
@@ -141,14 +210,14 @@ where
     T3: Digital,
     Q: Digital,
 {
-    fn test_string(&self, name: &str, args: (T0, T1, T2, T3)) -> String {
+    fn test_call(&self, name: &str, args: (T0, T1, T2, T3)) -> (TypedBits, String) {
         let (t0, t1, t2, t3) = args;
-        let q = verilog_binary_string((*self)(t0, t1, t2, t3));
+        let q = (*self)(t0, t1, t2, t3).typed_bits();
         let t0 = verilog_binary_string(t0);
         let t1 = verilog_binary_string(t1);
         let t2 = verilog_binary_string(t2);
         let t3 = verilog_binary_string(t3);
-        format!("$display(\"0x%0h 0x%0h\", {q}, {name}({t0},{t1},{t2},{t3}));\n")
+        (q, format!("{name}({t0},{t1},{t2},{t3})"))
     }
     fn apply(&self, args: (T0, T1, T2, T3)) -> Q {
         let (t0, t1, t2, t3) = args;
@@ -166,15 +235,15 @@ where
     T4: Digital,
     Q: Digital,
 {
-    fn test_string(&self, name: &str, args: (T0, T1, T2, T3, T4)) -> String {
+    fn test_call(&self, name: &str, args: (T0, T1, T2, T3, T4)) -> (TypedBits, String) {
         let (t0, t1, t2, t3, t4) = args;
-        let q = verilog_binary_string((*self)(t0, t1, t2, t3, t4));
+        let q = (*self)(t0, t1, t2, t3, t4).typed_bits();
         let t0 = verilog_binary_string(t0);
         let t1 = verilog_binary_string(t1);
         let t2 = verilog_binary_string(t2);
         let t3 = verilog_binary_string(t3);
         let t4 = verilog_binary_string(t4);
-        format!("$display(\"0x%0h 0x%0h\", {q}, {name}({t0},{t1},{t2},{t3},{t4}));\n")
+        (q, format!("{name}({t0},{t1},{t2},{t3},{t4})"))
     }
     fn apply(&self, args: (T0, T1, T2, T3, T4)) -> Q {
         let (t0, t1, t2, t3, t4) = args;
@@ -186,25 +255,45 @@ fn test_module<F, Args, T0>(
     uut: F,
     desc: VerilogDescriptor,
     vals: impl Iterator<Item = Args>,
-) -> TestModule
+    options: &TestModuleOptions,
+) -> Result<TestModule>
 where
     F: Testable<Args, T0>,
     T0: Digital,
 {
     let VerilogDescriptor { name, body } = desc;
     let mut num_cases = 0;
+    // A result with no bits has nothing to slice
+    let display = match options.display {
+        TestDisplay::Exploded if T0::bits() == 0 => TestDisplay::Hex,
+        display => display,
+    };
     let cases = vals
         .map(|x| {
             num_cases += 1;
             x
         })
-        .map(|arg| uut.test_string(&name, arg))
-        .collect::<String>();
-    TestModule {
+        .map(|arg| {
+            let (expected, call) = uut.test_call(&name, arg);
+            let q = verilog_bits_literal(&expected.bits);
+            Ok(match display {
+                TestDisplay::Hex => format!("$display(\"0x%0h 0x%0h\", {q}, {call});\n"),
+                TestDisplay::Binary => format!("$display(\"0b%b 0b%b\", {q}, {call});\n"),
+                TestDisplay::Exploded => exploded_case(&expected, &call)?,
+            })
+        })
+        .collect::<Result<String>>()?;
+    let result = if display == TestDisplay::Exploded {
+        format!("reg[{}:0] result;\n", T0::bits() - 1)
+    } else {
+        String::new()
+    };
+    Ok(TestModule {
         testbench: format!(
             "
 module testbench;
    {body}
+   {result}
 
    initial
        begin
@@ -215,7 +304,205 @@ endmodule
     "
         ),
         num_cases,
+        stimulus: None,
+        masked: Default::default(),
+    })
+}
+
+// The file of test vectors loaded by a testbench built with
+// `TestModule::new_with_stimulus_file`, relative to the directory in
+// which the testbench runs.
+pub const STIMULUS_FILE: &str = "stimulus.hex";
+
+// Render bits (LSB first) as a line of a `$readmemh` file.
+fn readmemh_line(bits: &[bool]) -> String {
+    let mut line = bits
+        .chunks(4)
+        .rev()
+        .map(|nibble| {
+            let digit = nibble
+                .iter()
+                .rev()
+                .fold(0, |acc, b| (acc << 1) | (*b as u32));
+            char::from_digit(digit, 16).unwrap()
+        })
+        .collect::<String>();
+    line.push('\n');
+    line
+}
+
+// Rather than writing each case into the `initial` block, which makes
+// for a testbench that grows with the number of cases, the expected
+// value and the arguments of each case are packed into a vector (with
+// the expected value in the low bits), and the vectors are loaded from
+// a file with `$readmemh` and looped over.
+fn test_module_from_file<F, Args, T0>(
+    uut: F,
+    desc: VerilogDescriptor,
+    vals: impl Iterator<Item = Args>,
+    options: &TestModuleOptions,
+) -> Result<TestModule>
+where
+    F: Testable<Args, T0>,
+    T0: Digital,
+    Args: TestArg,
+{
+    let VerilogDescriptor { name, body } = desc;
+    let format = match options.display {
+        TestDisplay::Hex => "0x%0h 0x%0h",
+        TestDisplay::Binary => "0b%b 0b%b",
+        TestDisplay::Exploded => {
+            bail!("The exploded display is not supported with a stimulus file")
+        }
+    };
+    let mut stimulus = String::new();
+    let mut arg_widths = vec![];
+    let mut num_cases = 0;
+    for arg in vals {
+        let args = arg.vec_tb();
+        let expected = uut.apply(arg).typed_bits();
+        arg_widths = args.iter().map(|x| x.bits.len()).collect();
+        let vector = expected
+            .bits
+            .into_iter()
+            .chain(args.into_iter().flat_map(|x| x.bits))
+            .collect::<Vec<_>>();
+        stimulus.push_str(&readmemh_line(&vector));
+        num_cases += 1;
     }
+    let slice = |start: usize, width: usize| {
+        if width == 0 {
+            "0".to_string()
+        } else {
+            format!("vectors[i][{}:{start}]", start + width - 1)
+        }
+    };
+    let expected = slice(0, T0::bits());
+    let mut start = T0::bits();
+    let args = arg_widths
+        .iter()
+        .map(|width| {
+            let arg = slice(start, *width);
+            start += width;
+            arg
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let width = start.max(1);
+    let depth = num_cases.max(1);
+    Ok(TestModule {
+        testbench: format!(
+            "
+module testbench;
+   {body}
+   reg[{msb}:0] vectors[0:{last}];
+   integer i;
+
+   initial
+       begin
+$readmemh(\"{STIMULUS_FILE}\", vectors);
+for (i = 0; i < {num_cases}; i = i + 1)
+    $display(\"{format}\", {expected}, {name}({args}));
+$finish;
+       end
+endmodule
+    ",
+            msb = width - 1,
+            last = depth - 1,
+        ),
+        num_cases,
+        stimulus: Some(stimulus),
+        masked: Default::default(),
+    })
+}
+
+// The testbench for a circuit applies each input in turn, and after
+// letting the design settle, compares its output with the output of the
+// simulation for the same input.  Clocks are part of the input, so the
+// input trace must include both phases of each clock.
+pub(crate) fn circuit_test_module<C: Circuit>(
+    circuit: &C,
+    inputs: &[<C as CircuitIO>::I],
+) -> Result<TestModule> {
+    masked_circuit_test_module(circuit, inputs, &OutputMask::default())
+}
+
+// As `circuit_test_module`, but the bits of the output ignored by `mask`
+// are cleared in both the expected and the actual output before they
+// are displayed, so that the HDL may leave them undefined.
+pub(crate) fn masked_circuit_test_module<C: Circuit>(
+    circuit: &C,
+    inputs: &[<C as CircuitIO>::I],
+    mask: &OutputMask<C::O>,
+) -> Result<TestModule> {
+    ensure!(
+        C::O::bits() != 0,
+        "Circuit {} has no outputs to test",
+        circuit.name()
+    );
+    verify_initial_state(circuit)?;
+    let hdl = circuit.as_hdl(HDLKind::Verilog)?;
+    let mut state = circuit.init_state();
+    let mut io = C::Z::default();
+    let has_input = C::I::bits() != 0;
+    let mut masked = MaskStats::default();
+    let cases = inputs
+        .iter()
+        .map(|input| {
+            let output = circuit.sim(*input, &mut state, &mut io);
+            let drive = if has_input {
+                format!("i = {}; ", as_verilog_literal(&input.typed_bits()))
+            } else {
+                String::new()
+            };
+            let ignored = mask.ignored(&output);
+            masked.record(&ignored);
+            let (expected, actual) = if ignored.contains(&true) {
+                let keep = ignored.iter().map(|bit| !bit).collect::<Vec<_>>();
+                (
+                    verilog_bits_literal(&clear_ignored(&output.bin(), &ignored)),
+                    format!("o & {}", verilog_bits_literal(&keep)),
+                )
+            } else {
+                (as_verilog_literal(&output.typed_bits()), "o".to_string())
+            };
+            format!("      {drive}#1; $display(\"0x%0h 0x%0h\", {expected}, {actual});\n")
+        })
+        .collect::<String>();
+    let (input_decl, input_port) = if has_input {
+        (
+            format!("   reg[{}:0] i;\n", C::I::bits() - 1),
+            format!(
+                ".{}(i), ",
+                hdl.port_name(HDLPortDirection::Input).unwrap_or("i")
+            ),
+        )
+    } else {
+        (String::new(), String::new())
+    };
+    let output_port = hdl.port_name(HDLPortDirection::Output).unwrap_or("o");
+    Ok(TestModule {
+        testbench: format!(
+            "
+module testbench;
+{input_decl}   wire[{output_bits}:0] o;
+
+   {name} dut({input_port}.{output_port}(o));
+
+   initial begin
+{cases}      $finish;
+   end
+endmodule
+
+{hdl}
+",
+            output_bits = C::O::bits() - 1,
+            name = hdl.name,
+        ),
+        num_cases: inputs.len(),
+        stimulus: None,
+        masked,
+    })
 }
 
 pub struct VerilogDescriptor {
@@ -276,6 +563,12 @@ impl std::fmt::Display for VerilogDescriptor {
 pub struct TestModule {
     pub testbench: String,
     pub num_cases: usize,
+    // The test vectors that the testbench loads from `STIMULUS_FILE`, if
+    // it does not have them inline.
+    pub stimulus: Option<String>,
+    // The don't-care bits of the output of a circuit that the testbench
+    // leaves out of each comparison (see `Circuit::masked_testbench`).
+    pub masked: MaskStats,
 }
 
 impl TestModule {
@@ -288,7 +581,35 @@ impl TestModule {
         F: Testable<Args, T0>,
         T0: Digital,
     {
-        test_module(uut, desc, vals)
+        test_module(uut, desc, vals, &TestModuleOptions::default())
+            .expect("The default display needs no slicing")
+    }
+    pub fn new_with_options<F, Args, T0>(
+        uut: F,
+        desc: VerilogDescriptor,
+        vals: impl Iterator<Item = Args>,
+        options: &TestModuleOptions,
+    ) -> Result<TestModule>
+    where
+        F: Testable<Args, T0>,
+        T0: Digital,
+    {
+        test_module(uut, desc, vals, options)
+    }
+    // A testbench that reads its test vectors from `STIMULUS_FILE`,
+    // for sets of cases too large to write out in the testbench itself.
+    pub fn new_with_stimulus_file<F, Args, T0>(
+        uut: F,
+        desc: VerilogDescriptor,
+        vals: impl Iterator<Item = Args>,
+        options: &TestModuleOptions,
+    ) -> Result<TestModule>
+    where
+        F: Testable<Args, T0>,
+        T0: Digital,
+        Args: TestArg,
+    {
+        test_module_from_file(uut, desc, vals, options)
     }
 }
 
@@ -299,7 +620,25 @@ pub fn test_kernel_vm_and_verilog<K, F, Args, T0>(
 where
     F: Testable<Args, T0>,
     T0: Digital,
-    K: DigitalFn,
+    K: DigitalFnSignature,
+    Args: TestArg,
+{
+    test_kernel_vm_and_verilog_with_options::<K, F, Args, T0>(
+        uut,
+        vals,
+        &TestModuleOptions::default(),
+    )
+}
+
+pub fn test_kernel_vm_and_verilog_with_options<K, F, Args, T0>(
+    uut: F,
+    vals: impl Iterator<Item = Args> + Clone,
+    options: &TestModuleOptions,
+) -> Result<()>
+where
+    F: Testable<Args, T0>,
+    T0: Digital,
+    K: DigitalFnSignature,
     Args: TestArg,
 {
     let Some(KernelFnKind::Kernel(kernel)) = K::kernel_fn() else {
@@ -308,12 +647,71 @@ where
     let design = compile_design(kernel)?;
     let verilog = generate_verilog(&design)?;
     eprintln!("Verilog {}", verilog);
-    let vm_inputs = vals.clone();
+    test_vm(&design, &K::signature(), &uut, vals.clone(), None)?;
+    let tm = test_module(uut, verilog, vals, options)?;
+    //eprintln!("{tm}");
+    tm.run_iverilog()
+}
+
+// Check the kernel against the RHIF interpreter only (so that iverilog is
+// not needed), and return the coverage of the design by the given inputs.
+pub fn test_kernel_vm_with_coverage<K, F, Args, T0>(
+    uut: F,
+    vals: impl Iterator<Item = Args>,
+) -> Result<CoverageMap>
+where
+    F: Testable<Args, T0>,
+    T0: Digital,
+    K: DigitalFnSignature,
+    Args: TestArg,
+{
+    let Some(KernelFnKind::Kernel(kernel)) = K::kernel_fn() else {
+        bail!("Kernel function not found");
+    };
+    let design = compile_design(kernel)?;
+    let mut coverage = CoverageMap::new(&design);
+    test_vm(&design, &K::signature(), &uut, vals, Some(&mut coverage))?;
+    Ok(coverage)
+}
+
+// The test vectors and the function under test are checked against the
+// declared signature of the kernel, so that testing a kernel against the
+// wrong function is reported as such, rather than as a failed case.
+fn test_vm<F, Args, T0>(
+    design: &Module,
+    signature: &DigitalSignature,
+    uut: &F,
+    vals: impl Iterator<Item = Args>,
+    mut coverage: Option<&mut CoverageMap>,
+) -> Result<()>
+where
+    F: Testable<Args, T0>,
+    T0: Digital,
+    Args: TestArg,
+{
+    ensure!(
+        T0::static_kind() == signature.ret,
+        "The function under test returns {}, but the kernel returns {}",
+        T0::static_kind(),
+        signature.ret
+    );
+    let simulator = Simulator::new(design);
     let mut vm_test_count = 0;
-    for input in vm_inputs {
+    for input in vals {
         let args_for_vm = input.vec_tb();
+        let arg_kinds = args_for_vm
+            .iter()
+            .map(|arg| arg.kind.clone())
+            .collect::<Vec<_>>();
+        ensure!(
+            arg_kinds == signature.arguments,
+            "The test vectors do not match the signature of the kernel {signature}"
+        );
         let expected = uut.apply(input).typed_bits();
-        let actual = execute_function(&design, args_for_vm)?;
+        let actual = match coverage.as_deref_mut() {
+            Some(coverage) => execute_function_with_coverage(design, args_for_vm, coverage)?,
+            None => simulator.execute(args_for_vm)?,
+        };
         ensure!(
             expected == actual,
             "VM test failed - expected {:?} but got {:?}",
@@ -323,9 +721,78 @@ where
         vm_test_count += 1;
     }
     eprintln!("VM test passed {} cases OK", vm_test_count);
-    let tm = test_module(uut, verilog, vals);
-    //eprintln!("{tm}");
-    tm.run_iverilog()
+    Ok(())
+}
+
+const VERILOG_OPERATORS: [&str; 19] = [
+    "<<<", ">>>", "===", "!==", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "**", "~&", "~|",
+    "~^", "^~", "+:", "-:",
+];
+
+// Split Verilog source into tokens, dropping whitespace and comments.
+// Sized literals like `8'b0101` are kept as a single token.
+pub fn verilog_tokens(text: &str) -> Vec<String> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '$' | '\'' | '`');
+    let mut tokens = vec![];
+    let mut ndx = 0;
+    while ndx < chars.len() {
+        let rest = &chars[ndx..];
+        let start = ndx;
+        if rest[0].is_whitespace() {
+            ndx += 1;
+            continue;
+        }
+        if rest.starts_with(&['/', '/']) {
+            while ndx < chars.len() && chars[ndx] != '\n' {
+                ndx += 1;
+            }
+            continue;
+        }
+        if rest.starts_with(&['/', '*']) {
+            ndx += 2;
+            while ndx < chars.len() && !chars[ndx..].starts_with(&['*', '/']) {
+                ndx += 1;
+            }
+            ndx = (ndx + 2).min(chars.len());
+            continue;
+        }
+        if rest[0] == '"' {
+            ndx += 1;
+            while ndx < chars.len() && chars[ndx] != '"' {
+                if chars[ndx] == '\\' {
+                    ndx += 1;
+                }
+                ndx += 1;
+            }
+            ndx = (ndx + 1).min(chars.len());
+        } else if rest[0] == '\\' {
+            // An escaped identifier runs up to the next whitespace
+            while ndx < chars.len() && !chars[ndx].is_whitespace() {
+                ndx += 1;
+            }
+        } else if is_word(rest[0]) {
+            while ndx < chars.len() && is_word(chars[ndx]) {
+                ndx += 1;
+            }
+        } else if let Some(op) = VERILOG_OPERATORS
+            .iter()
+            .find(|op| op.chars().eq(rest.iter().copied().take(op.len())))
+        {
+            ndx += op.len();
+        } else {
+            ndx += 1;
+        }
+        tokens.push(chars[start..ndx].iter().collect());
+    }
+    tokens
+}
+
+// Compare two pieces of Verilog, ignoring differences in whitespace and
+// comments.  Useful for checking that a change to the code generator
+// did not change the logic it emits.
+pub fn verilog_semantic_eq(a: &str, b: &str) -> bool {
+    verilog_tokens(a) == verilog_tokens(b)
 }
 
 impl std::fmt::Display for TestModule {
@@ -337,10 +804,26 @@ impl std::fmt::Display for TestModule {
 #[cfg(feature = "iverilog")]
 impl TestModule {
     pub fn run_iverilog(&self) -> anyhow::Result<()> {
+        self.check_output(&self.iverilog_output()?)?;
+        if self.masked.total() != 0 {
+            eprintln!(
+                "iverilog test passed {} cases OK ({})",
+                self.num_cases, self.masked
+            );
+        } else {
+            eprintln!("iverilog test passed {} cases OK", self.num_cases);
+        }
+        Ok(())
+    }
+    // Compile and run the testbench, and return what it displays
+    pub fn iverilog_output(&self) -> anyhow::Result<String> {
         let d = tempfile::tempdir()?;
         // Write the test bench to a file
         let d_path = d.path();
         std::fs::write(d_path.join("testbench.v"), &self.testbench)?;
+        if let Some(stimulus) = &self.stimulus {
+            std::fs::write(d_path.join(STIMULUS_FILE), stimulus)?;
+        }
         // Compile the test bench
         let mut cmd = std::process::Command::new("iverilog");
         cmd.arg("-o")
@@ -353,20 +836,38 @@ impl TestModule {
             bail!("Failed to compile testbench with {}", status);
         }
         let mut cmd = std::process::Command::new("vvp");
-        cmd.arg(d_path.join("testbench"));
+        cmd.arg(d_path.join("testbench")).current_dir(d_path);
         let output = cmd.output()?;
-        for case in String::from_utf8_lossy(&output.stdout)
-            .lines()
-            .take(self.num_cases)
-            .map(|line| line.split(' ').collect::<Vec<_>>())
-        {
-            let expected = case[0];
-            let actual = case[1];
-            if case[0] != case[1] {
-                bail!("Expected {} but got {}", expected, actual);
+        Ok(String::from_utf8_lossy(&output.stdout).into())
+    }
+}
+
+impl TestModule {
+    // Compare the expected and actual values displayed by the simulator.
+    // A line is either the two values, or (in the exploded display) the
+    // name of each field followed by its expected and actual values.
+    pub fn check_output(&self, output: &str) -> Result<()> {
+        for (ndx, line) in output.lines().take(self.num_cases).enumerate() {
+            let case = line.split(' ').collect::<Vec<_>>();
+            if case.len() == 2 {
+                if case[0] != case[1] {
+                    bail!("Expected {} but got {}", case[0], case[1]);
+                }
+                continue;
+            }
+            ensure!(
+                case.len().is_multiple_of(3),
+                "Cannot parse the output `{line}` of case {ndx}"
+            );
+            let mismatches = case
+                .chunks(3)
+                .filter(|field| field[1] != field[2])
+                .map(|field| format!("{} expected {} but got {}", field[0], field[1], field[2]))
+                .collect::<Vec<_>>();
+            if !mismatches.is_empty() {
+                bail!("In case {ndx}, {}", mismatches.join(", "));
             }
         }
-        eprintln!("iverilog test passed {} cases OK", self.num_cases);
         Ok(())
     }
 }
@@ -383,7 +884,7 @@ where
     F: Testable<Args, T0>,
     T0: Digital,
 {
-    test_module(uut, desc, vals).run_iverilog()
+    test_module(uut, desc, vals, &TestModuleOptions::default())?.run_iverilog()
 }
 
 impl TryFrom<KernelFnKind> for VerilogDescriptor {
@@ -467,6 +968,47 @@ mod tests {
         module.run_iverilog()
     }
 
+    #[test]
+    fn test_verilog_semantic_eq() {
+        let a = "
+module top(input wire[3:0] i, output wire[3:0] o);
+   // Flip the low bit
+   assign o = i ^ 4'b0001;
+endmodule
+";
+        let b = "module top (input wire [3:0] i, output wire [3:0] o); /* generated */
+assign o=i^4'b0001; endmodule";
+        assert!(verilog_semantic_eq(a, b));
+        let c = "module top(input wire[3:0] i, output wire[3:0] o);
+   assign o = i ^ 4'b0010;
+endmodule";
+        assert!(!verilog_semantic_eq(a, c));
+        // `<<` is a single operator, and not the same as `< <`
+        assert!(!verilog_semantic_eq("a << b", "a < < b"));
+        assert_eq!(
+            verilog_tokens("r0 <= {a[3:0], \"x y\"}; // done"),
+            ["r0", "<=", "{", "a", "[", "3", ":", "0", "]", ",", "\"x y\"", "}", ";"]
+        );
+    }
+
+    #[test]
+    fn test_check_output_names_the_field() {
+        let tm = TestModule {
+            testbench: String::new(),
+            num_cases: 2,
+            stimulus: None,
+            masked: Default::default(),
+        };
+        assert!(tm.check_output("0x5 0x5\n0x3 0x3\n").is_ok());
+        let err = tm.check_output("0x5 0x5\n0x3 0x7\n").unwrap_err();
+        assert_eq!(err.to_string(), "Expected 0x3 but got 0x7");
+        let err = tm
+            .check_output(".a 0x5 0x5 .b -3 -3\n.a 0x1 0x1 .b -3 5\n")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "In case 1, .b expected -3 but got 5");
+        assert!(tm.check_output(".a 0x5 0x5 .b\n").is_err());
+    }
+
     #[test]
     fn test_xor_generic() -> anyhow::Result<()> {
         let nibbles_a = (0..=15).map(bits);
@@ -0,0 +1,152 @@
+// Support for property based testing with proptest.
+//
+// `arbitrary_digital` generates values of any `Digital` type from its
+// kind: it draws a valid bit pattern with `arbitrary_typed_bits` and
+// decodes it with `maybe_from_bin`.  Enums only ever get discriminants
+// that correspond to a variant, and all values shrink toward all zero
+// bits (and the first variant of an enum).  Shrinking works on the bits,
+// so it does not know about the fields of a struct.  For field-wise
+// shrinking, user types can instead `#[derive(Arbitrary)]` using
+// `proptest-derive` and use `any::<T>()`.  The `Bits` and `SignedBits`
+// types implement `Arbitrary` (with the `proptest` feature enabled).
+use proptest::prelude::*;
+use proptest::strategy::Union;
+
+pub use proptest;
+
+use crate::{Digital, Kind, TypedBits};
+
+// The generated bits are always valid for the kind, so a value is only
+// rejected if `T` does not support decoding from bits at all.
+pub fn arbitrary_digital<T: Digital + std::fmt::Debug>() -> impl Strategy<Value = T> {
+    arbitrary_bits(&T::static_kind())
+        .prop_filter_map("Type does not support conversion from bits", |bits| {
+            T::maybe_from_bin(&bits).ok()
+        })
+}
+
+fn concat<'a>(kinds: impl Iterator<Item = &'a Kind>) -> BoxedStrategy<Vec<bool>> {
+    kinds
+        .map(arbitrary_bits)
+        .collect::<Vec<_>>()
+        .prop_map(|x| x.concat())
+        .boxed()
+}
+
+fn arbitrary_bits(kind: &Kind) -> BoxedStrategy<Vec<bool>> {
+    match kind {
+        Kind::Bits(n) | Kind::Signed(n) => prop::collection::vec(any::<bool>(), *n).boxed(),
+        Kind::Empty => Just(vec![]).boxed(),
+        // Only generate values that are within the bounds
+        Kind::Ranged(ranged) => {
+            let width = ranged.base.bits();
+            (ranged.min..=ranged.max)
+                .prop_map(move |x| (0..width).map(|i| (x >> i.min(127)) & 1 == 1).collect())
+                .boxed()
+        }
+        Kind::Array(array) => concat(std::iter::repeat_n(array.base.as_ref(), array.size)),
+        Kind::Tuple(tuple) => concat(tuple.elements.iter()),
+        Kind::Struct(structure) => concat(structure.fields.iter().map(|f| &f.kind)),
+        Kind::Enum(enumerate) => {
+            let width = enumerate.discriminant_layout.width;
+            Union::new(enumerate.variants.iter().map(|variant| {
+                let kind = kind.clone();
+                let discriminant = (0..width)
+                    .map(|i| (variant.discriminant >> i.min(63)) & 1 == 1)
+                    .collect::<Vec<_>>();
+                arbitrary_bits(&variant.kind).prop_map(move |payload| {
+                    kind.pad(discriminant.iter().copied().chain(payload).collect())
+                })
+            }))
+            .boxed()
+        }
+    }
+}
+
+pub fn arbitrary_typed_bits(kind: Kind) -> impl Strategy<Value = TypedBits> {
+    arbitrary_bits(&kind).prop_map(move |bits| TypedBits {
+        bits,
+        kind: kind.clone(),
+    })
+}
+
+// Check that a kernel gives the same answer when run as a Rust function
+// and through the RHIF interpreter.  Use inside a proptest property:
+//
+//   prop_assert_kernel_equiv!(design, add, a, b);
+//
+// where `design` is the result of compiling the kernel.  The arguments
+// are evaluated twice, so they should be plain values.  To also collect
+// the coverage of the design, pass a `CoverageMap` first:
+//
+//   prop_assert_kernel_equiv!(coverage = &mut map, design, add, a, b);
+#[macro_export]
+macro_rules! prop_assert_kernel_equiv {
+    (coverage = $coverage:expr, $design:expr, $func:expr, $($arg:expr),+ $(,)?) => {{
+        let expected = $crate::Digital::typed_bits($func($($arg),+));
+        let actual = $crate::rhif::vm::execute_function_with_coverage(
+            &$design,
+            vec![$($crate::Digital::typed_bits($arg)),+],
+            $coverage,
+        )
+        .map_err(|err| {
+            $crate::types::arbitrary::proptest::test_runner::TestCaseError::fail(err.to_string())
+        })?;
+        $crate::types::arbitrary::proptest::prop_assert_eq!(expected, actual);
+    }};
+    ($design:expr, $func:expr, $($arg:expr),+ $(,)?) => {{
+        let expected = $crate::Digital::typed_bits($func($($arg),+));
+        let actual = $crate::rhif::vm::execute_function(
+            &$design,
+            vec![$($crate::Digital::typed_bits($arg)),+],
+        )
+        .map_err(|err| {
+            $crate::types::arbitrary::proptest::test_runner::TestCaseError::fail(err.to_string())
+        })?;
+        $crate::types::arbitrary::proptest::prop_assert_eq!(expected, actual);
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiscriminantAlignment, DiscriminantType};
+    use rhdl_bits::{Bits, SignedBits};
+
+    fn enum_kind() -> Kind {
+        Kind::make_enum(
+            "Test",
+            vec![
+                Kind::make_variant("A", Kind::Empty, -1),
+                Kind::make_variant("B", Kind::make_bits(4), 2),
+                Kind::make_variant(
+                    "C",
+                    Kind::make_struct(
+                        "C",
+                        vec![
+                            Kind::make_field("x", Kind::make_signed(3)),
+                            Kind::make_field("y", Kind::make_array(Kind::make_bool(), 2)),
+                        ],
+                    ),
+                    5,
+                ),
+            ],
+            Kind::make_discriminant_layout(4, DiscriminantAlignment::Msb, DiscriminantType::Signed),
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn test_enum_discriminants_are_valid(tb in arbitrary_typed_bits(enum_kind())) {
+            prop_assert_eq!(tb.bits.len(), enum_kind().bits());
+            let discriminant = tb.discriminant().unwrap().as_i64().unwrap();
+            prop_assert!([-1, 2, 5].contains(&discriminant));
+        }
+
+        #[test]
+        fn test_wide_bits(x in arbitrary_digital::<Bits<128>>(), y in any::<SignedBits<128>>()) {
+            prop_assert_eq!(x.typed_bits().bits.len(), 128);
+            prop_assert_eq!(y.typed_bits().bits.len(), 128);
+        }
+    }
+}
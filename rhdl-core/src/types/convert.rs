@@ -0,0 +1,177 @@
+use anyhow::{bail, ensure, Result};
+
+use crate::{Digital, Kind};
+
+use super::kind::Enum;
+
+/// How closely the layouts of two [Digital] types must match before
+/// one can be converted into the other by reinterpreting its bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    /// The kinds must be identical, apart from the names of the types
+    /// themselves.  Field names, variant names and discriminants must all
+    /// match.
+    Strict,
+    /// The kinds must have the same leaves in the same order.  Names are
+    /// ignored, but each leaf must have the same width and signedness, and
+    /// enums must line up with enums of the same shape.
+    Layout,
+    /// The kinds only need to have the same number of bits.
+    Loose,
+}
+
+/// Convert a value into another type with a compatible layout (in the
+/// sense of [Compatibility::Layout]), by way of its binary representation.
+pub fn convert<A: Digital, B: Digital>(a: A) -> Result<B> {
+    convert_with(a, Compatibility::Layout)
+}
+
+pub fn convert_with<A: Digital, B: Digital>(a: A, mode: Compatibility) -> Result<B> {
+    check_compatible(&A::static_kind(), &B::static_kind(), mode)?;
+    B::maybe_from_bin(&a.bin())
+}
+
+pub fn check_compatible(from: &Kind, to: &Kind, mode: Compatibility) -> Result<()> {
+    ensure!(
+        from.bits() == to.bits(),
+        "Cannot convert {} ({} bits) to {} ({} bits)",
+        from,
+        from.bits(),
+        to,
+        to.bits()
+    );
+    let compatible = match mode {
+        Compatibility::Strict => strip_type_names(from) == strip_type_names(to),
+        Compatibility::Layout => layouts_match(from, to),
+        Compatibility::Loose => true,
+    };
+    if !compatible {
+        bail!(
+            "Cannot convert {} to {}, as their layouts are not compatible ({:?})",
+            from,
+            to,
+            mode
+        );
+    }
+    Ok(())
+}
+
+fn strip_type_names(kind: &Kind) -> Kind {
+    match kind {
+        Kind::Array(array) => Kind::make_array(strip_type_names(&array.base), array.size),
+        Kind::Tuple(tuple) => {
+            Kind::make_tuple(tuple.elements.iter().map(strip_type_names).collect())
+        }
+        Kind::Struct(structure) => Kind::make_struct(
+            "",
+            structure
+                .fields
+                .iter()
+                .map(|field| Kind::make_field(&field.name, strip_type_names(&field.kind)))
+                .collect(),
+        ),
+        Kind::Enum(enumerate) => {
+            let mut enumerate = enumerate.clone();
+            enumerate.name = Default::default();
+            for variant in &mut enumerate.variants {
+                variant.kind = strip_type_names(&variant.kind);
+            }
+            Kind::Enum(enumerate)
+        }
+        Kind::Bits(_) | Kind::Signed(_) | Kind::Empty => kind.clone(),
+        Kind::Ranged(r) => strip_type_names(&r.base),
+    }
+}
+
+enum Leaf<'a> {
+    Bits(usize),
+    Signed(usize),
+    Enum(&'a Enum),
+}
+
+fn leaves<'a>(kind: &'a Kind, out: &mut Vec<Leaf<'a>>) {
+    match kind {
+        Kind::Array(array) => {
+            for _ in 0..array.size {
+                leaves(&array.base, out);
+            }
+        }
+        Kind::Tuple(tuple) => tuple.elements.iter().for_each(|x| leaves(x, out)),
+        Kind::Struct(structure) => structure
+            .fields
+            .iter()
+            .for_each(|field| leaves(&field.kind, out)),
+        Kind::Enum(enumerate) => out.push(Leaf::Enum(enumerate)),
+        Kind::Bits(width) => out.push(Leaf::Bits(*width)),
+        Kind::Signed(width) => out.push(Leaf::Signed(*width)),
+        Kind::Empty => {}
+        Kind::Ranged(r) => leaves(&r.base, out),
+    }
+}
+
+fn layouts_match(from: &Kind, to: &Kind) -> bool {
+    let mut from_leaves = vec![];
+    let mut to_leaves = vec![];
+    leaves(from, &mut from_leaves);
+    leaves(to, &mut to_leaves);
+    from_leaves.len() == to_leaves.len()
+        && from_leaves
+            .iter()
+            .zip(to_leaves.iter())
+            .all(|(a, b)| match (a, b) {
+                (Leaf::Bits(a), Leaf::Bits(b)) => a == b,
+                (Leaf::Signed(a), Leaf::Signed(b)) => a == b,
+                (Leaf::Enum(a), Leaf::Enum(b)) => enum_layouts_match(a, b),
+                _ => false,
+            })
+}
+
+fn enum_layouts_match(a: &Enum, b: &Enum) -> bool {
+    a.discriminant_layout == b.discriminant_layout
+        && a.variants.len() == b.variants.len()
+        && a.variants.iter().zip(b.variants.iter()).all(|(a, b)| {
+            a.discriminant == b.discriminant
+                && a.kind.bits() == b.kind.bits()
+                && layouts_match(&a.kind, &b.kind)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Kind {
+        Kind::make_struct(
+            "Header",
+            vec![
+                Kind::make_field("tag", Kind::make_bits(4)),
+                Kind::make_field("len", Kind::make_signed(4)),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_compatibility_modes() {
+        let view = Kind::make_struct(
+            "View",
+            vec![
+                Kind::make_field("tag", Kind::make_bits(4)),
+                Kind::make_field("len", Kind::make_signed(4)),
+            ],
+        );
+        let renamed = Kind::make_tuple(vec![Kind::make_bits(4), Kind::make_signed(4)]);
+        let flat = Kind::make_bits(8);
+        let wide = Kind::make_bits(9);
+        for (to, strict, layout, loose) in [
+            (&view, true, true, true),
+            (&renamed, false, true, true),
+            (&flat, false, false, true),
+            (&wide, false, false, false),
+        ] {
+            let check = |mode| check_compatible(&header(), to, mode).is_ok();
+            assert_eq!(check(Compatibility::Strict), strict, "{to}");
+            assert_eq!(check(Compatibility::Layout), layout, "{to}");
+            assert_eq!(check(Compatibility::Loose), loose, "{to}");
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use anyhow::{bail, ensure};
 use rhdl_bits::{Bits, SignedBits};
+use serde::{Deserialize, Serialize};
 
-use crate::{Kind, NoteKey, NoteWriter, TypedBits};
+use crate::compiler::driver::installed_max_kind_bits;
+use crate::{path::Path, Kind, NoteKey, NoteWriter, TypedBits};
 
 use super::note::Notable;
 
@@ -49,6 +52,28 @@ pub trait Digital: Copy + PartialEq + Sized + Clone + 'static + Notable {
         Self::static_kind()
     }
     fn bin(self) -> Vec<bool>;
+    /// Rebuild a value from the binary representation produced by
+    /// [Digital::bin].  This fails if the number of bits is wrong, or
+    /// if the bits do not form a legal value (for example, an enum
+    /// with a discriminant that does not belong to any variant).
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        let _ = bits;
+        bail!(
+            "Type {} does not support conversion from bits",
+            Self::static_kind().get_name()
+        )
+    }
+    /// The timing exceptions on the fields of the type, from the
+    /// `#[rhdl(multicycle = N)]` and `#[rhdl(false_path)]` attributes,
+    /// with the paths to the fields they are on.
+    fn timing_exceptions() -> Vec<(Path, TimingException)> {
+        vec![]
+    }
+    /// The doc comments on the fields of the type, with the paths to
+    /// the fields they are on, for the generated documentation.
+    fn doc_comments() -> Vec<(Path, String)> {
+        vec![]
+    }
     fn typed_bits(self) -> TypedBits {
         TypedBits {
             bits: self.bin(),
@@ -70,6 +95,167 @@ pub trait Digital: Copy + PartialEq + Sized + Clone + 'static + Notable {
     }
 }
 
+/// A relaxation of the timing of a signal.  A multicycle path has
+/// the given number of clock cycles to settle, and a false path is
+/// not timed at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TimingException {
+    Multicycle(usize),
+    FalsePath,
+}
+
+/// The number of bits in a `T`, as given by [Digital::bits], but this
+/// fails (rather than overflowing) if `T`, or any part of it, is wider
+/// than the `max_kind_bits` of the installed `CompileOptions`.  The
+/// error names the part that is too wide.  The derived impls use this
+/// wherever they can report an error.
+pub fn checked_bits<T: Digital>() -> anyhow::Result<usize> {
+    T::static_kind().checked_bits(installed_max_kind_bits())
+}
+
+// A type too wide to count has bits, and is reported when the design is
+// checked, so this only needs to not overflow.
+fn has_no_bits<T: Digital>() -> bool {
+    matches!(T::static_kind().checked_bits(usize::MAX), Ok(0))
+}
+
+/// The timing exceptions of the field `field` of a struct, i.e., the
+/// one on the field itself (if any), followed by those on the fields
+/// of its type.  A field with no bits has no timing, so it has no
+/// exceptions.  This is used to implement [Digital::timing_exceptions]
+/// for structs.
+pub fn field_timing_exceptions<T: Digital>(
+    field: &str,
+    exception: Option<TimingException>,
+) -> Vec<(Path, TimingException)> {
+    if has_no_bits::<T>() {
+        return vec![];
+    }
+    let path = Path::default().field(field);
+    exception
+        .map(|exception| (path.clone(), exception))
+        .into_iter()
+        .chain(
+            T::timing_exceptions()
+                .into_iter()
+                .map(|(inner, exception)| (path.clone().join(&inner), exception)),
+        )
+        .collect()
+}
+
+/// The doc comments of the field `field` of a struct, i.e., the one
+/// on the field itself (if any), followed by those on the fields of its
+/// type.  As with [field_timing_exceptions], a field with no bits has
+/// none.  This is used to implement [Digital::doc_comments] for structs.
+pub fn field_doc_comments<T: Digital>(field: &str, doc: Option<&str>) -> Vec<(Path, String)> {
+    if has_no_bits::<T>() {
+        return vec![];
+    }
+    let path = Path::default().field(field);
+    doc.map(|doc| (path.clone(), doc.to_string()))
+        .into_iter()
+        .chain(
+            T::doc_comments()
+                .into_iter()
+                .map(|(inner, doc)| (path.clone().join(&inner), doc)),
+        )
+        .collect()
+}
+
+/// Check the layout of `T` against the digest given with
+/// `#[rhdl(layout_digest = "...")]`.  The derive emits a test that
+/// calls this, since the layout of a type is only known once the
+/// types of its fields are (and not in const context).  So the digest
+/// is only checked by `cargo test` on the crate that declares the type,
+/// and a type declared inside a function is not checked at all.  On a
+/// mismatch, the error gives the new digest, to be pasted into the
+/// attribute if the change is intended.
+pub fn check_layout_digest<T: Digital>(expected: &str) -> anyhow::Result<()> {
+    let kind = T::static_kind();
+    let digest = kind.layout_digest();
+    ensure!(
+        digest == expected,
+        "The layout of {} has changed (its digest is now {digest}, not {expected}).  If that is intended, update the attribute to #[rhdl(layout_digest = \"{digest}\")].  The new layout is:\n{}",
+        kind.get_name(),
+        kind.bit_layout_string()
+    );
+    Ok(())
+}
+
+/// The layout digests of a list of types, for filling in (or updating)
+/// their `#[rhdl(layout_digest = "...")]` attributes:
+///
+/// ```ignore
+/// for (name, digest) in layout_digests!(Header, Packet) {
+///     println!("{name}: #[rhdl(layout_digest = \"{digest}\")]");
+/// }
+/// ```
+#[macro_export]
+macro_rules! layout_digests {
+    ($($ty:ty),+ $(,)?) => {
+        vec![$((
+            stringify!($ty),
+            <$ty as $crate::Digital>::static_kind().layout_digest(),
+        )),+]
+    };
+}
+
+/// Reads the fields of a value, one after the other, out of its
+/// binary representation.  This is used to implement [Digital::maybe_from_bin]
+/// for composite types.
+pub struct BinReader<'a> {
+    bits: &'a [bool],
+}
+
+impl<'a> BinReader<'a> {
+    pub fn new(bits: &'a [bool]) -> Self {
+        Self { bits }
+    }
+    pub fn read<T: Digital>(&mut self) -> anyhow::Result<T> {
+        let len = checked_bits::<T>()?;
+        ensure!(
+            self.bits.len() >= len,
+            "Not enough bits to read a {} ({} < {})",
+            T::static_kind().get_name(),
+            self.bits.len(),
+            len
+        );
+        let (head, tail) = self.bits.split_at(len);
+        self.bits = tail;
+        T::maybe_from_bin(head)
+    }
+}
+
+/// Check that `bits` has the right length to hold a `T`.
+pub fn check_bin_len<T: Digital>(bits: &[bool]) -> anyhow::Result<()> {
+    let len = checked_bits::<T>()?;
+    ensure!(
+        bits.len() == len,
+        "Expected {len} bits for a {}, but got {}",
+        T::static_kind().get_name(),
+        bits.len()
+    );
+    Ok(())
+}
+
+fn unsigned_from_bin<T: Digital>(bits: &[bool]) -> anyhow::Result<u128> {
+    check_bin_len::<T>(bits)?;
+    Ok(bits
+        .iter()
+        .rev()
+        .fold(0, |acc, b| (acc << 1) | (*b as u128)))
+}
+
+fn signed_from_bin<T: Digital>(bits: &[bool]) -> anyhow::Result<i128> {
+    let value = unsigned_from_bin::<T>(bits)?;
+    // Sign extend from the top bit
+    let shift = 128 - bits.len();
+    if shift == 128 {
+        return Ok(0);
+    }
+    Ok(((value << shift) as i128) >> shift)
+}
+
 impl Digital for () {
     fn static_kind() -> Kind {
         Kind::Empty
@@ -77,6 +263,9 @@ impl Digital for () {
     fn bin(self) -> Vec<bool> {
         Vec::new()
     }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        check_bin_len::<Self>(bits)
+    }
 }
 
 impl Notable for () {
@@ -90,6 +279,10 @@ impl Digital for bool {
     fn bin(self) -> Vec<bool> {
         vec![self]
     }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        check_bin_len::<Self>(bits)?;
+        Ok(bits[0])
+    }
 }
 
 impl Notable for bool {
@@ -103,7 +296,10 @@ impl Digital for u8 {
         Kind::make_bits(8)
     }
     fn bin(self) -> Vec<bool> {
-        Bits::<8>::from(self as u128).to_bools()
+        Bits::<8>::from(self as u128).bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(unsigned_from_bin::<Self>(bits)? as u8)
     }
 }
 
@@ -118,7 +314,10 @@ impl Digital for u16 {
         Kind::make_bits(16)
     }
     fn bin(self) -> Vec<bool> {
-        Bits::<16>::from(self as u128).to_bools()
+        Bits::<16>::from(self as u128).bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(unsigned_from_bin::<Self>(bits)? as u16)
     }
 }
 
@@ -133,7 +332,10 @@ impl Digital for usize {
         Kind::make_bits(usize::BITS as usize)
     }
     fn bin(self) -> Vec<bool> {
-        Bits::<{ usize::BITS as usize }>::from(self as u128).to_bools()
+        Bits::<{ usize::BITS as usize }>::from(self as u128).bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(unsigned_from_bin::<Self>(bits)? as usize)
     }
 }
 
@@ -148,7 +350,10 @@ impl Digital for u128 {
         Kind::make_bits(128)
     }
     fn bin(self) -> Vec<bool> {
-        Bits::<128>::from(self).to_bools()
+        Bits::<128>::from(self).bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        unsigned_from_bin::<Self>(bits)
     }
 }
 
@@ -163,7 +368,10 @@ impl Digital for i128 {
         Kind::make_signed(128)
     }
     fn bin(self) -> Vec<bool> {
-        SignedBits::<128>::from(self).as_unsigned().to_bools()
+        SignedBits::<128>::from(self).as_unsigned().bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        signed_from_bin::<Self>(bits)
     }
 }
 
@@ -178,9 +386,10 @@ impl Digital for i32 {
         Kind::Signed(32)
     }
     fn bin(self) -> Vec<bool> {
-        SignedBits::<32>::from(self as i128)
-            .as_unsigned()
-            .to_bools()
+        SignedBits::<32>::from(self as i128).as_unsigned().bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(signed_from_bin::<Self>(bits)? as i32)
     }
 }
 
@@ -195,7 +404,10 @@ impl Digital for i8 {
         Kind::Signed(8)
     }
     fn bin(self) -> Vec<bool> {
-        SignedBits::<8>::from(self as i128).as_unsigned().to_bools()
+        SignedBits::<8>::from(self as i128).as_unsigned().bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(signed_from_bin::<Self>(bits)? as i8)
     }
 }
 
@@ -210,9 +422,10 @@ impl Digital for i64 {
         Kind::Signed(64)
     }
     fn bin(self) -> Vec<bool> {
-        SignedBits::<64>::from(self as i128)
-            .as_unsigned()
-            .to_bools()
+        SignedBits::<64>::from(self as i128).as_unsigned().bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(signed_from_bin::<Self>(bits)? as i64)
     }
 }
 
@@ -227,7 +440,10 @@ impl<const N: usize> Digital for Bits<N> {
         Kind::make_bits(N)
     }
     fn bin(self) -> Vec<bool> {
-        self.to_bools()
+        self.to_bools().to_vec()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(Bits(unsigned_from_bin::<Self>(bits)?))
     }
 }
 
@@ -242,7 +458,10 @@ impl<const N: usize> Digital for SignedBits<N> {
         Kind::make_signed(N)
     }
     fn bin(self) -> Vec<bool> {
-        self.as_unsigned().to_bools()
+        self.as_unsigned().bin()
+    }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        Ok(SignedBits(signed_from_bin::<Self>(bits)?))
     }
 }
 
@@ -260,6 +479,11 @@ impl<T0: Digital> Digital for (T0,) {
     fn bin(self) -> Vec<bool> {
         self.0.bin()
     }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        check_bin_len::<Self>(bits)?;
+        let mut reader = BinReader::new(bits);
+        Ok((reader.read()?,))
+    }
 }
 
 impl<T0: Notable> Notable for (T0,) {
@@ -277,6 +501,11 @@ impl<T0: Digital, T1: Digital> Digital for (T0, T1) {
         v.extend(self.1.bin());
         v
     }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        check_bin_len::<Self>(bits)?;
+        let mut reader = BinReader::new(bits);
+        Ok((reader.read()?, reader.read()?))
+    }
 }
 
 impl<T0: Notable, T1: Notable> Notable for (T0, T1) {
@@ -300,6 +529,11 @@ impl<T0: Digital, T1: Digital, T2: Digital> Digital for (T0, T1, T2) {
         v.extend(self.2.bin());
         v
     }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        check_bin_len::<Self>(bits)?;
+        let mut reader = BinReader::new(bits);
+        Ok((reader.read()?, reader.read()?, reader.read()?))
+    }
 }
 
 impl<T0: Notable, T1: Notable, T2: Notable> Notable for (T0, T1, T2) {
@@ -326,6 +560,16 @@ impl<T0: Digital, T1: Digital, T2: Digital, T3: Digital> Digital for (T0, T1, T2
         v.extend(self.3.bin());
         v
     }
+    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+        check_bin_len::<Self>(bits)?;
+        let mut reader = BinReader::new(bits);
+        Ok((
+            reader.read()?,
+            reader.read()?,
+            reader.read()?,
+            reader.read()?,
+        ))
+    }
 }
 
 impl<T0: Notable, T1: Notable, T2: Notable, T3: Notable> Notable for (T0, T1, T2, T3) {
@@ -369,6 +613,15 @@ macro_rules! impl_array {
                     }
                     v
                 }
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                    check_bin_len::<Self>(bits)?;
+                    let mut reader = BinReader::new(bits);
+                    let mut v = Vec::with_capacity($N);
+                    for _ in 0..$N {
+                        v.push(reader.read::<T>()?);
+                    }
+                    Ok(v.try_into().unwrap_or_else(|_| unreachable!()))
+                }
             }
 
             impl<T: Notable> Notable for [T; $N] {
@@ -382,14 +635,16 @@ macro_rules! impl_array {
     };
 }
 
-impl_array!(1, 2, 3, 4, 5, 6, 7, 8);
+// Beyond 8 elements, only the power of two sizes (as used for lookup
+// tables and memories) are supported.
+impl_array!(1, 2, 3, 4, 5, 6, 7, 8, 10, 16, 32, 64, 128, 256);
 
 #[cfg(test)]
 mod test {
     use std::iter::repeat;
 
     use super::*;
-    use crate::types::kind::{DiscriminantAlignment, Variant};
+    use crate::types::kind::{DiscriminantAlignment, PayloadAlignment, Variant};
     use rhdl_bits::alias::*;
 
     #[test]
@@ -417,21 +672,25 @@ mod test {
                             name: "None".to_string(),
                             discriminant: 0,
                             kind: Kind::Empty,
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Bool".to_string(),
                             discriminant: 1,
                             kind: Kind::make_bits(1),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Tuple".to_string(),
                             discriminant: 2,
                             kind: Kind::make_tuple(vec![Kind::make_bits(1), Kind::make_bits(3)]),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Array".to_string(),
                             discriminant: 3,
                             kind: Kind::make_array(Kind::make_bits(1), 3),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Strct".to_string(),
@@ -443,6 +702,7 @@ mod test {
                                     Kind::make_field("b", Kind::make_bits(3)),
                                 ],
                             ),
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                     ],
                     Kind::make_discriminant_layout(
@@ -454,27 +714,27 @@ mod test {
             }
             fn bin(self) -> Vec<bool> {
                 let raw = match self {
-                    Self::None => rhdl_bits::bits::<3>(0).to_bools(),
+                    Self::None => rhdl_bits::bits::<3>(0).to_bools().to_vec(),
                     Self::Bool(b) => {
-                        let mut v = rhdl_bits::bits::<3>(1).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(1).to_bools().to_vec();
                         v.extend(b.bin());
                         v
                     }
                     Self::Tuple(b, c) => {
-                        let mut v = rhdl_bits::bits::<3>(2).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(2).to_bools().to_vec();
                         v.extend(b.bin());
                         v.extend(c.bin());
                         v
                     }
                     Self::Array([b, c, d]) => {
-                        let mut v = rhdl_bits::bits::<3>(3).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(3).to_bools().to_vec();
                         v.extend(b.bin());
                         v.extend(c.bin());
                         v.extend(d.bin());
                         v
                     }
                     Self::Strct { a, b } => {
-                        let mut v = rhdl_bits::bits::<3>(4).to_bools();
+                        let mut v = rhdl_bits::bits::<3>(4).to_bools().to_vec();
                         v.extend(a.bin());
                         v.extend(b.bin());
                         v
@@ -549,26 +809,31 @@ mod test {
                             name: "Init".to_string(),
                             discriminant: 0,
                             kind: Kind::Empty,
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Boot".to_string(),
                             discriminant: 1,
                             kind: Kind::Empty,
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Running".to_string(),
                             discriminant: 2,
                             kind: Kind::Empty,
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Stop".to_string(),
                             discriminant: 3,
                             kind: Kind::Empty,
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                         Variant {
                             name: "Boom".to_string(),
                             discriminant: 4,
                             kind: Kind::Empty,
+                            payload_alignment: PayloadAlignment::Lsb,
                         },
                     ],
                     Kind::make_discriminant_layout(
@@ -580,11 +845,11 @@ mod test {
             }
             fn bin(self) -> Vec<bool> {
                 match self {
-                    Self::Init => rhdl_bits::bits::<3>(0).to_bools(),
-                    Self::Boot => rhdl_bits::bits::<3>(1).to_bools(),
-                    Self::Running => rhdl_bits::bits::<3>(2).to_bools(),
-                    Self::Stop => rhdl_bits::bits::<3>(3).to_bools(),
-                    Self::Boom => rhdl_bits::bits::<3>(4).to_bools(),
+                    Self::Init => rhdl_bits::bits::<3>(0).to_bools().to_vec(),
+                    Self::Boot => rhdl_bits::bits::<3>(1).to_bools().to_vec(),
+                    Self::Running => rhdl_bits::bits::<3>(2).to_bools().to_vec(),
+                    Self::Stop => rhdl_bits::bits::<3>(3).to_bools().to_vec(),
+                    Self::Boom => rhdl_bits::bits::<3>(4).to_bools().to_vec(),
                 }
             }
         }
@@ -611,26 +876,31 @@ mod test {
                         name: "Init".to_string(),
                         discriminant: 0,
                         kind: Kind::Empty,
+                        payload_alignment: PayloadAlignment::Lsb,
                     },
                     Variant {
                         name: "Boot".to_string(),
                         discriminant: 1,
                         kind: Kind::Empty,
+                        payload_alignment: PayloadAlignment::Lsb,
                     },
                     Variant {
                         name: "Running".to_string(),
                         discriminant: 2,
                         kind: Kind::Empty,
+                        payload_alignment: PayloadAlignment::Lsb,
                     },
                     Variant {
                         name: "Stop".to_string(),
                         discriminant: 3,
                         kind: Kind::Empty,
+                        payload_alignment: PayloadAlignment::Lsb,
                     },
                     Variant {
                         name: "Boom".to_string(),
                         discriminant: 4,
                         kind: Kind::Empty,
+                        payload_alignment: PayloadAlignment::Lsb,
                     },
                 ],
                 Kind::make_discriminant_layout(
@@ -663,4 +933,23 @@ mod test {
         let y = x.as_i64().unwrap();
         assert_eq!(y, -6);
     }
+
+    #[test]
+    fn test_maybe_from_bin_round_trip() {
+        fn round_trip<T: Digital + std::fmt::Debug>(x: T) {
+            assert_eq!(T::maybe_from_bin(&x.bin()).unwrap(), x);
+        }
+        round_trip(true);
+        round_trip(0xA5_u8);
+        round_trip(-100_i8);
+        round_trip(i128::MIN);
+        round_trip(b8(0xC3));
+        round_trip(s4(-8));
+        round_trip(s4(7));
+        round_trip((b4(3), s6(-2), true));
+        round_trip([b3(1), b3(5), b3(7)]);
+        round_trip([(true, s4(-1)); 2]);
+        assert!(b8::maybe_from_bin(&[true; 7]).is_err());
+        assert!(<(b4, b4)>::maybe_from_bin(&[false; 9]).is_err());
+    }
 }
@@ -8,9 +8,23 @@ pub trait DigitalFn {
     }
 }
 
+// The argument and return kinds of a kernel, as declared by the types in
+// its signature.  The kernel macro implements this, so that tools can get
+// at them without compiling the kernel.
+pub trait DigitalFnSignature: DigitalFn {
+    fn arg_kinds() -> Vec<Kind>;
+    fn return_kind() -> Kind;
+    fn signature() -> DigitalSignature {
+        DigitalSignature {
+            arguments: Self::arg_kinds(),
+            ret: Self::return_kind(),
+        }
+    }
+}
+
 // See: https://jsdw.me/posts/rust-fn-traits/
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DigitalSignature {
     pub arguments: Vec<Kind>,
     pub ret: Kind,
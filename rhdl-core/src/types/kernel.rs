@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{ast::ast_impl, TypedBits};
+use crate::{
+    ast::ast_impl::{self, PatKind},
+    DigitalSignature, Kind, TypedBits,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Kernel(Box<ast_impl::KernelFn>);
@@ -18,6 +21,35 @@ impl Kernel {
     pub fn inner_mut(&mut self) -> &mut ast_impl::KernelFn {
         &mut self.0
     }
+    // The signature declared by the kernel's arguments and return type
+    pub fn signature(&self) -> DigitalSignature {
+        DigitalSignature {
+            arguments: self
+                .0
+                .inputs
+                .iter()
+                .map(|pat| match &pat.kind {
+                    PatKind::Type(ty) => ty.kind.clone(),
+                    _ => Kind::Empty,
+                })
+                .collect(),
+            ret: self.0.ret.clone(),
+        }
+    }
+    // The inputs marked with `#[rhdl(param)]`, by position and name
+    pub fn params(&self) -> Vec<(usize, String)> {
+        self.0
+            .params
+            .iter()
+            .filter_map(|ndx| match &self.0.inputs.get(*ndx)?.kind {
+                PatKind::Type(ty) => match &ty.pat.kind {
+                    PatKind::Ident(ident) => Some((*ndx, ident.name.clone())),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
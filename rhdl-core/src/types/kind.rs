@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
-use std::{iter::repeat, ops::Range};
+use std::{hash::Hasher, iter::repeat, ops::Range};
 
 use anyhow::Result;
 
-use crate::TypedBits;
+use crate::{
+    path::{bit_range, leaf_paths, Path},
+    TypedBits,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub enum Kind {
@@ -14,6 +17,7 @@ pub enum Kind {
     Bits(usize),
     Signed(usize),
     Empty,
+    Ranged(Ranged),
 }
 
 impl std::fmt::Display for Kind {
@@ -34,6 +38,7 @@ impl std::fmt::Display for Kind {
             Kind::Bits(digits) => write!(f, "b{}", digits),
             Kind::Signed(digits) => write!(f, "s{}", digits),
             Kind::Empty => write!(f, "()"),
+            Kind::Ranged(r) => write!(f, "{} in {}..={}", r.base, r.min, r.max),
         }
     }
 }
@@ -44,6 +49,16 @@ pub struct Array {
     pub size: usize,
 }
 
+// An integer kind that is known to only hold values in `min..=max`.
+// The bounds do not change the layout of the value, and are only used
+// for checking (and documenting) the values written to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct Ranged {
+    pub base: Box<Kind>,
+    pub min: i128,
+    pub max: i128,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct Tuple {
     pub elements: Vec<Kind>,
@@ -53,6 +68,10 @@ pub struct Tuple {
 pub struct Struct {
     pub name: String,
     pub fields: Vec<Field>,
+    // Set for a newtype derived with `#[rhdl(transparent_ops)]`, whose
+    // operators are those of its (only) field.
+    #[serde(default)]
+    pub transparent_ops: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
@@ -72,6 +91,18 @@ pub enum DiscriminantType {
     Unsigned,
 }
 
+// Where the payload of a variant sits in the payload region of the enum
+// (the bits not used by the discriminant), when the payload is narrower
+// than the widest payload.  `Lsb` packs the payload against the low end
+// of the region, and `Msb` against the high end.  Aligning all the
+// variants to the same end gives the layout of a C-style union.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash, Default)]
+pub enum PayloadAlignment {
+    #[default]
+    Lsb,
+    Msb,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct DiscriminantLayout {
     pub width: usize,
@@ -84,6 +115,11 @@ pub struct Enum {
     pub name: String,
     pub variants: Vec<Variant>,
     pub discriminant_layout: DiscriminantLayout,
+    // The variant returned by `Default::default()`.  Derived enums always
+    // have one.  It is the variant marked `#[rhdl(default)]`, or failing
+    // that, the variant with a discriminant of zero.
+    #[serde(default)]
+    pub default_variant: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
@@ -97,6 +133,8 @@ pub struct Variant {
     pub name: String,
     pub discriminant: i64,
     pub kind: Kind,
+    #[serde(default)]
+    pub payload_alignment: PayloadAlignment,
 }
 
 impl Variant {
@@ -106,6 +144,59 @@ impl Variant {
             ..self
         }
     }
+    pub fn with_payload_alignment(self, payload_alignment: PayloadAlignment) -> Variant {
+        Variant {
+            payload_alignment,
+            ..self
+        }
+    }
+}
+
+impl Enum {
+    // The width of the payload region, which is wide enough for the
+    // widest payload.
+    pub fn payload_width(&self) -> usize {
+        self.variants
+            .iter()
+            .map(|x| x.kind.bits())
+            .max()
+            .unwrap_or(0)
+    }
+    // The bits of the enum (counting from the LSB) that carry the payload
+    // of the given variant.
+    pub fn payload_range(&self, variant: &Variant) -> Range<usize> {
+        let region_start = match self.discriminant_layout.alignment {
+            DiscriminantAlignment::Lsb => self.discriminant_layout.width,
+            DiscriminantAlignment::Msb => 0,
+        };
+        let width = variant.kind.bits();
+        let start = match variant.payload_alignment {
+            PayloadAlignment::Lsb => region_start,
+            PayloadAlignment::Msb => region_start + self.payload_width() - width,
+        };
+        start..start + width
+    }
+    // The variant selected by the given discriminant bits, if any.
+    fn variant_for_discriminant(&self, bits: &[bool]) -> Option<&Variant> {
+        self.variant_index_for_discriminant(bits)
+            .map(|ndx| &self.variants[ndx])
+    }
+    // The position (in `variants`) of the variant selected by the given
+    // discriminant bits, if any.
+    pub(crate) fn variant_index_for_discriminant(&self, bits: &[bool]) -> Option<usize> {
+        let value = bits
+            .iter()
+            .rev()
+            .fold(0_i64, |acc, b| (acc << 1) | (*b as i64));
+        let width = bits.len();
+        let value = match self.discriminant_layout.ty {
+            DiscriminantType::Signed if width > 0 && width < 64 && bits[width - 1] => {
+                value - (1 << width)
+            }
+            _ => value,
+        };
+        self.variants.iter().position(|x| x.discriminant == value)
+    }
 }
 
 impl Kind {
@@ -129,14 +220,35 @@ impl Kind {
             name: name.to_string(),
             discriminant,
             kind,
+            payload_alignment: PayloadAlignment::Lsb,
         }
     }
     pub fn make_struct(name: &str, fields: Vec<Field>) -> Self {
         Self::Struct(Struct {
             name: name.into(),
             fields,
+            transparent_ops: false,
         })
     }
+    // Mark a newtype as having the operators of its field, so that
+    // kernels can do arithmetic on it as they would on the field.  Panics
+    // unless the kind is a struct with a single (signed or unsigned)
+    // integer field.
+    pub fn with_transparent_ops(self) -> Self {
+        match self {
+            Kind::Struct(mut strukt)
+                if strukt.fields.len() == 1
+                    && matches!(
+                        strukt.fields[0].kind.unranged(),
+                        Kind::Bits(_) | Kind::Signed(_)
+                    ) =>
+            {
+                strukt.transparent_ops = true;
+                Kind::Struct(strukt)
+            }
+            _ => panic!("Only a struct with a single Bits or SignedBits field can have transparent ops, not {self}"),
+        }
+    }
     pub fn make_discriminant_layout(
         width: usize,
         alignment: DiscriminantAlignment,
@@ -153,12 +265,26 @@ impl Kind {
         variants: Vec<Variant>,
         discriminant_layout: DiscriminantLayout,
     ) -> Self {
+        let default_variant = variants
+            .iter()
+            .find(|x| x.discriminant == 0)
+            .map(|x| x.name.clone());
         Self::Enum(Enum {
             name: name.into(),
             variants,
             discriminant_layout,
+            default_variant,
         })
     }
+    pub fn with_default_variant(self, variant: &str) -> Self {
+        match self {
+            Kind::Enum(e) => Kind::Enum(Enum {
+                default_variant: Some(variant.into()),
+                ..e
+            }),
+            _ => self,
+        }
+    }
     pub fn make_bool() -> Self {
         Self::Bits(1)
     }
@@ -168,42 +294,153 @@ impl Kind {
     pub fn make_signed(digits: usize) -> Self {
         Self::Signed(digits)
     }
+    pub fn make_bits_ranged(digits: usize, min: i128, max: i128) -> Self {
+        Self::Bits(digits).with_range(min, max)
+    }
+    // Attach the bounds `min..=max` to an integer kind (replacing any
+    // existing bounds).  Panics if the kind is not an integer, or if the
+    // bounds are empty or cannot be represented in the kind.
+    pub fn with_range(self, min: i128, max: i128) -> Self {
+        let base = self.unranged().clone();
+        let Some((lo, hi)) = base.integer_bounds() else {
+            panic!("Only (non-empty) integer kinds can have a range, not {base}");
+        };
+        assert!(
+            lo <= min && min <= max && max <= hi,
+            "The range {min}..={max} is not valid for {base}"
+        );
+        Self::Ranged(Ranged {
+            base: Box::new(base),
+            min,
+            max,
+        })
+    }
+    // The smallest and largest values that an integer kind can hold.
+    pub fn integer_bounds(&self) -> Option<(i128, i128)> {
+        match self.unranged() {
+            Kind::Bits(digits) if *digits < 128 => Some((0, (1_i128 << digits) - 1)),
+            Kind::Bits(_) => Some((0, i128::MAX)),
+            Kind::Signed(digits) if (1..=128).contains(digits) => {
+                Some((i128::MIN >> (128 - digits), i128::MAX >> (128 - digits)))
+            }
+            _ => None,
+        }
+    }
+    // The kind without any range attached to it.
+    pub fn unranged(&self) -> &Kind {
+        match self {
+            Kind::Ranged(r) => &r.base,
+            _ => self,
+        }
+    }
+    // The bounds of a ranged kind, as `(min, max)`.
+    pub fn range(&self) -> Option<(i128, i128)> {
+        match self {
+            Kind::Ranged(r) => Some((r.min, r.max)),
+            _ => None,
+        }
+    }
     pub fn bits(&self) -> usize {
         match self {
             Kind::Array(array) => array.base.bits() * array.size,
             Kind::Tuple(tuple) => tuple.elements.iter().map(|x| x.bits()).sum(),
             Kind::Struct(kind) => kind.fields.iter().map(|x| x.kind.bits()).sum(),
-            Kind::Enum(kind) => {
-                kind.discriminant_layout.width
-                    + kind
-                        .variants
-                        .iter()
-                        .map(|x| x.kind.bits())
-                        .max()
-                        .unwrap_or(0)
-            }
+            Kind::Enum(kind) => kind.discriminant_layout.width + kind.payload_width(),
             Kind::Bits(digits) => *digits,
             Kind::Signed(digits) => *digits,
             Kind::Empty => 0,
+            Kind::Ranged(r) => r.base.bits(),
         }
     }
+    // As `bits`, but fails rather than overflowing (or laying out a
+    // huge value) if the kind, or any part of it, is wider than `limit`
+    // bits.  The error names the innermost part that is over the limit,
+    // e.g., `Top.regs` for a field `regs` of a struct `Top`, so that a
+    // typo in the size of an array is easy to find.
+    pub fn checked_bits(&self, limit: usize) -> Result<usize> {
+        checked_width(self, &self.to_string(), limit)
+    }
     pub fn pad(&self, bits: Vec<bool>) -> Vec<bool> {
         if bits.len() > self.bits() {
             panic!("Too many bits for kind!");
         }
         let pad_len = self.bits() - bits.len();
-        let bits = bits.into_iter().chain(repeat(false).take(pad_len));
         match self {
-            Kind::Enum(kind) => match kind.discriminant_layout.alignment {
-                DiscriminantAlignment::Lsb => bits.collect(),
-                DiscriminantAlignment::Msb => {
-                    let discriminant_width = kind.discriminant_layout.width;
-                    let discriminant = bits.clone().take(discriminant_width);
-                    let payload = bits.skip(discriminant_width);
-                    payload.chain(discriminant).collect()
+            Kind::Enum(kind) => {
+                // The bits are the discriminant followed by the payload.
+                // Move each of them to where the layout puts it.
+                let discriminant_width = kind.discriminant_layout.width;
+                let (discriminant, payload) = bits.split_at(discriminant_width.min(bits.len()));
+                let (payload_start, payload) = match kind.variant_for_discriminant(discriminant) {
+                    Some(variant) => {
+                        let range = kind.payload_range(variant);
+                        (range.start, &payload[..payload.len().min(range.len())])
+                    }
+                    None => match kind.discriminant_layout.alignment {
+                        DiscriminantAlignment::Lsb => (discriminant_width, payload),
+                        DiscriminantAlignment::Msb => (0, payload),
+                    },
+                };
+                let discriminant_start = match kind.discriminant_layout.alignment {
+                    DiscriminantAlignment::Lsb => 0,
+                    DiscriminantAlignment::Msb => self.bits() - discriminant_width,
+                };
+                let mut result = vec![false; self.bits()];
+                result[discriminant_start..discriminant_start + discriminant.len()]
+                    .copy_from_slice(discriminant);
+                result[payload_start..payload_start + payload.len()].copy_from_slice(payload);
+                result
+            }
+            _ => bits
+                .into_iter()
+                .chain(repeat(false).take(pad_len))
+                .collect(),
+        }
+    }
+    // The packed bits of the default value of this kind.  This is all
+    // zeros, except for enums, where the default variant is selected
+    // (with a default payload).  For an enum with no default variant,
+    // the bits are all zeros, which may not be a valid value.
+    pub fn default_bit_pattern(&self) -> Vec<bool> {
+        match self {
+            Kind::Array(array) => {
+                let base = array.base.default_bit_pattern();
+                base.iter().copied().cycle().take(self.bits()).collect()
+            }
+            Kind::Tuple(tuple) => tuple
+                .elements
+                .iter()
+                .flat_map(|x| x.default_bit_pattern())
+                .collect(),
+            Kind::Struct(kind) => kind
+                .fields
+                .iter()
+                .flat_map(|x| x.kind.default_bit_pattern())
+                .collect(),
+            Kind::Enum(kind) => {
+                let variant = kind
+                    .default_variant
+                    .as_ref()
+                    .and_then(|name| kind.variants.iter().find(|x| &x.name == name));
+                match variant {
+                    Some(variant) => {
+                        let discriminant: TypedBits = variant.discriminant.into();
+                        let width = kind.discriminant_layout.width;
+                        let discriminant = match kind.discriminant_layout.ty {
+                            DiscriminantType::Signed => discriminant.signed_cast(width),
+                            DiscriminantType::Unsigned => discriminant.unsigned_cast(width),
+                        }
+                        .expect("Discriminant of a variant must fit in the discriminant width");
+                        let mut bits = discriminant.bits;
+                        bits.extend(variant.kind.default_bit_pattern());
+                        self.pad(bits)
+                    }
+                    None => vec![false; self.bits()],
                 }
-            },
-            _ => bits.collect(),
+            }
+            Kind::Bits(digits) | Kind::Signed(digits) => vec![false; *digits],
+            Kind::Empty => vec![],
+            Kind::Ranged(r) => r.base.default_bit_pattern(),
         }
     }
     pub fn get_tuple_kind(&self, ndx: usize) -> Result<Kind> {
@@ -230,6 +467,7 @@ impl Kind {
             }
             Kind::Struct(s) => s.name.clone(),
             Kind::Enum(e) => e.name.clone(),
+            Kind::Ranged(r) => r.base.get_name(),
         }
     }
 
@@ -341,23 +579,121 @@ impl Kind {
         }
     }
 
+    // The kind that operators act on.  This is the kind itself, except
+    // for ranged kinds and newtypes with transparent ops, which act as
+    // the kinds they wrap.
+    pub fn operand_kind(&self) -> &Kind {
+        match self.unranged() {
+            Kind::Struct(strukt) if strukt.transparent_ops => strukt.fields[0].kind.unranged(),
+            kind => kind,
+        }
+    }
+
     pub fn is_composite(&self) -> bool {
         matches!(
-            self,
+            self.operand_kind(),
             Kind::Array(_) | Kind::Tuple(_) | Kind::Struct(_) | Kind::Enum(_)
         )
     }
 
     pub fn is_signed(&self) -> bool {
-        matches!(self, Kind::Signed(_))
+        matches!(self.operand_kind(), Kind::Signed(_))
     }
 
     pub fn is_unsigned(&self) -> bool {
-        matches!(self, Kind::Bits(_))
+        matches!(self.operand_kind(), Kind::Bits(_))
     }
 
     pub fn is_bool(&self) -> bool {
-        matches!(self, Kind::Bits(1))
+        matches!(self.unranged(), Kind::Bits(1))
+    }
+
+    // The leaves of the kind (the fields that are not composite), with
+    // the path to each, its bits and its kind.  Leaves with no bits are
+    // left out.
+    pub fn leaves(&self) -> Vec<(Path, Range<usize>, Kind)> {
+        leaf_paths(self, Path::default())
+            .into_iter()
+            .filter_map(|path| {
+                let (range, kind) = bit_range(self.clone(), &path).ok()?;
+                (!range.is_empty()).then_some((path, range, kind))
+            })
+            .collect()
+    }
+
+    // A line for each leaf, as in `[7:4] header.version : b4`, for
+    // documenting the layout of the kind.
+    pub fn bit_layout_string(&self) -> String {
+        self.leaves()
+            .into_iter()
+            .map(|(path, range, kind)| {
+                let bits = if range.len() == 1 {
+                    format!("[{}]", range.start)
+                } else {
+                    format!("[{}:{}]", range.end - 1, range.start)
+                };
+                let path = path.to_string();
+                let path = path.strip_prefix('.').unwrap_or(&path);
+                format!("{bits} {path} : {kind}\n")
+            })
+            .collect()
+    }
+
+    // A stable hash of the layout of the kind, i.e., of everything that
+    // decides where each bit goes: the widths, the order of the fields
+    // and elements, and the discriminants and alignments of enums.  Names
+    // (of types, fields and variants) are left out, so renaming a field
+    // without moving it keeps the digest.  So are the bounds of ranged
+    // kinds, which do not change the layout.
+    pub fn layout_digest(&self) -> String {
+        let mut hasher = fnv::FnvHasher::default();
+        hasher.write(self.layout_signature().as_bytes());
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn layout_signature(&self) -> String {
+        let join = |kinds: Vec<&Kind>| {
+            kinds
+                .into_iter()
+                .map(Kind::layout_signature)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        match self {
+            Kind::Bits(digits) => format!("b{digits}"),
+            Kind::Signed(digits) => format!("s{digits}"),
+            Kind::Empty => "e".into(),
+            Kind::Ranged(ranged) => ranged.base.layout_signature(),
+            Kind::Array(array) => format!("[{};{}]", array.base.layout_signature(), array.size),
+            Kind::Tuple(tuple) => format!("({})", join(tuple.elements.iter().collect())),
+            Kind::Struct(structure) => format!(
+                "{{{}}}",
+                join(structure.fields.iter().map(|field| &field.kind).collect())
+            ),
+            Kind::Enum(enumerate) => {
+                // The discriminant of a variant decides its encoding, not
+                // its place in the declaration
+                let mut variants = enumerate.variants.iter().collect::<Vec<_>>();
+                variants.sort_by_key(|variant| variant.discriminant);
+                let layout = &enumerate.discriminant_layout;
+                format!(
+                    "<{}{:?}{:?}|{}>",
+                    layout.width,
+                    layout.alignment,
+                    layout.ty,
+                    variants
+                        .into_iter()
+                        .map(|variant| format!(
+                            "{}:{}{:?}",
+                            variant.discriminant,
+                            variant.kind.layout_signature(),
+                            variant.payload_alignment
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )
+            }
+        }
     }
 }
 
@@ -377,6 +713,7 @@ fn generate_kind_layout(
 ) -> Vec<KindLayout> {
     match kind {
         Kind::Empty => vec![],
+        Kind::Ranged(r) => generate_kind_layout(&r.base, name, offset_row, offset_col),
         Kind::Bits(digits) => {
             vec![KindLayout {
                 row: offset_row,
@@ -460,10 +797,6 @@ fn generate_kind_layout(
                         ..(offset_col + kind.bits())
                 }
             };
-            let payload_offset = match e.discriminant_layout.alignment {
-                DiscriminantAlignment::Lsb => offset_col + e.discriminant_layout.width,
-                DiscriminantAlignment::Msb => offset_col,
-            };
             let disc_width = e.discriminant_layout.width;
             for variant in &e.variants {
                 let discriminant = if variant.discriminant < 0 {
@@ -486,7 +819,7 @@ fn generate_kind_layout(
                     &variant.kind,
                     &variant.name,
                     offset_row + 1,
-                    payload_offset,
+                    offset_col + e.payload_range(variant).start,
                 );
                 let new_offset_row = variant_layout
                     .iter()
@@ -794,6 +1127,40 @@ pub mod kind_svg {
     }
 }
 
+fn checked_width(kind: &Kind, path: &str, limit: usize) -> Result<usize> {
+    let part = |kind: &Kind, path: String| checked_width(kind, &path, limit).map(|w| w as u128);
+    let width = match kind {
+        Kind::Array(array) => part(&array.base, format!("{path}[_]"))? * array.size as u128,
+        Kind::Tuple(tuple) => tuple
+            .elements
+            .iter()
+            .enumerate()
+            .map(|(ndx, element)| part(element, format!("{path}.{ndx}")))
+            .sum::<Result<u128>>()?,
+        Kind::Struct(strukt) => strukt
+            .fields
+            .iter()
+            .map(|field| part(&field.kind, format!("{path}.{}", field.name)))
+            .sum::<Result<u128>>()?,
+        Kind::Enum(enumerate) => {
+            let payload = enumerate
+                .variants
+                .iter()
+                .map(|variant| part(&variant.kind, format!("{path}::{}", variant.name)))
+                .try_fold(0, |acc, width| width.map(|width| acc.max(width)))?;
+            enumerate.discriminant_layout.width as u128 + payload
+        }
+        Kind::Bits(digits) | Kind::Signed(digits) => *digits as u128,
+        Kind::Empty => 0,
+        Kind::Ranged(ranged) => part(&ranged.base, path.to_string())?,
+    };
+    anyhow::ensure!(
+        width <= limit as u128,
+        "{path} is {width} bits wide (as a {kind}), which is more than the limit of {limit} bits"
+    );
+    Ok(width as usize)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -907,16 +1274,19 @@ mod test {
                     name: "A".to_string(),
                     discriminant: 0,
                     kind: Kind::Empty,
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "B".to_string(),
                     discriminant: 1,
                     kind: Kind::make_bits(8),
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "C".to_string(),
                     discriminant: 2,
                     kind: Kind::make_tuple(vec![Kind::make_bits(8), Kind::make_bits(16)]),
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "D".to_string(),
@@ -934,11 +1304,13 @@ mod test {
                             },
                         ],
                     ),
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "E".to_string(),
                     discriminant: 4,
                     kind: Kind::make_array(Kind::make_bits(8), 4),
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "F".to_string(),
@@ -956,6 +1328,7 @@ mod test {
                             },
                         ],
                     ),
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "G".to_string(),
@@ -977,6 +1350,7 @@ mod test {
                             },
                         ],
                     ),
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "H".to_string(),
@@ -988,16 +1362,19 @@ mod test {
                                 name: "A".to_string(),
                                 discriminant: 0,
                                 kind: Kind::Empty,
+                                payload_alignment: PayloadAlignment::Lsb,
                             },
                             Variant {
                                 name: "B".to_string(),
                                 discriminant: 1,
                                 kind: Kind::Bits(4),
+                                payload_alignment: PayloadAlignment::Lsb,
                             },
                             Variant {
                                 name: "C".to_string(),
                                 discriminant: 2,
                                 kind: Kind::Empty,
+                                payload_alignment: PayloadAlignment::Lsb,
                             },
                         ],
                         Kind::make_discriminant_layout(
@@ -1006,6 +1383,7 @@ mod test {
                             DiscriminantType::Unsigned,
                         ),
                     ),
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
             ],
             Kind::make_discriminant_layout(
@@ -1112,16 +1490,19 @@ mod test {
                     name: "A".to_string(),
                     discriminant: 0,
                     kind: Kind::Empty,
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "B".to_string(),
                     discriminant: 1,
                     kind: Kind::Empty,
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
                 Variant {
                     name: "C".to_string(),
                     discriminant: 2,
                     kind: Kind::Empty,
+                    payload_alignment: PayloadAlignment::Lsb,
                 },
             ],
             Kind::make_discriminant_layout(
@@ -1140,4 +1521,124 @@ mod test {
             svg::save("test_vertical.svg", &svg).unwrap();
         }
     }
+
+    #[test]
+    fn test_default_bit_pattern() {
+        // No variant has a discriminant of zero, so there is no default
+        let kind = make_enum_msb_signed_kind();
+        assert_eq!(kind.default_bit_pattern(), vec![false; kind.bits()]);
+        let kind = kind.with_default_variant("A");
+        assert_eq!(
+            kind.default_bit_pattern(),
+            kind.enum_template("A").unwrap().bits
+        );
+        let kind = Kind::make_tuple(vec![Kind::make_array(kind.clone(), 2), Kind::Bits(3)]);
+        let pattern = kind.default_bit_pattern();
+        assert_eq!(pattern.len(), kind.bits());
+        assert!(pattern[pattern.len() - 3..].iter().all(|x| !x));
+    }
+
+    #[test]
+    fn test_bit_layout_string() {
+        let header = Kind::make_struct(
+            "Header",
+            vec![
+                Kind::make_field("version", Kind::Bits(4)),
+                Kind::make_field("flags", Kind::Bits(4)),
+            ],
+        );
+        let packet = Kind::make_struct(
+            "Packet",
+            vec![
+                Kind::make_field("header", header),
+                Kind::make_field("valid", Kind::make_bool()),
+                Kind::make_field("payload", Kind::Signed(8)),
+            ],
+        );
+        let layout = packet.bit_layout_string();
+        assert!(layout.contains("[3:0] header.version : b4\n"), "{layout}");
+        assert!(layout.contains("[7:4] header.flags : b4\n"), "{layout}");
+        assert!(layout.contains("[8] valid : b1\n"), "{layout}");
+        assert!(layout.contains("[16:9] payload : s8\n"), "{layout}");
+    }
+
+    #[test]
+    fn test_layout_digest() {
+        let packet = |first: &str, second: &str, width: usize| {
+            Kind::make_struct(
+                "Packet",
+                vec![
+                    Kind::make_field(first, Kind::Bits(width)),
+                    Kind::make_field(second, Kind::make_bool()),
+                ],
+            )
+        };
+        let digest = packet("data", "valid", 4).layout_digest();
+        assert_eq!(digest.len(), 16);
+        // Renaming the fields (or the struct) keeps the layout
+        assert_eq!(packet("payload", "ready", 4).layout_digest(), digest);
+        let renamed = Kind::make_struct(
+            "Frame",
+            vec![
+                Kind::make_field("data", Kind::Bits(4)),
+                Kind::make_field("valid", Kind::make_bool()),
+            ],
+        );
+        assert_eq!(renamed.layout_digest(), digest);
+        // Reordering them, or changing a width, does not
+        let reordered = Kind::make_struct(
+            "Packet",
+            vec![
+                Kind::make_field("valid", Kind::make_bool()),
+                Kind::make_field("data", Kind::Bits(4)),
+            ],
+        );
+        assert_ne!(reordered.layout_digest(), digest);
+        assert_ne!(packet("data", "valid", 5).layout_digest(), digest);
+        assert_ne!(
+            Kind::make_tuple(vec![Kind::Bits(4), Kind::make_bool()]).layout_digest(),
+            digest
+        );
+        // Nor does moving a variant to another discriminant
+        let state = |run: i64| {
+            Kind::make_enum(
+                "State",
+                vec![
+                    Kind::make_variant("Idle", Kind::Empty, 0),
+                    Kind::make_variant("Run", Kind::Bits(3), run),
+                ],
+                Kind::make_discriminant_layout(
+                    2,
+                    DiscriminantAlignment::Msb,
+                    DiscriminantType::Unsigned,
+                ),
+            )
+        };
+        assert_eq!(state(1).layout_digest(), state(1).layout_digest());
+        assert_ne!(state(1).layout_digest(), state(2).layout_digest());
+    }
+
+    #[test]
+    fn test_checked_bits_names_the_wide_part() {
+        let foo = Kind::make_struct("Foo", vec![Kind::make_field("data", Kind::make_bits(32))]);
+        let top = Kind::make_struct(
+            "Top",
+            vec![
+                Kind::make_field("flag", Kind::make_bits(1)),
+                Kind::make_field("regs", Kind::make_array(foo, 1 << 16)),
+            ],
+        );
+        let err = top.checked_bits(1 << 20).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Top.regs is 2097152 bits wide (as a [Foo; 65536]), which is more than the limit of 1048576 bits"
+        );
+        assert_eq!(top.checked_bits(1 << 22).unwrap(), top.bits());
+        // Widths that would overflow a usize are still reported
+        let huge = Kind::make_array(Kind::make_array(Kind::make_bits(64), usize::MAX), 2);
+        let err = huge.checked_bits(1 << 20).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("[[b64; 18446744073709551615]; 2][_] is"));
+    }
 }
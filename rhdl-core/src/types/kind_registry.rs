@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::{Digital, Kind};
+
+lazy_static! {
+    static ref KIND_REGISTRY: RwLock<BTreeMap<String, Kind>> = Default::default();
+}
+
+// A process wide table of the Kinds of Digital types, keyed by the name
+// of the Kind (for derived types, this is the fully qualified name of the
+// type, including any generic arguments).  It lets a host tool look up the
+// layout of a hardware type by name, without knowing the Rust type at
+// compile time.
+pub struct KindRegistry {}
+
+impl KindRegistry {
+    pub fn register(kind: Kind) {
+        KIND_REGISTRY.write().insert(kind.get_name(), kind);
+    }
+    // Look up a Kind by its full name (e.g. `my_crate::types::MyStruct`),
+    // or by the last segment of that name (e.g. `MyStruct`), if only one
+    // registered type has that name.
+    pub fn lookup(name: &str) -> Option<Kind> {
+        let registry = KIND_REGISTRY.read();
+        if let Some(kind) = registry.get(name) {
+            return Some(kind.clone());
+        }
+        let mut matches = registry
+            .iter()
+            .filter(|(full_name, _)| short_name(full_name) == name);
+        match (matches.next(), matches.next()) {
+            (Some((_, kind)), None) => Some(kind.clone()),
+            _ => None,
+        }
+    }
+    pub fn names() -> Vec<String> {
+        KIND_REGISTRY.read().keys().cloned().collect()
+    }
+}
+
+pub fn register_kind<T: Digital>() -> Kind {
+    let kind = T::static_kind();
+    KindRegistry::register(kind.clone());
+    kind
+}
+
+// The name with the module path removed.  Generic arguments can contain
+// paths of their own, so only separators before the first `<` count.
+fn short_name(name: &str) -> &str {
+    let base_len = name.find('<').unwrap_or(name.len());
+    match name[..base_len].rfind("::") {
+        Some(ndx) => &name[ndx + 2..],
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name("a::b::Foo"), "Foo");
+        assert_eq!(short_name("Foo"), "Foo");
+        assert_eq!(short_name("a::Foo<b::Bar>"), "Foo<b::Bar>");
+        assert_eq!(short_name("b8"), "b8");
+    }
+
+    #[test]
+    fn test_lookup_of_builtin_kinds() {
+        register_kind::<rhdl_bits::Bits<8>>();
+        register_kind::<(bool, rhdl_bits::Bits<4>)>();
+        assert_eq!(KindRegistry::lookup("b8"), Some(Kind::make_bits(8)));
+        assert_eq!(
+            KindRegistry::lookup("(b1, b4)"),
+            Some(Kind::make_tuple(vec![
+                Kind::make_bits(1),
+                Kind::make_bits(4)
+            ]))
+        );
+        assert!(KindRegistry::names().contains(&"b8".to_string()));
+        assert_eq!(KindRegistry::lookup("b9"), None);
+    }
+}
@@ -1,7 +1,12 @@
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+pub mod convert;
 pub mod digital;
 pub mod digital_fn;
 pub mod kernel;
 pub mod kind;
+pub mod kind_registry;
 pub mod note;
 pub mod synchronous;
 pub mod typed_bits;
+pub mod x_typed_bits;
@@ -72,3 +72,28 @@ impl<T: Notable> Notable for &T {
         (*self).note(key, writer)
     }
 }
+
+// Notes a `[bool; N]` as a single bitstring (with element 0 as the
+// least significant bit), rather than as N separate bools.  So
+// `note("flags", BitString(flags))` shows up in a trace as one
+// N bit wide signal.  Arrays wider than 128 bits do not fit in
+// a bitstring, and are noted element by element instead.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BitString<const N: usize>(pub [bool; N]);
+
+impl<const N: usize> Notable for BitString<N> {
+    fn note(&self, key: impl NoteKey, mut writer: impl NoteWriter) {
+        if N > 128 {
+            for (i, x) in self.0.iter().enumerate() {
+                writer.write_bool((key, i), *x);
+            }
+            return;
+        }
+        let value = self
+            .0
+            .iter()
+            .rev()
+            .fold(0_u128, |acc, &bit| (acc << 1) | bit as u128);
+        writer.write_bits(key, value, N as u8);
+    }
+}
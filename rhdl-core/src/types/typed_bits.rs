@@ -1,12 +1,15 @@
 use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::iter::repeat;
 
 use crate::dyn_bit_manip::bits_shr_signed;
 use crate::dyn_bit_manip::{
-    bit_neg, bit_not, bits_and, bits_or, bits_shl, bits_shr, bits_xor, full_add, full_sub,
+    bit_neg, bit_not, bits_and, bits_or, bits_shl, bits_shr, bits_xor, full_add, full_mul, full_sub,
 };
+use crate::rhif::spec::{AluBinary, AluUnary};
 use crate::Digital;
 use crate::{
     path::{bit_range, Path},
@@ -251,6 +254,144 @@ impl TypedBits {
             kind: Kind::make_bits(count),
         })
     }
+    // Check that the bits hold a legal value of the kind.  Bits that come
+    // from outside of RHDL (say, read back from hardware) need not: the
+    // discriminant of an enum may belong to none of its variants, or a
+    // ranged integer may be out of its range.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.bits.len() == self.kind.bits(),
+            "Expected {} bits for {}, but got {}",
+            self.kind.bits(),
+            self.kind,
+            self.bits.len()
+        );
+        match &self.kind {
+            Kind::Array(array) => {
+                for ndx in 0..array.size {
+                    self.path(&Path::default().index(ndx))?
+                        .validate()
+                        .with_context(|| format!("In element {ndx} of {}", self.kind))?;
+                }
+            }
+            Kind::Tuple(tuple) => {
+                for ndx in 0..tuple.elements.len() {
+                    self.path(&Path::default().index(ndx))?
+                        .validate()
+                        .with_context(|| format!("In element {ndx} of {}", self.kind))?;
+                }
+            }
+            Kind::Struct(strukt) => {
+                for field in &strukt.fields {
+                    self.path(&Path::default().field(&field.name))?
+                        .validate()
+                        .with_context(|| format!("In field {} of {}", field.name, strukt.name))?;
+                }
+            }
+            Kind::Enum(enumerate) => {
+                let discriminant = self.path(&Path::default().discriminant())?.as_i64()?;
+                let Some(variant) = enumerate
+                    .variants
+                    .iter()
+                    .find(|variant| variant.discriminant == discriminant)
+                else {
+                    bail!(
+                        "Invalid discriminant {discriminant} for enum {}",
+                        enumerate.name
+                    );
+                };
+                TypedBits {
+                    bits: self.bits[enumerate.payload_range(variant)].to_vec(),
+                    kind: variant.kind.clone(),
+                }
+                .validate()
+                .with_context(|| format!("In variant {} of {}", variant.name, enumerate.name))?;
+            }
+            Kind::Ranged(ranged) => {
+                let value = TypedBits {
+                    bits: self.bits.clone(),
+                    kind: (*ranged.base).clone(),
+                }
+                .as_i128();
+                ensure!(
+                    (ranged.min..=ranged.max).contains(&value),
+                    "The value {value} is out of the range {}..={}",
+                    ranged.min,
+                    ranged.max
+                );
+            }
+            Kind::Bits(_) | Kind::Signed(_) | Kind::Empty => {}
+        }
+        Ok(())
+    }
+    // Apply a binary ALU operation as a kernel does, so that a test can
+    // build the expected result of a kernel out of `TypedBits` alone.
+    // Arithmetic wraps to the width of the arguments, and whether a
+    // comparison or right shift is signed comes from the kind of the
+    // value.  This is what the interpreter runs.
+    pub fn binary(self, op: &AluBinary, rhs: TypedBits) -> Result<TypedBits> {
+        // Comparing values of different kinds (e.g., signed against
+        // unsigned) has no single meaning, so refuse rather than
+        // quietly returning false.
+        if matches!(
+            op,
+            AluBinary::Eq
+                | AluBinary::Ne
+                | AluBinary::Lt
+                | AluBinary::Le
+                | AluBinary::Gt
+                | AluBinary::Ge
+        ) {
+            ensure!(
+                self.kind == rhs.kind,
+                "Cannot compare {} with {}",
+                self.kind,
+                rhs.kind
+            );
+        }
+        match op {
+            AluBinary::Add => self + rhs,
+            AluBinary::Sub => self - rhs,
+            AluBinary::Mul => self * rhs,
+            AluBinary::BitXor => self ^ rhs,
+            AluBinary::BitAnd => self & rhs,
+            AluBinary::BitOr => self | rhs,
+            AluBinary::Shl => self << rhs,
+            AluBinary::Shr => self >> rhs,
+            AluBinary::Eq => Ok((self == rhs).typed_bits()),
+            AluBinary::Ne => Ok((self != rhs).typed_bits()),
+            AluBinary::Lt => Ok((self < rhs).typed_bits()),
+            AluBinary::Le => Ok((self <= rhs).typed_bits()),
+            AluBinary::Gt => Ok((self > rhs).typed_bits()),
+            AluBinary::Ge => Ok((self >= rhs).typed_bits()),
+        }
+    }
+    // The unary counterpart of `binary`.
+    pub fn unary(self, op: &AluUnary) -> Result<TypedBits> {
+        match op {
+            AluUnary::Not => !self,
+            AluUnary::Neg => -self,
+            AluUnary::All => Ok(self.all()),
+            AluUnary::Any => Ok(self.any()),
+            AluUnary::Signed => self.as_signed(),
+            AluUnary::Unsigned => self.as_unsigned(),
+            AluUnary::Xor => Ok(self.xor()),
+        }
+    }
+    // The value of an integer, which must fit in 128 bits.
+    fn as_i128(&self) -> i128 {
+        let value = self
+            .bits
+            .iter()
+            .rev()
+            .fold(0_i128, |acc, b| (acc << 1) | (*b as i128));
+        let width = self.bits.len();
+        if self.kind.is_signed() && width > 0 && width < 128 && self.bits[width - 1] {
+            value - (1 << width)
+        } else {
+            value
+        }
+    }
 }
 
 impl std::ops::Add<TypedBits> for TypedBits {
@@ -289,6 +430,27 @@ impl std::ops::Sub<TypedBits> for TypedBits {
     }
 }
 
+impl std::ops::Mul<TypedBits> for TypedBits {
+    type Output = Result<TypedBits>;
+
+    fn mul(self, rhs: TypedBits) -> Self::Output {
+        if self.kind != rhs.kind {
+            bail!(
+                "Cannot multiply {} and {} because they have different types",
+                self,
+                rhs
+            );
+        }
+        if self.kind.is_composite() {
+            bail!("Cannot multiply composite {}", self);
+        }
+        Ok(TypedBits {
+            bits: full_mul(&self.bits, &rhs.bits),
+            kind: self.kind,
+        })
+    }
+}
+
 impl std::ops::Not for TypedBits {
     type Output = Result<TypedBits>;
 
@@ -390,14 +552,9 @@ impl std::ops::Shl<TypedBits> for TypedBits {
         if !rhs.kind.is_unsigned() {
             bail!("Shift amount must be unsigned: {}", rhs);
         }
+        // Shifting by the width or more shifts every bit out, as in
+        // Verilog, rather than being an error.
         let shift = rhs.as_i64()?;
-        if shift >= self.bits.len() as i64 {
-            bail!(
-                "Shift amount {} is greater than the number of bits in {}",
-                shift,
-                self
-            );
-        }
         Ok(TypedBits {
             bits: bits_shl(&self.bits, shift),
             kind: self.kind,
@@ -415,14 +572,10 @@ impl std::ops::Shr<TypedBits> for TypedBits {
         if !rhs.kind.is_unsigned() {
             bail!("Shift amount must be unsigned: {}", rhs);
         }
+        // Shifting by the width or more shifts every bit out, as in
+        // Verilog, rather than being an error.  Signed values shift
+        // arithmetically (the sign bit fills in from the left).
         let shift = rhs.as_i64()?;
-        if shift >= self.bits.len() as i64 {
-            bail!(
-                "Shift amount {} is greater than the number of bits in {}",
-                shift,
-                self
-            );
-        }
         if self.kind.is_signed() {
             Ok(TypedBits {
                 bits: bits_shr_signed(&self.bits, shift),
@@ -474,6 +627,20 @@ impl std::fmt::Display for TypedBits {
     }
 }
 
+// Render bits as a value of the given kind, e.g., signed values as signed
+// decimals, enums by the name of their variant, and structs as a list of
+// fields.  This is how literals are shown in RHIF dumps, schematics and
+// the comments of the generated Verilog.
+pub fn render_typed_bits(kind: &Kind, bits: &[bool]) -> String {
+    struct Render<'a>(&'a Kind, &'a [bool]);
+    impl std::fmt::Display for Render<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write_kind_with_bits(self.0, self.1, f)
+        }
+    }
+    Render(kind, bits).to_string()
+}
+
 fn write_kind_with_bits(
     kind: &Kind,
     bits: &[bool],
@@ -487,6 +654,7 @@ fn write_kind_with_bits(
         Kind::Bits(_) => write_bits(bits, f),
         Kind::Signed(_) => write_signed(bits, f),
         Kind::Empty => write!(f, "()"),
+        Kind::Ranged(r) => write_kind_with_bits(&r.base, bits, f),
     }
 }
 
@@ -515,18 +683,25 @@ fn write_enumerate(
     let (range, kind) = bit_range(root_kind.clone(), &Path::default().discriminant())
         .map_err(|_| std::fmt::Error)?;
     let discriminant_value = interpret_bits_as_i64(&bits[range], kind.is_signed());
-    // Get the variant for this discriminant
-    let variant = enumerate
+    // Get the variant for this discriminant.  The bits need not hold a
+    // valid value (e.g., a register that was never written), so show the
+    // discriminant when there is no variant for it.
+    let Some(variant) = enumerate
         .variants
         .iter()
         .find(|v| v.discriminant == discriminant_value)
-        .ok_or(std::fmt::Error)?;
+    else {
+        return write!(f, "{}::<{}>", enumerate.name, discriminant_value);
+    };
     write!(f, "{}::{}", enumerate.name, variant.name)?;
     let (payload_range, payload_kind) = bit_range(
         root_kind,
         &Path::default().payload_by_value(discriminant_value),
     )
     .map_err(|_| std::fmt::Error)?;
+    if payload_kind.is_empty() {
+        return Ok(());
+    }
     let payload = &bits[payload_range];
     write_kind_with_bits(&payload_kind, payload, f)
 }
@@ -610,6 +785,7 @@ fn write_tuple(tuple: &Tuple, bits: &[bool], f: &mut std::fmt::Formatter<'_>) ->
 
 #[cfg(test)]
 mod tests {
+    use super::render_typed_bits;
     use crate::{Digital, DiscriminantAlignment, DiscriminantType, Kind, Notable, TypedBits};
 
     #[test]
@@ -624,6 +800,62 @@ mod tests {
         assert_eq!(c, 238_u8.typed_bits());
     }
 
+    #[test]
+    fn test_typed_bits_binary_matches_unsigned_bits() {
+        use crate::rhif::spec::AluBinary;
+        use rhdl_bits::Bits;
+        for (a, b) in (0..16).flat_map(|a| (0..16).map(move |b| (a, b))) {
+            let (x, y) = (Bits::<4>::from(a), Bits::<4>::from(b));
+            let eval = |op| x.typed_bits().binary(&op, y.typed_bits()).unwrap();
+            assert_eq!(eval(AluBinary::Add), (x + y).typed_bits());
+            assert_eq!(eval(AluBinary::Sub), (x - y).typed_bits());
+            assert_eq!(eval(AluBinary::BitAnd), (x & y).typed_bits());
+            assert_eq!(
+                eval(AluBinary::Mul),
+                Bits::<4>::from(a * b % 16).typed_bits()
+            );
+            assert_eq!(eval(AluBinary::Eq), (x == y).typed_bits());
+            assert_eq!(eval(AluBinary::Ne), (x != y).typed_bits());
+            assert_eq!(eval(AluBinary::Lt), (x < y).typed_bits());
+            assert_eq!(eval(AluBinary::Le), (x <= y).typed_bits());
+            assert_eq!(eval(AluBinary::Gt), (x > y).typed_bits());
+            assert_eq!(eval(AluBinary::Ge), (x >= y).typed_bits());
+            let n = Bits::<2>::from(b % 4);
+            let shift = |op| x.typed_bits().binary(&op, n.typed_bits()).unwrap();
+            assert_eq!(shift(AluBinary::Shl), (x << n).typed_bits());
+            assert_eq!(shift(AluBinary::Shr), (x >> n).typed_bits());
+        }
+    }
+
+    #[test]
+    fn test_typed_bits_binary_matches_signed_bits() {
+        use crate::rhif::spec::AluBinary;
+        use rhdl_bits::{Bits, SignedBits};
+        for (a, b) in (-8..8).flat_map(|a| (-8..8).map(move |b| (a, b))) {
+            let (x, y) = (SignedBits::<4>::from(a), SignedBits::<4>::from(b));
+            let eval = |op| x.typed_bits().binary(&op, y.typed_bits()).unwrap();
+            assert_eq!(eval(AluBinary::Add), (x + y).typed_bits());
+            assert_eq!(eval(AluBinary::Sub), (x - y).typed_bits());
+            let product = Bits::<4>::from(((a * b) & 0xF) as u128).as_signed();
+            assert_eq!(eval(AluBinary::Mul), product.typed_bits());
+            assert_eq!(eval(AluBinary::Eq), (x == y).typed_bits());
+            assert_eq!(eval(AluBinary::Lt), (x < y).typed_bits());
+            assert_eq!(eval(AluBinary::Le), (x <= y).typed_bits());
+            assert_eq!(eval(AluBinary::Gt), (x > y).typed_bits());
+            assert_eq!(eval(AluBinary::Ge), (x >= y).typed_bits());
+            let n = Bits::<2>::from((b & 3) as u128);
+            let shift = |op| x.typed_bits().binary(&op, n.typed_bits()).unwrap();
+            assert_eq!(shift(AluBinary::Shl), (x << n).typed_bits());
+            assert_eq!(shift(AluBinary::Shr), (x >> n).typed_bits());
+        }
+        // Signed and unsigned values do not mix
+        let err = (-1_i8)
+            .typed_bits()
+            .binary(&AluBinary::Lt, 255_u8.typed_bits())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Cannot compare s8 with b8");
+    }
+
     #[test]
     fn test_display_typed_bits() {
         #[derive(Debug, Clone, PartialEq, Copy)]
@@ -682,17 +914,17 @@ mod tests {
             fn bin(self) -> Vec<bool> {
                 self.kind().pad(match self {
                     Self::A(_0) => {
-                        let mut v = rhdl_bits::bits::<2usize>(0i64 as u128).to_bools();
+                        let mut v = rhdl_bits::bits::<2usize>(0i64 as u128).to_bools().to_vec();
                         v.extend(_0.bin());
                         v
                     }
                     Self::B { foo } => {
-                        let mut v = rhdl_bits::bits::<2usize>(1i64 as u128).to_bools();
+                        let mut v = rhdl_bits::bits::<2usize>(1i64 as u128).to_bools().to_vec();
                         v.extend(foo.bin());
                         v
                     }
                     Self::C(_0) => {
-                        let mut v = rhdl_bits::bits::<2usize>(2i64 as u128).to_bools();
+                        let mut v = rhdl_bits::bits::<2usize>(2i64 as u128).to_bools().to_vec();
                         v.extend(_0.bin());
                         v
                     }
@@ -801,4 +1033,98 @@ mod tests {
             "rhdl_core::types::typed_bits::tests::Baz::A(Bar {0: 47_b8, 1: 80_b8, 2: true})"
         );
     }
+
+    #[test]
+    fn test_render_typed_bits() {
+        let bits = |value: u8, width: usize| {
+            (0..width)
+                .map(|i| value & (1 << i) != 0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(render_typed_bits(&Kind::Signed(8), &bits(0xfc, 8)), "-4_s8");
+        assert_eq!(render_typed_bits(&Kind::Signed(8), &bits(0x04, 8)), "4_s8");
+        let state = Kind::make_enum(
+            "State",
+            vec![
+                Kind::make_variant("Idle", Kind::Empty, 0),
+                Kind::make_variant("Busy", Kind::Empty, 1),
+                Kind::make_variant("Done", Kind::Empty, -1),
+            ],
+            Kind::make_discriminant_layout(2, DiscriminantAlignment::Lsb, DiscriminantType::Signed),
+        );
+        assert_eq!(render_typed_bits(&state, &bits(0b01, 2)), "State::Busy");
+        assert_eq!(render_typed_bits(&state, &bits(0b11, 2)), "State::Done");
+        // There is no variant with a discriminant of -2
+        assert_eq!(render_typed_bits(&state, &bits(0b10, 2)), "State::<-2>");
+        let point = Kind::make_struct(
+            "Point",
+            vec![
+                Kind::make_field("x", Kind::Signed(4)),
+                Kind::make_field("y", Kind::Bits(4)),
+            ],
+        );
+        assert_eq!(
+            render_typed_bits(&point, &bits(0x3e, 8)),
+            "Point {x: -2_s4, y: 3_b4}"
+        );
+    }
+
+    #[test]
+    fn test_validate_typed_bits() {
+        let bits = |value: u8, width: usize| {
+            (0..width)
+                .map(|i| value & (1 << i) != 0)
+                .collect::<Vec<_>>()
+        };
+        let state = Kind::make_enum(
+            "State",
+            vec![
+                Kind::make_variant("Idle", Kind::Empty, 0),
+                Kind::make_variant("Busy", Kind::Bits(4), 1),
+                Kind::make_variant("Done", Kind::Empty, -1),
+            ],
+            Kind::make_discriminant_layout(2, DiscriminantAlignment::Lsb, DiscriminantType::Signed),
+        );
+        let value = |value: u8| TypedBits {
+            bits: bits(value, 6),
+            kind: state.clone(),
+        };
+        assert!(value(0b1001_01).validate().is_ok());
+        assert!(value(0b0000_11).validate().is_ok());
+        // There is no variant with a discriminant of -2
+        let err = value(0b0000_10).validate().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid discriminant -2 for enum State");
+        // The discriminant is checked inside of other kinds too
+        let pair = Kind::make_struct(
+            "Pair",
+            vec![
+                Kind::make_field("level", Kind::Bits(2)),
+                Kind::make_field("state", state.clone()),
+            ],
+        );
+        let value = |value: u8| TypedBits {
+            bits: bits(value, 8),
+            kind: Kind::make_array(pair.clone(), 1),
+        };
+        assert!(value(0b0000_01_11).validate().is_ok());
+        let err = value(0b0000_10_11).validate().unwrap_err();
+        assert_eq!(
+            err.chain().map(|x| x.to_string()).collect::<Vec<_>>(),
+            [
+                "In element 0 of [Pair; 1]",
+                "In field state of Pair",
+                "Invalid discriminant -2 for enum State",
+            ]
+        );
+        let ranged = TypedBits {
+            bits: bits(9, 4),
+            kind: Kind::Bits(4).with_range(2, 9),
+        };
+        assert!(ranged.validate().is_ok());
+        let ranged = TypedBits {
+            bits: bits(10, 4),
+            ..ranged
+        };
+        assert!(ranged.validate().is_err());
+    }
 }
@@ -0,0 +1,278 @@
+use anyhow::{bail, ensure, Result};
+
+use crate::dyn_bit_manip::{bit_not, bits_shl, bits_shr, bits_shr_signed};
+use crate::path::Path;
+use crate::rhif::spec::{AluBinary, AluUnary};
+use crate::{Digital, Kind, TypedBits};
+
+// A value in which some bits may be unknown (X), as they are in an
+// uninitialized register.  Bit `i` of the value is known if `known[i]`
+// is set.  Unknown bits are always stored as zero in `value`, so that
+// two values with the same known bits compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct XTypedBits {
+    pub value: TypedBits,
+    pub known: Vec<bool>,
+}
+
+impl From<TypedBits> for XTypedBits {
+    fn from(value: TypedBits) -> Self {
+        let known = vec![true; value.bits.len()];
+        XTypedBits { value, known }
+    }
+}
+
+impl XTypedBits {
+    // A value of the given kind of which nothing is known.
+    pub fn unknown(kind: Kind) -> XTypedBits {
+        let width = kind.bits();
+        XTypedBits {
+            value: TypedBits {
+                bits: vec![false; width],
+                kind,
+            },
+            known: vec![false; width],
+        }
+    }
+    pub fn new(value: TypedBits, known: Vec<bool>) -> Result<XTypedBits> {
+        ensure!(
+            value.bits.len() == known.len(),
+            "The mask of known bits has {} bits, but {} has {}",
+            known.len(),
+            value,
+            value.bits.len()
+        );
+        Ok(Self::with_mask(value, known))
+    }
+    fn with_mask(mut value: TypedBits, known: Vec<bool>) -> XTypedBits {
+        for (bit, known) in value.bits.iter_mut().zip(&known) {
+            *bit &= known;
+        }
+        XTypedBits { value, known }
+    }
+    // The mask of known bits, laid out as a value of the same kind, so
+    // that it can be taken apart and put together with the value.
+    fn mask(&self) -> TypedBits {
+        TypedBits {
+            bits: self.known.clone(),
+            kind: self.value.kind.clone(),
+        }
+    }
+    fn from_parts(value: TypedBits, mask: TypedBits) -> XTypedBits {
+        Self::with_mask(value, mask.bits)
+    }
+    pub fn kind(&self) -> &Kind {
+        &self.value.kind
+    }
+    pub fn is_known(&self) -> bool {
+        self.known.iter().all(|k| *k)
+    }
+    // The indices of the bits that are unknown, LSB first.
+    pub fn unknown_bits(&self) -> Vec<usize> {
+        self.known
+            .iter()
+            .enumerate()
+            .filter(|(_, known)| !**known)
+            .map(|(ndx, _)| ndx)
+            .collect()
+    }
+    // The value, provided that none of its bits are unknown.
+    pub fn into_known(self) -> Result<TypedBits> {
+        if !self.is_known() {
+            bail!(
+                "X reached the output: bits {:?} of {} are unknown",
+                self.unknown_bits(),
+                self
+            );
+        }
+        Ok(self.value)
+    }
+    pub fn path(&self, path: &Path) -> Result<XTypedBits> {
+        Ok(Self::from_parts(
+            self.value.path(path)?,
+            self.mask().path(path)?,
+        ))
+    }
+    pub fn splice(&self, path: &Path, value: XTypedBits) -> Result<XTypedBits> {
+        let mask = value.mask();
+        Ok(Self::from_parts(
+            self.value.splice(path, value.value)?,
+            self.mask().splice(path, mask)?,
+        ))
+    }
+    pub fn repeat(&self, count: usize) -> XTypedBits {
+        Self::from_parts(self.value.repeat(count), self.mask().repeat(count))
+    }
+    // The value that is one of `self` or `other`, without knowing which.
+    // Only the bits that are known and equal in both remain known.
+    pub fn merge(&self, other: &XTypedBits) -> Result<XTypedBits> {
+        ensure!(
+            self.kind() == other.kind(),
+            "Cannot merge {} and {} because they have different types",
+            self,
+            other
+        );
+        let known = (0..self.known.len())
+            .map(|ndx| {
+                self.known[ndx] && other.known[ndx] && self.value.bits[ndx] == other.value.bits[ndx]
+            })
+            .collect();
+        Ok(Self::with_mask(self.value.clone(), known))
+    }
+    pub fn unsigned_cast(&self, bits: usize) -> Result<XTypedBits> {
+        if self.is_known() {
+            return Ok(self.value.unsigned_cast(bits)?.into());
+        }
+        // Truncating unknown bits cannot be checked, so it is not.
+        let extend = |bits_in: &[bool], fill: bool| {
+            bits_in
+                .iter()
+                .copied()
+                .chain(std::iter::repeat(fill))
+                .take(bits)
+                .collect::<Vec<_>>()
+        };
+        Ok(Self::with_mask(
+            TypedBits {
+                bits: extend(&self.value.bits, false),
+                kind: Kind::make_bits(bits),
+            },
+            extend(&self.known, true),
+        ))
+    }
+    pub fn signed_cast(&self, bits: usize) -> Result<XTypedBits> {
+        if self.is_known() {
+            return Ok(self.value.signed_cast(bits)?.into());
+        }
+        let extend = |bits_in: &[bool]| {
+            let sign = bits_in.last().copied().unwrap_or_default();
+            bits_in
+                .iter()
+                .copied()
+                .chain(std::iter::repeat(sign))
+                .take(bits)
+                .collect::<Vec<_>>()
+        };
+        Ok(Self::with_mask(
+            TypedBits {
+                bits: extend(&self.value.bits),
+                kind: Kind::make_signed(bits),
+            },
+            extend(&self.known),
+        ))
+    }
+    // As `TypedBits::binary`, with X propagated through the operation.
+    // A bit of the result is known if it is the same for every choice
+    // of the unknown bits of the arguments (e.g., `X & 0 = 0`), or at
+    // least in the cases that are cheap to work out.
+    pub fn binary(self, op: &AluBinary, rhs: XTypedBits) -> Result<XTypedBits> {
+        let value = self.value.clone().binary(op, rhs.value.clone())?;
+        let width = value.bits.len();
+        let both_known = self
+            .known
+            .iter()
+            .zip(&rhs.known)
+            .map(|(a, b)| *a && *b)
+            .collect::<Vec<_>>();
+        let all_known = self.is_known() && rhs.is_known();
+        let known = match op {
+            AluBinary::BitAnd => (0..width)
+                .map(|ndx| {
+                    both_known[ndx]
+                        || (self.known[ndx] && !self.value.bits[ndx])
+                        || (rhs.known[ndx] && !rhs.value.bits[ndx])
+                })
+                .collect(),
+            AluBinary::BitOr => (0..width)
+                .map(|ndx| {
+                    both_known[ndx]
+                        || (self.known[ndx] && self.value.bits[ndx])
+                        || (rhs.known[ndx] && rhs.value.bits[ndx])
+                })
+                .collect(),
+            AluBinary::BitXor => both_known,
+            // Bit `i` of a sum, difference or product depends only on
+            // bits `0..=i` of the arguments.
+            AluBinary::Add | AluBinary::Sub | AluBinary::Mul => known_below_first_x(&both_known),
+            AluBinary::Shl | AluBinary::Shr if !rhs.is_known() => vec![false; width],
+            AluBinary::Shl => {
+                let shift = rhs.value.as_i64()?;
+                bit_not(&bits_shl(&bit_not(&self.known), shift))
+            }
+            AluBinary::Shr => {
+                let shift = rhs.value.as_i64()?;
+                if self.kind().is_signed() {
+                    bit_not(&bits_shr_signed(&bit_not(&self.known), shift))
+                } else {
+                    bit_not(&bits_shr(&bit_not(&self.known), shift))
+                }
+            }
+            AluBinary::Eq | AluBinary::Ne => {
+                // Values that differ in a bit known in both are unequal,
+                // whatever the unknown bits are.
+                let differ = (0..both_known.len())
+                    .any(|ndx| both_known[ndx] && self.value.bits[ndx] != rhs.value.bits[ndx]);
+                if differ {
+                    return Ok((matches!(op, AluBinary::Ne)).typed_bits().into());
+                }
+                vec![all_known]
+            }
+            AluBinary::Lt | AluBinary::Le | AluBinary::Gt | AluBinary::Ge => vec![all_known],
+        };
+        Ok(Self::with_mask(value, known))
+    }
+    // The unary counterpart of `binary`.
+    pub fn unary(self, op: &AluUnary) -> Result<XTypedBits> {
+        let value = self.value.clone().unary(op)?;
+        let known = match op {
+            AluUnary::Not | AluUnary::Signed | AluUnary::Unsigned => self.known.clone(),
+            AluUnary::Neg => known_below_first_x(&self.known),
+            AluUnary::All => {
+                let known_clear =
+                    (0..self.known.len()).any(|ndx| self.known[ndx] && !self.value.bits[ndx]);
+                if known_clear {
+                    return Ok(false.typed_bits().into());
+                }
+                vec![self.is_known()]
+            }
+            AluUnary::Any => {
+                let known_set =
+                    (0..self.known.len()).any(|ndx| self.known[ndx] && self.value.bits[ndx]);
+                if known_set {
+                    return Ok(true.typed_bits().into());
+                }
+                vec![self.is_known()]
+            }
+            AluUnary::Xor => vec![self.is_known()],
+        };
+        Ok(Self::with_mask(value, known))
+    }
+}
+
+fn known_below_first_x(known: &[bool]) -> Vec<bool> {
+    known
+        .iter()
+        .scan(true, |so_far, known| {
+            *so_far &= known;
+            Some(*so_far)
+        })
+        .collect()
+}
+
+impl std::fmt::Display for XTypedBits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_known() {
+            return write!(f, "{}", self.value);
+        }
+        // With unknown bits, the value can only be shown as bits, MSB first.
+        write!(f, "{}'b", self.known.len())?;
+        for ndx in (0..self.known.len()).rev() {
+            match (self.known[ndx], self.value.bits[ndx]) {
+                (false, _) => write!(f, "x")?,
+                (true, true) => write!(f, "1")?,
+                (true, false) => write!(f, "0")?,
+            }
+        }
+        Ok(())
+    }
+}
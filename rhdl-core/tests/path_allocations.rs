@@ -0,0 +1,77 @@
+// The operations on paths that the schematic builder and the CDC checker
+// run most often should borrow rather than clone.  This counts the
+// allocations they make, which needs a global allocator of its own, and
+// so lives in a test binary of its own.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use rhdl_core::path::{leaf_paths, Path, PathElement};
+use rhdl_core::Kind;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// Only the allocations made by the current thread are counted, so that
+// the test harness does not disturb the count.
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|count| count.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|count| count.get()) - before)
+}
+
+#[test]
+fn test_path_operations_borrow() {
+    // The kind of the input of a typical circuit, with nested structs
+    let inner = Kind::make_struct(
+        "inner",
+        vec![
+            Kind::make_field("valid", Kind::make_bits(1)),
+            Kind::make_field("data", Kind::make_bits(8)),
+        ],
+    );
+    let kind = Kind::make_struct(
+        "outer",
+        vec![
+            Kind::make_field("clock", Kind::make_bits(1)),
+            Kind::make_field("upstream", inner.clone()),
+            Kind::make_field("downstream", inner),
+        ],
+    );
+    let prefix = Path::default().index(1).field("upstream");
+    let paths = leaf_paths(&kind, Path::default())
+        .iter()
+        .map(|leaf| prefix.clone().join(leaf))
+        .collect::<Vec<_>>();
+    for path in &paths {
+        // Checking for a prefix allocates nothing
+        let (is_prefix, count) = allocations(|| prefix.is_prefix_of(path));
+        assert!(is_prefix);
+        assert_eq!(count, 0);
+        // Stripping it allocates only the rest of the path: its elements,
+        // and the name of each field in them
+        let (rest, count) = allocations(|| path.strip_prefix(&prefix).unwrap());
+        let names = rest
+            .elements
+            .iter()
+            .filter(|element| matches!(element, PathElement::Field(_)))
+            .count();
+        assert_eq!(count, 1 + names, "{path}");
+    }
+    assert_eq!(paths[1].to_string(), "[1].upstream.upstream.valid");
+}
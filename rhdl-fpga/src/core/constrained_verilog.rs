@@ -2,8 +2,10 @@
 // generate a Verilog module and constraint file.  For now, let's just
 // get the Alchitry Cu working.
 
+use rhdl_core::clocked_always_block;
 use rhdl_core::compile_design;
 use rhdl_core::generate_verilog;
+use rhdl_core::AssignmentStyle;
 use rhdl_core::KernelFnKind;
 
 use rhdl_core::Digital;
@@ -21,15 +23,37 @@ pub struct ConstrainedVerilog {
 }
 
 pub fn make_constrained_verilog<M: Synchronous>(
+    obj: M,
+    constraints: Vec<PinConstraint>,
+    clock_source: Constraint,
+) -> Result<ConstrainedVerilog> {
+    make_constrained_verilog_with_style(obj, constraints, clock_source, AssignmentStyle::default())
+}
+
+// As above, but with the given style for the register updates.  The
+// default (non-blocking) is almost always the one you want.
+pub fn make_constrained_verilog_with_style<M: Synchronous>(
     obj: M,
     mut constraints: Vec<PinConstraint>,
     clock_source: Constraint,
+    style: AssignmentStyle,
 ) -> Result<ConstrainedVerilog> {
     let Some(KernelFnKind::Kernel(kernel)) = M::Update::kernel_fn() else {
         return Err(anyhow::anyhow!("No kernel function found"));
     };
     let verilog = generate_verilog(&compile_design(kernel)?)?;
     let module_code = format!("{}", verilog);
+    let registers = clocked_always_block(
+        "clk",
+        &[
+            (
+                "state".into(),
+                format!("update_result[{}:0]", M::State::bits().saturating_sub(1)),
+            ),
+            ("top_out".into(), "output_value".into()),
+        ],
+        style,
+    );
     let module = format!(
         "
 module top(input wire clk, input wire[{INPUT_BITS}:0] top_in, output reg[{OUTPUT_BITS}:0] top_out);
@@ -43,11 +67,7 @@ module top(input wire clk, input wire[{INPUT_BITS}:0] top_in, output reg[{OUTPUT
     assign update_result = {update_fn}(config_value, state, top_in);
     assign output_value = update_result[{OUTPUT_END}:{OUTPUT_START}];
 
-    always @(posedge clk) begin 
-        state <= update_result[{STATE_BITS}:0];
-        top_out <= output_value;
-    end
-
+{registers}
     // This may not work.
     initial begin
         state <= {initial_state};
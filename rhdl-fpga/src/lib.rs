@@ -1,10 +1,12 @@
 pub mod bsp;
 pub mod core;
+pub mod primitives;
 pub use anyhow::Result;
 pub use core::bga::bga_pin;
 pub use core::bga::BGAPin;
 pub use core::bga::BGARow;
 pub use core::constrained_verilog::make_constrained_verilog;
+pub use core::constrained_verilog::make_constrained_verilog_with_style;
 pub use core::constrained_verilog::ConstrainedVerilog;
 pub use core::constraint::Constraint;
 pub use core::constraint::PinConstraint;
@@ -0,0 +1,137 @@
+use rhdl_bits::Bits;
+use rhdl_core::path::Path;
+use rhdl_core::{CircuitIO, Primitive, PrimitivePort};
+use rhdl_macro::Digital;
+
+// An N bit adder with a carry in and out, built on a dedicated carry
+// chain.  The `CARRY_ADDER` module is generic, and is mapped onto the
+// carry chain of the device by the vendor flow.  Its fallback model is
+// the `+` operator, which is what most synthesis tools infer a carry
+// chain from anyway.
+#[derive(Clone, Default)]
+pub struct CarryAdder<const N: usize> {}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct CarryAdderI<const N: usize> {
+    pub a: Bits<N>,
+    pub b: Bits<N>,
+    pub carry_in: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct CarryAdderO<const N: usize> {
+    pub sum: Bits<N>,
+    pub carry_out: bool,
+}
+
+impl<const N: usize> CircuitIO for CarryAdder<N> {
+    type I = CarryAdderI<N>;
+    type O = CarryAdderO<N>;
+}
+
+impl<const N: usize> Primitive for CarryAdder<N> {
+    fn module(&self) -> &'static str {
+        "CARRY_ADDER"
+    }
+
+    fn parameters(&self) -> Vec<(String, String)> {
+        vec![("WIDTH".into(), N.to_string())]
+    }
+
+    fn ports(&self) -> Vec<PrimitivePort> {
+        vec![
+            PrimitivePort::input("A", Path::default().field("a")),
+            PrimitivePort::input("B", Path::default().field("b")),
+            PrimitivePort::input("CI", Path::default().field("carry_in")),
+            PrimitivePort::output("S", Path::default().field("sum")),
+            PrimitivePort::output("CO", Path::default().field("carry_out")),
+        ]
+    }
+
+    fn model(&self, input: CarryAdderI<N>) -> CarryAdderO<N> {
+        let (sum, carry) = input.a.0.overflowing_add(input.b.0);
+        let (sum, carry_in) = sum.overflowing_add(input.carry_in as u128);
+        let carry_out = if N == 128 {
+            carry || carry_in
+        } else {
+            sum & (1 << N) != 0
+        };
+        CarryAdderO {
+            sum: Bits(sum & Bits::<N>::MASK.0),
+            carry_out,
+        }
+    }
+
+    fn fallback_model(&self) -> Option<String> {
+        Some(
+            "
+module CARRY_ADDER #(parameter WIDTH = 8)(input wire[WIDTH-1:0] A, input wire[WIDTH-1:0] B, input wire CI, output wire[WIDTH-1:0] S, output wire CO);
+   assign {CO, S} = A + B + CI;
+endmodule
+"
+            .into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rhdl_bits::bits;
+    use rhdl_core::{Circuit, HDLKind, PrimitiveCircuit};
+
+    use super::*;
+
+    #[test]
+    fn test_carry_adder_model() {
+        let adder = CarryAdder::<4>::default();
+        let add = |a, b, carry_in| {
+            adder.model(CarryAdderI {
+                a: bits(a),
+                b: bits(b),
+                carry_in,
+            })
+        };
+        assert_eq!(
+            add(9, 6, false),
+            CarryAdderO {
+                sum: bits(15),
+                carry_out: false
+            }
+        );
+        assert_eq!(
+            add(9, 6, true),
+            CarryAdderO {
+                sum: bits(0),
+                carry_out: true
+            }
+        );
+        assert_eq!(
+            add(15, 15, true),
+            CarryAdderO {
+                sum: bits(15),
+                carry_out: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_carry_adder_instance() {
+        let adder = PrimitiveCircuit::from(CarryAdder::<4>::default());
+        let hdl = adder.as_hdl(HDLKind::Verilog).unwrap();
+        assert!(hdl
+            .body
+            .contains("CARRY_ADDER #(\n      .WIDTH(4)\n   ) primitive ("));
+        assert!(hdl.body.contains(".A(i[3:0])"));
+        assert!(hdl.body.contains(".CI(i[8])"));
+        assert!(hdl.body.contains(".CO(o[4])"));
+        // The vendor module is only defined when the fallback is asked for
+        assert!(hdl.children.is_empty());
+        let hdl = adder
+            .with_fallback_model()
+            .as_hdl(HDLKind::Verilog)
+            .unwrap();
+        assert!(hdl.children["CARRY_ADDER"]
+            .body
+            .contains("assign {CO, S} = A + B + CI;"));
+    }
+}
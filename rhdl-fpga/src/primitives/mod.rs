@@ -0,0 +1,4 @@
+#[cfg(feature = "carry-adder")]
+pub mod carry_adder;
+#[cfg(feature = "mult-add")]
+pub mod mult_add;
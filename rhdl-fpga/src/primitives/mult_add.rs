@@ -0,0 +1,158 @@
+use rhdl_bits::Bits;
+use rhdl_core::path::Path;
+use rhdl_core::{CircuitIO, Primitive, PrimitivePort};
+use rhdl_macro::Digital;
+
+// A DSP style multiply-add, computing `a * b + c` with N bit operands
+// and an M bit result (which wraps).  The `MULT_ADD` module stands for
+// the multiplier block of the device, and is combinational, with its
+// clock enable tied on.
+#[derive(Clone, Default)]
+pub struct MultAdd<const N: usize, const M: usize> {}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct MultAddI<const N: usize, const M: usize> {
+    pub a: Bits<N>,
+    pub b: Bits<N>,
+    pub c: Bits<M>,
+}
+
+impl<const N: usize, const M: usize> CircuitIO for MultAdd<N, M> {
+    type I = MultAddI<N, M>;
+    type O = Bits<M>;
+}
+
+impl<const N: usize, const M: usize> Primitive for MultAdd<N, M> {
+    fn module(&self) -> &'static str {
+        "MULT_ADD"
+    }
+
+    fn parameters(&self) -> Vec<(String, String)> {
+        vec![
+            ("A_WIDTH".into(), N.to_string()),
+            ("B_WIDTH".into(), N.to_string()),
+            ("P_WIDTH".into(), M.to_string()),
+        ]
+    }
+
+    fn ports(&self) -> Vec<PrimitivePort> {
+        vec![
+            PrimitivePort::input("A", Path::default().field("a")),
+            PrimitivePort::input("B", Path::default().field("b")),
+            PrimitivePort::input("C", Path::default().field("c")),
+            PrimitivePort::constant("CE", true),
+            PrimitivePort::output("P", Path::default()),
+        ]
+    }
+
+    fn model(&self, input: MultAddI<N, M>) -> Bits<M> {
+        let product = input.a.0.wrapping_mul(input.b.0);
+        Bits(product.wrapping_add(input.c.0) & Bits::<M>::MASK.0)
+    }
+
+    fn fallback_model(&self) -> Option<String> {
+        Some(
+            "
+module MULT_ADD #(parameter A_WIDTH = 18, parameter B_WIDTH = 18, parameter P_WIDTH = 48)(input wire[A_WIDTH-1:0] A, input wire[B_WIDTH-1:0] B, input wire[P_WIDTH-1:0] C, input wire CE, output wire[P_WIDTH-1:0] P);
+   assign P = A * B + C;
+endmodule
+"
+            .into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rhdl_bits::bits;
+    use rhdl_core::{Circuit, HDLKind, PrimitiveCircuit};
+    use rhdl_macro::{kernel, Circuit};
+
+    use super::*;
+
+    // Scale a sample by a gain, and add a fixed offset, on a DSP block
+    #[derive(Clone, Default, Circuit)]
+    #[rhdl(kernel = scale)]
+    pub struct Scale {
+        mac: PrimitiveCircuit<MultAdd<8, 16>>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+    pub struct ScaleI {
+        pub sample: Bits<8>,
+        pub gain: Bits<8>,
+    }
+
+    impl CircuitIO for Scale {
+        type I = ScaleI;
+        type O = Bits<16>;
+    }
+
+    #[kernel]
+    pub fn scale(i: ScaleI, q: ScaleQ) -> (Bits<16>, ScaleD) {
+        let mut d = ScaleD::default();
+        d.mac.a = i.sample;
+        d.mac.b = i.gain;
+        d.mac.c = bits::<16>(0x100);
+        (q.mac, d)
+    }
+
+    fn inputs() -> Vec<ScaleI> {
+        [(0, 0), (3, 7), (255, 255), (128, 2), (17, 200)]
+            .into_iter()
+            .map(|(sample, gain)| ScaleI {
+                sample: bits(sample),
+                gain: bits(gain),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scale_simulates_with_the_model() {
+        let scale = Scale::default();
+        let mut state = scale.init_state();
+        let mut io = <Scale as Circuit>::Z::default();
+        for input in inputs() {
+            let expected = input.sample.0 * input.gain.0 + 0x100;
+            assert_eq!(
+                scale.sim(input, &mut state, &mut io),
+                bits(expected & 0xffff)
+            );
+        }
+    }
+
+    #[test]
+    fn test_scale_instantiates_the_primitive() {
+        let hdl = Scale::default().as_hdl(HDLKind::Verilog).unwrap();
+        let mac = &hdl.children["mac"];
+        assert!(mac.body.contains(
+            "MULT_ADD #(
+      .A_WIDTH(8),
+      .B_WIDTH(8),
+      .P_WIDTH(16)
+   ) primitive (
+      .A(i[7:0]),
+      .B(i[15:8]),
+      .C(i[31:16]),
+      .CE(1'b1),
+      .P(o[15:0])
+   );"
+        ));
+        // Without the fallback, the module is left to the vendor library
+        assert!(!hdl.to_string().contains("module MULT_ADD #("));
+    }
+
+    #[test]
+    fn test_scale_matches_the_fallback_model() {
+        let scale = Scale {
+            mac: PrimitiveCircuit::from(MultAdd::default()).with_fallback_model(),
+        };
+        assert!(scale
+            .as_hdl(HDLKind::Verilog)
+            .unwrap()
+            .to_string()
+            .contains("module MULT_ADD #("));
+        scale.check_combinational_equivalence(100).unwrap();
+        scale.testbench(&inputs()).unwrap().run_iverilog().unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
+use quote::{format_ident, quote, quote_spanned};
 use syn::{spanned::Spanned, Attribute, Data, DeriveInput, Expr, ExprPath};
 
 pub fn derive_circuit(input: TokenStream) -> syn::Result<TokenStream> {
@@ -10,6 +10,15 @@ pub fn derive_circuit(input: TokenStream) -> syn::Result<TokenStream> {
 pub struct FieldSet<'a> {
     component_name: Vec<syn::Ident>,
     component_ty: Vec<&'a syn::Type>,
+    // The timing, cfg and doc attributes of each component, which apply
+    // to its fields of the D and Q of the circuit.
+    component_timing: Vec<Vec<&'a Attribute>>,
+    // The flag that must be enabled for the component to be present, if
+    // it is marked `#[rhdl(cfg(flag = "..."))]`.  Otherwise it is left
+    // out of the descriptor, the HDL and the simulation, and its fields
+    // of the D and Q have no bits.  (Its tristate signals are still
+    // counted in the Z of the circuit, which is fixed by its type.)
+    component_cfg: Vec<Option<String>>,
 }
 
 impl<'a> TryFrom<&'a syn::Fields> for FieldSet<'a> {
@@ -18,19 +27,46 @@ impl<'a> TryFrom<&'a syn::Fields> for FieldSet<'a> {
     fn try_from(fields: &'a syn::Fields) -> syn::Result<Self> {
         let mut component_name = Vec::new();
         let mut component_ty = Vec::new();
+        let mut component_timing = Vec::new();
+        let mut component_cfg = Vec::new();
         for field in fields.iter() {
             component_name.push(field.ident.clone().ok_or_else(|| {
                 syn::Error::new(field.span(), "Circuit components (fields) must have names")
             })?);
             component_ty.push(&field.ty);
+            component_timing.push(
+                field
+                    .attrs
+                    .iter()
+                    .filter(|attr| {
+                        crate::utils::is_timing_attribute(attr)
+                            || attr.path().is_ident("doc")
+                            || matches!(
+                                crate::utils::cfg_flag(std::slice::from_ref(attr)),
+                                Ok(Some(_))
+                            )
+                    })
+                    .collect(),
+            );
+            component_cfg.push(crate::utils::cfg_flag(&field.attrs)?);
         }
         Ok(FieldSet {
             component_name,
             component_ty,
+            component_timing,
+            component_cfg,
         })
     }
 }
 
+// Guard the code for a component with its flag, if it has one.
+fn guard_component(cfg: &Option<String>, code: TokenStream) -> TokenStream {
+    match cfg {
+        Some(flag) => quote! {if rhdl_core::expect_compile_flag(#flag) { #code }},
+        None => code,
+    }
+}
+
 fn define_init_state_fn(field_set: &FieldSet) -> TokenStream {
     let component_name = &field_set.component_name;
     quote! {
@@ -43,45 +79,139 @@ fn define_init_state_fn(field_set: &FieldSet) -> TokenStream {
     }
 }
 
-fn define_descriptor_fn(field_set: &FieldSet) -> TokenStream {
+fn define_state_signals_fn(field_set: &FieldSet) -> TokenStream {
     let component_name = &field_set.component_name;
+    let extend = component_name
+        .iter()
+        .zip(&field_set.component_cfg)
+        .enumerate()
+        .map(|(ndx, (name, cfg))| {
+            let index = syn::Index::from(ndx + 1);
+            guard_component(
+                cfg,
+                quote! {ret.extend(rhdl_core::child_state_signals(stringify!(#name), self.#name.state_signals(&state.#index)));},
+            )
+        });
+    quote! {
+        fn state_signals(&self, state: &Self::S) -> Vec<(String, rhdl_core::TypedBits)> {
+            let mut ret = vec![];
+            #(#extend)*
+            ret
+        }
+    }
+}
+
+fn define_descriptor_fn(
+    field_set: &FieldSet,
+    named_child_wires: bool,
+    port_names: &PortNames,
+    clocks: &[syn::Ident],
+    doc: Option<String>,
+) -> TokenStream {
+    let add_child = field_set
+        .component_name
+        .iter()
+        .zip(&field_set.component_cfg)
+        .map(|(name, cfg)| {
+            let add_child = quote! {ret.add_child(stringify!(#name), &self.#name);};
+            match cfg {
+                // The flags of the children are recorded with those of
+                // the kernel
+                Some(flag) => quote! {
+                    ret.flags.insert(#flag.into(), rhdl_core::expect_compile_flag(#flag));
+                    if rhdl_core::expect_compile_flag(#flag) { #add_child }
+                },
+                None => add_child,
+            }
+        });
+    let named_child_wires = named_child_wires.then(|| quote! {ret = ret.with_named_child_wires();});
+    let input_port = port_names
+        .input
+        .as_ref()
+        .map(|name| quote! {ret.port_names.input = stringify!(#name).into();});
+    let output_port = port_names
+        .output
+        .as_ref()
+        .map(|name| quote! {ret.port_names.output = stringify!(#name).into();});
+    let doc = doc.map(|doc| quote! {ret.doc = Some(#doc.into());});
+    let clock_domains = clocks.iter().map(|clock| {
+        quote! {
+            ret = ret.with_clock_domain(
+                stringify!(#clock),
+                rhdl_core::path::Path::default().field(stringify!(#clock)),
+            );
+        }
+    });
     quote! {
         fn descriptor(&self) -> rhdl_core::CircuitDescriptor {
             let mut ret = rhdl_core::root_descriptor(self);
-            #(ret.add_child(stringify!(#component_name), &self.#component_name);)*
+            #doc
+            #named_child_wires
+            #input_port
+            #output_port
+            #(#clock_domains)*
+            #(#add_child)*
             ret
         }
     }
 }
 
 fn define_hdl_fn(field_set: &FieldSet) -> TokenStream {
-    let component_name = &field_set.component_name;
+    let add_child = field_set
+        .component_name
+        .iter()
+        .zip(&field_set.component_cfg)
+        .map(|(name, cfg)| {
+            let add_child = quote! {ret.add_child(stringify!(#name), &self.#name, kind)?;};
+            match cfg {
+                // An unknown flag is an error here, as it is in a kernel
+                Some(flag) => quote! {if rhdl_core::known_compile_flag(#flag)? { #add_child }},
+                None => add_child,
+            }
+        });
+    // The flags are checked before anything else, since the kinds of the
+    // D and Q depend on them
+    let flags = field_set
+        .component_cfg
+        .iter()
+        .flatten()
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter();
     quote! {
         fn as_hdl(&self, kind: rhdl_core::HDLKind) -> anyhow::Result<rhdl_core::HDLDescriptor> {
+            #(rhdl_core::known_compile_flag(#flags)?;)*
             let mut ret = rhdl_core::root_hdl(self, kind)?;
-            #(ret.add_child(stringify!(#component_name), &self.#component_name, kind)?;)*
+            #(#add_child)*
             Ok(ret)
         }
     }
 }
 
 fn define_sim_fn(field_set: &FieldSet) -> TokenStream {
-    let component_name = &field_set.component_name;
-    let component_index = (1..=component_name.len())
-        .map(syn::Index::from)
-        .collect::<Vec<_>>();
+    let sim_child = field_set
+        .component_name
+        .iter()
+        .zip(&field_set.component_cfg)
+        .enumerate()
+        .map(|(ndx, (name, cfg))| {
+            let index = syn::Index::from(ndx + 1);
+            guard_component(
+                cfg,
+                quote! {
+                    rhdl_core::note_push_path(stringify!(#name));
+                    state.0.#name =
+                    self.#name.sim(internal_inputs.#name, &mut state.#index, &mut io.#name);
+                    rhdl_core::note_pop_path();
+                },
+            )
+        });
     quote! {
         fn sim(&self, input: <Self as CircuitIO>::I, state: &mut Self::S, io: &mut Self::Z) -> <Self as CircuitIO>::O {
             rhdl_core::note("input", input);
             for _ in 0..rhdl_core::MAX_ITERS {
                 let prev_state = state.clone();
                 let (outputs, internal_inputs) = Self::UPDATE(input, state.0);
-                #(
-                    rhdl_core::note_push_path(stringify!(#component_name));
-                    state.0.#component_name =
-                    self.#component_name.sim(internal_inputs.#component_name, &mut state.#component_index, &mut io.#component_name);
-                    rhdl_core::note_pop_path();
-                )*
+                #(#sim_child)*
                 if state == &prev_state {
                     rhdl_core::note("outputs", outputs);
                     return outputs;
@@ -92,32 +222,32 @@ fn define_sim_fn(field_set: &FieldSet) -> TokenStream {
     }
 }
 
+// The update kernel is given with `#[rhdl(update = name)]` (or the older
+// `#[rhdl(kernel = name)]`).  If it is omitted, the kernel is assumed to
+// be a `#[kernel]` function named `update` in scope of the circuit, with
+// the generics of the circuit.  That is either a free function, or a
+// method of the circuit declared in a `#[kernel] impl` block.
 fn extract_kernel_name_from_attributes(attrs: &[Attribute]) -> syn::Result<Option<ExprPath>> {
+    const USAGE: &str = "Expected rhdl attribute to be of the form #[rhdl(update = name)]";
     for attr in attrs {
+        if is_named_child_wires_attribute(attr)
+            || port_name_attribute(attr).is_some()
+            || clock_attribute(attr).is_some()
+        {
+            continue;
+        }
         if attr.path().is_ident("rhdl") {
             let Expr::Assign(assign) = attr.parse_args::<Expr>()? else {
-                return Err(syn::Error::new(
-                    attr.span(),
-                    "Expected rhdl attribute to be of the form #[rhdl(kernel = name)]",
-                ));
+                return Err(syn::Error::new(attr.span(), USAGE));
             };
             let Expr::Path(path) = *assign.left else {
-                return Err(syn::Error::new(
-                    assign.left.span(),
-                    "Expected rhdl attribute to be of the form #[rhdl(kernel = name)]",
-                ));
+                return Err(syn::Error::new(assign.left.span(), USAGE));
             };
-            if !path.path.is_ident("kernel") {
-                return Err(syn::Error::new(
-                    path.span(),
-                    "Expected rhdl attribute to be of the form #[rhdl(kernel = name)]",
-                ));
+            if !path.path.is_ident("update") && !path.path.is_ident("kernel") {
+                return Err(syn::Error::new(path.span(), USAGE));
             }
             let Expr::Path(expr_path) = *assign.right else {
-                return Err(syn::Error::new(
-                    assign.right.span(),
-                    "Expected rhdl attribute to be of the form #[rhdl(kernel = name)]",
-                ));
+                return Err(syn::Error::new(assign.right.span(), USAGE));
             };
             return Ok(Some(expr_path));
         }
@@ -125,9 +255,94 @@ fn extract_kernel_name_from_attributes(attrs: &[Attribute]) -> syn::Result<Optio
     Ok(None)
 }
 
+// `#[rhdl(named_child_wires)]` connects the children of the circuit
+// through named wires in the generated Verilog.
+fn is_named_child_wires_attribute(attr: &Attribute) -> bool {
+    attr.path().is_ident("rhdl")
+        && attr
+            .parse_args::<syn::Ident>()
+            .is_ok_and(|ident| ident == "named_child_wires")
+}
+
+// `#[rhdl(input_port = name)]` and `#[rhdl(output_port = name)]` rename
+// the ports of the generated module (which are `i` and `o` otherwise).
+#[derive(Default)]
+struct PortNames {
+    input: Option<syn::Ident>,
+    output: Option<syn::Ident>,
+}
+
+fn port_name_attribute(attr: &Attribute) -> Option<(syn::Ident, syn::Ident)> {
+    if !attr.path().is_ident("rhdl") {
+        return None;
+    }
+    let Ok(Expr::Assign(assign)) = attr.parse_args::<Expr>() else {
+        return None;
+    };
+    let (Expr::Path(key), Expr::Path(name)) = (*assign.left, *assign.right) else {
+        return None;
+    };
+    let key = key.path.get_ident()?;
+    if key != "input_port" && key != "output_port" {
+        return None;
+    }
+    Some((key.clone(), name.path.get_ident()?.clone()))
+}
+
+fn extract_port_names(attrs: &[Attribute]) -> PortNames {
+    let mut names = PortNames::default();
+    for (key, name) in attrs.iter().filter_map(port_name_attribute) {
+        if key == "input_port" {
+            names.input = Some(name);
+        } else {
+            names.output = Some(name);
+        }
+    }
+    names
+}
+
+// `#[rhdl(clock = field)]` marks a field of the input as a clock, so
+// that a clocked testbench can drive it (see `ClockDomainDescriptor`).
+// It may be given once for each clock.
+fn clock_attribute(attr: &Attribute) -> Option<syn::Ident> {
+    if !attr.path().is_ident("rhdl") {
+        return None;
+    }
+    let Ok(Expr::Assign(assign)) = attr.parse_args::<Expr>() else {
+        return None;
+    };
+    let (Expr::Path(key), Expr::Path(field)) = (*assign.left, *assign.right) else {
+        return None;
+    };
+    if !key.path.is_ident("clock") {
+        return None;
+    }
+    field.path.get_ident().cloned()
+}
+
 fn derive_circuit_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
     let struct_name = &decl.ident;
-    let kernel_name = extract_kernel_name_from_attributes(&decl.attrs)?;
+    let kernel_name = match extract_kernel_name_from_attributes(&decl.attrs)? {
+        Some(kernel_name) => kernel_name,
+        None => {
+            let update = syn::Ident::new("update", decl.ident.span());
+            let params = decl
+                .generics
+                .params
+                .iter()
+                .filter_map(|param| match param {
+                    syn::GenericParam::Type(ty) => Some(&ty.ident),
+                    syn::GenericParam::Const(konst) => Some(&konst.ident),
+                    syn::GenericParam::Lifetime(_) => None,
+                })
+                .collect::<Vec<_>>();
+            if params.is_empty() {
+                syn::parse_quote!(#update)
+            } else {
+                syn::parse_quote!(#update::<#(#params),*>)
+            }
+        }
+    };
     let (impl_generics, ty_generics, where_clause) = decl.generics.split_for_impl();
     let Data::Struct(s) = &decl.data else {
         return Err(syn::Error::new(
@@ -138,6 +353,7 @@ fn derive_circuit_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
     let field_set = FieldSet::try_from(&s.fields)?;
     let component_ty = &field_set.component_ty;
     let component_name = &field_set.component_name;
+    let component_timing = &field_set.component_timing;
     let generics = &decl.generics;
     // Create a new struct by appending a Q to the name of the struct, and for each field, map
     // the type to <ty as rhdl_core::Circuit>::O,
@@ -145,7 +361,7 @@ fn derive_circuit_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
     let new_struct_q = quote! {
         #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
         pub struct #name_q #generics #where_clause {
-            #(#component_name: <#component_ty as rhdl_core::CircuitIO>::O),*
+            #(#(#component_timing)* #component_name: <#component_ty as rhdl_core::CircuitIO>::O),*
         }
     };
     // Repeat with D and ::I
@@ -153,7 +369,7 @@ fn derive_circuit_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
     let new_struct_d = quote! {
         #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
         pub struct #name_d #generics #where_clause {
-            #(#component_name: <#component_ty as rhdl_core::CircuitIO>::I),*
+            #(#(#component_timing)* #component_name: <#component_ty as rhdl_core::CircuitIO>::I),*
         }
     };
     // Repeat again with Z and ::Z
@@ -196,7 +412,21 @@ fn derive_circuit_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
     // Add a tuple of the states of the components
     let state_tuple = quote!((Self::Q, #(<#component_ty as rhdl_core::Circuit>::S),*));
     let init_state_fn = define_init_state_fn(&field_set);
-    let descriptor_fn = define_descriptor_fn(&field_set);
+    let state_signals_fn = define_state_signals_fn(&field_set);
+    let named_child_wires = decl.attrs.iter().any(is_named_child_wires_attribute);
+    let port_names = extract_port_names(&decl.attrs);
+    let clocks = decl
+        .attrs
+        .iter()
+        .filter_map(clock_attribute)
+        .collect::<Vec<_>>();
+    let descriptor_fn = define_descriptor_fn(
+        &field_set,
+        named_child_wires,
+        &port_names,
+        &clocks,
+        crate::utils::doc_comment(&decl.attrs),
+    );
     let hdl_fn = define_hdl_fn(&field_set);
     let sim_fn = define_sim_fn(&field_set);
     let name_fn = quote!(
@@ -204,6 +434,21 @@ fn derive_circuit_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
             stringify!(#struct_name)
         }
     );
+    // Call the kernel through `run_update_kernel`, with the types of the
+    // circuit spelled out, so that a kernel with the wrong signature is
+    // reported (at the attribute) as not being an `UpdateKernel`, rather
+    // than as a type mismatch in generated code.
+    let check_kernel = quote_spanned! {kernel_name.span()=>
+        |input, q| {
+            let kernel: fn(_, _) -> _ = #kernel_name;
+            rhdl_core::circuit::circuit_impl::run_update_kernel::<
+                <Self as rhdl_core::CircuitIO>::I,
+                Self::Q,
+                <Self as rhdl_core::CircuitIO>::O,
+                Self::D,
+            >(kernel, input, q)
+        }
+    };
     let circuit_impl = quote! {
         impl #impl_generics rhdl_core::Circuit for #struct_name #ty_generics #where_clause {
             type Q = #name_q #ty_generics;
@@ -213,10 +458,12 @@ fn derive_circuit_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
 
             type Update = #kernel_name;
 
-            const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = #kernel_name;
+            const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = #check_kernel;
 
             #init_state_fn
 
+            #state_signals_fn
+
             #name_fn
 
             #descriptor_fn
@@ -249,6 +496,7 @@ mod test {
             #[rhdl(kernel = pushd::<N>)]
             pub struct Strobe<const N: usize> {
                 strobe: DFF<Bits<N>>,
+                #[rhdl(false_path)]
                 value: Constant<Bits<N>>,
             }
         );
@@ -257,11 +505,13 @@ mod test {
             #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
             pub struct StrobeQ<const N: usize> {
                 strobe: <DFF<Bits<N>> as rhdl_core::CircuitIO>::O,
+                #[rhdl(false_path)]
                 value: <Constant<Bits<N>> as rhdl_core::CircuitIO>::O,
             }
             #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
             pub struct StrobeD<const N: usize> {
                 strobe: <DFF<Bits<N>> as rhdl_core::CircuitIO>::I,
+                #[rhdl(false_path)]
                 value: <Constant<Bits<N>> as rhdl_core::CircuitIO>::I,
             }
             #[derive(Debug, Clone, PartialEq, Default, Copy)]
@@ -294,7 +544,15 @@ mod test {
                     <Constant<Bits<N>> as rhdl_core::Circuit>::S,
                 );
                 type Update = pushd<N>;
-                const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = pushd::<N>;
+                const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |input, q| {
+                    let kernel: fn(_, _) -> _ = pushd::<N>;
+                    rhdl_core::circuit::circuit_impl::run_update_kernel::<
+                        <Self as rhdl_core::CircuitIO>::I,
+                        Self::Q,
+                        <Self as rhdl_core::CircuitIO>::O,
+                        Self::D,
+                    >(kernel, input, q)
+                };
                 fn init_state(&self) -> Self::S {
                     (
                         Default::default(),
@@ -302,6 +560,18 @@ mod test {
                         self.value.init_state(),
                     )
                 }
+                fn state_signals(&self, state: &Self::S) -> Vec<(String, rhdl_core::TypedBits)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::child_state_signals(
+                        stringify!(strobe),
+                        self.strobe.state_signals(&state.1),
+                    ));
+                    ret.extend(rhdl_core::child_state_signals(
+                        stringify!(value),
+                        self.value.state_signals(&state.2),
+                    ));
+                    ret
+                }
                 fn name(&self) -> &'static str {
                     stringify!(Strobe)
                 }
@@ -420,7 +690,15 @@ mod test {
                         <DFF<Bits<8>> as rhdl_core::Circuit>::S,
                     );
                     type Update = pushd;
-                    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = pushd;
+                    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |input, q| {
+                        let kernel: fn(_, _) -> _ = pushd;
+                        rhdl_core::circuit::circuit_impl::run_update_kernel::<
+                            <Self as rhdl_core::CircuitIO>::I,
+                            Self::Q,
+                            <Self as rhdl_core::CircuitIO>::O,
+                            Self::D,
+                        >(kernel, input, q)
+                    };
                 fn init_state(&self) -> Self::S {
                     (
                         Default::default(),
@@ -431,6 +709,18 @@ mod test {
                         self.latch.init_state(),
                     )
                 }
+                fn state_signals(
+                    &self,
+                    state: &Self::S,
+                ) -> Vec<(String, rhdl_core::TypedBits)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::child_state_signals(stringify!(strobe), self.strobe.state_signals(&state.1)));
+                    ret.extend(rhdl_core::child_state_signals(stringify!(value), self.value.state_signals(&state.2)));
+                    ret.extend(rhdl_core::child_state_signals(stringify!(buf_z), self.buf_z.state_signals(&state.3)));
+                    ret.extend(rhdl_core::child_state_signals(stringify!(side), self.side.state_signals(&state.4)));
+                    ret.extend(rhdl_core::child_state_signals(stringify!(latch), self.latch.state_signals(&state.5)));
+                    ret
+                }
                 fn name(&self) -> &'static str {
                     stringify!(Push)
                 }
@@ -509,4 +799,188 @@ mod test {
         );
         assert_tokens_eq(&expected, &output);
     }
+
+    #[test]
+    fn test_circuit_derive_defaults_to_update_kernel() {
+        let decl = quote!(
+            pub struct Blinky {
+                counter: Counter<8>,
+            }
+        );
+        let output = derive_circuit(decl).unwrap().to_string();
+        assert!(output.contains("type Update = update ;"));
+        assert!(output.contains("let kernel : fn (_ , _) -> _ = update ;"));
+    }
+
+    #[test]
+    fn test_circuit_derive_with_clock_domains() {
+        let decl = quote!(
+            #[rhdl(kernel = fifo)]
+            #[rhdl(clock = write_clock)]
+            #[rhdl(clock = read_clock)]
+            pub struct Fifo {
+                ram: DualPortRam<b8, 3>,
+            }
+        );
+        let output = derive_circuit(decl).unwrap().to_string();
+        let write = output.find("ret = ret . with_clock_domain (stringify ! (write_clock) , rhdl_core :: path :: Path :: default () . field (stringify ! (write_clock)) ,) ;").unwrap();
+        let read = output
+            .find("ret = ret . with_clock_domain (stringify ! (read_clock) ,")
+            .unwrap();
+        assert!(write < read);
+    }
+
+    #[test]
+    fn test_circuit_derive_rejects_unknown_attribute() {
+        let decl = quote!(
+            #[rhdl(updater = pushd)]
+            pub struct Push {
+                strobe: Strobe<32>,
+            }
+        );
+        let err = derive_circuit(decl).unwrap_err();
+        assert!(err.to_string().contains("#[rhdl(update = name)]"));
+    }
+
+    #[test]
+    fn test_conditional_child_circuit_derive() {
+        let decl = quote!(
+            #[rhdl(kernel = idle_counter)]
+            pub struct IdleCounter {
+                count: Counter<4>,
+                #[rhdl(cfg(flag = "debug_counters"))]
+                idle: Counter<4>,
+            }
+        );
+        let output = derive_circuit(decl).unwrap();
+        let expected = quote!(
+            #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+            pub struct IdleCounterQ {
+                count: <Counter<4> as rhdl_core::CircuitIO>::O,
+                #[rhdl(cfg(flag = "debug_counters"))]
+                idle: <Counter<4> as rhdl_core::CircuitIO>::O,
+            }
+            #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+            pub struct IdleCounterD {
+                count: <Counter<4> as rhdl_core::CircuitIO>::I,
+                #[rhdl(cfg(flag = "debug_counters"))]
+                idle: <Counter<4> as rhdl_core::CircuitIO>::I,
+            }
+            #[derive(Debug, Clone, PartialEq, Default, Copy)]
+            pub struct IdleCounterZ {
+                count: <Counter<4> as rhdl_core::Circuit>::Z,
+                idle: <Counter<4> as rhdl_core::Circuit>::Z,
+            }
+            impl rhdl_core::Notable for IdleCounterZ {
+                fn note(
+                    &self,
+                    key: impl rhdl_core::NoteKey,
+                    mut writer: impl rhdl_core::NoteWriter,
+                ) {
+                    self.count.note((key, stringify!(count)), &mut writer);
+                    self.idle.note((key, stringify!(idle)), &mut writer);
+                }
+            }
+            impl rhdl_core::Tristate for IdleCounterZ {
+                const N: usize = <Counter<4> as rhdl_core::Circuit>::Z::N
+                    + <Counter<4> as rhdl_core::Circuit>::Z::N + 0;
+            }
+            impl rhdl_core::Circuit for IdleCounter {
+                type Q = IdleCounterQ;
+                type D = IdleCounterD;
+                type Z = IdleCounterZ;
+                type S = (
+                    Self::Q,
+                    <Counter<4> as rhdl_core::Circuit>::S,
+                    <Counter<4> as rhdl_core::Circuit>::S,
+                );
+                type Update = idle_counter;
+                const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |input, q| {
+                    let kernel: fn(_, _) -> _ = idle_counter;
+                    rhdl_core::circuit::circuit_impl::run_update_kernel::<
+                        <Self as rhdl_core::CircuitIO>::I,
+                        Self::Q,
+                        <Self as rhdl_core::CircuitIO>::O,
+                        Self::D,
+                    >(kernel, input, q)
+                };
+                fn init_state(&self) -> Self::S {
+                    (Default::default(), self.count.init_state(), self.idle.init_state())
+                }
+                fn state_signals(&self, state: &Self::S) -> Vec<(String, rhdl_core::TypedBits)> {
+                    let mut ret = vec![];
+                    ret.extend(
+                        rhdl_core::child_state_signals(
+                            stringify!(count),
+                            self.count.state_signals(&state.1),
+                        ),
+                    );
+                    if rhdl_core::expect_compile_flag("debug_counters") {
+                        ret.extend(
+                            rhdl_core::child_state_signals(
+                                stringify!(idle),
+                                self.idle.state_signals(&state.2),
+                            ),
+                        );
+                    }
+                    ret
+                }
+                fn name(&self) -> &'static str {
+                    stringify!(IdleCounter)
+                }
+                fn descriptor(&self) -> rhdl_core::CircuitDescriptor {
+                    let mut ret = rhdl_core::root_descriptor(self);
+                    ret.add_child(stringify!(count), &self.count);
+                    ret.flags
+                        .insert("debug_counters".into(), rhdl_core::expect_compile_flag("debug_counters"));
+                    if rhdl_core::expect_compile_flag("debug_counters") {
+                        ret.add_child(stringify!(idle), &self.idle);
+                    }
+                    ret
+                }
+                fn as_hdl(
+                    &self,
+                    kind: rhdl_core::HDLKind,
+                ) -> anyhow::Result<rhdl_core::HDLDescriptor> {
+                    rhdl_core::known_compile_flag("debug_counters")?;
+                    let mut ret = rhdl_core::root_hdl(self, kind)?;
+                    ret.add_child(stringify!(count), &self.count, kind)?;
+                    if rhdl_core::known_compile_flag("debug_counters")? {
+                        ret.add_child(stringify!(idle), &self.idle, kind)?;
+                    }
+                    Ok(ret)
+                }
+                fn sim(
+                    &self,
+                    input: <Self as CircuitIO>::I,
+                    state: &mut Self::S,
+                    io: &mut Self::Z,
+                ) -> <Self as CircuitIO>::O {
+                    rhdl_core::note("input", input);
+                    for _ in 0..rhdl_core::MAX_ITERS {
+                        let prev_state = state.clone();
+                        let (outputs, internal_inputs) = Self::UPDATE(input, state.0);
+                        rhdl_core::note_push_path(stringify!(count));
+                        state.0.count = self
+                            .count
+                            .sim(internal_inputs.count, &mut state.1, &mut io.count);
+                        rhdl_core::note_pop_path();
+                        if rhdl_core::expect_compile_flag("debug_counters") {
+                            rhdl_core::note_push_path(stringify!(idle));
+                            state.0.idle = self
+                                .idle
+                                .sim(internal_inputs.idle, &mut state.2, &mut io.idle);
+                            rhdl_core::note_pop_path();
+                        }
+                        if state == &prev_state {
+                            rhdl_core::note("outputs", outputs);
+                            return outputs;
+                        }
+                    }
+                    panic!("Simulation did not converge");
+                }
+            }
+        );
+        assert_tokens_eq(&expected, &output);
+    }
 }
@@ -6,14 +6,25 @@ use crate::digital_enum::derive_digital_enum;
 
 pub fn derive_digital(input: TokenStream) -> syn::Result<TokenStream> {
     let decl = syn::parse2::<syn::DeriveInput>(input)?;
-    match &decl.data {
+    let compatible_with = crate::utils::derive_compatible_with(&decl)?;
+    let transparent_ops = crate::utils::derive_transparent_ops(&decl)?;
+    let layout_digest = crate::utils::derive_layout_digest(&decl)?;
+    let payload_accessors = crate::path_of::derive_payload_accessors(&decl);
+    let digital = match &decl.data {
         Data::Struct(_s) => derive_digital_struct(decl),
         Data::Enum(_e) => derive_digital_enum(decl),
         _ => Err(syn::Error::new(
             decl.span(),
             "Only structs and enums can be digital",
         )),
-    }
+    }?;
+    Ok(quote! {
+        #digital
+        #compatible_with
+        #transparent_ops
+        #layout_digest
+        #payload_accessors
+    })
 }
 
 fn derive_digital_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
@@ -30,12 +41,41 @@ fn derive_digital_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
     }
 }
 
+// The code that writes each field to the bits of the struct, and that
+// reads it back, which skips the fields that are configured out.
+fn field_bins_and_reads(
+    fields: &syn::Fields,
+    names: &[impl quote::ToTokens],
+) -> syn::Result<(Vec<TokenStream>, Vec<TokenStream>)> {
+    let mut bins = vec![];
+    let mut reads = vec![];
+    for (field, name) in fields.iter().zip(names) {
+        let ty = &field.ty;
+        bins.push(crate::utils::guard_field(
+            field,
+            quote!(result.extend(self.#name.bin());),
+            quote!(),
+        )?);
+        reads.push(crate::utils::guard_field(
+            field,
+            quote!(reader.read::<#ty>()?),
+            quote!(Default::default()),
+        )?);
+    }
+    Ok((bins, reads))
+}
+
 //  Add the module path to the name
 
 fn derive_digital_tuple_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
     let struct_name = &decl.ident;
     let fqdn = crate::utils::get_fqdn(&decl);
     let (impl_generics, ty_generics, where_clause) = decl.generics.split_for_impl();
+    let transparent_ops = if crate::utils::has_transparent_ops(&decl) {
+        quote!(.with_transparent_ops())
+    } else {
+        quote!()
+    };
     match decl.data {
         Data::Struct(s) => {
             let fields = s
@@ -44,7 +84,14 @@ fn derive_digital_tuple_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
                 .enumerate()
                 .map(|(ndx, _)| syn::Index::from(ndx))
                 .collect::<Vec<_>>();
-            let field_types = s.fields.iter().map(|x| &x.ty).collect::<Vec<_>>();
+            let field_kinds = s
+                .fields
+                .iter()
+                .map(crate::utils::field_kind)
+                .collect::<syn::Result<Vec<_>>>()?;
+            let timing_exceptions = crate::utils::timing_exceptions_fn(&s.fields, &fields)?;
+            let doc_comments = crate::utils::doc_comments_fn(&s.fields, &fields)?;
+            let (field_bins, field_reads) = field_bins_and_reads(&s.fields, &fields)?;
             Ok(quote! {
                 impl #impl_generics rhdl_core::Digital for #struct_name #ty_generics #where_clause {
                     fn static_kind() -> rhdl_core::Kind {
@@ -52,17 +99,28 @@ fn derive_digital_tuple_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
                             #fqdn,
                             vec![
                             #(
-                                rhdl_core::Kind::make_field(stringify!(#fields), <#field_types as rhdl_core::Digital>::static_kind()),
+                                rhdl_core::Kind::make_field(stringify!(#fields), #field_kinds),
                             )*
-                        ])
+                        ])#transparent_ops
                     }
                     fn bin(self) -> Vec<bool> {
                         let mut result = vec![];
                         #(
-                            result.extend(self.#fields.bin());
+                            #field_bins
                         )*
                         result
                     }
+                    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                        rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                        let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                        Ok(Self(
+                            #(
+                                #field_reads,
+                            )*
+                        ))
+                    }
+                    #timing_exceptions
+                    #doc_comments
                 }
                 impl #impl_generics rhdl_core::Notable for #struct_name #ty_generics #where_clause {
                     fn note(&self, key: impl rhdl_core::NoteKey, mut writer: impl rhdl_core::NoteWriter) {
@@ -93,7 +151,14 @@ fn derive_digital_named_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
                 .iter()
                 .map(|field| &field.ident)
                 .collect::<Vec<_>>();
-            let field_types = s.fields.iter().map(|x| &x.ty).collect::<Vec<_>>();
+            let field_kinds = s
+                .fields
+                .iter()
+                .map(crate::utils::field_kind)
+                .collect::<syn::Result<Vec<_>>>()?;
+            let timing_exceptions = crate::utils::timing_exceptions_fn(&s.fields, &fields)?;
+            let doc_comments = crate::utils::doc_comments_fn(&s.fields, &fields)?;
+            let (field_bins, field_reads) = field_bins_and_reads(&s.fields, &fields)?;
             Ok(quote! {
                 impl #impl_generics rhdl_core::Digital for #struct_name #ty_generics #where_clause {
                     fn static_kind() -> rhdl_core::Kind {
@@ -101,17 +166,28 @@ fn derive_digital_named_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
                             #fqdn,
                             vec![
                             #(
-                                rhdl_core::Kind::make_field(stringify!(#fields), <#field_types as rhdl_core::Digital>::static_kind()),
+                                rhdl_core::Kind::make_field(stringify!(#fields), #field_kinds),
                             )*
                         ])
                     }
                     fn bin(self) -> Vec<bool> {
                         let mut result = vec![];
                         #(
-                            result.extend(self.#fields.bin());
+                            #field_bins
                         )*
                         result
                     }
+                    fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                        rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                        let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                        Ok(Self {
+                            #(
+                                #fields: #field_reads,
+                            )*
+                        })
+                    }
+                    #timing_exceptions
+                    #doc_comments
                 }
 
                 impl #impl_generics rhdl_core::Notable for #struct_name #ty_generics #where_clause {
@@ -130,13 +206,14 @@ fn derive_digital_named_struct(decl: DeriveInput) -> syn::Result<TokenStream> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::utils::assert_tokens_eq;
+    use crate::utils::{assert_frag_eq, assert_tokens_eq};
 
     #[test]
     fn test_digital_proc_macro() {
         let decl = quote!(
             pub struct NestedBits {
                 nest_1: bool,
+                #[rhdl(multicycle = 2)]
                 nest_2: u8,
                 nest_3: TwoBits,
             }
@@ -160,6 +237,29 @@ mod test {
                     result.extend(self.nest_3.bin());
                     result
                 }
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                    let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                    Ok(Self {
+                        nest_1: reader.read::<bool>()?,
+                        nest_2: reader.read::<u8>()?,
+                        nest_3: reader.read::<TwoBits>()?,
+                    })
+                }
+                fn timing_exceptions() -> Vec<(rhdl_core::path::Path, rhdl_core::TimingException)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(nest_1), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<u8>(stringify!(nest_2), Some(rhdl_core::TimingException::Multicycle(2))));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<TwoBits>(stringify!(nest_3), None));
+                    ret
+                }
+                fn doc_comments() -> Vec<(rhdl_core::path::Path, String)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(nest_1), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<u8>(stringify!(nest_2), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<TwoBits>(stringify!(nest_3), None));
+                    ret
+                }
             }
             impl rhdl_core::Notable for NestedBits {
                 fn note(&self, key: impl rhdl_core::NoteKey, mut writer: impl rhdl_core::NoteWriter) {
@@ -200,6 +300,29 @@ mod test {
                     result.extend(self.read.bin());
                     result
                 }
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                    let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                    Ok(Self {
+                        input: reader.read::<u32>()?,
+                        write: reader.read::<bool>()?,
+                        read: reader.read::<bool>()?,
+                    })
+                }
+                fn timing_exceptions() -> Vec<(rhdl_core::path::Path, rhdl_core::TimingException)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<u32>(stringify!(input), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(write), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(read), None));
+                    ret
+                }
+                fn doc_comments() -> Vec<(rhdl_core::path::Path, String)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<u32>(stringify!(input), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(write), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(read), None));
+                    ret
+                }
             }
             impl rhdl_core::Notable for Inputs {
                 fn note(&self, key: impl rhdl_core::NoteKey, mut writer: impl rhdl_core::NoteWriter) {
@@ -212,6 +335,77 @@ mod test {
         assert_tokens_eq(&expected, &output);
     }
 
+    #[test]
+    fn test_digital_with_cfg_field() {
+        let decl = quote!(
+            pub struct Inputs {
+                pub input: u32,
+                #[rhdl(cfg(flag = "debug"))]
+                pub debug: u8,
+            }
+        );
+        let output = derive_digital(decl).unwrap();
+        let expected = quote! {
+            impl rhdl_core::Digital for Inputs {
+                fn static_kind() -> rhdl_core::Kind {
+                    rhdl_core::Kind::make_struct(
+                        concat!(module_path!(), "::", stringify!(Inputs)),
+                        vec![
+                        rhdl_core::Kind::make_field(stringify!(input), <u32 as rhdl_core::Digital>::static_kind()),
+                        rhdl_core::Kind::make_field(stringify!(debug), if rhdl_core::expect_compile_flag("debug") {
+                            <u8 as rhdl_core::Digital>::static_kind()
+                        } else {
+                            rhdl_core::Kind::Empty
+                        }),
+                    ])
+                }
+                fn bin(self) -> Vec<bool> {
+                    let mut result = vec![];
+                    result.extend(self.input.bin());
+                    if rhdl_core::expect_compile_flag("debug") {
+                        result.extend(self.debug.bin());
+                    }
+                    result
+                }
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                    let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                    Ok(Self {
+                        input: reader.read::<u32>()?,
+                        debug: if rhdl_core::expect_compile_flag("debug") {
+                            reader.read::<u8>()?
+                        } else {
+                            Default::default()
+                        },
+                    })
+                }
+                fn timing_exceptions() -> Vec<(rhdl_core::path::Path, rhdl_core::TimingException)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<u32>(stringify!(input), None));
+                    if rhdl_core::expect_compile_flag("debug") {
+                        ret.extend(rhdl_core::types::digital::field_timing_exceptions::<u8>(stringify!(debug), None));
+                    }
+                    ret
+                }
+                fn doc_comments() -> Vec<(rhdl_core::path::Path, String)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<u32>(stringify!(input), None));
+                    if rhdl_core::expect_compile_flag("debug") {
+                        ret.extend(rhdl_core::types::digital::field_doc_comments::<u8>(stringify!(debug), None));
+                    }
+                    ret
+                }
+            }
+            impl rhdl_core::Notable for Inputs {
+                fn note(&self, key: impl rhdl_core::NoteKey, mut writer: impl rhdl_core::NoteWriter) {
+                    rhdl_core::Notable::note(&self.input, (key, stringify!(input)), &mut writer);
+                    rhdl_core::Notable::note(&self.debug, (key, stringify!(debug)), &mut writer);
+                }
+            }
+        };
+        assert_tokens_eq(&expected, &output);
+    }
+
     #[test]
     fn test_struct_with_generics() {
         let decl = quote!(
@@ -243,6 +437,29 @@ mod test {
                     result.extend(self.read.bin());
                     result
                 }
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                    let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                    Ok(Self {
+                        input: reader.read::<T>()?,
+                        write: reader.read::<bool>()?,
+                        read: reader.read::<bool>()?,
+                    })
+                }
+                fn timing_exceptions() -> Vec<(rhdl_core::path::Path, rhdl_core::TimingException)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<T>(stringify!(input), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(write), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(read), None));
+                    ret
+                }
+                fn doc_comments() -> Vec<(rhdl_core::path::Path, String)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<T>(stringify!(input), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(write), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(read), None));
+                    ret
+                }
             }
             impl<T: Digital> rhdl_core::Notable for Inputs<T> {
                 fn note(&self, key: impl rhdl_core::NoteKey, mut writer: impl rhdl_core::NoteWriter) {
@@ -283,6 +500,29 @@ mod test {
                     result.extend(self.read.bin());
                     result
                 }
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                    let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                    Ok(Self {
+                        input: reader.read::<u32>()?,
+                        write: reader.read::<bool>()?,
+                        read: reader.read::<(bool, bool)>()?,
+                    })
+                }
+                fn timing_exceptions() -> Vec<(rhdl_core::path::Path, rhdl_core::TimingException)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<u32>(stringify!(input), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(write), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<(bool, bool)>(stringify!(read), None));
+                    ret
+                }
+                fn doc_comments() -> Vec<(rhdl_core::path::Path, String)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<u32>(stringify!(input), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(write), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<(bool, bool)>(stringify!(read), None));
+                    ret
+                }
             }
             impl rhdl_core::Notable for Inputs {
                 fn note(&self, key: impl rhdl_core::NoteKey, mut writer: impl rhdl_core::NoteWriter) {
@@ -319,6 +559,29 @@ mod test {
                     result.extend(self.2.bin());
                     result
                 }
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                    let mut reader = rhdl_core::types::digital::BinReader::new(bits);
+                    Ok(Self(
+                        reader.read::<u32>()?,
+                        reader.read::<bool>()?,
+                        reader.read::<bool>()?,
+                    ))
+                }
+                fn timing_exceptions() -> Vec<(rhdl_core::path::Path, rhdl_core::TimingException)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<u32>(stringify!(0), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(1), None));
+                    ret.extend(rhdl_core::types::digital::field_timing_exceptions::<bool>(stringify!(2), None));
+                    ret
+                }
+                fn doc_comments() -> Vec<(rhdl_core::path::Path, String)> {
+                    let mut ret = vec![];
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<u32>(stringify!(0), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(1), None));
+                    ret.extend(rhdl_core::types::digital::field_doc_comments::<bool>(stringify!(2), None));
+                    ret
+                }
             }
             impl rhdl_core::Notable for Inputs {
                 fn note(&self, key: impl rhdl_core::NoteKey, mut writer: impl rhdl_core::NoteWriter) {
@@ -335,4 +598,152 @@ mod test {
         };
         assert_tokens_eq(&expected, &output);
     }
+
+    #[test]
+    fn test_digital_compatible_with() {
+        let decl = quote!(
+            #[rhdl(compatible_with = "other::Header")]
+            pub struct Header {
+                pub tag: b4,
+                pub len: b4,
+            }
+        );
+        let decl = syn::parse2::<syn::DeriveInput>(decl).unwrap();
+        let output = crate::utils::derive_compatible_with(&decl).unwrap();
+        let expected = quote! {
+            #[cfg(test)]
+            #[allow(non_snake_case)]
+            mod __rhdl_compatible_with_Header {
+                #[allow(unused_imports)]
+                use super::*;
+
+                #[test]
+                fn check_layouts() {
+                    rhdl_core::types::convert::check_compatible(
+                        &<Header as rhdl_core::Digital>::static_kind(),
+                        &<other::Header as rhdl_core::Digital>::static_kind(),
+                        rhdl_core::types::convert::Compatibility::Layout,
+                    )
+                    .unwrap_or_else(|err| panic!("{err}"));
+                }
+            }
+            impl From<other::Header> for Header {
+                fn from(value: other::Header) -> Self {
+                    rhdl_core::types::convert::convert(value).unwrap_or_else(|err| panic!("{err}"))
+                }
+            }
+            impl From<Header> for other::Header {
+                fn from(value: Header) -> Self {
+                    rhdl_core::types::convert::convert(value).unwrap_or_else(|err| panic!("{err}"))
+                }
+            }
+        };
+        assert_tokens_eq(&expected, &output);
+    }
+
+    #[test]
+    fn test_digital_with_transparent_ops() {
+        let decl = quote!(
+            #[rhdl(transparent_ops)]
+            pub struct Volts(pub b12);
+        );
+        let output = derive_digital(decl).unwrap().to_string();
+        assert!(output.contains(&quote!(.with_transparent_ops()).to_string()));
+        assert!(output.contains(&quote!(Self(self.0 + rhs.0)).to_string()));
+        assert!(output.contains(&quote!(self.0 ^= rhs.0;).to_string()));
+        let decl = quote!(
+            #[rhdl(transparent_ops)]
+            pub struct Pair(pub b4, pub b4);
+        );
+        let err = derive_digital(decl).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Only a tuple struct with a single field"));
+    }
+
+    #[test]
+    fn test_digital_with_ranged_field() {
+        let decl = quote!(
+            pub struct Digit {
+                #[rhdl(range(0, 9))]
+                pub value: b4,
+                pub carry: bool,
+            }
+        );
+        let decl = syn::parse2::<syn::DeriveInput>(decl).unwrap();
+        let Data::Struct(s) = &decl.data else {
+            panic!("Expected a struct");
+        };
+        let kinds = s
+            .fields
+            .iter()
+            .map(crate::utils::field_kind)
+            .collect::<syn::Result<Vec<_>>>()
+            .unwrap();
+        assert_frag_eq(
+            &quote!(<b4 as rhdl_core::Digital>::static_kind().with_range(0, 9)),
+            &kinds[0],
+        );
+        assert_frag_eq(
+            &quote!(<bool as rhdl_core::Digital>::static_kind()),
+            &kinds[1],
+        );
+    }
+
+    #[test]
+    fn test_digital_rejects_short_multicycle() {
+        let decl = quote!(
+            pub struct Config {
+                #[rhdl(multicycle = 1)]
+                pub divider: b8,
+            }
+        );
+        let err = derive_digital(decl).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("A multicycle path must take at least 2 cycles"));
+    }
+
+    #[test]
+    fn test_digital_with_layout_digest() {
+        let decl = quote!(
+            #[rhdl(layout_digest = "0123456789abcdef")]
+            pub struct Header {
+                pub version: b4,
+                pub flags: b4,
+            }
+        );
+        let decl = syn::parse2::<syn::DeriveInput>(decl).unwrap();
+        let output = crate::utils::derive_layout_digest(&decl).unwrap();
+        let expected = quote! {
+            #[cfg(test)]
+            #[test]
+            #[allow(non_snake_case)]
+            fn __rhdl_layout_digest_Header_0123456789abcdef() {
+                rhdl_core::types::digital::check_layout_digest::<Header>("0123456789abcdef")
+                    .unwrap_or_else(|err| panic!("{err}"));
+            }
+        };
+        assert_tokens_eq(&expected, &output);
+        let decl = quote!(
+            #[rhdl(layout_digest = "0123456789abcdef")]
+            pub struct Wrapper<T: Digital> {
+                pub value: T,
+            }
+        );
+        assert!(derive_digital(decl)
+            .unwrap_err()
+            .to_string()
+            .contains("A generic type cannot have a layout digest"));
+        let decl = quote!(
+            #[rhdl(layout_digest = "not-a-digest")]
+            pub struct Header {
+                pub version: b4,
+            }
+        );
+        assert!(derive_digital(decl)
+            .unwrap_err()
+            .to_string()
+            .contains("Expected the digest as 16 lowercase hex digits"));
+    }
 }
@@ -38,6 +38,18 @@ enum DiscriminantAlignment {
     Msb,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PayloadAlignment {
+    Lsb,
+    Msb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiscriminantEncoding {
+    Binary,
+    OneHot,
+}
+
 fn override_width(
     width: DiscriminantType,
     new_width: Option<(usize, Span)>,
@@ -100,6 +112,70 @@ fn parse_discriminant_alignment_attribute(
     Ok(None)
 }
 
+// The payload alignment can be given for the whole enum, and overridden
+// for individual variants, using `#[rhdl(payload_align = "lsb"|"msb")]`.
+fn parse_payload_alignment_attribute(attrs: &[Attribute]) -> syn::Result<Option<PayloadAlignment>> {
+    for attr in attrs {
+        if attr.path().is_ident("rhdl") {
+            if let Ok(Expr::Assign(assign)) = attr.parse_args::<Expr>() {
+                if let Expr::Path(path) = *assign.left {
+                    if path.path.is_ident("payload_align") {
+                        if let Expr::Lit(ExprLit {
+                            lit: Lit::Str(value),
+                            ..
+                        }) = *assign.right
+                        {
+                            if value.value() == "lsb" {
+                                return Ok(Some(PayloadAlignment::Lsb));
+                            } else if value.value() == "msb" {
+                                return Ok(Some(PayloadAlignment::Msb));
+                            } else {
+                                return Err(syn::Error::new(
+                                    value.span(),
+                                    "Unknown payload alignment value (expected either lsb or msb)",
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn parse_discriminant_encoding_attribute(
+    attrs: &[Attribute],
+) -> syn::Result<Option<DiscriminantEncoding>> {
+    for attr in attrs {
+        if attr.path().is_ident("rhdl") {
+            if let Ok(Expr::Assign(assign)) = attr.parse_args::<Expr>() {
+                if let Expr::Path(path) = *assign.left {
+                    if path.path.is_ident("encoding") {
+                        if let Expr::Lit(ExprLit {
+                            lit: Lit::Str(value),
+                            ..
+                        }) = *assign.right
+                        {
+                            if value.value() == "binary" {
+                                return Ok(Some(DiscriminantEncoding::Binary));
+                            } else if value.value() == "one_hot" {
+                                return Ok(Some(DiscriminantEncoding::OneHot));
+                            } else {
+                                return Err(syn::Error::new(
+                                    value.span(),
+                                    "Unknown encoding value (expected either binary or one_hot)",
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
 fn parse_discriminant_width_attribute(attrs: &[Attribute]) -> syn::Result<Option<(usize, Span)>> {
     for attr in attrs {
         if attr.path().is_ident("rhdl") {
@@ -121,6 +197,78 @@ fn parse_discriminant_width_attribute(attrs: &[Attribute]) -> syn::Result<Option
     Ok(None)
 }
 
+fn is_default_variant(variant: &Variant) -> bool {
+    variant.attrs.iter().any(|attr| {
+        attr.path().is_ident("rhdl")
+            && attr
+                .parse_args::<Ident>()
+                .is_ok_and(|ident| ident == "default")
+    })
+}
+
+// The default variant is the one marked with `#[rhdl(default)]`, or if
+// none is marked, the one with a discriminant of zero.  If neither exists,
+// there is no sensible default (an all zero value would not be valid),
+// so the user must pick one.
+fn default_variant_index(
+    enum_name: &Ident,
+    variants: &[&Variant],
+    discriminants: &[i64],
+) -> syn::Result<usize> {
+    let marked = variants
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| is_default_variant(v))
+        .collect::<Vec<_>>();
+    match marked.as_slice() {
+        [(ndx, _)] => Ok(*ndx),
+        [] => discriminants.iter().position(|x| *x == 0).ok_or_else(|| {
+            syn::Error::new(
+                enum_name.span(),
+                format!("Enum {enum_name} has no variant with a discriminant of zero, so one variant must be marked with #[rhdl(default)]"),
+            )
+        }),
+        [_, (_, second), ..] => Err(syn::Error::new(
+            second.span(),
+            "Only one variant can be marked with #[rhdl(default)]",
+        )),
+    }
+}
+
+// Generate the `Default` impl for the enum, filling any payload of the
+// default variant with default values.
+fn default_impl(decl: &DeriveInput, variant: &Variant) -> TokenStream {
+    let enum_name = &decl.ident;
+    let variant_name = &variant.ident;
+    let mut generics = decl.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for field in &variant.fields {
+        let ty = &field.ty;
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: Default));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let value = match &variant.fields {
+        syn::Fields::Unit => quote! { Self::#variant_name },
+        syn::Fields::Unnamed(fields) => {
+            let defaults = fields.unnamed.iter().map(|_| quote! { Default::default() });
+            quote! { Self::#variant_name(#(#defaults),*) }
+        }
+        syn::Fields::Named(fields) => {
+            let field_names = fields.named.iter().map(|f| &f.ident);
+            quote! { Self::#variant_name { #(#field_names: Default::default()),* } }
+        }
+    };
+    quote! {
+        impl #impl_generics Default for #enum_name #ty_generics #where_clause {
+            fn default() -> Self {
+                #value
+            }
+        }
+    }
+}
+
 fn discriminant_kind(discriminants: &[i64]) -> DiscriminantType {
     let min = discriminants.iter().min().unwrap();
     let max = discriminants.iter().max().unwrap();
@@ -140,6 +288,33 @@ fn discriminant_kind(discriminants: &[i64]) -> DiscriminantType {
     }
 }
 
+// With a one-hot encoding, each variant gets its own bit in the
+// discriminant, so the discriminant of the ith variant is 1 << i,
+// and the discriminant is as wide as the number of variants.
+fn one_hot_discriminants(
+    variants: &[&Variant],
+    discriminants: &[Option<i64>],
+    span: Span,
+) -> syn::Result<Vec<i64>> {
+    if let Some(variant) = variants
+        .iter()
+        .zip(discriminants)
+        .find_map(|(v, d)| d.map(|_| v))
+    {
+        return Err(syn::Error::new(
+            variant.span(),
+            "Explicit discriminants are not allowed with one_hot encoding",
+        ));
+    }
+    if variants.len() > 63 {
+        return Err(syn::Error::new(
+            span,
+            "One hot encoding is only supported for enums with at most 63 variants",
+        ));
+    }
+    Ok((0..variants.len()).map(|i| 1_i64 << i).collect())
+}
+
 fn evaluate_const_expression(expr: &syn::Expr) -> syn::Result<i64> {
     let expr_as_string = quote!(#expr).to_string();
     match evalexpr::eval_int(&expr_as_string) {
@@ -219,12 +394,12 @@ fn variant_payload_bin(
     let discriminant = match kind {
         DiscriminantType::Unsigned(x) => {
             quote! {
-                rhdl_bits::bits::<#x>(#discriminant as u128).to_bools()
+                rhdl_bits::bits::<#x>(#discriminant as u128).to_bools().to_vec()
             }
         }
         DiscriminantType::Signed(x) => {
             quote! {
-                rhdl_bits::signed::<#x>(#discriminant as i128).to_bools()
+                rhdl_bits::signed::<#x>(#discriminant as i128).to_bools().to_vec()
             }
         }
     };
@@ -259,6 +434,44 @@ fn variant_payload_bin(
     }
 }
 
+// Rebuild a variant from the payload bits of the enum, reading
+// its fields in order.
+fn variant_payload_from_bin(variant: &Variant, discriminant: i64) -> TokenStream {
+    let variant_name = &variant.ident;
+    let payload = quote! {
+        let payload = value.path(&rhdl_core::path::Path::default().payload_by_value(#discriminant))?;
+        let mut reader = rhdl_core::types::digital::BinReader::new(&payload.bits);
+    };
+    match &variant.fields {
+        syn::Fields::Unit => quote! {
+            Ok(Self::#variant_name)
+        },
+        syn::Fields::Unnamed(fields) => {
+            let field_types = fields.unnamed.iter().map(|f| &f.ty);
+            quote! {
+                #payload
+                Ok(Self::#variant_name(
+                    #(
+                        reader.read::<#field_types>()?,
+                    )*
+                ))
+            }
+        }
+        syn::Fields::Named(fields) => {
+            let field_names = fields.named.iter().map(|f| &f.ident);
+            let field_types = fields.named.iter().map(|f| &f.ty);
+            quote! {
+                #payload
+                Ok(Self::#variant_name {
+                    #(
+                        #field_names: reader.read::<#field_types>()?,
+                    )*
+                })
+            }
+        }
+    }
+}
+
 fn variant_note_case(variant: &Variant, kind: DiscriminantType, disc: &i64) -> TokenStream {
     let variant_name = &variant.ident;
     let discriminant = match kind {
@@ -350,7 +563,7 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
     let enum_name = &decl.ident;
     let fqdn = crate::utils::get_fqdn(&decl);
     let (impl_generics, ty_generics, where_clause) = decl.generics.split_for_impl();
-    let Data::Enum(e) = decl.data else {
+    let Data::Enum(e) = &decl.data else {
         return Err(syn::Error::new(decl.span(), "Only enums can be digital"));
     };
     let discriminant_alignment = match parse_discriminant_alignment_attribute(&decl.attrs)?
@@ -359,6 +572,24 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
         DiscriminantAlignment::Lsb => quote! { rhdl_core::DiscriminantAlignment::Lsb },
         DiscriminantAlignment::Msb => quote! { rhdl_core::DiscriminantAlignment::Msb },
     };
+    let payload_alignment = parse_payload_alignment_attribute(&decl.attrs)?;
+    let payload_alignments = e
+        .variants
+        .iter()
+        .map(|v| {
+            Ok(
+                match parse_payload_alignment_attribute(&v.attrs)?.or(payload_alignment) {
+                    Some(PayloadAlignment::Lsb) => {
+                        quote! { .with_payload_alignment(rhdl_core::PayloadAlignment::Lsb) }
+                    }
+                    Some(PayloadAlignment::Msb) => {
+                        quote! { .with_payload_alignment(rhdl_core::PayloadAlignment::Msb) }
+                    }
+                    None => quote! {},
+                },
+            )
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
     let variant_names = e.variants.iter().map(|x| &x.ident).collect::<Vec<_>>();
     let variant_destructure_args = e
         .variants
@@ -381,8 +612,26 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
         })
         .map(|x| x.transpose())
         .collect::<Result<Vec<_>, _>>()?;
-    let discriminants_values = allocate_discriminants(&discriminants);
-    let kind = discriminant_kind(&discriminants_values);
+    let encoding =
+        parse_discriminant_encoding_attribute(&decl.attrs)?.unwrap_or(DiscriminantEncoding::Binary);
+    let (discriminants_values, kind) = match encoding {
+        DiscriminantEncoding::Binary => {
+            let values = allocate_discriminants(&discriminants);
+            let kind = discriminant_kind(&values);
+            (values, kind)
+        }
+        DiscriminantEncoding::OneHot => {
+            let variants = e.variants.iter().collect::<Vec<_>>();
+            let values = one_hot_discriminants(&variants, &discriminants, enum_name.span())?;
+            (values, DiscriminantType::Unsigned(variants.len()))
+        }
+    };
+    let default_variant = {
+        let variants = e.variants.iter().collect::<Vec<_>>();
+        variants[default_variant_index(enum_name, &variants, &discriminants_values)?]
+    };
+    let default_variant_name = &default_variant.ident;
+    let default_fn = default_impl(&decl, default_variant);
     let width_override = parse_discriminant_width_attribute(&decl.attrs)?;
     let kind = override_width(kind, width_override)?;
     let note_fns = e
@@ -397,6 +646,12 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
         .iter()
         .zip(discriminants_values.iter())
         .map(|(variant, discriminant)| variant_payload_bin(variant, kind, *discriminant));
+    let from_bin_fns = e
+        .variants
+        .iter()
+        .zip(discriminants_values.iter())
+        .map(|(variant, discriminant)| variant_payload_from_bin(variant, *discriminant));
+    let from_bin_discriminants = discriminants_values.iter().map(|x| quote! { #x });
     let discriminants_as_typed_bits =
         make_discriminant_values_into_typed_bits(kind, &discriminants_values);
     let discriminant_ty = match kind {
@@ -410,7 +665,7 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
                     #fqdn,
                     vec![
                         #(
-                            rhdl_core::Kind::make_variant(stringify!(#variant_names), #kind_mapping, #discriminants)
+                            rhdl_core::Kind::make_variant(stringify!(#variant_names), #kind_mapping, #discriminants)#payload_alignments
                         ),*
                     ],
                     rhdl_core::Kind::make_discriminant_layout(
@@ -418,7 +673,7 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
                         #discriminant_alignment,
                         #discriminant_ty
                     )
-                )
+                ).with_default_variant(stringify!(#default_variant_name))
             }
             fn bin(self) -> Vec<bool> {
                 self.kind().pad(match self {
@@ -428,17 +683,31 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
                 })
 
             }
+            fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+                rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+                let value = rhdl_core::TypedBits {
+                    bits: bits.to_vec(),
+                    kind: <Self as rhdl_core::Digital>::static_kind(),
+                };
+                let discriminant = value.discriminant()?.as_i64()?;
+                match discriminant {
+                    #(
+                        #from_bin_discriminants => {#from_bin_fns}
+                    )*
+                    _ => anyhow::bail!("Invalid discriminant {} for enum {}", discriminant, stringify!(#enum_name)),
+                }
+            }
             fn discriminant(self) -> rhdl_core::TypedBits {
                 match self {
                     #(
-                        Self::#variant_names #variant_destructure_args => {#discriminants_as_typed_bits}
+                        Self::#variant_names { .. } => {#discriminants_as_typed_bits}
                     )*
                 }
             }
             fn variant_kind(self) -> rhdl_core::Kind {
                 match self {
                     #(
-                        Self::#variant_names #variant_destructure_args => {#variant_kind_mapping}
+                        Self::#variant_names { .. } => {#variant_kind_mapping}
                     )*
                 }
             }
@@ -452,6 +721,7 @@ pub fn derive_digital_enum(decl: DeriveInput) -> syn::Result<TokenStream> {
                 }
             }
         }
+        #default_fn
     })
 }
 
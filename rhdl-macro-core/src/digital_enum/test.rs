@@ -29,6 +29,7 @@ fn test_variant_destructure_args_function() {
 fn test_enum_derive() {
     let input: syn::DeriveInput = syn::parse_quote! {
         enum Test {
+            #[rhdl(default)]
             A = 1,
             B(Bits::<16>),
             C {a: Bits::<32>, b: Bits::<8>},
@@ -57,22 +58,22 @@ fn test_enum_derive() {
                         2usize,
                         rhdl_core::DiscriminantAlignment::Msb,
                         rhdl_core::DiscriminantType::Unsigned),
-                    )
+                    ).with_default_variant(stringify!(A))
                 }
                 fn bin(self) -> Vec<bool> {
                     self.kind()
                         .pad(
                             match self {
-                                Self::A => rhdl_bits::bits::<2usize>(1i64 as u128).to_bools(),
+                                Self::A => rhdl_bits::bits::<2usize>(1i64 as u128).to_bools().to_vec(),
                                 Self::B(_0) => {
                                     let mut v = rhdl_bits::bits::<2usize>(2i64 as u128)
-                                        .to_bools();
+                                        .to_bools().to_vec();
                                     v.extend(_0.bin());
                                     v
                                 }
                                 Self::C { a, b } => {
                                     let mut v = rhdl_bits::bits::<2usize>(3i64 as u128)
-                                        .to_bools();
+                                        .to_bools().to_vec();
                                     v.extend(a.bin());
                                     v.extend(b.bin());
                                     v
@@ -80,24 +81,46 @@ fn test_enum_derive() {
                             },
                         )
                 }
-                fn discriminant(self) -> rhdl_core::TypedBits {
+                fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+    let value = rhdl_core::TypedBits {
+        bits: bits.to_vec(),
+        kind: <Self as rhdl_core::Digital>::static_kind(),
+    };
+    let discriminant = value.discriminant()?.as_i64()?;
+    match discriminant {
+        1i64 => { Ok(Self::A) }
+        2i64 => {
+            let payload = value.path(&rhdl_core::path::Path::default().payload_by_value(2i64))?;
+            let mut reader = rhdl_core::types::digital::BinReader::new(&payload.bits);
+            Ok(Self::B(reader.read::<Bits::<16>>()?,))
+        }
+        3i64 => {
+            let payload = value.path(&rhdl_core::path::Path::default().payload_by_value(3i64))?;
+            let mut reader = rhdl_core::types::digital::BinReader::new(&payload.bits);
+            Ok(Self::C { a: reader.read::<Bits::<32>>()?, b: reader.read::<Bits::<8>>()?, })
+        }
+        _ => anyhow::bail!("Invalid discriminant {} for enum {}", discriminant, stringify!(Test)),
+    }
+}
+fn discriminant(self) -> rhdl_core::TypedBits {
                     match self {
-                        Self::A => rhdl_bits::bits::<2usize>(1i64 as u128).typed_bits(),
-                        Self::B(_0) => rhdl_bits::bits::<2usize>(2i64 as u128).typed_bits(),
-                        Self::C { a, b } => {
+                        Self::A { .. } => rhdl_bits::bits::<2usize>(1i64 as u128).typed_bits(),
+                        Self::B { .. } => rhdl_bits::bits::<2usize>(2i64 as u128).typed_bits(),
+                        Self::C { .. } => {
                             rhdl_bits::bits::<2usize>(3i64 as u128).typed_bits()
                         }
                     }
                 }
                 fn variant_kind(self) -> rhdl_core::Kind {
                     match self {
-                        Self::A => rhdl_core::Kind::Empty,
-                        Self::B(_0) => {
+                        Self::A { .. } => rhdl_core::Kind::Empty,
+                        Self::B { .. } => {
                             rhdl_core::Kind::make_tuple(
                                 vec![< Bits:: < 16 > as rhdl_core::Digital > ::static_kind()],
                             )
                         }
-                        Self::C { a, b } => {
+                        Self::C { .. } => {
                             rhdl_core::Kind::make_struct(
                                 stringify!(_Test__C),
                                 vec![
@@ -132,6 +155,11 @@ fn test_enum_derive() {
                     }
                 }
             }
+            impl Default for Test {
+                fn default() -> Self {
+                    Self::A
+                }
+            }
         },
     );
 }
@@ -165,46 +193,62 @@ fn test_enum_no_payloads() {
                         rhdl_core::DiscriminantAlignment::Msb,
                         rhdl_core::DiscriminantType::Unsigned,
                     ),
-                )
+                ).with_default_variant(stringify!(Init))
             }
             fn bin(self) -> Vec<bool> {
                 self.kind()
                     .pad(
                         match self {
                             Self::Init => {
-                                rhdl_bits::bits::<3usize>(0i64 as u128).to_bools()
+                                rhdl_bits::bits::<3usize>(0i64 as u128).to_bools().to_vec()
                             }
                             Self::Boot => {
-                                rhdl_bits::bits::<3usize>(1i64 as u128).to_bools()
+                                rhdl_bits::bits::<3usize>(1i64 as u128).to_bools().to_vec()
                             }
                             Self::Running => {
-                                rhdl_bits::bits::<3usize>(2i64 as u128).to_bools()
+                                rhdl_bits::bits::<3usize>(2i64 as u128).to_bools().to_vec()
                             }
                             Self::Stop => {
-                                rhdl_bits::bits::<3usize>(3i64 as u128).to_bools()
+                                rhdl_bits::bits::<3usize>(3i64 as u128).to_bools().to_vec()
                             }
                             Self::Boom => {
-                                rhdl_bits::bits::<3usize>(4i64 as u128).to_bools()
+                                rhdl_bits::bits::<3usize>(4i64 as u128).to_bools().to_vec()
                             }
                         },
                     )
             }
-            fn discriminant(self) -> rhdl_core::TypedBits {
+            fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+    let value = rhdl_core::TypedBits {
+        bits: bits.to_vec(),
+        kind: <Self as rhdl_core::Digital>::static_kind(),
+    };
+    let discriminant = value.discriminant()?.as_i64()?;
+    match discriminant {
+        0i64 => { Ok(Self::Init) }
+        1i64 => { Ok(Self::Boot) }
+        2i64 => { Ok(Self::Running) }
+        3i64 => { Ok(Self::Stop) }
+        4i64 => { Ok(Self::Boom) }
+        _ => anyhow::bail!("Invalid discriminant {} for enum {}", discriminant, stringify!(State)),
+    }
+}
+fn discriminant(self) -> rhdl_core::TypedBits {
                 match self {
-                    Self::Init => rhdl_bits::bits::<3usize>(0i64 as u128).typed_bits(),
-                    Self::Boot => rhdl_bits::bits::<3usize>(1i64 as u128).typed_bits(),
-                    Self::Running => rhdl_bits::bits::<3usize>(2i64 as u128).typed_bits(),
-                    Self::Stop => rhdl_bits::bits::<3usize>(3i64 as u128).typed_bits(),
-                    Self::Boom => rhdl_bits::bits::<3usize>(4i64 as u128).typed_bits(),
+                    Self::Init { .. } => rhdl_bits::bits::<3usize>(0i64 as u128).typed_bits(),
+                    Self::Boot { .. } => rhdl_bits::bits::<3usize>(1i64 as u128).typed_bits(),
+                    Self::Running { .. } => rhdl_bits::bits::<3usize>(2i64 as u128).typed_bits(),
+                    Self::Stop { .. } => rhdl_bits::bits::<3usize>(3i64 as u128).typed_bits(),
+                    Self::Boom { .. } => rhdl_bits::bits::<3usize>(4i64 as u128).typed_bits(),
                 }
             }
             fn variant_kind(self) -> rhdl_core::Kind {
                 match self {
-                    Self::Init => rhdl_core::Kind::Empty,
-                    Self::Boot => rhdl_core::Kind::Empty,
-                    Self::Running => rhdl_core::Kind::Empty,
-                    Self::Stop => rhdl_core::Kind::Empty,
-                    Self::Boom => rhdl_core::Kind::Empty,
+                    Self::Init { .. } => rhdl_core::Kind::Empty,
+                    Self::Boot { .. } => rhdl_core::Kind::Empty,
+                    Self::Running { .. } => rhdl_core::Kind::Empty,
+                    Self::Stop { .. } => rhdl_core::Kind::Empty,
+                    Self::Boom { .. } => rhdl_core::Kind::Empty,
                 }
             }
         }
@@ -234,6 +278,11 @@ fn test_enum_no_payloads() {
                 }
             }
         }
+        impl Default for State {
+            fn default() -> Self {
+                Self::Init
+            }
+        }
     };
     assert_tokens_eq(&expected, &output);
 }
@@ -242,6 +291,7 @@ fn test_enum_no_payloads() {
 fn test_enum_with_signed_discriminants() {
     let decl = quote! {
         enum Test {
+            #[rhdl(default)]
             A = 1,
             B = 3 + 6,
             C = -8,
@@ -262,36 +312,50 @@ fn test_enum_with_signed_discriminants() {
                     5usize,
                     rhdl_core::DiscriminantAlignment::Msb,
                     rhdl_core::DiscriminantType::Signed),
-                )
+                ).with_default_variant(stringify!(A))
             }
             fn bin(self) -> Vec<bool> {
                 self.kind()
                     .pad(
                         match self {
                             Self::A => {
-                                rhdl_bits::signed::<5usize>(1i64 as i128).to_bools()
+                                rhdl_bits::signed::<5usize>(1i64 as i128).to_bools().to_vec()
                             }
                             Self::B => {
-                                rhdl_bits::signed::<5usize>(9i64 as i128).to_bools()
+                                rhdl_bits::signed::<5usize>(9i64 as i128).to_bools().to_vec()
                             }
                             Self::C => {
-                                rhdl_bits::signed::<5usize>(-8i64 as i128).to_bools()
+                                rhdl_bits::signed::<5usize>(-8i64 as i128).to_bools().to_vec()
                             }
                         },
                     )
             }
-            fn discriminant(self) -> rhdl_core::TypedBits {
+            fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+    let value = rhdl_core::TypedBits {
+        bits: bits.to_vec(),
+        kind: <Self as rhdl_core::Digital>::static_kind(),
+    };
+    let discriminant = value.discriminant()?.as_i64()?;
+    match discriminant {
+        1i64 => { Ok(Self::A) }
+        9i64 => { Ok(Self::B) }
+        -8i64 => { Ok(Self::C) }
+        _ => anyhow::bail!("Invalid discriminant {} for enum {}", discriminant, stringify!(Test)),
+    }
+}
+fn discriminant(self) -> rhdl_core::TypedBits {
                 match self {
-                    Self::A => rhdl_bits::signed::<5usize>(1i128).typed_bits(),
-                    Self::B => rhdl_bits::signed::<5usize>(9i128).typed_bits(),
-                    Self::C => rhdl_bits::signed::<5usize>(-8i128).typed_bits(),
+                    Self::A { .. } => rhdl_bits::signed::<5usize>(1i128).typed_bits(),
+                    Self::B { .. } => rhdl_bits::signed::<5usize>(9i128).typed_bits(),
+                    Self::C { .. } => rhdl_bits::signed::<5usize>(-8i128).typed_bits(),
                 }
             }
             fn variant_kind(self) -> rhdl_core::Kind {
                 match self {
-                    Self::A => rhdl_core::Kind::Empty,
-                    Self::B => rhdl_core::Kind::Empty,
-                    Self::C => rhdl_core::Kind::Empty,
+                    Self::A { .. } => rhdl_core::Kind::Empty,
+                    Self::B { .. } => rhdl_core::Kind::Empty,
+                    Self::C { .. } => rhdl_core::Kind::Empty,
                 }
             }
         }
@@ -313,6 +377,11 @@ fn test_enum_with_signed_discriminants() {
                 }
             }
         }
+        impl Default for Test {
+            fn default() -> Self {
+                Self::A
+            }
+        }
     };
     assert_tokens_eq(&expected, &output);
 }
@@ -321,6 +390,7 @@ fn test_enum_with_signed_discriminants() {
 fn test_enum_with_discriminants() {
     let decl = quote! {
         enum Test {
+            #[rhdl(default)]
             A = 1,
             B = 7-1,
             C = 8,
@@ -335,36 +405,50 @@ fn test_enum_with_discriminants() {
                 rhdl_core::Kind::make_variant(stringify!(C), rhdl_core::Kind::Empty, 8i64)],
                 rhdl_core::Kind::make_discriminant_layout(
                 4usize, rhdl_core::DiscriminantAlignment::Msb, rhdl_core::DiscriminantType::Unsigned),
-                )
+                ).with_default_variant(stringify!(A))
             }
             fn bin(self) -> Vec<bool> {
                 self.kind()
                     .pad(
                         match self {
                             Self::A => {
-                                rhdl_bits::bits::<4usize>(1i64 as u128).to_bools()
+                                rhdl_bits::bits::<4usize>(1i64 as u128).to_bools().to_vec()
                             }
                             Self::B => {
-                                rhdl_bits::bits::<4usize>(6i64 as u128).to_bools()
+                                rhdl_bits::bits::<4usize>(6i64 as u128).to_bools().to_vec()
                             }
                             Self::C => {
-                                rhdl_bits::bits::<4usize>(8i64 as u128).to_bools()
+                                rhdl_bits::bits::<4usize>(8i64 as u128).to_bools().to_vec()
                             }
                         },
                     )
             }
-            fn discriminant(self) -> rhdl_core::TypedBits {
+            fn maybe_from_bin(bits: &[bool]) -> anyhow::Result<Self> {
+    rhdl_core::types::digital::check_bin_len::<Self>(bits)?;
+    let value = rhdl_core::TypedBits {
+        bits: bits.to_vec(),
+        kind: <Self as rhdl_core::Digital>::static_kind(),
+    };
+    let discriminant = value.discriminant()?.as_i64()?;
+    match discriminant {
+        1i64 => { Ok(Self::A) }
+        6i64 => { Ok(Self::B) }
+        8i64 => { Ok(Self::C) }
+        _ => anyhow::bail!("Invalid discriminant {} for enum {}", discriminant, stringify!(Test)),
+    }
+}
+fn discriminant(self) -> rhdl_core::TypedBits {
                 match self {
-                    Self::A => rhdl_bits::bits::<4usize>(1i64 as u128).typed_bits(),
-                    Self::B => rhdl_bits::bits::<4usize>(6i64 as u128).typed_bits(),
-                    Self::C => rhdl_bits::bits::<4usize>(8i64 as u128).typed_bits(),
+                    Self::A { .. } => rhdl_bits::bits::<4usize>(1i64 as u128).typed_bits(),
+                    Self::B { .. } => rhdl_bits::bits::<4usize>(6i64 as u128).typed_bits(),
+                    Self::C { .. } => rhdl_bits::bits::<4usize>(8i64 as u128).typed_bits(),
                 }
             }
             fn variant_kind(self) -> rhdl_core::Kind {
                 match self {
-                    Self::A => rhdl_core::Kind::Empty,
-                    Self::B => rhdl_core::Kind::Empty,
-                    Self::C => rhdl_core::Kind::Empty,
+                    Self::A { .. } => rhdl_core::Kind::Empty,
+                    Self::B { .. } => rhdl_core::Kind::Empty,
+                    Self::C { .. } => rhdl_core::Kind::Empty,
                 }
             }
         }
@@ -386,6 +470,11 @@ fn test_enum_with_discriminants() {
                 }
             }
         }
+        impl Default for Test {
+            fn default() -> Self {
+                Self::A
+            }
+        }
     };
     assert_tokens_eq(&expected, &output);
 }
@@ -422,6 +511,7 @@ fn test_parse_attributes() {
         #[derive(Digital)]
         #[rhdl(discriminant_align = "msb")]
         enum Test {
+            #[rhdl(default)]
             Start = 1,
             Stop = 2,
             Boom = 3,
@@ -434,6 +524,7 @@ fn test_parse_attributes() {
         #[derive(Digital)]
         #[rhdl(discriminant_align = "lsb")]
         enum Test {
+            #[rhdl(default)]
             Start = 1,
             Stop = 2,
             Boom = 3,
@@ -450,6 +541,7 @@ fn test_width_override() {
         #[derive(Digital)]
         #[rhdl(discriminant_width = 8)]
         enum Test {
+            #[rhdl(default)]
             Start = 1,
             Stop = 2,
             Boom = 3
@@ -459,3 +551,53 @@ fn test_width_override() {
     let digital = derive_digital_enum(input).unwrap();
     assert!(digital.to_string().contains("8usize"));
 }
+
+#[test]
+fn test_default_variant_selection() {
+    let decl = quote! {
+        enum Test {
+            A = 1,
+            B(Bits::<4>, bool) = 0,
+        }
+    };
+    let output = derive_digital_enum(syn::parse2(decl).unwrap()).unwrap();
+    assert!(output
+        .to_string()
+        .contains(&quote!(with_default_variant(stringify!(B))).to_string()));
+    assert!(output.to_string().contains(
+        &quote! {
+            impl Default for Test
+            where
+                Bits::<4>: Default,
+                bool: Default
+            {
+                fn default() -> Self {
+                    Self::B(Default::default(), Default::default())
+                }
+            }
+        }
+        .to_string()
+    ));
+    let decl = quote! {
+        enum Test {
+            A = 1,
+            B = 2,
+        }
+    };
+    let err = derive_digital_enum(syn::parse2(decl).unwrap()).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("must be marked with #[rhdl(default)]"));
+    let decl = quote! {
+        enum Test {
+            #[rhdl(default)]
+            A,
+            #[rhdl(default)]
+            B,
+        }
+    };
+    let err = derive_digital_enum(syn::parse2(decl).unwrap()).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Only one variant can be marked with #[rhdl(default)]"));
+}
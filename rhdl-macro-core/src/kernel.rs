@@ -3,8 +3,12 @@ use std::{collections::HashSet, iter::repeat};
 use inflections::Inflect;
 use quote::{format_ident, quote, quote_spanned};
 use syn::{
-    punctuated::Punctuated, spanned::Spanned, token::Comma, FnArg, Ident, Pat, PatType, Path, Type,
+    parse::Parser, punctuated::Punctuated, spanned::Spanned, token::Comma, visit_mut::VisitMut,
+    Expr, ExprLit, FnArg, Ident, Lit, Meta, MetaNameValue, Pat, PatType, Path, Type,
 };
+
+use crate::utils::cfg_flag;
+
 type TS = proc_macro2::TokenStream;
 type Result<T> = syn::Result<T>;
 
@@ -35,6 +39,9 @@ struct Scope {
 pub struct Context {
     scopes: Vec<Scope>,
     active_scope: ScopeId,
+    pure: bool,
+    const_eval: bool,
+    mux_strategy: Option<TS>,
 }
 
 impl Default for Context {
@@ -42,6 +49,9 @@ impl Default for Context {
         Context {
             scopes: vec![Default::default()],
             active_scope: Default::default(),
+            pure: false,
+            const_eval: false,
+            mux_strategy: None,
         }
     }
 }
@@ -136,6 +146,106 @@ fn rewrite_pattern_as_typed_bits(pat: &syn::Pat) -> syn::Result<TS> {
     }
 }
 
+// An integer literal, possibly negated or in parentheses.
+fn is_int_literal(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(_),
+            ..
+        }) => true,
+        syn::Expr::Paren(paren) => is_int_literal(&paren.expr),
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr,
+            ..
+        }) => is_int_literal(expr),
+        _ => false,
+    }
+}
+
+fn path_is_bits(path: &Path) -> bool {
+    path.segments
+        .last()
+        .map(|x| x.ident == "Bits" || x.ident == "SignedBits")
+        .unwrap_or(false)
+}
+
+// Rewrite a pattern without bindings into the list of cases it matches.
+// Or-patterns are flattened, and the integer patterns inside of `Bits::<N>(..)`
+// and `SignedBits::<N>(..)` are passed on as untyped literals, so that the
+// compiler can check them against the width of the scrutinee.
+fn rewrite_pattern_as_arm_cases(pat: &syn::Pat) -> syn::Result<Vec<TS>> {
+    match pat {
+        Pat::Or(subs) => Ok(subs
+            .cases
+            .iter()
+            .map(rewrite_pattern_as_arm_cases)
+            .collect::<syn::Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect()),
+        Pat::Paren(pat) => rewrite_pattern_as_arm_cases(&pat.pat),
+        Pat::TupleStruct(tuple) if path_is_bits(&tuple.path) && tuple.elems.len() == 1 => {
+            rewrite_integer_pattern_as_arm_cases(&tuple.elems[0])
+        }
+        _ => {
+            let value = rewrite_pattern_as_typed_bits(pat)?;
+            Ok(vec![
+                quote! {rhdl_core::ast_builder::arm_kind_constant(#value)},
+            ])
+        }
+    }
+}
+
+fn rewrite_integer_pattern_as_arm_cases(pat: &syn::Pat) -> syn::Result<Vec<TS>> {
+    match pat {
+        Pat::Or(subs) => Ok(subs
+            .cases
+            .iter()
+            .map(rewrite_integer_pattern_as_arm_cases)
+            .collect::<syn::Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect()),
+        Pat::Paren(pat) => rewrite_integer_pattern_as_arm_cases(&pat.pat),
+        Pat::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(i),
+            ..
+        }) => Ok(vec![
+            quote! {rhdl_core::ast_builder::arm_kind_constant(rhdl_core::ast_builder::expr_lit_int(stringify!(#i)))},
+        ]),
+        Pat::Range(range) => {
+            let bound = |expr: Option<&Box<syn::Expr>>| match expr.map(|x| x.as_ref()) {
+                Some(syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Int(i),
+                    ..
+                })) => Ok(quote! {rhdl_core::ast_builder::expr_lit_int(stringify!(#i))}),
+                _ => Err(syn::Error::new(
+                    range.span(),
+                    "Range patterns in rhdl kernel functions need integer literal bounds on both ends",
+                )),
+            };
+            let start = bound(range.start.as_ref())?;
+            let end = bound(range.end.as_ref())?;
+            let limits = match range.limits {
+                syn::RangeLimits::HalfOpen(_) => {
+                    quote!(rhdl_core::ast_builder::range_limits_half_open())
+                }
+                syn::RangeLimits::Closed(_) => {
+                    quote!(rhdl_core::ast_builder::range_limits_closed())
+                }
+            };
+            Ok(vec![
+                quote! {rhdl_core::ast_builder::arm_kind_range(#start, #limits, #end)},
+            ])
+        }
+        _ => Err(syn::Error::new(
+            pat.span(),
+            "Unsupported pattern for a Bits value in rhdl kernel function (only integer literals, ranges and or-patterns are allowed)",
+        )),
+    }
+}
+
 fn rewrite_pattern_to_use_defaults_for_bindings(pat: &syn::Pat) -> TS {
     match pat {
         Pat::Ident(_) => {
@@ -274,9 +384,220 @@ impl Context {
 }
 
 pub fn hdl_kernel(input: TS) -> Result<TS> {
-    let input = syn::parse::<syn::ItemFn>(input.into())?;
+    Context::default().item(syn::parse2::<syn::Item>(input)?)
+}
+
+// The `#[kernel]` attribute accepts a list of flags, as in
+// `#[kernel(pure, const_eval)]`.  A `pure` kernel's calls can be memoized,
+// and a `const_eval` kernel's calls with constant arguments are evaluated
+// when the caller is compiled.  `mux = "tree"` (or `"one_hot"`, or
+// `"part_select"`) chooses how the kernel's dynamic array indices are
+// written in Verilog.
+pub fn hdl_kernel_with_attrs(attr: TS, input: TS) -> Result<TS> {
     let mut context = Context::default();
-    context.function(input)
+    let flags = Punctuated::<Meta, Comma>::parse_terminated.parse2(attr)?;
+    for flag in flags {
+        if flag.path().is_ident("mux") {
+            context.mux_strategy = Some(mux_strategy(&flag)?);
+        } else if flag.path().is_ident("pure") && matches!(flag, Meta::Path(_)) {
+            context.pure = true;
+        } else if flag.path().is_ident("const_eval") && matches!(flag, Meta::Path(_)) {
+            context.const_eval = true;
+        } else {
+            return Err(syn::Error::new(
+                flag.span(),
+                "Expected kernel attribute to be of the form #[kernel(pure, const_eval, mux = \"tree\")]",
+            ));
+        }
+    }
+    context.item(syn::parse2::<syn::Item>(input)?)
+}
+
+fn mux_strategy(flag: &Meta) -> Result<TS> {
+    let error = || {
+        syn::Error::new(
+            flag.span(),
+            "Expected `mux = \"part_select\"`, `mux = \"tree\"` or `mux = \"one_hot\"`",
+        )
+    };
+    let Meta::NameValue(MetaNameValue {
+        value: Expr::Lit(ExprLit {
+            lit: Lit::Str(strategy),
+            ..
+        }),
+        ..
+    }) = flag
+    else {
+        return Err(error());
+    };
+    match strategy.value().as_str() {
+        "part_select" => Ok(quote! {rhdl_core::codegen::mux::MuxStrategy::PartSelect}),
+        "tree" => Ok(quote! {rhdl_core::codegen::mux::MuxStrategy::Tree}),
+        "one_hot" => Ok(quote! {rhdl_core::codegen::mux::MuxStrategy::OneHot}),
+        _ => Err(error()),
+    }
+}
+
+// A `let` binding marked with `#[rhdl(probe)]` is given a named wire in
+// the generated Verilog.  The wire takes the name of the binding, unless
+// one is given with `#[rhdl(probe = "name")]`.  A binding marked with
+// `#[rhdl(keep)]` (or `#[rhdl(keep = "name")]`) is probed in the same way,
+// and its wire is also given a `(* keep *)` attribute, so that synthesis
+// tools do not optimize it away.  Returns the name of the probe, and
+// whether it is kept.
+fn probe_name(local: &syn::Local) -> Result<Option<(String, bool)>> {
+    const USAGE: &str = "Expected probe attribute to be of the form #[rhdl(probe)], #[rhdl(probe = \"name\")], #[rhdl(keep)] or #[rhdl(keep = \"name\")]";
+    let is_probe_attr = |path: &syn::ExprPath| {
+        ["probe", "keep"]
+            .iter()
+            .find(|ident| path.path.is_ident(ident))
+            .map(|ident| *ident == "keep")
+    };
+    let mut name = None;
+    let mut probe = None;
+    for attr in &local.attrs {
+        if !attr.path().is_ident("rhdl") {
+            continue;
+        }
+        match attr.parse_args::<syn::Expr>()? {
+            syn::Expr::Path(path) if is_probe_attr(&path).is_some() => {
+                probe = Some(probe.unwrap_or(false) || is_probe_attr(&path) == Some(true));
+            }
+            syn::Expr::Assign(assign) => {
+                let syn::Expr::Path(path) = assign.left.as_ref() else {
+                    return Err(syn::Error::new(assign.left.span(), USAGE));
+                };
+                let Some(keep) = is_probe_attr(path) else {
+                    return Err(syn::Error::new(path.span(), USAGE));
+                };
+                let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(value),
+                    ..
+                }) = assign.right.as_ref()
+                else {
+                    return Err(syn::Error::new(assign.right.span(), USAGE));
+                };
+                name = Some(value.value());
+                probe = Some(probe.unwrap_or(false) || keep);
+            }
+            _ => return Err(syn::Error::new(attr.span(), USAGE)),
+        }
+    }
+    let Some(keep) = probe else {
+        return Ok(None);
+    };
+    if let Some(name) = name {
+        return Ok(Some((name, keep)));
+    }
+    let mut pat = &local.pat;
+    if let syn::Pat::Type(ty) = pat {
+        pat = &ty.pat;
+    }
+    let syn::Pat::Ident(ident) = pat else {
+        return Err(syn::Error::new(
+            local.pat.span(),
+            "A probe on a pattern must be named, as in #[rhdl(probe = \"name\")]",
+        ));
+    };
+    Ok(Some((ident.ident.to_string(), keep)))
+}
+
+// An argument marked with `#[rhdl(param)]` is a compile-time parameter
+// of the kernel.  A design compiled with `compile_design_with_params`
+// fixes it to a value, rather than taking it as an input.
+fn is_param(arg: &syn::PatType) -> Result<bool> {
+    const USAGE: &str = "Expected param attribute to be of the form #[rhdl(param)]";
+    let mut param = false;
+    for attr in &arg.attrs {
+        if !attr.path().is_ident("rhdl") {
+            continue;
+        }
+        match attr.parse_args::<syn::Expr>()? {
+            syn::Expr::Path(path) if path.path.is_ident("param") => param = true,
+            _ => return Err(syn::Error::new(attr.span(), USAGE)),
+        }
+    }
+    if param && !matches!(arg.pat.as_ref(), syn::Pat::Ident(_)) {
+        return Err(syn::Error::new(
+            arg.pat.span(),
+            "A parameter must be bound to a name, as in #[rhdl(param)] width: b8",
+        ));
+    }
+    Ok(param)
+}
+
+// The attributes of an expression statement.  Rust only accepts them on
+// blocks, `if` and `match` statements.
+fn expr_attrs(expr: &syn::Expr) -> &[syn::Attribute] {
+    match expr {
+        syn::Expr::Block(expr) => &expr.attrs,
+        syn::Expr::If(expr) => &expr.attrs,
+        syn::Expr::Match(expr) => &expr.attrs,
+        _ => &[],
+    }
+}
+
+fn expr_attrs_mut(expr: &mut syn::Expr) -> Option<&mut Vec<syn::Attribute>> {
+    match expr {
+        syn::Expr::Block(expr) => Some(&mut expr.attrs),
+        syn::Expr::If(expr) => Some(&mut expr.attrs),
+        syn::Expr::Match(expr) => Some(&mut expr.attrs),
+        _ => None,
+    }
+}
+
+// The rhdl attributes mean nothing to the Rust compiler, so they are
+// removed from the function that is emitted.  The flags are not known
+// until the kernel is compiled, so the Rust function tests them when it
+// runs, using the options installed with `with_compile_options`.
+struct StripRhdlAttributes;
+
+impl VisitMut for StripRhdlAttributes {
+    fn visit_local_mut(&mut self, local: &mut syn::Local) {
+        // A probe is often only there to be looked at, and is not used
+        // by the rest of the kernel
+        let probed = matches!(probe_name(local), Ok(Some(_)));
+        local.attrs.retain(|attr| !attr.path().is_ident("rhdl"));
+        if probed {
+            local
+                .attrs
+                .push(syn::parse_quote!(#[allow(unused_variables)]));
+        }
+        syn::visit_mut::visit_local_mut(self, local);
+    }
+    fn visit_stmt_mut(&mut self, stmt: &mut syn::Stmt) {
+        syn::visit_mut::visit_stmt_mut(self, stmt);
+        let syn::Stmt::Expr(expr, _) = stmt else {
+            return;
+        };
+        let Ok(Some(flag)) = cfg_flag(expr_attrs(expr)) else {
+            return;
+        };
+        if let Some(attrs) = expr_attrs_mut(expr) {
+            attrs.retain(|attr| !attr.path().is_ident("rhdl"));
+        }
+        *stmt = syn::parse_quote! {
+            if rhdl_core::compile_flag(#flag) {
+                #stmt
+            }
+        };
+    }
+    fn visit_arm_mut(&mut self, arm: &mut syn::Arm) {
+        syn::visit_mut::visit_arm_mut(self, arm);
+        let Ok(Some(flag)) = cfg_flag(&arm.attrs) else {
+            return;
+        };
+        arm.attrs.retain(|attr| !attr.path().is_ident("rhdl"));
+        let guard: syn::Expr = match arm.guard.take() {
+            Some((_, guard)) => syn::parse_quote! {rhdl_core::compile_flag(#flag) && (#guard)},
+            None => syn::parse_quote! {rhdl_core::compile_flag(#flag)},
+        };
+        arm.guard = Some((Default::default(), Box::new(guard)));
+    }
+    fn visit_pat_type_mut(&mut self, arg: &mut syn::PatType) {
+        arg.attrs.retain(|attr| !attr.path().is_ident("rhdl"));
+        syn::visit_mut::visit_pat_type_mut(self, arg);
+    }
 }
 
 // Convert a pattern that would appear in a function argument into an expression.
@@ -404,6 +725,115 @@ fn note_wrap_function(function: &syn::ItemFn) -> Result<TS> {
 }
 
 impl Context {
+    // A kernel is a function, or an inherent impl, in which case each of
+    // its functions is a kernel.
+    fn item(&mut self, item: syn::Item) -> Result<TS> {
+        match item {
+            syn::Item::Fn(function) => self.function(function),
+            syn::Item::Impl(item_impl) => self.inherent_impl(item_impl),
+            _ => Err(syn::Error::new(
+                item.span(),
+                "Only functions and inherent impls can be kernels",
+            )),
+        }
+    }
+
+    // The functions of an inherent impl are compiled as free functions of
+    // the module (with the generics of the impl as well as their own), so
+    // that they can name a kernel type, and the methods call them.  This
+    // is how the `update` kernel of a circuit can be a method of the
+    // circuit, which the `Circuit` derive finds as `update`.
+    fn inherent_impl(&mut self, item_impl: syn::ItemImpl) -> Result<TS> {
+        if let Some((_, path, _)) = &item_impl.trait_ {
+            return Err(syn::Error::new(
+                path.span(),
+                "Kernels can only be declared in an inherent impl",
+            ));
+        }
+        let mut kernels = vec![];
+        let mut items = vec![];
+        for item in &item_impl.items {
+            let syn::ImplItem::Fn(method) = item else {
+                items.push(quote! {#item});
+                continue;
+            };
+            let mut generics = item_impl.generics.clone();
+            generics
+                .params
+                .extend(method.sig.generics.params.iter().cloned());
+            if let Some(where_clause) = &method.sig.generics.where_clause {
+                generics
+                    .make_where_clause()
+                    .predicates
+                    .extend(where_clause.predicates.iter().cloned());
+            }
+            let function = syn::ItemFn {
+                attrs: method.attrs.clone(),
+                vis: method.vis.clone(),
+                sig: syn::Signature {
+                    generics: generics.clone(),
+                    ..method.sig.clone()
+                },
+                block: Box::new(method.block.clone()),
+            };
+            let mut context = Context {
+                pure: self.pure,
+                const_eval: self.const_eval,
+                mux_strategy: self.mux_strategy.clone(),
+                ..Default::default()
+            };
+            kernels.push(context.function(function)?);
+            // The method passes its arguments on to the kernel
+            let mut sig = method.sig.clone();
+            let mut args = vec![];
+            for (ndx, arg) in sig.inputs.iter_mut().enumerate() {
+                let syn::FnArg::Typed(arg) = arg else {
+                    return Err(syn::Error::new(
+                        arg.span(),
+                        "Unsupported receiver in rhdl kernel function",
+                    ));
+                };
+                let ident = format_ident!("arg_{}", ndx);
+                arg.attrs.retain(|attr| !attr.path().is_ident("rhdl"));
+                *arg.pat = syn::parse_quote!(#ident);
+                args.push(ident);
+            }
+            let params = generics
+                .params
+                .iter()
+                .filter_map(|param| match param {
+                    syn::GenericParam::Type(ty) => Some(&ty.ident),
+                    syn::GenericParam::Const(konst) => Some(&konst.ident),
+                    syn::GenericParam::Lifetime(_) => None,
+                })
+                .collect::<Vec<_>>();
+            let turbofish = (!params.is_empty()).then(|| quote! {::<#(#params),*>});
+            let name = &sig.ident;
+            let vis = &method.vis;
+            let attrs = method
+                .attrs
+                .iter()
+                .filter(|attr| !attr.path().is_ident("rhdl"));
+            items.push(quote! {
+                #(#attrs)*
+                #vis #sig {
+                    #name #turbofish(#(#args),*)
+                }
+            });
+        }
+        let attrs = &item_impl.attrs;
+        let (impl_generics, _, where_clause) = item_impl.generics.split_for_impl();
+        let self_ty = &item_impl.self_ty;
+        Ok(quote! {
+            #(#attrs)*
+            impl #impl_generics #self_ty #where_clause {
+                #(#items)*
+            }
+
+            #(#kernels)*
+        })
+    }
+
     fn function(&mut self, function: syn::ItemFn) -> Result<TS> {
         let orig_name = &function.sig.ident;
         let vis = &function.vis;
@@ -465,7 +895,47 @@ impl Context {
                 }
             })
             .collect::<Result<Vec<_>>>()?;
-        let wrapped_function = note_wrap_function(&function)?;
+        let arg_kinds = function.sig.inputs.iter().filter_map(|arg| match arg {
+            syn::FnArg::Typed(pat) => {
+                let ty = &pat.ty;
+                Some(quote! {<#ty as rhdl_core::Digital>::static_kind()})
+            }
+            syn::FnArg::Receiver(_) => None,
+        });
+        let mut params = vec![];
+        for (ndx, arg) in function.sig.inputs.iter().enumerate() {
+            if let syn::FnArg::Typed(arg) = arg {
+                if is_param(arg)? {
+                    params.push(ndx);
+                }
+            }
+        }
+        let mut stripped = function.clone();
+        StripRhdlAttributes.visit_item_fn_mut(&mut stripped);
+        let wrapped_function = note_wrap_function(&stripped)?;
+        let builder = if self.pure {
+            quote! {pure_kernel_fn}
+        } else {
+            quote! {kernel_fn}
+        };
+        let mut kernel = quote! {
+            rhdl_core::ast_builder::#builder(
+                stringify!(#orig_name),
+                vec!{#(#args),*},
+                #ret,
+                #block,
+                std::any::TypeId::of::<#name #ty_generics>(),
+            )
+        };
+        if !params.is_empty() {
+            kernel = quote! {rhdl_core::ast_builder::with_params(#kernel, vec![#(#params),*])};
+        }
+        if self.const_eval {
+            kernel = quote! {rhdl_core::ast_builder::with_const_eval(#kernel)};
+        }
+        if let Some(strategy) = &self.mux_strategy {
+            kernel = quote! {rhdl_core::ast_builder::with_mux_strategy(#kernel, #strategy)};
+        }
         Ok(quote! {
             #wrapped_function
 
@@ -474,13 +944,16 @@ impl Context {
 
             impl #impl_generics rhdl_core::digital_fn::DigitalFn for #name #ty_generics #where_clause {
                 fn kernel_fn() -> Option<rhdl_core::digital_fn::KernelFnKind> {
-                    Some(rhdl_core::ast_builder::kernel_fn(
-                        stringify!(#orig_name),
-                        vec!{#(#args),*},
-                        #ret,
-                        #block,
-                        std::any::TypeId::of::<#name #ty_generics>(),
-                    ))
+                    Some(#kernel)
+                }
+            }
+
+            impl #impl_generics rhdl_core::digital_fn::DigitalFnSignature for #name #ty_generics #where_clause {
+                fn arg_kinds() -> Vec<rhdl_core::Kind> {
+                    vec![#(#arg_kinds),*]
+                }
+                fn return_kind() -> rhdl_core::Kind {
+                    #ret
                 }
             }
         })
@@ -499,13 +972,44 @@ impl Context {
         let stmts = block
             .stmts
             .iter()
-            .map(|x| self.stmt(x))
+            .enumerate()
+            .map(|(ndx, x)| self.cfg_stmt(x, ndx + 1 == block.stmts.len()))
             .collect::<Result<Vec<_>>>()?;
         Ok(quote! {
             rhdl_core::ast_builder::block(vec![#(#stmts),*],)
         })
     }
 
+    fn cfg_stmt(&mut self, statement: &syn::Stmt, is_last: bool) -> Result<TS> {
+        let flag = match statement {
+            syn::Stmt::Local(local) => {
+                if cfg_flag(&local.attrs)?.is_some() {
+                    return Err(syn::Error::new(
+                        local.span(),
+                        "A let binding cannot be marked #[rhdl(cfg)], since the code after it may use it",
+                    ));
+                }
+                None
+            }
+            syn::Stmt::Expr(expr, semi) => {
+                let flag = cfg_flag(expr_attrs(expr))?;
+                if flag.is_some() && semi.is_none() && is_last {
+                    return Err(syn::Error::new(
+                        expr.span(),
+                        "The value of a block cannot be marked #[rhdl(cfg)]",
+                    ));
+                }
+                flag
+            }
+            _ => None,
+        };
+        let stmt = self.stmt(statement)?;
+        Ok(match flag {
+            Some(flag) => quote! {rhdl_core::ast_builder::cfg_stmt(#stmt, #flag)},
+            None => stmt,
+        })
+    }
+
     fn stmt(&mut self, statement: &syn::Stmt) -> Result<TS> {
         match statement {
             syn::Stmt::Local(local) => self.stmt_local(local),
@@ -538,6 +1042,16 @@ impl Context {
             .transpose()?
             .map(|x| quote!(Some(#x)))
             .unwrap_or(quote! {None});
+        if let Some((name, keep)) = probe_name(local)? {
+            if keep {
+                return Ok(quote! {
+                    rhdl_core::ast_builder::keep_local_stmt(#pattern, #local_init, #name)
+                });
+            }
+            return Ok(quote! {
+                rhdl_core::ast_builder::probe_local_stmt(#pattern, #local_init, #name)
+            });
+        }
         Ok(quote! {
                 rhdl_core::ast_builder::local_stmt(#pattern, #local_init)
         })
@@ -690,6 +1204,11 @@ impl Context {
     }
 
     fn method_call(&mut self, expr: &syn::ExprMethodCall) -> Result<TS> {
+        // An integer literal takes its type from where it is used, so
+        // converting it with `.into()` does nothing.
+        if expr.method == "into" && expr.args.is_empty() && is_int_literal(&expr.receiver) {
+            return self.expr(&expr.receiver);
+        }
         let receiver = self.expr(&expr.receiver)?;
         let args = expr
             .args
@@ -697,7 +1216,15 @@ impl Context {
             .map(|x| self.expr(x))
             .collect::<Result<Vec<_>>>()?;
         let method = &expr.method;
-        if !["any", "all", "xor", "as_signed", "as_unsigned"].contains(&method.to_string().as_str())
+        if ![
+            "any",
+            "all",
+            "xor",
+            "as_signed",
+            "as_unsigned",
+            "arithmetic_shr",
+        ]
+        .contains(&method.to_string().as_str())
         {
             return Err(syn::Error::new(
                 expr.span(),
@@ -917,25 +1444,41 @@ impl Context {
     }
 
     fn arm(&mut self, arm: &syn::Arm) -> Result<TS> {
+        let flag = cfg_flag(&arm.attrs)?;
         self.new_scope();
         let pat = &arm.pat;
-        let arm = if !pattern_has_bindings(pat) {
-            let body = self.expr(&arm.body)?;
+        let has_bindings = pattern_has_bindings(pat);
+        if has_bindings {
+            self.add_scoped_binding(pat)?;
+        }
+        // The guard can refer to the bindings of the pattern
+        let guard = arm
+            .guard
+            .as_ref()
+            .map(|(_, guard)| self.expr(guard))
+            .transpose()?;
+        let body = self.expr(&arm.body)?;
+        let arm = if !has_bindings {
             if let syn::Pat::Wild(_) = &pat {
                 quote! {rhdl_core::ast_builder::arm_wild(#body)}
             } else {
-                let pat = rewrite_pattern_as_typed_bits(pat)?;
-                quote! {rhdl_core::ast_builder::arm_constant(#pat, #body)}
+                let cases = rewrite_pattern_as_arm_cases(pat)?;
+                quote! {rhdl_core::ast_builder::arm_cases(vec![#(#cases),*], #body)}
             }
         } else {
-            self.add_scoped_binding(pat)?;
-            let body = self.expr(&arm.body)?;
             let pat_as_expr = rewrite_pattern_to_use_defaults_for_bindings(pat);
             let inner = self.pat(pat)?;
             quote! {rhdl_core::ast_builder::arm_enum(#inner, rhdl_core::Digital::typed_bits(#pat_as_expr), rhdl_core::Digital::variant_kind(#pat_as_expr), #body)}
         };
         self.end_scope();
-        Ok(arm)
+        let arm = match guard {
+            Some(guard) => quote! {rhdl_core::ast_builder::guard_arm(#arm, #guard)},
+            None => arm,
+        };
+        Ok(match flag {
+            Some(flag) => quote! {rhdl_core::ast_builder::cfg_arm(#arm, #flag)},
+            None => arm,
+        })
     }
 
     fn let_ex(&mut self, expr: &syn::ExprLet) -> Result<TS> {
@@ -1259,6 +1802,49 @@ mod test {
         println!("{}", result);
     }
 
+    #[test]
+    fn test_kernel_impl() {
+        let test_code = quote! {
+            impl<const N: usize> Inverter<N> {
+                pub fn update(i: Bits<N>, _q: InverterQ<N>) -> (Bits<N>, InverterD<N>) {
+                    (!i, InverterD::<N>::default())
+                }
+            }
+        };
+        let item = syn::parse2::<syn::Item>(test_code).unwrap();
+        let file = syn::parse2::<syn::File>(Context::default().item(item).unwrap()).unwrap();
+        // The method calls the kernel, which is a free function of the
+        // module, with the generics of the impl
+        let syn::Item::Impl(item_impl) = &file.items[0] else {
+            panic!("Expected the impl first");
+        };
+        let method = &item_impl.items[0];
+        let expected: syn::ImplItem = syn::parse_quote! {
+            pub fn update(arg_0: Bits<N>, arg_1: InverterQ<N>) -> (Bits<N>, InverterD<N>) {
+                update::<N>(arg_0, arg_1)
+            }
+        };
+        assert_eq!(quote!(#method).to_string(), quote!(#expected).to_string());
+        assert!(file.items.iter().any(|item| matches!(
+            item,
+            syn::Item::Fn(function) if function.sig.ident == "update" && function.sig.generics.params.len() == 1
+        )));
+        assert!(file.items.iter().any(|item| matches!(
+            item,
+            syn::Item::Struct(kernel) if kernel.ident == "update"
+        )));
+        // Kernels cannot implement traits
+        let test_code = quote! {
+            impl Default for Inverter {
+                fn default() -> Self {
+                    Inverter {}
+                }
+            }
+        };
+        let item = syn::parse2::<syn::Item>(test_code).unwrap();
+        assert!(Context::default().item(item).is_err());
+    }
+
     #[test]
     fn test_basic_block() {
         let test_code = quote! {
@@ -3,7 +3,12 @@ mod utils;
 pub use digital::derive_digital;
 mod digital_enum;
 mod kernel;
+mod opcode_map;
+pub use opcode_map::opcode_map;
 pub use kernel::hdl_kernel;
+pub use kernel::hdl_kernel_with_attrs;
 mod circuit;
 mod suffix;
 pub use circuit::derive_circuit;
+mod path_of;
+pub use path_of::path_of;
@@ -0,0 +1,226 @@
+use inflections::Inflect;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    braced,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Attribute, Ident, LitInt, Token, Visibility,
+};
+
+// The input to `opcode_map!`, which is written like an enum with
+// explicit discriminants, and the width of the patterns after the name:
+//
+//   opcode_map! {
+//       pub enum Opcode: 7 {
+//           Load = 0b000_0011,
+//           Store = 0b010_0011,
+//       }
+//   }
+//
+// The enum itself gets dense discriminants.  The sparse patterns are
+// only used by the constants (`Opcode::LOAD`, ...), and by the kernels
+// that are generated along with it.  `opcode_decode` returns the op with
+// a given pattern, and a flag that is false (with the first op) if no op
+// has that pattern.  Kernels have no `Option`, so the flag stands in for
+// it.  `opcode_encode` returns the pattern of an op.
+struct OpcodeMap {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    name: Ident,
+    width: LitInt,
+    entries: Punctuated<Entry, Token![,]>,
+}
+
+struct Entry {
+    name: Ident,
+    pattern: LitInt,
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let pattern = input.parse()?;
+        Ok(Entry { name, pattern })
+    }
+}
+
+impl Parse for OpcodeMap {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![enum]>()?;
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let width = input.parse()?;
+        let content;
+        braced!(content in input);
+        let entries = content.parse_terminated(Entry::parse, Token![,])?;
+        Ok(OpcodeMap {
+            attrs,
+            vis,
+            name,
+            width,
+            entries,
+        })
+    }
+}
+
+pub fn opcode_map(input: TokenStream) -> syn::Result<TokenStream> {
+    let map = syn::parse2::<OpcodeMap>(input)?;
+    let OpcodeMap {
+        attrs,
+        vis,
+        name,
+        width,
+        entries,
+    } = &map;
+    let bits = width.base10_parse::<usize>()?;
+    if bits == 0 || bits > 128 {
+        return Err(syn::Error::new(
+            width.span(),
+            "The patterns of an opcode map must be between 1 and 128 bits wide",
+        ));
+    }
+    if entries.is_empty() {
+        return Err(syn::Error::new(
+            name.span(),
+            "An opcode map needs at least one entry",
+        ));
+    }
+    let mut patterns: Vec<(u128, &Entry)> = vec![];
+    for entry in entries {
+        let pattern = entry.pattern.base10_parse::<u128>()?;
+        if bits < 128 && pattern >> bits != 0 {
+            return Err(syn::Error::new(
+                entry.pattern.span(),
+                format!("The pattern for {} does not fit in {bits} bits", entry.name),
+            ));
+        }
+        if let Some((_, first)) = patterns.iter().find(|(other, _)| *other == pattern) {
+            let mut err = syn::Error::new(
+                entry.pattern.span(),
+                format!(
+                    "The pattern for {} is the same as the one for {}",
+                    entry.name, first.name
+                ),
+            );
+            err.combine(syn::Error::new(
+                first.pattern.span(),
+                format!("The pattern for {} is given here", first.name),
+            ));
+            return Err(err);
+        }
+        patterns.push((pattern, entry));
+    }
+    let variants = entries.iter().map(|entry| &entry.name).collect::<Vec<_>>();
+    let first = variants[0];
+    let constants = variants
+        .iter()
+        .map(|variant| format_ident!("{}", variant.to_string().to_constant_case()))
+        .collect::<Vec<_>>();
+    let values = entries.iter().map(|entry| &entry.pattern);
+    let prefix = name.to_string().to_snake_case();
+    let decode = format_ident!("{prefix}_decode");
+    let encode = format_ident!("{prefix}_encode");
+    Ok(quote! {
+        #(#attrs)*
+        #[derive(Copy, Clone, PartialEq, Debug, rhdl_macro::Digital)]
+        #vis enum #name {
+            #(#variants,)*
+        }
+
+        impl #name {
+            #(pub const #constants: rhdl_bits::Bits<#width> = rhdl_bits::bits(#values);)*
+        }
+
+        #[rhdl_macro::kernel(pure)]
+        #vis fn #decode(x: rhdl_bits::Bits<#width>) -> (bool, #name) {
+            match x {
+                #(#name::#constants => (true, #name::#variants),)*
+                _ => (false, #name::#first),
+            }
+        }
+
+        #[rhdl_macro::kernel(pure)]
+        #vis fn #encode(op: #name) -> rhdl_bits::Bits<#width> {
+            match op {
+                #(#name::#variants => #name::#constants,)*
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::assert_tokens_eq;
+
+    #[test]
+    fn test_opcode_map() {
+        let input = quote! {
+            pub enum RiscvOp: 7 {
+                Load = 0b000_0011,
+                OpImm = 0b001_0011,
+            }
+        };
+        let output = opcode_map(input).unwrap();
+        let expected = quote! {
+            #[derive(Copy, Clone, PartialEq, Debug, rhdl_macro::Digital)]
+            pub enum RiscvOp {
+                Load,
+                OpImm,
+            }
+
+            impl RiscvOp {
+                pub const LOAD: rhdl_bits::Bits<7> = rhdl_bits::bits(0b000_0011);
+                pub const OP_IMM: rhdl_bits::Bits<7> = rhdl_bits::bits(0b001_0011);
+            }
+
+            #[rhdl_macro::kernel(pure)]
+            pub fn riscv_op_decode(x: rhdl_bits::Bits<7>) -> (bool, RiscvOp) {
+                match x {
+                    RiscvOp::LOAD => (true, RiscvOp::Load),
+                    RiscvOp::OP_IMM => (true, RiscvOp::OpImm),
+                    _ => (false, RiscvOp::Load),
+                }
+            }
+
+            #[rhdl_macro::kernel(pure)]
+            pub fn riscv_op_encode(op: RiscvOp) -> rhdl_bits::Bits<7> {
+                match op {
+                    RiscvOp::Load => RiscvOp::LOAD,
+                    RiscvOp::OpImm => RiscvOp::OP_IMM,
+                }
+            }
+        };
+        assert_tokens_eq(&expected, &output);
+    }
+
+    #[test]
+    fn test_opcode_map_rejects_duplicates() {
+        let input = quote! {
+            enum Op: 4 {
+                Add = 0x3,
+                Sub = 3,
+            }
+        };
+        let err = opcode_map(input).unwrap_err();
+        assert_eq!(
+            err.into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "The pattern for Sub is the same as the one for Add",
+                "The pattern for Add is given here"
+            ]
+        );
+        let input = quote! {
+            enum Op: 4 {
+                Add = 0x13,
+            }
+        };
+        assert!(opcode_map(input).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+    Data, DeriveInput, Ident, LitInt, Token, Type,
+};
+
+// `path_of!(Type, a.b[3]#Variant.value)` builds the `Path` to a part of a
+// digital type, and checks it against the type when the crate is built.
+// The check is a closure (that is never called) which follows the same
+// path through a value of the type, so that a misspelled field or tuple
+// index is reported by rustc, at the segment of the path that is wrong.
+// Indices into arrays are checked by the `unconditional_panic` lint,
+// which rustc only runs when building (not checking) the crate.
+//
+// Rust has no syntax for the payload of a variant, so the Digital derive
+// gives each enum a (hidden) accessor per variant, that the check goes
+// through instead:
+//
+//   __rhdl_payload_Variant(self) -> (fields of the variant, as a tuple)
+//   __rhdl_payload_Variant__field(self) -> (the field of a struct variant)
+pub fn path_of(input: TokenStream) -> syn::Result<TokenStream> {
+    let PathOf { ty, segments } = syn::parse2(input)?;
+    // rustc does not lint code that comes from a macro, so the value the
+    // check starts from takes its span from the caller, which lets the
+    // bounds of the array indices be checked.
+    let value = Ident::new("value", ty.span());
+    let mut check = quote! { #value };
+    let mut path = quote! { rhdl_core::path::Path::default() };
+    let mut segments = segments.into_iter().peekable();
+    while let Some(segment) = segments.next() {
+        match segment {
+            Segment::Field(field) => {
+                let name = field.to_string();
+                check = quote! { #check.#field };
+                path = quote! { #path.field(#name) };
+            }
+            Segment::TupleIndex(index) => {
+                let member = syn::Index {
+                    index: index.base10_parse()?,
+                    span: index.span(),
+                };
+                check = quote! { #check.#member };
+                path = quote! { #path.index(#index) };
+            }
+            Segment::ArrayIndex(index) => {
+                check = quote_spanned! { index.span() => #check[#index] };
+                path = quote! { #path.index(#index) };
+            }
+            Segment::Payload(variant) => {
+                let name = variant.to_string();
+                path = quote! { #path.payload(#name) };
+                match segments.peek() {
+                    Some(Segment::Field(field)) => {
+                        let accessor = format_ident!(
+                            "__rhdl_payload_{}__{}",
+                            variant,
+                            field,
+                            span = field.span()
+                        );
+                        let name = field.to_string();
+                        check = quote! { #check.#accessor() };
+                        path = quote! { #path.field(#name) };
+                        segments.next();
+                    }
+                    _ => {
+                        let accessor =
+                            format_ident!("__rhdl_payload_{}", variant, span = variant.span());
+                        check = quote! { #check.#accessor() };
+                    }
+                }
+            }
+        }
+    }
+    Ok(quote! {
+        {
+            let _ = |#value: #ty| {
+                let _ = #check;
+            };
+            #path
+        }
+    })
+}
+
+struct PathOf {
+    ty: Type,
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    Field(Ident),
+    TupleIndex(LitInt),
+    ArrayIndex(LitInt),
+    Payload(Ident),
+}
+
+impl Parse for PathOf {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ty = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let tokens = input
+            .parse::<TokenStream>()?
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut segments = vec![];
+        let mut ndx = 0;
+        while ndx < tokens.len() {
+            match &tokens[ndx] {
+                // The first field (or tuple index) of the path needs no dot
+                TokenTree::Ident(field) if ndx == 0 => segments.push(Segment::Field(field.clone())),
+                TokenTree::Literal(_) if ndx == 0 => {
+                    segments.extend(tuple_indices(&tokens[ndx])?);
+                }
+                TokenTree::Punct(punct) if punct.as_char() == '.' => {
+                    ndx += 1;
+                    match tokens.get(ndx) {
+                        Some(TokenTree::Ident(field)) => {
+                            segments.push(Segment::Field(field.clone()))
+                        }
+                        Some(index @ TokenTree::Literal(_)) => {
+                            segments.extend(tuple_indices(index)?)
+                        }
+                        _ => {
+                            return Err(syn::Error::new(
+                                punct.span(),
+                                "Expected a field name or a tuple index after the `.`",
+                            ))
+                        }
+                    }
+                }
+                TokenTree::Punct(punct) if punct.as_char() == '#' => {
+                    ndx += 1;
+                    match tokens.get(ndx) {
+                        Some(TokenTree::Ident(variant)) => {
+                            segments.push(Segment::Payload(variant.clone()))
+                        }
+                        _ => {
+                            return Err(syn::Error::new(
+                                punct.span(),
+                                "Expected the name of a variant after the `#`",
+                            ))
+                        }
+                    }
+                }
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Bracket => {
+                    let index = syn::parse2::<LitInt>(group.stream()).map_err(|_| {
+                        syn::Error::new(group.span(), "Expected a constant array index")
+                    })?;
+                    segments.push(Segment::ArrayIndex(index));
+                }
+                other => {
+                    return Err(syn::Error::new(
+                        other.span(),
+                        "Expected `.field`, `.0`, `[index]` or `#Variant` in the path",
+                    ))
+                }
+            }
+            ndx += 1;
+        }
+        Ok(PathOf { ty, segments })
+    }
+}
+
+// A tuple index.  The lexer reads `.0.1` as a dot and the float `0.1`, so
+// a literal may hold two indices.
+fn tuple_indices(token: &TokenTree) -> syn::Result<Vec<Segment>> {
+    let span = token.span();
+    let text = token.to_string();
+    let indices = text
+        .split('.')
+        .map(|index| {
+            if index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+                Err(syn::Error::new(
+                    span,
+                    format!("Expected a tuple index, rather than `{text}`"),
+                ))
+            } else {
+                Ok(Segment::TupleIndex(LitInt::new(index, span)))
+            }
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(indices)
+}
+
+// The accessors that `path_of!` uses to check the payloads of an enum.
+// They panic if the value is some other variant, but are only ever named
+// in a closure that is not called.
+pub(crate) fn derive_payload_accessors(decl: &DeriveInput) -> TokenStream {
+    let Data::Enum(e) = &decl.data else {
+        return quote! {};
+    };
+    let name = &decl.ident;
+    let (impl_generics, ty_generics, where_clause) = decl.generics.split_for_impl();
+    let accessors = e.variants.iter().map(|variant| {
+        let ident = &variant.ident;
+        let accessor = format_ident!("__rhdl_payload_{}", ident);
+        let message = format!("The value is not a {name}::{ident}");
+        let members = variant
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(ndx, field)| match &field.ident {
+                Some(field) => quote! { #field },
+                None => {
+                    let index = syn::Index::from(ndx);
+                    quote! { #index }
+                }
+            })
+            .collect::<Vec<_>>();
+        let bindings = (0..members.len())
+            .map(|ndx| format_ident!("_{}", ndx))
+            .collect::<Vec<_>>();
+        let types = variant.fields.iter().map(|field| &field.ty);
+        let fields = variant.fields.iter().filter_map(|field| {
+            let field_name = field.ident.as_ref()?;
+            let accessor = format_ident!("__rhdl_payload_{}__{}", ident, field_name);
+            let ty = &field.ty;
+            Some(quote! {
+                pub fn #accessor(self) -> #ty {
+                    match self {
+                        Self::#ident { #field_name, .. } => #field_name,
+                        _ => panic!(#message),
+                    }
+                }
+            })
+        });
+        quote! {
+            pub fn #accessor(self) -> (#(#types,)*) {
+                match self {
+                    Self::#ident { #(#members: #bindings),* } => (#(#bindings,)*),
+                    _ => panic!(#message),
+                }
+            }
+            #(#fields)*
+        }
+    });
+    quote! {
+        #[doc(hidden)]
+        #[allow(non_snake_case, dead_code, unreachable_patterns)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#accessors)*
+        }
+    }
+}
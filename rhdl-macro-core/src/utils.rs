@@ -1,6 +1,6 @@
 use proc_macro2::TokenStream;
-use quote::quote;
-use syn::DeriveInput;
+use quote::{format_ident, quote};
+use syn::{spanned::Spanned, DeriveInput, Expr, ExprLit, Lit, LitStr};
 
 pub(crate) fn get_fqdn(decl: &DeriveInput) -> TokenStream {
     let struct_name = &decl.ident;
@@ -16,6 +16,469 @@ pub(crate) fn get_fqdn(decl: &DeriveInput) -> TokenStream {
     }
 }
 
+// Types listed with `#[rhdl(compatible_with = "path::to::Type")]` get a
+// pair of `From` impls that convert to and from this type by way of its
+// bits.  The widths of the fields are not known while deriving, so the
+// layouts cannot be checked at compile time.  Instead, a test is
+// generated that checks them (in the sense of `Compatibility::Layout`),
+// and the conversion itself panics if they do not line up.  A generic
+// type gets no test, since its layout depends on its parameters.
+pub(crate) fn derive_compatible_with(decl: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &decl.ident;
+    let (impl_generics, ty_generics, where_clause) = decl.generics.split_for_impl();
+    let others = parse_compatible_with_attributes(decl)?;
+    let layout_test = if others.is_empty() || !decl.generics.params.is_empty() {
+        quote! {}
+    } else {
+        let module = format_ident!("__rhdl_compatible_with_{}", name);
+        quote! {
+            #[cfg(test)]
+            #[allow(non_snake_case)]
+            mod #module {
+                #[allow(unused_imports)]
+                use super::*;
+
+                #[test]
+                fn check_layouts() {
+                    #(
+                        rhdl_core::types::convert::check_compatible(
+                            &<#name as rhdl_core::Digital>::static_kind(),
+                            &<#others as rhdl_core::Digital>::static_kind(),
+                            rhdl_core::types::convert::Compatibility::Layout,
+                        )
+                        .unwrap_or_else(|err| panic!("{err}"));
+                    )*
+                }
+            }
+        }
+    };
+    Ok(quote! {
+        #layout_test
+        #(
+            impl #impl_generics From<#others> for #name #ty_generics #where_clause {
+                fn from(value: #others) -> Self {
+                    rhdl_core::types::convert::convert(value).unwrap_or_else(|err| panic!("{err}"))
+                }
+            }
+            impl #impl_generics From<#name #ty_generics> for #others #where_clause {
+                fn from(value: #name #ty_generics) -> Self {
+                    rhdl_core::types::convert::convert(value).unwrap_or_else(|err| panic!("{err}"))
+                }
+            }
+        )*
+    })
+}
+
+fn parse_compatible_with_attributes(decl: &DeriveInput) -> syn::Result<Vec<syn::Path>> {
+    let mut others = vec![];
+    for attr in &decl.attrs {
+        if attr.path().is_ident("rhdl") {
+            if let Ok(Expr::Assign(assign)) = attr.parse_args::<Expr>() {
+                if let Expr::Path(path) = *assign.left {
+                    if path.path.is_ident("compatible_with") {
+                        match *assign.right {
+                            Expr::Lit(ExprLit {
+                                lit: Lit::Str(value),
+                                ..
+                            }) => others.push(value.parse::<syn::Path>()?),
+                            Expr::Path(path) => others.push(path.path),
+                            right => {
+                                return Err(syn::Error::new(
+                                    right.span(),
+                                    "Expected a type path for compatible_with",
+                                ))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(others)
+}
+
+// A type marked with `#[rhdl(layout_digest = "...")]` gets a test that
+// checks its layout against the digest, so that a change to the layout
+// (such as reordering two fields) shows up in review as a change to the
+// attribute.  The check cannot be done while deriving, since the widths
+// of the fields are not known yet, and cannot be a const assertion,
+// since kinds are not built in const context.  So it is only checked by
+// `cargo test` on the crate that declares the type, and not at all for a
+// type declared inside a function (where the test cannot be named).  The
+// test is named after both the type and the digest, so that it does not
+// clash with other items in the module.
+pub(crate) fn derive_layout_digest(decl: &DeriveInput) -> syn::Result<TokenStream> {
+    let Some(digest) = parse_layout_digest_attribute(decl)? else {
+        return Ok(quote! {});
+    };
+    if !decl.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            digest.span(),
+            "A generic type cannot have a layout digest, since its layout depends on its parameters",
+        ));
+    }
+    let value = digest.value();
+    if value.len() != 16 || !value.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        return Err(syn::Error::new(
+            digest.span(),
+            "Expected the digest as 16 lowercase hex digits, as given by layout_digests!",
+        ));
+    }
+    let name = &decl.ident;
+    let test_name = format_ident!("__rhdl_layout_digest_{}_{}", name, value);
+    Ok(quote! {
+        #[cfg(test)]
+        #[test]
+        #[allow(non_snake_case)]
+        fn #test_name() {
+            rhdl_core::types::digital::check_layout_digest::<#name>(#digest)
+                .unwrap_or_else(|err| panic!("{err}"));
+        }
+    })
+}
+
+fn parse_layout_digest_attribute(decl: &DeriveInput) -> syn::Result<Option<LitStr>> {
+    for attr in &decl.attrs {
+        if attr.path().is_ident("rhdl") {
+            if let Ok(Expr::Assign(assign)) = attr.parse_args::<Expr>() {
+                if let Expr::Path(path) = *assign.left {
+                    if path.path.is_ident("layout_digest") {
+                        return match *assign.right {
+                            Expr::Lit(ExprLit {
+                                lit: Lit::Str(value),
+                                ..
+                            }) => Ok(Some(value)),
+                            right => Err(syn::Error::new(
+                                right.span(),
+                                "Expected the digest as a string, as in layout_digest = \"...\"",
+                            )),
+                        };
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+// A newtype marked with `#[rhdl(transparent_ops)]` gets the operators of
+// its field, by way of the field.  Its kind is marked as well, so that
+// kernels can use the same operators on it.
+pub(crate) fn has_transparent_ops(decl: &DeriveInput) -> bool {
+    decl.attrs.iter().any(|attr| {
+        if !attr.path().is_ident("rhdl") {
+            return false;
+        }
+        match attr.parse_args::<Expr>() {
+            Ok(Expr::Path(path)) => path.path.is_ident("transparent_ops"),
+            _ => false,
+        }
+    })
+}
+
+pub(crate) fn derive_transparent_ops(decl: &DeriveInput) -> syn::Result<TokenStream> {
+    if !has_transparent_ops(decl) {
+        return Ok(quote! {});
+    }
+    let is_newtype = match &decl.data {
+        syn::Data::Struct(s) => {
+            matches!(&s.fields, syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1)
+        }
+        _ => false,
+    };
+    if !is_newtype {
+        return Err(syn::Error::new(
+            decl.span(),
+            "Only a tuple struct with a single field can have #[rhdl(transparent_ops)]",
+        ));
+    }
+    let name = &decl.ident;
+    let (impl_generics, ty_generics, where_clause) = decl.generics.split_for_impl();
+    let binary = [
+        (quote!(Add), quote!(add), quote!(+)),
+        (quote!(Sub), quote!(sub), quote!(-)),
+        (quote!(BitAnd), quote!(bitand), quote!(&)),
+        (quote!(BitOr), quote!(bitor), quote!(|)),
+        (quote!(BitXor), quote!(bitxor), quote!(^)),
+    ];
+    let assign = [
+        (quote!(AddAssign), quote!(add_assign), quote!(+=)),
+        (quote!(SubAssign), quote!(sub_assign), quote!(-=)),
+        (quote!(BitAndAssign), quote!(bitand_assign), quote!(&=)),
+        (quote!(BitOrAssign), quote!(bitor_assign), quote!(|=)),
+        (quote!(BitXorAssign), quote!(bitxor_assign), quote!(^=)),
+    ];
+    let binary = binary.iter().map(|(trait_, method, op)| {
+        quote! {
+            impl #impl_generics std::ops::#trait_ for #name #ty_generics #where_clause {
+                type Output = Self;
+                fn #method(self, rhs: Self) -> Self {
+                    Self(self.0 #op rhs.0)
+                }
+            }
+        }
+    });
+    let assign = assign.iter().map(|(trait_, method, op)| {
+        quote! {
+            impl #impl_generics std::ops::#trait_ for #name #ty_generics #where_clause {
+                fn #method(&mut self, rhs: Self) {
+                    self.0 #op rhs.0;
+                }
+            }
+        }
+    });
+    Ok(quote! {
+        #(#binary)*
+        #(#assign)*
+        impl #impl_generics std::ops::Not for #name #ty_generics #where_clause {
+            type Output = Self;
+            fn not(self) -> Self {
+                Self(!self.0)
+            }
+        }
+    })
+}
+
+// The kind of a struct field.  A field marked `#[rhdl(range(min, max))]`
+// gets those bounds attached to its kind (which does not change the
+// layout of the struct).
+pub(crate) fn field_kind(field: &syn::Field) -> syn::Result<TokenStream> {
+    let ty = &field.ty;
+    let kind = quote!(<#ty as rhdl_core::Digital>::static_kind());
+    let kind = match parse_range_attribute(field)? {
+        Some((min, max)) => quote!(#kind.with_range(#min, #max)),
+        None => kind,
+    };
+    guard_field(field, kind, quote!(rhdl_core::Kind::Empty))
+}
+
+// A field marked `#[rhdl(cfg(flag = "name"))]` is only present when the
+// flag is enabled in the installed `CompileOptions`.  Otherwise it has no
+// bits, and reads back as its default.  A flag that is not in the options
+// at all panics, as it is an error in a kernel.  (This is how the children of a
+// circuit are made conditional, in its D and Q.)  Guards the code for
+// the field with the flag, with `otherwise` (if not empty) used in its
+// place.
+pub(crate) fn guard_field(
+    field: &syn::Field,
+    code: TokenStream,
+    otherwise: TokenStream,
+) -> syn::Result<TokenStream> {
+    match cfg_flag(&field.attrs)? {
+        Some(flag) if otherwise.is_empty() => {
+            Ok(quote!(if rhdl_core::expect_compile_flag(#flag) { #code }))
+        }
+        Some(flag) => {
+            Ok(quote!(if rhdl_core::expect_compile_flag(#flag) { #code } else { #otherwise }))
+        }
+        None => Ok(code),
+    }
+}
+
+// A statement or match arm of a kernel (or a field of a circuit) marked
+// with `#[rhdl(cfg(flag = "name"))]` is only compiled when the flag is
+// enabled in the `CompileOptions`.  Returns the name of the flag, if there
+// is one.
+pub(crate) fn cfg_flag(attrs: &[syn::Attribute]) -> syn::Result<Option<String>> {
+    const USAGE: &str = "Expected cfg attribute to be of the form #[rhdl(cfg(flag = \"name\"))]";
+    for attr in attrs {
+        if !attr.path().is_ident("rhdl") {
+            continue;
+        }
+        let Ok(syn::Expr::Call(call)) = attr.parse_args::<syn::Expr>() else {
+            continue;
+        };
+        let syn::Expr::Path(func) = call.func.as_ref() else {
+            continue;
+        };
+        if !func.path.is_ident("cfg") {
+            continue;
+        }
+        let [syn::Expr::Assign(assign)] = call.args.iter().collect::<Vec<_>>()[..] else {
+            return Err(syn::Error::new(call.span(), USAGE));
+        };
+        let syn::Expr::Path(key) = assign.left.as_ref() else {
+            return Err(syn::Error::new(assign.left.span(), USAGE));
+        };
+        if !key.path.is_ident("flag") {
+            return Err(syn::Error::new(key.span(), USAGE));
+        }
+        let syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(name),
+            ..
+        }) = assign.right.as_ref()
+        else {
+            return Err(syn::Error::new(assign.right.span(), USAGE));
+        };
+        return Ok(Some(name.value()));
+    }
+    Ok(None)
+}
+
+fn parse_range_attribute(field: &syn::Field) -> syn::Result<Option<(Expr, Expr)>> {
+    for attr in &field.attrs {
+        if attr.path().is_ident("rhdl") {
+            if let Ok(Expr::Call(call)) = attr.parse_args::<Expr>() {
+                if let Expr::Path(path) = call.func.as_ref() {
+                    if path.path.is_ident("range") {
+                        let mut args = call.args.iter().cloned();
+                        return match (args.next(), args.next(), args.next()) {
+                            (Some(min), Some(max), None) => Ok(Some((min, max))),
+                            _ => Err(syn::Error::new(
+                                call.span(),
+                                "Expected the bounds of the range as range(min, max)",
+                            )),
+                        };
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+// A field marked `#[rhdl(multicycle = N)]` or `#[rhdl(false_path)]` has
+// relaxed timing, which is passed on to the SDC constraints of any
+// circuit that carries the struct in its inputs, outputs, D or Q.
+pub(crate) fn is_timing_attribute(attr: &syn::Attribute) -> bool {
+    attr.path().is_ident("rhdl")
+        && match attr.parse_args::<Expr>() {
+            Ok(Expr::Assign(assign)) => {
+                matches!(assign.left.as_ref(), Expr::Path(path) if path.path.is_ident("multicycle"))
+            }
+            Ok(Expr::Path(path)) => path.path.is_ident("false_path"),
+            _ => false,
+        }
+}
+
+fn parse_timing_attribute(field: &syn::Field) -> syn::Result<Option<TokenStream>> {
+    let mut ret = None;
+    for attr in field.attrs.iter().filter(|attr| is_timing_attribute(attr)) {
+        if ret.is_some() {
+            return Err(syn::Error::new(
+                attr.span(),
+                "A field can only have one timing exception",
+            ));
+        }
+        ret = Some(match attr.parse_args::<Expr>()? {
+            Expr::Assign(assign) => {
+                let Expr::Lit(ExprLit {
+                    lit: Lit::Int(cycles),
+                    ..
+                }) = assign.right.as_ref()
+                else {
+                    return Err(syn::Error::new(
+                        assign.right.span(),
+                        "Expected the number of cycles as multicycle = N",
+                    ));
+                };
+                if cycles.base10_parse::<usize>()? < 2 {
+                    return Err(syn::Error::new(
+                        cycles.span(),
+                        "A multicycle path must take at least 2 cycles",
+                    ));
+                }
+                quote!(rhdl_core::TimingException::Multicycle(#cycles))
+            }
+            _ => quote!(rhdl_core::TimingException::FalsePath),
+        });
+    }
+    Ok(ret)
+}
+
+// The body of `Digital::timing_exceptions` for a struct.  Each field
+// contributes its own exception (if it has one), and those of the
+// fields of its type.
+pub(crate) fn timing_exceptions_fn(
+    fields: &syn::Fields,
+    names: &[impl quote::ToTokens],
+) -> syn::Result<TokenStream> {
+    let exceptions = fields
+        .iter()
+        .map(|field| {
+            Ok(match parse_timing_attribute(field)? {
+                Some(exception) => quote!(Some(#exception)),
+                None => quote!(None),
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let extend = fields
+        .iter()
+        .zip(names)
+        .zip(exceptions)
+        .map(|((field, name), exception)| {
+            let ty = &field.ty;
+            guard_field(
+                field,
+                quote!(ret.extend(rhdl_core::types::digital::field_timing_exceptions::<#ty>(stringify!(#name), #exception));),
+                quote!(),
+            )
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote! {
+        fn timing_exceptions() -> Vec<(rhdl_core::path::Path, rhdl_core::TimingException)> {
+            let mut ret = vec![];
+            #(#extend)*
+            ret
+        }
+    })
+}
+
+// The doc comment given by the `#[doc = "..."]` attributes (i.e., the
+// `///` lines), with the space after each `///` removed.
+pub(crate) fn doc_comment(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value:
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(text),
+                        ..
+                    }),
+                ..
+            }) => Some(text.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<_>>();
+    let doc = lines.join("\n").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+// The body of `Digital::doc_comments` for a struct.  As with the timing
+// exceptions, each field contributes its own doc comment (if it has
+// one), and those of the fields of its type.
+pub(crate) fn doc_comments_fn(
+    fields: &syn::Fields,
+    names: &[impl quote::ToTokens],
+) -> syn::Result<TokenStream> {
+    let extend = fields
+        .iter()
+        .zip(names)
+        .map(|(field, name)| {
+            let ty = &field.ty;
+            let doc = match doc_comment(&field.attrs) {
+                Some(doc) => quote!(Some(#doc)),
+                None => quote!(None),
+            };
+            guard_field(
+                field,
+                quote!(ret.extend(rhdl_core::types::digital::field_doc_comments::<#ty>(stringify!(#name), #doc));),
+                quote!(),
+            )
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote! {
+        fn doc_comments() -> Vec<(rhdl_core::path::Path, String)> {
+            let mut ret = vec![];
+            #(#extend)*
+            ret
+        }
+    })
+}
+
 #[cfg(test)]
 pub(crate) fn assert_tokens_eq(
     expected: &proc_macro2::TokenStream,
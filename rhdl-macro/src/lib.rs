@@ -9,8 +9,8 @@ pub fn digital(input: TokenStream) -> TokenStream {
 }
 
 #[proc_macro_attribute]
-pub fn kernel(_attr: TokenStream, input: TokenStream) -> TokenStream {
-    match rhdl_macro_core::hdl_kernel(input.into()) {
+pub fn kernel(attr: TokenStream, input: TokenStream) -> TokenStream {
+    match rhdl_macro_core::hdl_kernel_with_attrs(attr.into(), input.into()) {
         Ok(output) => output.into(),
         Err(err) => err.to_compile_error().into(),
     }
@@ -31,3 +31,19 @@ pub fn circuit(input: TokenStream) -> TokenStream {
         Err(err) => err.to_compile_error().into(),
     }
 }
+
+#[proc_macro]
+pub fn opcode_map(input: TokenStream) -> TokenStream {
+    match rhdl_macro_core::opcode_map(input.into()) {
+        Ok(output) => output.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[proc_macro]
+pub fn path_of(input: TokenStream) -> TokenStream {
+    match rhdl_macro_core::path_of(input.into()) {
+        Ok(output) => output.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
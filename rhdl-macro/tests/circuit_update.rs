@@ -0,0 +1,21 @@
+mod common;
+
+#[test]
+fn test_circuit_update_kernel() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/circuit_update_attribute.rs");
+    t.pass("tests/ui/circuit_update_default.rs");
+    t.pass("tests/ui/circuit_update_method.rs");
+}
+
+#[test]
+fn test_circuit_update_wrong_signature() {
+    common::assert_compile_error(
+        "tests/ui/circuit_update_wrong_signature.rs",
+        &[
+            "error[E0277]: `fn(rhdl_bits::Bits<8>, InverterQ) -> (rhdl_bits::Bits<4>, InverterD)` is not an update kernel for this circuit",
+            "circuit_update_wrong_signature.rs:6:17",
+            "^^^^^^ expected an update kernel of type `fn(rhdl_bits::Bits<8>, InverterQ) -> (rhdl_bits::Bits<8>, InverterD)`",
+        ],
+    );
+}
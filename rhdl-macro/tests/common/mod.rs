@@ -0,0 +1,54 @@
+// Shared by the UI tests of the crates in the workspace (which include it
+// with `#[path]`), so that there is one copy of it.
+use std::path::Path;
+
+// trybuild compares all of the output of rustc with a snapshot, but the
+// help and notes that rustc adds to an error change between toolchains.
+// So the snapshot is taken afresh, from a copy of the test in a scratch
+// directory, and only searched for the lines of the primary error.
+#[allow(dead_code)]
+pub fn assert_compile_error(test: &str, expected: &[&str]) {
+    compile_error(test, None, expected)
+}
+
+// Some errors (e.g., from the `unconditional_panic` lint) are only
+// reported when the crate is built, but trybuild only checks the test
+// cases unless one of them is expected to pass.  So `passing` is run
+// alongside the test, to make trybuild build it.
+#[allow(dead_code)]
+pub fn assert_build_error(test: &str, passing: &str, expected: &[&str]) {
+    compile_error(test, Some(passing), expected)
+}
+
+fn compile_error(test: &str, passing: Option<&str>, expected: &[&str]) {
+    let scratch = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ui");
+    std::fs::create_dir_all(&scratch).unwrap();
+    let copy = scratch.join(Path::new(test).file_name().unwrap());
+    std::fs::copy(test, &copy).unwrap();
+    let stderr = copy.with_extension("stderr");
+    let _ = std::fs::remove_file(&stderr);
+    let wip = Path::new("wip").join(stderr.file_name().unwrap());
+    let _ = std::fs::remove_file(&wip);
+    // As the copy has no snapshot, trybuild writes the output of rustc
+    // to `wip` (and fails), unless run with `TRYBUILD=overwrite`, when it
+    // writes the snapshot next to the copy instead.  The test cases are
+    // run when they are dropped.
+    let _ = std::panic::catch_unwind(|| {
+        let t = trybuild::TestCases::new();
+        if let Some(passing) = passing {
+            t.pass(passing);
+        }
+        t.compile_fail(&copy);
+    });
+    let output = std::fs::read_to_string(&wip).or_else(|_| std::fs::read_to_string(&stderr));
+    let _ = std::fs::remove_file(&wip);
+    let Ok(output) = output else {
+        panic!("Expected {test} to fail to compile");
+    };
+    for line in expected {
+        assert!(
+            output.contains(line),
+            "Expected the error of {test} to contain:\n{line}\nbut it was:\n{output}"
+        );
+    }
+}
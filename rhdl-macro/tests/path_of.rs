@@ -0,0 +1,32 @@
+mod common;
+
+#[test]
+fn test_path_of() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/path_of_valid.rs");
+}
+
+#[test]
+fn test_path_of_bad_field() {
+    common::assert_compile_error(
+        "tests/ui/path_of_bad_field.rs",
+        &[
+            "error[E0609]: no field `wrods` on type `Sub`",
+            "path_of_bad_field.rs:11:27",
+            "^^^^^ unknown field",
+        ],
+    );
+}
+
+#[test]
+fn test_path_of_bad_index() {
+    common::assert_build_error(
+        "tests/ui/path_of_bad_index.rs",
+        "tests/ui/path_of_valid.rs",
+        &[
+            "error: this operation will panic at runtime",
+            "path_of_bad_index.rs:11:22",
+            "^^^^^^^^^^^^ index out of bounds: the length is 4 but the index is 4",
+        ],
+    );
+}
@@ -0,0 +1,26 @@
+use rhdl_bits::alias::*;
+use rhdl_core::{Circuit, CircuitIO};
+use rhdl_macro::{kernel, Circuit, Digital};
+
+// A circuit with no children, whose update kernel is named in the attribute
+#[derive(Clone, Circuit)]
+#[rhdl(update = invert)]
+pub struct Inverter {}
+
+impl CircuitIO for Inverter {
+    type I = b8;
+    type O = b8;
+}
+
+#[kernel]
+pub fn invert(i: b8, _q: InverterQ) -> (b8, InverterD) {
+    (!i, InverterD::default())
+}
+
+fn main() {
+    let inverter = Inverter {};
+    let mut state = inverter.init_state();
+    let mut io = <Inverter as Circuit>::Z::default();
+    assert_eq!(inverter.sim(b8(0x0F), &mut state, &mut io), b8(0xF0));
+    assert!(inverter.as_hdl(rhdl_core::HDLKind::Verilog).is_ok());
+}
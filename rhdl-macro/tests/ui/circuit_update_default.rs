@@ -0,0 +1,24 @@
+use rhdl_bits::alias::*;
+use rhdl_core::{Circuit, CircuitIO};
+use rhdl_macro::{kernel, Circuit, Digital};
+
+// Without an attribute, the update kernel is the function named `update`
+#[derive(Clone, Circuit)]
+pub struct Inverter {}
+
+impl CircuitIO for Inverter {
+    type I = b8;
+    type O = b8;
+}
+
+#[kernel]
+pub fn update(i: b8, _q: InverterQ) -> (b8, InverterD) {
+    (!i, InverterD::default())
+}
+
+fn main() {
+    let inverter = Inverter {};
+    let mut state = inverter.init_state();
+    let mut io = <Inverter as Circuit>::Z::default();
+    assert_eq!(inverter.sim(b8(0x0F), &mut state, &mut io), b8(0xF0));
+}
@@ -0,0 +1,29 @@
+use rhdl_bits::{alias::*, Bits};
+use rhdl_core::{Circuit, CircuitIO};
+use rhdl_macro::{kernel, Circuit, Digital};
+
+// Without an attribute, the update kernel can also be a method named
+// `update`, declared in a `#[kernel]` impl of the circuit
+#[derive(Clone, Circuit)]
+pub struct Inverter<const N: usize> {}
+
+impl<const N: usize> CircuitIO for Inverter<N> {
+    type I = Bits<N>;
+    type O = Bits<N>;
+}
+
+#[kernel]
+impl<const N: usize> Inverter<N> {
+    pub fn update(i: Bits<N>, _q: InverterQ<N>) -> (Bits<N>, InverterD<N>) {
+        (!i, InverterD::<N>::default())
+    }
+}
+
+fn main() {
+    let inverter = Inverter::<8> {};
+    let mut state = inverter.init_state();
+    let mut io = <Inverter<8> as Circuit>::Z::default();
+    assert_eq!(inverter.sim(b8(0x0F), &mut state, &mut io), b8(0xF0));
+    assert_eq!(Inverter::<8>::update(b8(0x0F), Default::default()).0, b8(0xF0));
+    assert!(inverter.as_hdl(rhdl_core::HDLKind::Verilog).is_ok());
+}
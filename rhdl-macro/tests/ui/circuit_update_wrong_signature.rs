@@ -0,0 +1,20 @@
+use rhdl_bits::alias::*;
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+
+#[derive(Clone, Circuit)]
+#[rhdl(update = invert)]
+pub struct Inverter {}
+
+impl CircuitIO for Inverter {
+    type I = b8;
+    type O = b8;
+}
+
+// The output should be a b8, not a b4
+#[kernel]
+pub fn invert(_i: b8, _q: InverterQ) -> (b4, InverterD) {
+    (b4(0), InverterD::default())
+}
+
+fn main() {}
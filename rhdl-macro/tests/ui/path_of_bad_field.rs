@@ -0,0 +1,12 @@
+use rhdl_bits::alias::*;
+use rhdl_macro::{path_of, Digital};
+
+#[derive(Copy, Clone, PartialEq, Default, Digital)]
+pub struct Sub {
+    pub flag: bool,
+    pub words: [b4; 4],
+}
+
+fn main() {
+    let _ = path_of!(Sub, wrods[1]);
+}
@@ -0,0 +1,59 @@
+use rhdl_bits::alias::*;
+use rhdl_core::path::{bit_range, Path};
+use rhdl_core::Digital;
+use rhdl_macro::{path_of, Digital};
+
+#[derive(Copy, Clone, PartialEq, Default, Digital)]
+pub struct Sub {
+    pub flag: bool,
+    pub words: [b4; 4],
+}
+
+#[derive(Copy, Clone, PartialEq, Digital)]
+pub enum Cmd {
+    Idle,
+    Add(b4, b2),
+    Set { value: b8, force: bool },
+}
+
+#[derive(Copy, Clone, PartialEq, Default, Digital)]
+pub struct Top {
+    pub sub: Sub,
+    pub pair: (b2, (b3, bool)),
+    pub cmd: Cmd,
+}
+
+fn main() {
+    assert_eq!(path_of!(Top, sub), Path::default().field("sub"));
+    assert_eq!(
+        path_of!(Top, sub.words[3]),
+        Path::default().field("sub").field("words").index(3)
+    );
+    // The lexer reads `1.0` as a float, which rustfmt would split up
+    #[rustfmt::skip]
+    let nested = path_of!(Top, pair.1.0);
+    assert_eq!(nested, Path::default().field("pair").index(1).index(0));
+    assert_eq!(path_of!(Top, pair.1 .0), nested);
+    assert_eq!(
+        path_of!(Top, cmd #Set.force),
+        Path::default().field("cmd").payload("Set").field("force")
+    );
+    assert_eq!(
+        path_of!(Top, cmd #Add.1),
+        Path::default().field("cmd").payload("Add").index(1)
+    );
+    assert_eq!(path_of!(Cmd, #Idle), Path::default().payload("Idle"));
+    assert_eq!(
+        path_of!([Sub; 2], [1].flag),
+        Path::default().index(1).field("flag")
+    );
+    // Every path resolves against the kind of the type
+    for path in [
+        path_of!(Top, sub.words[3]),
+        path_of!(Top, pair.1 .0),
+        path_of!(Top, cmd #Set.force),
+        path_of!(Top, cmd #Add.1),
+    ] {
+        assert!(bit_range(Top::static_kind(), &path).is_ok());
+    }
+}
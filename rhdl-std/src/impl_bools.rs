@@ -0,0 +1,105 @@
+use rhdl_bits::Bits;
+use rhdl_core::kernel::ExternalKernelDef;
+use rhdl_core::kernel::KernelFnKind;
+use rhdl_core::DigitalFn;
+use rhdl_core::Kind;
+use rhdl_core::TypedBits;
+
+// A `[bool; N]` is laid out with element 0 in the least significant
+// bit, which is the same layout as a `Bits<N>`.  So the conversions in
+// both directions are just wires, and the generated Verilog passes the
+// bits through untouched.
+
+pub fn to_bools<const N: usize>(x: Bits<N>) -> [bool; N] {
+    x.to_bools()
+}
+
+pub fn from_bools<const N: usize>(x: [bool; N]) -> Bits<N> {
+    Bits::<N>::from_bools(x)
+}
+
+fn vm_rewire(args: &[TypedBits], kind: Kind) -> anyhow::Result<TypedBits> {
+    anyhow::ensure!(
+        args[0].bits.len() == kind.bits(),
+        "Cannot convert {} to {}, as they have different widths",
+        args[0].kind,
+        kind
+    );
+    Ok(TypedBits {
+        bits: args[0].bits.clone(),
+        kind,
+    })
+}
+
+fn vm_to_bools<const N: usize>(args: &[TypedBits]) -> anyhow::Result<TypedBits> {
+    vm_rewire(args, Kind::make_array(Kind::make_bits(1), N))
+}
+
+fn vm_from_bools<const N: usize>(args: &[TypedBits]) -> anyhow::Result<TypedBits> {
+    vm_rewire(args, Kind::make_bits(N))
+}
+
+fn wiring_body(name: &str, width: usize) -> String {
+    format!(
+        "function [{}:0] {name}(input [{}:0] a); {name} = a; endfunction",
+        width - 1,
+        width - 1
+    )
+}
+
+#[allow(non_camel_case_types)]
+pub struct to_bools<const N: usize> {}
+
+impl<const N: usize> DigitalFn for to_bools<N> {
+    fn kernel_fn() -> Option<KernelFnKind> {
+        let name = format!("to_bools_{N}");
+        Some(KernelFnKind::Extern(ExternalKernelDef {
+            body: wiring_body(&name, N),
+            name,
+            vm_stub: Some(vm_to_bools::<N>),
+        }))
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub struct from_bools<const N: usize> {}
+
+impl<const N: usize> DigitalFn for from_bools<N> {
+    fn kernel_fn() -> Option<KernelFnKind> {
+        let name = format!("from_bools_{N}");
+        Some(KernelFnKind::Extern(ExternalKernelDef {
+            body: wiring_body(&name, N),
+            name,
+            vm_stub: Some(vm_from_bools::<N>),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_core::Digital;
+
+    #[test]
+    fn test_bools_round_trip() {
+        let bits: Bits<8> = 0b1101_0010.into();
+        let bools = to_bools(bits);
+        assert_eq!(bools, [false, true, false, false, true, false, true, true]);
+        assert_eq!(from_bools(bools), bits);
+        // The layouts agree, so the VM stubs only change the kind
+        let stub = vm_to_bools::<8>(&[bits.typed_bits()]).unwrap();
+        assert_eq!(stub, bools.typed_bits());
+        let stub = vm_from_bools::<8>(&[bools.typed_bits()]).unwrap();
+        assert_eq!(stub, bits.typed_bits());
+    }
+
+    #[test]
+    fn test_iverilog() -> anyhow::Result<()> {
+        let test_values = (0..=255).map(|x| (Bits::<8>::from(x),));
+        rhdl_core::test_with_iverilog(
+            to_bools::<8>,
+            to_bools::<8>::kernel_fn().unwrap().try_into()?,
+            test_values,
+        )
+    }
+}
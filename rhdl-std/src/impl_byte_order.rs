@@ -0,0 +1,123 @@
+use rhdl_bits::alias::*;
+use rhdl_bits::Bits;
+use rhdl_core::kernel::ExternalKernelDef;
+use rhdl_core::kernel::KernelFnKind;
+use rhdl_core::DigitalFn;
+use rhdl_core::TypedBits;
+
+// Reverse the order of the bytes in a value.  The width must be a whole,
+// non-zero, number of bytes, which is checked when the function is
+// instantiated.
+pub fn swap_bytes<const N: usize>(x: Bits<N>) -> Bits<N> {
+    const { assert_whole_bytes::<N>() };
+    Bits(x.0.swap_bytes() >> (128 - N))
+}
+
+const fn assert_whole_bytes<const N: usize>() {
+    assert!(
+        N > 0 && N.is_multiple_of(8),
+        "swap_bytes needs a whole number of bytes"
+    );
+}
+
+fn vm_swap_bytes(args: &[TypedBits]) -> anyhow::Result<TypedBits> {
+    let arg = &args[0];
+    anyhow::ensure!(
+        !arg.bits.is_empty() && arg.bits.len().is_multiple_of(8),
+        "swap_bytes needs a whole number of bytes"
+    );
+    Ok(TypedBits {
+        bits: arg.bits.chunks(8).rev().flatten().copied().collect(),
+        kind: arg.kind.clone(),
+    })
+}
+
+#[allow(non_camel_case_types)]
+pub struct swap_bytes<const N: usize> {}
+
+impl<const N: usize> DigitalFn for swap_bytes<N> {
+    fn kernel_fn() -> Option<KernelFnKind> {
+        const { assert_whole_bytes::<N>() };
+        let bytes = (0..N / 8)
+            .map(|ndx| format!("a[{}:{}]", ndx * 8 + 7, ndx * 8))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(KernelFnKind::Extern(ExternalKernelDef {
+            name: format!("swap_bytes_{N}"),
+            body: format!(
+                "function [{}:0] swap_bytes_{N}(input [{}:0] a); swap_bytes_{N} = {{{bytes}}}; endfunction",
+                N - 1,
+                N - 1
+            ),
+            vm_stub: Some(vm_swap_bytes),
+        }))
+    }
+}
+
+// Conversions between host and network (big endian) byte order, as in
+// the sockets API.  The host is taken to be little endian, so that a
+// value whose first byte on the wire is in its low bits (the way a byte
+// stream is usually packed into a word) reads correctly.  All four are
+// `swap_bytes`, and share its Verilog.
+macro_rules! byte_order_fn {
+    ($name: ident, $ty: ident, $width: literal) => {
+        pub fn $name(x: $ty) -> $ty {
+            swap_bytes::<$width>(x)
+        }
+
+        #[allow(non_camel_case_types)]
+        pub struct $name {}
+
+        impl DigitalFn for $name {
+            fn kernel_fn() -> Option<KernelFnKind> {
+                swap_bytes::<$width>::kernel_fn()
+            }
+        }
+    };
+}
+
+byte_order_fn!(htons, b16, 16);
+byte_order_fn!(ntohs, b16, 16);
+byte_order_fn!(htonl, b32, 32);
+byte_order_fn!(ntohl, b32, 32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_core::Digital;
+
+    #[test]
+    fn test_swap_bytes() {
+        assert_eq!(swap_bytes::<8>(Bits(0xA5)), Bits(0xA5));
+        assert_eq!(swap_bytes::<16>(Bits(0x1234)), Bits(0x3412));
+        assert_eq!(swap_bytes::<24>(Bits(0x12_3456)), Bits(0x56_3412));
+        assert_eq!(
+            swap_bytes::<128>(Bits(0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10)),
+            Bits(0x100F_0E0D_0C0B_0A09_0807_0605_0403_0201)
+        );
+        assert_eq!(htons(b16(0x0800)), b16(0x0008));
+        assert_eq!(ntohs(htons(b16(0xBEEF))), b16(0xBEEF));
+        assert_eq!(htonl(b32(0xC0A8_0001)), b32(0x0100_A8C0));
+        assert_eq!(ntohl(htonl(b32(0xDEAD_BEEF))), b32(0xDEAD_BEEF));
+    }
+
+    #[test]
+    fn test_vm_swap_bytes() {
+        for x in [0, 1, 0x1234_5678, 0xFF00_00FF, 0xFFFF_FFFF] {
+            let y = vm_swap_bytes(&[b32(x).typed_bits()]).unwrap();
+            assert_eq!(y, htonl(b32(x)).typed_bits());
+        }
+        assert!(vm_swap_bytes(&[Bits::<12>(0x123).typed_bits()]).is_err());
+        assert!(vm_swap_bytes(&[Bits::<0>(0).typed_bits()]).is_err());
+    }
+
+    #[test]
+    fn test_iverilog() -> anyhow::Result<()> {
+        let test_values = (0..=0xFFFF).step_by(7).map(|x| (b16(x),));
+        rhdl_core::test_with_iverilog(htons, htons::kernel_fn().unwrap().try_into()?, test_values)?;
+        let test_values = [0, 1, 0x1234_5678, 0xFF00_00FF, 0xFFFF_FFFF, 0xC0A8_0001]
+            .into_iter()
+            .map(|x| (b32(x),));
+        rhdl_core::test_with_iverilog(ntohl, ntohl::kernel_fn().unwrap().try_into()?, test_values)
+    }
+}
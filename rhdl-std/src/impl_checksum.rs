@@ -0,0 +1,225 @@
+use rhdl_bits::alias::*;
+use rhdl_bits::Bits;
+use rhdl_core::kernel::ExternalKernelDef;
+use rhdl_core::kernel::KernelFnKind;
+use rhdl_core::Digital;
+use rhdl_core::DigitalFn;
+use rhdl_core::TypedBits;
+
+// The 16 bit ones-complement arithmetic of the Internet checksum (RFC
+// 1071).  The sum is an ordinary 16 bit add, with the carry out of the
+// top bit added back into the bottom ("end-around carry").  The
+// checksum of a header is the complement of the ones-complement sum of
+// its 16 bit words, taken with the checksum field set to zero, so that a
+// receiver which sums the header with the checksum in place gets 0xFFFF.
+//
+// The sum of two words never carries twice, and the sum is only 0x0000
+// when both words are zero.  Otherwise zero comes out as 0xFFFF (the
+// "negative zero" of ones-complement), as RFC 1071 requires.
+pub fn ones_complement_add16(a: b16, b: b16) -> b16 {
+    let sum = a.0 + b.0;
+    Bits((sum & 0xFFFF) + (sum >> 16))
+}
+
+// The ones-complement sum of an array of words, added in order.  The
+// order does not change the result (RFC 1071 section 2), so this matches
+// summing with the carries deferred to the end.  An empty array sums to
+// zero, but has no kernel, as Verilog has no empty vector to pass it in.
+pub fn ones_complement_sum<const N: usize>(words: [b16; N]) -> b16 {
+    words.into_iter().fold(b16(0), ones_complement_add16)
+}
+
+fn word(bits: &[bool]) -> u128 {
+    bits.iter()
+        .rev()
+        .fold(0, |acc, bit| (acc << 1) | *bit as u128)
+}
+
+fn vm_ones_complement_add16(args: &[TypedBits]) -> anyhow::Result<TypedBits> {
+    let a = b16(word(&args[0].bits));
+    let b = b16(word(&args[1].bits));
+    Ok(ones_complement_add16(a, b).typed_bits())
+}
+
+fn vm_ones_complement_sum(args: &[TypedBits]) -> anyhow::Result<TypedBits> {
+    Ok(args[0]
+        .bits
+        .chunks(16)
+        .map(|bits| b16(word(bits)))
+        .fold(b16(0), ones_complement_add16)
+        .typed_bits())
+}
+
+#[allow(non_camel_case_types)]
+pub struct ones_complement_add16 {}
+
+impl DigitalFn for ones_complement_add16 {
+    fn kernel_fn() -> Option<KernelFnKind> {
+        Some(KernelFnKind::Extern(ExternalKernelDef {
+            name: "ones_complement_add16".into(),
+            body: "function [15:0] ones_complement_add16(input [15:0] a, input [15:0] b); reg [16:0] s; begin s = a + b; ones_complement_add16 = s[15:0] + s[16]; end endfunction".into(),
+            vm_stub: Some(vm_ones_complement_add16),
+        }))
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub struct ones_complement_sum<const N: usize> {}
+
+impl<const N: usize> DigitalFn for ones_complement_sum<N> {
+    fn kernel_fn() -> Option<KernelFnKind> {
+        const {
+            assert!(
+                N > 0,
+                "ones_complement_sum needs at least one word in a kernel"
+            )
+        };
+        let name = format!("ones_complement_sum_{N}");
+        // The adds are unrolled, one per word
+        let adds = (0..N)
+            .map(|ndx| {
+                format!(
+                    "s = acc + w[{}:{}]; acc = s[15:0] + s[16];",
+                    ndx * 16 + 15,
+                    ndx * 16
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(KernelFnKind::Extern(ExternalKernelDef {
+            body: format!(
+                "function [15:0] {name}(input [{}:0] w); reg [15:0] acc; reg [16:0] s; begin acc = 16'h0; {adds} {name} = acc; end endfunction",
+                N * 16 - 1,
+            ),
+            name,
+            vm_stub: Some(vm_ones_complement_sum),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The IPv4 header from the Wikipedia article on the header checksum,
+    // with the checksum field (word 5) zeroed, and its checksum.
+    const HEADER: [u128; 10] = [
+        0x4500, 0x0073, 0x0000, 0x4000, 0x4011, 0x0000, 0xC0A8, 0x0001, 0xC0A8, 0x00C7,
+    ];
+    const HEADER_CHECKSUM: u128 = 0xB861;
+
+    // The example from RFC 1071 section 3, where the sum of the bytes
+    // 00 01 f2 03 f4 f5 f6 f7 is ddf2.
+    const RFC_1071: [u128; 4] = [0x0001, 0xF203, 0xF4F5, 0xF6F7];
+    const RFC_1071_SUM: u128 = 0xDDF2;
+
+    fn words<const N: usize>(words: [u128; N]) -> [b16; N] {
+        words.map(b16)
+    }
+
+    // RFC 1071 with the carries deferred: add the words in 32 bits, and
+    // fold the carries back in at the end.
+    fn deferred_sum(words: &[u128]) -> u128 {
+        let mut sum = words.iter().sum::<u128>();
+        while sum >> 16 != 0 {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        sum
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        assert_eq!(ones_complement_sum(words(RFC_1071)), b16(RFC_1071_SUM));
+        assert_eq!(deferred_sum(&RFC_1071), RFC_1071_SUM);
+        let sum = ones_complement_sum(words(HEADER));
+        assert_eq!(!sum, b16(HEADER_CHECKSUM));
+        // A receiver sums the header with the checksum in place
+        let mut received = HEADER;
+        received[5] = HEADER_CHECKSUM;
+        assert_eq!(ones_complement_sum(words(received)), b16(0xFFFF));
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let add = |a, b| ones_complement_add16(b16(a), b16(b)).0;
+        assert_eq!(add(0, 0), 0);
+        assert_eq!(add(0xFFFF, 0), 0xFFFF);
+        assert_eq!(add(0xFFFF, 0xFFFF), 0xFFFF);
+        assert_eq!(add(0xFFFF, 1), 1);
+        assert_eq!(add(0x8000, 0x8000), 1);
+        assert_eq!(add(0xFFFE, 1), 0xFFFF);
+        assert_eq!(ones_complement_sum(words([0; 10])), b16(0));
+        assert_eq!(!ones_complement_sum(words([0; 10])), b16(0xFFFF));
+        assert_eq!(ones_complement_sum(words([0xFFFF; 10])), b16(0xFFFF));
+        assert_eq!(!ones_complement_sum(words([0xFFFF; 10])), b16(0));
+        assert_eq!(ones_complement_sum::<0>([]), b16(0));
+    }
+
+    #[test]
+    fn test_order_and_deferred_carries_agree() {
+        let mut state = 0x1234_5678_u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state & 0xFFFF) as u128
+        };
+        for _ in 0..1000 {
+            let data: [u128; 8] = std::array::from_fn(|_| next());
+            let sum = ones_complement_sum(words(data));
+            let mut reversed = data;
+            reversed.reverse();
+            assert_eq!(sum, ones_complement_sum(words(reversed)));
+            assert_eq!(sum.0, deferred_sum(&data));
+        }
+    }
+
+    #[test]
+    fn test_vm_matches_rust() {
+        let pairs = [0, 1, 0x7FFF, 0x8000, 0xFFFE, 0xFFFF, 0x1234];
+        for a in pairs {
+            for b in pairs {
+                let args = [b16(a).typed_bits(), b16(b).typed_bits()];
+                assert_eq!(
+                    vm_ones_complement_add16(&args).unwrap(),
+                    ones_complement_add16(b16(a), b16(b)).typed_bits()
+                );
+            }
+        }
+        for header in [HEADER, [0; 10], [0xFFFF; 10]] {
+            let args = [words(header).typed_bits()];
+            assert_eq!(
+                vm_ones_complement_sum(&args).unwrap(),
+                ones_complement_sum(words(header)).typed_bits()
+            );
+        }
+    }
+
+    #[test]
+    fn test_iverilog() -> anyhow::Result<()> {
+        let values = [0, 1, 0x7FFF, 0x8000, 0xFFFE, 0xFFFF, 0x1234, 0xC0A8];
+        let test_values = values
+            .into_iter()
+            .flat_map(|a| values.map(|b| (b16(a), b16(b))));
+        rhdl_core::test_with_iverilog(
+            ones_complement_add16,
+            ones_complement_add16::kernel_fn().unwrap().try_into()?,
+            test_values,
+        )?;
+        let test_values = [
+            [0x4500, 0x0073, 0x0000, 0x4000],
+            [0x4011, 0x0000, 0xC0A8, 0x0001],
+            RFC_1071,
+            [0; 4],
+            [0xFFFF; 4],
+            [0xFFFF, 0x0001, 0xFFFF, 0x0000],
+        ]
+        .into_iter()
+        .map(|data| (words(data),));
+        rhdl_core::test_with_iverilog(
+            ones_complement_sum::<4>,
+            ones_complement_sum::<4>::kernel_fn().unwrap().try_into()?,
+            test_values,
+        )
+    }
+}
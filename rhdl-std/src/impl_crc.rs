@@ -0,0 +1,360 @@
+use rhdl_bits::Bits;
+use rhdl_core::kernel::ExternalKernelDef;
+use rhdl_core::kernel::KernelFnKind;
+use rhdl_core::DigitalFn;
+use rhdl_core::Kind;
+use rhdl_core::TypedBits;
+
+// A CRC in the usual parameterized model: the width of the register, the
+// polynomial (without the implicit top bit), the initial value, whether
+// the input and output are reflected, and a value xor-ed into the result.
+//
+// The hardware only needs the step function `crc_update`, which advances
+// the CRC register by one word of data.  A reflected CRC keeps its
+// register reflected (and shifts the data in LSB first), so the register
+// starts at `initial_state` rather than `init`, and the checksum is
+// recovered from it with `finalize`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrcModel {
+    pub width: usize,
+    pub poly: u128,
+    pub init: u128,
+    pub refin: bool,
+    pub refout: bool,
+    pub xorout: u128,
+}
+
+pub const CRC8_ATM: CrcModel = CrcModel {
+    width: 8,
+    poly: 0x07,
+    init: 0,
+    refin: false,
+    refout: false,
+    xorout: 0,
+};
+
+pub const CRC16_CCITT: CrcModel = CrcModel {
+    width: 16,
+    poly: 0x1021,
+    init: 0xFFFF,
+    refin: false,
+    refout: false,
+    xorout: 0,
+};
+
+pub const CRC32: CrcModel = CrcModel {
+    width: 32,
+    poly: 0x04C1_1DB7,
+    init: 0xFFFF_FFFF,
+    refin: true,
+    refout: true,
+    xorout: 0xFFFF_FFFF,
+};
+
+fn mask(width: usize) -> u128 {
+    u128::MAX >> (128 - width)
+}
+
+fn reflect(x: u128, width: usize) -> u128 {
+    x.reverse_bits() >> (128 - width)
+}
+
+impl CrcModel {
+    pub fn new(width: usize, poly: u128) -> Self {
+        assert!((1..=128).contains(&width), "CRC width must be 1..=128");
+        Self {
+            width,
+            poly,
+            init: 0,
+            refin: false,
+            refout: false,
+            xorout: 0,
+        }
+    }
+    pub fn init(self, init: u128) -> Self {
+        Self { init, ..self }
+    }
+    pub fn reflect(self, refin: bool, refout: bool) -> Self {
+        Self {
+            refin,
+            refout,
+            ..self
+        }
+    }
+    pub fn xorout(self, xorout: u128) -> Self {
+        Self { xorout, ..self }
+    }
+    // The value of the CRC register before any data is shifted in.
+    pub fn initial_state(&self) -> u128 {
+        if self.refin {
+            reflect(self.init, self.width)
+        } else {
+            self.init
+        }
+    }
+    // The checksum for a given value of the CRC register.
+    pub fn finalize(&self, state: u128) -> u128 {
+        let state = if self.refin != self.refout {
+            reflect(state, self.width)
+        } else {
+            state
+        };
+        (state ^ self.xorout) & mask(self.width)
+    }
+    // Shift `data_width` bits of data into the CRC register, one bit at a
+    // time.  This is the software model for `crc_update`.
+    pub fn step(&self, state: u128, data: u128, data_width: usize) -> u128 {
+        let mask = mask(self.width);
+        let mut state = state & mask;
+        if self.refin {
+            let poly = reflect(self.poly, self.width);
+            for bit in 0..data_width {
+                let feedback = (state ^ (data >> bit)) & 1;
+                state >>= 1;
+                if feedback == 1 {
+                    state ^= poly;
+                }
+            }
+        } else {
+            for bit in (0..data_width).rev() {
+                let feedback = ((state >> (self.width - 1)) ^ (data >> bit)) & 1;
+                state = (state << 1) & mask;
+                if feedback == 1 {
+                    state ^= self.poly & mask;
+                }
+            }
+        }
+        state
+    }
+    pub fn checksum(&self, data: &[u8]) -> u128 {
+        let state = data.iter().fold(self.initial_state(), |state, byte| {
+            self.step(state, *byte as u128, 8)
+        });
+        self.finalize(state)
+    }
+    // The XOR network that implements `step`.  For each bit of the next
+    // value of the register, the bits of the current register and of the
+    // data word that are xor-ed together to give it.
+    pub fn network(&self, data_width: usize) -> Vec<(u128, u128)> {
+        let xor = |a: (u128, u128), b: (u128, u128)| (a.0 ^ b.0, a.1 ^ b.1);
+        let mut state = (0..self.width)
+            .map(|bit| (1_u128 << bit, 0))
+            .collect::<Vec<_>>();
+        if self.refin {
+            let poly = reflect(self.poly, self.width);
+            for bit in 0..data_width {
+                let feedback = xor(state[0], (0, 1 << bit));
+                state.remove(0);
+                state.push((0, 0));
+                for (ndx, term) in state.iter_mut().enumerate() {
+                    if (poly >> ndx) & 1 == 1 {
+                        *term = xor(*term, feedback);
+                    }
+                }
+            }
+        } else {
+            for bit in (0..data_width).rev() {
+                let feedback = xor(state[self.width - 1], (0, 1 << bit));
+                state.pop();
+                state.insert(0, (0, 0));
+                for (ndx, term) in state.iter_mut().enumerate() {
+                    if (self.poly >> ndx) & 1 == 1 {
+                        *term = xor(*term, feedback);
+                    }
+                }
+            }
+        }
+        state
+    }
+}
+
+// Advance the register of a CRC with the given polynomial by one `D` bit
+// word of data.  For the standard models:
+//
+//   CRC-8/ATM:      crc_update::<8, 8, 0x07, false>
+//   CRC-16/CCITT:   crc_update::<16, 8, 0x1021, false>
+//   CRC-32:         crc_update::<32, 8, 0x04C11DB7, true>
+//
+// The register starts at `CrcModel::initial_state`, and the checksum is
+// `CrcModel::finalize` of the register.  The RHIF and Verilog versions are
+// both generated from `CrcModel::network`.
+pub fn crc_update<const W: usize, const D: usize, const POLY: u128, const REFIN: bool>(
+    state: Bits<W>,
+    data: Bits<D>,
+) -> Bits<W> {
+    Bits(
+        CrcModel::new(W, POLY)
+            .reflect(REFIN, REFIN)
+            .step(state.0, data.0, D),
+    )
+}
+
+fn parity(bits: &[bool], mask: u128) -> bool {
+    bits.iter()
+        .enumerate()
+        .filter(|(ndx, _)| (mask >> ndx) & 1 == 1)
+        .fold(false, |acc, (_, bit)| acc ^ bit)
+}
+
+fn vm_crc_update<const W: usize, const D: usize, const POLY: u128, const REFIN: bool>(
+    args: &[TypedBits],
+) -> anyhow::Result<TypedBits> {
+    let network = CrcModel::new(W, POLY).reflect(REFIN, REFIN).network(D);
+    let bits = network
+        .iter()
+        .map(|(state, data)| parity(&args[0].bits, *state) ^ parity(&args[1].bits, *data))
+        .collect();
+    Ok(TypedBits {
+        bits,
+        kind: Kind::make_bits(W),
+    })
+}
+
+fn verilog_terms(name: &str, mask: u128) -> impl Iterator<Item = String> + '_ {
+    (0..128)
+        .filter(move |ndx| (mask >> ndx) & 1 == 1)
+        .map(move |ndx| format!("{name}[{ndx}]"))
+}
+
+#[allow(non_camel_case_types)]
+pub struct crc_update<const W: usize, const D: usize, const POLY: u128, const REFIN: bool> {}
+
+impl<const W: usize, const D: usize, const POLY: u128, const REFIN: bool> DigitalFn
+    for crc_update<W, D, POLY, REFIN>
+{
+    fn kernel_fn() -> Option<KernelFnKind> {
+        let name = format!("crc_{W}_{D}_{POLY:x}{}", if REFIN { "_r" } else { "" });
+        let network = CrcModel::new(W, POLY).reflect(REFIN, REFIN).network(D);
+        let assigns = network
+            .iter()
+            .enumerate()
+            .map(|(ndx, (state, data))| {
+                let terms = verilog_terms("s", *state)
+                    .chain(verilog_terms("d", *data))
+                    .collect::<Vec<_>>();
+                let value = if terms.is_empty() {
+                    "1'b0".to_string()
+                } else {
+                    terms.join(" ^ ")
+                };
+                format!("{name}[{ndx}] = {value};")
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(KernelFnKind::Extern(ExternalKernelDef {
+            body: format!(
+                "function [{}:0] {name}(input [{}:0] s, input [{}:0] d); begin {assigns} end endfunction",
+                W - 1,
+                W - 1,
+                D - 1,
+            ),
+            name,
+            vm_stub: Some(vm_crc_update::<W, D, POLY, REFIN>),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_core::Digital;
+
+    const CHECK: &[u8] = b"123456789";
+
+    // Run the check string through the step function (as given), and
+    // return the checksum.
+    fn checksum(model: &CrcModel, step: impl Fn(u128, u8) -> u128) -> u128 {
+        let state = CHECK
+            .iter()
+            .fold(model.initial_state(), |state, byte| step(state, *byte));
+        model.finalize(state)
+    }
+
+    fn vm_checksum<const W: usize, const POLY: u128, const REFIN: bool>(model: &CrcModel) -> u128 {
+        checksum(model, |state, byte| {
+            let args = [
+                Bits::<W>(state).typed_bits(),
+                Bits::<8>(byte as u128).typed_bits(),
+            ];
+            let next = vm_crc_update::<W, 8, POLY, REFIN>(&args).unwrap();
+            next.bits
+                .iter()
+                .rev()
+                .fold(0, |acc, bit| (acc << 1) | *bit as u128)
+        })
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        assert_eq!(CRC8_ATM.checksum(CHECK), 0xF4);
+        assert_eq!(CRC16_CCITT.checksum(CHECK), 0x29B1);
+        assert_eq!(CRC32.checksum(CHECK), 0xCBF4_3926);
+        let model = CrcModel::new(32, 0x04C1_1DB7)
+            .init(0xFFFF_FFFF)
+            .reflect(true, true)
+            .xorout(0xFFFF_FFFF);
+        assert_eq!(model, CRC32);
+    }
+
+    #[test]
+    fn test_crc_update_matches_model() {
+        let rust = |state, byte| crc_update::<8, 8, 0x07, false>(Bits(state), Bits(byte as u128)).0;
+        assert_eq!(checksum(&CRC8_ATM, rust), 0xF4);
+        let rust =
+            |state, byte| crc_update::<16, 8, 0x1021, false>(Bits(state), Bits(byte as u128)).0;
+        assert_eq!(checksum(&CRC16_CCITT, rust), 0x29B1);
+        let rust =
+            |state, byte| crc_update::<32, 8, 0x04C1_1DB7, true>(Bits(state), Bits(byte as u128)).0;
+        assert_eq!(checksum(&CRC32, rust), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_vm_matches_model() {
+        assert_eq!(vm_checksum::<8, 0x07, false>(&CRC8_ATM), 0xF4);
+        assert_eq!(vm_checksum::<16, 0x1021, false>(&CRC16_CCITT), 0x29B1);
+        assert_eq!(vm_checksum::<32, 0x04C1_1DB7, true>(&CRC32), 0xCBF4_3926);
+        // A data word wider than the register
+        let model = CRC8_ATM;
+        for word in [0_u128, 1, 0x1234, 0xFFFF, 0xA5C3] {
+            let args = [Bits::<8>(0x5A).typed_bits(), Bits::<16>(word).typed_bits()];
+            let next = vm_crc_update::<8, 16, 0x07, false>(&args).unwrap();
+            assert_eq!(next, Bits::<8>(model.step(0x5A, word, 16)).typed_bits());
+        }
+    }
+
+    #[test]
+    fn test_iverilog() -> anyhow::Result<()> {
+        let test_values = (0..=255)
+            .flat_map(|state| [0x00, 0x31, 0x5A, 0xFF].map(|byte| (state, byte)))
+            .map(|(state, byte)| (Bits::<8>::from(state), Bits::<8>::from(byte)));
+        rhdl_core::test_with_iverilog(
+            crc_update::<8, 8, 0x07, false>,
+            crc_update::<8, 8, 0x07, false>::kernel_fn()
+                .unwrap()
+                .try_into()?,
+            test_values,
+        )?;
+        let test_values = [0, 0x1234, 0xFFFF, 0x29B1]
+            .into_iter()
+            .flat_map(|state| [0x00, 0x31, 0x5A, 0xFF].map(|byte| (state, byte)))
+            .map(|(state, byte)| (Bits::<16>::from(state), Bits::<8>::from(byte)));
+        rhdl_core::test_with_iverilog(
+            crc_update::<16, 8, 0x1021, false>,
+            crc_update::<16, 8, 0x1021, false>::kernel_fn()
+                .unwrap()
+                .try_into()?,
+            test_values,
+        )?;
+        let test_values = [0, 0x1234_5678, 0xFFFF_FFFF, 0xCBF4_3926]
+            .into_iter()
+            .flat_map(|state| [0x00, 0x31, 0x5A, 0xFF].map(|byte| (state, byte)))
+            .map(|(state, byte)| (Bits::<32>::from(state), Bits::<8>::from(byte)));
+        rhdl_core::test_with_iverilog(
+            crc_update::<32, 8, 0x04C1_1DB7, true>,
+            crc_update::<32, 8, 0x04C1_1DB7, true>::kernel_fn()
+                .unwrap()
+                .try_into()?,
+            test_values,
+        )
+    }
+}
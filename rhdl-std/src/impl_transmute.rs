@@ -0,0 +1,88 @@
+use std::marker::PhantomData;
+
+use rhdl_core::kernel::ExternalKernelDef;
+use rhdl_core::kernel::KernelFnKind;
+use rhdl_core::types::convert::{convert_with, Compatibility};
+use rhdl_core::Digital;
+use rhdl_core::DigitalFn;
+use rhdl_core::Kind;
+use rhdl_core::TypedBits;
+
+// Reinterpret the bits of one Digital type as another type of the same
+// width.  The layouts of the two types are not checked beyond their
+// width.  Use `rhdl_core::convert` outside of a kernel if you want them
+// to be checked.
+pub fn transmute_digital<A: Digital, B: Digital>(a: A) -> B {
+    convert_with(a, Compatibility::Loose).unwrap()
+}
+
+fn vm_transmute_digital<B: Digital>(args: &[TypedBits]) -> anyhow::Result<TypedBits> {
+    let kind = B::static_kind();
+    anyhow::ensure!(
+        args[0].bits.len() == kind.bits(),
+        "Cannot transmute {} to {}, as they have different widths",
+        args[0].kind,
+        kind
+    );
+    Ok(TypedBits {
+        bits: args[0].bits.clone(),
+        kind,
+    })
+}
+
+#[allow(non_camel_case_types)]
+pub struct transmute_digital<A, B> {
+    _marker: PhantomData<(A, B)>,
+}
+
+impl<A: Digital, B: Digital> DigitalFn for transmute_digital<A, B> {
+    fn kernel_fn() -> Option<KernelFnKind> {
+        let width = B::static_kind().bits();
+        // A zero width value is passed to a function as a placeholder bit
+        let msb = width.saturating_sub(1);
+        let (name, signed) = if matches!(B::static_kind(), Kind::Signed(_)) {
+            (format!("transmute_s{width}"), "signed ")
+        } else {
+            (format!("transmute_{width}"), "")
+        };
+        Some(KernelFnKind::Extern(ExternalKernelDef {
+            body: format!(
+                "function {signed}[{msb}:0] {name}(input [{msb}:0] a); {name} = a; endfunction"
+            ),
+            name,
+            vm_stub: Some(vm_transmute_digital::<B>),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_bits::{Bits, SignedBits};
+
+    #[test]
+    fn test_transmute_digital() {
+        let x: Bits<8> = transmute_digital((Bits::<4>(0x3), Bits::<4>(0xA)));
+        assert_eq!(x, Bits::<8>(0xA3));
+        let y: SignedBits<8> = transmute_digital(Bits::<8>(0xFF));
+        assert_eq!(y, SignedBits::<8>(-1));
+    }
+
+    #[test]
+    fn test_transmute_digital_zero_width() {
+        transmute_digital::<(), ()>(());
+        let Some(KernelFnKind::Extern(def)) = transmute_digital::<(), ()>::kernel_fn() else {
+            panic!("Expected an extern kernel");
+        };
+        assert_eq!(
+            def.body,
+            "function [0:0] transmute_0(input [0:0] a); transmute_0 = a; endfunction"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_transmute_digital_width_mismatch() {
+        let _: Bits<8> = transmute_digital(Bits::<4>(0x3));
+    }
+}
@@ -55,20 +55,30 @@ mod impl_all;
 mod impl_any;
 mod impl_as_signed;
 mod impl_as_unsigned;
+mod impl_bools;
+mod impl_byte_order;
+mod impl_checksum;
+mod impl_crc;
 mod impl_get_bit;
 mod impl_set_bit;
 mod impl_sign_bit;
 mod impl_slice;
+mod impl_transmute;
 mod impl_xor;
 
 pub use impl_all::*;
 pub use impl_any::*;
 pub use impl_as_signed::*;
 pub use impl_as_unsigned::*;
+pub use impl_bools::*;
+pub use impl_byte_order::*;
+pub use impl_checksum::*;
+pub use impl_crc::*;
 pub use impl_get_bit::*;
 pub use impl_set_bit::*;
 pub use impl_sign_bit::*;
 pub use impl_slice::*;
+pub use impl_transmute::*;
 pub use impl_xor::*;
 
 mod impl_methods;
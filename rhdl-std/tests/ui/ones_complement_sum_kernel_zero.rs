@@ -0,0 +1,6 @@
+use rhdl_core::DigitalFn;
+use rhdl_std::ones_complement_sum;
+
+fn main() {
+    let _ = <ones_complement_sum<0> as DigitalFn>::kernel_fn();
+}
@@ -0,0 +1,6 @@
+use rhdl_core::DigitalFn;
+use rhdl_std::swap_bytes;
+
+fn main() {
+    let _ = <swap_bytes<0> as DigitalFn>::kernel_fn();
+}
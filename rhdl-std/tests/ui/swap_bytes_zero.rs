@@ -0,0 +1,6 @@
+use rhdl_bits::Bits;
+use rhdl_std::swap_bytes;
+
+fn main() {
+    let _ = swap_bytes::<0>(Bits(0));
+}
@@ -0,0 +1,10 @@
+use rhdl_bits::alias::*;
+use rhdl_core::DigitalFn;
+use rhdl_std::{ones_complement_sum, swap_bytes};
+
+fn main() {
+    let _ = swap_bytes::<16>(b16(0x1234));
+    let _ = <swap_bytes<16> as DigitalFn>::kernel_fn();
+    let _ = ones_complement_sum::<2>([b16(1), b16(2)]);
+    let _ = <ones_complement_sum<2> as DigitalFn>::kernel_fn();
+}
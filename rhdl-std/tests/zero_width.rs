@@ -0,0 +1,38 @@
+#[path = "../../rhdl-macro/tests/common/mod.rs"]
+mod common;
+
+#[test]
+fn test_swap_bytes_of_nothing_is_rejected() {
+    common::assert_build_error(
+        "tests/ui/swap_bytes_zero.rs",
+        "tests/ui/zero_width_valid.rs",
+        &[
+            "swap_bytes_zero.rs:5:13",
+            "swap_bytes needs a whole number of bytes",
+        ],
+    );
+}
+
+#[test]
+fn test_swap_bytes_kernel_of_nothing_is_rejected() {
+    common::assert_build_error(
+        "tests/ui/swap_bytes_kernel_zero.rs",
+        "tests/ui/zero_width_valid.rs",
+        &[
+            "swap_bytes_kernel_zero.rs:5:13",
+            "swap_bytes needs a whole number of bytes",
+        ],
+    );
+}
+
+#[test]
+fn test_ones_complement_sum_kernel_of_nothing_is_rejected() {
+    common::assert_build_error(
+        "tests/ui/ones_complement_sum_kernel_zero.rs",
+        "tests/ui/zero_width_valid.rs",
+        &[
+            "ones_complement_sum_kernel_zero.rs:5:13",
+            "ones_complement_sum needs at least one word in a kernel",
+        ],
+    );
+}
@@ -0,0 +1,171 @@
+use rhdl_bits::Bits;
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+
+// A 4 bit adder, with no registers, i.e., a purely combinational
+// circuit.  The sum wraps on overflow.
+#[derive(Default, Clone, Circuit)]
+#[rhdl(kernel = adder)]
+pub struct Adder {}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct AdderI {
+    pub a: Bits<4>,
+    pub b: Bits<4>,
+}
+
+impl CircuitIO for Adder {
+    type I = AdderI;
+    type O = Bits<4>;
+}
+
+#[kernel]
+pub fn adder(i: AdderI, _q: AdderQ) -> (Bits<4>, AdderD) {
+    (i.a + i.b, AdderD::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_bits::bits;
+    use rhdl_core::{Circuit, CircuitDescriptor, HDLDescriptor, HDLKind};
+
+    #[test]
+    fn test_adder_matches_verilog() {
+        Adder::default()
+            .check_combinational_equivalence(100)
+            .unwrap();
+    }
+
+    // The adder, with a simulation that saturates rather than wraps
+    #[derive(Default, Clone)]
+    struct SaturatingSim(Adder);
+
+    impl CircuitIO for SaturatingSim {
+        type I = AdderI;
+        type O = Bits<4>;
+    }
+
+    impl Circuit for SaturatingSim {
+        type Q = AdderQ;
+        type D = AdderD;
+        type Z = <Adder as Circuit>::Z;
+        type S = <Adder as Circuit>::S;
+        type Update = adder;
+        const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = <Adder as Circuit>::UPDATE;
+
+        fn sim(&self, input: AdderI, state: &mut Self::S, io: &mut Self::Z) -> Bits<4> {
+            let sum = self.0.sim(input, state, io);
+            if sum < input.a {
+                Bits::<4>::mask()
+            } else {
+                sum
+            }
+        }
+
+        fn name(&self) -> &'static str {
+            "SaturatingSim"
+        }
+
+        fn descriptor(&self) -> CircuitDescriptor {
+            self.0.descriptor()
+        }
+
+        fn as_hdl(&self, kind: HDLKind) -> anyhow::Result<HDLDescriptor> {
+            self.0.as_hdl(kind)
+        }
+    }
+
+    #[test]
+    fn test_broken_sim_is_caught() {
+        let err = SaturatingSim::default()
+            .check_combinational_equivalence(100)
+            .unwrap_err();
+        // The inputs with all bits set overflow, and come right after the
+        // inputs with all bits clear
+        let err = err.to_string();
+        assert!(err.starts_with("Circuit SaturatingSim diverges from its Verilog"));
+        assert!(err.ends_with(
+            "AdderI {a: f_b4, b: f_b4} (sample 1): sim gives 0xf but the Verilog gives 0xe"
+        ));
+    }
+
+    // The adder, with the ports of its module named for a hand-written top
+    // level, and a circuit that instantiates it
+    #[derive(Default, Clone, Circuit)]
+    #[rhdl(kernel = named_adder)]
+    #[rhdl(input_port = operands)]
+    #[rhdl(output_port = sum)]
+    pub struct NamedAdder {}
+
+    impl CircuitIO for NamedAdder {
+        type I = AdderI;
+        type O = Bits<4>;
+    }
+
+    #[kernel]
+    pub fn named_adder(i: AdderI, _q: NamedAdderQ) -> (Bits<4>, NamedAdderD) {
+        (i.a + i.b, NamedAdderD::default())
+    }
+
+    #[derive(Default, Clone, Circuit)]
+    #[rhdl(kernel = wrapper)]
+    pub struct Wrapper {
+        inner: NamedAdder,
+    }
+
+    impl CircuitIO for Wrapper {
+        type I = AdderI;
+        type O = Bits<4>;
+    }
+
+    #[kernel]
+    pub fn wrapper(i: AdderI, q: WrapperQ) -> (Bits<4>, WrapperD) {
+        let mut d = WrapperD::default();
+        d.inner = i;
+        (q.inner, d)
+    }
+
+    #[test]
+    fn test_named_ports() {
+        let hdl = NamedAdder::default().as_hdl(HDLKind::Verilog).unwrap();
+        assert!(hdl
+            .body
+            .contains("(input wire[7:0] operands, output wire[3:0] sum);"));
+        assert!(hdl.body.contains("assign i = operands;"));
+        assert!(hdl.body.contains("assign sum = o;"));
+        assert_eq!(
+            hdl.ports
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            ["operands", "sum"]
+        );
+        // The parent uses the same names to instantiate it
+        let hdl = Wrapper::default().as_hdl(HDLKind::Verilog).unwrap();
+        assert!(hdl
+            .body
+            .contains("(input wire[7:0] i, output wire[3:0] o);"));
+        assert!(hdl.body.contains(".operands(d[7:0]),.sum(q[3:0])"));
+        // As do the testbench and the instantiation template
+        let inputs = [AdderI {
+            a: bits(3),
+            b: bits(4),
+        }];
+        let tm = NamedAdder::default().testbench(&inputs).unwrap();
+        assert!(tm.testbench.contains(".operands(i), .sum(o));"));
+        let template = hdl.children["inner"].instantiation_template().unwrap();
+        assert!(template.contains(".operands("));
+        assert!(template.contains(".sum("));
+    }
+
+    #[test]
+    fn test_named_ports_match_verilog() {
+        NamedAdder::default()
+            .check_combinational_equivalence(100)
+            .unwrap();
+        Wrapper::default()
+            .check_combinational_equivalence(100)
+            .unwrap();
+    }
+}
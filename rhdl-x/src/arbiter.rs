@@ -0,0 +1,226 @@
+use anyhow::Result;
+use rhdl_bits::{bits, Bits};
+use rhdl_core::note;
+use rhdl_core::root_descriptor;
+use rhdl_core::root_hdl;
+use rhdl_core::Circuit;
+use rhdl_core::CircuitDescriptor;
+use rhdl_core::CircuitIO;
+use rhdl_core::Digital;
+use rhdl_core::HDLDescriptor;
+use rhdl_core::HDLKind;
+use rhdl_macro::{kernel, Circuit, Digital};
+use rhdl_std::UnsignedMethods;
+
+use crate::{clock::Clock, dff::DFF};
+
+// Keep only the lowest set bit of `x`, i.e., a one-hot priority encoding
+// in which bit 0 has the highest priority.  Zero stays zero.
+#[kernel]
+pub fn lowest_set_bit<const N: usize>(x: Bits<N>) -> Bits<N> {
+    x & (!x + bits::<{ N }>(1))
+}
+
+// A fixed priority arbiter.  The grant is the lowest numbered request,
+// so a requester can be starved by the ones below it.
+#[derive(Default, Clone)]
+pub struct PriorityArbiter<const N: usize> {}
+
+impl<const N: usize> CircuitIO for PriorityArbiter<N> {
+    type I = Bits<N>;
+    type O = Bits<N>;
+}
+
+impl<const N: usize> Circuit for PriorityArbiter<N> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = priority_arbiter<N>;
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = priority_arbiter::<N>;
+
+    type S = Self::Q;
+
+    fn sim(&self, input: Self::I, _state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        let (outputs, _) = Self::UPDATE(input, ());
+        note("outputs", outputs);
+        outputs
+    }
+
+    fn name(&self) -> &'static str {
+        "PriorityArbiter"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        root_hdl(self, kind)
+    }
+}
+
+#[kernel]
+pub fn priority_arbiter<const N: usize>(i: Bits<N>, q: ()) -> (Bits<N>, ()) {
+    (lowest_set_bit::<{ N }>(i), q)
+}
+
+// A round robin arbiter.  The state holds a mask of the requesters that
+// come after the last one granted.  Those are served first, and once
+// none of them is requesting, the search wraps around to bit 0.  So a
+// requester that keeps asserting its request is granted within `N`
+// cycles.
+#[derive(Clone, Circuit)]
+#[rhdl(kernel = round_robin_arbiter::<N>)]
+pub struct RoundRobinArbiter<const N: usize> {
+    mask: DFF<Bits<N>>,
+}
+
+impl<const N: usize> Default for RoundRobinArbiter<N> {
+    fn default() -> Self {
+        Self {
+            mask: Bits::<N>::mask().into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct RoundRobinArbiterI<const N: usize> {
+    pub clock: Clock,
+    pub request: Bits<N>,
+}
+
+impl<const N: usize> CircuitIO for RoundRobinArbiter<N> {
+    type I = RoundRobinArbiterI<N>;
+    type O = Bits<N>;
+}
+
+#[kernel]
+pub fn round_robin_arbiter<const N: usize>(
+    i: RoundRobinArbiterI<N>,
+    q: RoundRobinArbiterQ<N>,
+) -> (Bits<N>, RoundRobinArbiterD<N>) {
+    let mut d = RoundRobinArbiterD::<N>::default();
+    let masked = i.request & q.mask;
+    let grant = if masked.any() {
+        lowest_set_bit::<{ N }>(masked)
+    } else {
+        lowest_set_bit::<{ N }>(i.request)
+    };
+    d.mask.clock = i.clock;
+    d.mask.data = q.mask;
+    if grant.any() {
+        // Everything above the granted bit
+        d.mask.data = !(grant | (grant - bits::<{ N }>(1)));
+    }
+    (grant, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn check_grant<const N: usize>(request: Bits<N>, grant: Bits<N>) {
+        // At most one grant, and only to a requester
+        assert!(grant.0.count_ones() <= 1);
+        assert_eq!(grant & request, grant);
+        // Someone is granted whenever anyone asks
+        assert_eq!(grant.any(), request.any());
+    }
+
+    #[test]
+    fn test_priority_arbiter_grants() {
+        let arbiter = PriorityArbiter::<4>::default();
+        for request in 0..16 {
+            let request = bits::<4>(request);
+            let grant = arbiter.sim(request, &mut (), &mut ());
+            check_grant(request, grant);
+            // The lowest requester always wins
+            assert!(grant.0 == 0 || grant.0.trailing_zeros() == request.0.trailing_zeros());
+        }
+    }
+
+    fn round_robin_inputs<const N: usize>(
+        requests: impl Iterator<Item = Bits<N>>,
+    ) -> Vec<RoundRobinArbiterI<N>> {
+        clocked_inputs(requests, |clock, request| RoundRobinArbiterI {
+            clock,
+            request,
+        })
+    }
+
+    // Run the arbiter, and return the request and grant for each cycle,
+    // sampled before the rising edge of the clock.
+    fn run_round_robin<const N: usize>(
+        inputs: &[RoundRobinArbiterI<N>],
+    ) -> Vec<(Bits<N>, Bits<N>)> {
+        let arbiter = RoundRobinArbiter::<N>::default();
+        let grants = run_clocked(&arbiter, &mut arbiter.init_state(), inputs);
+        inputs
+            .iter()
+            .step_by(2)
+            .map(|input| input.request)
+            .zip(grants)
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin_arbiter_grants() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let requests = (0..500).map(|_| bits::<4>(rng.gen_range(0..16)));
+        for (request, grant) in run_round_robin(&round_robin_inputs(requests)) {
+            check_grant(request, grant);
+        }
+    }
+
+    #[test]
+    fn test_round_robin_arbiter_is_fair() {
+        // Any requester that asserts its request for N cycles in a row
+        // is granted in one of them, however the others behave.
+        const N: usize = 5;
+        let mut rng = StdRng::seed_from_u64(0xfa17);
+        let requests = (0..1000)
+            .map(|_| bits::<N>(rng.gen_range(0..32)))
+            .collect::<Vec<_>>();
+        let cycles = run_round_robin(&round_robin_inputs(requests.into_iter()));
+        for window in cycles.windows(N) {
+            for bit in 0..N {
+                let requested = window
+                    .iter()
+                    .all(|(request, _)| request.0 & (1 << bit) != 0);
+                let granted = window.iter().any(|(_, grant)| grant.0 & (1 << bit) != 0);
+                assert!(!requested || granted);
+            }
+        }
+        // With everyone asking, the grant rotates through all of them
+        let cycles = run_round_robin(&round_robin_inputs((0..3 * N).map(|_| Bits::<N>::mask())));
+        for (ndx, (_, grant)) in cycles.iter().enumerate() {
+            assert_eq!(grant.0, 1 << (ndx % N));
+        }
+    }
+
+    #[test]
+    fn test_arbiters_match_verilog() {
+        let requests = (0..16).map(bits::<4>).collect::<Vec<_>>();
+        let tm = PriorityArbiter::<4>::default()
+            .testbench(&requests)
+            .unwrap();
+        assert_eq!(tm.num_cases, 16);
+        // The complement in `lowest_set_bit` must be bitwise
+        assert!(tm.testbench.contains("= ~(r0);"));
+        let mut rng = StdRng::seed_from_u64(0xab17);
+        let requests = (0..100).map(|_| bits::<4>(rng.gen_range(0..16)));
+        let inputs = round_robin_inputs(requests);
+        let rr = RoundRobinArbiter::<4>::default()
+            .testbench(&inputs)
+            .unwrap();
+        assert_eq!(rr.num_cases, 200);
+        tm.run_iverilog().unwrap();
+        rr.run_iverilog().unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+use rhdl_bits::{bits, Bits};
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+
+use crate::{clock::Clock, dff::DFF, ram::DualPortRam, synchronizer::Synchronizer};
+
+// A FIFO between two clock domains.  The producer writes on
+// `write_clock`, and the consumer reads on `read_clock`.  The data goes
+// through a dual port RAM, and each side sees the pointer of the other
+// through a synchronizer.  The pointers are gray coded (and registered)
+// before they cross, so that only one bit changes at a time.
+//
+// The pointers have one more bit than is needed to address the 8
+// entries, so that a full FIFO (the pointers are 8 apart) can be told
+// from an empty one (they are equal).  The RAM is addressed with all 4
+// bits, and so has room for 16, but never holds more than 8.
+//
+// The head of the FIFO is on `data` whenever `empty` is false, and it is
+// popped on the rising edge of `read_clock` if `read_enable` is set.  A
+// write when `full` is set is dropped.  Since the pointers take time to
+// cross, `full` and `empty` can be set for a few cycles longer than
+// needed, but never too short.
+#[derive(Default, Clone, Circuit)]
+#[rhdl(kernel = async_fifo)]
+#[rhdl(clock = write_clock)]
+#[rhdl(clock = read_clock)]
+pub struct AsyncFifo {
+    ram: DualPortRam<Bits<8>, 4>,
+    write_ptr: DFF<Bits<4>>,
+    write_gray: DFF<Bits<4>>,
+    write_sync: Synchronizer<Bits<4>>,
+    read_ptr: DFF<Bits<4>>,
+    read_gray: DFF<Bits<4>>,
+    read_sync: Synchronizer<Bits<4>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct AsyncFifoI {
+    pub write_clock: Clock,
+    pub read_clock: Clock,
+    pub write_enable: bool,
+    pub write_data: Bits<8>,
+    pub read_enable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct AsyncFifoO {
+    pub data: Bits<8>,
+    pub empty: bool,
+    pub full: bool,
+}
+
+impl CircuitIO for AsyncFifo {
+    type I = AsyncFifoI;
+    type O = AsyncFifoO;
+}
+
+#[kernel]
+pub fn async_fifo(i: AsyncFifoI, q: AsyncFifoQ) -> (AsyncFifoO, AsyncFifoD) {
+    let mut d = AsyncFifoD::default();
+    // The write side.  The FIFO is full when the write pointer is 8
+    // ahead of the read pointer, which in gray code is when the top two
+    // bits differ and the rest are the same.
+    let full = q.write_gray == (q.read_sync ^ bits::<4>(0b1100));
+    let write = i.write_enable && !full;
+    let write_ptr = if write {
+        q.write_ptr + bits::<4>(1)
+    } else {
+        q.write_ptr
+    };
+    d.ram.a.clock = i.write_clock;
+    d.ram.a.addr = q.write_ptr;
+    d.ram.a.write_enable = write;
+    d.ram.a.write_data = i.write_data;
+    d.write_ptr.clock = i.write_clock;
+    d.write_ptr.data = write_ptr;
+    d.write_gray.clock = i.write_clock;
+    d.write_gray.data = write_ptr ^ (write_ptr >> bits::<1>(1));
+    d.read_sync.clock = i.write_clock;
+    d.read_sync.data = q.read_gray;
+    // The read side.  The RAM is read at the next read pointer, so that
+    // the new head is on its output after the edge that pops the old one.
+    let empty = q.read_gray == q.write_sync;
+    let read = i.read_enable && !empty;
+    let read_ptr = if read {
+        q.read_ptr + bits::<4>(1)
+    } else {
+        q.read_ptr
+    };
+    d.ram.b.clock = i.read_clock;
+    d.ram.b.addr = read_ptr;
+    d.read_ptr.clock = i.read_clock;
+    d.read_ptr.data = read_ptr;
+    d.read_gray.clock = i.read_clock;
+    d.read_gray.data = read_ptr ^ (read_ptr >> bits::<1>(1));
+    d.write_sync.clock = i.read_clock;
+    d.write_sync.data = q.write_gray;
+    (
+        AsyncFifoO {
+            data: q.ram.b,
+            empty,
+            full,
+        },
+        d,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_core::{Circuit, ClockSpec, ClockedTestbench, OutputSampling};
+
+    // A 100MHz producer and a consumer at about 30MHz, which do not line
+    // up with each other.  The inputs are applied with the producer's
+    // clock.
+    fn clocks() -> ClockedTestbench {
+        ClockedTestbench::new(vec![
+            ClockSpec::new("write_clock", 10_000),
+            ClockSpec::new("read_clock", 33_000).with_phase(20_000),
+        ])
+    }
+
+    // Write the cycle number on the cycles picked by `write`, and read
+    // on those picked by `read`.  The clocks are filled in by the
+    // testbench.
+    fn inputs(
+        cycles: usize,
+        write: impl Fn(usize) -> bool,
+        read: impl Fn(usize) -> bool,
+    ) -> Vec<AsyncFifoI> {
+        (0..cycles)
+            .map(|ndx| AsyncFifoI {
+                write_enable: write(ndx),
+                write_data: bits(ndx as u128),
+                read_enable: read(ndx),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn written(inputs: &[AsyncFifoI]) -> Vec<Bits<8>> {
+        inputs
+            .iter()
+            .filter(|input| input.write_enable)
+            .map(|input| input.write_data)
+            .collect()
+    }
+
+    // The head of the FIFO after each edge of the read clock.  When the
+    // consumer reads on every cycle, each of those edges pops the head,
+    // so these are the values in the order that they come out.
+    fn read_out(inputs: &[AsyncFifoI]) -> Vec<Bits<8>> {
+        clocks()
+            .with_sampling(OutputSampling::Clock(1))
+            .simulate(&AsyncFifo::default(), inputs)
+            .unwrap()
+            .into_iter()
+            .filter(|output| !output.empty)
+            .map(|output| output.data)
+            .collect()
+    }
+
+    #[test]
+    fn test_async_fifo_passes_data_across() {
+        // The producer writes once every 4 fast cycles, which is slower
+        // than the consumer reads, so the FIFO never fills up
+        let inputs = inputs(100, |ndx| ndx % 4 == 0 && ndx < 60, |_| true);
+        assert_eq!(read_out(&inputs), written(&inputs));
+    }
+
+    #[test]
+    fn test_async_fifo_drops_writes_when_full() {
+        // A burst of 12 writes, with the consumer held off until long
+        // after.  Only the first 8 fit.
+        let inputs = inputs(100, |ndx| ndx < 12, |ndx| ndx >= 40);
+        // The head is seen again on each cycle until the consumer reads
+        let mut out = read_out(&inputs);
+        out.dedup();
+        assert_eq!(out, written(&inputs)[..8].to_vec());
+        let outputs = clocks().simulate(&AsyncFifo::default(), &inputs).unwrap();
+        // Sampled after each write, so full from the 8th on
+        assert!(outputs[..7].iter().all(|output| !output.full));
+        assert!(outputs[7..40].iter().all(|output| output.full));
+        assert!(!outputs[99].full);
+    }
+
+    #[test]
+    fn test_async_fifo_needs_a_spec_for_each_clock() {
+        let bench = ClockedTestbench::new(vec![ClockSpec::new("write_clock", 10_000)]);
+        let err = AsyncFifo::default()
+            .clocked_testbench(&bench, &inputs(4, |_| true, |_| true))
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("There is no ClockSpec for the clock read_clock of AsyncFifo"));
+    }
+
+    #[test]
+    fn test_async_fifo_two_clocks() {
+        let inputs = inputs(100, |ndx| ndx % 3 == 0 && ndx < 60, |ndx| ndx % 5 != 2);
+        let tm = AsyncFifo::default()
+            .clocked_testbench(&clocks().with_sampling(OutputSampling::EveryEdge), &inputs)
+            .unwrap();
+        assert!(tm.testbench.contains("`timescale 1ps/1ps"));
+        tm.run_iverilog().unwrap();
+    }
+}
@@ -85,6 +85,7 @@ endmodule
         name: module_name.into(),
         body: code,
         children: Default::default(),
+        initial: vec![],
     })
 }
 
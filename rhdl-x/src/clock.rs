@@ -1,3 +1,4 @@
+use rhdl_core::Circuit;
 use rhdl_macro::Digital;
 
 // New type for the clock
@@ -9,3 +10,45 @@ pub fn clock() -> impl Iterator<Item = Clock> {
         .chain(std::iter::once(Clock(false)))
         .cycle()
 }
+
+// Drive a circuit through one full clock cycle, as a high phase (which
+// starts with the rising edge) followed by a low phase (which starts
+// with the falling edge).  The input for each phase is built from the
+// clock level, and the outputs at the end of both phases are returned.
+// This is needed to see what circuits that act on both edges of the
+// clock do within a cycle.
+pub fn step_phases<C: Circuit>(
+    circuit: &C,
+    state: &mut C::S,
+    io: &mut C::Z,
+    input: impl Fn(Clock) -> C::I,
+) -> [C::O; 2] {
+    let high = circuit.sim(input(Clock(true)), state, io);
+    let low = circuit.sim(input(Clock(false)), state, io);
+    [high, low]
+}
+
+// The inputs for one cycle of the clock per value, each as a low phase
+// followed by a high phase (which starts with the rising edge).  The
+// input for each phase is built from the clock and the value.
+pub fn clocked_inputs<I, T: Copy>(
+    values: impl IntoIterator<Item = T>,
+    input: impl Fn(Clock, T) -> I,
+) -> Vec<I> {
+    values
+        .into_iter()
+        .flat_map(|value| [input(Clock(false), value), input(Clock(true), value)])
+        .collect()
+}
+
+// Run a circuit on inputs from `clocked_inputs`, and return the output
+// for each cycle, as sampled just before its rising edge (so the first
+// output is that of the initial state).
+pub fn run_clocked<C: Circuit>(circuit: &C, state: &mut C::S, inputs: &[C::I]) -> Vec<C::O> {
+    let mut io = C::Z::default();
+    inputs
+        .iter()
+        .map(|input| circuit.sim(*input, state, &mut io))
+        .step_by(2)
+        .collect()
+}
@@ -1,5 +1,6 @@
 use anyhow::ensure;
 use anyhow::Result;
+use rhdl_core::circuit_ports;
 use rhdl_core::root_descriptor;
 use rhdl_core::schematic::components::ComponentKind;
 use rhdl_core::schematic::components::ConstantComponent;
@@ -98,7 +99,9 @@ endmodule
         HDLDescriptor {
             name: module_name,
             body,
+            ports: circuit_ports::<Self>(),
             children: Default::default(),
+            initial: vec![],
         }
     }
 }
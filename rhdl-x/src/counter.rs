@@ -1,5 +1,6 @@
 use anyhow::Result;
-use rhdl_bits::Bits;
+use rhdl_bits::{bits, Bits};
+use rhdl_core::child_state_signals;
 use rhdl_core::note;
 use rhdl_core::note_pop_path;
 use rhdl_core::note_push_path;
@@ -11,17 +12,38 @@ use rhdl_core::CircuitIO;
 use rhdl_core::Digital;
 use rhdl_core::HDLDescriptor;
 use rhdl_core::HDLKind;
+use rhdl_core::TypedBits;
 use rhdl_macro::{kernel, Digital};
 
 use crate::{
     clock::Clock,
+    constant::Constant,
     dff::{DFF, DFFI},
 };
 
-// Next a counter with an enable signal
-#[derive(Default, Clone)]
+// Next a counter with an enable signal.  It counts up on each enabled
+// clock, and wraps to zero after `max`.  The default `max` is the
+// largest value of `N` bits, so it wraps like an `N` bit integer.
+#[derive(Clone)]
 pub struct Counter<const N: usize> {
     count: DFF<Bits<N>>,
+    max: Constant<Bits<N>>,
+}
+
+impl<const N: usize> Counter<N> {
+    // A counter modulo `max + 1`
+    pub fn new(max: Bits<N>) -> Self {
+        Self {
+            count: DFF::default(),
+            max: max.into(),
+        }
+    }
+}
+
+impl<const N: usize> Default for Counter<N> {
+    fn default() -> Self {
+        Self::new(Bits::<N>::mask())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
@@ -33,11 +55,13 @@ pub struct CounterI {
 #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
 pub struct CounterQ<const N: usize> {
     pub count: <DFF<Bits<N>> as CircuitIO>::O,
+    pub max: <Constant<Bits<N>> as CircuitIO>::O,
 }
 
 #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
 pub struct CounterD<const N: usize> {
     pub count: <DFF<Bits<N>> as CircuitIO>::I,
+    pub max: <Constant<Bits<N>> as CircuitIO>::I,
 }
 
 impl<const N: usize> CircuitIO for Counter<N> {
@@ -55,10 +79,19 @@ impl<const N: usize> Circuit for Counter<N> {
     type Update = counter<N>;
     const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = counter::<N>;
 
-    type S = (Self::Q, <DFF<Bits<N>> as Circuit>::S);
+    type S = (
+        Self::Q,
+        <DFF<Bits<N>> as Circuit>::S,
+        <Constant<Bits<N>> as Circuit>::S,
+    );
 
     fn sim(&self, input: Self::I, state: &mut Self::S, io: &mut Self::Z) -> Self::O {
         note("input", input);
+        // The maximum has no inputs, so it is settled before the count
+        // can see it
+        note_push_path("max");
+        state.0.max = self.max.sim((), &mut state.2, io);
+        note_pop_path();
         loop {
             let prev_state = state.clone();
             let (outputs, internal_inputs) = Self::UPDATE(input, state.0);
@@ -72,14 +105,20 @@ impl<const N: usize> Circuit for Counter<N> {
         }
     }
 
+    fn state_signals(&self, state: &Self::S) -> Vec<(String, TypedBits)> {
+        child_state_signals("count", self.count.state_signals(&state.1))
+            .chain(child_state_signals("max", self.max.state_signals(&state.2)))
+            .collect()
+    }
+
     fn name(&self) -> &'static str {
         "Counter"
     }
 
     fn descriptor(&self) -> CircuitDescriptor {
         let mut ret = root_descriptor(self);
-        ret.children
-            .insert("count".to_string(), self.count.descriptor());
+        ret.insert_child("count", self.count.descriptor());
+        ret.insert_child("max", self.max.descriptor());
         ret
     }
 
@@ -87,6 +126,8 @@ impl<const N: usize> Circuit for Counter<N> {
         let mut ret = root_hdl(self, kind)?;
         ret.children
             .insert("count".to_string(), self.count.as_hdl(kind)?);
+        ret.children
+            .insert("max".to_string(), self.max.as_hdl(kind)?);
         Ok(ret)
     }
 }
@@ -97,7 +138,245 @@ pub fn counter<const N: usize>(i: CounterI, q: CounterQ<N>) -> (Bits<N>, Counter
     d.count.clock = i.clock;
     d.count.data = q.count;
     if i.enable {
-        d.count.data = q.count + 1;
+        d.count.data = if q.count == q.max {
+            bits::<{ N }>(0)
+        } else {
+            q.count + 1
+        };
     }
     (q.count, d)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+    use rhdl_macro::Circuit;
+
+    fn inputs(enable: &[bool]) -> Vec<CounterI> {
+        clocked_inputs(enable.iter().copied(), |clock, enable| CounterI {
+            clock,
+            enable,
+        })
+    }
+
+    // The count before each rising edge of the clock
+    fn run<const N: usize>(counter: &Counter<N>, enable: &[bool]) -> Vec<u128> {
+        run_clocked(counter, &mut counter.init_state(), &inputs(enable))
+            .into_iter()
+            .map(|count| count.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_counter_counts_when_enabled() {
+        let enable = [true, false, true, true, false, false, true];
+        let counts = run(&Counter::<4>::default(), &enable);
+        assert_eq!(counts, [0, 1, 1, 2, 3, 3, 3]);
+    }
+
+    #[test]
+    fn test_counter_wraps() {
+        // At the largest value of its width
+        let counts = run(&Counter::<3>::default(), &[true; 10]);
+        assert_eq!(counts, [0, 1, 2, 3, 4, 5, 6, 7, 0, 1]);
+        // And after `max`, for a count that is not a power of two
+        let counts = run(&Counter::<4>::new(bits(4)), &[true; 12]);
+        assert_eq!(counts, [0, 1, 2, 3, 4, 0, 1, 2, 3, 4, 0, 1]);
+    }
+
+    #[test]
+    fn test_counter_modulo_verilog() {
+        let counter = Counter::<4>::new(bits(9));
+        let hdl = counter.as_hdl(HDLKind::Verilog).unwrap();
+        assert!(hdl.children.contains_key("max"));
+        let inputs = crate::clock::clock()
+            .zip([true, true, false].into_iter().cycle())
+            .map(|(clock, enable)| CounterI { clock, enable })
+            .take(100)
+            .collect::<Vec<_>>();
+        let tm = counter.testbench(&inputs).unwrap();
+        assert_eq!(tm.num_cases, 100);
+        tm.run_iverilog().unwrap();
+    }
+
+    #[test]
+    fn test_counter_json_netlist() {
+        let schematic = Counter::<4>::default()
+            .descriptor()
+            .schematic()
+            .unwrap()
+            .inlined();
+        let netlist = schematic.to_json_netlist();
+        let components = netlist["components"].as_array().unwrap();
+        let nets = netlist["nets"].as_array().unwrap();
+        assert_eq!(components.len(), 39);
+        assert_eq!(nets.len(), 35);
+        // The two children are flattened, leaving the DFF as the only
+        // black box, and no kernels
+        let kinds = components
+            .iter()
+            .map(|c| c["kind"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(!kinds.contains(&"kernel"));
+        let black_boxes = components
+            .iter()
+            .filter(|c| c["kind"] == "black_box")
+            .collect::<Vec<_>>();
+        assert_eq!(black_boxes.len(), 1);
+        assert_eq!(black_boxes[0]["name"], "DFF");
+        assert_eq!(black_boxes[0]["path"][0], "count_1");
+        // Every pin of a net belongs to one of the components
+        let pins = components
+            .iter()
+            .flat_map(|c| c["pins"].as_array().unwrap())
+            .map(|pin| pin["id"].clone())
+            .collect::<Vec<_>>();
+        assert!(nets
+            .iter()
+            .flat_map(|net| net["pins"].as_array().unwrap())
+            .all(|pin| pins.contains(pin)));
+        assert_eq!(netlist["inputs"].as_array().unwrap().len(), 1);
+    }
+
+    // A counter with a second counter, of the cycles in which it is not
+    // enabled, that is only built with the `debug_counters` flag
+    #[derive(Clone, Default, Circuit)]
+    #[rhdl(kernel = idle_counter)]
+    struct IdleCounter {
+        count: Counter<4>,
+        #[rhdl(cfg(flag = "debug_counters"))]
+        idle: Counter<4>,
+    }
+
+    impl CircuitIO for IdleCounter {
+        type I = CounterI;
+        type O = (Bits<4>, Bits<4>);
+    }
+
+    #[kernel]
+    fn idle_counter(i: CounterI, q: IdleCounterQ) -> ((Bits<4>, Bits<4>), IdleCounterD) {
+        let mut d = IdleCounterD::default();
+        d.count = i;
+        let mut idle = bits::<4>(0);
+        #[rhdl(cfg(flag = "debug_counters"))]
+        {
+            d.idle.clock = i.clock;
+            d.idle.enable = !i.enable;
+            idle = q.idle;
+        }
+        ((q.count, idle), d)
+    }
+
+    #[test]
+    fn test_children_can_be_configured_out() -> Result<()> {
+        use rhdl_core::{with_compile_options, CompileOptions};
+
+        let on = CompileOptions::default().with_flag("debug_counters", true);
+        let off = CompileOptions::default().with_flag("debug_counters", false);
+        let circuit = IdleCounter::default();
+        let inputs = inputs(&[true, false, true, false, false, true]);
+        // The outputs before each rising edge of the clock
+        let run = || {
+            run_clocked(&circuit, &mut circuit.init_state(), &inputs)
+                .into_iter()
+                .map(|(count, idle)| (count.0, idle.0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            with_compile_options(&on, run),
+            [(0, 0), (1, 0), (1, 1), (2, 1), (2, 2), (2, 3)]
+        );
+        assert_eq!(
+            with_compile_options(&off, run),
+            [(0, 0), (1, 0), (1, 0), (2, 0), (2, 0), (2, 0)]
+        );
+        // Without the flag, the child is left out, and its fields of the
+        // D and Q have no bits
+        let with = with_compile_options(&on, || circuit.descriptor());
+        let without = with_compile_options(&off, || circuit.descriptor());
+        assert!(with.children.contains_key("idle"));
+        assert!(!without.children.contains_key("idle"));
+        assert!(with.flags["debug_counters"]);
+        assert!(!without.flags["debug_counters"]);
+        assert_eq!(with.d_kind.bits(), 2 * without.d_kind.bits());
+        assert_eq!(with.q_kind.bits(), 2 * without.q_kind.bits());
+        // The flag must be known when the HDL is generated
+        let err = circuit.as_hdl(HDLKind::Verilog).unwrap_err();
+        assert!(err.to_string().contains("Unknown flag debug_counters"));
+        for options in [on, off] {
+            with_compile_options(&options, || -> Result<()> {
+                let hdl = circuit.as_hdl(HDLKind::Verilog)?;
+                assert_eq!(
+                    hdl.children.contains_key("idle"),
+                    options.flags["debug_counters"]
+                );
+                circuit.testbench(&inputs)?.run_iverilog()
+            })?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_initial_state_matches_hdl() {
+        rhdl_core::verify_initial_state(&Counter::<4>::new(bits(9))).unwrap();
+    }
+
+    // A counter whose HDL starts the count at 5, rather than at 0
+    #[derive(Clone, Default)]
+    struct LateCounter(Counter<4>);
+
+    impl CircuitIO for LateCounter {
+        type I = CounterI;
+        type O = Bits<4>;
+    }
+
+    impl Circuit for LateCounter {
+        type Q = CounterQ<4>;
+        type D = CounterD<4>;
+        type Z = ();
+        type S = <Counter<4> as Circuit>::S;
+        type Update = counter<4>;
+        const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = <Counter<4> as Circuit>::UPDATE;
+
+        fn init_state(&self) -> Self::S {
+            self.0.init_state()
+        }
+
+        fn state_signals(&self, state: &Self::S) -> Vec<(String, TypedBits)> {
+            self.0.state_signals(state)
+        }
+
+        fn sim(&self, input: CounterI, state: &mut Self::S, io: &mut Self::Z) -> Bits<4> {
+            self.0.sim(input, state, io)
+        }
+
+        fn name(&self) -> &'static str {
+            "LateCounter"
+        }
+
+        fn descriptor(&self) -> CircuitDescriptor {
+            self.0.descriptor()
+        }
+
+        fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+            let mut hdl = self.0.as_hdl(kind)?;
+            let count = hdl.children.get_mut("count").unwrap();
+            count.initial[0].value = bits::<4>(5).typed_bits();
+            Ok(hdl)
+        }
+    }
+
+    #[test]
+    fn test_initial_state_mismatch_is_caught() {
+        let counter = LateCounter::default();
+        let err = rhdl_core::verify_initial_state(&counter).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The initial state of circuit LateCounter differs between the simulation and the HDL:\n\
+             register LateCounter.count starts at 0_b4 in the simulation, but at 5_b4 in the HDL"
+        );
+        // The testbench runs the same check
+        assert!(counter.testbench(&[]).is_err());
+    }
+}
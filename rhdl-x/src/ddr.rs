@@ -0,0 +1,660 @@
+use anyhow::ensure;
+use anyhow::Result;
+use rhdl_core::as_verilog_literal;
+use rhdl_core::circuit_ports;
+use rhdl_core::note;
+use rhdl_core::path::Path;
+use rhdl_core::root_descriptor;
+use rhdl_core::Circuit;
+use rhdl_core::CircuitDescriptor;
+use rhdl_core::CircuitIO;
+use rhdl_core::HDLDescriptor;
+use rhdl_core::HDLKind;
+use rhdl_core::{Digital, DigitalFn};
+use rhdl_macro::Digital;
+
+use crate::clock::Clock;
+use crate::dff::DFFI;
+use crate::ram::slice;
+
+// IO boundary primitives.
+//
+// These circuits act on the falling edge of the clock (or on both edges),
+// which the rest of the design does not model.  They exist only to talk to
+// external chips that need data launched or captured on the falling edge,
+// or at double data rate.  Place them directly at the pins of the design,
+// and keep everything else on the rising edge.  Use `step_phases` in
+// `clock.rs` to simulate them, so that both edges of each cycle are seen.
+
+// A register that captures its input on the falling edge of the clock.
+#[derive(Default, Clone)]
+pub struct NegEdgeReg<T: Digital> {
+    init: T,
+}
+
+impl<T: Digital> From<T> for NegEdgeReg<T> {
+    fn from(init: T) -> Self {
+        Self { init }
+    }
+}
+
+impl<T: Digital> CircuitIO for NegEdgeReg<T> {
+    type I = DFFI<T>;
+    type O = T;
+}
+
+impl<T: Digital> DigitalFn for NegEdgeReg<T> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<T: Digital + Default> Circuit for NegEdgeReg<T> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |i, _| (i.data, ());
+
+    type S = DFFI<T>;
+
+    fn init_state(&self) -> Self::S {
+        DFFI {
+            clock: Clock(false),
+            data: self.init,
+        }
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        let output = if !input.clock.0 && state.clock.0 {
+            input.data
+        } else {
+            state.data
+        };
+        state.clock = input.clock;
+        state.data = output;
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "NegEdgeReg"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        self.as_verilog()
+    }
+}
+
+impl<T: Digital + Default> NegEdgeReg<T> {
+    fn as_verilog(&self) -> Result<HDLDescriptor> {
+        let module_name = self.descriptor().unique_name;
+        let i_kind = <Self as CircuitIO>::I::static_kind();
+        let input_bits = i_kind.bits() - 1;
+        let output_bits = T::bits() - 1;
+        let clock = slice("i", i_kind.clone(), &Path::default().field("clock"))?;
+        let data = slice("i", i_kind.clone(), &Path::default().field("data"))?;
+        let init = as_verilog_literal(&self.init.typed_bits());
+        let body = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output reg[{output_bits}:0] o);
+   wire clk;
+   wire[{output_bits}:0] d;
+   assign clk = {clock};
+   assign d = {data};
+   initial begin
+      o = {init};
+   end
+   always @(negedge clk) begin
+      o <= d;
+   end
+endmodule
+"
+        );
+        Ok(HDLDescriptor {
+            name: module_name,
+            body,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial: vec![],
+        })
+    }
+}
+
+// A double data rate output.  Both values are captured on the rising edge
+// of the clock.  The `rise` value is driven out while the clock is high,
+// and the `fall` value while it is low, so the output carries two values
+// per cycle.
+//
+// The generated Verilog is a vendor agnostic template that does not use
+// the clock as data.  Both values are captured on the rising edge, and
+// the output is the XOR of a register that is loaded on the rising edge
+// and one that is loaded on the falling edge:
+//
+//   q_fall <= fall            (on the rising edge)
+//   q_pos <= rise ^ q_neg     (on the rising edge)
+//   q_neg <= q_fall ^ q_pos   (on the falling edge)
+//   out = q_pos ^ q_neg
+//
+// so that after the rising edge the output is `rise`, and after the
+// falling edge it is `fall`.  The output only changes when one of the
+// registers does, so it does not glitch.
+//
+// To use a vendor primitive instead, name an external module with
+// `with_external_cell`.  It is instantiated once per bit with the ports
+// `(.C(clk), .D1(rise), .D2(fall), .Q(out))`, which match a Xilinx `ODDR`
+// in `SAME_EDGE` mode.  For other vendors, write a thin wrapper module
+// with these ports.
+#[derive(Clone)]
+pub struct DdrOut<T: Digital> {
+    external_cell: Option<String>,
+    marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Digital> Default for DdrOut<T> {
+    fn default() -> Self {
+        Self {
+            external_cell: None,
+            marker: Default::default(),
+        }
+    }
+}
+
+impl<T: Digital> DdrOut<T> {
+    pub fn with_external_cell(mut self, name: &str) -> Self {
+        self.external_cell = Some(name.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct DdrOutI<T: Digital> {
+    pub clock: Clock,
+    pub rise: T,
+    pub fall: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DdrOutS<T: Digital> {
+    clock: Clock,
+    rise: T,
+    fall: T,
+}
+
+impl<T: Digital> CircuitIO for DdrOut<T> {
+    type I = DdrOutI<T>;
+    type O = T;
+}
+
+impl<T: Digital> DigitalFn for DdrOut<T> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<T: Digital + Default> Circuit for DdrOut<T> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    // The path from the value that goes out while the clock is high
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |i, _| (i.rise, ());
+
+    type S = DdrOutS<T>;
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        if input.clock.0 && !state.clock.0 {
+            state.rise = input.rise;
+            state.fall = input.fall;
+        }
+        state.clock = input.clock;
+        let output = if input.clock.0 {
+            state.rise
+        } else {
+            state.fall
+        };
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "DdrOut"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        self.as_verilog()
+    }
+}
+
+impl<T: Digital + Default> DdrOut<T> {
+    fn as_verilog(&self) -> Result<HDLDescriptor> {
+        let module_name = self.descriptor().unique_name;
+        let i_kind = <Self as CircuitIO>::I::static_kind();
+        let input_bits = i_kind.bits() - 1;
+        let data_bits = T::bits() - 1;
+        let clock = slice("i", i_kind.clone(), &Path::default().field("clock"))?;
+        let rise = slice("i", i_kind.clone(), &Path::default().field("rise"))?;
+        let fall = slice("i", i_kind.clone(), &Path::default().field("fall"))?;
+        let zero = as_verilog_literal(&T::default().typed_bits());
+        let cells = match &self.external_cell {
+            Some(cell) => format!(
+                "
+   genvar n;
+   generate
+      for (n = 0; n <= {data_bits}; n = n + 1) begin : ddr
+         {cell} cell(.C(clk), .D1(d_rise[n]), .D2(d_fall[n]), .Q(o[n]));
+      end
+   endgenerate"
+            ),
+            None => format!(
+                "
+   reg[{data_bits}:0] q_fall;
+   reg[{data_bits}:0] q_pos;
+   reg[{data_bits}:0] q_neg;
+   initial begin
+      q_fall = {zero};
+      q_pos = {zero};
+      q_neg = {zero};
+   end
+   always @(posedge clk) begin
+      q_fall <= d_fall;
+      q_pos <= d_rise ^ q_neg;
+   end
+   always @(negedge clk) begin
+      q_neg <= q_fall ^ q_pos;
+   end
+   assign o = q_pos ^ q_neg;"
+            ),
+        };
+        let body = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output wire[{data_bits}:0] o);
+   wire clk;
+   wire[{data_bits}:0] d_rise;
+   wire[{data_bits}:0] d_fall;
+   assign clk = {clock};
+   assign d_rise = {rise};
+   assign d_fall = {fall};{cells}
+endmodule
+"
+        );
+        Ok(HDLDescriptor {
+            name: module_name,
+            body,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial: vec![],
+        })
+    }
+}
+
+// A double data rate input.  The data is sampled on both edges of the
+// clock, and each rising edge presents the pair sampled during the
+// previous cycle: `rise` from the previous rising edge, and `fall` from
+// the falling edge that followed it.
+//
+// As with `DdrOut`, the generated Verilog is a vendor agnostic template.
+// An external module named with `with_external_cell` is instantiated once
+// per bit with the ports `(.C(clk), .D(data), .Q1(rise), .Q2(fall))`,
+// which match a Xilinx `IDDR` in `SAME_EDGE_PIPELINED` mode.
+#[derive(Clone)]
+pub struct DdrIn<T: Digital> {
+    external_cell: Option<String>,
+    marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Digital> Default for DdrIn<T> {
+    fn default() -> Self {
+        Self {
+            external_cell: None,
+            marker: Default::default(),
+        }
+    }
+}
+
+impl<T: Digital> DdrIn<T> {
+    pub fn with_external_cell(mut self, name: &str) -> Self {
+        self.external_cell = Some(name.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct DdrInI<T: Digital> {
+    pub clock: Clock,
+    pub data: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct DdrInO<T: Digital> {
+    pub rise: T,
+    pub fall: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DdrInS<T: Digital> {
+    clock: Clock,
+    rise: T,
+    fall: T,
+    output: DdrInO<T>,
+}
+
+impl<T: Digital> CircuitIO for DdrIn<T> {
+    type I = DdrInI<T>;
+    type O = DdrInO<T>;
+}
+
+impl<T: Digital> DigitalFn for DdrIn<T> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<T: Digital + Default> Circuit for DdrIn<T> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    // The path from the pin to the values sampled on either edge
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |i, _| {
+        (
+            DdrInO {
+                rise: i.data,
+                fall: i.data,
+            },
+            (),
+        )
+    };
+
+    type S = DdrInS<T>;
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        if input.clock.0 && !state.clock.0 {
+            state.output = DdrInO {
+                rise: state.rise,
+                fall: state.fall,
+            };
+            state.rise = input.data;
+        }
+        if !input.clock.0 && state.clock.0 {
+            state.fall = input.data;
+        }
+        state.clock = input.clock;
+        note("output", state.output);
+        state.output
+    }
+
+    fn name(&self) -> &'static str {
+        "DdrIn"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        self.as_verilog()
+    }
+}
+
+impl<T: Digital + Default> DdrIn<T> {
+    fn as_verilog(&self) -> Result<HDLDescriptor> {
+        let module_name = self.descriptor().unique_name;
+        let i_kind = <Self as CircuitIO>::I::static_kind();
+        let o_kind = <Self as CircuitIO>::O::static_kind();
+        let input_bits = i_kind.bits() - 1;
+        let output_bits = o_kind.bits() - 1;
+        let data_bits = T::bits() - 1;
+        let clock = slice("i", i_kind.clone(), &Path::default().field("clock"))?;
+        let data = slice("i", i_kind.clone(), &Path::default().field("data"))?;
+        let o_rise = slice("o", o_kind.clone(), &Path::default().field("rise"))?;
+        let o_fall = slice("o", o_kind.clone(), &Path::default().field("fall"))?;
+        let zero = as_verilog_literal(&T::default().typed_bits());
+        let cells = match &self.external_cell {
+            Some(cell) => format!(
+                "
+   wire[{data_bits}:0] q_rise;
+   wire[{data_bits}:0] q_fall;
+   genvar n;
+   generate
+      for (n = 0; n <= {data_bits}; n = n + 1) begin : ddr
+         {cell} cell(.C(clk), .D(d[n]), .Q1(q_rise[n]), .Q2(q_fall[n]));
+      end
+   endgenerate"
+            ),
+            None => format!(
+                "
+   reg[{data_bits}:0] s_rise;
+   reg[{data_bits}:0] s_fall;
+   reg[{data_bits}:0] q_rise;
+   reg[{data_bits}:0] q_fall;
+   initial begin
+      s_rise = {zero};
+      s_fall = {zero};
+      q_rise = {zero};
+      q_fall = {zero};
+   end
+   always @(negedge clk) begin
+      s_fall <= d;
+   end
+   always @(posedge clk) begin
+      q_rise <= s_rise;
+      q_fall <= s_fall;
+      s_rise <= d;
+   end"
+            ),
+        };
+        let body = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output wire[{output_bits}:0] o);
+   wire clk;
+   wire[{data_bits}:0] d;
+   assign clk = {clock};
+   assign d = {data};{cells}
+   assign {o_rise} = q_rise;
+   assign {o_fall} = q_fall;
+endmodule
+"
+        );
+        Ok(HDLDescriptor {
+            name: module_name,
+            body,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rhdl_bits::alias::*;
+
+    use super::*;
+    use crate::clock::step_phases;
+
+    #[test]
+    fn test_neg_edge_reg_updates_on_falling_edge() {
+        let reg = NegEdgeReg::<b4>::default();
+        let mut state = reg.init_state();
+        let mut waveform = vec![];
+        for data in [3, 5, 9] {
+            let [high, low] = step_phases(&reg, &mut state, &mut (), |clock| DFFI {
+                clock,
+                data: b4(data),
+            });
+            waveform.extend([high, low]);
+        }
+        // Nothing changes on the rising edge, and each falling edge
+        // captures the data for that cycle.
+        assert_eq!(waveform, [b4(0), b4(3), b4(3), b4(5), b4(5), b4(9)]);
+    }
+
+    #[test]
+    fn test_ddr_out_waveform() {
+        let ddr = DdrOut::<b4>::default();
+        let mut state = ddr.init_state();
+        let pairs = [(1, 2), (3, 4), (5, 6), (7, 8)];
+        let mut waveform = vec![];
+        for (rise, fall) in pairs {
+            let phases = step_phases(&ddr, &mut state, &mut (), |clock| DdrOutI {
+                clock,
+                rise: b4(rise),
+                fall: b4(fall),
+            });
+            waveform.extend(phases);
+        }
+        // Two values go out per cycle, in order
+        assert_eq!(waveform, (1..=8).map(b4).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ddr_loopback() {
+        // Feed the output of a DDR output into a DDR input, and check
+        // that the pairs come back out a cycle later.
+        let ddr_out = DdrOut::<b4>::default();
+        let ddr_in = DdrIn::<b4>::default();
+        let mut out_state = ddr_out.init_state();
+        let mut in_state = ddr_in.init_state();
+        let mut received = vec![];
+        for (rise, fall) in [(1, 2), (3, 4), (5, 6), (0, 0), (0, 0)] {
+            for clock in [Clock(true), Clock(false)] {
+                let data = ddr_out.sim(
+                    DdrOutI {
+                        clock,
+                        rise: b4(rise),
+                        fall: b4(fall),
+                    },
+                    &mut out_state,
+                    &mut (),
+                );
+                let output = ddr_in.sim(DdrInI { clock, data }, &mut in_state, &mut ());
+                if clock.0 {
+                    received.push((output.rise, output.fall));
+                }
+            }
+        }
+        // Each pair is presented on the rising edge after the cycle
+        // in which it was launched.
+        assert_eq!(
+            received,
+            [
+                (b4(0), b4(0)),
+                (b4(1), b4(2)),
+                (b4(3), b4(4)),
+                (b4(5), b4(6)),
+                (b4(0), b4(0))
+            ]
+        );
+    }
+
+    fn snapshot(hdl: HDLDescriptor) -> String {
+        hdl.body.replace(&hdl.name, "dut")
+    }
+
+    #[test]
+    fn test_neg_edge_reg_verilog() {
+        let hdl = NegEdgeReg::<b4>::from(b4(2))
+            .as_hdl(HDLKind::Verilog)
+            .unwrap();
+        assert_eq!(
+            snapshot(hdl),
+            "
+module dut(input wire[4:0] i, output reg[3:0] o);
+   wire clk;
+   wire[3:0] d;
+   assign clk = i[0:0];
+   assign d = i[4:1];
+   initial begin
+      o = 4'b0010;
+   end
+   always @(negedge clk) begin
+      o <= d;
+   end
+endmodule
+"
+        );
+    }
+
+    #[test]
+    fn test_ddr_out_verilog() {
+        let hdl = DdrOut::<b4>::default().as_hdl(HDLKind::Verilog).unwrap();
+        assert_eq!(
+            snapshot(hdl),
+            "
+module dut(input wire[8:0] i, output wire[3:0] o);
+   wire clk;
+   wire[3:0] d_rise;
+   wire[3:0] d_fall;
+   assign clk = i[0:0];
+   assign d_rise = i[4:1];
+   assign d_fall = i[8:5];
+   reg[3:0] q_fall;
+   reg[3:0] q_pos;
+   reg[3:0] q_neg;
+   initial begin
+      q_fall = 4'b0000;
+      q_pos = 4'b0000;
+      q_neg = 4'b0000;
+   end
+   always @(posedge clk) begin
+      q_fall <= d_fall;
+      q_pos <= d_rise ^ q_neg;
+   end
+   always @(negedge clk) begin
+      q_neg <= q_fall ^ q_pos;
+   end
+   assign o = q_pos ^ q_neg;
+endmodule
+"
+        );
+    }
+
+    #[test]
+    fn test_ddr_external_cells() {
+        let hdl = DdrOut::<b4>::default()
+            .with_external_cell("ODDR")
+            .as_hdl(HDLKind::Verilog)
+            .unwrap();
+        assert!(hdl
+            .body
+            .contains("ODDR cell(.C(clk), .D1(d_rise[n]), .D2(d_fall[n]), .Q(o[n]));"));
+        assert!(!hdl.body.contains("always"));
+        let hdl = DdrIn::<b4>::default()
+            .with_external_cell("IDDR")
+            .as_hdl(HDLKind::Verilog)
+            .unwrap();
+        assert!(hdl
+            .body
+            .contains("IDDR cell(.C(clk), .D(d[n]), .Q1(q_rise[n]), .Q2(q_fall[n]));"));
+        assert!(hdl.body.contains("assign o[3:0] = q_rise;"));
+        assert!(hdl.body.contains("assign o[7:4] = q_fall;"));
+        assert!(!hdl.body.contains("always"));
+    }
+}
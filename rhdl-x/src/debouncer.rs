@@ -0,0 +1,173 @@
+use rhdl_bits::{bits, Bits};
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+use rhdl_std::UnsignedMethods;
+
+use crate::{
+    clock::Clock,
+    dff::DFF,
+    synchronizer::{Jitter, Synchronizer},
+};
+
+// Debounce an asynchronous input, such as a push button.  The input is
+// first brought into the clock domain by a synchronizer.  The output
+// follows it only once it has differed from the output for 2^W cycles
+// in a row, so any glitch or bounce shorter than that is ignored.
+#[derive(Default, Clone, Circuit)]
+#[rhdl(kernel = debouncer::<W>)]
+pub struct Debouncer<const W: usize> {
+    input: Synchronizer<bool>,
+    count: DFF<Bits<W>>,
+    state: DFF<bool>,
+}
+
+impl<const W: usize> Debouncer<W> {
+    // Simulate with jitter on the input (see `Jitter`)
+    pub fn with_jitter(jitter: Jitter) -> Self {
+        Self {
+            input: Synchronizer::default().with_jitter(jitter),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct DebouncerI {
+    pub clock: Clock,
+    pub input: bool,
+}
+
+impl<const W: usize> CircuitIO for Debouncer<W> {
+    type I = DebouncerI;
+    type O = bool;
+}
+
+#[kernel]
+pub fn debouncer<const W: usize>(i: DebouncerI, q: DebouncerQ<W>) -> (bool, DebouncerD<W>) {
+    let mut d = DebouncerD::<W>::default();
+    d.input.clock = i.clock;
+    d.input.data = i.input;
+    d.count.clock = i.clock;
+    d.state.clock = i.clock;
+    d.state.data = q.state;
+    // The count restarts whenever the input agrees with the output
+    d.count.data = bits::<{ W }>(0);
+    if q.input != q.state {
+        if q.count.all() {
+            d.state.data = q.input;
+        } else {
+            d.count.data = q.count + bits::<{ W }>(1);
+        }
+    }
+    (q.state, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+    use rhdl_core::Circuit;
+
+    // Hold each level for the given number of cycles.  The first
+    // `bounces` cycles of each level alternate with its complement, as
+    // the contacts of a switch do when they settle.
+    fn bouncy(levels: &[(bool, usize)], bounces: usize) -> Vec<bool> {
+        levels
+            .iter()
+            .flat_map(|&(level, cycles)| {
+                (0..cycles).map(move |ndx| {
+                    if ndx < bounces && ndx % 2 == 0 {
+                        !level
+                    } else {
+                        level
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn inputs(levels: &[bool]) -> Vec<DebouncerI> {
+        clocked_inputs(levels.iter().copied(), |clock, input| DebouncerI {
+            clock,
+            input,
+        })
+    }
+
+    // The output for each cycle, sampled before the rising edge
+    fn run<const W: usize>(levels: &[bool]) -> Vec<bool> {
+        run_with_jitter::<W>(levels, Jitter::default())
+    }
+
+    fn run_with_jitter<const W: usize>(levels: &[bool], jitter: Jitter) -> Vec<bool> {
+        let debouncer = Debouncer::<W>::with_jitter(jitter);
+        run_clocked(&debouncer, &mut debouncer.init_state(), &inputs(levels))
+    }
+
+    // The number of times that the output changes
+    fn edges(outputs: &[bool]) -> usize {
+        outputs.windows(2).filter(|w| w[0] != w[1]).count()
+    }
+
+    #[test]
+    fn test_debouncer_rejects_glitches() {
+        // With W = 3, a change must last 8 cycles to get through
+        for width in 1..8 {
+            let levels = bouncy(&[(false, 10), (true, width), (false, 20)], 0);
+            assert!(run::<3>(&levels).iter().all(|x| !x));
+        }
+        let levels = bouncy(&[(false, 10), (true, 8), (false, 20)], 0);
+        assert_eq!(edges(&run::<3>(&levels)), 2);
+    }
+
+    #[test]
+    fn test_debouncer_passes_stable_changes() {
+        let levels = bouncy(&[(false, 10), (true, 30), (false, 30)], 0);
+        let outputs = run::<3>(&levels);
+        // Two cycles in the synchronizer, and then 8 to count
+        let rise = outputs.iter().position(|x| *x).unwrap();
+        assert_eq!(rise, 10 + 2 + 8);
+        let fall = rise + outputs[rise..].iter().position(|x| !x).unwrap();
+        assert_eq!(fall, 40 + 2 + 8);
+        assert_eq!(edges(&outputs), 2);
+    }
+
+    #[test]
+    fn test_debouncer_ignores_bounces() {
+        // Each change bounces for 5 cycles before it settles.  The output
+        // still changes exactly once per change of level.
+        let levels = bouncy(&[(false, 20), (true, 40), (false, 40), (true, 40)], 5);
+        let outputs = run::<3>(&levels);
+        assert_eq!(edges(&outputs), 3);
+        assert!(outputs[outputs.len() - 1]);
+    }
+
+    #[test]
+    fn test_debouncer_with_jitter() {
+        for seed in 0..16 {
+            let jitter = Jitter {
+                max_cycles: 2,
+                seed,
+            };
+            // A clean change arrives up to 2 cycles late
+            let levels = bouncy(&[(false, 10), (true, 30)], 0);
+            let outputs = run_with_jitter::<3>(&levels, jitter);
+            let rise = outputs.iter().position(|x| *x).unwrap();
+            assert!((20..=22).contains(&rise));
+            // Jitter on a bouncing input does not let a bounce through
+            let levels = bouncy(&[(false, 20), (true, 40), (false, 40), (true, 40)], 5);
+            let outputs = run_with_jitter::<3>(&levels, jitter);
+            assert_eq!(edges(&outputs), 3);
+            assert!(outputs[outputs.len() - 1]);
+        }
+    }
+
+    #[test]
+    fn test_debouncer_testbench() {
+        let levels = bouncy(&[(false, 10), (true, 30), (false, 30)], 5);
+        let tm = Debouncer::<3>::default()
+            .testbench(&inputs(&levels))
+            .unwrap();
+        assert_eq!(tm.num_cases, 140);
+        tm.run_iverilog().unwrap();
+    }
+}
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Result;
+use rhdl_core::circuit_ports;
 use rhdl_core::constraint_input_synchronous;
 use rhdl_core::constraint_must_clock;
 use rhdl_core::constraint_not_constant_valued;
@@ -26,7 +27,8 @@ use rhdl_core::EdgeType;
 use rhdl_core::HDLDescriptor;
 use rhdl_core::HDLKind;
 use rhdl_core::Kind;
-use rhdl_core::{as_verilog_literal, Digital, DigitalFn};
+use rhdl_core::TypedBits;
+use rhdl_core::{initial_block, Digital, DigitalFn, HDLInitial};
 use rhdl_macro::Digital;
 
 use crate::clock::Clock;
@@ -119,6 +121,10 @@ impl<T: Digital + Default> Circuit for DFF<T> {
         }
     }
 
+    fn state_signals(&self, state: &Self::S) -> Vec<(String, TypedBits)> {
+        vec![(String::new(), state.data.typed_bits())]
+    }
+
     fn sim(&self, input: Self::I, state: &mut Self::S, io: &mut Self::Z) -> Self::O {
         note("input", input);
         let output = if input.clock.0 && !state.clock.0 {
@@ -212,7 +218,12 @@ impl<T: Digital + Default> DFF<T> {
         let module_name = self.descriptor().unique_name;
         let input_bits = T::bits();
         let output_bits = T::bits().saturating_sub(1);
-        let init = as_verilog_literal(&self.init.typed_bits());
+        let initial = vec![HDLInitial {
+            name: String::new(),
+            reg: "o".into(),
+            value: self.init.typed_bits(),
+        }];
+        let init = initial_block(&initial);
         let code = format!(
             "
 module {module_name}(input wire[{input_bits}:0] i, output reg[{output_bits}:0] o);
@@ -220,10 +231,7 @@ module {module_name}(input wire[{input_bits}:0] i, output reg[{output_bits}:0] o
    wire[{output_bits}:0] d;
    assign clk = i[0];
    assign d = i[{input_bits}:1];
-   initial begin
-      o = {init};
-    end
-    always @(posedge clk) begin
+{init}    always @(posedge clk) begin
         o <= d;
     end
 endmodule
@@ -232,7 +240,9 @@ endmodule
         HDLDescriptor {
             name: module_name,
             body: code,
+            ports: circuit_ports::<Self>(),
             children: Default::default(),
+            initial,
         }
     }
 }
@@ -0,0 +1,103 @@
+use rhdl_bits::{bits, Bits};
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+
+use crate::{clock::Clock, counter::Counter, dff::DFF, synchronizer::Synchronizer};
+
+// Carry a count from a fast clock domain to a slow one, as the pointers
+// of an asynchronous FIFO are.  The count is gray coded and registered
+// in the fast domain, so that only one bit changes at a time on the
+// wires that cross, and then brought into the slow domain by a
+// synchronizer.  The output is the count as seen in the slow domain
+// (still gray coded).
+#[derive(Default, Clone, Circuit)]
+#[rhdl(kernel = gray_crossing::<N>)]
+#[rhdl(clock = fast)]
+#[rhdl(clock = slow)]
+pub struct GrayCrossing<const N: usize> {
+    count: Counter<N>,
+    gray: DFF<Bits<N>>,
+    sync: Synchronizer<Bits<N>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct GrayCrossingI {
+    pub fast: Clock,
+    pub slow: Clock,
+    pub enable: bool,
+}
+
+impl<const N: usize> CircuitIO for GrayCrossing<N> {
+    type I = GrayCrossingI;
+    type O = Bits<N>;
+}
+
+#[kernel]
+pub fn gray_crossing<const N: usize>(
+    i: GrayCrossingI,
+    q: GrayCrossingQ<N>,
+) -> (Bits<N>, GrayCrossingD<N>) {
+    let mut d = GrayCrossingD::<N>::default();
+    d.count.clock = i.fast;
+    d.count.enable = i.enable;
+    d.gray.clock = i.fast;
+    d.gray.data = q.count ^ (q.count >> bits::<1>(1));
+    d.sync.clock = i.slow;
+    d.sync.data = q.gray;
+    (q.sync, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_core::{Circuit, ClockSpec, ClockedTestbench, OutputSampling};
+
+    // A 100MHz producer and a consumer at about 30MHz, which do not line
+    // up with each other
+    fn clocks() -> ClockedTestbench {
+        ClockedTestbench::new(vec![
+            ClockSpec::new("fast", 10_000),
+            ClockSpec::new("slow", 33_000).with_phase(20_000),
+        ])
+    }
+
+    // The clocks are filled in by the testbench
+    fn inputs(enables: &[bool]) -> Vec<GrayCrossingI> {
+        enables
+            .iter()
+            .map(|&enable| GrayCrossingI {
+                enable,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn enables() -> Vec<bool> {
+        (0..40).map(|ndx| ndx % 7 != 3).collect()
+    }
+
+    #[test]
+    fn test_gray_crossing_two_clocks() {
+        let tm = GrayCrossing::<4>::default()
+            .clocked_testbench(&clocks(), &inputs(&enables()))
+            .unwrap();
+        assert_eq!(tm.num_cases, 40);
+        assert!(tm.testbench.contains("`timescale 1ps/1ps"));
+        assert!(tm.testbench.contains("#20000;"));
+        tm.run_iverilog().unwrap();
+    }
+
+    #[test]
+    fn test_gray_crossing_sampled_in_both_domains() {
+        let tm = GrayCrossing::<4>::default()
+            .clocked_testbench(
+                &clocks().with_sampling(OutputSampling::EveryEdge),
+                &inputs(&enables()),
+            )
+            .unwrap();
+        // The 79 edges of the fast clock up to the last input, and the 21
+        // of the 24 edges of the slow clock that do not line up with them
+        assert_eq!(tm.num_cases, 79 + 21);
+        tm.run_iverilog().unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use rhdl_bits::{bits, Bits};
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+use rhdl_std::UnsignedMethods;
+
+use crate::{clock::Clock, constant::Constant, dff::DFF};
+
+// A Fibonacci LFSR.  On each enabled clock, the register shifts up by
+// one, and the parity of the tapped bits (those set in `taps`) is shifted
+// in at the bottom.  The output is the current value of the register.
+// The seed must not be zero, or the register stays at zero forever.
+// The feedback is probed, and the children are connected through named
+// wires, so both can be found in a waveform viewer.
+#[derive(Clone, Circuit)]
+#[rhdl(kernel = lfsr::<W>)]
+#[rhdl(named_child_wires)]
+pub struct Lfsr<const W: usize> {
+    taps: Constant<Bits<W>>,
+    state: DFF<Bits<W>>,
+}
+
+impl<const W: usize> Lfsr<W> {
+    pub fn new(taps: Bits<W>, seed: Bits<W>) -> Self {
+        Self {
+            taps: taps.into(),
+            state: seed.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct LfsrI {
+    pub clock: Clock,
+    pub enable: bool,
+}
+
+impl<const W: usize> CircuitIO for Lfsr<W> {
+    type I = LfsrI;
+    type O = Bits<W>;
+}
+
+#[kernel]
+pub fn lfsr<const W: usize>(i: LfsrI, q: LfsrQ<W>) -> (Bits<W>, LfsrD<W>) {
+    let mut d = LfsrD::<W>::default();
+    #[rhdl(probe)]
+    let feedback = if (q.state & q.taps).xor() {
+        bits::<{ W }>(1)
+    } else {
+        bits::<{ W }>(0)
+    };
+    d.state.clock = i.clock;
+    d.state.data = if i.enable {
+        (q.state << bits::<1>(1)) | feedback
+    } else {
+        q.state
+    };
+    (q.state, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+    use rhdl_core::{circuit::manifest::DesignManifest, Circuit, HDLKind};
+    use std::collections::HashSet;
+
+    // The software model of the LFSR
+    fn next(state: u128, taps: u128, width: usize) -> u128 {
+        let feedback = (state & taps).count_ones() as u128 & 1;
+        ((state << 1) | feedback) & ((1 << width) - 1)
+    }
+
+    #[test]
+    fn test_lfsr_matches_model() {
+        // x^4 + x^3 + 1 is maximal length, so the LFSR visits all 15
+        // nonzero values before repeating
+        let lfsr = Lfsr::<4>::new(bits(0b1100), bits(0b0001));
+        let inputs = clocked_inputs(0..30, |clock, _| LfsrI {
+            clock,
+            enable: true,
+        });
+        // The first sample comes before the first rising edge, so it is
+        // the seed
+        let outputs = run_clocked(&lfsr, &mut lfsr.init_state(), &inputs);
+        let mut expected = 0b0001;
+        for output in &outputs {
+            assert_eq!(output.0, expected);
+            expected = next(expected, 0b1100, 4);
+        }
+        assert_eq!(outputs.iter().collect::<HashSet<_>>().len(), 15);
+        assert_eq!(outputs[0], outputs[15]);
+    }
+
+    #[test]
+    fn test_lfsr_holds_when_disabled() {
+        let lfsr = Lfsr::<8>::new(bits(0b1011_1000), bits(0x5A));
+        let mut state = lfsr.init_state();
+        let mut io = <Lfsr<8> as Circuit>::Z::default();
+        for clock in crate::clock::clock().take(10) {
+            let output = lfsr.sim(
+                LfsrI {
+                    clock,
+                    enable: false,
+                },
+                &mut state,
+                &mut io,
+            );
+            assert_eq!(output, bits(0x5A));
+        }
+    }
+
+    #[test]
+    fn test_lfsr_probe_and_child_wires() {
+        let lfsr = Lfsr::<8>::new(bits(0b1011_1000), bits(0x5A));
+        let hdl = lfsr.as_hdl(HDLKind::Verilog).unwrap();
+        let body = &hdl.body;
+        // The probe is a wire of the module, driven by a copy of the
+        // update function that returns the probes
+        assert!(body.contains("wire[7:0] feedback;"));
+        let assign = body
+            .lines()
+            .find(|line| line.starts_with("assign {feedback} = "))
+            .unwrap();
+        let probes_fn = assign
+            .trim_start_matches("assign {feedback} = ")
+            .split('(')
+            .next()
+            .unwrap();
+        assert!(probes_fn.ends_with("_probes"));
+        assert!(body.contains(&format!("function  [7:0] {probes_fn}(")));
+        // The state register is connected through named wires.  The taps
+        // have no inputs, so there is no `taps_d`.
+        assert!(body.contains("wire[8:0] state_d;"));
+        assert!(body.contains("wire[7:0] state_q;"));
+        assert!(body.contains(".i(state_d),.o(state_q)"));
+        assert!(body.contains("wire[7:0] taps_q;"));
+        assert!(!body.contains("taps_d"));
+        let manifest = DesignManifest::from_circuit(&lfsr).unwrap();
+        assert_eq!(manifest.top.probes.len(), 1);
+        assert_eq!(manifest.top.probes[0].name, "feedback");
+        assert_eq!(manifest.top.probes[0].function, "lfsr");
+        assert_eq!(manifest.top.probes[0].width, 8);
+        let tm = rhdl_core::test_module::TestModule {
+            testbench: format!("{hdl}"),
+            num_cases: 0,
+            stimulus: None,
+            masked: Default::default(),
+        };
+        tm.run_iverilog().unwrap();
+    }
+
+    #[test]
+    fn test_lfsr_testbench() {
+        let lfsr = Lfsr::<8>::new(bits(0b1011_1000), bits(0x5A));
+        let inputs = crate::clock::clock()
+            .map(|clock| LfsrI {
+                clock,
+                enable: true,
+            })
+            .take(100)
+            .collect::<Vec<_>>();
+        let tm = lfsr.testbench(&inputs).unwrap();
+        assert_eq!(tm.num_cases, 100);
+        tm.run_iverilog().unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use rhdl_bits::{bits, Bits};
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+
+// A lookup of an address into a window of 8 entries, with no registers.
+// Addresses outside of the window are not valid, and their data is a
+// don't-care (which the simulation gives as zero).
+#[derive(Default, Clone, Circuit)]
+#[rhdl(kernel = lookup)]
+pub struct Lookup {}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct LookupO {
+    pub valid: bool,
+    pub data: Bits<4>,
+}
+
+impl CircuitIO for Lookup {
+    type I = Bits<4>;
+    type O = LookupO;
+}
+
+#[kernel]
+pub fn lookup(i: Bits<4>, _q: LookupQ) -> (LookupO, LookupD) {
+    let valid = i < bits::<4>(8);
+    let data = if valid { i + 3 } else { bits::<4>(0) };
+    (LookupO { valid, data }, LookupD::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_core::circuit::trace::{record, replay, replay_masked};
+    use rhdl_core::path::Path;
+    use rhdl_core::{
+        circuit_ports, Circuit, CircuitDescriptor, HDLDescriptor, HDLKind, OutputMask,
+    };
+
+    fn mask() -> OutputMask<LookupO> {
+        OutputMask::ignore_when(&[Path::default().field("data")], |o: &LookupO| !o.valid).unwrap()
+    }
+
+    // The lookup, with Verilog that leaves the data undefined (X) when
+    // the address is not valid, as synthesis is free to.
+    #[derive(Default, Clone)]
+    struct UndefinedLookup(Lookup);
+
+    impl CircuitIO for UndefinedLookup {
+        type I = Bits<4>;
+        type O = LookupO;
+    }
+
+    impl Circuit for UndefinedLookup {
+        type Q = LookupQ;
+        type D = LookupD;
+        type Z = <Lookup as Circuit>::Z;
+        type S = <Lookup as Circuit>::S;
+        type Update = lookup;
+        const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = <Lookup as Circuit>::UPDATE;
+
+        fn sim(&self, input: Bits<4>, state: &mut Self::S, io: &mut Self::Z) -> LookupO {
+            self.0.sim(input, state, io)
+        }
+
+        fn name(&self) -> &'static str {
+            "UndefinedLookup"
+        }
+
+        fn descriptor(&self) -> CircuitDescriptor {
+            self.0.descriptor()
+        }
+
+        fn as_hdl(&self, _kind: HDLKind) -> anyhow::Result<HDLDescriptor> {
+            let name = "undefined_lookup".to_string();
+            let body = format!(
+                "
+module {name}(input wire[3:0] i, output wire[4:0] o);
+   wire valid = ~i[3];
+   assign o = {{valid ? i + 4'd3 : 4'bxxxx, valid}};
+endmodule
+"
+            );
+            Ok(HDLDescriptor {
+                name,
+                body,
+                ports: circuit_ports::<Self>(),
+                children: Default::default(),
+                initial: vec![],
+            })
+        }
+    }
+
+    #[test]
+    fn test_undefined_data_passes_only_when_masked() {
+        let lookup = UndefinedLookup::default();
+        let err = lookup
+            .check_combinational_equivalence(100)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("but the Verilog gives 0xx"), "{err}");
+        let stats = lookup
+            .check_masked_combinational_equivalence(100, &mask())
+            .unwrap();
+        // The data of each invalid address is masked
+        let invalid = stats.masked_bits.iter().filter(|bits| **bits == 4).count();
+        assert!(invalid > 0);
+        assert_eq!(stats.total(), 4 * invalid);
+        let inputs = (0..16).map(bits).collect::<Vec<_>>();
+        assert!(lookup.testbench(&inputs).unwrap().run_iverilog().is_err());
+        let tm = lookup.masked_testbench(&inputs, &mask()).unwrap();
+        assert_eq!(tm.masked.masked_bits, [[0; 8], [4; 8]].concat());
+        tm.run_iverilog().unwrap();
+    }
+
+    // The same lookup, but with the address as the data for invalid
+    // addresses
+    #[derive(Default, Clone, Circuit)]
+    #[rhdl(kernel = noisy_lookup)]
+    struct NoisyLookup {}
+
+    impl CircuitIO for NoisyLookup {
+        type I = Bits<4>;
+        type O = LookupO;
+    }
+
+    #[kernel]
+    fn noisy_lookup(i: Bits<4>, _q: NoisyLookupQ) -> (LookupO, NoisyLookupD) {
+        let valid = i < bits::<4>(8);
+        let data = if valid { i + 3 } else { i };
+        (LookupO { valid, data }, NoisyLookupD::default())
+    }
+
+    #[test]
+    fn test_masked_replay() {
+        let trace = record(&Lookup::default(), (0..16).map(bits));
+        let report = replay(&NoisyLookup::default(), &trace).unwrap();
+        assert_eq!(report.mismatches.len(), 8);
+        assert_eq!(report.first_mismatch().unwrap().cycle, 8);
+        let report = replay_masked(&NoisyLookup::default(), &trace, &mask()).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.masked.masked_bits, [[0; 8], [4; 8]].concat());
+        assert_eq!(
+            report.to_string(),
+            "Replayed 16 cycles with 0 mismatches\n  32 bits masked in 8 of 16 cycles\n"
+        );
+        // A mismatch in a bit that is not masked is still reported
+        let mask = OutputMask::ignore(&[Path::default().field("valid")]).unwrap();
+        let report = replay_masked(&NoisyLookup::default(), &trace, &mask).unwrap();
+        assert_eq!(report.mismatches.len(), 8);
+        assert_eq!(report.masked.total(), 16);
+    }
+}
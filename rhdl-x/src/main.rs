@@ -14,6 +14,7 @@ use rhdl_core::note_pop_path;
 use rhdl_core::note_push_path;
 use rhdl_core::note_take;
 use rhdl_core::Circuit;
+use rhdl_core::CircuitIO;
 use rhdl_core::DigitalFn;
 use rhdl_core::HDLKind;
 use rhdl_core::KernelFnKind;
@@ -32,14 +33,29 @@ use strobe::StrobeI;
 use std::fmt::Write;
 //mod backend;
 //mod circuit;
+mod adder;
+mod arbiter;
+mod async_fifo;
 mod clock;
 mod constant;
 mod counter;
+mod ddr;
+mod debouncer;
 mod descriptions;
 mod dff;
+mod gray_crossing;
+mod lfsr;
+mod lookup;
 mod push_pull;
+mod ram;
+mod registered;
+mod shift_register;
+mod slow_config;
+mod stream;
 mod strobe;
+mod synchronizer;
 mod tristate;
+mod uart;
 //mod traitx;
 //mod translator;
 //mod verilog;
@@ -186,9 +202,8 @@ fn main() {
 
 #[test]
 fn test_timing_note() {
-    #[derive(Copy, Clone, PartialEq, Digital, Default)]
+    #[derive(Copy, Clone, PartialEq, Digital)]
     pub enum State {
-        #[default]
         A,
         B,
         C,
@@ -250,12 +265,6 @@ fn test_dfg_analysis_of_kernel() {
         C { x: b4, y: b4 },
     }
 
-    impl Default for Bar {
-        fn default() -> Self {
-            Bar::A(b8(0))
-        }
-    }
-
     #[kernel]
     fn concatenate_bits(x: b4, y: b4) -> (b4, b4) {
         let d = Foo {
@@ -289,3 +298,374 @@ fn test_dfg_analysis_of_kernel() {
     };
     let design = compile_design(kernel).unwrap();
 }
+
+#[test]
+fn test_check_circuit_wiring() {
+    // The counter routes both the clock and data of its DFF, and reads its output
+    rhdl_core::check_circuit(&Counter::<8>::default()).unwrap();
+    // The strobe never looks at the output of its second counter
+    let err = rhdl_core::check_circuit(&Strobe::<8>::new(b8(5)))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("output of child `counter_2` is never read"));
+    assert!(!err.contains("counter_1"));
+}
+
+#[test]
+fn test_check_circuit_flags_unrouted_input() {
+    use rhdl_macro::Circuit;
+
+    #[derive(Clone, Circuit)]
+    #[rhdl(kernel = unrouted)]
+    pub struct Unrouted {
+        counter: Counter<4>,
+    }
+
+    impl CircuitIO for Unrouted {
+        type I = CounterI;
+        type O = b4;
+    }
+
+    #[kernel]
+    pub fn unrouted(i: CounterI, q: UnroutedQ) -> (b4, UnroutedD) {
+        let mut d = UnroutedD::default();
+        d.counter.clock = i.clock;
+        (q.counter, d)
+    }
+
+    let circuit = Unrouted {
+        counter: Counter::default(),
+    };
+    let err = rhdl_core::check_circuit(&circuit).unwrap_err().to_string();
+    assert!(err.contains("input `counter.enable` of child `counter` is never driven"));
+    assert!(!err.contains("counter.clock"));
+    assert!(!err.contains("never read"));
+}
+
+#[test]
+fn test_update_kernel_must_fit_the_circuit() {
+    use rhdl_core::{root_descriptor, root_hdl, CircuitDescriptor, HDLDescriptor};
+
+    // The circuit was changed to output a b4, but `Update` still names
+    // the kernel that returned a b8
+    #[kernel]
+    pub fn wide(i: b4, q: ()) -> (b8, ()) {
+        (b8(0), q)
+    }
+
+    #[kernel]
+    pub fn narrow(i: b4, q: ()) -> (b4, ()) {
+        (i, q)
+    }
+
+    #[derive(Clone)]
+    pub struct Narrow {}
+
+    impl CircuitIO for Narrow {
+        type I = b4;
+        type O = b4;
+    }
+
+    impl Circuit for Narrow {
+        type Q = ();
+        type D = ();
+        type Z = ();
+        type Update = wide;
+        const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = narrow;
+        type S = ();
+
+        fn sim(&self, input: Self::I, _state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+            Self::UPDATE(input, ()).0
+        }
+
+        fn name(&self) -> &'static str {
+            "Narrow"
+        }
+
+        fn descriptor(&self) -> CircuitDescriptor {
+            root_descriptor(self)
+        }
+
+        fn as_hdl(&self, kind: HDLKind) -> anyhow::Result<HDLDescriptor> {
+            root_hdl(self, kind)
+        }
+    }
+
+    let circuit = Narrow {};
+    let descriptor = circuit.descriptor();
+    let expected = descriptor.expected_update_signature().to_string();
+    let actual = descriptor.update_signature.clone().unwrap().to_string();
+    assert_ne!(expected, actual);
+    // No Verilog is made, and the error names the circuit and both kinds
+    let err = circuit.as_hdl(HDLKind::Verilog).unwrap_err().to_string();
+    assert!(err.contains(&format!(
+        "The update kernel of circuit {} has the signature {actual}, but the circuit needs {expected}",
+        descriptor.unique_name
+    )));
+    assert!(descriptor.schematic().is_none());
+    let err = descriptor.try_schematic().unwrap_err().to_string();
+    assert!(err.contains(&expected));
+    assert!(rhdl_core::check_circuit(&circuit).is_err());
+}
+
+#[test]
+fn test_counter_testbench() {
+    let clock = clock::clock();
+    let enable = [false, false, true, true, true, false].into_iter().cycle();
+    let inputs = clock
+        .zip(enable)
+        .map(|(clock, enable)| CounterI { clock, enable })
+        .take(200)
+        .collect::<Vec<_>>();
+    let counter = Counter::<4>::default();
+    let tm = counter.testbench(&inputs).unwrap();
+    assert_eq!(tm.num_cases, 200);
+    let name = counter.descriptor().unique_name;
+    assert!(tm.testbench.contains(&format!("{name} dut(.i(i), .o(o));")));
+    assert!(tm.testbench.contains("reg[1:0] i;"));
+    assert!(tm.testbench.contains("wire[3:0] o;"));
+    tm.run_iverilog().unwrap();
+}
+
+#[test]
+fn test_counter_instantiation_template() {
+    let counter = Counter::<4>::default();
+    let hdl = counter.as_hdl(HDLKind::Verilog).unwrap();
+    let template = hdl.instantiation_template().unwrap();
+    // The ports of the module declaration, i.e., `i` and `o`
+    let decl = hdl
+        .body
+        .lines()
+        .find(|line| line.starts_with("module"))
+        .unwrap();
+    let decl = &decl[decl.find('(').unwrap() + 1..decl.rfind(')').unwrap()];
+    let declared = decl
+        .split(',')
+        .map(|port| port.split_whitespace().last().unwrap())
+        .collect::<Vec<_>>();
+    let connected = template
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('.'))
+        .map(|line| &line[..line.find('(').unwrap()])
+        .collect::<Vec<_>>();
+    assert_eq!(declared, connected);
+    assert!(template.contains(&format!("{} {}_inst (", hdl.name, hdl.name)));
+    assert!(template.contains("//   i[0] .clock"));
+    assert!(template.contains("//   i[1] .enable"));
+    let top = rhdl_core::example_top(
+        &counter,
+        &rhdl_core::ExampleTopOptions {
+            clock: Some(rhdl_core::path::Path::default().field("clock")),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(
+        top.contains("module top(input wire clk, input wire[0:0] i_enable, output wire[3:0] o);")
+    );
+    assert!(top.contains("assign dut_i[0] = clk;"));
+    let tm = rhdl_core::test_module::TestModule {
+        testbench: format!("{top}\n{hdl}"),
+        num_cases: 0,
+        stimulus: None,
+        masked: Default::default(),
+    };
+    tm.run_iverilog().unwrap();
+}
+
+#[test]
+fn test_counter_state_signals() {
+    use rhdl_macro::Circuit;
+
+    #[derive(Clone, Circuit)]
+    #[rhdl(kernel = watch)]
+    pub struct Watch {
+        counter: Counter<4>,
+    }
+
+    impl CircuitIO for Watch {
+        type I = CounterI;
+        type O = b4;
+    }
+
+    #[kernel]
+    pub fn watch(i: CounterI, q: WatchQ) -> (b4, WatchD) {
+        let mut d = WatchD::default();
+        d.counter = i;
+        (q.counter, d)
+    }
+
+    let inputs = clock::clock().map(|clock| CounterI {
+        clock,
+        enable: true,
+    });
+    let watch = Watch {
+        counter: Counter::default(),
+    };
+    let mut state = watch.init_state();
+    let mut io = <Watch as Circuit>::Z::default();
+    let mut last = b4(0);
+    for input in inputs.take(12) {
+        last = watch.sim(input, &mut state, &mut io);
+        assert_eq!(
+            watch.state_signals(&state),
+            vec![("counter.count".to_string(), last.typed_bits())]
+        );
+    }
+    // Every other step starts with a rising edge of the clock
+    assert_eq!(last, b4(6));
+}
+
+#[test]
+fn test_counter_vcd_note_writer() {
+    use rhdl_core::{Notable, VcdNoteWriter};
+
+    let clock = clock::clock();
+    let enable = [false, true, true].into_iter().cycle();
+    let inputs = clock
+        .zip(enable)
+        .map(|(clock, enable)| CounterI { clock, enable });
+    let counter = Counter::<4>::default();
+    let mut state = counter.init_state();
+    let mut io = <Counter<4> as Circuit>::Z::default();
+    let mut writer = VcdNoteWriter::new();
+    writer.capture(|| {
+        for (time, input) in inputs.enumerate().take(100) {
+            note_time(time as u64 * 100);
+            counter.sim(input, &mut state, &mut io);
+        }
+    });
+    // Values can also be noted directly
+    writer.set_time(10_000);
+    CounterI::default().note("final", &mut writer);
+    let mut vcd = vec![];
+    writer.write_vcd(&mut vcd).unwrap();
+    let vcd = String::from_utf8(vcd).unwrap();
+    // The VCD names are the note keys, with `::` replaced by `__`
+    for name in [
+        "__input__clock__0",
+        "__input__enable",
+        "__outputs",
+        "count__input__data",
+        "__final__enable",
+    ] {
+        assert!(
+            vcd.contains(&format!(" {name} $end")),
+            "missing {name} in\n{vcd}"
+        );
+    }
+    assert!(vcd.contains("#9900"));
+}
+
+#[test]
+fn test_trace_record_and_replay() {
+    use rhdl_core::circuit::trace::{record, replay};
+    use rhdl_core::TraceFile;
+    use rhdl_macro::Circuit;
+
+    #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+    pub struct TallyO {
+        count: b4,
+        full: bool,
+    }
+
+    #[derive(Clone, Circuit)]
+    #[rhdl(kernel = tally)]
+    pub struct Tally {
+        counter: Counter<4>,
+    }
+
+    impl CircuitIO for Tally {
+        type I = CounterI;
+        type O = TallyO;
+    }
+
+    #[kernel]
+    pub fn tally(i: CounterI, q: TallyQ) -> (TallyO, TallyD) {
+        let mut d = TallyD::default();
+        d.counter = i;
+        (
+            TallyO {
+                count: q.counter,
+                full: q.counter == b4(15),
+            },
+            d,
+        )
+    }
+
+    // The same circuit, but with an off-by-one in the `full` flag
+    #[derive(Clone, Circuit)]
+    #[rhdl(kernel = broken_tally)]
+    pub struct BrokenTally {
+        counter: Counter<4>,
+    }
+
+    impl CircuitIO for BrokenTally {
+        type I = CounterI;
+        type O = TallyO;
+    }
+
+    #[kernel]
+    pub fn broken_tally(i: CounterI, q: BrokenTallyQ) -> (TallyO, BrokenTallyD) {
+        let mut d = BrokenTallyD::default();
+        d.counter = i;
+        (
+            TallyO {
+                count: q.counter,
+                full: q.counter == b4(14),
+            },
+            d,
+        )
+    }
+
+    let inputs = clock::clock()
+        .zip([true, true, false].into_iter().cycle())
+        .map(|(clock, enable)| CounterI { clock, enable })
+        .take(100)
+        .collect::<Vec<_>>();
+    let tally = Tally {
+        counter: Counter::default(),
+    };
+    let trace = record(&tally, inputs.iter().copied());
+    assert_eq!(trace.cycles.len(), 100);
+    // Round trip the trace through its file format
+    let mut buf = vec![];
+    trace.write(&mut buf).unwrap();
+    let trace = TraceFile::read(&buf[..]).unwrap();
+    assert!(replay(&tally, &trace).unwrap().is_ok());
+    let stimulus = trace.input_stimulus_verilog();
+    assert_eq!(stimulus.lines().count(), 101);
+    assert_eq!(stimulus.lines().nth(1), Some("3"));
+    // The first divergence is the first time the count reaches 14
+    let first_14 = (0..trace.cycles.len())
+        .map(|cycle| TallyO::maybe_from_bin(&trace.output(cycle).bits).unwrap())
+        .position(|output| output.count == b4(14))
+        .unwrap();
+    let broken = BrokenTally {
+        counter: Counter::default(),
+    };
+    let report = replay(&broken, &trace).unwrap();
+    assert!(!report.is_ok());
+    let text = report.to_string();
+    let mut lines = text.lines();
+    let summary = lines.next().unwrap();
+    assert!(summary.starts_with("Replayed 100 cycles with "));
+    let first = lines.next().unwrap();
+    assert!(first.starts_with(&format!("  cycle {first_14}: expected ")));
+    assert!(first.ends_with("(differs in o.full)"));
+    let mismatch = report.first_mismatch().unwrap();
+    assert_eq!(mismatch.cycle, first_14);
+    assert_eq!(
+        mismatch
+            .paths
+            .iter()
+            .map(|path| path.to_string())
+            .collect::<Vec<_>>(),
+        vec![".full"]
+    );
+    // A circuit with a different interface is rejected up front
+    let err = replay(&Counter::<4>::default(), &trace)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Output of circuit Counter"));
+}
@@ -2,6 +2,7 @@ use anyhow::ensure;
 use rhdl_bits::alias::*;
 use rhdl_bits::Bits;
 use rhdl_core::circuit::circuit_impl::CircuitUpdateFn;
+use rhdl_core::circuit_ports;
 use rhdl_core::note;
 use rhdl_core::note_init_db;
 use rhdl_core::note_pop_path;
@@ -63,7 +64,9 @@ endmodule
         HDLDescriptor {
             name: module_name,
             body: code,
+            ports: circuit_ports::<Self>(),
             children: Default::default(),
+            initial: vec![],
         }
     }
 }
@@ -110,9 +113,8 @@ impl<const N: usize> Circuit for ZDriver<N> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+#[derive(Debug, Clone, PartialEq, Digital, Copy)]
 pub enum Side {
-    #[default]
     Left,
     Right,
 }
@@ -307,16 +309,11 @@ impl Circuit for Push {
 
     fn descriptor(&self) -> crate::circuit::CircuitDescriptor {
         let mut ret = root_descriptor(self);
-        ret.children
-            .insert("strobe".to_string(), self.strobe.descriptor());
-        ret.children
-            .insert("value".to_string(), self.value.descriptor());
-        ret.children
-            .insert("buf_z".to_string(), self.buf_z.descriptor());
-        ret.children
-            .insert("side".to_string(), self.side.descriptor());
-        ret.children
-            .insert("latch".to_string(), self.latch.descriptor());
+        ret.insert_child("strobe", self.strobe.descriptor());
+        ret.insert_child("value", self.value.descriptor());
+        ret.insert_child("buf_z", self.buf_z.descriptor());
+        ret.insert_child("side", self.side.descriptor());
+        ret.insert_child("latch", self.latch.descriptor());
         ret
     }
 
@@ -528,10 +525,8 @@ impl Circuit for PushPair {
 
     fn descriptor(&self) -> CircuitDescriptor {
         let mut ret = root_descriptor(self);
-        ret.children
-            .insert("left".to_string(), self.left.descriptor());
-        ret.children
-            .insert("right".to_string(), self.right.descriptor());
+        ret.insert_child("left", self.left.descriptor());
+        ret.insert_child("right", self.right.descriptor());
         ret
     }
 
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use rhdl_core::child_state_signals;
+use rhdl_core::note;
+use rhdl_core::note_pop_path;
+use rhdl_core::note_push_path;
+use rhdl_core::root_descriptor;
+use rhdl_core::root_hdl;
+use rhdl_core::Circuit;
+use rhdl_core::CircuitDescriptor;
+use rhdl_core::CircuitIO;
+use rhdl_core::Digital;
+use rhdl_core::HDLDescriptor;
+use rhdl_core::HDLKind;
+use rhdl_core::TypedBits;
+use rhdl_macro::{kernel, Digital};
+
+use crate::{
+    clock::Clock,
+    dff::{DFF, DFFI},
+};
+
+// A circuit with its output registered, i.e., with a pipeline stage
+// added after it.  The output of `C` is taken on each rising edge of the
+// clock, so it appears on the output one cycle later.  `C` is usually
+// combinational, so it has no clock of its own, and the clock is added
+// to its input.
+#[derive(Clone, Default)]
+pub struct Registered<C: Circuit>
+where
+    C::O: Default,
+{
+    inner: C,
+    output: DFF<C::O>,
+}
+
+impl<C: Circuit> Registered<C>
+where
+    C::O: Default,
+{
+    // The register starts out holding `init`, which is the output until
+    // the first rising edge of the clock.
+    pub fn new(inner: C, init: C::O) -> Self {
+        Self {
+            inner,
+            output: init.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct RegisteredI<T: Digital> {
+    pub clock: Clock,
+    pub data: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct RegisteredQ<O: Digital> {
+    pub inner: O,
+    pub output: O,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct RegisteredD<I: Digital, O: Digital> {
+    pub inner: I,
+    pub output: DFFI<O>,
+}
+
+impl<C: Circuit> CircuitIO for Registered<C>
+where
+    C::O: Default,
+{
+    type I = RegisteredI<C::I>;
+    type O = C::O;
+}
+
+impl<C: Circuit> Circuit for Registered<C>
+where
+    C::I: Default,
+    C::O: Default,
+{
+    type Q = RegisteredQ<C::O>;
+
+    type D = RegisteredD<C::I, C::O>;
+
+    type Z = C::Z;
+
+    type Update = registered<C::I, C::O>;
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = registered::<C::I, C::O>;
+
+    type S = (Self::Q, C::S, <DFF<C::O> as Circuit>::S);
+
+    fn init_state(&self) -> Self::S {
+        (
+            Default::default(),
+            self.inner.init_state(),
+            self.output.init_state(),
+        )
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        loop {
+            let prev_state = state.clone();
+            let (outputs, internal_inputs) = Self::UPDATE(input, state.0);
+            note_push_path("inner");
+            state.0.inner = self.inner.sim(internal_inputs.inner, &mut state.1, io);
+            note_pop_path();
+            note_push_path("output");
+            state.0.output = self
+                .output
+                .sim(internal_inputs.output, &mut state.2, &mut ());
+            note_pop_path();
+            if state == &prev_state {
+                note("outputs", outputs);
+                return outputs;
+            }
+        }
+    }
+
+    fn state_signals(&self, state: &Self::S) -> Vec<(String, TypedBits)> {
+        child_state_signals("inner", self.inner.state_signals(&state.1))
+            .chain(child_state_signals(
+                "output",
+                self.output.state_signals(&state.2),
+            ))
+            .collect()
+    }
+
+    fn name(&self) -> &'static str {
+        "Registered"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        let mut ret = root_descriptor(self);
+        ret.insert_child("inner", self.inner.descriptor());
+        ret.insert_child("output", self.output.descriptor());
+        ret
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        let mut ret = root_hdl(self, kind)?;
+        ret.children
+            .insert("inner".to_string(), self.inner.as_hdl(kind)?);
+        ret.children
+            .insert("output".to_string(), self.output.as_hdl(kind)?);
+        Ok(ret)
+    }
+}
+
+#[kernel]
+pub fn registered<I: Digital, O: Digital>(
+    i: RegisteredI<I>,
+    q: RegisteredQ<O>,
+) -> (O, RegisteredD<I, O>) {
+    let d = RegisteredD::<I, O> {
+        inner: i.data,
+        output: DFFI::<O> {
+            clock: i.clock,
+            data: q.inner,
+        },
+    };
+    (q.output, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use rhdl_bits::{bits, Bits};
+
+    use super::*;
+    use crate::adder::{Adder, AdderI};
+    use crate::clock::{clocked_inputs, run_clocked};
+
+    fn inputs() -> Vec<RegisteredI<AdderI>> {
+        clocked_inputs(
+            [(1, 2), (3, 4), (5, 9), (15, 15), (0, 7)],
+            |clock, (a, b)| RegisteredI {
+                clock,
+                data: AdderI {
+                    a: bits(a),
+                    b: bits(b),
+                },
+            },
+        )
+    }
+
+    #[test]
+    fn test_registered_adder_is_one_cycle_late() {
+        let adder = Registered::new(Adder::default(), bits(6));
+        let mut state = adder.init_state();
+        // The output before each rising edge of the clock
+        let sums: Vec<Bits<4>> = run_clocked(&adder, &mut state, &inputs());
+        // The first output is the initial value of the register, and
+        // each sum follows a cycle after its inputs
+        assert_eq!(sums, [bits(6), bits(3), bits(7), bits(14), bits(14)]);
+    }
+
+    #[test]
+    fn test_registered_adder_verilog() {
+        let adder = Registered::new(Adder::default(), bits(6));
+        let hdl = adder.as_hdl(HDLKind::Verilog).unwrap();
+        assert!(hdl.children.contains_key("inner"));
+        assert!(hdl.children.contains_key("output"));
+        let tm = adder.testbench(&inputs()).unwrap();
+        tm.run_iverilog().unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use anyhow::ensure;
+use anyhow::Result;
+use rhdl_bits::{bits, Bits};
+use rhdl_core::circuit_ports;
+use rhdl_core::clocked_always_block;
+use rhdl_core::constraint_input_synchronous;
+use rhdl_core::constraint_must_clock;
+use rhdl_core::constraint_output_synchronous;
+use rhdl_core::note;
+use rhdl_core::path::Path;
+use rhdl_core::root_descriptor;
+use rhdl_core::schematic::components::BlackBoxComponent;
+use rhdl_core::schematic::components::ComponentKind;
+use rhdl_core::schematic::schematic_impl::pin_path;
+use rhdl_core::schematic::schematic_impl::PinIx;
+use rhdl_core::schematic::schematic_impl::Schematic;
+use rhdl_core::AssignmentStyle;
+use rhdl_core::BlackBoxTrait;
+use rhdl_core::Circuit;
+use rhdl_core::CircuitDescriptor;
+use rhdl_core::CircuitIO;
+use rhdl_core::Constraint;
+use rhdl_core::EdgeType;
+use rhdl_core::HDLDescriptor;
+use rhdl_core::HDLKind;
+use rhdl_core::TypedBits;
+use rhdl_core::{initial_block, Digital, DigitalFn, HDLInitial};
+use rhdl_macro::Digital;
+
+use crate::clock::Clock;
+
+// A serial in, serial out shift register of `N` bits.  On each rising
+// edge of the clock, the register shifts up by one and the input is
+// shifted in at the bottom.  The output is the top bit, so the input
+// comes out `N` cycles later.
+#[derive(Default, Clone)]
+pub struct ShiftRegister<const N: usize> {
+    init: Bits<N>,
+}
+
+impl<const N: usize> From<Bits<N>> for ShiftRegister<N> {
+    fn from(init: Bits<N>) -> Self {
+        Self { init }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct ShiftRegisterI {
+    pub clock: Clock,
+    pub data: Bits<1>,
+}
+
+impl<const N: usize> CircuitIO for ShiftRegister<N> {
+    type I = ShiftRegisterI;
+    type O = Bits<1>;
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ShiftRegisterState<const N: usize> {
+    clock: Clock,
+    bits: Bits<N>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShiftRegisterComponent {
+    clock: PinIx,
+    d: PinIx,
+    q: PinIx,
+}
+
+impl BlackBoxTrait for ShiftRegisterComponent {
+    fn name(&self) -> &'static str {
+        "ShiftRegister"
+    }
+
+    fn args(&self) -> Vec<PinIx> {
+        vec![self.clock, self.d]
+    }
+
+    fn output(&self) -> PinIx {
+        self.q
+    }
+
+    fn offset(&self, shift: usize) -> BlackBoxComponent {
+        BlackBoxComponent::new(ShiftRegisterComponent {
+            clock: self.clock.offset(shift),
+            d: self.d.offset(shift),
+            q: self.q.offset(shift),
+        })
+    }
+
+    fn constraints(&self) -> Vec<Constraint> {
+        vec![
+            constraint_must_clock(pin_path(self.clock, Path::default())),
+            constraint_output_synchronous(
+                pin_path(self.q, Path::default()),
+                pin_path(self.clock, Path::default()),
+                EdgeType::Positive,
+            ),
+            constraint_input_synchronous(
+                pin_path(self.d, Path::default()),
+                pin_path(self.clock, Path::default()),
+                EdgeType::Positive,
+            ),
+        ]
+    }
+}
+
+impl<const N: usize> Circuit for ShiftRegister<N> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |i, _| (i.data, ());
+
+    type S = ShiftRegisterState<N>;
+
+    fn init_state(&self) -> Self::S {
+        ShiftRegisterState {
+            clock: Clock(true),
+            bits: self.init,
+        }
+    }
+
+    fn state_signals(&self, state: &Self::S) -> Vec<(String, TypedBits)> {
+        vec![(String::new(), state.bits.typed_bits())]
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        if input.clock.0 && !state.clock.0 {
+            state.bits = (state.bits << 1) | Bits::<N>(input.data.0);
+        }
+        state.clock = input.clock;
+        let output = bits::<1>(state.bits.0 >> (N - 1));
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "ShiftRegister"
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        Ok(self.as_verilog())
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        let mut desc = root_descriptor(self);
+        let mut schematic = Schematic::default();
+        let (input_rx, input_tx) = schematic.make_buffer(ShiftRegisterI::static_kind(), None);
+        // Split the clock and the data out of the input
+        let clock = schematic.make_field_tap(input_tx, "clock", Clock::static_kind());
+        let data = schematic.make_field_tap(input_tx, "data", Bits::<1>::static_kind());
+        let c = schematic.make_pin(Clock::static_kind(), "clock".to_string(), None);
+        let d = schematic.make_pin(Bits::<1>::static_kind(), "d".to_string(), None);
+        let q = schematic.make_pin(Bits::<1>::static_kind(), "q".to_string(), None);
+        let shift = schematic.make_component(
+            ComponentKind::BlackBox(BlackBoxComponent::new(ShiftRegisterComponent {
+                clock: c,
+                d,
+                q,
+            })),
+            None,
+        );
+        schematic.pin_mut(c).parent(shift);
+        schematic.pin_mut(d).parent(shift);
+        schematic.pin_mut(q).parent(shift);
+        schematic.wire(clock, c);
+        schematic.wire(data, d);
+        schematic.inputs = vec![input_rx];
+        schematic.output = schematic.make_update_output(q);
+        desc.update_schematic = Some(schematic);
+        desc
+    }
+}
+
+impl<const N: usize> DigitalFn for ShiftRegister<N> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<const N: usize> ShiftRegister<N> {
+    fn as_verilog(&self) -> HDLDescriptor {
+        let module_name = self.descriptor().unique_name;
+        let top = N - 1;
+        let initial = vec![HDLInitial {
+            name: String::new(),
+            reg: "sr".into(),
+            value: self.init.typed_bits(),
+        }];
+        let init = initial_block(&initial);
+        // A one bit register has nothing to keep when it shifts
+        let next = if N == 1 {
+            "d".to_string()
+        } else {
+            format!("{{sr[{}:0], d}}", N - 2)
+        };
+        let update = clocked_always_block(
+            "clk",
+            &[("sr".to_string(), next)],
+            AssignmentStyle::NonBlocking,
+        );
+        let code = format!(
+            "
+module {module_name}(input wire[1:0] i, output wire[0:0] o);
+   wire clk;
+   wire[0:0] d;
+   reg[{top}:0] sr;
+   assign clk = i[0];
+   assign d = i[1:1];
+   assign o = sr[{top}];
+{init}{update}endmodule
+"
+        );
+        HDLDescriptor {
+            name: module_name,
+            body: code,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+
+    fn inputs(data: &[u8]) -> Vec<ShiftRegisterI> {
+        clocked_inputs(data.iter().copied(), |clock, data| ShiftRegisterI {
+            clock,
+            data: bits(data as u128),
+        })
+    }
+
+    // The output before each rising edge
+    fn outputs<const N: usize>(
+        sr: &ShiftRegister<N>,
+        state: &mut ShiftRegisterState<N>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        run_clocked(sr, state, &inputs(data))
+            .into_iter()
+            .map(|output| output.0 as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_shift_register_delays_by_its_length() {
+        let data = [1, 0, 1, 1, 0, 0, 1, 0, 1, 1, 1, 0];
+        let sr = ShiftRegister::<4>::default();
+        let mut state = sr.init_state();
+        let outputs = outputs(&sr, &mut state, &data);
+        assert_eq!(outputs, [0, 0, 0, 0, 1, 0, 1, 1, 0, 0, 1, 0]);
+        // The last four inputs are held in the register, oldest on top
+        assert_eq!(state.bits, bits(0b1110));
+    }
+
+    #[test]
+    fn test_shift_register_starts_from_init() {
+        let sr = ShiftRegister::<3>::from(bits(0b101));
+        let mut state = sr.init_state();
+        let outputs = outputs(&sr, &mut state, &[0, 0, 0, 0]);
+        assert_eq!(outputs, [1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_shift_register_verilog() {
+        let hdl = ShiftRegister::<4>::default()
+            .as_hdl(HDLKind::Verilog)
+            .unwrap();
+        assert!(hdl.body.contains("sr <= {sr[2:0], d};"));
+        assert!(hdl.body.contains("assign o = sr[3];"));
+        let hdl = ShiftRegister::<1>::default()
+            .as_hdl(HDLKind::Verilog)
+            .unwrap();
+        assert!(hdl.body.contains("sr <= d;"));
+        let data = [1, 0, 1, 1, 0, 0, 1, 0, 1, 1, 1, 0];
+        let tm = ShiftRegister::<4>::from(bits(0b0110))
+            .testbench(&inputs(&data))
+            .unwrap();
+        assert_eq!(tm.num_cases, 24);
+        tm.run_iverilog().unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use rhdl_bits::Bits;
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+
+use crate::{clock::Clock, dff::DFF};
+
+// A configuration register that is written over a bus, and that feeds
+// an accumulator.  The register only changes when the bus writes it,
+// and the bus holds its data for two cycles, so neither path needs to
+// settle in a single cycle.  The timing attributes tell synthesis as
+// much, through the SDC constraints of the circuit.
+//
+// The doc comments end up in the generated documentation.
+/// Adds a configured step to a running total on every cycle.
+#[derive(Default, Clone, Circuit)]
+#[rhdl(kernel = slow_config)]
+pub struct SlowConfig {
+    /// The step, as last written by the bus
+    #[rhdl(multicycle = 2)]
+    config: DFF<Bits<4>>,
+    total: DFF<Bits<4>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct ConfigBus {
+    /// Set to load `data` into the register
+    pub write: bool,
+    #[rhdl(false_path)]
+    pub data: Bits<4>,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct SlowConfigI {
+    pub clock: Clock,
+    /// The configuration bus
+    pub bus: ConfigBus,
+}
+
+impl CircuitIO for SlowConfig {
+    type I = SlowConfigI;
+    type O = Bits<4>;
+}
+
+#[kernel]
+pub fn slow_config(i: SlowConfigI, q: SlowConfigQ) -> (Bits<4>, SlowConfigD) {
+    let mut d = SlowConfigD::default();
+    d.config.clock = i.clock;
+    d.config.data = q.config;
+    if i.bus.write {
+        d.config.data = i.bus.data;
+    }
+    d.total.clock = i.clock;
+    d.total.data = q.total + q.config;
+    (q.total, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhdl_core::{
+        docs::render_markdown, root_sdc, Circuit, DesignManifest, HDLKind, TimingException,
+    };
+
+    #[test]
+    fn test_slow_config_sdc() {
+        let circuit = SlowConfig::default();
+        let sdc = root_sdc(&circuit).unwrap();
+        let body = circuit.as_hdl(HDLKind::Verilog).unwrap().body;
+        // The bus data is bits 2..6 of the input (after the clock and the
+        // write strobe), the config register is the first 5 bits of D
+        // (its clock and data), and the first 4 bits of Q
+        assert!(body.contains("input wire[5:0] i"));
+        assert!(body.contains("(* keep *) wire[9:0] d;"));
+        assert!(body.contains("(* keep *) wire[7:0] q;"));
+        let lines = sdc
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "set_false_path -from [get_ports {i[2] i[3] i[4] i[5]}]",
+                "set_multicycle_path -setup 2 -through [get_nets {d[0] d[1] d[2] d[3] d[4]}]",
+                "set_multicycle_path -hold 1 -through [get_nets {d[0] d[1] d[2] d[3] d[4]}]",
+                "set_multicycle_path -setup 2 -through [get_nets {q[0] q[1] q[2] q[3]}]",
+                "set_multicycle_path -hold 1 -through [get_nets {q[0] q[1] q[2] q[3]}]",
+            ]
+        );
+        let manifest = DesignManifest::from_circuit(&circuit).unwrap();
+        let timing = manifest
+            .top
+            .timing
+            .iter()
+            .map(|timing| (timing.port.as_str(), timing.path.as_str(), timing.exception))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timing,
+            vec![
+                ("i", ".bus.data", TimingException::FalsePath),
+                ("d", ".config", TimingException::Multicycle(2)),
+                ("q", ".config", TimingException::Multicycle(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slow_config_docs() {
+        let manifest = DesignManifest::from_circuit(&SlowConfig::default()).unwrap();
+        assert_eq!(
+            manifest.top.doc.as_deref(),
+            Some("Adds a configured step to a running total on every cycle.")
+        );
+        let markdown = render_markdown(&manifest);
+        assert!(markdown.contains("\nAdds a configured step to a running total on every cycle.\n"));
+        // A field without a doc comment of its own is described by the
+        // field that holds it
+        assert!(markdown
+            .contains("| `i.bus.write` | `[1]` | `b1` | Set to load `data` into the register |"));
+        assert!(markdown
+            .contains("| `i.bus.data` | `[5:2]` | `b4` | The configuration bus; false path |"));
+        assert!(markdown.contains("| `i.clock.0` | `[0]` | `b1` |  |"));
+        assert!(markdown.contains(
+            "| `q.config` | `[3:0]` | `b4` | The step, as last written by the bus; multicycle (2 cycles) |"
+        ));
+        assert!(markdown.contains(
+            "| `d.config.data` | `[4:1]` | `b4` | The step, as last written by the bus; multicycle (2 cycles) |"
+        ));
+        assert!(markdown.contains("| `q.total` | `[7:4]` | `b4` |  |"));
+    }
+}
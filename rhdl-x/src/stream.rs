@@ -0,0 +1,1178 @@
+use std::marker::PhantomData;
+
+use anyhow::ensure;
+use anyhow::Result;
+use rhdl_bits::Bits;
+use rhdl_core::as_verilog_literal;
+use rhdl_core::circuit_ports;
+use rhdl_core::note;
+use rhdl_core::path::bit_range;
+use rhdl_core::path::Path;
+use rhdl_core::root_descriptor;
+use rhdl_core::Circuit;
+use rhdl_core::CircuitDescriptor;
+use rhdl_core::CircuitIO;
+use rhdl_core::HDLDescriptor;
+use rhdl_core::HDLKind;
+use rhdl_core::Kind;
+use rhdl_core::{Digital, DigitalFn};
+use rhdl_macro::Digital;
+
+use crate::clock::Clock;
+use crate::ram::slice;
+
+// Streams move words downstream with a ready/valid handshake: a word is
+// transferred on a rising edge of the clock on which both `valid` and
+// `ready` are high.  `StreamI` is the input of a stage, with the word
+// offered from upstream, and whether downstream is ready to take a word.
+// `StreamO` is the output of a stage, with the word it offers downstream,
+// and whether it is ready to take a word from upstream.  The stages in
+// this file only change their outputs on a rising edge, so they can be
+// chained without making combinational paths.
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct StreamI<T: Digital> {
+    pub clock: Clock,
+    pub data: T,
+    pub valid: bool,
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct StreamO<T: Digital> {
+    pub data: T,
+    pub valid: bool,
+    pub ready: bool,
+}
+
+// The output of a stage that holds no word: it offers nothing downstream,
+// and is ready to take a word from upstream.  The outputs of the stages
+// in this file come only from their registers, which `UPDATE` cannot
+// see, so this is what their `UPDATE`s give.
+fn idle<T: Digital + Default>() -> StreamO<T> {
+    StreamO {
+        data: T::default(),
+        valid: false,
+        ready: true,
+    }
+}
+
+// Declare a wire for each of the given fields of the input `i`.
+fn input_wires(kind: &Kind, fields: &[&str]) -> Result<String> {
+    let mut decls = vec![];
+    let mut assigns = vec![];
+    for field in fields {
+        let (range, _) = bit_range(kind.clone(), &Path::default().field(field))?;
+        decls.push(format!("   wire[{}:0] {field};", range.len() - 1));
+        assigns.push(format!(
+            "   assign {field} = {};",
+            slice("i", kind.clone(), &Path::default().field(field))?
+        ));
+    }
+    Ok(format!("{}\n{}", decls.join("\n"), assigns.join("\n")))
+}
+
+// Drive each of the given fields of the output `o` from an expression.
+fn output_assigns(kind: &Kind, fields: &[(&str, String)]) -> Result<String> {
+    Ok(fields
+        .iter()
+        .map(|(field, value)| {
+            Ok(format!(
+                "   assign {} = {value};",
+                slice("o", kind.clone(), &Path::default().field(field))?
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join("\n"))
+}
+
+// Break each word of `IN` bits into `IN / OUT` words of `OUT` bits, which
+// are sent least significant first.  A new word is taken once the last
+// part of the previous one has been sent.
+#[derive(Clone)]
+pub struct WidthDown<const IN: usize, const OUT: usize> {}
+
+impl<const IN: usize, const OUT: usize> WidthDown<IN, OUT> {
+    // The number of output words in each input word.  Any use of it
+    // fails the build if `IN` is not a multiple of `OUT`.
+    const RATIO: usize = {
+        assert!(
+            OUT > 0 && IN.is_multiple_of(OUT),
+            "WidthDown needs IN to be a multiple of OUT"
+        );
+        IN / OUT
+    };
+}
+
+impl<const IN: usize, const OUT: usize> Default for WidthDown<IN, OUT> {
+    fn default() -> Self {
+        let _ = Self::RATIO;
+        Self {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WidthDownS<const IN: usize> {
+    clock: Clock,
+    shift: Bits<IN>,
+    // The number of output words left to send
+    count: usize,
+}
+
+impl<const IN: usize, const OUT: usize> CircuitIO for WidthDown<IN, OUT> {
+    type I = StreamI<Bits<IN>>;
+    type O = StreamO<Bits<OUT>>;
+}
+
+impl<const IN: usize, const OUT: usize> DigitalFn for WidthDown<IN, OUT> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<const IN: usize, const OUT: usize> Circuit for WidthDown<IN, OUT> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |_, _| (idle(), ());
+
+    type S = WidthDownS<IN>;
+
+    fn init_state(&self) -> Self::S {
+        WidthDownS {
+            clock: Clock(true),
+            ..Default::default()
+        }
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        if input.clock.0 && !state.clock.0 {
+            if state.count != 0 {
+                if input.ready {
+                    state.shift = Bits(state.shift.0.checked_shr(OUT as u32).unwrap_or(0));
+                    state.count -= 1;
+                }
+            } else if input.valid {
+                state.shift = input.data;
+                state.count = Self::RATIO;
+            }
+        }
+        state.clock = input.clock;
+        let output = StreamO {
+            data: Bits(state.shift.0 & Bits::<OUT>::mask().0),
+            valid: state.count != 0,
+            ready: state.count == 0,
+        };
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "WidthDown"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        self.as_verilog()
+    }
+}
+
+impl<const IN: usize, const OUT: usize> WidthDown<IN, OUT> {
+    fn as_verilog(&self) -> Result<HDLDescriptor> {
+        let module_name = self.descriptor().unique_name;
+        let i_kind = <Self as CircuitIO>::I::static_kind();
+        let o_kind = <Self as CircuitIO>::O::static_kind();
+        let input_bits = i_kind.bits() - 1;
+        let output_bits = o_kind.bits() - 1;
+        let inputs = input_wires(&i_kind, &["clock", "data", "valid", "ready"])?;
+        let outputs = output_assigns(
+            &o_kind,
+            &[
+                ("data", format!("shift[{}:0]", OUT - 1)),
+                ("valid", "count != 0".into()),
+                ("ready", "count == 0".into()),
+            ],
+        )?;
+        let ratio = Self::RATIO;
+        let body = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output wire[{output_bits}:0] o);
+{inputs}
+   reg[{top}:0] shift;
+   reg[31:0] count;
+{outputs}
+   initial begin
+      shift = 0;
+      count = 0;
+   end
+   always @(posedge clock) begin
+      if (count != 0) begin
+         if (ready) begin
+            shift <= shift >> {OUT};
+            count <= count - 1;
+         end
+      end else if (valid) begin
+         shift <= data;
+         count <= {ratio};
+      end
+   end
+endmodule
+",
+            top = IN - 1,
+        );
+        Ok(HDLDescriptor {
+            name: module_name,
+            body,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial: vec![],
+        })
+    }
+}
+
+// Gather `OUT / IN` words of `IN` bits into a word of `OUT` bits, with
+// the first word in the least significant bits.  The gathered word is
+// offered until it is taken, and no words are taken in the meantime.
+#[derive(Clone)]
+pub struct WidthUp<const IN: usize, const OUT: usize> {}
+
+impl<const IN: usize, const OUT: usize> WidthUp<IN, OUT> {
+    // The number of input words in each output word.  Any use of it
+    // fails the build if `OUT` is not a multiple of `IN`.
+    const RATIO: usize = {
+        assert!(
+            IN > 0 && OUT.is_multiple_of(IN),
+            "WidthUp needs OUT to be a multiple of IN"
+        );
+        OUT / IN
+    };
+}
+
+impl<const IN: usize, const OUT: usize> Default for WidthUp<IN, OUT> {
+    fn default() -> Self {
+        let _ = Self::RATIO;
+        Self {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WidthUpS<const OUT: usize> {
+    clock: Clock,
+    shift: Bits<OUT>,
+    // The number of input words gathered so far
+    count: usize,
+}
+
+impl<const IN: usize, const OUT: usize> CircuitIO for WidthUp<IN, OUT> {
+    type I = StreamI<Bits<IN>>;
+    type O = StreamO<Bits<OUT>>;
+}
+
+impl<const IN: usize, const OUT: usize> DigitalFn for WidthUp<IN, OUT> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<const IN: usize, const OUT: usize> Circuit for WidthUp<IN, OUT> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |_, _| (idle(), ());
+
+    type S = WidthUpS<OUT>;
+
+    fn init_state(&self) -> Self::S {
+        WidthUpS {
+            clock: Clock(true),
+            ..Default::default()
+        }
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        if input.clock.0 && !state.clock.0 {
+            if state.count == Self::RATIO {
+                if input.ready {
+                    state.count = 0;
+                }
+            } else if input.valid {
+                let shift = state.shift.0.checked_shr(IN as u32).unwrap_or(0);
+                state.shift = Bits(shift | (input.data.0 << (OUT - IN)));
+                state.count += 1;
+            }
+        }
+        state.clock = input.clock;
+        let output = StreamO {
+            data: state.shift,
+            valid: state.count == Self::RATIO,
+            ready: state.count != Self::RATIO,
+        };
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "WidthUp"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        self.as_verilog()
+    }
+}
+
+impl<const IN: usize, const OUT: usize> WidthUp<IN, OUT> {
+    fn as_verilog(&self) -> Result<HDLDescriptor> {
+        let module_name = self.descriptor().unique_name;
+        let i_kind = <Self as CircuitIO>::I::static_kind();
+        let o_kind = <Self as CircuitIO>::O::static_kind();
+        let input_bits = i_kind.bits() - 1;
+        let output_bits = o_kind.bits() - 1;
+        let ratio = Self::RATIO;
+        let inputs = input_wires(&i_kind, &["clock", "data", "valid", "ready"])?;
+        let outputs = output_assigns(
+            &o_kind,
+            &[
+                ("data", "shift".into()),
+                ("valid", format!("count == {ratio}")),
+                ("ready", format!("count != {ratio}")),
+            ],
+        )?;
+        // With one input word to each output word, there is nothing to keep
+        let next = if ratio == 1 {
+            "data".to_string()
+        } else {
+            format!("{{data, shift[{}:{IN}]}}", OUT - 1)
+        };
+        let body = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output wire[{output_bits}:0] o);
+{inputs}
+   reg[{top}:0] shift;
+   reg[31:0] count;
+{outputs}
+   initial begin
+      shift = 0;
+      count = 0;
+   end
+   always @(posedge clock) begin
+      if (count == {ratio}) begin
+         if (ready) begin
+            count <= 0;
+         end
+      end else if (valid) begin
+         shift <= {next};
+         count <= count + 1;
+      end
+   end
+endmodule
+",
+            top = OUT - 1,
+        );
+        Ok(HDLDescriptor {
+            name: module_name,
+            body,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial: vec![],
+        })
+    }
+}
+
+// The number of bytes in the payload of a packet holding a `T`.  This is
+// sent as the first byte of the packet, so it must fit in a byte.
+fn payload_bytes<T: Digital>() -> usize {
+    let bytes = T::bits().div_ceil(8);
+    assert!(
+        (1..=255).contains(&bytes),
+        "A packet must hold between 1 and 255 bytes, but {} needs {bytes}",
+        T::static_kind().get_name()
+    );
+    bytes
+}
+
+fn byte_bits(byte: u128) -> impl Iterator<Item = bool> {
+    (0..8).map(move |ndx| byte & (1 << ndx) != 0)
+}
+
+fn bits_byte(bits: &[bool]) -> Bits<8> {
+    Bits(
+        bits.iter()
+            .rev()
+            .fold(0, |acc, bit| (acc << 1) | (*bit as u128)),
+    )
+}
+
+// Send each value taken from upstream as a packet of bytes.  The packet
+// starts with the number of bytes in the payload, followed by the bits of
+// the value (least significant byte first), padded with zeros to a whole
+// number of bytes.  A new value is taken once the last byte of the
+// previous packet has been sent.
+#[derive(Clone)]
+pub struct Packetizer<T: Digital> {
+    marker: PhantomData<T>,
+}
+
+impl<T: Digital> Default for Packetizer<T> {
+    fn default() -> Self {
+        payload_bytes::<T>();
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PacketizerS {
+    clock: Clock,
+    // The bits of the packet still to be sent, with the current byte first
+    frame: Vec<bool>,
+    // The number of bytes of the packet left to send
+    count: usize,
+}
+
+impl<T: Digital + Default> CircuitIO for Packetizer<T> {
+    type I = StreamI<T>;
+    type O = StreamO<Bits<8>>;
+}
+
+impl<T: Digital> DigitalFn for Packetizer<T> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<T: Digital + Default> Circuit for Packetizer<T> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |_, _| (idle(), ());
+
+    type S = PacketizerS;
+
+    fn init_state(&self) -> Self::S {
+        PacketizerS {
+            clock: Clock(true),
+            frame: vec![false; (payload_bytes::<T>() + 1) * 8],
+            count: 0,
+        }
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        let bytes = payload_bytes::<T>();
+        if input.clock.0 && !state.clock.0 {
+            if state.count != 0 {
+                if input.ready {
+                    state.frame.drain(..8);
+                    state.frame.extend([false; 8]);
+                    state.count -= 1;
+                }
+            } else if input.valid {
+                state.frame = byte_bits(bytes as u128).chain(input.data.bin()).collect();
+                state.frame.resize((bytes + 1) * 8, false);
+                state.count = bytes + 1;
+            }
+        }
+        state.clock = input.clock;
+        let output = StreamO {
+            data: bits_byte(&state.frame[..8]),
+            valid: state.count != 0,
+            ready: state.count == 0,
+        };
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "Packetizer"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        self.as_verilog()
+    }
+}
+
+impl<T: Digital + Default> Packetizer<T> {
+    fn as_verilog(&self) -> Result<HDLDescriptor> {
+        let module_name = self.descriptor().unique_name;
+        let i_kind = <Self as CircuitIO>::I::static_kind();
+        let o_kind = <Self as CircuitIO>::O::static_kind();
+        let input_bits = i_kind.bits() - 1;
+        let output_bits = o_kind.bits() - 1;
+        let bytes = payload_bytes::<T>();
+        let inputs = input_wires(&i_kind, &["clock", "data", "valid", "ready"])?;
+        let outputs = output_assigns(
+            &o_kind,
+            &[
+                ("data", "frame[7:0]".into()),
+                ("valid", "count != 0".into()),
+                ("ready", "count == 0".into()),
+            ],
+        )?;
+        let body = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output wire[{output_bits}:0] o);
+{inputs}
+   reg[{top}:0] frame;
+   reg[31:0] count;
+{outputs}
+   initial begin
+      frame = 0;
+      count = 0;
+   end
+   always @(posedge clock) begin
+      if (count != 0) begin
+         if (ready) begin
+            frame <= frame >> 8;
+            count <= count - 1;
+         end
+      end else if (valid) begin
+         frame <= {{data, 8'd{bytes}}};
+         count <= {packet};
+      end
+   end
+endmodule
+",
+            top = (bytes + 1) * 8 - 1,
+            packet = bytes + 1,
+        );
+        Ok(HDLDescriptor {
+            name: module_name,
+            body,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial: vec![],
+        })
+    }
+}
+
+// A Verilog expression that is true when the bits of `name` starting at
+// `offset` hold a legal value of `kind`, i.e., one that `maybe_from_bin`
+// accepts.  Only an enum can be illegal: its discriminant must belong to
+// one of its variants, and the payload of that variant must be legal.
+fn legal_expr(kind: &Kind, name: &str, offset: usize) -> Result<String> {
+    let count = match kind {
+        Kind::Array(array) => array.size,
+        Kind::Tuple(tuple) => tuple.elements.len(),
+        Kind::Struct(strukt) => strukt.fields.len(),
+        Kind::Ranged(ranged) => return legal_expr(&ranged.base, name, offset),
+        Kind::Enum(enumerate) => {
+            let (range, _) = bit_range(kind.clone(), &Path::default().discriminant())?;
+            let width = range.len();
+            let variants = enumerate
+                .variants
+                .iter()
+                .map(|variant| {
+                    let start = offset + enumerate.payload_range(variant).start;
+                    let payload = legal_expr(&variant.kind, name, start)?;
+                    if width == 0 {
+                        return Ok(payload);
+                    }
+                    let value = variant.discriminant as u128 & (u128::MAX >> (128 - width));
+                    let discriminant = format!(
+                        "{name}[{}:{}] == {width}'d{value}",
+                        offset + range.end - 1,
+                        offset + range.start
+                    );
+                    Ok(if payload == "1'b1" {
+                        discriminant
+                    } else {
+                        format!("{discriminant} && {payload}")
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(format!("(({}))", variants.join(") || (")));
+        }
+        _ => 0,
+    };
+    let terms = (0..count)
+        .map(|ndx| {
+            let (range, kind) = bit_range(kind.clone(), &Path::default().index(ndx))?;
+            legal_expr(&kind, name, offset + range.start)
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|term| term != "1'b1")
+        .collect::<Vec<_>>();
+    Ok(if terms.is_empty() {
+        "1'b1".to_string()
+    } else {
+        terms.join(" && ")
+    })
+}
+
+// Rebuild the values sent by a `Packetizer`.  Once all the bytes of a
+// packet have arrived, the value is offered downstream until it is taken,
+// and no bytes are taken in the meantime.  A packet whose length prefix
+// is wrong, or whose payload does not decode to a `T` (say, because it
+// holds an enum discriminant that belongs to no variant), is offered as a
+// framing error instead, with `data` holding the default `T`.  Every
+// packet is taken to be as long as a packet of a `T`, whatever its
+// length prefix says.
+#[derive(Clone)]
+pub struct Depacketizer<T: Digital> {
+    marker: PhantomData<T>,
+}
+
+impl<T: Digital> Default for Depacketizer<T> {
+    fn default() -> Self {
+        payload_bytes::<T>();
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct DepacketizerO<T: Digital> {
+    pub data: T,
+    pub valid: bool,
+    pub error: bool,
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DepacketizerS<T: Digital> {
+    clock: Clock,
+    // The bytes of the payload received so far, shifted in at the top
+    payload: Vec<bool>,
+    // The number of bytes of the packet received so far
+    count: usize,
+    length_ok: bool,
+    output: DepacketizerO<T>,
+}
+
+impl<T: Digital + Default> CircuitIO for Depacketizer<T> {
+    type I = StreamI<Bits<8>>;
+    type O = DepacketizerO<T>;
+}
+
+impl<T: Digital> DigitalFn for Depacketizer<T> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<T: Digital + Default> Circuit for Depacketizer<T> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |_, _| {
+        let idle = idle::<T>();
+        (
+            DepacketizerO {
+                data: idle.data,
+                valid: idle.valid,
+                error: false,
+                ready: idle.ready,
+            },
+            (),
+        )
+    };
+
+    type S = DepacketizerS<T>;
+
+    fn init_state(&self) -> Self::S {
+        DepacketizerS {
+            clock: Clock(true),
+            payload: vec![false; payload_bytes::<T>() * 8],
+            count: 0,
+            length_ok: false,
+            output: Default::default(),
+        }
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        let bytes = payload_bytes::<T>();
+        if input.clock.0 && !state.clock.0 {
+            if state.output.valid || state.output.error {
+                if input.ready {
+                    state.output.valid = false;
+                    state.output.error = false;
+                }
+            } else if input.valid {
+                if state.count == 0 {
+                    state.length_ok = input.data.0 == bytes as u128;
+                    state.count = 1;
+                } else {
+                    state.payload.drain(..8);
+                    state.payload.extend(byte_bits(input.data.0));
+                    state.count += 1;
+                }
+                if state.count == bytes + 1 {
+                    state.count = 0;
+                    match T::maybe_from_bin(&state.payload[..T::bits()]) {
+                        Ok(data) if state.length_ok => {
+                            state.output.data = data;
+                            state.output.valid = true;
+                        }
+                        _ => {
+                            state.output.data = T::default();
+                            state.output.error = true;
+                        }
+                    }
+                }
+            }
+        }
+        state.clock = input.clock;
+        let output = DepacketizerO {
+            ready: !(state.output.valid || state.output.error),
+            ..state.output
+        };
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "Depacketizer"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        self.as_verilog()
+    }
+}
+
+impl<T: Digital + Default> Depacketizer<T> {
+    fn as_verilog(&self) -> Result<HDLDescriptor> {
+        let module_name = self.descriptor().unique_name;
+        let i_kind = <Self as CircuitIO>::I::static_kind();
+        let o_kind = <Self as CircuitIO>::O::static_kind();
+        let input_bits = i_kind.bits() - 1;
+        let output_bits = o_kind.bits() - 1;
+        let bytes = payload_bytes::<T>();
+        let top = bytes * 8 - 1;
+        let data_bits = T::bits() - 1;
+        let inputs = input_wires(&i_kind, &["clock", "data", "valid", "ready"])?;
+        let outputs = output_assigns(
+            &o_kind,
+            &[
+                ("data", "out_data".into()),
+                ("valid", "out_valid".into()),
+                ("error", "out_error".into()),
+                ("ready", "!(out_valid || out_error)".into()),
+            ],
+        )?;
+        // A one byte payload has nothing to keep when a byte arrives
+        let next = if bytes == 1 {
+            "data".to_string()
+        } else {
+            format!("{{data, payload[{top}:8]}}")
+        };
+        let legal = legal_expr(&T::static_kind(), "next_payload", 0)?;
+        let zero = as_verilog_literal(&T::default().typed_bits());
+        let body = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output wire[{output_bits}:0] o);
+{inputs}
+   reg[{top}:0] payload;
+   reg[31:0] count;
+   reg length_ok;
+   reg[{data_bits}:0] out_data;
+   reg out_valid;
+   reg out_error;
+   wire[{top}:0] next_payload;
+   wire legal;
+   assign next_payload = {next};
+   assign legal = {legal};
+{outputs}
+   initial begin
+      payload = 0;
+      count = 0;
+      length_ok = 0;
+      out_data = {zero};
+      out_valid = 0;
+      out_error = 0;
+   end
+   always @(posedge clock) begin
+      if (out_valid || out_error) begin
+         if (ready) begin
+            out_valid <= 0;
+            out_error <= 0;
+         end
+      end else if (valid) begin
+         if (count == 0) begin
+            length_ok <= data == 8'd{bytes};
+            count <= 1;
+         end else if (count == {bytes}) begin
+            payload <= next_payload;
+            count <= 0;
+            if (length_ok && legal) begin
+               out_data <= next_payload[{data_bits}:0];
+               out_valid <= 1;
+            end else begin
+               out_data <= {zero};
+               out_error <= 1;
+            end
+         end else begin
+            payload <= next_payload;
+            count <= count + 1;
+         end
+      end
+   end
+endmodule
+"
+        );
+        Ok(HDLDescriptor {
+            name: module_name,
+            body,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rhdl_bits::alias::*;
+    use rhdl_macro::{kernel, Circuit};
+
+    #[derive(Debug, Clone, PartialEq, Digital, Copy)]
+    pub enum Command {
+        Nop,
+        Write(b4, b8),
+        Read(b4),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+    pub struct Request {
+        pub command: Command,
+        pub tag: b3,
+        pub flags: [bool; 2],
+    }
+
+    // Run a stage through one clock cycle, and return its output after
+    // the rising edge.
+    fn tick<C: Circuit>(circuit: &C, state: &mut C::S, input: impl Fn(Clock) -> C::I) -> C::O {
+        let mut io = C::Z::default();
+        circuit.sim(input(Clock(false)), state, &mut io);
+        circuit.sim(input(Clock(true)), state, &mut io)
+    }
+
+    // Random values of `T`, made by decoding random bits, and keeping the
+    // ones that are legal.
+    fn random_values<T: Digital>(rng: &mut StdRng, count: usize) -> Vec<T> {
+        std::iter::repeat_with(|| (0..T::bits()).map(|_| rng.gen()).collect::<Vec<bool>>())
+            .filter_map(|bits| T::maybe_from_bin(&bits).ok())
+            .take(count)
+            .collect()
+    }
+
+    // Send `values` through a packetizer into a depacketizer, offering the
+    // next value whenever the packetizer is ready.  `ready` decides, for
+    // each cycle, whether the depacketizer output is taken.  Returns the
+    // packets received, with `None` for a framing error.
+    fn round_trip<T: Digital + Default>(
+        values: &[T],
+        mut ready: impl FnMut() -> bool,
+    ) -> Vec<Option<T>> {
+        let pack = Packetizer::<T>::default();
+        let depack = Depacketizer::<T>::default();
+        let mut pack_state = pack.init_state();
+        let mut depack_state = depack.init_state();
+        let mut p = tick(&pack, &mut pack_state, |_| StreamI::default());
+        let mut d = tick(&depack, &mut depack_state, |_| StreamI::default());
+        let mut sent = 0;
+        let mut received = vec![];
+        let cycles = values.len() * (payload_bytes::<T>() + 1) * 4 + 100;
+        for _ in 0..cycles {
+            let taken = ready();
+            if (d.valid || d.error) && taken {
+                received.push(d.valid.then_some(d.data));
+            }
+            let offered = StreamI {
+                clock: Clock(false),
+                data: values.get(sent).copied().unwrap_or_default(),
+                valid: sent < values.len(),
+                ready: d.ready,
+            };
+            if p.ready && offered.valid {
+                sent += 1;
+            }
+            let bytes = StreamI {
+                clock: Clock(false),
+                data: p.data,
+                valid: p.valid,
+                ready: taken,
+            };
+            p = tick(&pack, &mut pack_state, |clock| StreamI { clock, ..offered });
+            d = tick(&depack, &mut depack_state, |clock| StreamI {
+                clock,
+                ..bytes
+            });
+            if received.len() == values.len() {
+                break;
+            }
+        }
+        received
+    }
+
+    fn check_round_trip<T: Digital + Default + std::fmt::Debug>(seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let values = random_values::<T>(&mut rng, 100);
+        let received = round_trip(&values, || rng.gen_bool(0.7));
+        assert_eq!(received, values.into_iter().map(Some).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_packet_round_trip() {
+        check_round_trip::<b8>(0x5eed);
+        check_round_trip::<(b4, bool, b12)>(0x7e57);
+        check_round_trip::<Command>(0xc0de);
+        check_round_trip::<Request>(0xbeef);
+        check_round_trip::<[b5; 7]>(0xface);
+    }
+
+    // The inputs that feed the given bytes into a depacketizer, one per
+    // cycle, with the output always taken.
+    fn byte_inputs(bytes: &[u8]) -> Vec<StreamI<b8>> {
+        clocked_inputs(bytes.iter().copied(), |clock, byte| StreamI {
+            clock,
+            data: b8(byte as u128),
+            valid: true,
+            ready: true,
+        })
+    }
+
+    // A good packet, a packet with the wrong length, a packet with a
+    // discriminant that belongs to no variant, and a good packet again.
+    // Each packet takes an extra cycle, as no bytes are taken while the
+    // result of the last one is offered.
+    const FRAMES: [u8; 16] = [
+        2, 0x91, 0x1a, 0, 3, 0x91, 0x1a, 0, 2, 0x00, 0x30, 0, 2, 0x05, 0x20, 0,
+    ];
+
+    #[test]
+    fn test_depacketizer_flags_framing_errors() {
+        let depack = Depacketizer::<Command>::default();
+        let mut state = depack.init_state();
+        let outputs = run_clocked(&depack, &mut state, &byte_inputs(&FRAMES))
+            .into_iter()
+            .filter(|o| o.valid || o.error)
+            .map(|o| o.valid.then_some(o.data))
+            .collect::<Vec<_>>();
+        // Write(0x1, 0xa9) is 0b01_1010_1001_0001, with the discriminant
+        // in the top two bits
+        let write = Command::Write(b4(0x1), b8(0xa9));
+        assert_eq!(Command::maybe_from_bin(&b14(0x1a91).bin()).unwrap(), write);
+        assert_eq!(
+            outputs,
+            [Some(write), None, None, Some(Command::Read(b4(0x5)))]
+        );
+    }
+
+    #[test]
+    fn test_width_round_trip() {
+        let down = WidthDown::<32, 8>::default();
+        let up = WidthUp::<8, 32>::default();
+        let mut down_state = down.init_state();
+        let mut up_state = up.init_state();
+        let mut rng = StdRng::seed_from_u64(0x3d3d);
+        let words = (0..100)
+            .map(|_| b32(rng.gen::<u32>() as u128))
+            .collect::<Vec<_>>();
+        let mut dn = tick(&down, &mut down_state, |_| StreamI::default());
+        let mut u = tick(&up, &mut up_state, |_| StreamI::default());
+        let mut sent = 0;
+        let mut received = vec![];
+        while received.len() < words.len() {
+            let taken = rng.gen_bool(0.5);
+            if u.valid && taken {
+                received.push(u.data);
+            }
+            let offered = StreamI {
+                clock: Clock(false),
+                data: words.get(sent).copied().unwrap_or_default(),
+                valid: sent < words.len(),
+                ready: u.ready,
+            };
+            if dn.ready && offered.valid {
+                sent += 1;
+            }
+            let parts = StreamI {
+                clock: Clock(false),
+                data: dn.data,
+                valid: dn.valid,
+                ready: taken,
+            };
+            dn = tick(&down, &mut down_state, |clock| StreamI { clock, ..offered });
+            u = tick(&up, &mut up_state, |clock| StreamI { clock, ..parts });
+        }
+        assert_eq!(received, words);
+    }
+
+    #[test]
+    fn test_width_down_sends_low_bits_first() {
+        let down = WidthDown::<12, 4>::default();
+        let mut state = down.init_state();
+        let mut outputs = vec![];
+        for cycle in 0..5 {
+            let o = tick(&down, &mut state, |clock| StreamI {
+                clock,
+                data: b12(0xabc),
+                valid: cycle == 0,
+                ready: true,
+            });
+            outputs.push((o.data.0, o.valid));
+        }
+        assert_eq!(
+            outputs,
+            [
+                (0xc, true),
+                (0xb, true),
+                (0xa, true),
+                (0, false),
+                (0, false)
+            ]
+        );
+    }
+
+    // A packetizer feeding a depacketizer, by way of a 24 bit datapath.  A
+    // packet of a `Command` is 3 bytes long, so each fills one word.
+    #[derive(Clone, Circuit)]
+    #[rhdl(kernel = command_loopback)]
+    pub struct CommandLoopback {
+        pack: Packetizer<Command>,
+        up: WidthUp<8, 24>,
+        down: WidthDown<24, 8>,
+        depack: Depacketizer<Command>,
+    }
+
+    impl CircuitIO for CommandLoopback {
+        type I = StreamI<Command>;
+        type O = DepacketizerO<Command>;
+    }
+
+    #[kernel]
+    pub fn command_loopback(
+        i: StreamI<Command>,
+        q: CommandLoopbackQ,
+    ) -> (DepacketizerO<Command>, CommandLoopbackD) {
+        let mut d = CommandLoopbackD::default();
+        d.pack.clock = i.clock;
+        d.pack.data = i.data;
+        d.pack.valid = i.valid;
+        d.pack.ready = q.up.ready;
+        d.up.clock = i.clock;
+        d.up.data = q.pack.data;
+        d.up.valid = q.pack.valid;
+        d.up.ready = q.down.ready;
+        d.down.clock = i.clock;
+        d.down.data = q.up.data;
+        d.down.valid = q.up.valid;
+        d.down.ready = q.depack.ready;
+        d.depack.clock = i.clock;
+        d.depack.data = q.down.data;
+        d.depack.valid = q.down.valid;
+        d.depack.ready = i.ready;
+        let mut o = q.depack;
+        o.ready = q.pack.ready;
+        (o, d)
+    }
+
+    #[test]
+    fn test_command_loopback() {
+        let loopback = CommandLoopback {
+            pack: Default::default(),
+            up: Default::default(),
+            down: Default::default(),
+            depack: Default::default(),
+        };
+        let mut rng = StdRng::seed_from_u64(0x100b);
+        let commands = random_values::<Command>(&mut rng, 20);
+        let mut state = loopback.init_state();
+        let mut o = tick(&loopback, &mut state, |_| StreamI::default());
+        let mut inputs = vec![];
+        let mut sent = 0;
+        let mut received = vec![];
+        while received.len() < commands.len() {
+            assert!(inputs.len() < 10_000);
+            let input = StreamI {
+                clock: Clock(false),
+                data: commands.get(sent).copied().unwrap_or(Command::Nop),
+                valid: sent < commands.len(),
+                ready: rng.gen_bool(0.5),
+            };
+            if o.valid && input.ready {
+                received.push(o.data);
+            }
+            assert!(!o.error);
+            if o.ready && input.valid {
+                sent += 1;
+            }
+            inputs.push(input);
+            inputs.push(StreamI {
+                clock: Clock(true),
+                ..input
+            });
+            o = tick(&loopback, &mut state, |clock| StreamI { clock, ..input });
+        }
+        assert_eq!(received, commands);
+        #[cfg(feature = "iverilog")]
+        {
+            // The testbench checks the outputs of the Verilog against the
+            // simulation on every cycle
+            let tm = loopback.testbench(&inputs).unwrap();
+            tm.run_iverilog().unwrap();
+        }
+    }
+
+    #[cfg(feature = "iverilog")]
+    #[test]
+    fn test_depacketizer_framing_errors_verilog() {
+        let tm = Depacketizer::<Command>::default()
+            .testbench(&byte_inputs(&FRAMES))
+            .unwrap();
+        tm.run_iverilog().unwrap();
+        let tm = Depacketizer::<Request>::default()
+            .testbench(&byte_inputs(&[
+                3, 0x91, 0x1a, 0x05, 0, 3, 0xff, 0xff, 0x07, 0,
+            ]))
+            .unwrap();
+        tm.run_iverilog().unwrap();
+    }
+}
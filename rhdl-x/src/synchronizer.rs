@@ -0,0 +1,449 @@
+use anyhow::ensure;
+use anyhow::Result;
+use rhdl_core::circuit_ports;
+use rhdl_core::constraint_domain_crossing;
+use rhdl_core::constraint_must_clock;
+use rhdl_core::constraint_output_synchronous;
+use rhdl_core::note;
+use rhdl_core::path::Path;
+use rhdl_core::root_descriptor;
+use rhdl_core::schematic::components::BlackBoxComponent;
+use rhdl_core::schematic::components::ComponentKind;
+use rhdl_core::schematic::schematic_impl::pin_path;
+use rhdl_core::schematic::schematic_impl::PinIx;
+use rhdl_core::schematic::schematic_impl::Schematic;
+use rhdl_core::verilog_attribute;
+use rhdl_core::BlackBoxTrait;
+use rhdl_core::Circuit;
+use rhdl_core::CircuitDescriptor;
+use rhdl_core::CircuitIO;
+use rhdl_core::Constraint;
+use rhdl_core::EdgeType;
+use rhdl_core::HDLDescriptor;
+use rhdl_core::HDLKind;
+use rhdl_core::TypedBits;
+use rhdl_core::{initial_block, Digital, DigitalFn, HDLInitial};
+use rhdl_macro::Digital;
+
+use crate::clock::Clock;
+
+// A chain of `STAGES` flip flops that brings an asynchronous signal (or
+// one from another clock domain) into the domain of `clock`.  The first
+// stage may go metastable, and the rest give it time to settle.
+//
+// Each bit is synchronized on its own, so a multi-bit `T` must change
+// at most one bit at a time (e.g., a gray coded counter), or the output
+// may briefly show a value that the input never had.
+#[derive(Clone)]
+pub struct Synchronizer<T: Digital, const STAGES: usize = 2> {
+    init: T,
+    jitter: Jitter,
+}
+
+impl<T: Digital, const STAGES: usize> Synchronizer<T, STAGES> {
+    const CHECK_STAGES: () = assert!(STAGES >= 2, "A synchronizer needs at least 2 stages");
+
+    pub fn new(init: T) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::CHECK_STAGES;
+        Self {
+            init,
+            jitter: Jitter::default(),
+        }
+    }
+
+    pub fn with_jitter(self, jitter: Jitter) -> Self {
+        Self { jitter, ..self }
+    }
+}
+
+// Jitter on the input, for the simulation model only.  Each time the
+// input changes, the first stage keeps sampling the old value for a
+// random number of edges, from 0 up to `max_cycles`, as it may when the
+// change lands close to the clock edge.  The same seed always gives the
+// same delays.  The generated Verilog does not change.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Jitter {
+    pub max_cycles: usize,
+    pub seed: u64,
+}
+
+impl Jitter {
+    // Draw the next delay from a xorshift generator
+    fn delay(&self, rng: &mut u64) -> usize {
+        if self.max_cycles == 0 {
+            return 0;
+        }
+        let mut x = (*rng).max(1);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        *rng = x;
+        (x % (self.max_cycles as u64 + 1)) as usize
+    }
+}
+
+impl<T: Digital + Default, const STAGES: usize> Default for Synchronizer<T, STAGES> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct SynchronizerI<T: Digital> {
+    pub clock: Clock,
+    pub data: T,
+}
+
+impl<T: Digital, const STAGES: usize> CircuitIO for Synchronizer<T, STAGES> {
+    type I = SynchronizerI<T>;
+    type O = T;
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SynchronizerState<T> {
+    clock: Clock,
+    stages: Vec<T>,
+    // The last input, and the edges left before the first stage sees it
+    input: T,
+    delay: usize,
+    rng: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SynchronizerComponent {
+    clock: PinIx,
+    d: PinIx,
+    q: PinIx,
+}
+
+impl BlackBoxTrait for SynchronizerComponent {
+    fn name(&self) -> &'static str {
+        "Synchronizer"
+    }
+
+    fn args(&self) -> Vec<PinIx> {
+        vec![self.clock, self.d]
+    }
+
+    fn output(&self) -> PinIx {
+        self.q
+    }
+
+    fn offset(&self, shift: usize) -> BlackBoxComponent {
+        BlackBoxComponent::new(SynchronizerComponent {
+            clock: self.clock.offset(shift),
+            d: self.d.offset(shift),
+            q: self.q.offset(shift),
+        })
+    }
+
+    // Unlike a DFF, the input need not be synchronous to the clock
+    fn constraints(&self) -> Vec<Constraint> {
+        vec![
+            constraint_must_clock(pin_path(self.clock, Path::default())),
+            constraint_domain_crossing(
+                pin_path(self.d, Path::default()),
+                pin_path(self.clock, Path::default()),
+            ),
+            constraint_output_synchronous(
+                pin_path(self.q, Path::default()),
+                pin_path(self.clock, Path::default()),
+                EdgeType::Positive,
+            ),
+        ]
+    }
+}
+
+impl<T: Digital + Default, const STAGES: usize> Circuit for Synchronizer<T, STAGES> {
+    type Q = ();
+
+    type D = ();
+
+    type Z = ();
+
+    type Update = Self;
+
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |i, _| (i.data, ());
+
+    type S = SynchronizerState<T>;
+
+    fn init_state(&self) -> Self::S {
+        SynchronizerState {
+            clock: Clock(true),
+            stages: vec![self.init; STAGES],
+            input: self.init,
+            delay: 0,
+            rng: self.jitter.seed,
+        }
+    }
+
+    fn state_signals(&self, state: &Self::S) -> Vec<(String, TypedBits)> {
+        state
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(ndx, stage)| (format!("stage{ndx}"), stage.typed_bits()))
+            .collect()
+    }
+
+    fn sim(&self, input: Self::I, state: &mut Self::S, _io: &mut Self::Z) -> Self::O {
+        note("input", input);
+        if input.clock.0 && !state.clock.0 {
+            if input.data != state.input {
+                state.input = input.data;
+                state.delay = self.jitter.delay(&mut state.rng);
+            }
+            let sampled = if state.delay > 0 {
+                state.delay -= 1;
+                state.stages[0]
+            } else {
+                input.data
+            };
+            state.stages.pop();
+            state.stages.insert(0, sampled);
+        }
+        state.clock = input.clock;
+        let output = state.stages[STAGES - 1];
+        note("output", output);
+        output
+    }
+
+    fn name(&self) -> &'static str {
+        "Synchronizer"
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> Result<HDLDescriptor> {
+        ensure!(kind == HDLKind::Verilog);
+        Ok(self.as_verilog())
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        let mut desc = root_descriptor(self);
+        let mut schematic = Schematic::default();
+        let (input_rx, input_tx) = schematic.make_buffer(SynchronizerI::<T>::static_kind(), None);
+        // Split the clock and the data out of the input
+        let clock = schematic.make_field_tap(input_tx, "clock", Clock::static_kind());
+        let data = schematic.make_field_tap(input_tx, "data", T::static_kind());
+        let c = schematic.make_pin(Clock::static_kind(), "clock".to_string(), None);
+        let d = schematic.make_pin(T::static_kind(), "d".to_string(), None);
+        let q = schematic.make_pin(T::static_kind(), "q".to_string(), None);
+        let sync = schematic.make_component(
+            ComponentKind::BlackBox(BlackBoxComponent::new(SynchronizerComponent {
+                clock: c,
+                d,
+                q,
+            })),
+            None,
+        );
+        schematic.pin_mut(c).parent(sync);
+        schematic.pin_mut(d).parent(sync);
+        schematic.pin_mut(q).parent(sync);
+        schematic.wire(clock, c);
+        schematic.wire(data, d);
+        schematic.inputs = vec![input_rx];
+        schematic.output = schematic.make_update_output(q);
+        desc.update_schematic = Some(schematic);
+        desc
+    }
+}
+
+impl<T: Digital, const STAGES: usize> DigitalFn for Synchronizer<T, STAGES> {
+    fn kernel_fn() -> Option<rhdl_core::KernelFnKind> {
+        None
+    }
+}
+
+impl<T: Digital + Default, const STAGES: usize> Synchronizer<T, STAGES> {
+    // The stages are marked with ASYNC_REG, so that the tools place them
+    // next to each other, and do not merge them or retime through them.
+    fn as_verilog(&self) -> HDLDescriptor {
+        let module_name = self.descriptor().unique_name;
+        let input_bits = T::bits();
+        let output_bits = T::bits().saturating_sub(1);
+        let initial = (0..STAGES)
+            .map(|ndx| HDLInitial {
+                name: format!("stage{ndx}"),
+                reg: format!("stage{ndx}"),
+                value: self.init.typed_bits(),
+            })
+            .collect::<Vec<_>>();
+        let attribute = verilog_attribute("ASYNC_REG", Some("TRUE"));
+        let decls = (0..STAGES)
+            .map(|ndx| format!("   {attribute}reg[{output_bits}:0] stage{ndx};\n"))
+            .collect::<String>();
+        let inits = initial_block(&initial);
+        let shifts = (1..STAGES)
+            .map(|ndx| format!("      stage{ndx} <= stage{};\n", ndx - 1))
+            .collect::<String>();
+        let last = STAGES - 1;
+        let code = format!(
+            "
+module {module_name}(input wire[{input_bits}:0] i, output wire[{output_bits}:0] o);
+   wire clk;
+   wire[{output_bits}:0] d;
+{decls}   assign clk = i[0];
+   assign d = i[{input_bits}:1];
+   assign o = stage{last};
+{inits}   always @(posedge clk) begin
+      stage0 <= d;
+{shifts}   end
+endmodule
+"
+        );
+        HDLDescriptor {
+            name: module_name,
+            body: code,
+            ports: circuit_ports::<Self>(),
+            children: Default::default(),
+            initial,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+    use crate::dff::DFF;
+    use rhdl_bits::{bits, Bits};
+    use rhdl_core::{check_schematic, crusty::index::IndexedSchematic};
+    use rhdl_macro::{kernel, Circuit};
+
+    fn inputs<T: Digital>(data: &[T]) -> Vec<SynchronizerI<T>> {
+        clocked_inputs(data.iter().copied(), |clock, data| SynchronizerI {
+            clock,
+            data,
+        })
+    }
+
+    #[test]
+    fn test_synchronizer_delays_by_its_stages() {
+        let data = [1, 2, 3, 4, 5, 6].map(bits::<4>);
+        let sync = Synchronizer::<Bits<4>, 3>::default();
+        let mut state = sync.init_state();
+        // Sampled before each rising edge
+        let outputs = run_clocked(&sync, &mut state, &inputs(&data));
+        assert_eq!(outputs, [0, 0, 0, 1, 2, 3].map(bits::<4>));
+        let signals = sync.state_signals(&state);
+        assert_eq!(signals.len(), 3);
+        assert_eq!(
+            signals[0],
+            ("stage0".to_string(), bits::<4>(6).typed_bits())
+        );
+    }
+
+    #[test]
+    fn test_synchronizer_jitter_delays_changes() {
+        // The input steps from 0 to 1 at cycle 4
+        let data = (0..10).map(|ndx| ndx >= 4).collect::<Vec<_>>();
+        let rise = |jitter: Jitter| {
+            let sync = Synchronizer::<bool>::default().with_jitter(jitter);
+            let mut state = sync.init_state();
+            run_clocked(&sync, &mut state, &inputs(&data))
+                .into_iter()
+                .position(|x| x)
+                .unwrap()
+        };
+        assert_eq!(rise(Jitter::default()), 6);
+        let rises = (0..16)
+            .map(|seed| {
+                rise(Jitter {
+                    max_cycles: 3,
+                    seed,
+                })
+            })
+            .collect::<Vec<_>>();
+        assert!(rises.iter().all(|rise| (6..=9).contains(rise)));
+        assert!(rises.iter().any(|rise| *rise != rises[0]));
+    }
+
+    // An input that comes from outside of the clock domain
+    fn check_with_async_data(descriptor: CircuitDescriptor) -> usize {
+        let schematic = descriptor.schematic().unwrap();
+        let mut is: IndexedSchematic = schematic.into();
+        let input = is.schematic.inputs[0];
+        is.add_synchronous_source(pin_path(input, Path::default().field("clock")), 0.into())
+            .unwrap();
+        is.add_asynchronous_source(pin_path(input, Path::default().field("data")))
+            .unwrap();
+        check_schematic(&mut is).len()
+    }
+
+    #[test]
+    fn test_synchronizer_is_a_domain_crossing() {
+        let sync = Synchronizer::<bool>::default();
+        assert_eq!(check_with_async_data(sync.descriptor()), 0);
+        // A plain DFF is not
+        let dff = DFF::<bool>::from(false);
+        assert_eq!(check_with_async_data(dff.descriptor()), 1);
+    }
+
+    // Data brought into the domain of `sync_clock`, and then (wrongly)
+    // registered on `reg_clock`
+    #[derive(Default, Clone, Circuit)]
+    #[rhdl(kernel = wrong_domain)]
+    struct WrongDomain {
+        sync: Synchronizer<bool>,
+        reg: DFF<bool>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+    struct WrongDomainI {
+        sync_clock: Clock,
+        reg_clock: Clock,
+        data: bool,
+    }
+
+    impl CircuitIO for WrongDomain {
+        type I = WrongDomainI;
+        type O = bool;
+    }
+
+    #[kernel]
+    fn wrong_domain(i: WrongDomainI, q: WrongDomainQ) -> (bool, WrongDomainD) {
+        let mut d = WrongDomainD::default();
+        d.sync.clock = i.sync_clock;
+        d.sync.data = i.data;
+        d.reg.clock = i.reg_clock;
+        d.reg.data = q.sync;
+        (q.reg, d)
+    }
+
+    // The number of errors when the two clocks are given the ids
+    fn check_with_clocks(sync_clock: usize, reg_clock: usize) -> usize {
+        let schematic = WrongDomain::default().descriptor().schematic().unwrap();
+        let mut is: IndexedSchematic = schematic.into();
+        let input = is.schematic.inputs[0];
+        let field = |name| pin_path(input, Path::default().field(name));
+        is.add_synchronous_source(field("sync_clock"), sync_clock.into())
+            .unwrap();
+        is.add_synchronous_source(field("reg_clock"), reg_clock.into())
+            .unwrap();
+        is.add_asynchronous_source(field("data")).unwrap();
+        check_schematic(&mut is).len()
+    }
+
+    #[test]
+    fn test_synchronizer_output_is_in_its_clock_domain() {
+        assert_eq!(check_with_clocks(0, 0), 0);
+        assert_eq!(check_with_clocks(0, 1), 1);
+    }
+
+    #[test]
+    fn test_synchronizer_verilog() {
+        let sync = Synchronizer::<Bits<4>>::new(bits(5));
+        let hdl = sync.as_hdl(HDLKind::Verilog).unwrap();
+        assert!(hdl
+            .body
+            .contains("(* ASYNC_REG = \"TRUE\" *) reg[3:0] stage0;"));
+        assert!(hdl
+            .body
+            .contains("(* ASYNC_REG = \"TRUE\" *) reg[3:0] stage1;"));
+        assert!(!hdl.body.contains("stage2"));
+        let data = (0..16).map(bits::<4>).collect::<Vec<_>>();
+        let tm = sync.testbench(&inputs(&data)).unwrap();
+        assert_eq!(tm.num_cases, 32);
+        tm.run_iverilog().unwrap();
+    }
+}
@@ -49,7 +49,7 @@ pub fn leaf_paths(kind: &Kind, base: Path) -> Vec<Path> {
             })
             .chain(once(base.clone().discriminant()))
             .collect(),
-        Kind::Bits(_) | Kind::Signed(_) | Kind::Empty => vec![base.clone()],
+        Kind::Bits(_) | Kind::Signed(_) | Kind::Empty | Kind::Ranged(_) => vec![base.clone()],
     }
 }
 
@@ -68,16 +68,12 @@ fn test_leaf_paths_for_kind() {
         b: b4,
     }
 
-    #[derive(Copy, Clone, PartialEq, Digital, Default)]
+    #[derive(Copy, Clone, PartialEq, Digital)]
     pub enum Baz {
-        #[default]
         O,
         A(b4),
         B(Bar),
-        C {
-            x: b4,
-            y: b4,
-        },
+        C { x: b4, y: b4 },
     }
 
     let kind = Baz::static_kind();
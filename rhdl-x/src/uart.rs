@@ -0,0 +1,434 @@
+use rhdl_bits::{bits, Bits};
+use rhdl_core::CircuitIO;
+use rhdl_macro::{kernel, Circuit, Digital};
+
+use crate::{clock::Clock, constant::Constant, dff::DFF, synchronizer::Synchronizer};
+
+// A UART with 8N1 framing: a low start bit, eight data bits (least
+// significant first), and a high stop bit.  The line idles high.  Each
+// bit lasts `divisor` clock cycles, so the baud rate is the clock rate
+// divided by `divisor`, which must be at least 2.  The divisor is an
+// instance constant, with `W` bits.
+
+#[derive(Debug, Clone, PartialEq, Digital, Copy)]
+pub enum UartState {
+    Idle,
+    Start,
+    Data,
+    Stop,
+}
+
+// The transmitter takes a byte when both `valid` and `ready` are high on
+// a rising edge, and is ready again once it has sent the stop bit.
+#[derive(Clone, Circuit)]
+#[rhdl(kernel = uart_tx::<W>)]
+pub struct UartTx<const W: usize> {
+    divisor: Constant<Bits<W>>,
+    state: DFF<UartState>,
+    count: DFF<Bits<W>>,
+    bit: DFF<Bits<3>>,
+    shift: DFF<Bits<8>>,
+}
+
+impl<const W: usize> UartTx<W> {
+    pub fn new(divisor: Bits<W>) -> Self {
+        Self {
+            divisor: divisor.into(),
+            state: UartState::Idle.into(),
+            count: Bits::<W>::default().into(),
+            bit: Bits::<3>::default().into(),
+            shift: Bits::<8>::default().into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct UartTxI {
+    pub clock: Clock,
+    pub data: Bits<8>,
+    pub valid: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct UartTxO {
+    pub tx: bool,
+    pub ready: bool,
+}
+
+impl<const W: usize> CircuitIO for UartTx<W> {
+    type I = UartTxI;
+    type O = UartTxO;
+}
+
+#[kernel]
+pub fn uart_tx<const W: usize>(i: UartTxI, q: UartTxQ<W>) -> (UartTxO, UartTxD<W>) {
+    let mut d = UartTxD::<W>::default();
+    d.state.clock = i.clock;
+    d.count.clock = i.clock;
+    d.bit.clock = i.clock;
+    d.shift.clock = i.clock;
+    d.count.data = q.count + bits::<{ W }>(1);
+    d.bit.data = q.bit;
+    d.shift.data = q.shift;
+    // The last cycle of the current bit
+    let done = q.count == q.divisor - bits::<{ W }>(1);
+    if done {
+        d.count.data = bits::<{ W }>(0);
+    }
+    let mut o = UartTxO {
+        tx: true,
+        ready: false,
+    };
+    d.state.data = match q.state {
+        UartState::Idle => {
+            o.ready = true;
+            d.count.data = bits::<{ W }>(0);
+            d.bit.data = bits::<3>(0);
+            if i.valid {
+                d.shift.data = i.data;
+                UartState::Start
+            } else {
+                UartState::Idle
+            }
+        }
+        UartState::Start => {
+            o.tx = false;
+            if done {
+                UartState::Data
+            } else {
+                UartState::Start
+            }
+        }
+        UartState::Data => {
+            o.tx = (q.shift & bits::<8>(1)) != bits::<8>(0);
+            if done {
+                d.shift.data = q.shift >> bits::<1>(1);
+                d.bit.data = q.bit + bits::<3>(1);
+                if q.bit == bits::<3>(7) {
+                    UartState::Stop
+                } else {
+                    UartState::Data
+                }
+            } else {
+                UartState::Data
+            }
+        }
+        UartState::Stop => {
+            if done {
+                UartState::Idle
+            } else {
+                UartState::Stop
+            }
+        }
+    };
+    (o, d)
+}
+
+// The receiver brings the line into its clock domain, waits for a start
+// bit, and samples each bit in the middle.  A received byte is held on
+// `data` with `valid` high until it is taken with `ready`.  A byte whose
+// stop bit is low (a framing error) is dropped, and a byte that arrives
+// before the last one was taken replaces it.
+#[derive(Clone, Circuit)]
+#[rhdl(kernel = uart_rx::<W>)]
+pub struct UartRx<const W: usize> {
+    divisor: Constant<Bits<W>>,
+    line: Synchronizer<bool>,
+    state: DFF<UartState>,
+    count: DFF<Bits<W>>,
+    bit: DFF<Bits<3>>,
+    shift: DFF<Bits<8>>,
+    data: DFF<Bits<8>>,
+    valid: DFF<bool>,
+}
+
+impl<const W: usize> UartRx<W> {
+    pub fn new(divisor: Bits<W>) -> Self {
+        Self {
+            divisor: divisor.into(),
+            // The idle line is high
+            line: Synchronizer::new(true),
+            state: UartState::Idle.into(),
+            count: Bits::<W>::default().into(),
+            bit: Bits::<3>::default().into(),
+            shift: Bits::<8>::default().into(),
+            data: Bits::<8>::default().into(),
+            valid: false.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct UartRxI {
+    pub clock: Clock,
+    pub rx: bool,
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct UartRxO {
+    pub data: Bits<8>,
+    pub valid: bool,
+}
+
+impl<const W: usize> CircuitIO for UartRx<W> {
+    type I = UartRxI;
+    type O = UartRxO;
+}
+
+#[kernel]
+pub fn uart_rx<const W: usize>(i: UartRxI, q: UartRxQ<W>) -> (UartRxO, UartRxD<W>) {
+    let mut d = UartRxD::<W>::default();
+    d.line.clock = i.clock;
+    d.line.data = i.rx;
+    d.state.clock = i.clock;
+    d.count.clock = i.clock;
+    d.bit.clock = i.clock;
+    d.shift.clock = i.clock;
+    d.data.clock = i.clock;
+    d.valid.clock = i.clock;
+    d.count.data = q.count + bits::<{ W }>(1);
+    d.bit.data = q.bit;
+    d.shift.data = q.shift;
+    d.data.data = q.data;
+    d.valid.data = q.valid & !i.ready;
+    // The middle of the start bit, and the middle of every bit after it
+    let half = q.count == (q.divisor >> bits::<1>(1));
+    let done = q.count == q.divisor - bits::<{ W }>(1);
+    if done {
+        d.count.data = bits::<{ W }>(0);
+    }
+    d.state.data = match q.state {
+        UartState::Idle => {
+            d.count.data = bits::<{ W }>(0);
+            d.bit.data = bits::<3>(0);
+            if q.line {
+                UartState::Idle
+            } else {
+                UartState::Start
+            }
+        }
+        UartState::Start => {
+            if half {
+                d.count.data = bits::<{ W }>(0);
+                // A start bit that does not last is a glitch
+                if q.line {
+                    UartState::Idle
+                } else {
+                    UartState::Data
+                }
+            } else {
+                UartState::Start
+            }
+        }
+        UartState::Data => {
+            if done {
+                d.shift.data = q.shift >> bits::<1>(1);
+                if q.line {
+                    d.shift.data = (q.shift >> bits::<1>(1)) | bits::<8>(0x80);
+                }
+                d.bit.data = q.bit + bits::<3>(1);
+                if q.bit == bits::<3>(7) {
+                    UartState::Stop
+                } else {
+                    UartState::Data
+                }
+            } else {
+                UartState::Data
+            }
+        }
+        UartState::Stop => {
+            if done {
+                if q.line {
+                    d.data.data = q.shift;
+                    d.valid.data = true;
+                }
+                UartState::Idle
+            } else {
+                UartState::Stop
+            }
+        }
+    };
+    (
+        UartRxO {
+            data: q.data,
+            valid: q.valid,
+        },
+        d,
+    )
+}
+
+// A transmitter wired to a receiver, as a self test of the pair.
+#[derive(Clone, Circuit)]
+#[rhdl(kernel = uart_loopback::<W>)]
+pub struct UartLoopback<const W: usize> {
+    tx: UartTx<W>,
+    rx: UartRx<W>,
+}
+
+impl<const W: usize> UartLoopback<W> {
+    pub fn new(divisor: Bits<W>) -> Self {
+        Self {
+            tx: UartTx::new(divisor),
+            rx: UartRx::new(divisor),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct UartLoopbackI {
+    pub clock: Clock,
+    pub data: Bits<8>,
+    pub valid: bool,
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Digital, Default, Copy)]
+pub struct UartLoopbackO {
+    pub tx_ready: bool,
+    pub data: Bits<8>,
+    pub valid: bool,
+}
+
+impl<const W: usize> CircuitIO for UartLoopback<W> {
+    type I = UartLoopbackI;
+    type O = UartLoopbackO;
+}
+
+#[kernel]
+pub fn uart_loopback<const W: usize>(
+    i: UartLoopbackI,
+    q: UartLoopbackQ<W>,
+) -> (UartLoopbackO, UartLoopbackD<W>) {
+    let mut d = UartLoopbackD::<W>::default();
+    d.tx.clock = i.clock;
+    d.tx.data = i.data;
+    d.tx.valid = i.valid;
+    d.rx.clock = i.clock;
+    d.rx.rx = q.tx.tx;
+    d.rx.ready = i.ready;
+    (
+        UartLoopbackO {
+            tx_ready: q.tx.ready,
+            data: q.rx.data,
+            valid: q.rx.valid,
+        },
+        d,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{clocked_inputs, run_clocked};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rhdl_core::Circuit;
+
+    // Send `bytes` through the loopback, offering the next byte whenever
+    // the transmitter is ready.  `ready` decides, for each cycle, whether
+    // the receiver side takes a byte.  Returns the inputs (for replay in
+    // a testbench), and the bytes received.
+    fn run_loopback<const W: usize>(
+        uart: &UartLoopback<W>,
+        bytes: &[u8],
+        mut ready: impl FnMut() -> bool,
+    ) -> (Vec<UartLoopbackI>, Vec<u8>) {
+        let mut state = uart.init_state();
+        let mut io = <UartLoopback<W> as Circuit>::Z::default();
+        let mut inputs = vec![];
+        let mut received = vec![];
+        let mut sent = 0;
+        // The outputs only change on a rising edge, so the ones from the
+        // last edge hold for the whole of the next cycle
+        let mut last = uart.sim(UartLoopbackI::default(), &mut state, &mut io);
+        // Allow 12 bit times per byte at the largest divisor
+        let cycles = bytes.len() * 12 * (1 << W) + 100;
+        for _ in 0..cycles {
+            let mut input = UartLoopbackI {
+                clock: Clock(false),
+                data: bits(bytes.get(sent).copied().unwrap_or_default() as u128),
+                valid: sent < bytes.len(),
+                ready: ready(),
+            };
+            if last.valid && input.ready {
+                received.push(last.data.0 as u8);
+            }
+            if last.tx_ready && input.valid {
+                sent += 1;
+            }
+            inputs.push(input);
+            uart.sim(input, &mut state, &mut io);
+            input.clock = Clock(true);
+            inputs.push(input);
+            last = uart.sim(input, &mut state, &mut io);
+            if received.len() == bytes.len() {
+                break;
+            }
+        }
+        (inputs, received)
+    }
+
+    #[test]
+    fn test_uart_tx_frames_a_byte() {
+        // 0x35 is sent as 1, 0, 1, 0, 1, 1, 0, 0 after the start bit
+        let tx = UartTx::<4>::new(bits(3));
+        let inputs = clocked_inputs(0..40, |clock, cycle| UartTxI {
+            clock,
+            data: bits(0x35),
+            valid: cycle == 0,
+        });
+        let line = run_clocked(&tx, &mut tx.init_state(), &inputs)
+            .into_iter()
+            .map(|o| o.tx)
+            .collect::<Vec<_>>();
+        let bits = line[1..31]
+            .chunks(3)
+            .map(|chunk| {
+                // Every bit is held for the whole of its 3 cycles
+                assert!(chunk.iter().all(|x| *x == chunk[0]));
+                chunk[0] as u8
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(bits, [0, 1, 0, 1, 0, 1, 1, 0, 0, 1]);
+        assert!(line[0] && line[31..].iter().all(|x| *x));
+    }
+
+    #[test]
+    fn test_uart_loopback() {
+        let bytes = [0x00, 0xFF, 0x55, 0xAA, 0x01, 0x80, 0x3C];
+        let (_, received) = run_loopback(&UartLoopback::<4>::new(bits(4)), &bytes, || true);
+        assert_eq!(received, bytes);
+        let (_, received) = run_loopback(&UartLoopback::<4>::new(bits(7)), &bytes, || true);
+        assert_eq!(received, bytes);
+    }
+
+    #[test]
+    fn test_uart_loopback_soak() {
+        let mut rng = StdRng::seed_from_u64(0x0a27);
+        let bytes = (0..200).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+        // The receiver side is slow to take bytes, but not so slow that
+        // it falls a whole byte behind
+        let mut slow = StdRng::seed_from_u64(0x51e3);
+        let (_, received) = run_loopback(&UartLoopback::<4>::new(bits(5)), &bytes, || {
+            slow.gen_bool(0.5)
+        });
+        assert_eq!(received, bytes);
+    }
+
+    #[cfg(feature = "iverilog")]
+    #[test]
+    fn test_uart_loopback_verilog() {
+        // The testbench checks the outputs of the Verilog against the
+        // simulation on every cycle, so the Verilog receives the same
+        // bytes as well
+        let bytes = [0xA5, 0x0F, 0x7E, 0x81];
+        for divisor in [4, 7] {
+            let uart = UartLoopback::<4>::new(bits(divisor));
+            let (inputs, received) = run_loopback(&uart, &bytes, || true);
+            assert_eq!(received, bytes);
+            let tm = uart.testbench(&inputs).unwrap();
+            assert_eq!(tm.num_cases, inputs.len());
+            tm.run_iverilog().unwrap();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// A long running loop that rebuilds a design every second, and prints
+// what changed.  Since the kernels are compiled into this binary, the
+// "edit" is simulated by a file that selects the version of the kernel
+// to use.  Run with
+//
+//   cargo run --example devloop -- variant.txt
+//
+// and then write `fast` or `slow` into variant.txt.
+use std::time::Duration;
+
+use rhdl_bits::alias::*;
+use rhdl_core::{compile_design, devloop::DesignWatcher, digital_fn::DigitalFn, KernelFnKind};
+use rhdl_macro::kernel;
+
+mod slow {
+    use super::*;
+
+    #[kernel]
+    pub fn step(a: b8) -> b8 {
+        a + 1
+    }
+
+    #[kernel]
+    pub fn counter(a: b8, enable: bool) -> b8 {
+        if enable {
+            step(a)
+        } else {
+            a
+        }
+    }
+}
+
+mod fast {
+    use super::*;
+
+    #[kernel]
+    pub fn step(a: b8) -> b8 {
+        a + 4
+    }
+
+    #[kernel]
+    pub fn counter(a: b8, enable: bool) -> b8 {
+        if enable {
+            step(a)
+        } else {
+            a
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let variant_file = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "variant.txt".into());
+    let mut watcher = DesignWatcher::new(move || {
+        let variant = std::fs::read_to_string(&variant_file).unwrap_or_default();
+        let kernel = if variant.trim() == "fast" {
+            fast::counter::kernel_fn()
+        } else {
+            slow::counter::kernel_fn()
+        };
+        let Some(KernelFnKind::Kernel(kernel)) = kernel else {
+            anyhow::bail!("Kernel not found");
+        };
+        compile_design(kernel)
+    });
+    loop {
+        match watcher.check_changed() {
+            Ok(delta) if !delta.is_empty() => println!("{delta}"),
+            Ok(_) => {}
+            Err(err) => eprintln!("Build failed: {err}"),
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
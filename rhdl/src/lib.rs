@@ -14,9 +14,19 @@ mod test_upstream;
 #[cfg(test)]
 mod test_downstream;
 
+#[cfg(test)]
+mod test_proptest;
+
+#[cfg(test)]
+mod test_passes;
+
+#[cfg(test)]
+mod test_stats;
+
 pub use crate::bits::Bits;
 pub use crate::bits::SignedBits;
 pub use crate::core::Digital;
 pub use crate::core::Kind;
 pub use rhdl_macro::kernel;
+pub use rhdl_macro::path_of;
 pub use rhdl_macro::Digital;
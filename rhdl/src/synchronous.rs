@@ -271,6 +271,8 @@ endmodule
     Ok(TestModule {
         testbench,
         num_cases: inputs.len(),
+        stimulus: None,
+        masked: Default::default(),
     })
 }
 
@@ -418,6 +420,33 @@ fn get_blinker_data_flow_graph() {
     let design = compile_design(kernel).unwrap();
 }
 
+#[test]
+fn test_constrained_verilog_register_assignments() -> Result<()> {
+    use rhdl_core::AssignmentStyle;
+    use rhdl_fpga::make_constrained_verilog_with_style;
+
+    let strobe = Strobe::<16> { period: bits(100) };
+    let clock = Constraint::Location(rhdl_fpga::bsp::alchitry::cu::BASE_CLOCK_100MHZ_LOCATION);
+    // Registers are updated with non-blocking assignments by default
+    let top = make_constrained_verilog(strobe, vec![], clock.clone())?;
+    assert!(top.module.contains("always @(posedge clk) begin"));
+    assert!(top.module.contains("state <= update_result[15:0];"));
+    assert!(top.module.contains("top_out <= output_value;"));
+    let blocking =
+        make_constrained_verilog_with_style(strobe, vec![], clock, AssignmentStyle::Blocking)?;
+    assert!(blocking.module.contains("state = update_result[15:0];"));
+    assert!(blocking.module.contains("top_out = output_value;"));
+    // The combinational logic stays in continuous assignments either way
+    for module in [&top.module, &blocking.module] {
+        assert!(module.contains("assign output_value = update_result["));
+        assert!(module
+            .lines()
+            .filter(|line| line.trim_start().starts_with("assign"))
+            .all(|line| !line.contains("<=")));
+    }
+    Ok(())
+}
+
 #[test]
 fn get_blinker_synth() -> Result<()> {
     let blinker = Blinker {
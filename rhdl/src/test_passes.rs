@@ -0,0 +1,186 @@
+// The kernels in the corpus that the passes are checked against (see
+// `rhdl_core::compiler::verify_pass`).  After changing or adding to them,
+// write the corpus out again with
+//
+//   cargo test -p rhdl --lib update_pass_corpus -- --ignored
+use rhdl_bits::{alias::*, Bits, SignedBits};
+use rhdl_core::{
+    compile_design,
+    compiler::verify_pass::{
+        random_arguments, unoptimized_design, verify_corpus_entry, PASS_CORPUS,
+    },
+    digital_fn::DigitalFn,
+    kernel::Kernel,
+    rhif::bytecode::{Engine, Simulator},
+    KernelFnKind,
+};
+use rhdl_macro::{kernel, Digital};
+
+#[derive(Copy, Clone, PartialEq, Debug, Digital)]
+pub enum Command {
+    Nop,
+    Load(b8),
+    Add(b8, b8),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Digital)]
+pub struct Counter {
+    pub count: b8,
+    pub enable: bool,
+    pub history: [b4; 4],
+}
+
+// Copies, a conditional update and a tuple, from the CompactSlotsPass work.
+#[kernel]
+fn compact(a: b8, b: b8) -> b8 {
+    let c = a;
+    let d = c;
+    let mut e = d + b;
+    if a > b {
+        e = e + 1;
+    }
+    let f = (e, d);
+    f.0 + 3
+}
+
+// Negated literals are folded into signed literals.  They were cast to
+// the width of their context first, and rejected.
+#[kernel]
+fn negative_literals(acc: s6) -> (s6, bool, bool) {
+    let x: s6 = (-3).into();
+    let y = s6(-32);
+    let z = if acc == -32 { y } else { acc + x };
+    (z, acc < -5, acc != -1)
+}
+
+// Literal, range and or-patterns on a Bits scrutinee, with overlapping ranges.
+#[kernel]
+fn decode(a: b8) -> b4 {
+    match a {
+        Bits::<8>(0) => b4(0),
+        Bits::<8>(1 | 2 | 3) => b4(1),
+        Bits::<8>(0x20..=0x2f) => b4(2),
+        Bits::<8>(0x30..0x38) | Bits::<8>(0xff) => b4(3),
+        Bits::<8>(0x28..=0x48) => b4(4),
+        _ => b4(5),
+    }
+}
+
+#[kernel]
+fn sign(a: s8) -> s8 {
+    match a {
+        SignedBits::<8>(-128..=-2) => s8(-2),
+        SignedBits::<8>(-1 | 0 | 1) => a,
+        _ => s8(2),
+    }
+}
+
+#[kernel]
+fn execute(cmd: Command, acc: b8) -> b8 {
+    match cmd {
+        Command::Nop => acc,
+        Command::Load(x) => x,
+        Command::Add(a, b) => acc + a + b,
+    }
+}
+
+#[kernel]
+fn double(a: b8) -> b8 {
+    a + a
+}
+
+// Calls to other kernels, which the interpreter runs from the design.
+#[kernel]
+fn call(a: b8, b: b8) -> b8 {
+    let c = double(a) + b;
+    c + a + b
+}
+
+// Dynamic indexing on read and on write.
+#[kernel]
+fn history(state: Counter, ndx: b2) -> (Counter, b4) {
+    let mut next = state;
+    if state.enable {
+        next.count = state.count + 1;
+        next.history[ndx] = rhdl_std::slice::<8, 4>(state.count, 0);
+    }
+    next.history[0] = b4(0);
+    (next, state.history[ndx])
+}
+
+// Shifts by amounts up to and past the width (arithmetic for signed
+// values), with negation and an array of repeated values.
+#[kernel]
+fn shifts(a: b6, b: s6, n: b3) -> ((b6, b6), (s6, s6), [s6; 2]) {
+    let c = -b;
+    let d = if b < c { !a } else { a };
+    ((d << n, a >> n), (b >> n, c << n), [c >> n; 2])
+}
+
+fn kernel<K: DigitalFn>() -> Kernel {
+    let Some(KernelFnKind::Kernel(kernel)) = K::kernel_fn() else {
+        panic!("No kernel function found");
+    };
+    kernel
+}
+
+fn corpus() -> Vec<(&'static str, Kernel)> {
+    vec![
+        ("compact", kernel::<compact>()),
+        ("negative_literals", kernel::<negative_literals>()),
+        ("decode", kernel::<decode>()),
+        ("sign", kernel::<sign>()),
+        ("execute", kernel::<execute>()),
+        ("call", kernel::<call>()),
+        ("history", kernel::<history>()),
+        ("shifts", kernel::<shifts>()),
+    ]
+}
+
+fn verify_corpus(samples: usize) {
+    for (name, kernel) in corpus() {
+        let design = unoptimized_design(kernel).unwrap();
+        if let Err(err) = verify_corpus_entry(&design, samples) {
+            panic!("{name}: {err:#}");
+        }
+    }
+}
+
+// Each sample is run through every pass of the pipeline, which is slow
+// in a debug build, so only a few are checked by default.
+#[test]
+fn test_corpus_kernels_verify() {
+    verify_corpus(8);
+}
+
+#[test]
+#[ignore]
+fn test_corpus_kernels_verify_thoroughly() {
+    verify_corpus(256);
+}
+
+// The bytecode engine and the interpreter agree on the compiled corpus
+// kernels, whatever their size.
+#[test]
+fn test_corpus_kernels_match_on_both_engines() {
+    for (name, kernel) in corpus() {
+        let design = compile_design(kernel).unwrap();
+        let simulator = Simulator::with_engine(&design, Engine::Checked).unwrap();
+        for args in random_arguments(&design.objects[&design.top], 256).unwrap() {
+            if let Err(err) = simulator.execute(args) {
+                panic!("{name}: {err:#}");
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn update_pass_corpus() {
+    std::fs::create_dir_all(PASS_CORPUS).unwrap();
+    for (name, kernel) in corpus() {
+        let path = std::path::Path::new(PASS_CORPUS).join(format!("{name}.json"));
+        let design = unoptimized_design(kernel).unwrap();
+        std::fs::write(path, serde_json::to_string(&design).unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use proptest::prelude::*;
+use proptest::test_runner::{Config, RngAlgorithm, TestError, TestRng, TestRunner};
+use proptest_derive::Arbitrary;
+use rhdl_bits::alias::*;
+use rhdl_core::{
+    compile_design, prop_assert_kernel_equiv, types::arbitrary::arbitrary_digital, DigitalFn,
+    KernelFnKind, Module,
+};
+use rhdl_macro::{kernel, Digital};
+
+fn design<K: DigitalFn>() -> Module {
+    let Some(KernelFnKind::Kernel(kernel)) = K::kernel_fn() else {
+        panic!("No kernel function found");
+    };
+    compile_design(kernel).unwrap()
+}
+
+#[kernel]
+fn add(a: b8, b: b8) -> b8 {
+    a + b
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Digital, Arbitrary)]
+enum Command {
+    Nop,
+    Load(b8),
+    Add { a: b8, b: b8 },
+}
+
+#[kernel]
+fn execute(cmd: Command, acc: b8) -> b8 {
+    match cmd {
+        Command::Nop => acc,
+        Command::Load(x) => x,
+        Command::Add { a, b } => acc + a + b,
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_add_is_commutative(a in any::<b8>(), b in any::<b8>()) {
+        prop_assert_eq!(add(a, b), add(b, a));
+    }
+}
+
+// The designs are compiled once for each test, rather than for each case
+#[test]
+fn test_add_matches_vm() {
+    let design = design::<add>();
+    proptest!(|(a in any::<b8>(), b in any::<b8>())| {
+        prop_assert_kernel_equiv!(design, add, a, b);
+    });
+}
+
+#[test]
+fn test_execute_matches_vm() {
+    let design = design::<execute>();
+    // Generated field by field by the derived `Arbitrary`
+    proptest!(|(cmd in any::<Command>(), acc in any::<b8>())| {
+        prop_assert_kernel_equiv!(design, execute, cmd, acc);
+    });
+    // Decoded from bit patterns of the kind of `Command`
+    proptest!(|(cmd in arbitrary_digital::<Command>(), acc in any::<b8>())| {
+        prop_assert_kernel_equiv!(design, execute, cmd, acc);
+    });
+}
+
+#[test]
+fn test_failing_property_shrinks() {
+    let rng = TestRng::deterministic_rng(RngAlgorithm::ChaCha);
+    let mut runner = TestRunner::new_with_rng(Config::default(), rng);
+    let result = runner.run(&(any::<b8>(), any::<b8>()), |(a, b)| {
+        // Fails whenever the sum wraps
+        prop_assert!(add(a, b) >= a);
+        Ok(())
+    });
+    let Err(TestError::Fail(_, (a, b))) = result else {
+        panic!("Expected the property to fail");
+    };
+    // Shrinking lands exactly on the boundary where the sum wraps
+    assert_eq!(a.0 + b.0, 256);
+}
@@ -0,0 +1,217 @@
+// Statistics collected from simulations of a counter and of an FSM, whose
+// registers are written out by hand, since there is no register circuit
+// to build them from.
+use rhdl_bits::alias::*;
+use rhdl_core::{
+    collect_stats, path::Path, root_descriptor, root_hdl, Circuit, CircuitDescriptor, CircuitIO,
+    Digital, HDLDescriptor, HDLKind, LeafStats, NoUpdateFn, StatsCollector, StatsLeaves,
+    TraceRecorder, TypedBits,
+};
+use rhdl_macro::Digital;
+
+// Counts the cycles in which it is enabled, and shows the count at the
+// start of the cycle.
+#[derive(Clone)]
+struct Counter;
+
+impl CircuitIO for Counter {
+    type I = bool;
+    type O = b4;
+}
+
+impl Circuit for Counter {
+    type D = ();
+    type Q = ();
+    type Z = ();
+    type Update = NoUpdateFn;
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |_, _| (Default::default(), ());
+    type S = b4;
+
+    fn sim(&self, enable: bool, count: &mut b4, _io: &mut ()) -> b4 {
+        let output = *count;
+        if enable {
+            *count += 1;
+        }
+        output
+    }
+
+    fn state_signals(&self, count: &b4) -> Vec<(String, TypedBits)> {
+        vec![("count".into(), count.typed_bits())]
+    }
+
+    fn name(&self) -> &'static str {
+        "Counter"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> anyhow::Result<HDLDescriptor> {
+        root_hdl(self, kind)
+    }
+}
+
+// The derived default is `Idle`, the first variant
+#[derive(Copy, Clone, PartialEq, Debug, Digital)]
+enum State {
+    Idle,
+    Busy,
+    Done,
+}
+
+// Starts when `go` is set, stays busy for as long as it is held, and
+// then spends a cycle done before it is idle again.
+#[derive(Clone)]
+struct Fsm;
+
+impl CircuitIO for Fsm {
+    type I = bool;
+    type O = bool;
+}
+
+impl Circuit for Fsm {
+    type D = ();
+    type Q = ();
+    type Z = ();
+    type Update = NoUpdateFn;
+    const UPDATE: fn(Self::I, Self::Q) -> (Self::O, Self::D) = |_, _| (Default::default(), ());
+    type S = State;
+
+    fn sim(&self, go: bool, state: &mut State, _io: &mut ()) -> bool {
+        let busy = *state == State::Busy;
+        *state = match (*state, go) {
+            (State::Idle, true) | (State::Busy, true) => State::Busy,
+            (State::Busy, false) => State::Done,
+            _ => State::Idle,
+        };
+        busy
+    }
+
+    fn state_signals(&self, state: &State) -> Vec<(String, TypedBits)> {
+        vec![("state".into(), state.typed_bits())]
+    }
+
+    fn name(&self) -> &'static str {
+        "Fsm"
+    }
+
+    fn descriptor(&self) -> CircuitDescriptor {
+        root_descriptor(self)
+    }
+
+    fn as_hdl(&self, kind: HDLKind) -> anyhow::Result<HDLDescriptor> {
+        root_hdl(self, kind)
+    }
+}
+
+const ENABLES: [bool; 10] = [
+    true, true, false, true, true, true, false, false, true, true,
+];
+
+#[test]
+fn test_counter_stats() -> anyhow::Result<()> {
+    let leaves = StatsLeaves::Paths(vec![
+        ("i".into(), Path::default()),
+        ("o".into(), Path::default()),
+        ("count".into(), Path::default()),
+    ]);
+    // The counts at the start of each cycle are 0, 1, 2, 2, 3, 4, 5, 5, 5, 6
+    let report = collect_stats(&Counter, &leaves, ENABLES)?;
+    assert_eq!(report.cycles, 10);
+    assert_eq!(
+        report.leaf("i"),
+        Some(&LeafStats::Bool {
+            set: 7,
+            duty_cycle: 0.7
+        })
+    );
+    let count = LeafStats::Unsigned {
+        min: 0,
+        max: 6,
+        mean: 3.3,
+    };
+    assert_eq!(report.leaf("o"), Some(&count));
+    assert_eq!(report.leaf("count"), Some(&count));
+    assert_eq!(
+        report.to_string(),
+        "Statistics of Counter over 10 cycles
+  i: set in 7 of 10 cycles (70.0%)
+  o: from 0 to 6, mean 3.30
+  count: from 0 to 6, mean 3.30
+"
+    );
+    // A long run keeps no trace, and the counter wraps every 16 cycles
+    let mut recorder = TraceRecorder::new(&Counter)
+        .with_stats(StatsCollector::new(&Counter, &leaves)?)
+        .without_cycles();
+    for _ in 0..100_000 {
+        recorder.step(true);
+    }
+    let (trace, report) = recorder.finish_with_stats();
+    assert!(trace.cycles.is_empty());
+    let report = report.unwrap();
+    assert_eq!(
+        report.leaf("count"),
+        Some(&LeafStats::Unsigned {
+            min: 0,
+            max: 15,
+            mean: 7.5
+        })
+    );
+    // A leaf must name a signal of the circuit
+    let leaves = StatsLeaves::Paths(vec![("fill".into(), Path::default())]);
+    let err = StatsCollector::new(&Counter, &leaves).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Circuit Counter has no signal named `fill`"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_fsm_stats() -> anyhow::Result<()> {
+    let inputs = [
+        false, true, true, true, false, false, false, true, false, false,
+    ];
+    // The states at the start of each cycle are Idle, Idle, Busy, Busy,
+    // Busy, Done, Idle, Idle, Busy and Done
+    let mut recorder =
+        TraceRecorder::new(&Fsm).with_stats(StatsCollector::new(&Fsm, &StatsLeaves::AllEnums)?);
+    let outputs = inputs.map(|go| recorder.step(go));
+    assert_eq!(outputs.iter().filter(|busy| **busy).count(), 4);
+    let (trace, report) = recorder.finish_with_stats();
+    assert_eq!(trace.cycles.len(), 10);
+    let report = report.unwrap();
+    let state = report.leaf("state").unwrap();
+    assert_eq!(state.occupancy("Idle"), Some(4));
+    assert_eq!(state.occupancy("Busy"), Some(4));
+    assert_eq!(state.occupancy("Done"), Some(2));
+    assert_eq!(
+        report.to_string(),
+        "Statistics of Fsm over 10 cycles
+  state: Idle 4 (40.0%), Busy 4 (40.0%), Done 2 (20.0%)
+"
+    );
+    let json = serde_json::to_value(&report)?;
+    assert_eq!(
+        json["leaves"][0]["stats"]["Enum"]["variants"],
+        serde_json::json!([["Idle", 4], ["Busy", 4], ["Done", 2]])
+    );
+    // The bools are the input and the output
+    let report = collect_stats(&Fsm, &StatsLeaves::AllBools, inputs)?;
+    let names = report
+        .leaves
+        .iter()
+        .map(|leaf| leaf.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["i", "o"]);
+    assert_eq!(
+        report.leaf("o"),
+        Some(&LeafStats::Bool {
+            set: 4,
+            duty_cycle: 0.4
+        })
+    );
+    Ok(())
+}
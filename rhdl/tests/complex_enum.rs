@@ -9,26 +9,27 @@ use rhdl_macro::Digital;
 #[derive(Copy, Clone, PartialEq, Debug, Digital)]
 #[repr(i8)]
 enum Packet {
-    Color { r: b8, g: b8, b: b8 } = 1,
-    Size { w: b16, h: b16 } = 2,
+    #[rhdl(default)]
+    Color {
+        r: b8,
+        g: b8,
+        b: b8,
+    } = 1,
+    Size {
+        w: b16,
+        h: b16,
+    } = 2,
     Position(b4, b4) = 4,
     State(State) = 8,
-    Log { msg: b32, level: LogLevel } = 16,
+    Log {
+        msg: b32,
+        level: LogLevel,
+    } = 16,
 }
 
-impl Default for Packet {
-    fn default() -> Self {
-        Self::Color {
-            r: b8::from(0),
-            g: b8::from(0),
-            b: b8::from(0),
-        }
-    }
-}
-
-#[derive(Copy, Clone, PartialEq, Debug, Digital, Default)]
+#[derive(Copy, Clone, PartialEq, Debug, Digital)]
 enum State {
-    #[default]
+    #[rhdl(default)]
     Init = -2,
     Boot,
     Running,